@@ -0,0 +1,72 @@
+use chrono::{DateTime, Utc};
+use firestore::*;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+pub fn config_env_var(name: &str) -> Result<String, String> {
+    std::env::var(name).map_err(|e| format!("{}: {}", name, e))
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct MyTestStructure {
+    some_id: String,
+    some_string: String,
+    created_at: DateTime<Utc>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Logging with debug enabled
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter("firestore=debug")
+        .finish();
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    // Create an instance
+    let db = FirestoreDb::new(&config_env_var("PROJECT_ID")?).await?;
+
+    const TEST_COLLECTION_NAME: &str = "test-batch-write";
+
+    println!("Populating a test collection");
+    let (bulk_writer, mut bulk_results_reader) = db
+        .create_bulk_writer_with_options(
+            FirestoreBulkWriterOptions::new()
+                .with_max_batch_size(100)
+                .with_max_concurrent_batches(20),
+        )
+        .await?;
+
+    let response_thread = tokio::spawn(async move {
+        let mut failed = 0;
+        while let Some(response) = bulk_results_reader.next().await {
+            if let Err(err) = response.result {
+                println!("Failed to write {}: {}", response.document_path, err);
+                failed += 1;
+            }
+        }
+        println!("Failed writes: {}", failed);
+    });
+
+    for idx in 0..10000 {
+        let my_struct = MyTestStructure {
+            some_id: format!("test-{}", idx),
+            some_string: "Test".to_string(),
+            created_at: Utc::now(),
+        };
+
+        let mut batch = bulk_writer.new_batch();
+        db.fluent()
+            .update()
+            .in_col(TEST_COLLECTION_NAME)
+            .document_id(&my_struct.some_id)
+            .object(&my_struct)
+            .add_to_batch(&mut batch)?;
+        batch.write().await?;
+    }
+
+    println!("Finishing...");
+    bulk_writer.finish().await;
+    let _ = tokio::join!(response_thread);
+
+    Ok(())
+}
@@ -29,7 +29,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // Create an instance
     let db = FirestoreDb::new(&config_env_var("PROJECT_ID")?).await?;
 
-    const TEST_COLLECTION_NAME: &'static str = "test-caching";
+    const TEST_COLLECTION_NAME: &str = "test-caching";
 
     let mut cache = FirestoreCache::new(
         "example-persistent-cache".into(),
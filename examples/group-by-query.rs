@@ -0,0 +1,82 @@
+use firestore::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub fn config_env_var(name: &str) -> Result<String, String> {
+    std::env::var(name).map_err(|e| format!("{}: {}", name, e))
+}
+
+// Example structure to play with
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct MyTestStructure {
+    some_id: String,
+    some_string: String,
+    some_num: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct MyGroupStats {
+    counter: usize,
+    calc_sum: u64,
+    calc_avg: Option<f64>,
+    calc_max: Option<u64>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Logging with debug enabled
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter("firestore=debug")
+        .finish();
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    // Create an instance
+    let db = FirestoreDb::new(&config_env_var("PROJECT_ID")?).await?;
+
+    const TEST_COLLECTION_NAME: &str = "test-group-by";
+
+    println!("Populating a test collection");
+    for i in 0..20 {
+        let my_struct = MyTestStructure {
+            some_id: format!("test-{}", i),
+            some_string: format!("group-{}", i % 3),
+            some_num: i,
+        };
+
+        db.fluent()
+            .update()
+            .in_col(TEST_COLLECTION_NAME)
+            .document_id(&my_struct.some_id)
+            .object(&my_struct)
+            .execute::<()>()
+            .await?;
+    }
+
+    println!("Grouping a test collection by a field");
+
+    let groups: BTreeMap<String, MyGroupStats> = db
+        .fluent()
+        .select()
+        .from(TEST_COLLECTION_NAME)
+        .group_by([path!(MyTestStructure::some_string)])
+        .aggregate(|a| {
+            a.fields([
+                a.field(path!(MyGroupStats::counter)).count(),
+                a.field(path!(MyGroupStats::calc_sum))
+                    .sum(path!(MyTestStructure::some_num)),
+                a.field(path!(MyGroupStats::calc_avg))
+                    .avg(path!(MyTestStructure::some_num)),
+                a.field(path!(MyGroupStats::calc_max))
+                    .max(path!(MyTestStructure::some_num)),
+            ])
+        })
+        .obj()
+        .query()
+        .await?;
+
+    for (group, stats) in groups {
+        println!("{}: {:?}", group, stats);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,149 @@
+//! Derive macros for the [`firestore`](https://docs.rs/firestore) crate.
+//!
+//! Enabled with the `derive` feature of `firestore`, which re-exports the macros.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+/// Implements `firestore::FirestoreEntity` for a struct with named fields.
+///
+/// The struct is annotated with `#[firestore(...)]`:
+/// - `collection = "users"`: the ID of the collection of the documents (required);
+/// - `id = "user_id"`: the field with the document ID, `id` if not specified.
+///
+/// The document ID is converted to a string with `ToString`.
+///
+/// Fields annotated with `#[firestore(timestamp)]` are written as Firestore timestamps
+/// by the entity operations, as with `#[serde(with = "firestore::serialize_as_timestamp")]`.
+/// The field names are taken from `#[serde(rename = "...")]` if specified.
+#[proc_macro_derive(FirestoreEntity, attributes(firestore))]
+pub fn derive_firestore_entity(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_firestore_entity(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand_firestore_entity(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut collection: Option<LitStr> = None;
+    let mut id: Option<LitStr> = None;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("firestore"))
+    {
+        attr.parse_nested_meta(|meta| {
+            let target = if meta.path.is_ident("collection") {
+                &mut collection
+            } else if meta.path.is_ident("id") {
+                &mut id
+            } else {
+                return Err(meta.error("expected `collection` or `id`"));
+            };
+            *target = Some(meta.value()?.parse()?);
+            Ok(())
+        })?;
+    }
+
+    let collection = collection.ok_or_else(|| {
+        syn::Error::new(
+            input.ident.span(),
+            "missing `#[firestore(collection = \"...\")]` attribute",
+        )
+    })?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new(
+                    input.ident.span(),
+                    "FirestoreEntity requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "FirestoreEntity can only be derived for structs",
+            ))
+        }
+    };
+
+    let find_field = |name: &LitStr| {
+        fields
+            .iter()
+            .filter_map(|field| field.ident.as_ref())
+            .find(|ident| *ident == name.value().as_str())
+            .cloned()
+            .ok_or_else(|| {
+                syn::Error::new(
+                    name.span(),
+                    format!("no field `{}` in the struct", name.value()),
+                )
+            })
+    };
+
+    let mut timestamp_fields: Vec<LitStr> = Vec::new();
+    for field in fields {
+        let mut timestamp = false;
+        let mut serde_rename: Option<LitStr> = None;
+        for attr in field.attrs.iter() {
+            if attr.path().is_ident("firestore") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("timestamp") {
+                        timestamp = true;
+                        Ok(())
+                    } else {
+                        Err(meta.error("expected `timestamp`"))
+                    }
+                })?;
+            } else if attr.path().is_ident("serde") {
+                // Other serde attributes are skipped, only the name of the field matters here
+                let _ = attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("rename") {
+                        serde_rename = Some(meta.value()?.parse()?);
+                    } else if meta.input.peek(syn::Token![=]) {
+                        meta.value()?.parse::<syn::Expr>()?;
+                    }
+                    Ok(())
+                });
+            }
+        }
+        if timestamp {
+            timestamp_fields.push(serde_rename.unwrap_or_else(|| {
+                let ident = field.ident.as_ref().expect("named field");
+                LitStr::new(ident.to_string().trim_start_matches("r#"), ident.span())
+            }));
+        }
+    }
+
+    let id_field = match &id {
+        Some(id) => find_field(id)?,
+        None => find_field(&LitStr::new("id", input.ident.span())).map_err(|_| {
+            syn::Error::new(
+                input.span(),
+                "missing `#[firestore(id = \"...\")]` attribute or `id` field",
+            )
+        })?,
+    };
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::firestore::FirestoreEntity for #ident #ty_generics #where_clause {
+            const COLLECTION_ID: &'static str = #collection;
+
+            const TIMESTAMP_FIELDS: &'static [&'static str] = &[#(#timestamp_fields),*];
+
+            fn document_id(&self) -> ::std::string::String {
+                ::std::string::ToString::to_string(&self.#id_field)
+            }
+        }
+    })
+}
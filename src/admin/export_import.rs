@@ -0,0 +1,141 @@
+use crate::admin::operation::decode_any_message;
+use crate::admin::{FirestoreAdmin, FirestoreOperation, FirestoreOperationResponse};
+use crate::errors::*;
+use crate::timestamp_utils::to_timestamp;
+use crate::FirestoreResult;
+use chrono::prelude::*;
+use gcloud_sdk::google::firestore::admin::v1 as admin;
+use gcloud_sdk::prost_types::Any;
+use rsb_derive::Builder;
+use tracing::*;
+
+/// Parameters of a managed export of documents to Cloud Storage.
+#[derive(Debug, PartialEq, Eq, Clone, Builder)]
+pub struct FirestoreExportDocumentsParams {
+    /// The destination, e.g. `gs://my-bucket/backups/2024-01-01`.
+    pub output_uri_prefix: String,
+    /// The collection ids to export, all collections if not specified.
+    pub collection_ids: Option<Vec<String>>,
+    /// The namespace ids to export, all namespaces if not specified.
+    pub namespace_ids: Option<Vec<String>>,
+    /// Exports a consistent snapshot of the database at this time (requires PITR to be enabled).
+    pub snapshot_time: Option<DateTime<Utc>>,
+}
+
+/// Parameters of a managed import of documents from Cloud Storage.
+#[derive(Debug, PartialEq, Eq, Clone, Builder)]
+pub struct FirestoreImportDocumentsParams {
+    /// The location of a previous export, i.e. its `output_uri_prefix`.
+    pub input_uri_prefix: String,
+    /// The collection ids to import, all exported collections if not specified.
+    pub collection_ids: Option<Vec<String>>,
+    /// The namespace ids to import, all exported namespaces if not specified.
+    pub namespace_ids: Option<Vec<String>>,
+}
+
+/// The result of a finished export.
+#[derive(Debug, PartialEq, Eq, Clone, Builder)]
+pub struct FirestoreExportDocumentsResult {
+    /// The location of the exported files, to be used as an import `input_uri_prefix`.
+    pub output_uri_prefix: String,
+}
+
+impl FirestoreOperationResponse for FirestoreExportDocumentsResult {
+    fn from_operation_response(response: Option<Any>) -> FirestoreResult<Self> {
+        let response = response.ok_or_else(|| {
+            FirestoreError::DeserializeError(FirestoreSerializationError::from_message(
+                "Export operation finished without a response",
+            ))
+        })?;
+        let response: admin::ExportDocumentsResponse = decode_any_message(
+            &response,
+            "google.firestore.admin.v1.ExportDocumentsResponse",
+        )?;
+        Ok(FirestoreExportDocumentsResult::new(
+            response.output_uri_prefix,
+        ))
+    }
+}
+
+impl FirestoreAdmin {
+    /// Starts a managed export of documents to Cloud Storage.
+    pub async fn export_documents(
+        &self,
+        params: FirestoreExportDocumentsParams,
+    ) -> FirestoreResult<FirestoreOperation<FirestoreExportDocumentsResult>> {
+        let operation = self
+            .client()
+            .export_documents(self.db.create_request(admin::ExportDocumentsRequest {
+                name: self.database_path().to_string(),
+                collection_ids: params.collection_ids.unwrap_or_default(),
+                output_uri_prefix: params.output_uri_prefix.clone(),
+                namespace_ids: params.namespace_ids.unwrap_or_default(),
+                snapshot_time: params.snapshot_time.map(to_timestamp),
+            }))
+            .await?
+            .into_inner();
+
+        debug!(
+            output_uri_prefix = params.output_uri_prefix,
+            operation = operation.name,
+            "Started exporting documents."
+        );
+        Ok(FirestoreOperation::new(self.clone(), operation))
+    }
+
+    /// Starts a managed import of documents from Cloud Storage.
+    ///
+    /// Imported documents overwrite existing documents with the same ids.
+    pub async fn import_documents(
+        &self,
+        params: FirestoreImportDocumentsParams,
+    ) -> FirestoreResult<FirestoreOperation<()>> {
+        let operation = self
+            .client()
+            .import_documents(self.db.create_request(admin::ImportDocumentsRequest {
+                name: self.database_path().to_string(),
+                collection_ids: params.collection_ids.unwrap_or_default(),
+                input_uri_prefix: params.input_uri_prefix.clone(),
+                namespace_ids: params.namespace_ids.unwrap_or_default(),
+            }))
+            .await?
+            .into_inner();
+
+        debug!(
+            input_uri_prefix = params.input_uri_prefix,
+            operation = operation.name,
+            "Started importing documents."
+        );
+        Ok(FirestoreOperation::new(self.clone(), operation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcloud_sdk::prost::Message;
+
+    #[test]
+    fn export_result_from_operation_response() {
+        let response = Any {
+            type_url: "type.googleapis.com/google.firestore.admin.v1.ExportDocumentsResponse"
+                .to_string(),
+            value: admin::ExportDocumentsResponse {
+                output_uri_prefix: "gs://bucket/export".to_string(),
+            }
+            .encode_to_vec(),
+        };
+
+        assert_eq!(
+            FirestoreExportDocumentsResult::from_operation_response(Some(response)).unwrap(),
+            FirestoreExportDocumentsResult::new("gs://bucket/export".to_string())
+        );
+
+        let wrong_type = Any {
+            type_url: "type.googleapis.com/google.protobuf.Empty".to_string(),
+            value: vec![],
+        };
+        assert!(FirestoreExportDocumentsResult::from_operation_response(Some(wrong_type)).is_err());
+        assert!(FirestoreExportDocumentsResult::from_operation_response(None).is_err());
+    }
+}
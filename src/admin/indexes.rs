@@ -0,0 +1,673 @@
+use crate::admin::operation::decode_any_message;
+use crate::admin::{FirestoreAdmin, FirestoreOperation, FirestoreOperationResponse};
+use crate::errors::*;
+use crate::FirestoreResult;
+use gcloud_sdk::google::firestore::admin::v1 as admin;
+use gcloud_sdk::prost_types::Any;
+use rsb_derive::Builder;
+use tracing::*;
+
+/// The scope of queries an index serves.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FirestoreIndexQueryScope {
+    /// Queries against a single collection (the default).
+    Collection,
+    /// Queries against all collections with the same collection id.
+    CollectionGroup,
+    /// Queries against a collection and all its descendant collections.
+    CollectionRecursive,
+}
+
+/// How a field is indexed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FirestoreIndexFieldMode {
+    /// Supports `<`, `<=`, `==`, `>=`, `>`, `!=`, `in` and ascending `order_by`.
+    Ascending,
+    /// Supports the same operators as `Ascending` and descending `order_by`.
+    Descending,
+    /// Supports `array-contains` and `array-contains-any` filters.
+    ArrayContains,
+    /// A flat vector index with the given number of dimensions.
+    Vector { dimension: u32 },
+}
+
+/// The lifecycle state of an index.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FirestoreIndexState {
+    /// The index is being built and cannot serve queries yet.
+    Creating,
+    /// The index is ready to serve queries.
+    Ready,
+    /// Building the index failed, it has to be deleted and recreated.
+    NeedsRepair,
+}
+
+/// A single field of an index.
+#[derive(Debug, PartialEq, Eq, Clone, Builder)]
+pub struct FirestoreIndexField {
+    /// The path of the indexed field.
+    pub field_path: String,
+    pub mode: FirestoreIndexFieldMode,
+}
+
+/// A Firestore composite index.
+///
+/// `name` and `state` are assigned by Firestore and ignored when creating an index.
+#[derive(Debug, PartialEq, Eq, Clone, Builder)]
+pub struct FirestoreIndex {
+    /// The collection group (collection id) the index applies to.
+    pub collection_group: String,
+    pub fields: Vec<FirestoreIndexField>,
+    #[default = "FirestoreIndexQueryScope::Collection"]
+    pub query_scope: FirestoreIndexQueryScope,
+    /// The full resource name of the index.
+    pub name: Option<String>,
+    pub state: Option<FirestoreIndexState>,
+}
+
+impl FirestoreIndex {
+    /// The index id, i.e. the last segment of the index resource name.
+    pub fn index_id(&self) -> Option<&str> {
+        self.name
+            .as_ref()
+            .and_then(|name| name.rsplit_once("/indexes/"))
+            .map(|(_, id)| id)
+    }
+}
+
+/// A single-field index setting, used by field index exemptions.
+#[derive(Debug, PartialEq, Eq, Clone, Builder)]
+pub struct FirestoreSingleFieldIndex {
+    pub mode: FirestoreIndexFieldMode,
+    #[default = "FirestoreIndexQueryScope::Collection"]
+    pub query_scope: FirestoreIndexQueryScope,
+}
+
+/// The state of a TTL (time-to-live) policy on a field.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FirestoreTtlState {
+    /// The TTL policy is being applied.
+    Creating,
+    /// Documents are deleted once the time in the field has passed.
+    Active,
+    /// Applying the TTL policy failed, it has to be re-enabled.
+    NeedsRepair,
+}
+
+/// The index and TTL configuration of a single field.
+#[derive(Debug, PartialEq, Eq, Clone, Builder)]
+pub struct FirestoreFieldConfig {
+    /// The collection group (collection id) of the field, `__default__` for the database defaults.
+    pub collection_group: String,
+    /// The field path, `*` for the collection group defaults.
+    pub field_path: String,
+    /// Single-field indexes that apply to the field.
+    pub indexes: Vec<FirestoreSingleFieldIndex>,
+    /// `true` when the field inherits the indexes of its ancestor instead of having an exemption.
+    pub uses_ancestor_config: bool,
+    /// The TTL policy state, if a TTL policy is configured for the field.
+    pub ttl_state: Option<FirestoreTtlState>,
+}
+
+impl FirestoreAdmin {
+    /// Lists all composite indexes of the collection group.
+    pub async fn list_indexes(
+        &self,
+        collection_group: &str,
+    ) -> FirestoreResult<Vec<FirestoreIndex>> {
+        let parent = self.collection_group_path(collection_group);
+        let mut indexes = Vec::new();
+        let mut page_token = String::new();
+
+        loop {
+            let response = self
+                .client()
+                .list_indexes(self.db.create_request(admin::ListIndexesRequest {
+                    parent: parent.clone(),
+                    filter: String::new(),
+                    page_size: 0,
+                    page_token: page_token.clone(),
+                }))
+                .await?
+                .into_inner();
+
+            for index in response.indexes {
+                indexes.push(index.try_into()?);
+            }
+
+            if response.next_page_token.is_empty() {
+                break;
+            }
+            page_token = response.next_page_token;
+        }
+
+        debug!(collection_group, count = indexes.len(), "Listed indexes.");
+        Ok(indexes)
+    }
+
+    /// Reads an index by its full resource name.
+    pub async fn get_index(&self, index_name: &str) -> FirestoreResult<FirestoreIndex> {
+        self.client()
+            .get_index(self.db.create_request(admin::GetIndexRequest {
+                name: index_name.to_string(),
+            }))
+            .await?
+            .into_inner()
+            .try_into()
+    }
+
+    /// Starts building a composite index.
+    ///
+    /// Building an index is a long-running operation,
+    /// await the returned operation to get the index once it is ready.
+    pub async fn create_index(
+        &self,
+        index: &FirestoreIndex,
+    ) -> FirestoreResult<FirestoreOperation<FirestoreIndex>> {
+        if index.fields.is_empty() {
+            return Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "fields".to_string(),
+                    "An index requires at least one field".to_string(),
+                )),
+            ));
+        }
+
+        let operation = self
+            .client()
+            .create_index(self.db.create_request(admin::CreateIndexRequest {
+                parent: self.collection_group_path(&index.collection_group),
+                index: Some(index.into()),
+            }))
+            .await?
+            .into_inner();
+
+        debug!(
+            collection_group = index.collection_group,
+            operation = operation.name,
+            "Started creating an index."
+        );
+        Ok(FirestoreOperation::new(self.clone(), operation))
+    }
+
+    /// Deletes an index by its full resource name.
+    pub async fn delete_index(&self, index_name: &str) -> FirestoreResult<()> {
+        self.client()
+            .delete_index(self.db.create_request(admin::DeleteIndexRequest {
+                name: index_name.to_string(),
+            }))
+            .await?;
+
+        debug!(index_name, "Deleted an index.");
+        Ok(())
+    }
+
+    /// Reads the index and TTL configuration of a field.
+    pub async fn get_field(
+        &self,
+        collection_group: &str,
+        field_path: &str,
+    ) -> FirestoreResult<FirestoreFieldConfig> {
+        self.client()
+            .get_field(self.db.create_request(admin::GetFieldRequest {
+                name: self.field_path(collection_group, field_path),
+            }))
+            .await?
+            .into_inner()
+            .try_into()
+    }
+
+    /// Lists the fields with single-field index exemptions.
+    /// Use `None` to list exemptions across all collection groups.
+    pub async fn list_field_exemptions(
+        &self,
+        collection_group: Option<&str>,
+    ) -> FirestoreResult<Vec<FirestoreFieldConfig>> {
+        self.list_fields(collection_group, "indexConfig.usesAncestorConfig:false")
+            .await
+    }
+
+    /// Lists the fields with a TTL policy.
+    /// Use `None` to list TTL policies across all collection groups.
+    pub async fn list_ttl_fields(
+        &self,
+        collection_group: Option<&str>,
+    ) -> FirestoreResult<Vec<FirestoreFieldConfig>> {
+        self.list_fields(collection_group, "ttlConfig:*").await
+    }
+
+    /// Replaces the single-field indexes of a field with the given ones (an index exemption).
+    /// An empty list disables single-field indexing for the field.
+    pub async fn update_field_indexes(
+        &self,
+        collection_group: &str,
+        field_path: &str,
+        indexes: Vec<FirestoreSingleFieldIndex>,
+    ) -> FirestoreResult<FirestoreOperation<FirestoreFieldConfig>> {
+        let index_config = admin::field::IndexConfig {
+            indexes: indexes
+                .iter()
+                .map(|index| single_field_index_to_proto(field_path, index))
+                .collect(),
+            ..Default::default()
+        };
+        self.update_field(collection_group, field_path, Some(index_config), None)
+            .await
+    }
+
+    /// Removes the index exemption of a field so that it inherits the collection group defaults.
+    pub async fn revert_field_indexes(
+        &self,
+        collection_group: &str,
+        field_path: &str,
+    ) -> FirestoreResult<FirestoreOperation<FirestoreFieldConfig>> {
+        // Clearing the index config (as opposed to an empty one) reverts to the ancestor config.
+        self.update_field(collection_group, field_path, None, None)
+            .await
+    }
+
+    /// Enables or disables a TTL policy on a timestamp field.
+    pub async fn set_field_ttl(
+        &self,
+        collection_group: &str,
+        field_path: &str,
+        enabled: bool,
+    ) -> FirestoreResult<FirestoreOperation<FirestoreFieldConfig>> {
+        let ttl_config = if enabled {
+            Some(admin::field::TtlConfig::default())
+        } else {
+            None
+        };
+        self.update_field(collection_group, field_path, None, Some(ttl_config))
+            .await
+    }
+
+    fn field_path(&self, collection_group: &str, field_path: &str) -> String {
+        format!(
+            "{}/fields/{}",
+            self.collection_group_path(collection_group),
+            field_path
+        )
+    }
+
+    async fn list_fields(
+        &self,
+        collection_group: Option<&str>,
+        filter: &str,
+    ) -> FirestoreResult<Vec<FirestoreFieldConfig>> {
+        let parent = self.collection_group_path(collection_group.unwrap_or("-"));
+        let mut fields = Vec::new();
+        let mut page_token = String::new();
+
+        loop {
+            let response = self
+                .client()
+                .list_fields(self.db.create_request(admin::ListFieldsRequest {
+                    parent: parent.clone(),
+                    filter: filter.to_string(),
+                    page_size: 0,
+                    page_token: page_token.clone(),
+                }))
+                .await?
+                .into_inner();
+
+            for field in response.fields {
+                fields.push(field.try_into()?);
+            }
+
+            if response.next_page_token.is_empty() {
+                break;
+            }
+            page_token = response.next_page_token;
+        }
+
+        Ok(fields)
+    }
+
+    async fn update_field(
+        &self,
+        collection_group: &str,
+        field_path: &str,
+        index_config: Option<admin::field::IndexConfig>,
+        ttl_config: Option<Option<admin::field::TtlConfig>>,
+    ) -> FirestoreResult<FirestoreOperation<FirestoreFieldConfig>> {
+        let update_mask = if ttl_config.is_some() {
+            "ttl_config"
+        } else {
+            "index_config"
+        };
+
+        let operation = self
+            .client()
+            .update_field(self.db.create_request(admin::UpdateFieldRequest {
+                field: Some(admin::Field {
+                    name: self.field_path(collection_group, field_path),
+                    index_config,
+                    ttl_config: ttl_config.flatten(),
+                }),
+                update_mask: Some(gcloud_sdk::prost_types::FieldMask {
+                    paths: vec![update_mask.to_string()],
+                }),
+            }))
+            .await?
+            .into_inner();
+
+        debug!(
+            collection_group,
+            field_path,
+            update_mask,
+            operation = operation.name,
+            "Started updating a field configuration."
+        );
+        Ok(FirestoreOperation::new(self.clone(), operation))
+    }
+}
+
+fn invalid_admin_response(message: String) -> FirestoreError {
+    FirestoreError::SystemError(FirestoreSystemError::new(
+        FirestoreErrorPublicGenericDetails::new("InvalidAdminResponse".into()),
+        message,
+    ))
+}
+
+/// Extracts the collection group id from an index or field resource name.
+fn collection_group_from_name(name: &str) -> Option<&str> {
+    name.split_once("/collectionGroups/")
+        .and_then(|(_, rest)| rest.split('/').next())
+}
+
+impl From<FirestoreIndexQueryScope> for admin::index::QueryScope {
+    fn from(scope: FirestoreIndexQueryScope) -> Self {
+        match scope {
+            FirestoreIndexQueryScope::Collection => admin::index::QueryScope::Collection,
+            FirestoreIndexQueryScope::CollectionGroup => admin::index::QueryScope::CollectionGroup,
+            FirestoreIndexQueryScope::CollectionRecursive => {
+                admin::index::QueryScope::CollectionRecursive
+            }
+        }
+    }
+}
+
+fn query_scope_from_proto(value: i32) -> FirestoreResult<FirestoreIndexQueryScope> {
+    match admin::index::QueryScope::try_from(value) {
+        Ok(admin::index::QueryScope::Collection) => Ok(FirestoreIndexQueryScope::Collection),
+        Ok(admin::index::QueryScope::CollectionGroup) => {
+            Ok(FirestoreIndexQueryScope::CollectionGroup)
+        }
+        Ok(admin::index::QueryScope::CollectionRecursive) => {
+            Ok(FirestoreIndexQueryScope::CollectionRecursive)
+        }
+        _ => Err(invalid_admin_response(format!(
+            "Unknown index query scope: {value}"
+        ))),
+    }
+}
+
+impl From<&FirestoreIndexFieldMode> for admin::index::index_field::ValueMode {
+    fn from(mode: &FirestoreIndexFieldMode) -> Self {
+        use admin::index::index_field::*;
+        match mode {
+            FirestoreIndexFieldMode::Ascending => ValueMode::Order(Order::Ascending.into()),
+            FirestoreIndexFieldMode::Descending => ValueMode::Order(Order::Descending.into()),
+            FirestoreIndexFieldMode::ArrayContains => {
+                ValueMode::ArrayConfig(ArrayConfig::Contains.into())
+            }
+            FirestoreIndexFieldMode::Vector { dimension } => {
+                ValueMode::VectorConfig(VectorConfig {
+                    dimension: *dimension as i32,
+                    r#type: Some(vector_config::Type::Flat(vector_config::FlatIndex {})),
+                })
+            }
+        }
+    }
+}
+
+impl TryFrom<admin::index::index_field::ValueMode> for FirestoreIndexFieldMode {
+    type Error = FirestoreError;
+
+    fn try_from(mode: admin::index::index_field::ValueMode) -> Result<Self, Self::Error> {
+        use admin::index::index_field::*;
+        match mode {
+            ValueMode::Order(order) if order == Order::Ascending as i32 => {
+                Ok(FirestoreIndexFieldMode::Ascending)
+            }
+            ValueMode::Order(order) if order == Order::Descending as i32 => {
+                Ok(FirestoreIndexFieldMode::Descending)
+            }
+            ValueMode::ArrayConfig(config) if config == ArrayConfig::Contains as i32 => {
+                Ok(FirestoreIndexFieldMode::ArrayContains)
+            }
+            ValueMode::VectorConfig(config) => Ok(FirestoreIndexFieldMode::Vector {
+                dimension: config.dimension.max(0) as u32,
+            }),
+            other => Err(invalid_admin_response(format!(
+                "Unsupported index field mode: {other:?}"
+            ))),
+        }
+    }
+}
+
+impl From<&FirestoreIndexField> for admin::index::IndexField {
+    fn from(field: &FirestoreIndexField) -> Self {
+        admin::index::IndexField {
+            field_path: field.field_path.clone(),
+            value_mode: Some((&field.mode).into()),
+        }
+    }
+}
+
+impl TryFrom<admin::index::IndexField> for FirestoreIndexField {
+    type Error = FirestoreError;
+
+    fn try_from(field: admin::index::IndexField) -> Result<Self, Self::Error> {
+        let mode = field.value_mode.ok_or_else(|| {
+            invalid_admin_response(format!(
+                "Index field {} has no value mode",
+                field.field_path
+            ))
+        })?;
+        Ok(FirestoreIndexField::new(field.field_path, mode.try_into()?))
+    }
+}
+
+impl From<&FirestoreIndex> for admin::Index {
+    fn from(index: &FirestoreIndex) -> Self {
+        admin::Index {
+            query_scope: admin::index::QueryScope::from(index.query_scope).into(),
+            fields: index.fields.iter().map(|field| field.into()).collect(),
+            ..Default::default()
+        }
+    }
+}
+
+impl TryFrom<admin::Index> for FirestoreIndex {
+    type Error = FirestoreError;
+
+    fn try_from(index: admin::Index) -> Result<Self, Self::Error> {
+        let collection_group = collection_group_from_name(&index.name)
+            .ok_or_else(|| invalid_admin_response(format!("Invalid index name: {}", index.name)))?
+            .to_string();
+
+        let state = match admin::index::State::try_from(index.state) {
+            Ok(admin::index::State::Creating) => Some(FirestoreIndexState::Creating),
+            Ok(admin::index::State::Ready) => Some(FirestoreIndexState::Ready),
+            Ok(admin::index::State::NeedsRepair) => Some(FirestoreIndexState::NeedsRepair),
+            _ => None,
+        };
+
+        Ok(FirestoreIndex {
+            collection_group,
+            fields: index
+                .fields
+                .into_iter()
+                .map(|field| field.try_into())
+                .collect::<FirestoreResult<Vec<_>>>()?,
+            query_scope: query_scope_from_proto(index.query_scope)?,
+            name: Some(index.name),
+            state,
+        })
+    }
+}
+
+fn single_field_index_to_proto(
+    field_path: &str,
+    index: &FirestoreSingleFieldIndex,
+) -> admin::Index {
+    admin::Index {
+        query_scope: admin::index::QueryScope::from(index.query_scope).into(),
+        fields: vec![admin::index::IndexField {
+            field_path: field_path.to_string(),
+            value_mode: Some((&index.mode).into()),
+        }],
+        ..Default::default()
+    }
+}
+
+impl TryFrom<admin::Field> for FirestoreFieldConfig {
+    type Error = FirestoreError;
+
+    fn try_from(field: admin::Field) -> Result<Self, Self::Error> {
+        let (collection_group, field_path) = collection_group_from_name(&field.name)
+            .zip(field.name.rsplit_once("/fields/").map(|(_, path)| path))
+            .ok_or_else(|| invalid_admin_response(format!("Invalid field name: {}", field.name)))?;
+
+        let index_config = field.index_config.unwrap_or_default();
+        let indexes = index_config
+            .indexes
+            .into_iter()
+            .map(|index| {
+                let query_scope = query_scope_from_proto(index.query_scope)?;
+                let mode = index
+                    .fields
+                    .into_iter()
+                    .next()
+                    .and_then(|field| field.value_mode)
+                    .ok_or_else(|| {
+                        invalid_admin_response(format!(
+                            "Single-field index of {} has no value mode",
+                            field.name
+                        ))
+                    })?
+                    .try_into()?;
+                Ok(FirestoreSingleFieldIndex::new(mode).with_query_scope(query_scope))
+            })
+            .collect::<FirestoreResult<Vec<_>>>()?;
+
+        let ttl_state = field.ttl_config.and_then(|ttl| {
+            match admin::field::ttl_config::State::try_from(ttl.state) {
+                Ok(admin::field::ttl_config::State::Creating) => Some(FirestoreTtlState::Creating),
+                Ok(admin::field::ttl_config::State::Active) => Some(FirestoreTtlState::Active),
+                Ok(admin::field::ttl_config::State::NeedsRepair) => {
+                    Some(FirestoreTtlState::NeedsRepair)
+                }
+                _ => None,
+            }
+        });
+
+        Ok(FirestoreFieldConfig {
+            collection_group: collection_group.to_string(),
+            field_path: field_path.to_string(),
+            indexes,
+            uses_ancestor_config: index_config.uses_ancestor_config,
+            ttl_state,
+        })
+    }
+}
+
+fn operation_response<M>(response: Option<Any>, type_name: &str) -> FirestoreResult<M>
+where
+    M: gcloud_sdk::prost::Message + Default,
+{
+    let response = response.ok_or_else(|| {
+        invalid_admin_response(format!("Operation finished without a {type_name} response"))
+    })?;
+    decode_any_message(&response, type_name)
+}
+
+impl FirestoreOperationResponse for FirestoreIndex {
+    fn from_operation_response(response: Option<Any>) -> FirestoreResult<Self> {
+        operation_response::<admin::Index>(response, "google.firestore.admin.v1.Index")?.try_into()
+    }
+}
+
+impl FirestoreOperationResponse for FirestoreFieldConfig {
+    fn from_operation_response(response: Option<Any>) -> FirestoreResult<Self> {
+        operation_response::<admin::Field>(response, "google.firestore.admin.v1.Field")?.try_into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_to_and_from_proto() {
+        let index = FirestoreIndex::new(
+            "users".to_string(),
+            vec![
+                FirestoreIndexField::new("age".to_string(), FirestoreIndexFieldMode::Ascending),
+                FirestoreIndexField::new(
+                    "tags".to_string(),
+                    FirestoreIndexFieldMode::ArrayContains,
+                ),
+                FirestoreIndexField::new(
+                    "embedding".to_string(),
+                    FirestoreIndexFieldMode::Vector { dimension: 3 },
+                ),
+            ],
+        )
+        .with_query_scope(FirestoreIndexQueryScope::CollectionGroup);
+
+        let mut proto: admin::Index = (&index).into();
+        assert_eq!(
+            proto.query_scope,
+            admin::index::QueryScope::CollectionGroup as i32
+        );
+        assert_eq!(proto.fields.len(), 3);
+
+        proto.name = "projects/p/databases/(default)/collectionGroups/users/indexes/CICAgJiUpoMK"
+            .to_string();
+        proto.state = admin::index::State::Ready.into();
+
+        let parsed: FirestoreIndex = proto.try_into().unwrap();
+        assert_eq!(parsed.collection_group, "users");
+        assert_eq!(parsed.fields, index.fields);
+        assert_eq!(
+            parsed.query_scope,
+            FirestoreIndexQueryScope::CollectionGroup
+        );
+        assert_eq!(parsed.state, Some(FirestoreIndexState::Ready));
+        assert_eq!(parsed.index_id(), Some("CICAgJiUpoMK"));
+    }
+
+    #[test]
+    fn field_config_from_proto() {
+        let field = admin::Field {
+            name: "projects/p/databases/(default)/collectionGroups/events/fields/expire_at"
+                .to_string(),
+            index_config: Some(admin::field::IndexConfig {
+                indexes: vec![single_field_index_to_proto(
+                    "expire_at",
+                    &FirestoreSingleFieldIndex::new(FirestoreIndexFieldMode::Descending),
+                )],
+                uses_ancestor_config: false,
+                ..Default::default()
+            }),
+            ttl_config: Some(admin::field::TtlConfig {
+                state: admin::field::ttl_config::State::Active.into(),
+            }),
+        };
+
+        let config: FirestoreFieldConfig = field.try_into().unwrap();
+        assert_eq!(config.collection_group, "events");
+        assert_eq!(config.field_path, "expire_at");
+        assert_eq!(
+            config.indexes,
+            vec![FirestoreSingleFieldIndex::new(
+                FirestoreIndexFieldMode::Descending
+            )]
+        );
+        assert!(!config.uses_ancestor_config);
+        assert_eq!(config.ttl_state, Some(FirestoreTtlState::Active));
+    }
+}
@@ -0,0 +1,64 @@
+use crate::FirestoreDb;
+use gcloud_sdk::google::firestore::admin::v1::firestore_admin_client::FirestoreAdminClient;
+use gcloud_sdk::google::longrunning::operations_client::OperationsClient;
+use gcloud_sdk::GoogleAuthMiddleware;
+
+/// Composite index and single-field index configuration management.
+mod indexes;
+pub use indexes::*;
+
+/// Typed handles to long-running admin operations.
+mod operation;
+pub use operation::*;
+
+/// Managed exports and imports of documents to and from Cloud Storage.
+mod export_import;
+pub use export_import::*;
+
+/// A typed wrapper over the Firestore Admin API for the database of a [`FirestoreDb`] instance.
+///
+/// It shares the authenticated gRPC channels (and so the credentials) of the `FirestoreDb`
+/// it was created from. Use [`FirestoreDb::admin`] to create one.
+#[derive(Clone)]
+pub struct FirestoreAdmin {
+    db: FirestoreDb,
+}
+
+impl FirestoreAdmin {
+    pub(crate) fn new(db: FirestoreDb) -> Self {
+        Self { db }
+    }
+
+    /// Returns the underlying Firestore Admin gRPC client.
+    pub fn client(&self) -> FirestoreAdminClient<GoogleAuthMiddleware> {
+        FirestoreAdminClient::new(self.db.client().next_channel())
+    }
+
+    pub(crate) fn operations_client(&self) -> OperationsClient<GoogleAuthMiddleware> {
+        OperationsClient::new(self.db.client().next_channel())
+    }
+
+    /// The database path this admin client operates on,
+    /// e.g. `projects/my-project/databases/(default)`.
+    pub fn database_path(&self) -> &str {
+        self.db.get_database_path().as_str()
+    }
+
+    pub(crate) fn collection_group_path(&self, collection_group: &str) -> String {
+        format!(
+            "{}/collectionGroups/{}",
+            self.database_path(),
+            collection_group
+        )
+    }
+}
+
+impl FirestoreDb {
+    /// Returns a [`FirestoreAdmin`] for managing indexes and other
+    /// administrative settings of this database.
+    ///
+    /// This is only available if the `admin` feature is enabled.
+    pub fn admin(&self) -> FirestoreAdmin {
+        FirestoreAdmin::new(self.clone())
+    }
+}
@@ -0,0 +1,369 @@
+use crate::admin::FirestoreAdmin;
+use crate::errors::*;
+use crate::timestamp_utils::from_timestamp;
+use crate::FirestoreResult;
+use chrono::prelude::*;
+use gcloud_sdk::google::firestore::admin::v1 as admin;
+use gcloud_sdk::google::longrunning::{
+    operation, CancelOperationRequest, GetOperationRequest, Operation,
+};
+use gcloud_sdk::prost_types::Any;
+use rsb_derive::Builder;
+use std::marker::PhantomData;
+use std::time::Duration;
+use tracing::*;
+
+/// Decodes the response of a finished long-running operation.
+pub trait FirestoreOperationResponse: Sized {
+    fn from_operation_response(response: Option<Any>) -> FirestoreResult<Self>;
+}
+
+impl FirestoreOperationResponse for () {
+    fn from_operation_response(_response: Option<Any>) -> FirestoreResult<Self> {
+        Ok(())
+    }
+}
+
+/// The state of a long-running operation as reported in its metadata.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FirestoreOperationState {
+    Unspecified,
+    Initializing,
+    Processing,
+    Cancelling,
+    Finalizing,
+    Successful,
+    Failed,
+    Cancelled,
+}
+
+impl From<i32> for FirestoreOperationState {
+    fn from(value: i32) -> Self {
+        match admin::OperationState::try_from(value) {
+            Ok(admin::OperationState::Initializing) => FirestoreOperationState::Initializing,
+            Ok(admin::OperationState::Processing) => FirestoreOperationState::Processing,
+            Ok(admin::OperationState::Cancelling) => FirestoreOperationState::Cancelling,
+            Ok(admin::OperationState::Finalizing) => FirestoreOperationState::Finalizing,
+            Ok(admin::OperationState::Successful) => FirestoreOperationState::Successful,
+            Ok(admin::OperationState::Failed) => FirestoreOperationState::Failed,
+            Ok(admin::OperationState::Cancelled) => FirestoreOperationState::Cancelled,
+            _ => FirestoreOperationState::Unspecified,
+        }
+    }
+}
+
+/// The estimated and completed amount of work of an operation.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Builder)]
+pub struct FirestoreOperationWork {
+    pub estimated: i64,
+    pub completed: i64,
+}
+
+impl FirestoreOperationWork {
+    /// The completed share of the work in the range `0.0..=1.0`, if the work is estimated.
+    pub fn ratio(&self) -> Option<f64> {
+        if self.estimated > 0 {
+            Some((self.completed as f64 / self.estimated as f64).clamp(0.0, 1.0))
+        } else {
+            None
+        }
+    }
+}
+
+impl From<admin::Progress> for FirestoreOperationWork {
+    fn from(progress: admin::Progress) -> Self {
+        FirestoreOperationWork::new(progress.estimated_work, progress.completed_work)
+    }
+}
+
+/// Progress of a long-running operation decoded from its metadata.
+///
+/// Decoded from export, import, index and field operation metadata.
+#[derive(Debug, PartialEq, Clone, Builder)]
+pub struct FirestoreOperationProgress {
+    pub state: FirestoreOperationState,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub documents: Option<FirestoreOperationWork>,
+    pub bytes: Option<FirestoreOperationWork>,
+}
+
+impl FirestoreOperationProgress {
+    fn from_parts(
+        state: i32,
+        start_time: Option<gcloud_sdk::prost_types::Timestamp>,
+        end_time: Option<gcloud_sdk::prost_types::Timestamp>,
+        documents: Option<admin::Progress>,
+        bytes: Option<admin::Progress>,
+    ) -> FirestoreResult<Self> {
+        Ok(FirestoreOperationProgress {
+            state: state.into(),
+            start_time: start_time.map(from_timestamp).transpose()?,
+            end_time: end_time.map(from_timestamp).transpose()?,
+            documents: documents.map(|progress| progress.into()),
+            bytes: bytes.map(|progress| progress.into()),
+        })
+    }
+
+    /// Decodes the progress from the metadata of an admin operation.
+    /// Returns `None` for unknown metadata types.
+    pub fn from_metadata(metadata: &Any) -> FirestoreResult<Option<Self>> {
+        let type_name = metadata.type_url.rsplit('/').next().unwrap_or_default();
+        match type_name {
+            "google.firestore.admin.v1.ExportDocumentsMetadata" => {
+                let m: admin::ExportDocumentsMetadata = decode_any_message(metadata, type_name)?;
+                Self::from_parts(
+                    m.operation_state,
+                    m.start_time,
+                    m.end_time,
+                    m.progress_documents,
+                    m.progress_bytes,
+                )
+                .map(Some)
+            }
+            "google.firestore.admin.v1.ImportDocumentsMetadata" => {
+                let m: admin::ImportDocumentsMetadata = decode_any_message(metadata, type_name)?;
+                Self::from_parts(
+                    m.operation_state,
+                    m.start_time,
+                    m.end_time,
+                    m.progress_documents,
+                    m.progress_bytes,
+                )
+                .map(Some)
+            }
+            "google.firestore.admin.v1.IndexOperationMetadata" => {
+                let m: admin::IndexOperationMetadata = decode_any_message(metadata, type_name)?;
+                Self::from_parts(
+                    m.state,
+                    m.start_time,
+                    m.end_time,
+                    m.progress_documents,
+                    m.progress_bytes,
+                )
+                .map(Some)
+            }
+            "google.firestore.admin.v1.FieldOperationMetadata" => {
+                let m: admin::FieldOperationMetadata = decode_any_message(metadata, type_name)?;
+                Self::from_parts(
+                    m.state,
+                    m.start_time,
+                    m.end_time,
+                    m.progress_documents,
+                    m.progress_bytes,
+                )
+                .map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// A handle to a long-running Firestore Admin operation, e.g. an export, an import or an index build.
+///
+/// The handle keeps the last known state of the operation,
+/// use [`refresh`](FirestoreOperation::refresh) or
+/// [`await_completion`](FirestoreOperation::await_completion) to poll for updates.
+pub struct FirestoreOperation<T> {
+    admin: FirestoreAdmin,
+    operation: Operation,
+    _response: PhantomData<T>,
+}
+
+impl<T> FirestoreOperation<T>
+where
+    T: FirestoreOperationResponse,
+{
+    pub(crate) fn new(admin: FirestoreAdmin, operation: Operation) -> Self {
+        Self {
+            admin,
+            operation,
+            _response: PhantomData,
+        }
+    }
+
+    /// The resource name of the operation.
+    pub fn name(&self) -> &str {
+        self.operation.name.as_str()
+    }
+
+    /// `true` if the operation finished, either successfully or with an error.
+    pub fn is_done(&self) -> bool {
+        self.operation.done
+    }
+
+    /// The last known raw state of the operation.
+    pub fn operation(&self) -> &Operation {
+        &self.operation
+    }
+
+    /// Reads the current state of the operation from Firestore.
+    pub async fn refresh(&mut self) -> FirestoreResult<()> {
+        self.operation = self
+            .admin
+            .operations_client()
+            .get_operation(self.admin.db.create_request(GetOperationRequest {
+                name: self.operation.name.clone(),
+            }))
+            .await?
+            .into_inner();
+        Ok(())
+    }
+
+    /// The last known progress of the operation, if reported by Firestore.
+    pub fn progress(&self) -> FirestoreResult<Option<FirestoreOperationProgress>> {
+        self.operation
+            .metadata
+            .as_ref()
+            .map(FirestoreOperationProgress::from_metadata)
+            .transpose()
+            .map(|progress| progress.flatten())
+    }
+
+    /// Requests cancellation of the operation.
+    ///
+    /// Cancellation is best-effort: the operation may still finish successfully,
+    /// otherwise its result is a `Cancelled` error. Work done before cancellation is not reverted.
+    pub async fn cancel(&self) -> FirestoreResult<()> {
+        self.admin
+            .operations_client()
+            .cancel_operation(self.admin.db.create_request(CancelOperationRequest {
+                name: self.operation.name.clone(),
+            }))
+            .await?;
+        debug!(
+            operation = self.operation.name,
+            "Requested operation cancellation."
+        );
+        Ok(())
+    }
+
+    /// The result of the operation if it is finished, `None` otherwise.
+    pub fn result(&self) -> Option<FirestoreResult<T>> {
+        if !self.operation.done {
+            return None;
+        }
+        Some(match &self.operation.result {
+            Some(operation::Result::Error(status)) => Err(gcloud_sdk::tonic::Status::new(
+                gcloud_sdk::tonic::Code::from(status.code),
+                status.message.clone(),
+            )
+            .into()),
+            Some(operation::Result::Response(response)) => {
+                T::from_operation_response(Some(response.clone()))
+            }
+            None => T::from_operation_response(None),
+        })
+    }
+
+    /// Polls the operation with the given interval until it is finished and returns its result.
+    ///
+    /// Fails if the operation isn't finished within `timeout`.
+    /// The operation itself keeps running in this case.
+    pub async fn await_completion(
+        mut self,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> FirestoreResult<T> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            if let Some(result) = self.result() {
+                return result;
+            }
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                return Err(FirestoreError::DatabaseError(FirestoreDatabaseError::new(
+                    FirestoreErrorPublicGenericDetails::new("DeadlineExceeded".into()),
+                    format!(
+                        "Operation {} has not finished in {:?}",
+                        self.operation.name, timeout
+                    ),
+                    false,
+                )));
+            }
+            tokio::time::sleep(poll_interval.min(deadline - now)).await;
+            self.refresh().await?;
+            debug!(
+                operation = self.operation.name,
+                done = self.operation.done,
+                "Polled a long-running operation."
+            );
+        }
+    }
+}
+
+impl FirestoreAdmin {
+    /// Reads a long-running operation by its name, e.g. to resume awaiting it.
+    pub async fn get_operation<T>(&self, name: &str) -> FirestoreResult<FirestoreOperation<T>>
+    where
+        T: FirestoreOperationResponse,
+    {
+        let operation = self
+            .operations_client()
+            .get_operation(self.db.create_request(GetOperationRequest {
+                name: name.to_string(),
+            }))
+            .await?
+            .into_inner();
+        Ok(FirestoreOperation::new(self.clone(), operation))
+    }
+}
+
+/// Decodes a protobuf message packed into `Any`, checking its type name.
+pub(crate) fn decode_any_message<M>(any: &Any, type_name: &str) -> FirestoreResult<M>
+where
+    M: gcloud_sdk::prost::Message + Default,
+{
+    if !any.type_url.ends_with(type_name) {
+        return Err(FirestoreError::DeserializeError(
+            FirestoreSerializationError::from_message(format!(
+                "Unexpected operation payload type {}, expected {}",
+                any.type_url, type_name
+            )),
+        ));
+    }
+    M::decode(any.value.as_slice()).map_err(|err| {
+        FirestoreError::DeserializeError(FirestoreSerializationError::from_message(format!(
+            "Unable to decode {type_name}: {err}"
+        )))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcloud_sdk::prost::Message;
+
+    #[test]
+    fn progress_from_index_metadata() {
+        let metadata = Any {
+            type_url: "type.googleapis.com/google.firestore.admin.v1.IndexOperationMetadata"
+                .to_string(),
+            value: admin::IndexOperationMetadata {
+                state: admin::OperationState::Processing.into(),
+                progress_documents: Some(admin::Progress {
+                    estimated_work: 200,
+                    completed_work: 50,
+                }),
+                ..Default::default()
+            }
+            .encode_to_vec(),
+        };
+
+        let progress = FirestoreOperationProgress::from_metadata(&metadata)
+            .unwrap()
+            .unwrap();
+        assert_eq!(progress.state, FirestoreOperationState::Processing);
+        assert_eq!(progress.documents.and_then(|work| work.ratio()), Some(0.25));
+        assert_eq!(progress.bytes, None);
+
+        let unknown = Any {
+            type_url: "type.googleapis.com/google.protobuf.Empty".to_string(),
+            value: vec![],
+        };
+        assert_eq!(
+            FirestoreOperationProgress::from_metadata(&unknown).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,682 @@
+//! Building and parsing Firestore data bundles.
+//!
+//! A bundle is a serialized set of named queries and document snapshots that can be
+//! distributed (e.g. via a CDN) and loaded by Firestore client SDKs without hitting the database.
+//! Each element of a bundle is a JSON object prefixed with its length in bytes.
+
+use crate::errors::*;
+use crate::firestore_serde::proto_json::*;
+use crate::{FirestoreDb, FirestoreQueryParams, FirestoreQuerySupport, FirestoreResult};
+use chrono::{DateTime, Utc};
+use gcloud_sdk::google::firestore::v1::{Document, StructuredQuery};
+use serde::Deserialize;
+use serde_json::{json, Map, Value as JsonValue};
+use std::collections::HashMap;
+
+fn bundle_format_error<S: AsRef<str>>(message: S) -> FirestoreError {
+    FirestoreError::DeserializeError(FirestoreSerializationError::from_message(format!(
+        "Invalid bundle: {}",
+        message.as_ref()
+    )))
+}
+
+/// The version of the bundle format produced by [`FirestoreBundleBuilder`].
+pub const FIRESTORE_BUNDLE_VERSION: u32 = 1;
+
+/// Metadata describing a bundle.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FirestoreBundleMetadata {
+    /// The ID of the bundle.
+    pub id: String,
+    /// The latest read time of the queries and documents in the bundle.
+    pub create_time: DateTime<Utc>,
+    /// The version of the bundle format.
+    pub version: u32,
+    /// The number of documents in the bundle.
+    pub total_documents: u32,
+    /// The number of bytes in the bundle following the metadata element.
+    pub total_bytes: u64,
+}
+
+/// Specifies how the results of a named query should be limited when loaded.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum FirestoreBundleQueryLimitType {
+    /// The query results are limited to the first documents.
+    First,
+    /// The query results are limited to the last documents (`limitToLast` in client SDKs).
+    Last,
+}
+
+impl FirestoreBundleQueryLimitType {
+    fn as_str_name(&self) -> &'static str {
+        match self {
+            FirestoreBundleQueryLimitType::First => "FIRST",
+            FirestoreBundleQueryLimitType::Last => "LAST",
+        }
+    }
+}
+
+/// A query stored in a bundle by name.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FirestoreBundleNamedQuery {
+    /// The name of the query.
+    pub name: String,
+    /// The parent resource path of the query.
+    pub parent: String,
+    /// The structured query in its JSON representation.
+    pub structured_query: JsonValue,
+    /// How the results of the query are limited.
+    pub limit_type: FirestoreBundleQueryLimitType,
+    /// The time the query results were read.
+    pub read_time: DateTime<Utc>,
+}
+
+impl FirestoreBundleNamedQuery {
+    /// Parses the stored JSON representation into a [`StructuredQuery`],
+    /// e.g. to run it with [`FirestoreDb::query_raw_at`] under [`parent`](Self::parent).
+    pub fn to_structured_query(&self) -> FirestoreResult<StructuredQuery> {
+        structured_query_from_json(&self.structured_query)
+    }
+}
+
+/// A document snapshot stored in a bundle.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FirestoreBundleDocument {
+    /// The full name of the document.
+    pub name: String,
+    /// The time the document was read.
+    pub read_time: DateTime<Utc>,
+    /// Whether the document existed at the read time.
+    pub exists: bool,
+    /// The names of the queries in the bundle this document matched.
+    pub queries: Vec<String>,
+    /// The document itself, if it exists.
+    pub document: Option<Document>,
+}
+
+/// A parsed Firestore bundle.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FirestoreBundle {
+    /// The bundle metadata.
+    pub metadata: FirestoreBundleMetadata,
+    /// The named queries in the bundle.
+    pub named_queries: Vec<FirestoreBundleNamedQuery>,
+    /// The documents in the bundle, in the order they were stored.
+    pub documents: Vec<FirestoreBundleDocument>,
+}
+
+impl FirestoreBundle {
+    /// Parses a bundle from its wire format.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing the parsed bundle or a
+    /// [`FirestoreError::DeserializeError`] if the bundle is malformed.
+    pub fn parse(bytes: &[u8]) -> FirestoreResult<Self> {
+        let mut elements = read_bundle_elements(bytes)?.into_iter();
+
+        let metadata = match elements.next() {
+            Some(element) => match element.get("metadata") {
+                Some(metadata) => metadata_from_json(metadata)?,
+                None => return Err(bundle_format_error("The first element must be metadata")),
+            },
+            None => return Err(bundle_format_error("Empty bundle")),
+        };
+
+        let mut named_queries = Vec::new();
+        let mut documents: Vec<FirestoreBundleDocument> = Vec::new();
+
+        for element in elements {
+            if let Some(named_query) = element.get("namedQuery") {
+                named_queries.push(named_query_from_json(named_query)?);
+            } else if let Some(doc_metadata) = element.get("documentMetadata") {
+                documents.push(document_metadata_from_json(doc_metadata)?);
+            } else if let Some(doc_json) = element.get("document") {
+                let doc = document_from_json(doc_json)?;
+                match documents.last_mut() {
+                    Some(bundle_doc) if bundle_doc.name == doc.name && bundle_doc.exists => {
+                        bundle_doc.document = Some(doc);
+                    }
+                    _ => {
+                        return Err(bundle_format_error(format!(
+                            "Document {} without preceding metadata",
+                            doc.name
+                        )))
+                    }
+                }
+            } else {
+                return Err(bundle_format_error(format!(
+                    "Unknown element: {:?}",
+                    element.keys().collect::<Vec<_>>()
+                )));
+            }
+        }
+
+        if let Some(doc) = documents
+            .iter()
+            .find(|doc| doc.exists && doc.document.is_none())
+        {
+            return Err(bundle_format_error(format!(
+                "Existing document {} without contents",
+                doc.name
+            )));
+        }
+
+        Ok(Self {
+            metadata,
+            named_queries,
+            documents,
+        })
+    }
+
+    /// Returns the named query with the specified name.
+    pub fn named_query(&self, name: &str) -> Option<&FirestoreBundleNamedQuery> {
+        self.named_queries.iter().find(|query| query.name == name)
+    }
+
+    /// Returns the existing documents matched by the named query, in bundle order.
+    pub fn query_documents(&self, query_name: &str) -> Vec<&Document> {
+        self.documents
+            .iter()
+            .filter(|doc| doc.queries.iter().any(|name| name == query_name))
+            .filter_map(|doc| doc.document.as_ref())
+            .collect()
+    }
+
+    /// Deserializes all existing documents of the bundle into objects of type `T`.
+    pub fn documents_obj<T>(&self) -> FirestoreResult<Vec<T>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        self.documents
+            .iter()
+            .filter_map(|doc| doc.document.as_ref())
+            .map(FirestoreDb::deserialize_doc_to)
+            .collect()
+    }
+
+    /// Deserializes the documents matched by the named query into objects of type `T`.
+    pub fn query_results_obj<T>(&self, query_name: &str) -> FirestoreResult<Vec<T>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        self.query_documents(query_name)
+            .into_iter()
+            .map(FirestoreDb::deserialize_doc_to)
+            .collect()
+    }
+}
+
+/// A builder of Firestore bundles.
+///
+/// Documents added several times (e.g. matched by multiple queries) are stored once
+/// with the latest read time.
+///
+/// # Examples
+/// ```rust,no_run
+/// # use firestore::*;
+/// # async fn run(db: &FirestoreDb) -> FirestoreResult<()> {
+/// let mut bundle_builder = FirestoreBundleBuilder::new("latest-news");
+/// bundle_builder
+///     .add_query(
+///         db,
+///         "latest-news-query",
+///         FirestoreQueryParams::new("news".into()).with_limit(10),
+///     )
+///     .await?;
+/// let bundle: Vec<u8> = bundle_builder.build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FirestoreBundleBuilder {
+    id: String,
+    named_queries: Vec<FirestoreBundleNamedQuery>,
+    documents: Vec<FirestoreBundleDocument>,
+    document_indices: HashMap<String, usize>,
+}
+
+impl FirestoreBundleBuilder {
+    /// Creates a new empty bundle builder with the specified bundle ID.
+    pub fn new<S: AsRef<str>>(id: S) -> Self {
+        Self {
+            id: id.as_ref().to_string(),
+            named_queries: Vec::new(),
+            documents: Vec::new(),
+            document_indices: HashMap::new(),
+        }
+    }
+
+    /// Adds a document snapshot to the bundle.
+    pub fn add_document(&mut self, doc: Document, read_time: DateTime<Utc>) -> &mut Self {
+        self.upsert_document(doc, read_time, None);
+        self
+    }
+
+    /// Adds a named query and its result documents to the bundle.
+    ///
+    /// # Arguments
+    /// * `name`: The name client SDKs use to look up the query.
+    /// * `params`: The query parameters. `params.parent` must be specified as the full parent path.
+    /// * `read_time`: The time the query results were read.
+    /// * `docs`: The query results.
+    pub fn add_named_query<S: AsRef<str>>(
+        &mut self,
+        name: S,
+        params: FirestoreQueryParams,
+        read_time: DateTime<Utc>,
+        docs: Vec<Document>,
+    ) -> FirestoreResult<&mut Self> {
+        let name = name.as_ref().to_string();
+        if self.named_queries.iter().any(|query| query.name == name) {
+            return Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "name".to_string(),
+                    format!("Query {name} is already in the bundle"),
+                )),
+            ));
+        }
+
+        let parent = params.parent.clone().ok_or_else(|| {
+            FirestoreError::InvalidParametersError(FirestoreInvalidParametersError::new(
+                FirestoreInvalidParametersPublicDetails::new(
+                    "parent".to_string(),
+                    "Bundled queries require a parent path".to_string(),
+                ),
+            ))
+        })?;
+
+        let structured_query = structured_query_to_json(&StructuredQuery::try_from(
+            params.resolve_document_ids(&parent),
+        )?)?;
+
+        self.named_queries.push(FirestoreBundleNamedQuery {
+            name: name.clone(),
+            parent,
+            structured_query,
+            limit_type: FirestoreBundleQueryLimitType::First,
+            read_time,
+        });
+
+        for doc in docs {
+            self.upsert_document(doc, read_time, Some(&name));
+        }
+
+        Ok(self)
+    }
+
+    /// Runs the query and adds it with its results to the bundle as a named query.
+    ///
+    /// If `params.parent` isn't specified, the documents path of the database is used.
+    pub async fn add_query<S: AsRef<str>>(
+        &mut self,
+        db: &FirestoreDb,
+        name: S,
+        params: FirestoreQueryParams,
+    ) -> FirestoreResult<&mut Self> {
+        let params = if params.parent.is_some() {
+            params
+        } else {
+            params.with_parent(db.get_documents_path().clone())
+        };
+        let read_time = Utc::now();
+        let docs = db.query_doc(params.clone()).await?;
+        self.add_named_query(name, params, read_time, docs)
+    }
+
+    /// Serializes the bundle into its wire format.
+    pub fn build(&self) -> FirestoreResult<Vec<u8>> {
+        let mut elements_bytes = Vec::new();
+
+        for named_query in &self.named_queries {
+            write_bundle_element(
+                &mut elements_bytes,
+                &json!({
+                    "namedQuery": {
+                        "name": named_query.name,
+                        "bundledQuery": {
+                            "parent": named_query.parent,
+                            "structuredQuery": named_query.structured_query,
+                            "limitType": named_query.limit_type.as_str_name()
+                        },
+                        "readTime": datetime_to_json(&named_query.read_time)
+                    }
+                }),
+            )?;
+        }
+
+        for bundle_doc in &self.documents {
+            write_bundle_element(
+                &mut elements_bytes,
+                &json!({
+                    "documentMetadata": {
+                        "name": bundle_doc.name,
+                        "readTime": datetime_to_json(&bundle_doc.read_time),
+                        "exists": bundle_doc.exists,
+                        "queries": bundle_doc.queries
+                    }
+                }),
+            )?;
+            if let Some(doc) = &bundle_doc.document {
+                write_bundle_element(
+                    &mut elements_bytes,
+                    &json!({ "document": document_to_json(doc)? }),
+                )?;
+            }
+        }
+
+        let create_time = self
+            .named_queries
+            .iter()
+            .map(|query| query.read_time)
+            .chain(self.documents.iter().map(|doc| doc.read_time))
+            .max()
+            .unwrap_or_else(Utc::now);
+
+        let mut bundle_bytes = Vec::with_capacity(elements_bytes.len() + 256);
+        write_bundle_element(
+            &mut bundle_bytes,
+            &json!({
+                "metadata": {
+                    "id": self.id,
+                    "createTime": datetime_to_json(&create_time),
+                    "version": FIRESTORE_BUNDLE_VERSION,
+                    "totalDocuments": self.documents.len(),
+                    "totalBytes": elements_bytes.len().to_string()
+                }
+            }),
+        )?;
+        bundle_bytes.extend(elements_bytes);
+        Ok(bundle_bytes)
+    }
+
+    fn upsert_document(
+        &mut self,
+        doc: Document,
+        read_time: DateTime<Utc>,
+        query_name: Option<&str>,
+    ) {
+        let index = match self.document_indices.get(&doc.name) {
+            Some(index) => {
+                let bundle_doc = &mut self.documents[*index];
+                if read_time >= bundle_doc.read_time {
+                    bundle_doc.read_time = read_time;
+                    bundle_doc.document = Some(doc);
+                }
+                *index
+            }
+            None => {
+                self.documents.push(FirestoreBundleDocument {
+                    name: doc.name.clone(),
+                    read_time,
+                    exists: true,
+                    queries: Vec::new(),
+                    document: None,
+                });
+                let index = self.documents.len() - 1;
+                self.document_indices.insert(doc.name.clone(), index);
+                self.documents[index].document = Some(doc);
+                index
+            }
+        };
+
+        if let Some(query_name) = query_name {
+            let queries = &mut self.documents[index].queries;
+            if !queries.iter().any(|name| name == query_name) {
+                queries.push(query_name.to_string());
+            }
+        }
+    }
+}
+
+fn write_bundle_element(bytes: &mut Vec<u8>, element: &JsonValue) -> FirestoreResult<()> {
+    let element_bytes = serde_json::to_vec(element).map_err(|err| {
+        FirestoreError::SerializeError(FirestoreSerializationError::from_message(format!(
+            "Unable to serialize bundle element: {err}"
+        )))
+    })?;
+    bytes.extend(element_bytes.len().to_string().as_bytes());
+    bytes.extend(element_bytes);
+    Ok(())
+}
+
+fn read_bundle_elements(bytes: &[u8]) -> FirestoreResult<Vec<Map<String, JsonValue>>> {
+    let mut elements = Vec::new();
+    let mut position = 0;
+
+    while position < bytes.len() {
+        let length_end = bytes[position..]
+            .iter()
+            .position(|byte| !byte.is_ascii_digit())
+            .map(|offset| position + offset)
+            .unwrap_or(bytes.len());
+
+        let length: usize = std::str::from_utf8(&bytes[position..length_end])
+            .ok()
+            .and_then(|length_str| length_str.parse().ok())
+            .ok_or_else(|| {
+                bundle_format_error(format!("Missing element length at byte {position}"))
+            })?;
+
+        let element_end = length_end
+            .checked_add(length)
+            .filter(|element_end| *element_end <= bytes.len())
+            .ok_or_else(|| bundle_format_error(format!("Truncated element at byte {position}")))?;
+
+        let element: Map<String, JsonValue> =
+            serde_json::from_slice(&bytes[length_end..element_end]).map_err(|err| {
+                bundle_format_error(format!("Invalid element at byte {position}: {err}"))
+            })?;
+        elements.push(element);
+        position = element_end;
+    }
+
+    Ok(elements)
+}
+
+fn json_string(json: &JsonValue, field_name: &str) -> FirestoreResult<String> {
+    json.get(field_name)
+        .and_then(|value| value.as_str())
+        .map(|value| value.to_string())
+        .ok_or_else(|| bundle_format_error(format!("Missing {field_name} in {json}")))
+}
+
+fn json_u64(json: &JsonValue, field_name: &str) -> FirestoreResult<u64> {
+    match json.get(field_name) {
+        Some(JsonValue::String(str_value)) => str_value.parse().ok(),
+        Some(value) => value.as_u64(),
+        None => Some(0),
+    }
+    .ok_or_else(|| bundle_format_error(format!("Invalid {field_name} in {json}")))
+}
+
+fn json_datetime(json: &JsonValue, field_name: &str) -> FirestoreResult<DateTime<Utc>> {
+    json.get(field_name)
+        .ok_or_else(|| bundle_format_error(format!("Missing {field_name} in {json}")))
+        .and_then(datetime_from_json)
+}
+
+fn metadata_from_json(json: &JsonValue) -> FirestoreResult<FirestoreBundleMetadata> {
+    Ok(FirestoreBundleMetadata {
+        id: json_string(json, "id")?,
+        create_time: json_datetime(json, "createTime")?,
+        version: json_u64(json, "version")? as u32,
+        total_documents: json_u64(json, "totalDocuments")? as u32,
+        total_bytes: json_u64(json, "totalBytes")?,
+    })
+}
+
+fn named_query_from_json(json: &JsonValue) -> FirestoreResult<FirestoreBundleNamedQuery> {
+    let bundled_query = json
+        .get("bundledQuery")
+        .ok_or_else(|| bundle_format_error(format!("Missing bundledQuery in {json}")))?;
+
+    let limit_type = match bundled_query.get("limitType").and_then(|v| v.as_str()) {
+        Some("LAST") => FirestoreBundleQueryLimitType::Last,
+        _ => FirestoreBundleQueryLimitType::First,
+    };
+
+    Ok(FirestoreBundleNamedQuery {
+        name: json_string(json, "name")?,
+        parent: json_string(bundled_query, "parent")?,
+        structured_query: bundled_query
+            .get("structuredQuery")
+            .cloned()
+            .unwrap_or(JsonValue::Null),
+        limit_type,
+        read_time: json_datetime(json, "readTime")?,
+    })
+}
+
+fn document_metadata_from_json(json: &JsonValue) -> FirestoreResult<FirestoreBundleDocument> {
+    Ok(FirestoreBundleDocument {
+        name: json_string(json, "name")?,
+        read_time: json_datetime(json, "readTime")?,
+        exists: json
+            .get("exists")
+            .and_then(|exists| exists.as_bool())
+            .unwrap_or(false),
+        queries: json
+            .get("queries")
+            .and_then(|queries| queries.as_array())
+            .map(|queries| {
+                queries
+                    .iter()
+                    .filter_map(|query| query.as_str().map(|query| query.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        document: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FirestoreQueryDirection, FirestoreQueryFilter, FirestoreQueryFilterCompare};
+    use chrono::TimeZone;
+    use gcloud_sdk::google::firestore::v1::{value, Value};
+    use serde::Serialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestNews {
+        title: String,
+        rank: i64,
+    }
+
+    fn test_doc(id: &str, title: &str, rank: i64) -> Document {
+        Document {
+            name: format!("projects/test/databases/(default)/documents/news/{id}"),
+            fields: [
+                (
+                    "title".to_string(),
+                    Value {
+                        value_type: Some(value::ValueType::StringValue(title.to_string())),
+                    },
+                ),
+                (
+                    "rank".to_string(),
+                    Value {
+                        value_type: Some(value::ValueType::IntegerValue(rank)),
+                    },
+                ),
+            ]
+            .into(),
+            create_time: None,
+            update_time: None,
+        }
+    }
+
+    #[test]
+    fn bundle_build_parse_roundtrip() {
+        let read_time = Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap();
+        let later_read_time = Utc.with_ymd_and_hms(2024, 5, 1, 11, 0, 0).unwrap();
+
+        let params = FirestoreQueryParams::new("news".into())
+            .with_parent("projects/test/databases/(default)/documents".to_string())
+            .with_filter(FirestoreQueryFilter::Compare(Some(
+                FirestoreQueryFilterCompare::GreaterThan("rank".to_string(), 1.into()),
+            )))
+            .with_order_by(vec![("rank", FirestoreQueryDirection::Descending).into()])
+            .with_limit(10);
+
+        let mut builder = FirestoreBundleBuilder::new("news-bundle");
+        builder
+            .add_named_query(
+                "top-news",
+                params,
+                read_time,
+                vec![test_doc("a", "A", 3), test_doc("b", "B", 2)],
+            )
+            .unwrap();
+        builder.add_document(test_doc("a", "A updated", 3), later_read_time);
+
+        let bundle_bytes = builder.build().unwrap();
+        assert!(bundle_bytes.starts_with(b"1"));
+
+        let bundle = FirestoreBundle::parse(&bundle_bytes).unwrap();
+        assert_eq!(bundle.metadata.id, "news-bundle");
+        assert_eq!(bundle.metadata.create_time, later_read_time);
+        assert_eq!(bundle.metadata.version, FIRESTORE_BUNDLE_VERSION);
+        assert_eq!(bundle.metadata.total_documents, 2);
+
+        let metadata_len = bundle_bytes.len() as u64 - bundle.metadata.total_bytes;
+        assert!(metadata_len > 0 && metadata_len < 200);
+
+        let query = bundle.named_query("top-news").unwrap();
+        assert_eq!(query.parent, "projects/test/databases/(default)/documents");
+        assert_eq!(query.limit_type, FirestoreBundleQueryLimitType::First);
+        assert_eq!(query.read_time, read_time);
+        assert_eq!(
+            query.structured_query["from"],
+            json!([{ "collectionId": "news", "allDescendants": false }])
+        );
+        assert_eq!(
+            query.structured_query["where"]["fieldFilter"]["op"],
+            json!("GREATER_THAN")
+        );
+        assert_eq!(
+            query.structured_query["orderBy"][0]["direction"],
+            json!("DESCENDING")
+        );
+        assert_eq!(query.structured_query["limit"], json!(10));
+
+        assert_eq!(bundle.documents[0].read_time, later_read_time);
+        assert_eq!(bundle.documents[0].queries, vec!["top-news".to_string()]);
+
+        let news: Vec<TestNews> = bundle.query_results_obj("top-news").unwrap();
+        assert_eq!(
+            news,
+            vec![
+                TestNews {
+                    title: "A updated".to_string(),
+                    rank: 3
+                },
+                TestNews {
+                    title: "B".to_string(),
+                    rank: 2
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn named_query_requires_parent() {
+        let mut builder = FirestoreBundleBuilder::new("test");
+        assert!(builder
+            .add_named_query(
+                "query",
+                FirestoreQueryParams::new("news".into()),
+                Utc::now(),
+                vec![]
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn malformed_bundles_are_rejected() {
+        assert!(FirestoreBundle::parse(b"").is_err());
+        assert!(FirestoreBundle::parse(b"100{\"metadata\":{}}").is_err());
+        assert!(FirestoreBundle::parse(b"{\"metadata\":{}}").is_err());
+        assert!(FirestoreBundle::parse(b"2{}").is_err());
+    }
+}
@@ -6,8 +6,15 @@ use futures::stream::BoxStream;
 use moka::future::{Cache, CacheBuilder};
 
 use crate::cache::cache_query_engine::FirestoreCacheQueryEngine;
-use futures::StreamExt;
+use futures::{StreamExt, TryStreamExt};
+use gcloud_sdk::prost::Message;
+use moka::notification::RemovalCause;
+use moka::policy::EvictionPolicy;
+use rsb_derive::Builder;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::*;
 
 pub type FirestoreMemCache = Cache<String, FirestoreDocument>;
@@ -17,15 +24,219 @@ pub type FirestoreMemCacheOptions = CacheBuilder<String, FirestoreDocument, Fire
 pub struct FirestoreMemoryCacheBackend {
     pub config: FirestoreCacheConfiguration,
     collection_caches: HashMap<String, FirestoreMemCache>,
+    collection_counters: HashMap<String, Arc<FirestoreMemoryCacheCounters>>,
+    collection_query_results: HashMap<String, FirestoreMemoryQueryResults>,
 }
 
 const FIRESTORE_MEMORY_CACHE_DEFAULT_MAX_CAPACITY: u64 = 50000;
 
+/// The results of the queries of a collection by their cache keys.
+/// Every result is computed at a generation of the collection, changed on every change
+/// of the collection documents, and is used only at the same generation.
+struct FirestoreMemoryQueryResults {
+    results: Cache<String, (u64, Arc<Vec<FirestoreDocument>>)>,
+    generation: Arc<AtomicU64>,
+}
+
+/// The policy used to select documents to evict when a collection cache reaches its limits.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum FirestoreMemoryCacheEvictionPolicy {
+    /// Admits and evicts documents based on their access frequency and recency (the default).
+    TinyLfu,
+    /// Evicts the least recently used documents.
+    Lru,
+}
+
+/// Limits of a collection cache.
+///
+/// When `max_bytes` is specified, documents are weighted by their encoded size and
+/// `max_entries` is ignored, since only one capacity can be enforced.
+///
+/// The results of queries are reused until the collection changes only when
+/// `query_results_max_bytes` is specified, limiting the encoded size of the reused results.
+///
+/// Once a document of the collection is evicted or expired, the cache no longer contains
+/// the whole collection, so listing and querying the collection read from Firestore.
+#[derive(Debug, PartialEq, Clone, Builder)]
+pub struct FirestoreMemoryCacheLimits {
+    pub max_entries: Option<u64>,
+    pub max_bytes: Option<u64>,
+    pub time_to_live: Option<Duration>,
+    pub time_to_idle: Option<Duration>,
+    #[default = "FirestoreMemoryCacheEvictionPolicy::TinyLfu"]
+    pub eviction_policy: FirestoreMemoryCacheEvictionPolicy,
+    pub query_results_max_bytes: Option<u64>,
+}
+
+impl FirestoreMemoryCacheLimits {
+    fn to_mem_cache_options(&self) -> FirestoreMemCacheOptions {
+        let mut mem_options =
+            FirestoreMemCache::builder().eviction_policy(match self.eviction_policy {
+                FirestoreMemoryCacheEvictionPolicy::TinyLfu => EvictionPolicy::tiny_lfu(),
+                FirestoreMemoryCacheEvictionPolicy::Lru => EvictionPolicy::lru(),
+            });
+
+        if let Some(max_bytes) = self.max_bytes {
+            mem_options = mem_options
+                .weigher(|document_id: &String, doc: &FirestoreDocument| {
+                    u32::try_from(document_id.len() + doc.encoded_len()).unwrap_or(u32::MAX)
+                })
+                .max_capacity(max_bytes);
+        } else if let Some(max_entries) = self.max_entries {
+            mem_options = mem_options.max_capacity(max_entries);
+        }
+
+        if let Some(time_to_live) = self.time_to_live {
+            mem_options = mem_options.time_to_live(time_to_live);
+        }
+
+        if let Some(time_to_idle) = self.time_to_idle {
+            mem_options = mem_options.time_to_idle(time_to_idle);
+        }
+
+        mem_options
+    }
+
+    fn to_query_results(&self) -> Option<FirestoreMemoryQueryResults> {
+        self.query_results_max_bytes
+            .map(|max_bytes| FirestoreMemoryQueryResults {
+                results: Cache::builder()
+                    .weigher(
+                        |query_key: &String, (_, docs): &(u64, Arc<Vec<FirestoreDocument>>)| {
+                            let docs_len: usize = docs.iter().map(|doc| doc.encoded_len()).sum();
+                            u32::try_from(query_key.len() + docs_len).unwrap_or(u32::MAX)
+                        },
+                    )
+                    .max_capacity(max_bytes)
+                    .build(),
+                generation: Arc::new(AtomicU64::new(0)),
+            })
+    }
+}
+
+/// Options of the memory cache backend with the default limits and limits for specific collections.
+#[derive(Debug, PartialEq, Clone, Builder)]
+pub struct FirestoreMemoryCacheOptions {
+    #[default = "FirestoreMemoryCacheLimits::new().with_max_entries(FIRESTORE_MEMORY_CACHE_DEFAULT_MAX_CAPACITY)"]
+    pub default_limits: FirestoreMemoryCacheLimits,
+    #[default = "HashMap::new()"]
+    pub collection_limits: HashMap<String, FirestoreMemoryCacheLimits>,
+}
+
+impl FirestoreMemoryCacheOptions {
+    /// Specifies limits for a collection by its name or full collection path.
+    #[inline]
+    pub fn add_collection_limits<S>(
+        mut self,
+        collection: S,
+        limits: FirestoreMemoryCacheLimits,
+    ) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.collection_limits
+            .insert(collection.as_ref().to_string(), limits);
+        self
+    }
+
+    fn limits_for(
+        &self,
+        collection_path: &str,
+        collection_config: &FirestoreCacheCollectionConfiguration,
+    ) -> &FirestoreMemoryCacheLimits {
+        self.collection_limits
+            .get(collection_path)
+            .or_else(|| {
+                self.collection_limits
+                    .get(collection_config.collection_name.as_str())
+            })
+            .unwrap_or(&self.default_limits)
+    }
+}
+
+/// Statistics of the memory cache.
+///
+/// Entry counts and sizes are approximate, since the cache applies some of its
+/// maintenance tasks asynchronously.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub struct FirestoreMemoryCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entry_count: u64,
+    pub weighted_size: u64,
+    pub query_hits: u64,
+    pub query_misses: u64,
+}
+
+impl std::ops::Add for FirestoreMemoryCacheStats {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            hits: self.hits + rhs.hits,
+            misses: self.misses + rhs.misses,
+            evictions: self.evictions + rhs.evictions,
+            entry_count: self.entry_count + rhs.entry_count,
+            weighted_size: self.weighted_size + rhs.weighted_size,
+            query_hits: self.query_hits + rhs.query_hits,
+            query_misses: self.query_misses + rhs.query_misses,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct FirestoreMemoryCacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    query_hits: AtomicU64,
+    query_misses: AtomicU64,
+}
+
 impl FirestoreMemoryCacheBackend {
     pub fn new(config: FirestoreCacheConfiguration) -> FirestoreResult<Self> {
         Self::with_max_capacity(config, FIRESTORE_MEMORY_CACHE_DEFAULT_MAX_CAPACITY)
     }
 
+    pub fn with_options(
+        config: FirestoreCacheConfiguration,
+        options: FirestoreMemoryCacheOptions,
+    ) -> FirestoreResult<Self> {
+        let collection_limits: HashMap<String, FirestoreMemoryCacheLimits> = config
+            .collections
+            .iter()
+            .map(|(collection_path, collection_config)| {
+                (
+                    collection_path.clone(),
+                    options
+                        .limits_for(collection_path, collection_config)
+                        .clone(),
+                )
+            })
+            .collect();
+
+        let collection_query_results = collection_limits
+            .iter()
+            .filter_map(|(collection_path, limits)| {
+                limits
+                    .to_query_results()
+                    .map(|query_results| (collection_path.clone(), query_results))
+            })
+            .collect();
+
+        Self::with_collection_options_and_query_results(
+            config,
+            |collection_path| {
+                collection_limits
+                    .get(collection_path)
+                    .unwrap_or(&options.default_limits)
+                    .to_mem_cache_options()
+            },
+            collection_query_results,
+        )
+    }
+
     pub fn with_max_capacity(
         config: FirestoreCacheConfiguration,
         max_capacity: u64,
@@ -42,13 +253,47 @@ impl FirestoreMemoryCacheBackend {
     where
         FN: Fn(&str) -> FirestoreMemCacheOptions,
     {
-        let collection_caches = config
+        Self::with_collection_options_and_query_results(
+            config,
+            collection_mem_options,
+            HashMap::new(),
+        )
+    }
+
+    fn with_collection_options_and_query_results<FN>(
+        config: FirestoreCacheConfiguration,
+        collection_mem_options: FN,
+        collection_query_results: HashMap<String, FirestoreMemoryQueryResults>,
+    ) -> FirestoreResult<Self>
+    where
+        FN: Fn(&str) -> FirestoreMemCacheOptions,
+    {
+        let collection_counters: HashMap<String, Arc<FirestoreMemoryCacheCounters>> = config
             .collections
             .keys()
-            .map(|collection_path| {
+            .map(|collection_path| (collection_path.clone(), Arc::new(Default::default())))
+            .collect();
+
+        let collection_caches = collection_counters
+            .iter()
+            .map(|(collection_path, counters)| {
+                let counters = counters.clone();
+                let generation = collection_query_results
+                    .get(collection_path)
+                    .map(|query_results| query_results.generation.clone());
                 (
                     collection_path.clone(),
-                    collection_mem_options(collection_path.as_str()).build(),
+                    collection_mem_options(collection_path.as_str())
+                        .eviction_listener(move |_, _, cause: RemovalCause| {
+                            // Expired and evicted documents change the query results as well
+                            if let Some(generation) = &generation {
+                                generation.fetch_add(1, Ordering::Release);
+                            }
+                            if cause.was_evicted() {
+                                counters.evictions.fetch_add(1, Ordering::Relaxed);
+                            }
+                        })
+                        .build(),
                 )
             })
             .collect();
@@ -56,9 +301,60 @@ impl FirestoreMemoryCacheBackend {
         Ok(Self {
             config,
             collection_caches,
+            collection_counters,
+            collection_query_results,
+        })
+    }
+
+    /// Returns the statistics summed over all cached collections.
+    pub fn stats(&self) -> FirestoreMemoryCacheStats {
+        self.collection_caches
+            .keys()
+            .filter_map(|collection_path| self.collection_stats(collection_path))
+            .fold(FirestoreMemoryCacheStats::default(), |acc, stats| {
+                acc + stats
+            })
+    }
+
+    /// Returns the statistics of the collection cache by its full collection path.
+    pub fn collection_stats(&self, collection_path: &str) -> Option<FirestoreMemoryCacheStats> {
+        let mem_cache = self.collection_caches.get(collection_path)?;
+        let counters = self.collection_counters.get(collection_path)?;
+        Some(FirestoreMemoryCacheStats {
+            hits: counters.hits.load(Ordering::Relaxed),
+            misses: counters.misses.load(Ordering::Relaxed),
+            evictions: counters.evictions.load(Ordering::Relaxed),
+            entry_count: mem_cache.entry_count(),
+            weighted_size: mem_cache.weighted_size(),
+            query_hits: counters.query_hits.load(Ordering::Relaxed),
+            query_misses: counters.query_misses.load(Ordering::Relaxed),
         })
     }
 
+    /// Invalidates the query results of the collection.
+    /// Must be called after the documents of the collection are changed.
+    fn invalidate_query_results(&self, collection_path: &str) {
+        if let Some(query_results) = self.collection_query_results.get(collection_path) {
+            query_results.generation.fetch_add(1, Ordering::Release);
+            query_results.results.invalidate_all();
+        }
+    }
+
+    /// Checks if the cache still contains all documents of the collection,
+    /// i.e. no document was evicted or expired.
+    async fn is_collection_complete(&self, collection_path: &str) -> bool {
+        let mem_cache = match self.collection_caches.get(collection_path) {
+            Some(mem_cache) => mem_cache,
+            None => return false,
+        };
+        // Expired documents are removed (and reported to the eviction listener) lazily
+        mem_cache.run_pending_tasks().await;
+        self.collection_counters
+            .get(collection_path)
+            .map(|counters| counters.evictions.load(Ordering::Relaxed) == 0)
+            .unwrap_or(false)
+    }
+
     async fn preload_collections(&self, db: &FirestoreDb) -> Result<(), FirestoreError> {
         for (collection_path, config) in &self.config.collections {
             match config.collection_load_mode {
@@ -97,6 +393,7 @@ impl FirestoreMemoryCacheBackend {
                             .await;
 
                         mem_cache.run_pending_tasks().await;
+                        self.invalidate_query_results(collection_path);
 
                         info!(
                             collection_path = collection_path.as_str(),
@@ -116,23 +413,65 @@ impl FirestoreMemoryCacheBackend {
         collection_path: &str,
         query_engine: FirestoreCacheQueryEngine,
     ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocument>>> {
-        match self.collection_caches.get(collection_path) {
-            Some(mem_cache) => {
-                let filtered_results: Vec<FirestoreResult<FirestoreDocument>> = mem_cache
-                    .iter()
-                    .filter(|(_, doc)| query_engine.matches_doc(doc))
-                    .map(|(_, doc)| Ok(doc))
-                    .collect();
-
-                let filtered_stream = futures::stream::iter(filtered_results);
-                let output_stream = query_engine
-                    .process_query_stream(Box::pin(filtered_stream))
-                    .await?;
-
-                Ok(output_stream)
+        let mem_cache = match self.collection_caches.get(collection_path) {
+            Some(mem_cache) => mem_cache,
+            None => return Ok(Box::pin(futures::stream::empty())),
+        };
+        let query_results = match self.collection_query_results.get(collection_path) {
+            Some(query_results) => query_results,
+            None => return Self::filter_cached_docs(mem_cache, &query_engine).await,
+        };
+
+        let query_key = query_engine.query.cache_key();
+        let generation = query_results.generation.load(Ordering::Acquire);
+        let counters = self.collection_counters.get(collection_path);
+
+        if let Some((results_generation, docs)) = query_results.results.get(&query_key).await {
+            if results_generation == generation {
+                if let Some(counters) = counters {
+                    counters.query_hits.fetch_add(1, Ordering::Relaxed);
+                }
+                return Ok(Box::pin(futures::stream::iter(
+                    docs.iter().cloned().map(Ok).collect::<Vec<_>>(),
+                )));
             }
-            None => Ok(Box::pin(futures::stream::empty())),
         }
+        if let Some(counters) = counters {
+            counters.query_misses.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let docs: Vec<FirestoreDocument> = Self::filter_cached_docs(mem_cache, &query_engine)
+            .await?
+            .try_collect()
+            .await?;
+
+        // The results computed while the collection was changed are stale,
+        // but never used since their generation is outdated
+        let docs = Arc::new(docs);
+        query_results
+            .results
+            .insert(query_key, (generation, docs.clone()))
+            .await;
+
+        Ok(Box::pin(futures::stream::iter(
+            docs.iter().cloned().map(Ok).collect::<Vec<_>>(),
+        )))
+    }
+
+    async fn filter_cached_docs<'b>(
+        mem_cache: &FirestoreMemCache,
+        query_engine: &FirestoreCacheQueryEngine,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocument>>> {
+        let filtered_results: Vec<FirestoreResult<FirestoreDocument>> = mem_cache
+            .iter()
+            .filter(|(_, doc)| query_engine.matches_doc(doc))
+            .map(|(_, doc)| Ok(doc))
+            .collect();
+
+        let filtered_stream = futures::stream::iter(filtered_results);
+        query_engine
+            .process_query_stream(Box::pin(filtered_stream))
+            .await
     }
 }
 
@@ -172,6 +511,7 @@ impl FirestoreCacheBackend for FirestoreMemoryCacheBackend {
             debug!(collection_path, "Invalidating cache for collection.");
             mem_cache.invalidate_all();
             mem_cache.run_pending_tasks().await;
+            self.invalidate_query_results(collection_path);
         }
         Ok(())
     }
@@ -185,12 +525,15 @@ impl FirestoreCacheBackend for FirestoreMemoryCacheBackend {
             FirestoreListenEvent::DocumentChange(doc_change) => {
                 if let Some(doc) = doc_change.document {
                     let (collection_path, document_id) = split_document_path(&doc.name);
-                    if let Some(mem_cache) = self.collection_caches.get(collection_path) {
+                    let (collection_path, document_id) =
+                        (collection_path.to_string(), document_id.to_string());
+                    if let Some(mem_cache) = self.collection_caches.get(&collection_path) {
                         trace!(
                             doc_name = ?doc.name,
                             "Writing document to cache due to listener event.",
                         );
-                        mem_cache.insert(document_id.to_string(), doc).await;
+                        mem_cache.insert(document_id, doc).await;
+                        self.invalidate_query_results(&collection_path);
                     }
                 }
                 Ok(())
@@ -203,12 +546,20 @@ impl FirestoreCacheBackend for FirestoreMemoryCacheBackend {
                         "Removing document from cache due to listener event.",
                     );
                     mem_cache.remove(document_id).await;
+                    self.invalidate_query_results(collection_path);
                 }
                 Ok(())
             }
             _ => Ok(()),
         }
     }
+
+    fn collection_consistency(&self, collection_path: &str) -> Option<&FirestoreCacheConsistency> {
+        self.config
+            .collections
+            .get(collection_path)
+            .map(|collection_config| &collection_config.consistency)
+    }
 }
 
 #[async_trait]
@@ -220,7 +571,17 @@ impl FirestoreCacheDocsByPathSupport for FirestoreMemoryCacheBackend {
         let (collection_path, document_id) = split_document_path(document_path);
 
         match self.collection_caches.get(collection_path) {
-            Some(mem_cache) => Ok(mem_cache.get(document_id).await),
+            Some(mem_cache) => {
+                let maybe_doc = mem_cache.get(document_id).await;
+                if let Some(counters) = self.collection_counters.get(collection_path) {
+                    if maybe_doc.is_some() {
+                        counters.hits.fetch_add(1, Ordering::Relaxed);
+                    } else {
+                        counters.misses.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                Ok(maybe_doc)
+            }
             None => Ok(None),
         }
     }
@@ -233,17 +594,31 @@ impl FirestoreCacheDocsByPathSupport for FirestoreMemoryCacheBackend {
                 mem_cache
                     .insert(document_id.to_string(), document.clone())
                     .await;
+                self.invalidate_query_results(collection_path);
                 Ok(())
             }
             None => Ok(()),
         }
     }
 
+    async fn delete_doc_by_path(&self, document_path: &str) -> FirestoreResult<()> {
+        let (collection_path, document_id) = split_document_path(document_path);
+
+        if let Some(mem_cache) = self.collection_caches.get(collection_path) {
+            mem_cache.remove(document_id).await;
+            self.invalidate_query_results(collection_path);
+        }
+        Ok(())
+    }
+
     async fn list_all_docs<'b>(
         &self,
         collection_path: &str,
     ) -> FirestoreResult<FirestoreCachedValue<BoxStream<'b, FirestoreResult<FirestoreDocument>>>>
     {
+        if !self.is_collection_complete(collection_path).await {
+            return Ok(FirestoreCachedValue::SkipCache);
+        }
         match self.collection_caches.get(collection_path) {
             Some(mem_cache) => {
                 let all_docs: Vec<FirestoreResult<FirestoreDocument>> =
@@ -263,7 +638,9 @@ impl FirestoreCacheDocsByPathSupport for FirestoreMemoryCacheBackend {
     ) -> FirestoreResult<FirestoreCachedValue<BoxStream<'b, FirestoreResult<FirestoreDocument>>>>
     {
         let simple_query_engine = FirestoreCacheQueryEngine::new(query);
-        if simple_query_engine.params_supported() {
+        if simple_query_engine.params_supported()
+            && self.is_collection_complete(collection_path).await
+        {
             Ok(FirestoreCachedValue::UseCached(
                 self.query_cached_docs(collection_path, simple_query_engine)
                     .await?,
@@ -273,3 +650,190 @@ impl FirestoreCacheDocsByPathSupport for FirestoreMemoryCacheBackend {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_doc(collection_path: &str, id: &str) -> FirestoreDocument {
+        FirestoreDocument {
+            name: format!("{collection_path}/{id}"),
+            fields: HashMap::new(),
+            create_time: None,
+            update_time: None,
+        }
+    }
+
+    fn test_config(collection_path: &str) -> FirestoreCacheConfiguration {
+        let mut config = FirestoreCacheConfiguration::new();
+        config.collections.insert(
+            collection_path.to_string(),
+            FirestoreCacheCollectionConfiguration::new(
+                "test",
+                FirestoreListenerTarget::new(1),
+                FirestoreCacheCollectionLoadMode::PreloadNone,
+            ),
+        );
+        config
+    }
+
+    #[tokio::test]
+    async fn bounded_cache_evicts_and_counts_stats() -> FirestoreResult<()> {
+        let collection_path = "projects/test/databases/(default)/documents/test";
+        let backend = FirestoreMemoryCacheBackend::with_options(
+            test_config(collection_path),
+            FirestoreMemoryCacheOptions::new().add_collection_limits(
+                "test",
+                FirestoreMemoryCacheLimits::new()
+                    .with_max_entries(2)
+                    .with_eviction_policy(FirestoreMemoryCacheEvictionPolicy::Lru),
+            ),
+        )?;
+
+        for id in ["a", "b", "c"] {
+            backend
+                .update_doc_by_path(&test_doc(collection_path, id))
+                .await?;
+        }
+        backend.collection_caches[collection_path]
+            .run_pending_tasks()
+            .await;
+
+        assert!(backend
+            .get_doc_by_path(&format!("{collection_path}/c"))
+            .await?
+            .is_some());
+        assert!(backend
+            .get_doc_by_path(&format!("{collection_path}/unknown"))
+            .await?
+            .is_none());
+
+        let stats = backend.collection_stats(collection_path).unwrap();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.entry_count, 2);
+        assert_eq!(stats.evictions, 1);
+        assert_eq!(backend.stats(), stats);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn equivalent_queries_share_results_until_collection_changes() -> FirestoreResult<()> {
+        let collection_path = "projects/test/databases/(default)/documents/test";
+        let backend = FirestoreMemoryCacheBackend::with_options(
+            test_config(collection_path),
+            FirestoreMemoryCacheOptions::new().with_default_limits(
+                FirestoreMemoryCacheLimits::new().with_query_results_max_bytes(1024 * 1024),
+            ),
+        )?;
+        for id in ["a", "b"] {
+            backend
+                .update_doc_by_path(&test_doc(collection_path, id))
+                .await?;
+        }
+
+        let query_ids = |query: FirestoreQueryParams| {
+            let backend = &backend;
+            async move {
+                match backend.query_docs(collection_path, &query).await? {
+                    FirestoreCachedValue::UseCached(stream) => {
+                        stream
+                            .map_ok(|doc| split_document_path(&doc.name).1.to_string())
+                            .try_collect::<Vec<String>>()
+                            .await
+                    }
+                    FirestoreCachedValue::SkipCache => panic!("Query should be cached"),
+                }
+            }
+        };
+
+        let query = FirestoreQueryParams::new("test".into());
+        let equivalent_query = FirestoreQueryParams::new("test".into())
+            .with_offset(0)
+            .with_order_by(vec![FirestoreQueryOrder::new(
+                FIRESTORE_DOCUMENT_NAME_FIELD.to_string(),
+                FirestoreQueryDirection::Ascending,
+            )]);
+
+        assert_eq!(query_ids(query.clone()).await?, vec!["a", "b"]);
+        assert_eq!(query_ids(equivalent_query.clone()).await?, vec!["a", "b"]);
+        let stats = backend.collection_stats(collection_path).unwrap();
+        assert_eq!((stats.query_hits, stats.query_misses), (1, 1));
+
+        backend
+            .update_doc_by_path(&test_doc(collection_path, "c"))
+            .await?;
+        assert_eq!(query_ids(equivalent_query).await?, vec!["a", "b", "c"]);
+        backend
+            .delete_doc_by_path(&format!("{collection_path}/a"))
+            .await?;
+        assert_eq!(query_ids(query).await?, vec!["b", "c"]);
+        let stats = backend.collection_stats(collection_path).unwrap();
+        assert_eq!((stats.query_hits, stats.query_misses), (1, 3));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn query_results_are_not_reused_by_default() -> FirestoreResult<()> {
+        let collection_path = "projects/test/databases/(default)/documents/test";
+        let backend = FirestoreMemoryCacheBackend::new(test_config(collection_path))?;
+        backend
+            .update_doc_by_path(&test_doc(collection_path, "a"))
+            .await?;
+
+        for _ in 0..2 {
+            match backend
+                .query_docs(collection_path, &FirestoreQueryParams::new("test".into()))
+                .await?
+            {
+                FirestoreCachedValue::UseCached(stream) => {
+                    assert_eq!(stream.try_collect::<Vec<_>>().await?.len(), 1)
+                }
+                FirestoreCachedValue::SkipCache => panic!("Query should be cached"),
+            }
+        }
+        assert!(backend.collection_query_results.is_empty());
+        let stats = backend.collection_stats(collection_path).unwrap();
+        assert_eq!((stats.query_hits, stats.query_misses), (0, 0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn expired_documents_skip_collection_cache() -> FirestoreResult<()> {
+        let collection_path = "projects/test/databases/(default)/documents/test";
+        let backend = FirestoreMemoryCacheBackend::with_options(
+            test_config(collection_path),
+            FirestoreMemoryCacheOptions::new().with_default_limits(
+                FirestoreMemoryCacheLimits::new().with_time_to_live(Duration::from_millis(200)),
+            ),
+        )?;
+        backend
+            .update_doc_by_path(&test_doc(collection_path, "a"))
+            .await?;
+        let query = FirestoreQueryParams::new("test".into());
+
+        assert!(matches!(
+            backend.list_all_docs(collection_path).await?,
+            FirestoreCachedValue::UseCached(_)
+        ));
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        backend
+            .update_doc_by_path(&test_doc(collection_path, "b"))
+            .await?;
+
+        assert!(matches!(
+            backend.list_all_docs(collection_path).await?,
+            FirestoreCachedValue::SkipCache
+        ));
+        assert!(matches!(
+            backend.query_docs(collection_path, &query).await?,
+            FirestoreCachedValue::SkipCache
+        ));
+        assert!(backend
+            .get_doc_by_path(&format!("{collection_path}/b"))
+            .await?
+            .is_some());
+        Ok(())
+    }
+}
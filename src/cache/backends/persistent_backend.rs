@@ -6,20 +6,257 @@ use futures::stream::BoxStream;
 use crate::cache::cache_query_engine::FirestoreCacheQueryEngine;
 use chrono::Utc;
 use futures::StreamExt;
-use gcloud_sdk::google::firestore::v1::Document;
+use gcloud_sdk::google::firestore::v1::{Document, TargetChange};
 use gcloud_sdk::prost::Message;
 use redb::*;
-use std::collections::HashMap;
+use rsb_derive::Builder;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::time::Duration;
 use tracing::*;
 
+const FIRESTORE_PERSISTENT_CACHE_BYTES_TABLE: TableDefinition<&str, u64> =
+    TableDefinition::new("#collection_bytes");
+
+const FIRESTORE_PERSISTENT_CACHE_TOKENS_TABLE: TableDefinition<u32, &[u8]> =
+    TableDefinition::new("#listener_tokens");
+
 pub struct FirestorePersistentCacheBackend {
     pub config: FirestoreCacheConfiguration,
+    options: FirestorePersistentCacheOptions,
     redb: Database,
 }
 
+/// Limits of a collection cache.
+///
+/// Documents are removed in the order they were written to the cache (the oldest first)
+/// when the collection exceeds `max_entries` or `max_bytes` (the encoded size of the documents),
+/// and when they were written more than `time_to_live` ago.
+#[derive(Debug, PartialEq, Clone, Builder)]
+pub struct FirestorePersistentCacheLimits {
+    pub max_entries: Option<u64>,
+    pub max_bytes: Option<u64>,
+    pub time_to_live: Option<Duration>,
+}
+
+/// Options of the persistent cache backend.
+#[derive(Debug, PartialEq, Clone, Builder)]
+pub struct FirestorePersistentCacheOptions {
+    /// The database file, a file in the temp directory if not specified.
+    pub data_file_path: Option<PathBuf>,
+    #[default = "FirestorePersistentCacheLimits::new()"]
+    pub default_limits: FirestorePersistentCacheLimits,
+    #[default = "HashMap::new()"]
+    pub collection_limits: HashMap<String, FirestorePersistentCacheLimits>,
+    /// Stores the resume tokens of the listener with the documents, so the listener resumes
+    /// from the cached state after restarts instead of reading the whole collections again.
+    #[default = "true"]
+    pub resume_listener: bool,
+}
+
+impl FirestorePersistentCacheOptions {
+    /// Specifies limits for a collection by its name or full collection path.
+    #[inline]
+    pub fn add_collection_limits<S>(
+        mut self,
+        collection: S,
+        limits: FirestorePersistentCacheLimits,
+    ) -> Self
+    where
+        S: AsRef<str>,
+    {
+        self.collection_limits
+            .insert(collection.as_ref().to_string(), limits);
+        self
+    }
+}
+
+/// Statistics of a collection in the persistent cache.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub struct FirestorePersistentCacheStats {
+    pub entry_count: u64,
+    pub bytes: u64,
+}
+
+/// The names of the tables of a cached collection: the documents by their IDs,
+/// and the write times and sizes of the documents to apply the limits.
+struct FirestorePersistentCollectionTables {
+    docs: String,
+    entries: String,
+    written: String,
+}
+
+impl FirestorePersistentCollectionTables {
+    fn new(collection_path: &str) -> Self {
+        Self {
+            docs: collection_path.to_string(),
+            entries: format!("{collection_path}#entries"),
+            written: format!("{collection_path}#written"),
+        }
+    }
+
+    fn docs(&self) -> TableDefinition<'_, &'static str, &'static [u8]> {
+        TableDefinition::new(&self.docs)
+    }
+
+    fn entries(&self) -> TableDefinition<'_, &'static str, (u64, u64)> {
+        TableDefinition::new(&self.entries)
+    }
+
+    fn written(&self) -> TableDefinition<'_, (u64, &'static str), ()> {
+        TableDefinition::new(&self.written)
+    }
+}
+
+/// The tables of a collection opened in a write transaction.
+struct FirestorePersistentCollectionWriter<'txn> {
+    collection_path: String,
+    docs: Table<'txn, &'static str, &'static [u8]>,
+    entries: Table<'txn, &'static str, (u64, u64)>,
+    written: Table<'txn, (u64, &'static str), ()>,
+    collection_bytes: Table<'txn, &'static str, u64>,
+    bytes: u64,
+}
+
+impl<'txn> FirestorePersistentCollectionWriter<'txn> {
+    fn open(write_txn: &'txn WriteTransaction, collection_path: &str) -> FirestoreResult<Self> {
+        let tables = FirestorePersistentCollectionTables::new(collection_path);
+        let collection_bytes = write_txn.open_table(FIRESTORE_PERSISTENT_CACHE_BYTES_TABLE)?;
+        let bytes = collection_bytes
+            .get(collection_path)?
+            .map(|bytes| bytes.value())
+            .unwrap_or(0);
+        Ok(Self {
+            collection_path: collection_path.to_string(),
+            docs: write_txn.open_table(tables.docs())?,
+            entries: write_txn.open_table(tables.entries())?,
+            written: write_txn.open_table(tables.written())?,
+            collection_bytes,
+            bytes,
+        })
+    }
+
+    fn insert(&mut self, document_id: &str, doc_bytes: &[u8], now: u64) -> FirestoreResult<()> {
+        self.remove_entry(document_id)?;
+        let size = (document_id.len() + doc_bytes.len()) as u64;
+        self.docs.insert(document_id, doc_bytes)?;
+        self.entries.insert(document_id, (now, size))?;
+        self.written.insert((now, document_id), ())?;
+        self.bytes += size;
+        Ok(())
+    }
+
+    fn remove(&mut self, document_id: &str) -> FirestoreResult<bool> {
+        self.remove_entry(document_id)?;
+        Ok(self.docs.remove(document_id)?.is_some())
+    }
+
+    fn remove_entry(&mut self, document_id: &str) -> FirestoreResult<()> {
+        let entry = self.entries.remove(document_id)?.map(|entry| entry.value());
+        if let Some((written_at, size)) = entry {
+            self.written.remove((written_at, document_id))?;
+            self.bytes = self.bytes.saturating_sub(size);
+        }
+        Ok(())
+    }
+
+    /// Removes the expired documents and the oldest documents exceeding the limits.
+    fn apply_limits(
+        &mut self,
+        limits: &FirestorePersistentCacheLimits,
+        now: u64,
+    ) -> FirestoreResult<u64> {
+        let expired_before = limits
+            .time_to_live
+            .map(|time_to_live| now.saturating_sub(time_to_live.as_millis() as u64));
+
+        let mut removed = 0;
+        loop {
+            let oldest = self.written.first()?.map(|(oldest, _)| {
+                let (written_at, document_id) = oldest.value();
+                (written_at, document_id.to_string())
+            });
+            let (written_at, document_id) = match oldest {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            let expired =
+                expired_before.map_or(false, |expired_before| written_at < expired_before);
+            let entry_count = self.docs.len()?;
+            let exceeds_limits = limits
+                .max_entries
+                .map_or(false, |max_entries| entry_count > max_entries)
+                || limits
+                    .max_bytes
+                    .map_or(false, |max_bytes| self.bytes > max_bytes);
+            if !expired && !exceeds_limits {
+                break;
+            }
+            self.remove(&document_id)?;
+            removed += 1;
+        }
+
+        if removed > 0 {
+            debug!(
+                collection_path = self.collection_path.as_str(),
+                removed, "Removed documents exceeding the cache limits.",
+            );
+        }
+        Ok(removed)
+    }
+
+    fn finish(mut self) -> FirestoreResult<()> {
+        self.collection_bytes
+            .insert(self.collection_path.as_str(), self.bytes)?;
+        Ok(())
+    }
+}
+
 impl FirestorePersistentCacheBackend {
     pub fn new(config: FirestoreCacheConfiguration) -> FirestoreResult<Self> {
+        Self::with_cache_options(config, FirestorePersistentCacheOptions::new())
+    }
+
+    pub fn with_options(
+        config: FirestoreCacheConfiguration,
+        data_file_path: PathBuf,
+    ) -> FirestoreResult<Self> {
+        Self::with_cache_options(
+            config,
+            FirestorePersistentCacheOptions::new().with_data_file_path(data_file_path),
+        )
+    }
+
+    pub fn with_cache_options(
+        config: FirestoreCacheConfiguration,
+        options: FirestorePersistentCacheOptions,
+    ) -> FirestoreResult<Self> {
+        let data_file_path = match &options.data_file_path {
+            Some(data_file_path) => data_file_path.clone(),
+            None => Self::temp_data_file_path()?,
+        };
+
+        if data_file_path.exists() {
+            debug!(?data_file_path, "Opening database for persistent cache...",);
+        } else {
+            debug!(?data_file_path, "Creating database for persistent cache...",);
+        }
+
+        let mut db = Database::create(data_file_path)?;
+
+        db.compact()?;
+        info!("Successfully opened database for persistent cache.");
+
+        let backend = Self {
+            config,
+            options,
+            redb: db,
+        };
+        backend.init_collections()?;
+        Ok(backend)
+    }
+
+    fn temp_data_file_path() -> FirestoreResult<PathBuf> {
         let temp_dir = std::env::temp_dir();
         let firestore_cache_dir = temp_dir.join("firestore_cache");
         let db_dir = firestore_cache_dir.join("persistent");
@@ -36,25 +273,161 @@ impl FirestorePersistentCacheBackend {
                 "Using a temp directory to store persistent cache.",
             );
         }
-        Self::with_options(config, db_dir.join("redb"))
+        Ok(db_dir.join("redb"))
     }
 
-    pub fn with_options(
-        config: FirestoreCacheConfiguration,
-        data_file_path: PathBuf,
-    ) -> FirestoreResult<Self> {
-        if data_file_path.exists() {
-            debug!(?data_file_path, "Opening database for persistent cache...",);
-        } else {
-            debug!(?data_file_path, "Creating database for persistent cache...",);
+    fn limits_for(&self, collection_path: &str) -> &FirestorePersistentCacheLimits {
+        self.options
+            .collection_limits
+            .get(collection_path)
+            .or_else(|| {
+                self.config
+                    .collections
+                    .get(collection_path)
+                    .and_then(|collection_config| {
+                        self.options
+                            .collection_limits
+                            .get(collection_config.collection_name.as_str())
+                    })
+            })
+            .unwrap_or(&self.options.default_limits)
+    }
+
+    fn now_millis() -> u64 {
+        Utc::now().timestamp_millis().max(0) as u64
+    }
+
+    /// Creates the tables of the collections, registers the documents cached by the previous
+    /// versions without the write times as written now, and applies the limits.
+    fn init_collections(&self) -> FirestoreResult<()> {
+        let now = Self::now_millis();
+        for collection_path in self.config.collections.keys() {
+            let write_txn = self.redb.begin_write()?;
+            {
+                let mut writer =
+                    FirestorePersistentCollectionWriter::open(&write_txn, collection_path)?;
+                if writer.entries.len()? < writer.docs.len()? {
+                    let mut unregistered: Vec<(String, u64)> = Vec::new();
+                    for record in writer.docs.iter()? {
+                        let (document_id, doc_bytes) = record?;
+                        let document_id = document_id.value();
+                        if writer.entries.get(document_id)?.is_none() {
+                            unregistered.push((
+                                document_id.to_string(),
+                                (document_id.len() + doc_bytes.value().len()) as u64,
+                            ));
+                        }
+                    }
+                    for (document_id, size) in unregistered {
+                        writer.entries.insert(document_id.as_str(), (now, size))?;
+                        writer.written.insert((now, document_id.as_str()), ())?;
+                        writer.bytes += size;
+                    }
+                }
+                writer.apply_limits(self.limits_for(collection_path), now)?;
+                writer.finish()?;
+            }
+            write_txn.commit()?;
         }
+        Ok(())
+    }
 
-        let mut db = Database::create(data_file_path)?;
+    /// Removes the expired documents and the oldest documents exceeding the limits
+    /// of the collections, returning the number of removed documents.
+    ///
+    /// The limits are applied when documents are written and when the cache is opened,
+    /// so this is needed only to remove the expired documents of collections without writes.
+    pub fn apply_limits(&self) -> FirestoreResult<u64> {
+        let now = Self::now_millis();
+        let mut removed = 0;
+        for collection_path in self.config.collections.keys() {
+            let write_txn = self.redb.begin_write()?;
+            {
+                let mut writer =
+                    FirestorePersistentCollectionWriter::open(&write_txn, collection_path)?;
+                removed += writer.apply_limits(self.limits_for(collection_path), now)?;
+                writer.finish()?;
+            }
+            write_txn.commit()?;
+        }
+        Ok(removed)
+    }
 
-        db.compact()?;
-        info!("Successfully opened database for persistent cache.");
+    /// Returns the statistics of the collection cache by its full collection path.
+    pub fn collection_stats(
+        &self,
+        collection_path: &str,
+    ) -> FirestoreResult<Option<FirestorePersistentCacheStats>> {
+        if !self.config.collections.contains_key(collection_path) {
+            return Ok(None);
+        }
+        let tables = FirestorePersistentCollectionTables::new(collection_path);
+        let read_tx = self.redb.begin_read()?;
+        let entry_count = read_tx.open_table(tables.docs())?.len()?;
+        let bytes = read_tx
+            .open_table(FIRESTORE_PERSISTENT_CACHE_BYTES_TABLE)?
+            .get(collection_path)?
+            .map(|bytes| bytes.value())
+            .unwrap_or(0);
+        Ok(Some(FirestorePersistentCacheStats { entry_count, bytes }))
+    }
+
+    /// The IDs of the expired documents, which aren't removed yet.
+    fn expired_document_ids(
+        &self,
+        read_tx: &ReadTransaction,
+        collection_path: &str,
+    ) -> FirestoreResult<HashSet<String>> {
+        let mut expired_ids = HashSet::new();
+        if let Some(time_to_live) = self.limits_for(collection_path).time_to_live {
+            let expired_before = Self::now_millis().saturating_sub(time_to_live.as_millis() as u64);
+            let tables = FirestorePersistentCollectionTables::new(collection_path);
+            let written = read_tx.open_table(tables.written())?;
+            for record in written.range::<(u64, &str)>(..(expired_before, ""))? {
+                let (written_key, _) = record?;
+                expired_ids.insert(written_key.value().1.to_string());
+            }
+        }
+        Ok(expired_ids)
+    }
+
+    fn read_resume_token(
+        &self,
+        target: &FirestoreListenerTarget,
+    ) -> FirestoreResult<Option<FirestoreListenerToken>> {
+        let read_tx = self.redb.begin_read()?;
+        let tokens = match read_tx.open_table(FIRESTORE_PERSISTENT_CACHE_TOKENS_TABLE) {
+            Ok(tokens) => tokens,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let token = tokens.get(*target.value())?;
+        Ok(token.map(|token| FirestoreListenerToken::new(token.value().to_vec())))
+    }
+
+    fn write_resume_token(&self, target_change: &TargetChange) -> FirestoreResult<()> {
+        let cached_targets: Vec<u32> = self
+            .config
+            .collections
+            .values()
+            .map(|collection_config| *collection_config.listener_target.value())
+            .filter(|target_id| {
+                target_change.target_ids.is_empty()
+                    || target_change.target_ids.contains(&(*target_id as i32))
+            })
+            .collect();
 
-        Ok(Self { config, redb: db })
+        if !cached_targets.is_empty() {
+            let write_txn = self.redb.begin_write()?;
+            {
+                let mut tokens = write_txn.open_table(FIRESTORE_PERSISTENT_CACHE_TOKENS_TABLE)?;
+                for target_id in cached_targets {
+                    tokens.insert(target_id, target_change.resume_token.as_slice())?;
+                }
+            }
+            write_txn.commit()?;
+        }
+        Ok(())
     }
 
     async fn preload_collections(&self, db: &FirestoreDb) -> Result<(), FirestoreError> {
@@ -119,7 +492,7 @@ impl FirestorePersistentCacheBackend {
                         })
                         .ready_chunks(100)
                         .for_each(|docs| async move {
-                            if let Err(err) = self.write_batch_docs(collection_path, docs) {
+                            if let Err(err) = self.write_docs(collection_path, &docs) {
                                 error!(?err, "Error while preloading collection.");
                             }
                         })
@@ -153,18 +526,19 @@ impl FirestorePersistentCacheBackend {
         Ok(())
     }
 
-    fn write_batch_docs(&self, collection_path: &str, docs: Vec<Document>) -> FirestoreResult<()> {
-        let td: TableDefinition<&str, &[u8]> = TableDefinition::new(collection_path);
-
+    fn write_docs(&self, collection_path: &str, docs: &[Document]) -> FirestoreResult<()> {
+        let now = Self::now_millis();
         let write_txn = self.redb.begin_write()?;
         {
-            let mut table = write_txn.open_table(td)?;
-
+            let mut writer =
+                FirestorePersistentCollectionWriter::open(&write_txn, collection_path)?;
             for doc in docs {
                 let (_, document_id) = split_document_path(&doc.name);
-                let doc_bytes = Self::document_to_buf(&doc)?;
-                table.insert(document_id, doc_bytes.as_slice())?;
+                let doc_bytes = Self::document_to_buf(doc)?;
+                writer.insert(document_id, doc_bytes.as_slice(), now)?;
             }
+            writer.apply_limits(self.limits_for(collection_path), now)?;
+            writer.finish()?;
         }
         write_txn.commit()?;
 
@@ -186,22 +560,29 @@ impl FirestorePersistentCacheBackend {
     }
 
     fn write_document(&self, doc: &Document) -> FirestoreResult<()> {
-        let (collection_path, document_id) = split_document_path(&doc.name);
+        let (collection_path, _) = split_document_path(&doc.name);
 
         if self.config.collections.contains_key(collection_path) {
-            let td: TableDefinition<&str, &[u8]> = TableDefinition::new(collection_path);
+            self.write_docs(collection_path, std::slice::from_ref(doc))
+        } else {
+            Ok(())
+        }
+    }
 
+    fn delete_document(&self, document_path: &str) -> FirestoreResult<()> {
+        let (collection_path, document_id) = split_document_path(document_path);
+
+        if self.config.collections.contains_key(collection_path) {
             let write_txn = self.redb.begin_write()?;
             {
-                let mut table = write_txn.open_table(td)?;
-                let doc_bytes = Self::document_to_buf(doc)?;
-                table.insert(document_id, doc_bytes.as_slice())?;
+                let mut writer =
+                    FirestorePersistentCollectionWriter::open(&write_txn, collection_path)?;
+                writer.remove(document_id)?;
+                writer.finish()?;
             }
             write_txn.commit()?;
-            Ok(())
-        } else {
-            Ok(())
         }
+        Ok(())
     }
 
     fn table_len(&self, collection_id: &str) -> FirestoreResult<u64> {
@@ -219,13 +600,17 @@ impl FirestorePersistentCacheBackend {
         let td: TableDefinition<&str, &[u8]> = TableDefinition::new(collection_path);
 
         let read_tx = self.redb.begin_read()?;
+        let expired_ids = self.expired_document_ids(&read_tx, collection_path)?;
         let table = read_tx.open_table(td)?;
         let iter = table.iter()?;
 
         // It seems there is no way to work with streaming for redb, so this is not efficient
         let mut docs: Vec<FirestoreResult<FirestoreDocument>> = Vec::new();
         for record in iter {
-            let (_, v) = record?;
+            let (k, v) = record?;
+            if expired_ids.contains(k.value()) {
+                continue;
+            }
             let doc = Self::buf_to_document(v.value())?;
             if query_engine.matches_doc(&doc) {
                 docs.push(Ok(doc));
@@ -250,18 +635,21 @@ impl FirestoreCacheBackend for FirestorePersistentCacheBackend {
 
         self.preload_collections(db).await?;
 
-        Ok(self
-            .config
+        self.config
             .collections
             .iter()
             .map(|(collection_path, collection_config)| {
                 let collection_table_len = self.table_len(collection_path).ok().unwrap_or(0);
                 let resume_type = if collection_table_len == 0 {
                     Some(FirestoreListenerTargetResumeType::ReadTime(read_from_time))
+                } else if self.options.resume_listener {
+                    // Resuming from the cached state instead of reading the collection again
+                    self.read_resume_token(&collection_config.listener_target)?
+                        .map(FirestoreListenerTargetResumeType::Token)
                 } else {
                     None
                 };
-                FirestoreListenerTargetParams::new(
+                Ok(FirestoreListenerTargetParams::new(
                     collection_config.listener_target.clone(),
                     FirestoreTargetType::Query(
                         FirestoreQueryParams::new(
@@ -271,27 +659,37 @@ impl FirestoreCacheBackend for FirestorePersistentCacheBackend {
                     ),
                     HashMap::new(),
                 )
-                .opt_resume_type(resume_type)
+                .opt_resume_type(resume_type))
             })
-            .collect())
+            .collect()
     }
 
     async fn invalidate_all(&self) -> FirestoreResult<()> {
         for collection_path in self.config.collections.keys() {
-            let td: TableDefinition<&str, &[u8]> = TableDefinition::new(collection_path.as_str());
-
             let write_txn = self.redb.begin_write()?;
             {
                 debug!(
                     collection_path,
                     "Invalidating collection and draining the corresponding table.",
                 );
-                let mut table = write_txn.open_table(td)?;
-                table.retain(|_, _| false)?;
+                let mut writer =
+                    FirestorePersistentCollectionWriter::open(&write_txn, collection_path)?;
+                writer.docs.retain(|_, _| false)?;
+                writer.entries.retain(|_, _| false)?;
+                writer.written.retain(|_, _| false)?;
+                writer.bytes = 0;
+                writer.finish()?;
             }
             write_txn.commit()?;
         }
 
+        // The cached state the listener could resume from doesn't exist anymore
+        let write_txn = self.redb.begin_write()?;
+        write_txn
+            .open_table(FIRESTORE_PERSISTENT_CACHE_TOKENS_TABLE)?
+            .retain(|_, _| false)?;
+        write_txn.commit()?;
+
         Ok(())
     }
 
@@ -313,22 +711,28 @@ impl FirestoreCacheBackend for FirestorePersistentCacheBackend {
                 Ok(())
             }
             FirestoreListenEvent::DocumentDelete(doc_deleted) => {
-                let (collection_path, document_id) = split_document_path(&doc_deleted.document);
-                let write_txn = self.redb.begin_write()?;
-                let td: TableDefinition<&str, &[u8]> = TableDefinition::new(collection_path);
-                let mut table = write_txn.open_table(td)?;
-
                 trace!(
                     deleted_doc = ?doc_deleted.document.as_str(),
                     "Removing document from cache due to listener event.",
                 );
 
-                table.remove(document_id)?;
-                Ok(())
+                self.delete_document(&doc_deleted.document)
+            }
+            FirestoreListenEvent::TargetChange(target_change)
+                if self.options.resume_listener && !target_change.resume_token.is_empty() =>
+            {
+                self.write_resume_token(&target_change)
             }
             _ => Ok(()),
         }
     }
+
+    fn collection_consistency(&self, collection_path: &str) -> Option<&FirestoreCacheConsistency> {
+        self.config
+            .collections
+            .get(collection_path)
+            .map(|collection_config| &collection_config.consistency)
+    }
 }
 
 #[async_trait]
@@ -341,6 +745,18 @@ impl FirestoreCacheDocsByPathSupport for FirestorePersistentCacheBackend {
         if self.config.collections.contains_key(collection_path) {
             let td: TableDefinition<&str, &[u8]> = TableDefinition::new(collection_path);
             let read_tx = self.redb.begin_read()?;
+            if let Some(time_to_live) = self.limits_for(collection_path).time_to_live {
+                let tables = FirestorePersistentCollectionTables::new(collection_path);
+                let entry = read_tx.open_table(tables.entries())?.get(document_id)?;
+                if let Some(entry) = entry {
+                    let (written_at, _) = entry.value();
+                    if written_at
+                        < Self::now_millis().saturating_sub(time_to_live.as_millis() as u64)
+                    {
+                        return Ok(None);
+                    }
+                }
+            }
             let table = read_tx.open_table(td)?;
             let value = table.get(document_id)?;
             value.map(|v| Self::buf_to_document(v.value())).transpose()
@@ -354,6 +770,10 @@ impl FirestoreCacheDocsByPathSupport for FirestorePersistentCacheBackend {
         Ok(())
     }
 
+    async fn delete_doc_by_path(&self, document_path: &str) -> FirestoreResult<()> {
+        self.delete_document(document_path)
+    }
+
     async fn list_all_docs<'b>(
         &self,
         collection_path: &str,
@@ -363,13 +783,17 @@ impl FirestoreCacheDocsByPathSupport for FirestorePersistentCacheBackend {
             let td: TableDefinition<&str, &[u8]> = TableDefinition::new(collection_path);
 
             let read_tx = self.redb.begin_read()?;
+            let expired_ids = self.expired_document_ids(&read_tx, collection_path)?;
             let table = read_tx.open_table(td)?;
             let iter = table.iter()?;
 
             // It seems there is no way to work with streaming for redb, so this is not efficient
             let mut docs: Vec<FirestoreResult<FirestoreDocument>> = Vec::new();
             for record in iter {
-                let (_, v) = record?;
+                let (k, v) = record?;
+                if expired_ids.contains(k.value()) {
+                    continue;
+                }
                 let doc = Self::buf_to_document(v.value())?;
                 docs.push(Ok(doc));
             }
@@ -467,3 +891,152 @@ impl From<redb::CompactionError> for FirestoreError {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcloud_sdk::google::firestore::v1::target_change::TargetChangeType;
+
+    const COLLECTION_PATH: &str = "projects/test/databases/(default)/documents/test";
+
+    fn test_doc(id: &str) -> FirestoreDocument {
+        FirestoreDocument {
+            name: format!("{COLLECTION_PATH}/{id}"),
+            fields: HashMap::new(),
+            create_time: None,
+            update_time: None,
+        }
+    }
+
+    fn test_config() -> FirestoreCacheConfiguration {
+        let mut config = FirestoreCacheConfiguration::new();
+        config.collections.insert(
+            COLLECTION_PATH.to_string(),
+            FirestoreCacheCollectionConfiguration::new(
+                "test",
+                FirestoreListenerTarget::new(7),
+                FirestoreCacheCollectionLoadMode::PreloadNone,
+            ),
+        );
+        config
+    }
+
+    fn test_data_file_path(name: &str) -> PathBuf {
+        let data_file_path = std::env::temp_dir().join(format!(
+            "firestore_cache_test_{name}_{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&data_file_path);
+        data_file_path
+    }
+
+    #[tokio::test]
+    async fn limits_remove_oldest_and_expired_docs() -> FirestoreResult<()> {
+        let data_file_path = test_data_file_path("limits");
+        let backend = FirestorePersistentCacheBackend::with_cache_options(
+            test_config(),
+            FirestorePersistentCacheOptions::new()
+                .with_data_file_path(data_file_path.clone())
+                .add_collection_limits(
+                    "test",
+                    FirestorePersistentCacheLimits::new()
+                        .with_max_entries(2)
+                        .with_time_to_live(Duration::from_millis(200)),
+                ),
+        )?;
+
+        for id in ["a", "b", "c"] {
+            backend.update_doc_by_path(&test_doc(id)).await?;
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+        assert!(backend
+            .get_doc_by_path(&format!("{COLLECTION_PATH}/a"))
+            .await?
+            .is_none());
+        assert!(backend
+            .get_doc_by_path(&format!("{COLLECTION_PATH}/c"))
+            .await?
+            .is_some());
+        let stats = backend.collection_stats(COLLECTION_PATH)?.unwrap();
+        assert_eq!(stats.entry_count, 2);
+        assert_eq!(
+            stats.bytes,
+            ["b", "c"]
+                .iter()
+                .map(|id| (id.len() + test_doc(id).encoded_len()) as u64)
+                .sum::<u64>()
+        );
+
+        backend
+            .delete_doc_by_path(&format!("{COLLECTION_PATH}/b"))
+            .await?;
+        assert_eq!(
+            backend.collection_stats(COLLECTION_PATH)?.unwrap().bytes,
+            (1 + test_doc("c").encoded_len()) as u64
+        );
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert!(backend
+            .get_doc_by_path(&format!("{COLLECTION_PATH}/c"))
+            .await?
+            .is_none());
+        match backend.list_all_docs(COLLECTION_PATH).await? {
+            FirestoreCachedValue::UseCached(docs) => assert_eq!(docs.count().await, 0),
+            FirestoreCachedValue::SkipCache => panic!("Collection should be cached"),
+        }
+        assert_eq!(backend.apply_limits()?, 1);
+        assert_eq!(
+            backend.collection_stats(COLLECTION_PATH)?.unwrap(),
+            FirestorePersistentCacheStats::default()
+        );
+
+        drop(backend);
+        let _ = std::fs::remove_file(&data_file_path);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resume_tokens_survive_restarts() -> FirestoreResult<()> {
+        let data_file_path = test_data_file_path("tokens");
+        let target = FirestoreListenerTarget::new(7);
+
+        let backend =
+            FirestorePersistentCacheBackend::with_options(test_config(), data_file_path.clone())?;
+        backend.update_doc_by_path(&test_doc("a")).await?;
+        backend
+            .on_listen_event(FirestoreListenEvent::TargetChange(TargetChange {
+                target_change_type: TargetChangeType::NoChange.into(),
+                target_ids: vec![7],
+                cause: None,
+                resume_token: vec![1, 2, 3],
+                read_time: None,
+            }))
+            .await?;
+        drop(backend);
+
+        let backend = FirestorePersistentCacheBackend::with_cache_options(
+            test_config(),
+            FirestorePersistentCacheOptions::new()
+                .with_data_file_path(data_file_path.clone())
+                .with_default_limits(FirestorePersistentCacheLimits::new().with_max_entries(10)),
+        )?;
+        assert_eq!(
+            backend
+                .read_resume_token(&target)?
+                .map(|token| token.value().clone()),
+            Some(vec![1, 2, 3])
+        );
+        assert!(backend
+            .get_doc_by_path(&format!("{COLLECTION_PATH}/a"))
+            .await?
+            .is_some());
+
+        backend.invalidate_all().await?;
+        assert!(backend.read_resume_token(&target)?.is_none());
+        assert_eq!(backend.table_len(COLLECTION_PATH)?, 0);
+
+        drop(backend);
+        let _ = std::fs::remove_file(&data_file_path);
+        Ok(())
+    }
+}
@@ -1,5 +1,9 @@
+use crate::cache::cache_query_engine::doc_field_value;
+use crate::firestore_value::{array_values, compare_field_values, value_type_order};
 use crate::FirestoreQueryFilter;
 use crate::*;
+use gcloud_sdk::google::firestore::v1::value::ValueType;
+use std::cmp::Ordering;
 
 pub struct FirestoreCacheFilterEngine<'a> {
     filter: &'a FirestoreQueryFilter,
@@ -41,46 +45,38 @@ impl<'a> FirestoreCacheFilterEngine<'a> {
         filter: &FirestoreQueryFilterUnary,
     ) -> bool {
         match filter {
-            FirestoreQueryFilterUnary::IsNan(field_path) => {
-                firestore_doc_get_field_by_path(doc, field_path)
-                    .map(|field_value| match field_value {
-                        gcloud_sdk::google::firestore::v1::value::ValueType::DoubleValue(
-                            double_value,
-                        ) => double_value.is_nan(),
-                        _ => false,
-                    })
-                    .unwrap_or(false)
-            }
-            FirestoreQueryFilterUnary::IsNotNan(field_path) => {
-                firestore_doc_get_field_by_path(doc, field_path)
-                    .map(|field_value| match field_value {
-                        gcloud_sdk::google::firestore::v1::value::ValueType::DoubleValue(
-                            double_value,
-                        ) => !double_value.is_nan(),
-                        _ => true,
-                    })
-                    .unwrap_or(true)
-            }
-            FirestoreQueryFilterUnary::IsNull(field_path) => {
-                firestore_doc_get_field_by_path(doc, field_path)
-                    .map(|field_value| {
-                        matches!(
-                            field_value,
-                            gcloud_sdk::google::firestore::v1::value::ValueType::NullValue(_)
-                        )
-                    })
-                    .unwrap_or(true)
-            }
-            FirestoreQueryFilterUnary::IsNotNull(field_path) => {
-                firestore_doc_get_field_by_path(doc, field_path)
-                    .map(|field_value| {
-                        !matches!(
-                            field_value,
-                            gcloud_sdk::google::firestore::v1::value::ValueType::NullValue(_)
-                        )
-                    })
-                    .unwrap_or(false)
-            }
+            FirestoreQueryFilterUnary::IsNan(field_path) => doc_field_value(doc, field_path)
+                .map(|field_value| match field_value.as_ref() {
+                    gcloud_sdk::google::firestore::v1::value::ValueType::DoubleValue(
+                        double_value,
+                    ) => double_value.is_nan(),
+                    _ => false,
+                })
+                .unwrap_or(false),
+            FirestoreQueryFilterUnary::IsNotNan(field_path) => doc_field_value(doc, field_path)
+                .map(|field_value| match field_value.as_ref() {
+                    gcloud_sdk::google::firestore::v1::value::ValueType::DoubleValue(
+                        double_value,
+                    ) => !double_value.is_nan(),
+                    _ => true,
+                })
+                .unwrap_or(false),
+            FirestoreQueryFilterUnary::IsNull(field_path) => doc_field_value(doc, field_path)
+                .map(|field_value| {
+                    matches!(
+                        field_value.as_ref(),
+                        gcloud_sdk::google::firestore::v1::value::ValueType::NullValue(_)
+                    )
+                })
+                .unwrap_or(false),
+            FirestoreQueryFilterUnary::IsNotNull(field_path) => doc_field_value(doc, field_path)
+                .map(|field_value| {
+                    !matches!(
+                        field_value.as_ref(),
+                        gcloud_sdk::google::firestore::v1::value::ValueType::NullValue(_)
+                    )
+                })
+                .unwrap_or(false),
         }
     }
 
@@ -90,33 +86,37 @@ impl<'a> FirestoreCacheFilterEngine<'a> {
     ) -> bool {
         match filter {
             Some(FirestoreQueryFilterCompare::Equal(field_path, compare_with)) => {
-                firestore_doc_get_field_by_path(doc, field_path)
+                doc_field_value(doc, field_path)
                     .and_then(|field_value| {
                         compare_with
                             .value
                             .value_type
                             .as_ref()
                             .map(|compare_with_value| {
-                                compare_values(CompareOp::Equal, field_value, compare_with_value)
+                                compare_values(CompareOp::Equal, &field_value, compare_with_value)
                             })
                     })
                     .unwrap_or(false)
             }
             Some(FirestoreQueryFilterCompare::LessThan(field_path, compare_with)) => {
-                firestore_doc_get_field_by_path(doc, field_path)
+                doc_field_value(doc, field_path)
                     .and_then(|field_value| {
                         compare_with
                             .value
                             .value_type
                             .as_ref()
                             .map(|compare_with_value| {
-                                compare_values(CompareOp::LessThan, field_value, compare_with_value)
+                                compare_values(
+                                    CompareOp::LessThan,
+                                    &field_value,
+                                    compare_with_value,
+                                )
                             })
                     })
                     .unwrap_or(false)
             }
             Some(FirestoreQueryFilterCompare::LessThanOrEqual(field_path, compare_with)) => {
-                firestore_doc_get_field_by_path(doc, field_path)
+                doc_field_value(doc, field_path)
                     .and_then(|field_value| {
                         compare_with
                             .value
@@ -125,7 +125,7 @@ impl<'a> FirestoreCacheFilterEngine<'a> {
                             .map(|compare_with_value| {
                                 compare_values(
                                     CompareOp::LessThanOrEqual,
-                                    field_value,
+                                    &field_value,
                                     compare_with_value,
                                 )
                             })
@@ -133,7 +133,7 @@ impl<'a> FirestoreCacheFilterEngine<'a> {
                     .unwrap_or(false)
             }
             Some(FirestoreQueryFilterCompare::GreaterThan(field_path, compare_with)) => {
-                firestore_doc_get_field_by_path(doc, field_path)
+                doc_field_value(doc, field_path)
                     .and_then(|field_value| {
                         compare_with
                             .value
@@ -142,7 +142,7 @@ impl<'a> FirestoreCacheFilterEngine<'a> {
                             .map(|compare_with_value| {
                                 compare_values(
                                     CompareOp::GreaterThan,
-                                    field_value,
+                                    &field_value,
                                     compare_with_value,
                                 )
                             })
@@ -150,7 +150,7 @@ impl<'a> FirestoreCacheFilterEngine<'a> {
                     .unwrap_or(false)
             }
             Some(FirestoreQueryFilterCompare::GreaterThanOrEqual(field_path, compare_with)) => {
-                firestore_doc_get_field_by_path(doc, field_path)
+                doc_field_value(doc, field_path)
                     .and_then(|field_value| {
                         compare_with
                             .value
@@ -159,7 +159,7 @@ impl<'a> FirestoreCacheFilterEngine<'a> {
                             .map(|compare_with_value| {
                                 compare_values(
                                     CompareOp::GreaterThanOrEqual,
-                                    field_value,
+                                    &field_value,
                                     compare_with_value,
                                 )
                             })
@@ -167,20 +167,24 @@ impl<'a> FirestoreCacheFilterEngine<'a> {
                     .unwrap_or(false)
             }
             Some(FirestoreQueryFilterCompare::NotEqual(field_path, compare_with)) => {
-                firestore_doc_get_field_by_path(doc, field_path)
+                doc_field_value(doc, field_path)
                     .and_then(|field_value| {
                         compare_with
                             .value
                             .value_type
                             .as_ref()
                             .map(|compare_with_value| {
-                                compare_values(CompareOp::NotEqual, field_value, compare_with_value)
+                                compare_values(
+                                    CompareOp::NotEqual,
+                                    &field_value,
+                                    compare_with_value,
+                                )
                             })
                     })
                     .unwrap_or(false)
             }
             Some(FirestoreQueryFilterCompare::ArrayContains(field_path, compare_with)) => {
-                firestore_doc_get_field_by_path(doc, field_path)
+                doc_field_value(doc, field_path)
                     .and_then(|field_value| {
                         compare_with
                             .value
@@ -189,7 +193,7 @@ impl<'a> FirestoreCacheFilterEngine<'a> {
                             .map(|compare_with_value| {
                                 compare_values(
                                     CompareOp::ArrayContains,
-                                    field_value,
+                                    &field_value,
                                     compare_with_value,
                                 )
                             })
@@ -197,20 +201,20 @@ impl<'a> FirestoreCacheFilterEngine<'a> {
                     .unwrap_or(false)
             }
             Some(FirestoreQueryFilterCompare::In(field_path, compare_with)) => {
-                firestore_doc_get_field_by_path(doc, field_path)
+                doc_field_value(doc, field_path)
                     .and_then(|field_value| {
                         compare_with
                             .value
                             .value_type
                             .as_ref()
                             .map(|compare_with_value| {
-                                compare_values(CompareOp::In, field_value, compare_with_value)
+                                compare_values(CompareOp::In, &field_value, compare_with_value)
                             })
                     })
                     .unwrap_or(false)
             }
             Some(FirestoreQueryFilterCompare::ArrayContainsAny(field_path, compare_with)) => {
-                firestore_doc_get_field_by_path(doc, field_path)
+                doc_field_value(doc, field_path)
                     .and_then(|field_value| {
                         compare_with
                             .value
@@ -219,7 +223,7 @@ impl<'a> FirestoreCacheFilterEngine<'a> {
                             .map(|compare_with_value| {
                                 compare_values(
                                     CompareOp::ArrayContainsAny,
-                                    field_value,
+                                    &field_value,
                                     compare_with_value,
                                 )
                             })
@@ -227,14 +231,14 @@ impl<'a> FirestoreCacheFilterEngine<'a> {
                     .unwrap_or(false)
             }
             Some(FirestoreQueryFilterCompare::NotIn(field_path, compare_with)) => {
-                firestore_doc_get_field_by_path(doc, field_path)
+                doc_field_value(doc, field_path)
                     .and_then(|field_value| {
                         compare_with
                             .value
                             .value_type
                             .as_ref()
                             .map(|compare_with_value| {
-                                compare_values(CompareOp::NotIn, field_value, compare_with_value)
+                                compare_values(CompareOp::NotIn, &field_value, compare_with_value)
                             })
                     })
                     .unwrap_or(false)
@@ -257,203 +261,125 @@ pub(super) enum CompareOp {
     NotIn,
 }
 
-pub(super) fn compare_values(
-    op: CompareOp,
-    a: &gcloud_sdk::google::firestore::v1::value::ValueType,
-    b: &gcloud_sdk::google::firestore::v1::value::ValueType,
-) -> bool {
-    match (op, a, b) {
-        // handle BooleanValue
-        (
-            CompareOp::Equal,
-            gcloud_sdk::google::firestore::v1::value::ValueType::BooleanValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::BooleanValue(b_val),
-        ) => a_val == b_val,
-
-        (
-            CompareOp::NotEqual,
-            gcloud_sdk::google::firestore::v1::value::ValueType::BooleanValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::BooleanValue(b_val),
-        ) => a_val != b_val,
-
-        // handle IntegerValue
-        (
-            CompareOp::Equal,
-            gcloud_sdk::google::firestore::v1::value::ValueType::IntegerValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::IntegerValue(b_val),
-        ) => a_val == b_val,
-
-        (
-            CompareOp::NotEqual,
-            gcloud_sdk::google::firestore::v1::value::ValueType::IntegerValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::IntegerValue(b_val),
-        ) => a_val != b_val,
-
-        (
-            CompareOp::LessThan,
-            gcloud_sdk::google::firestore::v1::value::ValueType::IntegerValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::IntegerValue(b_val),
-        ) => a_val < b_val,
+/// Evaluates a filter operator for a document field value `a` and a filter value `b`
+/// following the Firestore query semantics:
+/// - inequalities only match values of the same type (integers and doubles are both numbers);
+/// - `!=` and `not-in` don't match `null` values;
+/// - `in`, `not-in` and `array-contains-any` expect an array of values to compare with.
+pub(super) fn compare_values(op: CompareOp, a: &ValueType, b: &ValueType) -> bool {
+    match op {
+        CompareOp::Equal => values_equal(a, b),
+        CompareOp::NotEqual => !is_null(a) && !values_equal(a, b),
+        CompareOp::LessThan => {
+            compare_same_type(a, b).map(|ordering| ordering == Ordering::Less) == Some(true)
+        }
+        CompareOp::LessThanOrEqual => {
+            compare_same_type(a, b).map(|ordering| ordering != Ordering::Greater) == Some(true)
+        }
+        CompareOp::GreaterThan => {
+            compare_same_type(a, b).map(|ordering| ordering == Ordering::Greater) == Some(true)
+        }
+        CompareOp::GreaterThanOrEqual => {
+            compare_same_type(a, b).map(|ordering| ordering != Ordering::Less) == Some(true)
+        }
+        CompareOp::ArrayContains => match a {
+            ValueType::ArrayValue(a_val) => array_values(a_val).any(|a_val| values_equal(a_val, b)),
+            _ => false,
+        },
+        CompareOp::ArrayContainsAny => match (a, b) {
+            (ValueType::ArrayValue(a_val), ValueType::ArrayValue(b_val)) => array_values(a_val)
+                .any(|a_val| array_values(b_val).any(|b_val| values_equal(a_val, b_val))),
+            _ => false,
+        },
+        CompareOp::In => match b {
+            ValueType::ArrayValue(b_val) => array_values(b_val).any(|b_val| values_equal(a, b_val)),
+            _ => false,
+        },
+        CompareOp::NotIn => match b {
+            ValueType::ArrayValue(b_val) => {
+                !is_null(a) && !array_values(b_val).any(|b_val| values_equal(a, b_val))
+            }
+            _ => false,
+        },
+    }
+}
 
-        (
-            CompareOp::LessThanOrEqual,
-            gcloud_sdk::google::firestore::v1::value::ValueType::IntegerValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::IntegerValue(b_val),
-        ) => a_val <= b_val,
+fn compare_same_type(a: &ValueType, b: &ValueType) -> Option<Ordering> {
+    if value_type_order(a) != value_type_order(b) || is_nan(a) || is_nan(b) || is_null(a) {
+        None
+    } else {
+        Some(compare_field_values(a, b))
+    }
+}
 
-        (
-            CompareOp::GreaterThan,
-            gcloud_sdk::google::firestore::v1::value::ValueType::IntegerValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::IntegerValue(b_val),
-        ) => a_val > b_val,
+fn values_equal(a: &ValueType, b: &ValueType) -> bool {
+    value_type_order(a) == value_type_order(b)
+        && compare_field_values(a, b) == Ordering::Equal
+        && is_nan(a) == is_nan(b)
+}
 
-        (
-            CompareOp::GreaterThanOrEqual,
-            gcloud_sdk::google::firestore::v1::value::ValueType::IntegerValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::IntegerValue(b_val),
-        ) => a_val >= b_val,
+fn is_null(value: &ValueType) -> bool {
+    matches!(value, ValueType::NullValue(_))
+}
 
-        // For DoubleValue
-        (
-            CompareOp::Equal,
-            gcloud_sdk::google::firestore::v1::value::ValueType::DoubleValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::DoubleValue(b_val),
-        ) => a_val == b_val,
+fn is_nan(value: &ValueType) -> bool {
+    matches!(value, ValueType::DoubleValue(double_value) if double_value.is_nan())
+}
 
-        (
-            CompareOp::NotEqual,
-            gcloud_sdk::google::firestore::v1::value::ValueType::DoubleValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::DoubleValue(b_val),
-        ) => a_val != b_val,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcloud_sdk::google::firestore::v1::ArrayValue;
+    use gcloud_sdk::prost_types::Timestamp;
 
-        (
+    #[test]
+    fn compare_values_follows_firestore_semantics() {
+        let ts = |seconds, nanos| ValueType::TimestampValue(Timestamp { seconds, nanos });
+        assert!(compare_values(
             CompareOp::LessThan,
-            gcloud_sdk::google::firestore::v1::value::ValueType::DoubleValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::DoubleValue(b_val),
-        ) => a_val < b_val,
-
-        (
-            CompareOp::LessThanOrEqual,
-            gcloud_sdk::google::firestore::v1::value::ValueType::DoubleValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::DoubleValue(b_val),
-        ) => a_val <= b_val,
-
-        (
+            &ts(1, 500),
+            &ts(2, 100)
+        ));
+        assert!(!compare_values(
             CompareOp::GreaterThan,
-            gcloud_sdk::google::firestore::v1::value::ValueType::DoubleValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::DoubleValue(b_val),
-        ) => a_val > b_val,
+            &ts(1, 500),
+            &ts(2, 100)
+        ));
 
-        (
+        let str_value = |value: &str| ValueType::StringValue(value.to_string());
+        assert!(compare_values(
             CompareOp::GreaterThanOrEqual,
-            gcloud_sdk::google::firestore::v1::value::ValueType::DoubleValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::DoubleValue(b_val),
-        ) => a_val >= b_val,
+            &str_value("b"),
+            &str_value("a")
+        ));
 
-        // For TimestampValue, assumes it's a numerical timestamp; if it's a string or date type, adjust accordingly
-        (
+        assert!(compare_values(
             CompareOp::Equal,
-            gcloud_sdk::google::firestore::v1::value::ValueType::TimestampValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::TimestampValue(b_val),
-        ) => a_val == b_val,
-
-        (
-            CompareOp::NotEqual,
-            gcloud_sdk::google::firestore::v1::value::ValueType::TimestampValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::TimestampValue(b_val),
-        ) => a_val != b_val,
-
-        (
+            &ValueType::IntegerValue(1),
+            &ValueType::DoubleValue(1.0)
+        ));
+        assert!(!compare_values(
             CompareOp::LessThan,
-            gcloud_sdk::google::firestore::v1::value::ValueType::TimestampValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::TimestampValue(b_val),
-        ) => a_val.seconds < b_val.seconds && a_val.nanos < b_val.nanos,
-
-        (
-            CompareOp::LessThanOrEqual,
-            gcloud_sdk::google::firestore::v1::value::ValueType::TimestampValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::TimestampValue(b_val),
-        ) => a_val.seconds <= b_val.seconds && a_val.nanos <= b_val.nanos,
-
-        (
-            CompareOp::GreaterThan,
-            gcloud_sdk::google::firestore::v1::value::ValueType::TimestampValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::TimestampValue(b_val),
-        ) => a_val.seconds > b_val.seconds && a_val.nanos > b_val.nanos,
-
-        (
-            CompareOp::GreaterThanOrEqual,
-            gcloud_sdk::google::firestore::v1::value::ValueType::TimestampValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::TimestampValue(b_val),
-        ) => a_val.seconds >= b_val.seconds && a_val.nanos >= b_val.nanos,
-
-        // For StringType only Equal, NotEqual operations make sense in a general context
-        (
-            CompareOp::Equal,
-            gcloud_sdk::google::firestore::v1::value::ValueType::StringValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::StringValue(b_val),
-        ) => a_val == b_val,
-
-        (
-            CompareOp::NotEqual,
-            gcloud_sdk::google::firestore::v1::value::ValueType::StringValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::StringValue(b_val),
-        ) => a_val != b_val,
-
-        //  Array Operation
-        (
+            &ValueType::IntegerValue(1),
+            &str_value("a")
+        ));
+
+        let array = ValueType::ArrayValue(ArrayValue {
+            values: vec![gcloud_sdk::google::firestore::v1::Value {
+                value_type: Some(str_value("a")),
+            }],
+        });
+        assert!(compare_values(
             CompareOp::ArrayContains,
-            gcloud_sdk::google::firestore::v1::value::ValueType::ArrayValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::ArrayValue(b_val),
-        ) => a_val
-            .values
-            .iter()
-            .flat_map(|v| &v.value_type)
-            .any(|a_val| {
-                b_val
-                    .values
-                    .iter()
-                    .flat_map(|v| &v.value_type)
-                    .all(|b_val| compare_values(CompareOp::Equal, a_val, b_val))
-            }),
-
-        (
-            CompareOp::ArrayContainsAny,
-            gcloud_sdk::google::firestore::v1::value::ValueType::ArrayValue(a_val),
-            gcloud_sdk::google::firestore::v1::value::ValueType::ArrayValue(b_val),
-        ) => a_val
-            .values
-            .iter()
-            .flat_map(|v| &v.value_type)
-            .any(|a_val| {
-                b_val
-                    .values
-                    .iter()
-                    .flat_map(|v| &v.value_type)
-                    .any(|b_val| compare_values(CompareOp::Equal, a_val, b_val))
-            }),
-
-        (
-            CompareOp::In,
-            gcloud_sdk::google::firestore::v1::value::ValueType::ArrayValue(a_val),
-            b_val,
-        ) => a_val
-            .values
-            .iter()
-            .flat_map(|v| &v.value_type)
-            .any(|a_val| compare_values(CompareOp::Equal, a_val, b_val)),
-
-        (
+            &array,
+            &str_value("a")
+        ));
+        assert!(compare_values(CompareOp::In, &str_value("a"), &array));
+        assert!(!compare_values(CompareOp::NotIn, &str_value("a"), &array));
+        assert!(!compare_values(
             CompareOp::NotIn,
-            gcloud_sdk::google::firestore::v1::value::ValueType::ArrayValue(a_val),
-            b_val,
-        ) => a_val
-            .values
-            .iter()
-            .flat_map(|v| &v.value_type)
-            .any(|a_val| !compare_values(CompareOp::Equal, a_val, b_val)),
-
-        // Any other combinations result in false
-        _ => false,
+            &ValueType::NullValue(0),
+            &array
+        ));
     }
 }
@@ -1,10 +1,25 @@
 use crate::cache::cache_filter_engine::FirestoreCacheFilterEngine;
+use crate::db::order_by_with_document_name;
+use crate::firestore_value::compare_field_values;
 use crate::*;
 use futures::stream::BoxStream;
 use futures::stream::StreamExt;
-use futures::{future, TryStreamExt};
+use futures::TryStreamExt;
+use gcloud_sdk::google::firestore::v1::value::ValueType;
+use std::borrow::Cow;
 use std::cmp::Ordering;
 
+/// Executes queries against cached documents.
+///
+/// The supported subset of queries on a single collection:
+/// - all filter operators (`==`, `!=`, `<`, `<=`, `>`, `>=`, `array-contains`, `array-contains-any`,
+///   `in`, `not-in`, unary filters) combined with `AND`/`OR`;
+/// - `order_by` on any fields including `__name__`. As in Firestore, documents without the
+///   ordered fields are excluded, and without explicit ordering documents are ordered by
+///   the inequality fields and the document name;
+/// - cursors, offset and limit.
+///
+/// Collection group queries and vector search aren't supported.
 #[derive(Clone)]
 pub struct FirestoreCacheQueryEngine {
     pub query: FirestoreQueryParams,
@@ -19,6 +34,11 @@ impl FirestoreCacheQueryEngine {
 
     pub fn params_supported(&self) -> bool {
         self.query.all_descendants.iter().all(|x| !*x)
+            && matches!(
+                self.query.collection_id,
+                FirestoreQueryCollection::Single(_)
+            )
+            && self.query.find_nearest.is_none()
     }
 
     pub fn matches_doc(&self, doc: &FirestoreDocument) -> bool {
@@ -30,251 +50,268 @@ impl FirestoreCacheQueryEngine {
         }
     }
 
-    pub async fn sort_stream<'a, 'b>(
+    pub async fn process_query_stream<'a, 'b>(
         &'a self,
         input: BoxStream<'b, FirestoreResult<FirestoreDocument>>,
     ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocument>>> {
-        if let Some(order_by) = &self.query.order_by {
-            let mut collected: Vec<FirestoreDocument> = input.try_collect().await?;
-            collected.sort_by(|doc_a, doc_b| {
-                let mut current_ordering = Ordering::Equal;
-                for sort_field in order_by {
-                    match (
-                        firestore_doc_get_field_by_path(doc_a, &sort_field.field_name),
-                        firestore_doc_get_field_by_path(doc_b, &sort_field.field_name),
-                    ) {
-                        (Some(field_a), Some(field_b)) => {
-                            if cache::cache_filter_engine::compare_values(
-                                cache::cache_filter_engine::CompareOp::Equal,
-                                field_a,
-                                field_b,
-                            ) {
-                                continue;
-                            }
+        let order_by = self.effective_order_by();
 
-                            if cache::cache_filter_engine::compare_values(
-                                cache::cache_filter_engine::CompareOp::LessThan,
-                                field_a,
-                                field_b,
-                            ) {
-                                current_ordering = match sort_field.direction {
-                                    FirestoreQueryDirection::Ascending => Ordering::Less,
-                                    FirestoreQueryDirection::Descending => Ordering::Greater,
-                                }
-                            } else {
-                                current_ordering = match sort_field.direction {
-                                    FirestoreQueryDirection::Ascending => Ordering::Greater,
-                                    FirestoreQueryDirection::Descending => Ordering::Less,
-                                }
-                            }
-                        }
-                        (None, None) => current_ordering = Ordering::Equal,
-                        (None, Some(_)) => current_ordering = Ordering::Equal,
-                        (Some(_), None) => current_ordering = Ordering::Equal,
-                    }
-                }
-                current_ordering
-            });
-            Ok(futures::stream::iter(collected.into_iter().map(Ok)).boxed())
-        } else {
-            Ok(input)
-        }
+        let mut docs: Vec<FirestoreDocument> = input
+            .try_filter(|doc| {
+                futures::future::ready(
+                    order_by
+                        .iter()
+                        .all(|order| doc_field_value(doc, &order.field_name).is_some()),
+                )
+            })
+            .try_collect()
+            .await?;
+
+        docs.sort_by(|doc_a, doc_b| compare_docs(doc_a, doc_b, &order_by));
+
+        let docs = docs
+            .into_iter()
+            .filter(|doc| self.after_start_at(doc, &order_by) && self.before_end_at(doc, &order_by))
+            .skip(self.query.offset.unwrap_or(0) as usize)
+            .take(
+                self.query
+                    .limit
+                    .map(|limit| limit as usize)
+                    .unwrap_or(usize::MAX),
+            );
+
+        Ok(futures::stream::iter(docs.map(Ok).collect::<Vec<_>>()).boxed())
     }
 
-    pub async fn limit_stream<'a, 'b>(
-        &'a self,
-        input: BoxStream<'b, FirestoreResult<FirestoreDocument>>,
-    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocument>>> {
-        if let Some(limit) = self.query.limit {
-            Ok(input
-                .scan(0_u32, move |index, doc| {
-                    if *index < limit {
-                        *index += 1;
-                        future::ready(Some(doc))
-                    } else {
-                        future::ready(None)
-                    }
+    /// Explicit ordering or the implicit Firestore ordering by inequality fields,
+    /// followed by the document name.
+    fn effective_order_by(&self) -> Vec<FirestoreQueryOrder> {
+        let order_by = self.query.order_by.clone().unwrap_or_else(|| {
+            let mut inequality_fields = Vec::new();
+            if let Some(filter) = &self.query.filter {
+                collect_inequality_fields(filter, &mut inequality_fields);
+            }
+            inequality_fields.sort();
+            inequality_fields.dedup();
+            inequality_fields
+                .into_iter()
+                .map(|field_name| {
+                    FirestoreQueryOrder::new(field_name, FirestoreQueryDirection::Ascending)
                 })
-                .boxed())
-        } else {
-            Ok(input)
+                .collect()
+        });
+        order_by_with_document_name(Some(order_by))
+    }
+
+    fn after_start_at(&self, doc: &FirestoreDocument, order_by: &[FirestoreQueryOrder]) -> bool {
+        match &self.query.start_at {
+            Some(FirestoreQueryCursor::BeforeValue(values)) => {
+                compare_doc_to_cursor(doc, values, order_by) != Ordering::Less
+            }
+            Some(FirestoreQueryCursor::AfterValue(values)) => {
+                compare_doc_to_cursor(doc, values, order_by) == Ordering::Greater
+            }
+            None => true,
         }
     }
 
-    pub async fn offset_stream<'a, 'b>(
-        &'a self,
-        input: BoxStream<'b, FirestoreResult<FirestoreDocument>>,
-    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocument>>> {
-        if let Some(offset) = self.query.offset {
-            Ok(input.skip(offset as usize).boxed())
-        } else {
-            Ok(input)
+    fn before_end_at(&self, doc: &FirestoreDocument, order_by: &[FirestoreQueryOrder]) -> bool {
+        match &self.query.end_at {
+            Some(FirestoreQueryCursor::BeforeValue(values)) => {
+                compare_doc_to_cursor(doc, values, order_by) == Ordering::Less
+            }
+            Some(FirestoreQueryCursor::AfterValue(values)) => {
+                compare_doc_to_cursor(doc, values, order_by) != Ordering::Greater
+            }
+            None => true,
         }
     }
+}
 
-    pub async fn start_at_stream<'a, 'b>(
-        &'a self,
-        input: BoxStream<'b, FirestoreResult<FirestoreDocument>>,
-    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocument>>> {
-        if let Some(start_at) = &self.query.start_at {
-            if let Some(order_by) = &self.query.order_by {
-                let start_at = start_at.clone();
-                let order_by = order_by.clone();
-                Ok(input
-                    .skip_while(move |doc_res| match doc_res {
-                        Ok(doc) => match &start_at {
-                            FirestoreQueryCursor::BeforeValue(values) => {
-                                let result = values.iter().zip(&order_by).any(
-                                    |(value, ordered_field)| {
-                                        let order_by_comp = match ordered_field.direction {
-                                            FirestoreQueryDirection::Ascending => cache::cache_filter_engine::CompareOp::LessThan,
-                                            FirestoreQueryDirection::Descending => cache::cache_filter_engine::CompareOp::GreaterThan
-                                        };
-                                        match (
-                                            firestore_doc_get_field_by_path(
-                                                doc,
-                                                &ordered_field.field_name,
-                                            ),
-                                            &value.value.value_type,
-                                        ) {
-                                            (Some(field_a), Some(field_b)) => {
-                                                cache::cache_filter_engine::compare_values(
-                                                    order_by_comp,
-                                                    field_a,
-                                                    field_b,
-                                                )
-                                            }
-                                            (_, _) => false,
-                                        }
-                                    },
-                                );
-                                future::ready(result)
-                            }
-                            FirestoreQueryCursor::AfterValue(values) => {
-                                let result = values.iter().zip(&order_by).any(
-                                    |(value, ordered_field)| {
-                                        let order_by_comp = match ordered_field.direction {
-                                            FirestoreQueryDirection::Ascending => cache::cache_filter_engine::CompareOp::LessThanOrEqual,
-                                            FirestoreQueryDirection::Descending => cache::cache_filter_engine::CompareOp::GreaterThanOrEqual
-                                        };
-                                        match (
-                                            firestore_doc_get_field_by_path(
-                                                doc,
-                                                &ordered_field.field_name,
-                                            ),
-                                            &value.value.value_type,
-                                        ) {
-                                            (Some(field_a), Some(field_b)) => {
-                                                cache::cache_filter_engine::compare_values(
-                                                    order_by_comp,
-                                                    field_a,
-                                                    field_b,
-                                                )
-                                            }
-                                            (_, _) => false,
-                                        }
-                                    },
-                                );
-                                future::ready(result)
-                            }
-                        },
-                        Err(_) => future::ready(false),
-                    })
-                    .boxed())
-            } else {
-                Ok(input)
+fn collect_inequality_fields(filter: &FirestoreQueryFilter, fields: &mut Vec<String>) {
+    match filter {
+        FirestoreQueryFilter::Composite(composite_filter) => {
+            for filter in &composite_filter.for_all_filters {
+                collect_inequality_fields(filter, fields);
             }
-        } else {
-            Ok(input)
         }
+        FirestoreQueryFilter::Compare(Some(
+            FirestoreQueryFilterCompare::LessThan(field_name, _)
+            | FirestoreQueryFilterCompare::LessThanOrEqual(field_name, _)
+            | FirestoreQueryFilterCompare::GreaterThan(field_name, _)
+            | FirestoreQueryFilterCompare::GreaterThanOrEqual(field_name, _)
+            | FirestoreQueryFilterCompare::NotEqual(field_name, _)
+            | FirestoreQueryFilterCompare::NotIn(field_name, _),
+        )) => fields.push(field_name.clone()),
+        _ => {}
     }
+}
 
-    pub async fn end_at_stream<'a, 'b>(
-        &'a self,
-        input: BoxStream<'b, FirestoreResult<FirestoreDocument>>,
-    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocument>>> {
-        if let Some(end_at) = &self.query.end_at {
-            if let Some(order_by) = &self.query.order_by {
-                let end_at = end_at.clone();
-                let order_by = order_by.clone();
-                Ok(input
-                    .take_while(move |doc_res| match doc_res {
-                        Ok(doc) => match &end_at {
-                            FirestoreQueryCursor::BeforeValue(values) => {
-                                let result = values.iter().zip(&order_by).any(
-                                    |(value, ordered_field)| {
-                                        let order_by_comp = match ordered_field.direction {
-                                            FirestoreQueryDirection::Ascending => cache::cache_filter_engine::CompareOp::LessThan,
-                                            FirestoreQueryDirection::Descending => cache::cache_filter_engine::CompareOp::GreaterThan
-                                        };
-                                        match (
-                                            firestore_doc_get_field_by_path(
-                                                doc,
-                                                &ordered_field.field_name,
-                                            ),
-                                            &value.value.value_type,
-                                        ) {
-                                            (Some(field_a), Some(field_b)) => {
-                                                cache::cache_filter_engine::compare_values(
-                                                    order_by_comp,
-                                                    field_a,
-                                                    field_b,
-                                                )
-                                            }
-                                            (_, _) => false,
-                                        }
-                                    },
-                                );
-                                future::ready(result)
-                            }
-                            FirestoreQueryCursor::AfterValue(values) => {
-                                let result = values.iter().zip(&order_by).any(
-                                    |(value, ordered_field)| {
-                                        let order_by_comp = match ordered_field.direction {
-                                            FirestoreQueryDirection::Ascending => cache::cache_filter_engine::CompareOp::LessThanOrEqual,
-                                            FirestoreQueryDirection::Descending => cache::cache_filter_engine::CompareOp::GreaterThanOrEqual
-                                        };
-                                        match (
-                                            firestore_doc_get_field_by_path(
-                                                doc,
-                                                &ordered_field.field_name,
-                                            ),
-                                            &value.value.value_type,
-                                        ) {
-                                            (Some(field_a), Some(field_b)) => {
-                                                cache::cache_filter_engine::compare_values(
-                                                    order_by_comp,
-                                                    field_a,
-                                                    field_b,
-                                                )
-                                            }
-                                            (_, _) => false,
-                                        }
-                                    },
-                                );
-                                future::ready(result)
-                            }
-                        },
-                        Err(_) => future::ready(false),
-                    })
-                    .boxed())
-            } else {
-                Ok(input)
+/// The value of the field, or the document name for `__name__`.
+pub(super) fn doc_field_value<'d>(
+    doc: &'d FirestoreDocument,
+    field_name: &str,
+) -> Option<Cow<'d, ValueType>> {
+    if field_name == FIRESTORE_DOCUMENT_NAME_FIELD {
+        Some(Cow::Owned(ValueType::ReferenceValue(doc.name.clone())))
+    } else {
+        firestore_doc_get_field_by_path(doc, field_name).map(Cow::Borrowed)
+    }
+}
+
+fn directed(ordering: Ordering, direction: &FirestoreQueryDirection) -> Ordering {
+    match direction {
+        FirestoreQueryDirection::Ascending => ordering,
+        FirestoreQueryDirection::Descending => ordering.reverse(),
+    }
+}
+
+fn compare_docs(
+    doc_a: &FirestoreDocument,
+    doc_b: &FirestoreDocument,
+    order_by: &[FirestoreQueryOrder],
+) -> Ordering {
+    order_by
+        .iter()
+        .map(|order| {
+            match (
+                doc_field_value(doc_a, &order.field_name),
+                doc_field_value(doc_b, &order.field_name),
+            ) {
+                (Some(value_a), Some(value_b)) => {
+                    directed(compare_field_values(&value_a, &value_b), &order.direction)
+                }
+                _ => Ordering::Equal,
             }
-        } else {
-            Ok(input)
+        })
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Compares the document with the cursor values, which are a prefix of the ordered fields.
+fn compare_doc_to_cursor(
+    doc: &FirestoreDocument,
+    values: &[FirestoreValue],
+    order_by: &[FirestoreQueryOrder],
+) -> Ordering {
+    values
+        .iter()
+        .zip(order_by)
+        .map(|(value, order)| {
+            match (
+                doc_field_value(doc, &order.field_name),
+                &value.value.value_type,
+            ) {
+                (Some(doc_value), Some(cursor_value)) => directed(
+                    compare_field_values(&doc_value, cursor_value),
+                    &order.direction,
+                ),
+                _ => Ordering::Equal,
+            }
+        })
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::split_document_path;
+    use gcloud_sdk::google::firestore::v1::Value;
+
+    fn test_doc(id: &str, rank: Option<i64>, tag: &str) -> FirestoreDocument {
+        let mut fields = std::collections::HashMap::from([(
+            "tag".to_string(),
+            Value {
+                value_type: Some(ValueType::StringValue(tag.to_string())),
+            },
+        )]);
+        if let Some(rank) = rank {
+            fields.insert(
+                "rank".to_string(),
+                Value {
+                    value_type: Some(ValueType::IntegerValue(rank)),
+                },
+            );
+        }
+        FirestoreDocument {
+            name: format!("projects/test/databases/(default)/documents/test/{id}"),
+            fields,
+            create_time: None,
+            update_time: None,
         }
     }
 
-    pub async fn process_query_stream<'a, 'b>(
-        &'a self,
-        input: BoxStream<'b, FirestoreResult<FirestoreDocument>>,
-    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocument>>> {
-        let input = self.sort_stream(input).await?;
-        let input = self.limit_stream(input).await?;
-        let input = self.offset_stream(input).await?;
-        let input = self.start_at_stream(input).await?;
-        let input = self.end_at_stream(input).await?;
-        Ok(input)
+    fn test_docs() -> Vec<FirestoreDocument> {
+        vec![
+            test_doc("d", Some(3), "b"),
+            test_doc("a", Some(1), "a"),
+            test_doc("c", None, "a"),
+            test_doc("b", Some(2), "b"),
+            test_doc("e", Some(2), "a"),
+        ]
+    }
+
+    async fn run_query(params: FirestoreQueryParams) -> Vec<String> {
+        let engine = FirestoreCacheQueryEngine::new(&params);
+        assert!(engine.params_supported());
+        let docs: Vec<FirestoreResult<FirestoreDocument>> = test_docs()
+            .into_iter()
+            .filter(|doc| engine.matches_doc(doc))
+            .map(Ok)
+            .collect();
+        engine
+            .process_query_stream(futures::stream::iter(docs).boxed())
+            .await
+            .unwrap()
+            .map_ok(|doc| split_document_path(&doc.name).1.to_string())
+            .try_collect()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn inequality_filters_with_implicit_ordering() {
+        let params = FirestoreQueryParams::new("test".into()).with_filter(
+            FirestoreQueryFilter::Compare(Some(FirestoreQueryFilterCompare::GreaterThanOrEqual(
+                "rank".to_string(),
+                2.into(),
+            ))),
+        );
+        assert_eq!(run_query(params).await, vec!["b", "e", "d"]);
+
+        let params = FirestoreQueryParams::new("test".into()).with_filter(
+            FirestoreQueryFilter::Compare(Some(FirestoreQueryFilterCompare::In(
+                "tag".to_string(),
+                vec!["a"].into(),
+            ))),
+        );
+        assert_eq!(run_query(params).await, vec!["a", "c", "e"]);
+    }
+
+    #[tokio::test]
+    async fn order_by_cursors_offset_and_limit() {
+        let params =
+            FirestoreQueryParams::new("test".into()).with_order_by(vec![FirestoreQueryOrder::new(
+                "rank".to_string(),
+                FirestoreQueryDirection::Descending,
+            )]);
+        assert_eq!(run_query(params.clone()).await, vec!["d", "e", "b", "a"]);
+
+        assert_eq!(
+            run_query(
+                params
+                    .clone()
+                    .with_start_at(FirestoreQueryCursor::AfterValue(vec![3.into()]))
+                    .with_end_at(FirestoreQueryCursor::AfterValue(vec![2.into()]))
+            )
+            .await,
+            vec!["e", "b"]
+        );
+
+        assert_eq!(
+            run_query(params.with_offset(1).with_limit(2)).await,
+            vec!["e", "b"]
+        );
     }
 }
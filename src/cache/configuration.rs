@@ -1,5 +1,7 @@
 use crate::{FirestoreDb, FirestoreListenerTarget};
+use rsb_derive::Builder;
 use std::collections::HashMap;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct FirestoreCacheConfiguration {
@@ -44,6 +46,7 @@ pub struct FirestoreCacheCollectionConfiguration {
     pub listener_target: FirestoreListenerTarget,
     pub collection_load_mode: FirestoreCacheCollectionLoadMode,
     pub indices: Vec<FirestoreCacheIndexConfiguration>,
+    pub consistency: FirestoreCacheConsistency,
 }
 
 impl FirestoreCacheCollectionConfiguration {
@@ -62,6 +65,7 @@ impl FirestoreCacheCollectionConfiguration {
             listener_target,
             collection_load_mode,
             indices: Vec::new(),
+            consistency: FirestoreCacheConsistency::new(),
         }
     }
 
@@ -82,6 +86,34 @@ impl FirestoreCacheCollectionConfiguration {
         indices.push(index);
         Self { indices, ..self }
     }
+
+    #[inline]
+    pub fn with_consistency(self, consistency: FirestoreCacheConsistency) -> Self {
+        Self {
+            consistency,
+            ..self
+        }
+    }
+}
+
+/// Consistency strategy of a cached collection in addition to the listener updates.
+///
+/// The strategy applies to the operations performed with a `FirestoreDb` instance
+/// associated with the cache (e.g. using `read_through_cache()`).
+#[derive(Debug, Eq, PartialEq, Clone, Builder)]
+pub struct FirestoreCacheConsistency {
+    /// Documents created, updated or deleted via the `FirestoreDb` instance are written to
+    /// the cache immediately, without waiting for the listener.
+    #[default = "false"]
+    pub write_through: bool,
+
+    /// Documents missing in the cache and read from Firestore are stored in the cache.
+    #[default = "true"]
+    pub read_through: bool,
+
+    /// Cached documents are served immediately and refreshed from Firestore in the background
+    /// if they haven't been refreshed for the specified duration.
+    pub stale_while_revalidate: Option<Duration>,
 }
 
 #[derive(Debug, Clone)]
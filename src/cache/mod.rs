@@ -39,6 +39,8 @@ mod configuration;
 pub use configuration::*;
 
 mod backends;
+// Empty without the `caching-memory` and `caching-persistent` features.
+#[allow(unused_imports)]
 pub use backends::*;
 
 use async_trait::async_trait;
@@ -47,7 +49,7 @@ use futures::StreamExt;
 use tracing::*;
 
 mod cache_filter_engine;
-mod cache_query_engine;
+pub(crate) mod cache_query_engine;
 
 /// Manages a cache of Firestore data.
 ///
@@ -181,6 +183,8 @@ where
         }
 
         let backend = self.inner.backend.clone();
+        // The persistent backend stores the resume tokens with the documents
+        self.inner.listener.enable_resume_token_events();
         self.inner
             .listener
             .start(move |event| {
@@ -273,6 +277,17 @@ pub trait FirestoreCacheBackend: FirestoreCacheDocsByPathSupport {
     /// # Returns
     /// A `FirestoreResult` indicating success or failure of processing the event.
     async fn on_listen_event(&self, event: FirestoreListenEvent) -> FirestoreResult<()>;
+
+    /// Returns the consistency strategy configured for a cached collection.
+    ///
+    /// # Arguments
+    /// * `collection_path`: The full path to the collection.
+    ///
+    /// # Returns
+    /// `None` if the collection isn't cached by this backend.
+    fn collection_consistency(&self, _collection_path: &str) -> Option<&FirestoreCacheConsistency> {
+        None
+    }
 }
 
 /// Defines support for retrieving and updating cached documents by their full path.
@@ -316,7 +331,7 @@ pub trait FirestoreCacheDocsByPathSupport {
                         let doc_id = document
                             .name
                             .split('/')
-                            .last()
+                            .next_back()
                             .map(|s| s.to_string())
                             .unwrap_or_else(|| document.name.clone());
                         Ok((doc_id, Some(document)))
@@ -341,6 +356,20 @@ pub trait FirestoreCacheDocsByPathSupport {
     /// A `FirestoreResult` indicating success or failure.
     async fn update_doc_by_path(&self, document: &FirestoreDocument) -> FirestoreResult<()>;
 
+    /// Removes a document from the cache by its full Firestore path.
+    ///
+    /// The default implementation keeps the document in the cache, so backends should
+    /// override it to stop serving deleted documents.
+    ///
+    /// # Arguments
+    /// * `document_path`: The full path to the document.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` indicating success or failure.
+    async fn delete_doc_by_path(&self, _document_path: &str) -> FirestoreResult<()> {
+        Ok(())
+    }
+
     /// Lists all documents in the cache for a given collection path.
     ///
     /// # Arguments
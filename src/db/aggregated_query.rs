@@ -1,6 +1,14 @@
 #![allow(clippy::derive_partial_eq_without_eq)] // Since we may not be able to implement Eq for the changes coming from Firestore protos
 
-use crate::{FirestoreDb, FirestoreError, FirestoreQueryParams, FirestoreResult};
+use crate::errors::{
+    firestore_query_status_error, FirestoreInvalidParametersError,
+    FirestoreInvalidParametersPublicDetails,
+};
+use crate::firestore_serde::firestore_aggregation_result_scope;
+use crate::{
+    FirestoreApiResponse, FirestoreDb, FirestoreError, FirestoreOperationKind,
+    FirestoreQueryParams, FirestoreResult, FirestoreRetryPolicy, FirestoreWithMetadata,
+};
 use async_trait::async_trait;
 use chrono::prelude::*;
 use futures::future::BoxFuture;
@@ -10,7 +18,6 @@ use futures::TryFutureExt;
 use futures::TryStreamExt;
 use futures::{future, StreamExt};
 use gcloud_sdk::google::firestore::v1::*;
-use rand::Rng;
 use rsb_derive::*;
 use serde::Deserialize;
 use tracing::*;
@@ -27,6 +34,26 @@ pub struct FirestoreAggregation {
     pub operator: Option<FirestoreAggregationOperator>,
 }
 
+/// Firestore returns the results of the aggregations with the same alias in a single field,
+/// so the duplicates are rejected instead of losing all but one of the results.
+pub(crate) fn ensure_unique_aggregation_aliases(
+    aggregations: &[FirestoreAggregation],
+) -> FirestoreResult<()> {
+    let mut aliases = std::collections::HashSet::new();
+    match aggregations
+        .iter()
+        .find(|aggregation| !aliases.insert(aggregation.alias.as_str()))
+    {
+        Some(duplicate) => Err(FirestoreError::InvalidParametersError(
+            FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                "aggregations".to_string(),
+                format!("Duplicate aggregation alias: {}", duplicate.alias),
+            )),
+        )),
+        None => Ok(()),
+    }
+}
+
 impl From<&FirestoreAggregation> for structured_aggregation_query::Aggregation {
     fn from(aggregation: &FirestoreAggregation) -> Self {
         structured_aggregation_query::Aggregation {
@@ -119,6 +146,11 @@ pub trait FirestoreAggregatedQuerySupport {
         params: FirestoreAggregatedQueryParams,
     ) -> FirestoreResult<BoxStream<'b, FirestoreResult<Document>>>;
 
+    async fn stream_aggregated_query_doc_with_metadata<'b>(
+        &self,
+        params: FirestoreAggregatedQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<Document>>>>;
+
     async fn aggregated_query_obj<T>(
         &self,
         params: FirestoreAggregatedQueryParams,
@@ -140,6 +172,14 @@ pub trait FirestoreAggregatedQuerySupport {
     where
         for<'de> T: Deserialize<'de>,
         T: Send + 'b;
+
+    async fn stream_aggregated_query_obj_with_metadata<'b, T>(
+        &self,
+        params: FirestoreAggregatedQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<T>>>>
+    where
+        for<'de> T: Deserialize<'de>,
+        T: Send + 'b;
 }
 
 #[async_trait]
@@ -154,7 +194,9 @@ impl FirestoreAggregatedQuerySupport for FirestoreDb {
             Level::DEBUG,
             "Firestore Aggregated Query",
             "/firestore/collection_name" = collection_str.as_str(),
-            "/firestore/response_time" = field::Empty
+            "/firestore/response_time" = field::Empty,
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty
         );
         self.aggregated_query_doc_with_retries(params, 0, &span)
             .await
@@ -170,7 +212,9 @@ impl FirestoreAggregatedQuerySupport for FirestoreDb {
             Level::DEBUG,
             "Firestore Streaming Aggregated Query",
             "/firestore/collection_name" = collection_str.as_str(),
-            "/firestore/response_time" = field::Empty
+            "/firestore/response_time" = field::Empty,
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty
         );
 
         let doc_stream = self
@@ -179,8 +223,7 @@ impl FirestoreAggregatedQuerySupport for FirestoreDb {
 
         Ok(Box::pin(doc_stream.filter_map(|doc_res| {
             future::ready(match doc_res {
-                Ok(Some(doc)) => Some(doc),
-                Ok(None) => None,
+                Ok(resp) => resp.document,
                 Err(err) => {
                     error!(%err, "Error occurred while consuming query.");
                     None
@@ -199,7 +242,9 @@ impl FirestoreAggregatedQuerySupport for FirestoreDb {
             Level::DEBUG,
             "Firestore Streaming Aggregated Query",
             "/firestore/collection_name" = collection_str.as_str(),
-            "/firestore/response_time" = field::Empty
+            "/firestore/response_time" = field::Empty,
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty
         );
 
         let doc_stream = self
@@ -208,8 +253,7 @@ impl FirestoreAggregatedQuerySupport for FirestoreDb {
 
         Ok(Box::pin(doc_stream.filter_map(|doc_res| {
             future::ready(match doc_res {
-                Ok(Some(doc)) => Some(Ok(doc)),
-                Ok(None) => None,
+                Ok(resp) => resp.document.map(Ok),
                 Err(err) => {
                     error!(%err, "Error occurred while consuming query.");
                     Some(Err(err))
@@ -218,6 +262,25 @@ impl FirestoreAggregatedQuerySupport for FirestoreDb {
         })))
     }
 
+    async fn stream_aggregated_query_doc_with_metadata<'b>(
+        &self,
+        params: FirestoreAggregatedQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<Document>>>> {
+        let collection_str = params.query_params.collection_id.to_string();
+
+        let span = span!(
+            Level::DEBUG,
+            "Firestore Streaming Aggregated Query with Metadata",
+            "/firestore/collection_name" = collection_str.as_str(),
+            "/firestore/response_time" = field::Empty,
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty
+        );
+
+        self.stream_aggregated_query_doc_with_retries(params, 0, &span)
+            .await
+    }
+
     async fn aggregated_query_obj<T>(
         &self,
         params: FirestoreAggregatedQueryParams,
@@ -228,7 +291,7 @@ impl FirestoreAggregatedQuerySupport for FirestoreDb {
         let doc_vec = self.aggregated_query_doc(params).await?;
         doc_vec
             .iter()
-            .map(|doc| Self::deserialize_doc_to(doc))
+            .map(|doc| Self::deserialize_aggregation_result(doc))
             .collect()
     }
 
@@ -241,7 +304,7 @@ impl FirestoreAggregatedQuerySupport for FirestoreDb {
     {
         let doc_stream = self.stream_aggregated_query_doc(params).await?;
         Ok(Box::pin(doc_stream.filter_map(|doc| async move {
-            match Self::deserialize_doc_to::<T>(&doc) {
+            match Self::deserialize_aggregation_result::<T>(&doc) {
                 Ok(obj) => Some(obj),
                 Err(err) => {
                     error!(
@@ -264,17 +327,58 @@ impl FirestoreAggregatedQuerySupport for FirestoreDb {
     {
         let doc_stream = self.stream_aggregated_query_doc_with_errors(params).await?;
         Ok(Box::pin(doc_stream.and_then(|doc| {
-            future::ready(Self::deserialize_doc_to::<T>(&doc))
+            future::ready(Self::deserialize_aggregation_result::<T>(&doc))
+        })))
+    }
+
+    async fn stream_aggregated_query_obj_with_metadata<'b, T>(
+        &self,
+        params: FirestoreAggregatedQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<T>>>>
+    where
+        for<'de> T: Deserialize<'de>,
+        T: Send + 'b,
+    {
+        let res_stream = self
+            .stream_aggregated_query_doc_with_metadata(params)
+            .await?;
+        Ok(Box::pin(res_stream.map(|res| {
+            res.and_then(|with_meta| {
+                Ok(FirestoreWithMetadata {
+                    document: with_meta
+                        .document
+                        .map(|document| Self::deserialize_aggregation_result::<T>(&document))
+                        .transpose()?,
+                    metadata: with_meta.metadata,
+                })
+            })
         })))
     }
 }
 
 impl FirestoreDb {
+    /// Deserializes a document of aggregation results, where sums over integer fields
+    /// may be returned as doubles without a fractional part.
+    fn deserialize_aggregation_result<T>(doc: &Document) -> FirestoreResult<T>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        firestore_aggregation_result_scope(|| Self::deserialize_doc_to(doc))
+    }
+
     fn create_aggregated_query_request(
         &self,
         params: FirestoreAggregatedQueryParams,
     ) -> FirestoreResult<gcloud_sdk::tonic::Request<RunAggregationQueryRequest>> {
-        Ok(gcloud_sdk::tonic::Request::new(RunAggregationQueryRequest {
+        ensure_unique_aggregation_aliases(&params.aggregations)?;
+        let explain_options = params
+            .query_params
+            .explain_options
+            .as_ref()
+            .map(|eo| eo.try_into())
+            .transpose()?;
+
+        self.create_api_request(RunAggregationQueryRequest {
             parent: params
                 .query_params
                 .parent
@@ -282,19 +386,18 @@ impl FirestoreDb {
                 .unwrap_or_else(|| self.get_documents_path())
                 .clone(),
             consistency_selector: self
-                .session_params
-                .consistency_selector
+                .read_consistency_selector(params.query_params.consistency_selector.as_ref())?
                 .as_ref()
                 .map(|selector| selector.try_into())
                 .transpose()?,
             query_type: Some(run_aggregation_query_request::QueryType::StructuredAggregationQuery(
                 StructuredAggregationQuery {
                     aggregations: params.aggregations.iter().map(|agg| agg.into()).collect(),
-                    query_type: Some(gcloud_sdk::google::firestore::v1::structured_aggregation_query::QueryType::StructuredQuery(params.query_params.try_into()?)),
+                    query_type: Some(gcloud_sdk::google::firestore::v1::structured_aggregation_query::QueryType::StructuredQuery(params.query_params.resolve_document_ids(self.get_documents_path()).try_into()?)),
                 }
             )),
-            explain_options: None,
-        }))
+            explain_options,
+        })
     }
 
     fn stream_aggregated_query_doc_with_retries<'a, 'b>(
@@ -302,23 +405,37 @@ impl FirestoreDb {
         params: FirestoreAggregatedQueryParams,
         retries: usize,
         span: &'a Span,
-    ) -> BoxFuture<'a, FirestoreResult<BoxStream<'b, FirestoreResult<Option<Document>>>>> {
+    ) -> BoxFuture<
+        'a,
+        FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<Document>>>>,
+    > {
         async move {
             let query_request = self.create_aggregated_query_request(params.clone())?;
             let begin_query_utc: DateTime<Utc> = Utc::now();
+            let metrics = self.record_operation(
+                FirestoreOperationKind::AggregatedQuery,
+                Some(params.query_params.collection_id.to_string().as_str()),
+                span,
+            );
 
             match self
                 .client()
                 .get()
                 .run_aggregation_query(query_request)
-                .map_err(|e| e.into())
+                .map_err(firestore_query_status_error)
                 .await
             {
                 Ok(query_response) => {
+                    let db = self.clone();
                     let query_stream = query_response
                         .into_inner()
-                        .map_ok(Self::aggregated_response_to_doc)
-                        .map_err(|e| e.into())
+                        .map_err(firestore_query_status_error)
+                        .inspect_ok(move |response| {
+                            db.notify_response_middlewares(
+                                FirestoreApiResponse::RunAggregationQuery(response),
+                            )
+                        })
+                        .and_then(|resp| future::ready(resp.try_into()))
                         .boxed();
 
                     let end_query_utc: DateTime<Utc> = Utc::now();
@@ -336,19 +453,23 @@ impl FirestoreDb {
                         );
                     });
 
-                    Ok(query_stream)
+                    Ok(metrics.record_stream(
+                        query_stream,
+                        |res: &FirestoreWithMetadata<Document>| usize::from(res.document.is_some()),
+                    ))
                 }
-                Err(err) => match err {
-                    FirestoreError::DatabaseError(ref db_err)
-                    if db_err.retry_possible && retries < self.inner.options.max_retries =>
+                Err(err) => {
+                    metrics.record_failure(&err);
+                    let retry_policy = FirestoreRetryPolicy::current(&self.inner.options);
+                    match err {
+                        FirestoreError::DatabaseError(ref db_err)
+                            if retry_policy.should_retry(&err, retries, true) =>
                         {
-                            let sleep_duration = tokio::time::Duration::from_millis(
-                                rand::rng().random_range(0..2u64.pow(retries as u32) * 1000 + 1),
-                            );
+                            let sleep_duration = retry_policy.backoff(retries);
                             warn!(
                                 err = %db_err,
                                 current_retry = retries + 1,
-                                max_retries = self.inner.options.max_retries,
+                                max_attempts = retry_policy.max_attempts,
                                 delay = sleep_duration.as_millis(),
                                 "Failed to run aggregation query. Retrying up to the specified number of times.",
                             );
@@ -358,8 +479,9 @@ impl FirestoreDb {
                             self.stream_aggregated_query_doc_with_retries(params, retries + 1, span)
                                 .await
                         }
-                    _ => Err(err),
-                },
+                        _ => Err(err),
+                    }
+                }
             }
         }
             .boxed()
@@ -374,6 +496,11 @@ impl FirestoreDb {
         async move {
             let query_request = self.create_aggregated_query_request(params.clone())?;
             let begin_query_utc: DateTime<Utc> = Utc::now();
+            let metrics = self.record_operation(
+                FirestoreOperationKind::AggregatedQuery,
+                Some(params.query_params.collection_id.to_string().as_str()),
+                span,
+            );
 
             match self
                 .client()
@@ -383,14 +510,21 @@ impl FirestoreDb {
                 .await
             {
                 Ok(query_response) => {
-                    let query_stream = query_response
+                    let query_result: FirestoreResult<Vec<Document>> = query_response
                         .into_inner()
+                        .inspect_ok(|response| {
+                            self.notify_response_middlewares(
+                                FirestoreApiResponse::RunAggregationQuery(response),
+                            )
+                        })
                         .map_ok(Self::aggregated_response_to_doc)
                         .try_collect::<Vec<Option<Document>>>()
-                        .await?
-                        .into_iter()
-                        .flatten()
-                        .collect();
+                        .await
+                        .map(|docs| docs.into_iter().flatten().collect())
+                        .map_err(|e| e.into());
+                    let document_count = query_result.as_ref().map_or(0, |docs| docs.len());
+                    metrics.record_result(&query_result, document_count);
+                    let query_stream = query_result?;
                     let end_query_utc: DateTime<Utc> = Utc::now();
                     let query_duration = end_query_utc.signed_duration_since(begin_query_utc);
 
@@ -408,17 +542,18 @@ impl FirestoreDb {
 
                     Ok(query_stream)
                 }
-                Err(err) => match err {
-                    FirestoreError::DatabaseError(ref db_err)
-                    if db_err.retry_possible && retries < self.inner.options.max_retries =>
+                Err(err) => {
+                    metrics.record_failure(&err);
+                    let retry_policy = FirestoreRetryPolicy::current(&self.inner.options);
+                    match err {
+                        FirestoreError::DatabaseError(ref db_err)
+                            if retry_policy.should_retry(&err, retries, true) =>
                         {
-                            let sleep_duration = tokio::time::Duration::from_millis(
-                                rand::rng().random_range(0..2u64.pow(retries as u32) * 1000 + 1),
-                            );
+                            let sleep_duration = retry_policy.backoff(retries);
                             warn!(
                                 err = %db_err,
                                 current_retry = retries + 1,
-                                max_retries = self.inner.options.max_retries,
+                                max_attempts = retry_policy.max_attempts,
                                 delay = sleep_duration.as_millis(),
                                 "Failed to run aggregation query. Retrying up to the specified number of times.",
                             );
@@ -428,8 +563,9 @@ impl FirestoreDb {
                             self.aggregated_query_doc_with_retries(params, retries + 1, span)
                                 .await
                         }
-                    _ => Err(err),
-                },
+                        _ => Err(err),
+                    }
+                }
             }
         }
             .boxed()
@@ -444,3 +580,102 @@ impl FirestoreDb {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcloud_sdk::google::firestore::v1::{value, Value};
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct AggStats {
+        counter: u64,
+        int_sum: i64,
+        double_sum: f64,
+        avg: Option<f64>,
+        empty_avg: Option<f64>,
+    }
+
+    fn value(value_type: value::ValueType) -> Value {
+        Value {
+            value_type: Some(value_type),
+        }
+    }
+
+    #[test]
+    fn aggregation_results_into_struct() {
+        let doc = Document {
+            name: "".to_string(),
+            fields: HashMap::from([
+                (
+                    "counter".to_string(),
+                    value(value::ValueType::IntegerValue(3)),
+                ),
+                (
+                    "int_sum".to_string(),
+                    value(value::ValueType::DoubleValue(15.0)),
+                ),
+                (
+                    "double_sum".to_string(),
+                    value(value::ValueType::IntegerValue(15)),
+                ),
+                ("avg".to_string(), value(value::ValueType::DoubleValue(5.5))),
+                (
+                    "empty_avg".to_string(),
+                    value(value::ValueType::NullValue(0)),
+                ),
+            ]),
+            create_time: None,
+            update_time: None,
+        };
+
+        assert_eq!(
+            FirestoreDb::deserialize_aggregation_result::<AggStats>(&doc).unwrap(),
+            AggStats {
+                counter: 3,
+                int_sum: 15,
+                double_sum: 15.0,
+                avg: Some(5.5),
+                empty_avg: None,
+            }
+        );
+        // Integral doubles are accepted for integer fields only in aggregation results
+        assert!(FirestoreDb::deserialize_doc_to::<AggStats>(&doc).is_err());
+    }
+
+    #[test]
+    fn fractional_sum_into_integer_fails() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct IntSum {
+            int_sum: i64,
+        }
+
+        let doc = Document {
+            name: "".to_string(),
+            fields: HashMap::from([(
+                "int_sum".to_string(),
+                value(value::ValueType::DoubleValue(15.5)),
+            )]),
+            create_time: None,
+            update_time: None,
+        };
+
+        assert!(FirestoreDb::deserialize_aggregation_result::<IntSum>(&doc).is_err());
+    }
+
+    #[test]
+    fn duplicate_aggregation_aliases_are_rejected() {
+        let count = |alias: &str| {
+            FirestoreAggregation::new(alias.to_string()).with_operator(
+                FirestoreAggregationOperator::Count(FirestoreAggregationOperatorCount::new()),
+            )
+        };
+
+        assert!(ensure_unique_aggregation_aliases(&[count("a"), count("b")]).is_ok());
+        assert!(matches!(
+            ensure_unique_aggregation_aliases(&[count("a"), count("b"), count("a")]),
+            Err(FirestoreError::InvalidParametersError(err)) if err.public.field == "aggregations"
+        ));
+    }
+}
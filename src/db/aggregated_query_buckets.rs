@@ -0,0 +1,303 @@
+use crate::errors::*;
+use crate::{
+    FirestoreAggregatedQueryParams, FirestoreAggregatedQuerySupport, FirestoreDb,
+    FirestoreQueryFilter, FirestoreQueryFilterCompare, FirestoreQueryFilterComposite,
+    FirestoreQueryFilterCompositeOperator, FirestoreResult, FirestoreTimestamp, FirestoreValue,
+};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use futures::TryStreamExt;
+use rsb_derive::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Parameters for aggregations computed separately for every range (bucket) of values of a field.
+///
+/// Firestore has no server-side `GROUP BY`, so an aggregation query restricted to every bucket
+/// is sent to Firestore, with up to `max_concurrency` of them running at the same time.
+/// Only the aggregated results are transferred, unlike [`FirestoreGroupByParams`](crate::FirestoreGroupByParams).
+#[derive(Debug, PartialEq, Clone, Builder)]
+pub struct FirestoreRangeAggregationParams {
+    /// The aggregation query executed for every bucket.
+    pub aggregated_query_params: FirestoreAggregatedQueryParams,
+    /// The field path the buckets are ranges of.
+    pub field_path: String,
+    /// The maximum number of bucket queries running at the same time.
+    #[default = "FIRESTORE_RANGE_AGGREGATION_DEFAULT_CONCURRENCY"]
+    pub max_concurrency: usize,
+}
+
+/// The default number of bucket queries running at the same time.
+pub const FIRESTORE_RANGE_AGGREGATION_DEFAULT_CONCURRENCY: usize = 10;
+
+/// A half-open range `[start, end)` of the values of a field, aggregated separately and
+/// identified by `key` in the results. Missing bounds leave the range unbounded.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FirestoreAggregationBucket<K> {
+    pub key: K,
+    /// The inclusive lower bound.
+    pub start: Option<FirestoreValue>,
+    /// The exclusive upper bound.
+    pub end: Option<FirestoreValue>,
+}
+
+impl<K> FirestoreAggregationBucket<K> {
+    pub fn new(key: K, start: Option<FirestoreValue>, end: Option<FirestoreValue>) -> Self {
+        Self { key, start, end }
+    }
+
+    /// Creates a bucket with both bounds, e.g. a range of keys or amounts.
+    pub fn range<S, E>(key: K, start: S, end: E) -> Self
+    where
+        S: Into<FirestoreValue>,
+        E: Into<FirestoreValue>,
+    {
+        Self::new(key, Some(start.into()), Some(end.into()))
+    }
+
+    fn to_filters(&self, field_path: &str) -> Vec<FirestoreQueryFilter> {
+        self.start
+            .iter()
+            .map(|start| {
+                FirestoreQueryFilter::Compare(Some(
+                    FirestoreQueryFilterCompare::GreaterThanOrEqual(
+                        field_path.to_string(),
+                        start.clone(),
+                    ),
+                ))
+            })
+            .chain(self.end.iter().map(|end| {
+                FirestoreQueryFilter::Compare(Some(FirestoreQueryFilterCompare::LessThan(
+                    field_path.to_string(),
+                    end.clone(),
+                )))
+            }))
+            .collect()
+    }
+}
+
+impl FirestoreAggregationBucket<DateTime<Utc>> {
+    /// Splits the time range `[start, end)` into consecutive buckets of the specified duration,
+    /// keyed by their start times, e.g. hourly or daily buckets over timestamp fields.
+    /// The last bucket ends at `end`, so it may be shorter.
+    pub fn time_buckets(
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        step: chrono::Duration,
+    ) -> FirestoreResult<Vec<Self>> {
+        if step <= chrono::Duration::zero() {
+            return Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "step".to_string(),
+                    format!("The step of the time buckets must be positive: {step}"),
+                )),
+            ));
+        }
+
+        let mut buckets = Vec::new();
+        let mut bucket_start = start;
+        while bucket_start < end {
+            let bucket_end = (bucket_start + step).min(end);
+            buckets.push(Self::range(
+                bucket_start,
+                FirestoreTimestamp(bucket_start),
+                FirestoreTimestamp(bucket_end),
+            ));
+            bucket_start = bucket_end;
+        }
+        Ok(buckets)
+    }
+}
+
+impl FirestoreRangeAggregationParams {
+    pub(crate) fn to_bucket_params<K>(
+        &self,
+        bucket: &FirestoreAggregationBucket<K>,
+    ) -> FirestoreAggregatedQueryParams {
+        let query_params = &self.aggregated_query_params.query_params;
+        let filters: Vec<FirestoreQueryFilter> = query_params
+            .filter
+            .iter()
+            .cloned()
+            .chain(bucket.to_filters(&self.field_path))
+            .collect();
+
+        let filter = match filters.len() {
+            0 => None,
+            1 => filters.into_iter().next(),
+            _ => Some(FirestoreQueryFilter::Composite(
+                FirestoreQueryFilterComposite::new(
+                    filters,
+                    FirestoreQueryFilterCompositeOperator::And,
+                ),
+            )),
+        };
+
+        FirestoreAggregatedQueryParams {
+            query_params: query_params.clone().opt_filter(filter),
+            ..self.aggregated_query_params.clone()
+        }
+    }
+}
+
+pub(crate) async fn aggregate_by_range_obj<D, K, T>(
+    db: &D,
+    params: FirestoreRangeAggregationParams,
+    buckets: Vec<FirestoreAggregationBucket<K>>,
+) -> FirestoreResult<BTreeMap<K, T>>
+where
+    D: FirestoreAggregatedQuerySupport + Sync,
+    K: Ord + Send,
+    for<'de> T: Deserialize<'de>,
+    T: Send,
+{
+    let max_concurrency = params.max_concurrency.max(1);
+    futures::stream::iter(buckets.into_iter().map(|bucket| {
+        let bucket_params = params.to_bucket_params(&bucket);
+        async move {
+            let results: Vec<T> = db.aggregated_query_obj(bucket_params).await?;
+            Ok(results
+                .into_iter()
+                .next()
+                .map(|result| (bucket.key, result)))
+        }
+    }))
+    .buffer_unordered(max_concurrency)
+    .try_filter_map(|result| futures::future::ready(Ok(result)))
+    .try_collect()
+    .await
+}
+
+impl FirestoreDb {
+    /// Runs the aggregation query for every bucket concurrently and returns the results by the bucket keys.
+    /// Buckets without results are omitted.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use firestore::*;
+    /// use chrono::{Duration, Utc};
+    /// use serde::Deserialize;
+    /// use std::collections::BTreeMap;
+    ///
+    /// #[derive(Debug, Deserialize)]
+    /// struct OrderStats {
+    ///     cnt: u64,
+    /// }
+    ///
+    /// # async fn run(db: &FirestoreDb) -> FirestoreResult<()> {
+    /// let params = FirestoreRangeAggregationParams::new(
+    ///     FirestoreAggregatedQueryParams::new(
+    ///         FirestoreQueryParams::new("orders".into()),
+    ///         vec![FirestoreAggregation::new("cnt".to_string())
+    ///             .with_operator(FirestoreAggregationOperator::Count(FirestoreAggregationOperatorCount::new()))],
+    ///     ),
+    ///     "created_at".to_string(),
+    /// );
+    /// let buckets = FirestoreAggregationBucket::time_buckets(
+    ///     Utc::now() - Duration::days(7),
+    ///     Utc::now(),
+    ///     Duration::days(1),
+    /// )?;
+    /// let daily: BTreeMap<_, OrderStats> = db.aggregate_grouped_by_range(params, buckets).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn aggregate_grouped_by_range<K, T>(
+        &self,
+        params: FirestoreRangeAggregationParams,
+        buckets: Vec<FirestoreAggregationBucket<K>>,
+    ) -> FirestoreResult<BTreeMap<K, T>>
+    where
+        K: Ord + Send,
+        for<'de> T: Deserialize<'de>,
+        T: Send,
+    {
+        aggregate_by_range_obj(self, params, buckets).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FirestoreAggregation, FirestoreQueryParams};
+    use chrono::TimeZone;
+
+    #[test]
+    fn time_buckets() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2024, 1, 3, 12, 0, 0).unwrap();
+        let buckets =
+            FirestoreAggregationBucket::time_buckets(start, end, chrono::Duration::days(1))
+                .unwrap();
+
+        assert_eq!(
+            buckets.iter().map(|bucket| bucket.key).collect::<Vec<_>>(),
+            vec![
+                start,
+                Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2024, 1, 3, 0, 0, 0).unwrap(),
+            ]
+        );
+        assert_eq!(buckets[2].end, Some(FirestoreTimestamp(end).into()));
+        assert!(
+            FirestoreAggregationBucket::time_buckets(start, end, chrono::Duration::zero()).is_err()
+        );
+    }
+
+    #[test]
+    fn bucket_filters() {
+        let existing_filter = FirestoreQueryFilter::Compare(Some(
+            FirestoreQueryFilterCompare::Equal("status".to_string(), "paid".into()),
+        ));
+        let params = FirestoreRangeAggregationParams::new(
+            FirestoreAggregatedQueryParams::new(
+                FirestoreQueryParams::new("orders".into()).with_filter(existing_filter.clone()),
+                vec![FirestoreAggregation::new("cnt".to_string())],
+            ),
+            "amount".to_string(),
+        );
+
+        let bucket_params =
+            params.to_bucket_params(&FirestoreAggregationBucket::range("small", 0, 100));
+        assert_eq!(
+            bucket_params.query_params.filter,
+            Some(FirestoreQueryFilter::Composite(
+                FirestoreQueryFilterComposite::new(
+                    vec![
+                        existing_filter,
+                        FirestoreQueryFilter::Compare(Some(
+                            FirestoreQueryFilterCompare::GreaterThanOrEqual(
+                                "amount".to_string(),
+                                0.into()
+                            )
+                        )),
+                        FirestoreQueryFilter::Compare(Some(FirestoreQueryFilterCompare::LessThan(
+                            "amount".to_string(),
+                            100.into()
+                        ))),
+                    ],
+                    FirestoreQueryFilterCompositeOperator::And,
+                )
+            ))
+        );
+
+        let unfiltered = FirestoreRangeAggregationParams {
+            aggregated_query_params: FirestoreAggregatedQueryParams::new(
+                FirestoreQueryParams::new("orders".into()),
+                vec![],
+            ),
+            ..params
+        };
+        let open_bucket = FirestoreAggregationBucket::new("large", Some(1000.into()), None);
+        assert_eq!(
+            unfiltered
+                .to_bucket_params(&open_bucket)
+                .query_params
+                .filter,
+            Some(FirestoreQueryFilter::Compare(Some(
+                FirestoreQueryFilterCompare::GreaterThanOrEqual("amount".to_string(), 1000.into())
+            )))
+        );
+    }
+}
@@ -0,0 +1,325 @@
+use crate::errors::*;
+use crate::*;
+use gcloud_sdk::google::firestore::v1::firestore_client::FirestoreClient;
+use gcloud_sdk::tonic::transport::{Channel, Endpoint};
+use gcloud_sdk::{GoogleAuthMiddleware, GoogleAuthTokenGenerator, TokenSourceType};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The gRPC client for the Firestore API.
+///
+/// Holds a pool of authenticated gRPC channels configured with
+/// [`FirestoreDbConnectionOptions`] and hands them out in round-robin order.
+/// The client is cheap to clone, and the clones share the channels.
+#[derive(Clone)]
+pub struct FirestoreApiClient {
+    channels: Arc<Vec<GoogleAuthMiddleware>>,
+    // The connections without authentication, to create the channels for another database
+    connections: Arc<Vec<Channel>>,
+    next_channel: Arc<AtomicUsize>,
+    token_generator: Arc<GoogleAuthTokenGenerator>,
+    cloud_resource_prefix: String,
+    max_decoding_message_size: Option<usize>,
+    max_encoding_message_size: Option<usize>,
+}
+
+impl FirestoreApiClient {
+    pub(crate) async fn connect(
+        api_url: String,
+        cloud_resource_prefix: String,
+        token_scopes: Vec<String>,
+        token_source_type: TokenSourceType,
+        connection: &FirestoreDbConnectionOptions,
+    ) -> FirestoreResult<Self> {
+        if connection.channels == 0 {
+            return Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "channels".to_string(),
+                    "At least one gRPC channel is required".to_string(),
+                )),
+            ));
+        }
+
+        let endpoint = create_endpoint(api_url, connection)?;
+
+        let channels: Vec<Channel> =
+            futures::future::try_join_all((0..connection.channels).map(|_| endpoint.connect()))
+                .await
+                .map_err(gcloud_sdk::error::Error::from)?;
+
+        let token_generator =
+            Arc::new(GoogleAuthTokenGenerator::new(token_source_type, token_scopes).await?);
+
+        Ok(Self::with_channels(
+            channels,
+            token_generator,
+            cloud_resource_prefix,
+            connection,
+        ))
+    }
+
+    fn with_channels(
+        channels: Vec<Channel>,
+        token_generator: Arc<GoogleAuthTokenGenerator>,
+        cloud_resource_prefix: String,
+        connection: &FirestoreDbConnectionOptions,
+    ) -> Self {
+        Self {
+            channels: Arc::new(auth_channels(
+                &channels,
+                &token_generator,
+                &cloud_resource_prefix,
+            )),
+            connections: Arc::new(channels),
+            next_channel: Arc::new(AtomicUsize::new(0)),
+            token_generator,
+            cloud_resource_prefix,
+            max_decoding_message_size: connection.max_decoding_message_size,
+            max_encoding_message_size: connection.max_encoding_message_size,
+        }
+    }
+
+    /// Creates a client for another database sharing the connections and the token generator.
+    ///
+    /// Firestore routes the requests by the `google-cloud-resource-prefix` header,
+    /// so the channels are recreated with the prefix of the other database.
+    pub(crate) fn with_cloud_resource_prefix(&self, cloud_resource_prefix: String) -> Self {
+        Self {
+            channels: Arc::new(auth_channels(
+                &self.connections,
+                &self.token_generator,
+                &cloud_resource_prefix,
+            )),
+            connections: self.connections.clone(),
+            next_channel: Arc::new(AtomicUsize::new(0)),
+            token_generator: self.token_generator.clone(),
+            cloud_resource_prefix,
+            max_decoding_message_size: self.max_decoding_message_size,
+            max_encoding_message_size: self.max_encoding_message_size,
+        }
+    }
+
+    /// The value of the `google-cloud-resource-prefix` header sent with the requests.
+    pub fn cloud_resource_prefix(&self) -> &str {
+        &self.cloud_resource_prefix
+    }
+
+    /// Returns a Firestore gRPC client using the next channel from the pool.
+    pub fn get(&self) -> FirestoreClient<GoogleAuthMiddleware> {
+        let mut client = FirestoreClient::new(self.next_channel());
+        if let Some(max_decoding_message_size) = self.max_decoding_message_size {
+            client = client.max_decoding_message_size(max_decoding_message_size);
+        }
+        if let Some(max_encoding_message_size) = self.max_encoding_message_size {
+            client = client.max_encoding_message_size(max_encoding_message_size);
+        }
+        client
+    }
+
+    /// Returns the next authenticated channel from the pool, e.g. to call other Firestore APIs.
+    pub(crate) fn next_channel(&self) -> GoogleAuthMiddleware {
+        let index = self.next_channel.fetch_add(1, Ordering::Relaxed) % self.channels.len();
+        self.channels[index].clone()
+    }
+
+    /// Drops the cached access token, so the next request authenticates with a new one.
+    pub(crate) async fn clear_token_cache(&self) {
+        self.token_generator.clear_cache().await
+    }
+
+    /// The number of gRPC channels in the pool.
+    pub fn channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Creates a client for unit tests, connecting to the emulator only when requests are sent.
+    #[cfg(test)]
+    pub(crate) async fn connect_lazy_for_tests(cloud_resource_prefix: String) -> Self {
+        let connection = FirestoreDbConnectionOptions::new();
+        let endpoint = create_endpoint("http://localhost:8080".to_string(), &connection).unwrap();
+        let token_generator = Arc::new(
+            GoogleAuthTokenGenerator::new(crate::db::emulator_token_source(), vec![])
+                .await
+                .unwrap(),
+        );
+        Self::with_channels(
+            vec![endpoint.connect_lazy()],
+            token_generator,
+            cloud_resource_prefix,
+            &connection,
+        )
+    }
+}
+
+fn auth_channels(
+    connections: &[Channel],
+    token_generator: &Arc<GoogleAuthTokenGenerator>,
+    cloud_resource_prefix: &str,
+) -> Vec<GoogleAuthMiddleware> {
+    connections
+        .iter()
+        .map(|channel| {
+            GoogleAuthMiddleware::new(
+                channel.clone(),
+                token_generator.clone(),
+                Some(cloud_resource_prefix.to_string()),
+            )
+        })
+        .collect()
+}
+
+fn create_endpoint(
+    api_url: String,
+    connection: &FirestoreDbConnectionOptions,
+) -> FirestoreResult<Endpoint> {
+    let tls_domain_name = if api_url.contains("http://") {
+        None
+    } else {
+        Some(api_url.replace("https://", ""))
+    };
+
+    let endpoint = Endpoint::from_shared(api_url)
+        .map_err(gcloud_sdk::error::Error::from)?
+        .connect_timeout(connection.connect_timeout)
+        .tcp_keepalive(Some(connection.keepalive_interval))
+        .http2_keep_alive_interval(connection.keepalive_interval)
+        .keep_alive_timeout(connection.keepalive_timeout)
+        .keep_alive_while_idle(true);
+
+    let endpoint = match connection.request_timeout {
+        Some(request_timeout) => endpoint.timeout(request_timeout),
+        None => endpoint,
+    };
+
+    match tls_domain_name {
+        Some(domain_name) => Ok(endpoint
+            .tls_config(tls_config(domain_name))
+            .map_err(gcloud_sdk::error::Error::from)?),
+        None => Ok(endpoint),
+    }
+}
+
+#[cfg(feature = "tls-roots")]
+fn tls_config(domain_name: String) -> gcloud_sdk::tonic::transport::ClientTlsConfig {
+    gcloud_sdk::tonic::transport::ClientTlsConfig::new()
+        .with_native_roots()
+        .domain_name(domain_name)
+}
+
+#[cfg(all(feature = "tls-webpki-roots", not(feature = "tls-roots")))]
+fn tls_config(domain_name: String) -> gcloud_sdk::tonic::transport::ClientTlsConfig {
+    gcloud_sdk::tonic::transport::ClientTlsConfig::new()
+        .with_webpki_roots()
+        .domain_name(domain_name)
+}
+
+#[cfg(not(any(feature = "tls-roots", feature = "tls-webpki-roots")))]
+fn tls_config(domain_name: String) -> gcloud_sdk::tonic::transport::ClientTlsConfig {
+    gcloud_sdk::tonic::transport::ClientTlsConfig::new()
+        .ca_certificate(gcloud_sdk::tonic::transport::Certificate::from_pem(
+            gcloud_sdk::CERTIFICATES,
+        ))
+        .domain_name(domain_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn distributes_requests_across_channels() {
+        let connection = FirestoreDbConnectionOptions::new()
+            .with_channels(3)
+            .with_request_timeout(Duration::from_secs(5));
+
+        let endpoint = create_endpoint("http://localhost:8080".to_string(), &connection).unwrap();
+        assert_eq!(endpoint.uri().to_string(), "http://localhost:8080/");
+
+        let token_generator = Arc::new(
+            GoogleAuthTokenGenerator::new(crate::db::emulator_token_source(), vec![])
+                .await
+                .unwrap(),
+        );
+        let client = FirestoreApiClient::with_channels(
+            (0..connection.channels)
+                .map(|_| endpoint.connect_lazy())
+                .collect(),
+            token_generator,
+            "projects/test/databases/(default)".to_string(),
+            &connection,
+        );
+        assert_eq!(client.channels(), 3);
+
+        for _ in 0..4 {
+            client.get();
+        }
+        assert_eq!(
+            client.next_channel.load(Ordering::Relaxed) % client.channels(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_the_resource_prefix_of_another_database() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut connection = h2::server::handshake(socket).await.unwrap();
+            let (request, mut respond) = connection.accept().await.unwrap().unwrap();
+            respond
+                .send_response(http::Response::new(()), true)
+                .unwrap();
+            request
+                .headers()
+                .get("google-cloud-resource-prefix")
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        });
+
+        let connection = FirestoreDbConnectionOptions::new();
+        let endpoint = create_endpoint(format!("http://{address}"), &connection).unwrap();
+        let token_generator = Arc::new(
+            GoogleAuthTokenGenerator::new(crate::db::emulator_token_source(), vec![])
+                .await
+                .unwrap(),
+        );
+        let client = FirestoreApiClient::with_channels(
+            vec![endpoint.connect_lazy()],
+            token_generator,
+            "projects/test/databases/(default)".to_string(),
+            &connection,
+        )
+        .with_cloud_resource_prefix("projects/test/databases/other".to_string());
+
+        let _ = client
+            .get()
+            .get_document(gcloud_sdk::google::firestore::v1::GetDocumentRequest {
+                name: "projects/test/databases/other/documents/test/doc".to_string(),
+                ..Default::default()
+            })
+            .await;
+
+        assert_eq!(
+            server.await.unwrap().as_deref(),
+            Some("projects/test/databases/other")
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_empty_pool() {
+        let result = FirestoreApiClient::connect(
+            "http://localhost:8080".to_string(),
+            "projects/test/databases/(default)".to_string(),
+            vec![],
+            TokenSourceType::Default,
+            &FirestoreDbConnectionOptions::new().with_channels(0),
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(FirestoreError::InvalidParametersError(_))
+        ));
+    }
+}
@@ -0,0 +1,405 @@
+use crate::errors::*;
+use crate::{
+    firestore_draining_writer_scope, FirestoreApiResponse, FirestoreBatch, FirestoreBatchWriter,
+    FirestoreDb, FirestoreOperationKind, FirestoreResult, FirestoreShutdownSignal,
+    FirestoreWriteRampUpOptions, FirestoreWriteRampUpThrottler, FirestoreWriteResult,
+};
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+use gcloud_sdk::google::firestore::v1::{write, BatchWriteRequest, Write};
+use rand::Rng;
+use rsb_derive::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
+use tracing::*;
+
+/// The maximum number of writes Firestore accepts in a single `BatchWrite` request.
+pub const FIRESTORE_BULK_WRITER_MAX_BATCH_SIZE: usize = 500;
+
+/// Options for [`FirestoreBulkWriter`].
+#[derive(Debug, Eq, PartialEq, Clone, Builder)]
+pub struct FirestoreBulkWriterOptions {
+    /// Maximum number of writes sent in a single `BatchWrite` request (up to 500).
+    #[default = "20"]
+    pub max_batch_size: usize,
+    /// Maximum number of `BatchWrite` requests in flight at the same time.
+    #[default = "10"]
+    pub max_concurrent_batches: usize,
+    /// Maximum number of retries for a write failing with a retryable error
+    /// (e.g. `ABORTED` or `UNAVAILABLE`).
+    #[default = "5"]
+    pub max_retries: usize,
+    /// Delay before the first retry, doubled for every following attempt.
+    #[default = "Duration::from_millis(500)"]
+    pub initial_retry_delay: Duration,
+    /// Upper bound for the delay between retries.
+    #[default = "Duration::from_secs(30)"]
+    pub max_retry_delay: Duration,
+    /// Ramps up the write traffic gradually, including retries (see [`FirestoreWriteRampUpOptions`]).
+    /// Disabled by default.
+    pub ramp_up: Option<FirestoreWriteRampUpOptions>,
+}
+
+/// The outcome of an individual write sent through a [`FirestoreBulkWriter`].
+#[derive(Debug)]
+pub struct FirestoreBulkWriteResult {
+    /// The sequence number of the write, in the order writes were added to the writer.
+    pub position: u64,
+    /// The full path of the written document.
+    pub document_path: String,
+    /// The number of attempts made, including the successful or final failed one.
+    pub attempts: usize,
+    /// The write result, or the error of the last attempt.
+    pub result: FirestoreResult<FirestoreWriteResult>,
+}
+
+struct FirestoreBulkWriteOperation {
+    position: u64,
+    write: Write,
+    attempts: usize,
+}
+
+/// A writer for large volumes of independent writes, similar to the `BulkWriter`
+/// available in the other Firestore Admin SDKs.
+///
+/// Writes are grouped into non-atomic `BatchWrite` requests that are sent in parallel,
+/// and every write failing with a retryable error is retried individually with
+/// exponential backoff. The outcome of every write is reported in the results stream
+/// returned when the writer is created.
+pub struct FirestoreBulkWriter {
+    pub db: FirestoreDb,
+    pub options: FirestoreBulkWriterOptions,
+    pub batch_span: Span,
+    writer: UnboundedSender<FirestoreBulkWriteOperation>,
+    position_counter: AtomicU64,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FirestoreBulkWriter {
+    pub async fn new<'b>(
+        db: FirestoreDb,
+        options: FirestoreBulkWriterOptions,
+    ) -> FirestoreResult<(FirestoreBulkWriter, BoxStream<'b, FirestoreBulkWriteResult>)> {
+        if options.max_batch_size == 0
+            || options.max_batch_size > FIRESTORE_BULK_WRITER_MAX_BATCH_SIZE
+        {
+            return Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "max_batch_size".to_string(),
+                    format!(
+                        "Batch size must be between 1 and {FIRESTORE_BULK_WRITER_MAX_BATCH_SIZE}"
+                    ),
+                )),
+            ));
+        }
+
+        if options.max_concurrent_batches == 0 {
+            return Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "max_concurrent_batches".to_string(),
+                    "At least one concurrent batch is required".to_string(),
+                )),
+            ));
+        }
+
+        let batch_span = span!(
+            Level::DEBUG,
+            "Firestore Bulk Write",
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty
+        );
+
+        let (requests_writer, requests_receiver) =
+            mpsc::unbounded_channel::<FirestoreBulkWriteOperation>();
+        let (responses_writer, responses_receiver) =
+            mpsc::unbounded_channel::<FirestoreBulkWriteResult>();
+
+        let thread_db = db.clone();
+        let thread_options = options.clone();
+        let thread_span = batch_span.clone();
+
+        let thread = tokio::spawn(firestore_draining_writer_scope(async move {
+            let throttler = thread_options
+                .ramp_up
+                .clone()
+                .map(FirestoreWriteRampUpThrottler::new);
+            let shutdown_signal = thread_db.shutdown_signal();
+            // The shutdown of the client waits for the writes already added
+            let _drain_guard = shutdown_signal.drain_guard();
+            bulk_operations_until_shutdown(requests_receiver, shutdown_signal)
+                .ready_chunks(thread_options.max_batch_size)
+                .map(|operations| {
+                    write_bulk_operations(
+                        &thread_db,
+                        &thread_options,
+                        throttler.as_ref(),
+                        &thread_span,
+                        operations,
+                        &responses_writer,
+                    )
+                })
+                .buffer_unordered(thread_options.max_concurrent_batches)
+                .collect::<()>()
+                .await;
+        }));
+
+        let responses_stream =
+            tokio_stream::wrappers::UnboundedReceiverStream::new(responses_receiver).boxed();
+
+        Ok((
+            Self {
+                db,
+                options,
+                batch_span,
+                writer: requests_writer,
+                position_counter: AtomicU64::new(0),
+                thread: Some(thread),
+            },
+            responses_stream,
+        ))
+    }
+
+    /// Waits until all writes added to the writer are completed, including retries.
+    /// The results stream ends after this.
+    pub async fn finish(self) {
+        let Self { writer, thread, .. } = self;
+        drop(writer);
+
+        if let Some(thread) = thread {
+            let _ = tokio::join!(thread);
+        }
+    }
+
+    /// Adds writes to the writer. Every write is reported separately in the results stream.
+    pub fn add_writes<I>(&self, writes: I) -> FirestoreResult<()>
+    where
+        I: IntoIterator,
+        I::Item: Into<Write>,
+    {
+        for write in writes {
+            self.writer
+                .send(FirestoreBulkWriteOperation {
+                    position: self.position_counter.fetch_add(1, Ordering::Relaxed),
+                    write: write.into(),
+                    attempts: 0,
+                })
+                .map_err(|err| {
+                    FirestoreError::NetworkError(FirestoreNetworkError::new(
+                        FirestoreErrorPublicGenericDetails::new("SEND_STREAM_ERROR".into()),
+                        format!("Send stream error: {err}"),
+                    ))
+                })?;
+        }
+        Ok(())
+    }
+
+    pub fn new_batch(&self) -> FirestoreBatch<'_, FirestoreBulkWriter> {
+        FirestoreBatch::new(&self.db, self)
+    }
+}
+
+#[async_trait]
+impl FirestoreBatchWriter for FirestoreBulkWriter {
+    type WriteResult = ();
+
+    async fn write(&self, writes: Vec<Write>) -> FirestoreResult<()> {
+        self.add_writes(writes)
+    }
+}
+
+/// Receives the added writes until the shutdown of the client is requested,
+/// and the writes already added after that.
+fn bulk_operations_until_shutdown(
+    receiver: mpsc::UnboundedReceiver<FirestoreBulkWriteOperation>,
+    shutdown_signal: FirestoreShutdownSignal,
+) -> impl Stream<Item = FirestoreBulkWriteOperation> {
+    futures::stream::unfold(
+        (receiver, Some(shutdown_signal)),
+        |(mut receiver, shutdown_signal)| async move {
+            let operation = match shutdown_signal {
+                Some(ref shutdown_signal) => tokio::select! {
+                    operation = receiver.recv() => operation,
+                    _ = shutdown_signal.requested() => {
+                        debug!("Firestore client is shutting down. Sending the writes already added...");
+                        receiver.close();
+                        return receiver
+                            .recv()
+                            .await
+                            .map(|operation| (operation, (receiver, None)));
+                    }
+                },
+                None => receiver.recv().await,
+            };
+            operation.map(|operation| (operation, (receiver, shutdown_signal)))
+        },
+    )
+}
+
+fn write_document_path(write: &Write) -> String {
+    match &write.operation {
+        Some(write::Operation::Update(doc)) => doc.name.clone(),
+        Some(write::Operation::Delete(document_path)) => document_path.clone(),
+        Some(write::Operation::Transform(transform)) => transform.document.clone(),
+        None => "".to_string(),
+    }
+}
+
+fn bulk_retry_delay(options: &FirestoreBulkWriterOptions, attempts: usize) -> Duration {
+    let max_delay = options
+        .initial_retry_delay
+        .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1) as u32))
+        .min(options.max_retry_delay);
+    let max_delay_millis = max_delay.as_millis() as u64;
+    Duration::from_millis(rand::rng().random_range(max_delay_millis / 2..=max_delay_millis))
+}
+
+async fn write_bulk_operations(
+    db: &FirestoreDb,
+    options: &FirestoreBulkWriterOptions,
+    throttler: Option<&FirestoreWriteRampUpThrottler>,
+    span: &Span,
+    mut operations: Vec<FirestoreBulkWriteOperation>,
+    responses: &UnboundedSender<FirestoreBulkWriteResult>,
+) {
+    while !operations.is_empty() {
+        if let Some(throttler) = throttler {
+            throttler.acquire(operations.len()).await;
+        }
+
+        let request = BatchWriteRequest {
+            database: db.get_database_path().to_string(),
+            writes: operations.iter().map(|op| op.write.clone()).collect(),
+            labels: HashMap::new(),
+        };
+
+        let metrics = db.record_operation(FirestoreOperationKind::BatchWrite, None, span);
+        let results: Vec<FirestoreResult<FirestoreWriteResult>> = match db
+            .create_api_request(request)
+        {
+            Ok(request) => match db.client().get().batch_write(request).await {
+                Ok(response) => {
+                    let response = response.into_inner();
+                    db.notify_response_middlewares(FirestoreApiResponse::BatchWrite(&response));
+                    let mut write_results = response.write_results.into_iter();
+                    let mut statuses = response.status.into_iter();
+                    let results: Vec<FirestoreResult<FirestoreWriteResult>> = operations
+                        .iter()
+                        .map(|_| {
+                            let write_result = write_results.next();
+                            match statuses.next() {
+                                Some(status) if status.code != 0 => {
+                                    Err(FirestoreError::from(gcloud_sdk::tonic::Status::new(
+                                        gcloud_sdk::tonic::Code::from(status.code),
+                                        status.message,
+                                    )))
+                                }
+                                _ => write_result
+                                    .map(|write_result| write_result.try_into())
+                                    .unwrap_or_else(|| Ok(FirestoreWriteResult::new(vec![]))),
+                            }
+                        })
+                        .collect();
+                    metrics.record_success(results.iter().filter(|result| result.is_ok()).count());
+                    results
+                }
+                Err(status) => {
+                    metrics.record_failure(&FirestoreError::from(status.clone()));
+                    operations
+                        .iter()
+                        .map(|_| Err(FirestoreError::from(status.clone())))
+                        .collect()
+                }
+            },
+            Err(err) => {
+                metrics.record_failure(&err);
+                operations
+                    .iter()
+                    .map(|_| {
+                        Err(FirestoreError::SystemError(FirestoreSystemError::new(
+                            FirestoreErrorPublicGenericDetails::new("SystemError".into()),
+                            format!("Batch write request rejected: {err}"),
+                        )))
+                    })
+                    .collect()
+            }
+        };
+
+        let mut retry_operations = Vec::new();
+
+        for (mut operation, result) in operations.into_iter().zip(results) {
+            operation.attempts += 1;
+            match result {
+                Err(FirestoreError::DatabaseError(ref db_err))
+                    if db_err.retry_possible && operation.attempts <= options.max_retries =>
+                {
+                    retry_operations.push(operation);
+                }
+                result => {
+                    responses
+                        .send(FirestoreBulkWriteResult {
+                            position: operation.position,
+                            document_path: write_document_path(&operation.write),
+                            attempts: operation.attempts,
+                            result,
+                        })
+                        .ok();
+                }
+            }
+        }
+
+        if let Some(attempts) = retry_operations.iter().map(|op| op.attempts).max() {
+            let sleep_duration = bulk_retry_delay(options, attempts);
+            span.in_scope(|| {
+                warn!(
+                    writes = retry_operations.len(),
+                    current_retry = attempts,
+                    max_retries = options.max_retries,
+                    delay = sleep_duration.as_millis(),
+                    "Bulk writes failed with retryable errors. Retrying up to the specified number of times."
+                )
+            });
+            tokio::time::sleep(sleep_duration).await;
+        }
+
+        operations = retry_operations;
+    }
+}
+
+impl FirestoreDb {
+    pub async fn create_bulk_writer<'b>(
+        &self,
+    ) -> FirestoreResult<(FirestoreBulkWriter, BoxStream<'b, FirestoreBulkWriteResult>)> {
+        self.create_bulk_writer_with_options(FirestoreBulkWriterOptions::new())
+            .await
+    }
+
+    pub async fn create_bulk_writer_with_options<'b>(
+        &self,
+        options: FirestoreBulkWriterOptions,
+    ) -> FirestoreResult<(FirestoreBulkWriter, BoxStream<'b, FirestoreBulkWriteResult>)> {
+        FirestoreBulkWriter::new(self.clone(), options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bulk_retry_delay_is_bounded() {
+        let options = FirestoreBulkWriterOptions::new()
+            .with_initial_retry_delay(Duration::from_millis(100))
+            .with_max_retry_delay(Duration::from_millis(1000));
+
+        let first = bulk_retry_delay(&options, 1);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+
+        let capped = bulk_retry_delay(&options, 20);
+        assert!(capped >= Duration::from_millis(500) && capped <= Duration::from_millis(1000));
+    }
+}
@@ -0,0 +1,174 @@
+use crate::errors::*;
+use crate::timestamp_utils::from_timestamp;
+use crate::{
+    FirestoreApiResponse, FirestoreBatch, FirestoreBatchWriteResponse, FirestoreBatchWriter,
+    FirestoreDb, FirestoreOperationKind, FirestoreResult, FirestoreWriteResult,
+};
+use async_trait::async_trait;
+use gcloud_sdk::google::firestore::v1::{write, CommitRequest, Write};
+use tracing::*;
+
+/// The maximum number of writes in a batch committed atomically.
+pub const FIRESTORE_MAX_COMMIT_BATCH_SIZE: usize = 500;
+
+impl FirestoreDb {
+    /// Creates a batch of writes committed atomically with a single `Commit` request.
+    ///
+    /// Either all writes of the batch are applied or none of them, e.g. when a precondition fails.
+    /// Unlike transactions, batches don't read documents and don't lock them.
+    /// A batch can contain up to [`FIRESTORE_MAX_COMMIT_BATCH_SIZE`] writes.
+    ///
+    /// The write results in the response are in the order the writes were added to the batch.
+    pub fn batch(&self) -> FirestoreBatch<'_, FirestoreDb> {
+        FirestoreBatch::new(self, self)
+    }
+}
+
+#[cfg(any(feature = "caching", test))]
+fn write_document_path(write: &Write) -> Option<&str> {
+    match write.operation.as_ref()? {
+        write::Operation::Update(document) => Some(document.name.as_str()),
+        write::Operation::Delete(document_path) => Some(document_path.as_str()),
+        write::Operation::Transform(transform) => Some(transform.document.as_str()),
+    }
+}
+
+fn is_idempotent_write(write: &Write) -> bool {
+    write.current_document.is_none()
+        && write.update_transforms.is_empty()
+        && !matches!(write.operation, Some(write::Operation::Transform(_)))
+}
+
+/// Commits the writes of a [`FirestoreBatch`] created with [`FirestoreDb::batch`] atomically.
+#[async_trait]
+impl FirestoreBatchWriter for FirestoreDb {
+    type WriteResult = FirestoreBatchWriteResponse;
+
+    async fn write(&self, writes: Vec<Write>) -> FirestoreResult<FirestoreBatchWriteResponse> {
+        if writes.len() > FIRESTORE_MAX_COMMIT_BATCH_SIZE {
+            return Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "writes".to_string(),
+                    format!(
+                        "A batch can contain up to {FIRESTORE_MAX_COMMIT_BATCH_SIZE} writes, {} specified",
+                        writes.len()
+                    ),
+                )),
+            ));
+        }
+
+        let span = span!(
+            Level::DEBUG,
+            "Firestore Batch Commit",
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty,
+            "/firestore/commit_time" = field::Empty
+        );
+
+        let has_precondition = writes.iter().any(|write| write.current_document.is_some());
+        let idempotent = writes.iter().all(is_idempotent_write);
+        #[cfg(feature = "caching")]
+        let document_paths: Vec<String> = writes
+            .iter()
+            .filter_map(write_document_path)
+            .map(|document_path| document_path.to_string())
+            .collect();
+        let writes_count = writes.len();
+
+        let commit_request = CommitRequest {
+            database: self.get_database_path().clone(),
+            writes,
+            transaction: vec![],
+        };
+
+        let response = self
+            .execute_with_retries("Committing a batch", idempotent, || {
+                let commit_request = commit_request.clone();
+                let metrics = self.record_operation(FirestoreOperationKind::Commit, None, &span);
+                async move {
+                    let result = async {
+                        let response = self
+                            .client()
+                            .get()
+                            .commit(self.create_api_request(commit_request)?)
+                            .await
+                            .map_err(|status| {
+                                if has_precondition {
+                                    firestore_precondition_status_error(status)
+                                } else {
+                                    status.into()
+                                }
+                            })?;
+                        self.notify_response_middlewares(FirestoreApiResponse::Commit(
+                            response.get_ref(),
+                        ));
+                        Ok(response)
+                    }
+                    .await;
+                    metrics.record_result(&result, writes_count);
+                    result
+                }
+            })
+            .await?
+            .into_inner();
+
+        let result = FirestoreBatchWriteResponse::new(
+            0,
+            response
+                .write_results
+                .into_iter()
+                .map(|write_result| write_result.try_into())
+                .collect::<FirestoreResult<Vec<FirestoreWriteResult>>>()?,
+            vec![],
+        )
+        .opt_commit_time(response.commit_time.map(from_timestamp).transpose()?);
+
+        if let Some(ref commit_time) = result.commit_time {
+            span.record("/firestore/commit_time", commit_time.to_rfc3339());
+        }
+        span.in_scope(|| {
+            debug!(writes = writes_count, "Committed a batch of writes.");
+        });
+
+        #[cfg(feature = "caching")]
+        for document_path in &document_paths {
+            self.offer_doc_delete_to_cache(document_path).await;
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcloud_sdk::google::firestore::v1::{precondition, Document, Precondition};
+
+    #[test]
+    fn commit_write_properties() {
+        let update = Write {
+            operation: Some(write::Operation::Update(Document {
+                name: "docs/a".to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        assert_eq!(write_document_path(&update), Some("docs/a"));
+        assert!(is_idempotent_write(&update));
+
+        let create = Write {
+            current_document: Some(Precondition {
+                condition_type: Some(precondition::ConditionType::Exists(false)),
+            }),
+            ..update
+        };
+        assert!(!is_idempotent_write(&create));
+
+        let delete = Write {
+            operation: Some(write::Operation::Delete("docs/b".to_string())),
+            ..Default::default()
+        };
+        assert_eq!(write_document_path(&delete), Some("docs/b"));
+        assert!(is_idempotent_write(&delete));
+    }
+}
@@ -1,7 +1,7 @@
 use crate::errors::*;
 use crate::{
-    FirestoreBatch, FirestoreBatchWriteResponse, FirestoreBatchWriter, FirestoreDb,
-    FirestoreResult, FirestoreWriteResult,
+    FirestoreApiResponse, FirestoreBatch, FirestoreBatchWriteResponse, FirestoreBatchWriter,
+    FirestoreDb, FirestoreOperationKind, FirestoreResult, FirestoreWriteResult,
 };
 use async_trait::async_trait;
 use futures::TryFutureExt;
@@ -26,7 +26,12 @@ impl FirestoreSimpleBatchWriter {
         db: FirestoreDb,
         options: FirestoreSimpleBatchWriteOptions,
     ) -> FirestoreResult<FirestoreSimpleBatchWriter> {
-        let batch_span = span!(Level::DEBUG, "Firestore Batch Write");
+        let batch_span = span!(
+            Level::DEBUG,
+            "Firestore Batch Write",
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty
+        );
 
         Ok(Self {
             db,
@@ -62,21 +67,42 @@ impl FirestoreBatchWriter for FirestoreSimpleBatchWriter {
 
         backoff::future::retry(backoff, || {
             async {
-                let response = self
-                    .db
-                    .client()
-                    .get()
-                    .batch_write(request.clone())
-                    .await
-                    .map_err(FirestoreError::from)?;
+                let metrics = self.db.record_operation(
+                    FirestoreOperationKind::BatchWrite,
+                    None,
+                    &self.batch_span,
+                );
+                let response = match self.db.create_api_request(request.clone()) {
+                    Ok(request) => self
+                        .db
+                        .client()
+                        .get()
+                        .batch_write(request)
+                        .await
+                        .map_err(FirestoreError::from),
+                    Err(err) => Err(err),
+                };
+                let response = match response {
+                    Ok(response) => response,
+                    Err(err) => {
+                        metrics.record_failure(&err);
+                        return Err(err);
+                    }
+                };
 
                 let batch_response = response.into_inner();
+                self.db
+                    .notify_response_middlewares(FirestoreApiResponse::BatchWrite(&batch_response));
 
                 let write_results: FirestoreResult<Vec<FirestoreWriteResult>> = batch_response
                     .write_results
                     .into_iter()
                     .map(|s| s.try_into())
                     .collect();
+                metrics.record_result(
+                    &write_results,
+                    write_results.as_ref().map_or(0, |results| results.len()),
+                );
 
                 Ok(FirestoreBatchWriteResponse::new(
                     0,
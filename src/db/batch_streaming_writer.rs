@@ -1,15 +1,17 @@
 use crate::{
-    FirestoreBatch, FirestoreBatchWriteResponse, FirestoreBatchWriter, FirestoreDb,
-    FirestoreResult, FirestoreWriteResult,
+    FirestoreApiResponse, FirestoreBatch, FirestoreBatchWriteResponse, FirestoreBatchWriter,
+    FirestoreDb, FirestoreResult, FirestoreWriteRampUpOptions, FirestoreWriteRampUpThrottler,
+    FirestoreWriteResult,
 };
 use async_trait::async_trait;
+use futures::future::BoxFuture;
 use futures::stream::BoxStream;
-use futures::{StreamExt, TryStreamExt};
+use futures::{FutureExt, StreamExt, TryStreamExt};
 use gcloud_sdk::google::firestore::v1::{Write, WriteRequest};
 use rsb_derive::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::sync::{mpsc, RwLock};
@@ -22,6 +24,9 @@ use tracing::*;
 pub struct FirestoreStreamingBatchWriteOptions {
     #[default = "Duration::from_millis(500)"]
     pub throttle_batch_duration: Duration,
+    /// Ramps up the write traffic gradually (see [`FirestoreWriteRampUpOptions`]).
+    /// Disabled by default.
+    pub ramp_up: Option<FirestoreWriteRampUpOptions>,
 }
 
 pub struct FirestoreStreamingBatchWriter {
@@ -35,6 +40,33 @@ pub struct FirestoreStreamingBatchWriter {
     sent_counter: Arc<AtomicU64>,
     received_counter: Arc<AtomicU64>,
     init_wait_reader: UnboundedReceiver<()>,
+    throttler: Option<FirestoreWriteRampUpThrottler>,
+    write_labels: Arc<Mutex<FirestoreStreamingWriteLabels>>,
+}
+
+/// The labels of the sent batches by their positions, kept until the results of the batches are received.
+#[derive(Debug, Default)]
+struct FirestoreStreamingWriteLabels {
+    pending: BTreeMap<u64, Vec<Option<String>>>,
+}
+
+impl FirestoreStreamingWriteLabels {
+    fn add(&mut self, position: u64, labels: Vec<Option<String>>) {
+        if labels.iter().any(|label| label.is_some()) {
+            self.pending.insert(position, labels);
+        }
+    }
+
+    fn take(&mut self, position: u64) -> Vec<Option<String>> {
+        self.pending.remove(&position).unwrap_or_default()
+    }
+
+    fn unacknowledged(&self) -> Vec<String> {
+        self.pending
+            .values()
+            .flat_map(|labels| labels.iter().flatten().cloned())
+            .collect()
+    }
 }
 
 impl Drop for FirestoreStreamingBatchWriter {
@@ -73,7 +105,11 @@ impl FirestoreStreamingBatchWriter {
         let last_token: Arc<RwLock<Vec<u8>>> = Arc::new(RwLock::new(vec![]));
         let thread_last_token = last_token.clone();
 
+        let write_labels = Arc::new(Mutex::new(FirestoreStreamingWriteLabels::default()));
+        let thread_write_labels = write_labels.clone();
+
         let mut thread_db_client = db.client().get();
+        let thread_db = db.clone();
         let thread_options = options.clone();
 
         let thread = tokio::spawn(async move {
@@ -82,15 +118,36 @@ impl FirestoreStreamingBatchWriter {
                 tokio_stream::wrappers::UnboundedReceiverStream::new(requests_receiver)
                     .throttle(thread_options.throttle_batch_duration)
             };
+            let shutdown_signal = thread_db.shutdown_signal();
+            // The shutdown of the client waits for the results of the writes already sent
+            let _drain_guard = shutdown_signal.drain_guard();
             match thread_db_client.write(stream).await {
                 Ok(response) => {
                     let mut response_stream = response.into_inner().boxed();
+                    let mut draining = false;
                     loop {
-                        let response_result = response_stream.try_next().await;
+                        let response_result = tokio::select! {
+                            response_result = response_stream.try_next() => response_result,
+                            _ = shutdown_signal.requested(), if !draining => {
+                                debug!("Firestore client is shutting down. Waiting for the results of the writes already sent...");
+                                draining = true;
+                                let _locked = thread_last_token.read().await;
+                                thread_finished.store(true, Ordering::Relaxed);
+                                if thread_sent_counter.load(Ordering::Relaxed) + 1
+                                    == thread_received_counter.load(Ordering::Relaxed)
+                                {
+                                    break;
+                                }
+                                continue;
+                            }
+                        };
                         let received_counter = thread_received_counter.load(Ordering::Relaxed);
 
                         match response_result {
                             Ok(Some(response)) => {
+                                thread_db.notify_response_middlewares(FirestoreApiResponse::Write(
+                                    &response,
+                                ));
                                 {
                                     let mut locked = thread_last_token.write().await;
                                     *locked = response.stream_token;
@@ -108,6 +165,10 @@ impl FirestoreStreamingBatchWriter {
 
                                     match write_results {
                                         Ok(write_results) => {
+                                            let labels = thread_write_labels
+                                                .lock()
+                                                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                                                .take(received_counter - 1);
                                             responses_writer
                                                 .send(Ok(FirestoreBatchWriteResponse::new(
                                                     received_counter - 1,
@@ -118,7 +179,8 @@ impl FirestoreStreamingBatchWriter {
                                                     response
                                                         .commit_time
                                                         .and_then(|ts| from_timestamp(ts).ok()),
-                                                )))
+                                                )
+                                                .with_labels(labels)))
                                                 .ok();
                                         }
                                         Err(err) => {
@@ -207,6 +269,11 @@ impl FirestoreStreamingBatchWriter {
         let responses_stream =
             tokio_stream::wrappers::UnboundedReceiverStream::new(responses_receiver).boxed();
 
+        let throttler = options
+            .ramp_up
+            .clone()
+            .map(FirestoreWriteRampUpThrottler::new);
+
         Ok((
             Self {
                 db,
@@ -219,6 +286,8 @@ impl FirestoreStreamingBatchWriter {
                 sent_counter,
                 received_counter,
                 init_wait_reader,
+                throttler,
+                write_labels,
             },
             responses_stream,
         ))
@@ -261,23 +330,50 @@ impl FirestoreStreamingBatchWriter {
         }
     }
 
-    async fn write_iterator<I>(&self, writes: I) -> FirestoreResult<()>
+    /// The labels of the writes sent without results received yet, in the order of the writes.
+    ///
+    /// If the responses stream fails, these writes have to be considered failed.
+    pub fn unacknowledged_labels(&self) -> Vec<String> {
+        self.write_labels
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .unacknowledged()
+    }
+
+    async fn write_iterator<I>(&self, writes: I, labels: Vec<Option<String>>) -> FirestoreResult<()>
     where
         I: IntoIterator,
         I::Item: Into<Write>,
     {
-        self.sent_counter.fetch_add(1, Ordering::Relaxed);
+        let writes: Vec<Write> = writes.into_iter().map(|write| write.into()).collect();
+        if let Some(throttler) = &self.throttler {
+            throttler.acquire(writes.len()).await;
+        }
 
-        Ok(self.writer.send(WriteRequest {
+        // The stream token is set right before sending
+        let mut request = WriteRequest {
             database: self.db.get_database_path().to_string(),
             stream_id: "".to_string(),
-            writes: writes.into_iter().map(|write| write.into()).collect(),
-            stream_token: {
-                let locked = self.last_token.read().await;
-                locked.clone()
-            },
+            writes,
+            stream_token: vec![],
             labels: HashMap::new(),
-        })?)
+        };
+        self.db.apply_message_middlewares(&mut request)?;
+
+        request.stream_token = {
+            let locked = self.last_token.read().await;
+            locked.clone()
+        };
+
+        // The position is assigned and the request is queued under the same lock,
+        // so concurrent writes are sent in the order of their positions
+        let mut write_labels = self
+            .write_labels
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let position = self.sent_counter.fetch_add(1, Ordering::Relaxed);
+        write_labels.add(position, labels);
+        Ok(self.writer.send(request)?)
     }
 
     pub fn new_batch(&self) -> FirestoreBatch<FirestoreStreamingBatchWriter> {
@@ -290,7 +386,15 @@ impl FirestoreBatchWriter for FirestoreStreamingBatchWriter {
     type WriteResult = ();
 
     async fn write(&self, writes: Vec<Write>) -> FirestoreResult<()> {
-        self.write_iterator(writes).await
+        self.write_iterator(writes, vec![]).await
+    }
+
+    fn write_labeled(
+        &self,
+        writes: Vec<Write>,
+        labels: Vec<Option<String>>,
+    ) -> BoxFuture<'_, FirestoreResult<()>> {
+        self.write_iterator(writes, labels).boxed()
     }
 }
 
@@ -315,3 +419,27 @@ impl FirestoreDb {
         FirestoreStreamingBatchWriter::new(self.clone(), options).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_labels_until_results_received() {
+        let mut write_labels = FirestoreStreamingWriteLabels::default();
+        write_labels.add(0, vec![Some("msg-1".to_string()), None]);
+        write_labels.add(1, vec![None]);
+        write_labels.add(
+            2,
+            vec![Some("msg-2".to_string()), Some("msg-3".to_string())],
+        );
+
+        assert_eq!(
+            write_labels.unacknowledged(),
+            vec!["msg-1", "msg-2", "msg-3"]
+        );
+        assert_eq!(write_labels.take(0), vec![Some("msg-1".to_string()), None]);
+        assert!(write_labels.take(1).is_empty());
+        assert_eq!(write_labels.unacknowledged(), vec!["msg-2", "msg-3"]);
+    }
+}
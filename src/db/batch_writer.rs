@@ -1,22 +1,36 @@
 use crate::db::transaction_ops::{TransformObjectOperation, UpdateObjectOperation};
 use crate::db::DeleteOperation;
-use crate::errors::FirestoreError;
+use crate::errors::{
+    FirestoreError, FirestoreInvalidParametersError, FirestoreInvalidParametersPublicDetails,
+};
 use crate::{
     FirestoreDb, FirestoreFieldTransform, FirestoreResult, FirestoreWritePrecondition,
     FirestoreWriteResult,
 };
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
 use gcloud_sdk::google::firestore::v1::Write;
 use gcloud_sdk::google::rpc::Status;
 use rsb_derive::*;
 use serde::Serialize;
+use std::collections::BTreeMap;
 
 #[async_trait]
 pub trait FirestoreBatchWriter {
     type WriteResult;
 
     async fn write(&self, writes: Vec<Write>) -> FirestoreResult<Self::WriteResult>;
+
+    /// Writes the batch with the labels of the writes (see [`FirestoreBatch::label`]).
+    /// By default the labels are ignored.
+    fn write_labeled(
+        &self,
+        writes: Vec<Write>,
+        _labels: Vec<Option<String>>,
+    ) -> BoxFuture<'_, FirestoreResult<Self::WriteResult>> {
+        self.write(writes)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Builder)]
@@ -25,6 +39,10 @@ pub struct FirestoreBatchWriteResponse {
     pub write_results: Vec<FirestoreWriteResult>,
     pub statuses: Vec<Status>,
     pub commit_time: Option<DateTime<Utc>>,
+    /// The labels of the writes (see [`FirestoreBatch::label`]) in the order of `write_results`.
+    /// Empty if no writes of the batch are labeled.
+    #[default = "Vec::new()"]
+    pub labels: Vec<Option<String>>,
 }
 
 pub struct FirestoreBatch<'a, W>
@@ -34,6 +52,7 @@ where
     pub db: &'a FirestoreDb,
     pub writer: &'a W,
     pub writes: Vec<Write>,
+    labels: BTreeMap<usize, String>,
 }
 
 impl<'a, W> FirestoreBatch<'a, W>
@@ -45,6 +64,7 @@ where
             db,
             writer,
             writes: Vec::new(),
+            labels: BTreeMap::new(),
         }
     }
 
@@ -57,9 +77,90 @@ where
         Ok(self)
     }
 
+    /// Labels the last added write, e.g. with the ID of the message the write is made for.
+    ///
+    /// The streaming batch writer returns the labels with the results of the writes
+    /// (see [`FirestoreBatchWriteResponse::labels`]), other writers ignore them.
+    pub fn label<S>(&mut self, label: S) -> FirestoreResult<&mut Self>
+    where
+        S: Into<String>,
+    {
+        if self.writes.is_empty() {
+            return Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "label".to_string(),
+                    "No writes to label in the batch".to_string(),
+                )),
+            ));
+        }
+        self.labels.insert(self.writes.len() - 1, label.into());
+        Ok(self)
+    }
+
     #[inline]
-    pub async fn write(self) -> FirestoreResult<W::WriteResult> {
-        self.writer.write(self.writes).await
+    pub async fn write(mut self) -> FirestoreResult<W::WriteResult> {
+        if self.labels.is_empty() {
+            self.writer.write(self.writes).await
+        } else {
+            let labels = (0..self.writes.len())
+                .map(|index| self.labels.remove(&index))
+                .collect();
+            self.writer.write_labeled(self.writes, labels).await
+        }
+    }
+
+    /// Writes the batch, an alias of [`FirestoreBatch::write`] for the chained updates:
+    ///
+    /// ```rust,ignore
+    /// let mut batch = db.batch();
+    /// batch
+    ///     .update("users", "u1", &user1, paths!(User::{name}))?
+    ///     .update("users", "u2", &user2, paths!(User::{email, updated_at}))?;
+    /// batch.commit().await?;
+    /// ```
+    #[inline]
+    pub async fn commit(self) -> FirestoreResult<W::WriteResult> {
+        self.write().await
+    }
+
+    /// Adds a write updating only the specified fields of the document,
+    /// so each document of the batch can have its own update mask.
+    pub fn update<T, S>(
+        &mut self,
+        collection_id: &str,
+        document_id: S,
+        obj: &T,
+        update_only: Vec<String>,
+    ) -> FirestoreResult<&mut Self>
+    where
+        T: Serialize + Sync + Send,
+        S: AsRef<str>,
+    {
+        self.update_with_transforms(collection_id, document_id, obj, update_only, vec![])
+    }
+
+    /// Adds a write updating only the specified fields of the document
+    /// and applying the transforms to it after the update.
+    pub fn update_with_transforms<T, S>(
+        &mut self,
+        collection_id: &str,
+        document_id: S,
+        obj: &T,
+        update_only: Vec<String>,
+        update_transforms: Vec<FirestoreFieldTransform>,
+    ) -> FirestoreResult<&mut Self>
+    where
+        T: Serialize + Sync + Send,
+        S: AsRef<str>,
+    {
+        self.update_object(
+            collection_id,
+            document_id,
+            obj,
+            Some(update_only),
+            None,
+            update_transforms,
+        )
     }
 
     pub fn update_object<T, S>(
@@ -111,6 +212,49 @@ where
         })
     }
 
+    /// Adds a write creating the document, failing the batch if it already exists.
+    pub fn create_object<T, S>(
+        &mut self,
+        collection_id: &str,
+        document_id: S,
+        obj: &T,
+    ) -> FirestoreResult<&mut Self>
+    where
+        T: Serialize + Sync + Send,
+        S: AsRef<str>,
+    {
+        self.create_object_at(
+            self.db.get_documents_path(),
+            collection_id,
+            document_id,
+            obj,
+        )
+    }
+
+    /// Adds a write creating the document under the specified parent,
+    /// failing the batch if it already exists.
+    pub fn create_object_at<T, S>(
+        &mut self,
+        parent: &str,
+        collection_id: &str,
+        document_id: S,
+        obj: &T,
+    ) -> FirestoreResult<&mut Self>
+    where
+        T: Serialize + Sync + Send,
+        S: AsRef<str>,
+    {
+        self.update_object_at(
+            parent,
+            collection_id,
+            document_id,
+            obj,
+            None,
+            Some(FirestoreWritePrecondition::Exists(false)),
+            vec![],
+        )
+    }
+
     pub fn delete_by_id<S>(
         &mut self,
         collection_id: &str,
@@ -185,3 +329,105 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FirestoreFieldTransformType, FirestoreWritePrecondition};
+    use gcloud_sdk::google::firestore::v1::{document_transform, write, DocumentMask};
+    use serde::Serialize;
+
+    struct TestBatchWriter;
+
+    #[async_trait]
+    impl FirestoreBatchWriter for TestBatchWriter {
+        type WriteResult = Vec<Write>;
+
+        async fn write(&self, writes: Vec<Write>) -> FirestoreResult<Vec<Write>> {
+            Ok(writes)
+        }
+    }
+
+    #[derive(Serialize)]
+    struct TestStock {
+        reserved: u32,
+        price: f64,
+    }
+
+    #[tokio::test]
+    async fn chained_updates_with_own_update_masks() -> FirestoreResult<()> {
+        let db = FirestoreDb::for_unit_tests().await;
+        let stock = TestStock {
+            reserved: 1,
+            price: 2.0,
+        };
+
+        let mut batch = FirestoreBatch::new(&db, &TestBatchWriter);
+        batch
+            .update("stock", "item-1", &stock, vec!["reserved".to_string()])?
+            .update_with_transforms(
+                "stock",
+                "item-2",
+                &stock,
+                vec!["reserved".to_string(), "price".to_string()],
+                vec![FirestoreFieldTransform::new(
+                    "orders".to_string(),
+                    FirestoreFieldTransformType::Increment(1.into()),
+                )],
+            )?;
+        let writes = batch.commit().await?;
+
+        assert_eq!(writes.len(), 2);
+        let document_name = |write: &Write| match &write.operation {
+            Some(write::Operation::Update(doc)) => doc.name.clone(),
+            _ => String::new(),
+        };
+        assert_eq!(
+            document_name(&writes[0]),
+            format!("{}/stock/item-1", db.get_documents_path())
+        );
+        assert_eq!(
+            writes[0].update_mask,
+            Some(DocumentMask {
+                field_paths: vec!["reserved".to_string()]
+            })
+        );
+        assert!(writes[0].update_transforms.is_empty());
+        assert_eq!(
+            document_name(&writes[1]),
+            format!("{}/stock/item-2", db.get_documents_path())
+        );
+        assert_eq!(
+            writes[1].update_mask,
+            Some(DocumentMask {
+                field_paths: vec!["reserved".to_string(), "price".to_string()]
+            })
+        );
+        assert_eq!(writes[1].update_transforms.len(), 1);
+        assert!(matches!(
+            writes[1].update_transforms[0].transform_type,
+            Some(document_transform::field_transform::TransformType::Increment(_))
+        ));
+        assert_eq!(writes[1].current_document, None);
+
+        let mut batch = FirestoreBatch::new(&db, &TestBatchWriter);
+        batch.create_object("stock", "item-3", &stock)?;
+        let writes = batch.commit().await?;
+        assert_eq!(
+            writes[0].current_document,
+            Some(FirestoreWritePrecondition::Exists(false).try_into()?)
+        );
+
+        Ok(())
+    }
+    #[tokio::test]
+    async fn update_returns_serialization_errors() {
+        let db = FirestoreDb::for_unit_tests().await;
+        let mut batch = FirestoreBatch::new(&db, &TestBatchWriter);
+
+        // Only structs and maps can be written as documents
+        let result = batch.update("stock", "item-1", &42, vec!["reserved".to_string()]);
+        assert!(result.is_err());
+        assert!(batch.writes.is_empty());
+    }
+}
@@ -0,0 +1,386 @@
+use crate::db::safe_document_path;
+use crate::errors::*;
+use crate::{
+    FirestoreDb, FirestoreListenEvent, FirestoreListener, FirestoreListenerTarget,
+    FirestoreListenerTargetParams, FirestoreMemListenStateStorage, FirestoreQueryCollection,
+    FirestoreQueryParams, FirestoreResult, FirestoreTargetType, FirestoreTypedChange,
+    FirestoreTypedChangeType, FirestoreTypedChangesTracker,
+};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use gcloud_sdk::google::firestore::v1::target_change;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::{broadcast, watch};
+use tracing::*;
+
+/// The default capacity of the change notifications channel of a [`FirestoreCollectionMirror`].
+pub const FIRESTORE_COLLECTION_MIRROR_CHANGES_CAPACITY: usize = 1024;
+
+struct FirestoreCollectionMirrorState<T> {
+    documents: RwLock<BTreeMap<String, T>>,
+    tracker: Mutex<FirestoreTypedChangesTracker>,
+    changes: broadcast::Sender<FirestoreTypedChange<T>>,
+    synced: watch::Sender<bool>,
+}
+
+impl<T> FirestoreCollectionMirrorState<T>
+where
+    for<'de> T: Deserialize<'de>,
+    T: Clone,
+{
+    fn apply_event(&self, event: FirestoreListenEvent) -> FirestoreResult<()> {
+        let is_current = matches!(
+            &event,
+            FirestoreListenEvent::TargetChange(target_change)
+                if target_change.target_change_type
+                    == target_change::TargetChangeType::Current as i32
+        );
+
+        let changes = self
+            .tracker
+            .lock()
+            .map_err(|err| mirror_lock_error(&err))?
+            .process_event::<T>(event);
+
+        for change in changes {
+            match change {
+                Ok(change) => {
+                    self.apply_change(&change)?;
+                    // No subscribers is not an error
+                    self.changes.send(change).ok();
+                }
+                Err(err) => warn!(%err, "Unable to apply a change to the collection mirror."),
+            }
+        }
+
+        if is_current {
+            self.synced.send_replace(true);
+        }
+        Ok(())
+    }
+
+    fn apply_change(&self, change: &FirestoreTypedChange<T>) -> FirestoreResult<()> {
+        let mut documents = self
+            .documents
+            .write()
+            .map_err(|err| mirror_lock_error(&err))?;
+        match (&change.change_type, &change.object) {
+            (FirestoreTypedChangeType::Removed, _) | (_, None) => {
+                documents.remove(&change.document_path);
+            }
+            (_, Some(object)) => {
+                documents.insert(change.document_path.clone(), object.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn mirror_lock_error<E: std::fmt::Display>(err: &E) -> FirestoreError {
+    FirestoreError::SystemError(FirestoreSystemError::new(
+        FirestoreErrorPublicGenericDetails::new("SystemError".into()),
+        format!("Collection mirror lock error: {err}"),
+    ))
+}
+
+/// A typed in-memory materialized view of the documents matching a query,
+/// kept up to date with a Firestore listener.
+///
+/// This is useful for small and frequently read collections, like configurations:
+/// reads are served from memory and never reach Firestore.
+/// The mirror resumes listening after connection errors without losing its state.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use firestore::*;
+/// use serde::Deserialize;
+///
+/// #[derive(Debug, Clone, Deserialize)]
+/// struct FeatureFlag {
+///     enabled: bool,
+/// }
+///
+/// # async fn run(db: &FirestoreDb) -> FirestoreResult<()> {
+/// let mut mirror: FirestoreCollectionMirror<FeatureFlag> = FirestoreCollectionMirror::start(
+///     db,
+///     FirestoreListenerTarget::new(42),
+///     FirestoreQueryParams::new("feature-flags".into()),
+/// )
+/// .await?;
+/// mirror.wait_synced().await;
+///
+/// let enabled = mirror.get("new-ui").map(|flag| flag.enabled).unwrap_or(false);
+///
+/// mirror.shutdown().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct FirestoreCollectionMirror<T> {
+    db: FirestoreDb,
+    params: FirestoreQueryParams,
+    state: Arc<FirestoreCollectionMirrorState<T>>,
+    synced: watch::Receiver<bool>,
+    listener: FirestoreListener<FirestoreDb, FirestoreMemListenStateStorage>,
+}
+
+impl<T> FirestoreCollectionMirror<T>
+where
+    for<'de> T: Deserialize<'de>,
+    T: Clone + Send + Sync + 'static,
+{
+    /// Starts mirroring the documents matching the query using the specified listener target.
+    ///
+    /// The target ID must be unique among the listeners of the application.
+    pub async fn start(
+        db: &FirestoreDb,
+        target: FirestoreListenerTarget,
+        params: FirestoreQueryParams,
+    ) -> FirestoreResult<Self> {
+        Self::start_with_capacity(
+            db,
+            target,
+            params,
+            FIRESTORE_COLLECTION_MIRROR_CHANGES_CAPACITY,
+        )
+        .await
+    }
+
+    /// Starts mirroring with the specified capacity of the change notifications channel.
+    /// Subscribers lagging behind by more than the capacity miss the oldest changes.
+    pub async fn start_with_capacity(
+        db: &FirestoreDb,
+        target: FirestoreListenerTarget,
+        params: FirestoreQueryParams,
+        changes_capacity: usize,
+    ) -> FirestoreResult<Self> {
+        let (changes, _) = broadcast::channel(changes_capacity);
+        let (synced_sender, synced) = watch::channel(false);
+        let state = Arc::new(FirestoreCollectionMirrorState {
+            documents: RwLock::new(BTreeMap::new()),
+            tracker: Mutex::new(FirestoreTypedChangesTracker::new()),
+            changes,
+            synced: synced_sender,
+        });
+
+        let mut listener = db
+            .create_listener(FirestoreMemListenStateStorage::new())
+            .await?;
+        listener.add_target(FirestoreListenerTargetParams::new(
+            target,
+            FirestoreTargetType::Query(params.clone()),
+            Default::default(),
+        ))?;
+
+        let listener_state = state.clone();
+        listener.enable_resume_token_events();
+        listener
+            .start(move |event| {
+                let state = listener_state.clone();
+                async move { state.apply_event(event).map_err(|err| err.into()) }
+            })
+            .await?;
+
+        Ok(Self {
+            db: db.clone(),
+            params,
+            state,
+            synced,
+            listener,
+        })
+    }
+
+    /// Waits until the mirror has received all the documents matching the query at least once.
+    pub async fn wait_synced(&mut self) {
+        // The sender lives as long as the mirror, so this can't fail
+        self.synced.wait_for(|synced| *synced).await.ok();
+    }
+
+    /// `true` if the mirror has received all the documents matching the query at least once.
+    pub fn is_synced(&self) -> bool {
+        *self.synced.borrow()
+    }
+
+    /// Returns the document with the specified ID.
+    ///
+    /// Only supported for queries of a single collection, use [`get_by_path`](Self::get_by_path)
+    /// for collection group queries.
+    pub fn get<S>(&self, document_id: S) -> Option<T>
+    where
+        S: AsRef<str>,
+    {
+        match &self.params.collection_id {
+            FirestoreQueryCollection::Single(collection_id) => safe_document_path(
+                self.params
+                    .parent
+                    .as_deref()
+                    .unwrap_or_else(|| self.db.get_documents_path()),
+                collection_id,
+                document_id.as_ref(),
+            )
+            .ok()
+            .and_then(|document_path| self.get_by_path(document_path)),
+            FirestoreQueryCollection::Group(_) => None,
+        }
+    }
+
+    /// Returns the document with the specified full path.
+    pub fn get_by_path<S>(&self, document_path: S) -> Option<T>
+    where
+        S: AsRef<str>,
+    {
+        self.state
+            .documents
+            .read()
+            .ok()
+            .and_then(|documents| documents.get(document_path.as_ref()).cloned())
+    }
+
+    /// Returns the documents matching the predicate, ordered by their paths.
+    pub fn query<FN>(&self, predicate: FN) -> Vec<T>
+    where
+        FN: Fn(&T) -> bool,
+    {
+        self.state
+            .documents
+            .read()
+            .map(|documents| {
+                documents
+                    .values()
+                    .filter(|object| predicate(object))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns all the documents with their full paths, ordered by their paths.
+    pub fn documents(&self) -> Vec<(String, T)> {
+        self.state
+            .documents
+            .read()
+            .map(|documents| {
+                documents
+                    .iter()
+                    .map(|(path, object)| (path.clone(), object.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The number of documents in the mirror.
+    pub fn len(&self) -> usize {
+        self.state
+            .documents
+            .read()
+            .map(|documents| documents.len())
+            .unwrap_or_default()
+    }
+
+    /// `true` if the mirror has no documents.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Subscribes to the changes applied to the mirror after this call.
+    ///
+    /// A subscriber lagging behind by more than the channel capacity skips the oldest changes.
+    pub fn changes(&self) -> BoxStream<'static, FirestoreTypedChange<T>> {
+        futures::stream::unfold(self.state.changes.subscribe(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(change) => return Some((change, receiver)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "Collection mirror subscriber lagged behind.");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed()
+    }
+
+    /// Stops listening the changes. Change streams end after this call.
+    pub async fn shutdown(mut self) -> FirestoreResult<()> {
+        self.listener.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcloud_sdk::google::firestore::v1::{DocumentChange, DocumentDelete, TargetChange};
+    use serde::Serialize;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct TestStructure {
+        some_num: u64,
+    }
+
+    fn doc_change(name: &str, some_num: u64, seconds: i64) -> FirestoreListenEvent {
+        let mut doc = FirestoreDb::serialize_to_doc(name, &TestStructure { some_num }).unwrap();
+        doc.update_time = Some(gcloud_sdk::prost_types::Timestamp { seconds, nanos: 0 });
+        FirestoreListenEvent::DocumentChange(DocumentChange {
+            document: Some(doc),
+            target_ids: vec![1],
+            removed_target_ids: vec![],
+        })
+    }
+
+    #[test]
+    fn mirror_state_applies_events() {
+        let (changes, mut receiver) = broadcast::channel(16);
+        let (synced, synced_receiver) = watch::channel(false);
+        let state = FirestoreCollectionMirrorState::<TestStructure> {
+            documents: RwLock::new(BTreeMap::new()),
+            tracker: Mutex::new(FirestoreTypedChangesTracker::new()),
+            changes,
+            synced,
+        };
+
+        state.apply_event(doc_change("docs/a", 1, 1)).unwrap();
+        state.apply_event(doc_change("docs/b", 2, 1)).unwrap();
+        assert!(!*synced_receiver.borrow());
+
+        state
+            .apply_event(FirestoreListenEvent::TargetChange(TargetChange {
+                target_change_type: target_change::TargetChangeType::Current.into(),
+                target_ids: vec![1],
+                ..Default::default()
+            }))
+            .unwrap();
+        assert!(*synced_receiver.borrow());
+
+        state.apply_event(doc_change("docs/a", 3, 2)).unwrap();
+        state
+            .apply_event(FirestoreListenEvent::DocumentDelete(DocumentDelete {
+                document: "docs/b".to_string(),
+                removed_target_ids: vec![1],
+                read_time: None,
+            }))
+            .unwrap();
+
+        let documents = state.documents.read().unwrap();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(
+            documents.get("docs/a"),
+            Some(&TestStructure { some_num: 3 })
+        );
+
+        let change_types: Vec<FirestoreTypedChangeType> =
+            std::iter::from_fn(|| receiver.try_recv().ok())
+                .map(|change| change.change_type)
+                .collect();
+        assert_eq!(
+            change_types,
+            vec![
+                FirestoreTypedChangeType::Added,
+                FirestoreTypedChangeType::Added,
+                FirestoreTypedChangeType::Modified,
+                FirestoreTypedChangeType::Removed,
+            ]
+        );
+    }
+}
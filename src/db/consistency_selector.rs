@@ -1,7 +1,17 @@
 use crate::errors::*;
 use crate::timestamp_utils::to_timestamp;
-use crate::{FirestoreError, FirestoreTransactionId};
+use crate::{FirestoreDb, FirestoreError, FirestoreResult, FirestoreTransactionId};
 use chrono::prelude::*;
+use futures::Future;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+tokio::task_local! {
+    static FIRESTORE_CONSISTENCY_SELECTOR_OVERRIDE: FirestoreConsistencySelector;
+}
+
+/// The clock skew allowed between the client and Firestore for read times in the future.
+const FIRESTORE_READ_TIME_CLOCK_SKEW: chrono::Duration = chrono::Duration::seconds(5);
 
 /// Specifies the consistency guarantee for Firestore read operations.
 ///
@@ -11,7 +21,7 @@ use chrono::prelude::*;
 ///
 /// See Google Cloud documentation for more details on Firestore consistency:
 /// [Data consistency](https://cloud.google.com/firestore/docs/concepts/transaction-options#data_consistency)
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub enum FirestoreConsistencySelector {
     /// Reads documents within an existing transaction.
     ///
@@ -32,6 +42,113 @@ pub enum FirestoreConsistencySelector {
     ReadTime(DateTime<Utc>),
 }
 
+impl FirestoreConsistencySelector {
+    /// Returns the selector overridden for the current fluent operation, if any.
+    pub(crate) fn current_override() -> Option<Self> {
+        FIRESTORE_CONSISTENCY_SELECTOR_OVERRIDE
+            .try_with(|selector| selector.clone())
+            .ok()
+    }
+
+    /// Runs the future with the selector overriding the one of the session.
+    pub(crate) async fn scope<F>(selector: Option<Self>, future: F) -> F::Output
+    where
+        F: Future,
+    {
+        match selector {
+            Some(selector) => {
+                FIRESTORE_CONSISTENCY_SELECTOR_OVERRIDE
+                    .scope(selector, future)
+                    .await
+            }
+            None => future.await,
+        }
+    }
+
+    /// Checks that a read time can be used for a point-in-time read now.
+    ///
+    /// Read times must not be in the future, allowing a few seconds of clock skew,
+    /// or older than `window`, and read times older than 1 hour must be whole minutes.
+    pub fn validate_read_time(
+        read_time: DateTime<Utc>,
+        window: Duration,
+        now: DateTime<Utc>,
+    ) -> FirestoreResult<()> {
+        let invalid_read_time = |message: String| {
+            Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "read_time".to_string(),
+                    message,
+                )),
+            ))
+        };
+
+        let age = now.signed_duration_since(read_time);
+        if age < -FIRESTORE_READ_TIME_CLOCK_SKEW {
+            return invalid_read_time(format!("Read time {read_time} is in the future"));
+        }
+
+        let window = chrono::Duration::from_std(window)?;
+        if age > window {
+            return invalid_read_time(format!(
+                "Read time {read_time} is older than the point-in-time read window of {} minutes",
+                window.num_minutes()
+            ));
+        }
+
+        if age > chrono::Duration::hours(1)
+            && (read_time.second() != 0 || read_time.nanosecond() != 0)
+        {
+            return invalid_read_time(format!(
+                "Read time {read_time} is older than 1 hour and must be a whole minute"
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl FirestoreDb {
+    /// Returns the consistency selector for a read: the selector of the request,
+    /// the one overridden for a fluent operation or the one of the session.
+    /// Read times of requests (e.g. set with `read_at()`) are validated against
+    /// [`FirestoreDbOptions::point_in_time_read_window`](crate::FirestoreDbOptions::point_in_time_read_window),
+    /// while the selector of the session is passed to Firestore as is.
+    pub(crate) fn read_consistency_selector(
+        &self,
+        request_selector: Option<&FirestoreConsistencySelector>,
+    ) -> FirestoreResult<Option<FirestoreConsistencySelector>> {
+        let request_selector = request_selector
+            .cloned()
+            .or_else(FirestoreConsistencySelector::current_override);
+
+        match request_selector {
+            Some(FirestoreConsistencySelector::ReadTime(read_time)) => {
+                FirestoreConsistencySelector::validate_read_time(
+                    read_time,
+                    self.get_options().point_in_time_read_window,
+                    Utc::now(),
+                )?;
+                Ok(Some(FirestoreConsistencySelector::ReadTime(read_time)))
+            }
+            Some(selector) => Ok(Some(selector)),
+            None => Ok(self.session_params.consistency_selector.clone()),
+        }
+    }
+
+    /// Returns `true` if a read has to be done at a point in time, so it can't be served by a cache.
+    #[cfg(feature = "caching")]
+    pub(crate) fn reads_at_point_in_time(
+        &self,
+        request_selector: Option<&FirestoreConsistencySelector>,
+    ) -> bool {
+        matches!(
+            self.read_consistency_selector(request_selector),
+            Ok(Some(FirestoreConsistencySelector::ReadTime(_))) | Err(_)
+        )
+    }
+}
+
 impl TryFrom<&FirestoreConsistencySelector>
     for gcloud_sdk::google::firestore::v1::get_document_request::ConsistencySelector
 {
@@ -141,3 +258,51 @@ impl TryFrom<&FirestoreConsistencySelector>
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_read_times() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 30).unwrap();
+        let window = Duration::from_secs(7 * 24 * 60 * 60);
+
+        assert!(FirestoreConsistencySelector::validate_read_time(
+            now - chrono::Duration::minutes(10),
+            window,
+            now
+        )
+        .is_ok());
+        assert!(FirestoreConsistencySelector::validate_read_time(
+            Utc.with_ymd_and_hms(2024, 4, 28, 8, 15, 0).unwrap(),
+            window,
+            now
+        )
+        .is_ok());
+        assert!(FirestoreConsistencySelector::validate_read_time(
+            now + chrono::Duration::seconds(1),
+            window,
+            now
+        )
+        .is_ok());
+
+        for read_time in [
+            now + chrono::Duration::minutes(1),
+            now - chrono::Duration::days(8),
+            Utc.with_ymd_and_hms(2024, 4, 28, 8, 15, 10).unwrap(),
+        ] {
+            assert!(matches!(
+                FirestoreConsistencySelector::validate_read_time(read_time, window, now),
+                Err(FirestoreError::InvalidParametersError(_))
+            ));
+        }
+
+        assert!(FirestoreConsistencySelector::validate_read_time(
+            now - chrono::Duration::hours(2),
+            Duration::from_secs(60 * 60),
+            now
+        )
+        .is_err());
+    }
+}
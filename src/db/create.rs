@@ -1,10 +1,46 @@
-use crate::{FirestoreDb, FirestoreResult};
+use crate::{FirestoreApiResponse, FirestoreDb, FirestoreOperationKind, FirestoreResult};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use gcloud_sdk::google::firestore::v1::*;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use tracing::*;
 
+const FIRESTORE_DOCUMENT_ID_ALPHABET: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+/// The length of the document IDs generated by [`FirestoreDb::generate_document_id`].
+pub const FIRESTORE_GENERATED_DOCUMENT_ID_LENGTH: usize = 20;
+
+/// A created document with its ID, returned by `execute_with_id()` of the insert builder.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FirestoreCreated<T> {
+    /// The ID of the created document, including generated ones.
+    pub document_id: String,
+    /// The full path of the created document.
+    pub document_path: String,
+    /// The created document deserialized as the requested type.
+    pub object: T,
+}
+
+impl FirestoreDb {
+    /// Generates a random document ID on the client side, in the same format as Firestore:
+    /// 20 alphanumeric characters.
+    ///
+    /// This is useful to know the ID of a document before creating it,
+    /// e.g. to reference it from other documents written in the same batch.
+    pub fn generate_document_id() -> String {
+        let mut rng = rand::rng();
+        (0..FIRESTORE_GENERATED_DOCUMENT_ID_LENGTH)
+            .map(|_| {
+                FIRESTORE_DOCUMENT_ID_ALPHABET
+                    [rng.random_range(0..FIRESTORE_DOCUMENT_ID_ALPHABET.len())]
+                    as char
+            })
+            .collect()
+    }
+}
+
 #[async_trait]
 pub trait FirestoreCreateSupport {
     async fn create_doc<S>(
@@ -93,9 +129,11 @@ impl FirestoreCreateSupport for FirestoreDb {
             "/firestore/collection_name" = collection_id,
             "/firestore/response_time" = field::Empty,
             "/firestore/document_name" = field::Empty,
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty,
         );
 
-        let create_document_request = gcloud_sdk::tonic::Request::new(CreateDocumentRequest {
+        let create_document_request = CreateDocumentRequest {
             parent: parent.into(),
             document_id: document_id
                 .as_ref()
@@ -106,14 +144,35 @@ impl FirestoreCreateSupport for FirestoreDb {
             }),
             collection_id: collection_id.into(),
             document: Some(input_doc),
-        });
+        };
 
         let begin_query_utc: DateTime<Utc> = Utc::now();
 
         let create_response = self
-            .client()
-            .get()
-            .create_document(create_document_request)
+            .execute_with_retries("Creating a document", false, || {
+                let create_document_request = create_document_request.clone();
+                let metrics = self.record_operation(
+                    FirestoreOperationKind::Create,
+                    Some(collection_id),
+                    &span,
+                );
+                async move {
+                    let result = async {
+                        let response = self
+                            .client()
+                            .get()
+                            .create_document(self.create_api_request(create_document_request)?)
+                            .await?;
+                        self.notify_response_middlewares(FirestoreApiResponse::CreateDocument(
+                            response.get_ref(),
+                        ));
+                        Ok(response)
+                    }
+                    .await;
+                    metrics.record_result(&result, 1);
+                    result
+                }
+            })
             .await?;
 
         let end_query_utc: DateTime<Utc> = Utc::now();
@@ -136,6 +195,10 @@ impl FirestoreCreateSupport for FirestoreDb {
             );
         });
 
+        #[cfg(feature = "caching")]
+        self.offer_doc_write_to_cache(&response_inner, return_only_fields.is_none())
+            .await;
+
         Ok(response_inner)
     }
 
@@ -174,7 +237,8 @@ impl FirestoreCreateSupport for FirestoreDb {
         for<'de> O: Deserialize<'de>,
         S: AsRef<str> + Send,
     {
-        let input_doc = Self::serialize_to_doc("", obj)?;
+        let input_doc =
+            crate::firestore_serde::firestore_new_document_from_serializable(collection_id, obj)?;
 
         let doc = self
             .create_doc_at(
@@ -189,3 +253,21 @@ impl FirestoreCreateSupport for FirestoreDb {
         Self::deserialize_doc_to(&doc)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn generates_document_ids() {
+        let ids: HashSet<String> = (0..100)
+            .map(|_| FirestoreDb::generate_document_id())
+            .collect();
+        assert_eq!(ids.len(), 100);
+        assert!(ids
+            .iter()
+            .all(|id| id.len() == FIRESTORE_GENERATED_DOCUMENT_ID_LENGTH
+                && id.chars().all(|c| c.is_ascii_alphanumeric())));
+    }
+}
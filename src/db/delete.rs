@@ -1,5 +1,9 @@
 use crate::db::safe_document_path;
-use crate::{FirestoreDb, FirestoreResult, FirestoreWritePrecondition};
+use crate::errors::firestore_precondition_status_error;
+use crate::{
+    FirestoreApiResponse, FirestoreDb, FirestoreOperationKind, FirestoreResult,
+    FirestoreWritePrecondition,
+};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use gcloud_sdk::google::firestore::v1::*;
@@ -65,15 +69,44 @@ impl FirestoreDeleteSupport for FirestoreDb {
             "/firestore/collection_name" = collection_id,
             "/firestore/response_time" = field::Empty,
             "/firestore/document_name" = document_path.as_str(),
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty,
         );
 
-        let request = gcloud_sdk::tonic::Request::new(DeleteDocumentRequest {
-            name: document_path,
+        let has_precondition = precondition.is_some();
+        let request = DeleteDocumentRequest {
+            name: document_path.clone(),
             current_document: precondition.map(|cond| cond.try_into()).transpose()?,
-        });
+        };
 
         let begin_query_utc: DateTime<Utc> = Utc::now();
-        self.client().get().delete_document(request).await?;
+        self.execute_with_retries("Deleting a document", !has_precondition, || {
+            let request = request.clone();
+            let metrics =
+                self.record_operation(FirestoreOperationKind::Delete, Some(collection_id), &span);
+            async move {
+                let result = async {
+                    let response = self
+                        .client()
+                        .get()
+                        .delete_document(self.create_api_request(request)?)
+                        .await
+                        .map_err(|status| {
+                            if has_precondition {
+                                firestore_precondition_status_error(status)
+                            } else {
+                                status.into()
+                            }
+                        })?;
+                    self.notify_response_middlewares(FirestoreApiResponse::DeleteDocument);
+                    Ok(response)
+                }
+                .await;
+                metrics.record_result(&result, 1);
+                result
+            }
+        })
+        .await?;
         let end_query_utc: DateTime<Utc> = Utc::now();
         let query_duration = end_query_utc.signed_duration_since(begin_query_utc);
 
@@ -90,6 +123,9 @@ impl FirestoreDeleteSupport for FirestoreDb {
             );
         });
 
+        #[cfg(feature = "caching")]
+        self.offer_doc_delete_to_cache(&document_path).await;
+
         Ok(())
     }
 }
@@ -0,0 +1,335 @@
+use crate::errors::*;
+use crate::timestamp_utils::from_timestamp;
+use crate::{
+    firestore_field_path_escape, FirestoreConsistencySelector, FirestoreDb,
+    FirestoreGetByIdSupport, FirestoreQueryParams, FirestoreQuerySupport, FirestoreResult,
+    FirestoreValue,
+};
+use chrono::prelude::*;
+use gcloud_sdk::google::firestore::v1::{value, Document, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// The values of a field changed between two states of a document.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FirestoreFieldValueChange {
+    pub from_value: FirestoreValue,
+    pub to_value: FirestoreValue,
+}
+
+/// A field-level difference between two states of a document.
+///
+/// Nested maps are compared field by field, so the paths point to the innermost changed fields.
+/// Other values (including arrays) are compared as a whole.
+/// The paths are Firestore field paths with escaped field names.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FirestoreDocumentDiff {
+    pub document_path: String,
+    /// The update time of the first state, `None` if the document didn't exist.
+    pub from_update_time: Option<DateTime<Utc>>,
+    /// The update time of the second state, `None` if the document didn't exist.
+    pub to_update_time: Option<DateTime<Utc>>,
+    pub added: BTreeMap<String, FirestoreValue>,
+    pub removed: BTreeMap<String, FirestoreValue>,
+    pub changed: BTreeMap<String, FirestoreFieldValueChange>,
+}
+
+impl FirestoreDocumentDiff {
+    /// Compares two states of a document. `None` means the document didn't exist.
+    pub fn from_documents(
+        document_path: String,
+        from_doc: Option<&Document>,
+        to_doc: Option<&Document>,
+    ) -> FirestoreResult<Self> {
+        let update_time = |doc: Option<&Document>| {
+            doc.and_then(|doc| doc.update_time)
+                .map(from_timestamp)
+                .transpose()
+        };
+
+        let mut diff = Self {
+            document_path,
+            from_update_time: update_time(from_doc)?,
+            to_update_time: update_time(to_doc)?,
+            added: BTreeMap::new(),
+            removed: BTreeMap::new(),
+            changed: BTreeMap::new(),
+        };
+
+        let no_fields = HashMap::new();
+        diff.compare_fields(
+            None,
+            from_doc.map(|doc| &doc.fields).unwrap_or(&no_fields),
+            to_doc.map(|doc| &doc.fields).unwrap_or(&no_fields),
+        );
+        Ok(diff)
+    }
+
+    /// `true` if no fields were added, removed or changed.
+    /// A document created or deleted without any fields still has different update times.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    fn compare_fields(
+        &mut self,
+        parent_path: Option<&str>,
+        from_fields: &HashMap<String, Value>,
+        to_fields: &HashMap<String, Value>,
+    ) {
+        let field_names: BTreeSet<&String> = from_fields.keys().chain(to_fields.keys()).collect();
+
+        for field_name in field_names {
+            let field_path = match parent_path {
+                Some(parent_path) => {
+                    format!(
+                        "{}.{}",
+                        parent_path,
+                        firestore_field_path_escape(field_name)
+                    )
+                }
+                None => firestore_field_path_escape(field_name),
+            };
+
+            match (from_fields.get(field_name), to_fields.get(field_name)) {
+                (
+                    Some(Value {
+                        value_type: Some(value::ValueType::MapValue(from_map)),
+                    }),
+                    Some(Value {
+                        value_type: Some(value::ValueType::MapValue(to_map)),
+                    }),
+                ) => self.compare_fields(Some(&field_path), &from_map.fields, &to_map.fields),
+                (Some(from_value), Some(to_value)) => {
+                    if from_value != to_value {
+                        self.changed.insert(
+                            field_path,
+                            FirestoreFieldValueChange {
+                                from_value: FirestoreValue::from(from_value.clone()),
+                                to_value: FirestoreValue::from(to_value.clone()),
+                            },
+                        );
+                    }
+                }
+                (Some(from_value), None) => {
+                    self.removed
+                        .insert(field_path, FirestoreValue::from(from_value.clone()));
+                }
+                (None, Some(to_value)) => {
+                    self.added
+                        .insert(field_path, FirestoreValue::from(to_value.clone()));
+                }
+                (None, None) => {}
+            }
+        }
+    }
+}
+
+impl FirestoreDb {
+    /// Compares the states of a document at two read times.
+    ///
+    /// Both read times have to be within [`FirestoreDbOptions::point_in_time_read_window`](crate::FirestoreDbOptions::point_in_time_read_window).
+    pub async fn diff_doc_read_times<S>(
+        &self,
+        collection_id: &str,
+        document_id: S,
+        from_read_time: DateTime<Utc>,
+        to_read_time: DateTime<Utc>,
+    ) -> FirestoreResult<FirestoreDocumentDiff>
+    where
+        S: AsRef<str> + Send,
+    {
+        self.diff_doc_read_times_at(
+            self.get_documents_path().as_str(),
+            collection_id,
+            document_id,
+            from_read_time,
+            to_read_time,
+        )
+        .await
+    }
+
+    /// Compares the states of a document in the collection of the parent at two read times.
+    pub async fn diff_doc_read_times_at<S>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_id: S,
+        from_read_time: DateTime<Utc>,
+        to_read_time: DateTime<Utc>,
+    ) -> FirestoreResult<FirestoreDocumentDiff>
+    where
+        S: AsRef<str> + Send,
+    {
+        let document_path =
+            crate::db::safe_document_path(parent, collection_id, document_id.as_ref())?;
+
+        let (from_doc, to_doc) = futures::future::try_join(
+            self.get_doc_at_read_time(parent, collection_id, document_id.as_ref(), from_read_time),
+            self.get_doc_at_read_time(parent, collection_id, document_id.as_ref(), to_read_time),
+        )
+        .await?;
+
+        FirestoreDocumentDiff::from_documents(document_path, from_doc.as_ref(), to_doc.as_ref())
+    }
+
+    /// Compares the results of a query at two read times, returning the diffs of the documents
+    /// that are different, ordered by the document paths.
+    ///
+    /// Documents that match the query only at one of the read times are reported as created or deleted.
+    pub async fn diff_query_read_times(
+        &self,
+        params: FirestoreQueryParams,
+        from_read_time: DateTime<Utc>,
+        to_read_time: DateTime<Utc>,
+    ) -> FirestoreResult<Vec<FirestoreDocumentDiff>> {
+        let (from_docs, to_docs) = futures::future::try_join(
+            self.clone_with_consistency_selector(FirestoreConsistencySelector::ReadTime(
+                from_read_time,
+            ))
+            .query_doc(params.clone()),
+            self.clone_with_consistency_selector(FirestoreConsistencySelector::ReadTime(
+                to_read_time,
+            ))
+            .query_doc(params),
+        )
+        .await?;
+
+        let from_docs: BTreeMap<String, Document> = from_docs
+            .into_iter()
+            .map(|doc| (doc.name.clone(), doc))
+            .collect();
+        let to_docs: BTreeMap<String, Document> = to_docs
+            .into_iter()
+            .map(|doc| (doc.name.clone(), doc))
+            .collect();
+        let document_paths: BTreeSet<&String> = from_docs.keys().chain(to_docs.keys()).collect();
+
+        let mut diffs = Vec::new();
+        for document_path in document_paths {
+            let from_doc = from_docs.get(document_path);
+            let to_doc = to_docs.get(document_path);
+            let diff =
+                FirestoreDocumentDiff::from_documents(document_path.clone(), from_doc, to_doc)?;
+            if from_doc.is_none() || to_doc.is_none() || !diff.is_empty() {
+                diffs.push(diff);
+            }
+        }
+        Ok(diffs)
+    }
+
+    async fn get_doc_at_read_time(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_id: &str,
+        read_time: DateTime<Utc>,
+    ) -> FirestoreResult<Option<Document>> {
+        match self
+            .clone_with_consistency_selector(FirestoreConsistencySelector::ReadTime(read_time))
+            .get_doc_at(parent, collection_id, document_id, None)
+            .await
+        {
+            Ok(doc) => Ok(Some(doc)),
+            Err(FirestoreError::DataNotFoundError(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestamp_utils::to_timestamp;
+    use crate::FirestoreDb;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Address {
+        city: String,
+        zip: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    struct Customer {
+        name: String,
+        tags: Vec<String>,
+        address: Address,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(rename = "e-mail")]
+        email: Option<String>,
+    }
+
+    fn customer_doc(customer: &Customer, update_time: DateTime<Utc>) -> Document {
+        let mut doc = FirestoreDb::serialize_to_doc("customers/c1", customer).unwrap();
+        doc.update_time = Some(to_timestamp(update_time));
+        doc
+    }
+
+    #[test]
+    fn diff_nested_fields() -> FirestoreResult<()> {
+        let from_time = Utc.with_ymd_and_hms(2024, 1, 1, 10, 0, 0).unwrap();
+        let to_time = Utc.with_ymd_and_hms(2024, 1, 1, 11, 0, 0).unwrap();
+        let from_doc = customer_doc(
+            &Customer {
+                name: "Alice".to_string(),
+                tags: vec!["a".to_string()],
+                address: Address {
+                    city: "Paris".to_string(),
+                    zip: Some("75001".to_string()),
+                },
+                email: Some("alice@example.com".to_string()),
+            },
+            from_time,
+        );
+        let to_doc = customer_doc(
+            &Customer {
+                name: "Alice".to_string(),
+                tags: vec!["a".to_string(), "b".to_string()],
+                address: Address {
+                    city: "Lyon".to_string(),
+                    zip: Some("75001".to_string()),
+                },
+                email: None,
+            },
+            to_time,
+        );
+
+        let diff = FirestoreDocumentDiff::from_documents(
+            "customers/c1".to_string(),
+            Some(&from_doc),
+            Some(&to_doc),
+        )?;
+
+        assert_eq!(diff.from_update_time, Some(from_time));
+        assert_eq!(diff.to_update_time, Some(to_time));
+        assert!(diff.added.is_empty());
+        assert_eq!(diff.removed.keys().collect::<Vec<_>>(), vec!["`e-mail`"]);
+        assert_eq!(
+            diff.changed.keys().collect::<Vec<_>>(),
+            vec!["address.city", "tags"]
+        );
+        assert_eq!(
+            diff.changed["address.city"].to_value,
+            FirestoreValue::from(Value {
+                value_type: Some(value::ValueType::StringValue("Lyon".to_string()))
+            })
+        );
+
+        let created =
+            FirestoreDocumentDiff::from_documents("customers/c1".to_string(), None, Some(&to_doc))?;
+        assert_eq!(created.from_update_time, None);
+        assert_eq!(
+            created.added.keys().collect::<Vec<_>>(),
+            vec!["address", "name", "tags"]
+        );
+
+        let unchanged = FirestoreDocumentDiff::from_documents(
+            "customers/c1".to_string(),
+            Some(&to_doc),
+            Some(&to_doc),
+        )?;
+        assert!(unchanged.is_empty());
+
+        Ok(())
+    }
+}
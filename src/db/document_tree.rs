@@ -0,0 +1,329 @@
+use crate::{
+    FirestoreConsistencySelector, FirestoreDb, FirestoreDocument, FirestoreListCollectionIdsParams,
+    FirestoreListDocParams, FirestoreListingSupport, FirestoreResult,
+};
+use futures::future::BoxFuture;
+use futures::stream::{BoxStream, FuturesUnordered};
+use futures::{FutureExt, StreamExt, TryStreamExt};
+use rsb_derive::*;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+/// Parameters of streaming the descendants of a document.
+#[derive(Debug, Clone, Builder)]
+pub struct FirestoreDocumentTreeParams {
+    /// The path of the document to stream the descendants of.
+    /// If `None`, all the documents of the database are streamed.
+    pub parent: Option<String>,
+    /// The maximum depth of the collections to descend into, `1` to stream only the documents
+    /// of the collections of the parent. Unlimited if not set.
+    pub max_depth: Option<usize>,
+    /// The maximum number of listing requests running concurrently.
+    #[default = "4"]
+    pub max_concurrency: usize,
+    #[default = "100"]
+    pub page_size: usize,
+    pub consistency_selector: Option<FirestoreConsistencySelector>,
+}
+
+type FirestoreDocumentTreeDeserializer<T> =
+    Arc<dyn Fn(&FirestoreDocument) -> FirestoreResult<T> + Send + Sync>;
+
+/// Deserializers of the documents of a tree by their collection IDs,
+/// e.g. to variants of an enum with a type for each collection.
+pub struct FirestoreDocumentTreeRegistry<T> {
+    deserializers: HashMap<String, FirestoreDocumentTreeDeserializer<T>>,
+}
+
+impl<T> FirestoreDocumentTreeRegistry<T>
+where
+    T: 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            deserializers: HashMap::new(),
+        }
+    }
+
+    /// Deserializes the documents of the collections with the ID to `O` and converts them to `T`.
+    pub fn register<O, F>(mut self, collection_id: &str, to_value: F) -> Self
+    where
+        for<'de> O: Deserialize<'de>,
+        F: Fn(O) -> T + Send + Sync + 'static,
+    {
+        self.deserializers.insert(
+            collection_id.to_string(),
+            Arc::new(move |doc| FirestoreDb::deserialize_doc_to::<O>(doc).map(&to_value)),
+        );
+        self
+    }
+
+    /// Deserializes the document, `None` if its collection isn't registered.
+    pub fn deserialize(&self, doc: &FirestoreDocument) -> Option<FirestoreResult<T>> {
+        let collection_id = doc.name.rsplit('/').nth(1)?;
+        self.deserializers
+            .get(collection_id)
+            .map(|deserializer| deserializer(doc))
+    }
+}
+
+impl<T> Default for FirestoreDocumentTreeRegistry<T>
+where
+    T: 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum FirestoreDocumentTreeTask {
+    ListCollections {
+        parent: String,
+        depth: usize,
+        page_token: Option<String>,
+    },
+    ListDocuments {
+        parent: String,
+        collection_id: String,
+        depth: usize,
+        page_token: Option<String>,
+    },
+}
+
+type FirestoreDocumentTreeTaskResult =
+    FirestoreResult<(Vec<FirestoreDocumentTreeTask>, Vec<FirestoreDocument>)>;
+
+struct FirestoreDocumentTreeState<D> {
+    db: D,
+    documents_path: String,
+    params: FirestoreDocumentTreeParams,
+    pending: Vec<FirestoreDocumentTreeTask>,
+    running: FuturesUnordered<BoxFuture<'static, FirestoreDocumentTreeTaskResult>>,
+    documents: VecDeque<FirestoreDocument>,
+}
+
+impl<D> FirestoreDocumentTreeState<D>
+where
+    D: FirestoreListingSupport + Clone + Send + Sync + 'static,
+{
+    fn run_task(
+        &self,
+        task: FirestoreDocumentTreeTask,
+    ) -> BoxFuture<'static, FirestoreDocumentTreeTaskResult> {
+        let db = self.db.clone();
+        let params = self.params.clone();
+        async move {
+            match task {
+                FirestoreDocumentTreeTask::ListCollections {
+                    parent,
+                    depth,
+                    page_token,
+                } => {
+                    let result = db
+                        .list_collection_ids(
+                            FirestoreListCollectionIdsParams::new()
+                                .with_parent(parent.clone())
+                                .with_page_size(params.page_size)
+                                .opt_page_token(page_token)
+                                .opt_consistency_selector(params.consistency_selector.clone()),
+                        )
+                        .await?;
+
+                    let mut tasks: Vec<FirestoreDocumentTreeTask> = result
+                        .collection_ids
+                        .into_iter()
+                        .map(|collection_id| FirestoreDocumentTreeTask::ListDocuments {
+                            parent: parent.clone(),
+                            collection_id,
+                            depth,
+                            page_token: None,
+                        })
+                        .collect();
+                    tasks.extend(result.page_token.map(|page_token| {
+                        FirestoreDocumentTreeTask::ListCollections {
+                            parent,
+                            depth,
+                            page_token: Some(page_token),
+                        }
+                    }));
+                    Ok((tasks, vec![]))
+                }
+                FirestoreDocumentTreeTask::ListDocuments {
+                    parent,
+                    collection_id,
+                    depth,
+                    page_token,
+                } => {
+                    let result = db
+                        .list_doc(
+                            FirestoreListDocParams::new(collection_id.clone())
+                                .with_parent(parent.clone())
+                                .with_page_size(params.page_size)
+                                .opt_page_token(page_token)
+                                .opt_consistency_selector(params.consistency_selector.clone())
+                                // Missing documents don't exist, but may have subcollections
+                                .with_show_missing(true),
+                        )
+                        .await?;
+
+                    let mut tasks: Vec<FirestoreDocumentTreeTask> = Vec::new();
+                    if params.max_depth.map_or(true, |max_depth| depth < max_depth) {
+                        tasks.extend(result.documents.iter().map(|doc| {
+                            FirestoreDocumentTreeTask::ListCollections {
+                                parent: doc.name.clone(),
+                                depth: depth + 1,
+                                page_token: None,
+                            }
+                        }));
+                    }
+                    tasks.extend(result.page_token.map(|page_token| {
+                        FirestoreDocumentTreeTask::ListDocuments {
+                            parent,
+                            collection_id,
+                            depth,
+                            page_token: Some(page_token),
+                        }
+                    }));
+                    let documents = result
+                        .documents
+                        .into_iter()
+                        .filter(|doc| doc.create_time.is_some() || !doc.fields.is_empty())
+                        .collect();
+                    Ok((tasks, documents))
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn relative_path(&self, doc: &FirestoreDocument) -> String {
+        doc.name
+            .strip_prefix(self.documents_path.as_str())
+            .and_then(|path| path.strip_prefix('/'))
+            .unwrap_or(doc.name.as_str())
+            .to_string()
+    }
+}
+
+/// Streams the documents under the parent with their paths relative to the documents path,
+/// listing the collections and the documents page by page.
+pub(crate) fn firestore_document_tree_stream<'b, D>(
+    db: D,
+    documents_path: String,
+    params: FirestoreDocumentTreeParams,
+) -> BoxStream<'b, FirestoreResult<(String, FirestoreDocument)>>
+where
+    D: FirestoreListingSupport + Clone + Send + Sync + 'static,
+{
+    let state = FirestoreDocumentTreeState {
+        pending: vec![FirestoreDocumentTreeTask::ListCollections {
+            parent: params
+                .parent
+                .clone()
+                .unwrap_or_else(|| documents_path.clone()),
+            depth: 1,
+            page_token: None,
+        }],
+        db,
+        documents_path,
+        params,
+        running: FuturesUnordered::new(),
+        documents: VecDeque::new(),
+    };
+
+    futures::stream::unfold(Some(state), |state| async move {
+        let mut state = state?;
+        loop {
+            if let Some(doc) = state.documents.pop_front() {
+                let path = state.relative_path(&doc);
+                return Some((Ok((path, doc)), Some(state)));
+            }
+
+            // The pending tasks are a stack, so the subcollections of the listed documents
+            // are traversed before the next pages, keeping the number of pending tasks bounded.
+            // The tasks are pushed in reverse to start with the first of them.
+            while state.running.len() < state.params.max_concurrency.max(1) {
+                match state.pending.pop() {
+                    Some(task) => {
+                        let future = state.run_task(task);
+                        state.running.push(future)
+                    }
+                    None => break,
+                }
+            }
+
+            match state.running.next().await {
+                Some(Ok((tasks, documents))) => {
+                    state.pending.extend(tasks.into_iter().rev());
+                    state.documents = documents.into();
+                }
+                Some(Err(err)) => return Some((Err(err), None)),
+                None => return None,
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Streams the documents under the parent deserialized with the registry,
+/// skipping the documents of the collections that aren't registered.
+pub(crate) fn firestore_document_tree_obj_stream<'b, D, T>(
+    db: D,
+    documents_path: String,
+    params: FirestoreDocumentTreeParams,
+    registry: FirestoreDocumentTreeRegistry<T>,
+) -> BoxStream<'b, FirestoreResult<(String, T)>>
+where
+    D: FirestoreListingSupport + Clone + Send + Sync + 'static,
+    T: Send + 'static,
+{
+    firestore_document_tree_stream(db, documents_path, params)
+        .try_filter_map(move |(path, doc)| {
+            futures::future::ready(
+                registry
+                    .deserialize(&doc)
+                    .transpose()
+                    .map(|obj| obj.map(|obj| (path, obj))),
+            )
+        })
+        .boxed()
+}
+
+impl FirestoreDb {
+    /// Streams the descendants of a document (or all the documents of the database)
+    /// with their paths relative to the documents path, e.g. `tenants/t1/users/u1`,
+    /// listing the subcollections recursively.
+    ///
+    /// The documents of different collections are streamed concurrently,
+    /// so they aren't ordered. The stream ends after the first error.
+    pub async fn stream_document_tree<'b>(
+        &self,
+        params: FirestoreDocumentTreeParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<(String, FirestoreDocument)>>> {
+        Ok(firestore_document_tree_stream(
+            self.clone(),
+            self.get_documents_path().clone(),
+            params,
+        ))
+    }
+
+    /// Streams the descendants of a document deserialized by their collection IDs.
+    /// The documents of the collections not in the registry are skipped,
+    /// but their subcollections are still traversed.
+    pub async fn stream_document_tree_obj<'b, T>(
+        &self,
+        params: FirestoreDocumentTreeParams,
+        registry: FirestoreDocumentTreeRegistry<T>,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<(String, T)>>>
+    where
+        T: Send + 'static,
+    {
+        Ok(firestore_document_tree_obj_stream(
+            self.clone(),
+            self.get_documents_path().clone(),
+            params,
+            registry,
+        ))
+    }
+}
@@ -1,4 +1,6 @@
 use crate::db::safe_document_path;
+#[cfg(feature = "caching")]
+use crate::db::split_document_path;
 use crate::errors::*;
 use crate::*;
 use async_trait::async_trait;
@@ -9,10 +11,32 @@ use futures::TryFutureExt;
 use futures::TryStreamExt;
 use futures::{future, StreamExt};
 use gcloud_sdk::google::firestore::v1::*;
-use rand::Rng;
 use serde::Deserialize;
+use std::collections::HashMap;
 use tracing::*;
 
+/// The outcome for a single requested document of an ordered batch get
+/// (see [`FirestoreGetByIdSupport::batch_get_objects_ordered`]).
+#[derive(Debug)]
+pub enum FirestoreBatchGetResult<T> {
+    /// The document was found and deserialized.
+    Found(T),
+    /// The document doesn't exist. Contains the full path of the document.
+    Missing(String),
+    /// The document was found, but couldn't be deserialized.
+    Error(FirestoreError),
+}
+
+impl<T> FirestoreBatchGetResult<T> {
+    /// Returns the deserialized object if the document was found and deserialized.
+    pub fn found(self) -> Option<T> {
+        match self {
+            FirestoreBatchGetResult::Found(obj) => Some(obj),
+            _ => None,
+        }
+    }
+}
+
 #[async_trait]
 pub trait FirestoreGetByIdSupport {
     async fn get_doc<S>(
@@ -178,6 +202,31 @@ pub trait FirestoreGetByIdSupport {
         for<'de> T: Deserialize<'de> + Send + 'a,
         S: AsRef<str> + Send,
         I: IntoIterator<Item = S> + Send;
+    /// Fetches documents by IDs and returns an outcome for every requested ID
+    /// in the request order, including missing documents and per-document
+    /// deserialization errors. Other errors fail the whole call.
+    async fn batch_get_objects_ordered<T, S, I>(
+        &self,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<Vec<(String, FirestoreBatchGetResult<T>)>>
+    where
+        for<'de> T: Deserialize<'de> + Send,
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send;
+
+    async fn batch_get_objects_ordered_at<T, S, I>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<Vec<(String, FirestoreBatchGetResult<T>)>>
+    where
+        for<'de> T: Deserialize<'de> + Send,
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send;
 }
 
 #[async_trait]
@@ -544,6 +593,85 @@ impl FirestoreGetByIdSupport for FirestoreDb {
             })
         })))
     }
+    async fn batch_get_objects_ordered<T, S, I>(
+        &self,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<Vec<(String, FirestoreBatchGetResult<T>)>>
+    where
+        for<'de> T: Deserialize<'de> + Send,
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send,
+    {
+        self.batch_get_objects_ordered_at(
+            self.get_documents_path(),
+            collection_id,
+            document_ids,
+            return_only_fields,
+        )
+        .await
+    }
+
+    async fn batch_get_objects_ordered_at<T, S, I>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<Vec<(String, FirestoreBatchGetResult<T>)>>
+    where
+        for<'de> T: Deserialize<'de> + Send,
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send,
+    {
+        let full_doc_ids: Vec<String> = document_ids
+            .into_iter()
+            .map(|document_id| safe_document_path(parent, collection_id, document_id.as_ref()))
+            .collect::<FirestoreResult<Vec<String>>>()?;
+
+        let found_docs: HashMap<String, Document> = self
+            .get_docs_by_ids(
+                collection_id.to_string(),
+                full_doc_ids.clone(),
+                return_only_fields,
+            )
+            .await?
+            .try_filter_map(|(_, maybe_doc)| {
+                future::ready(Ok(maybe_doc.map(|doc| (doc.name.clone(), doc))))
+            })
+            .try_collect()
+            .await?;
+
+        Ok(order_batch_get_results(full_doc_ids, &found_docs))
+    }
+}
+
+pub(crate) fn order_batch_get_results<T>(
+    full_doc_ids: Vec<String>,
+    found_docs: &HashMap<String, Document>,
+) -> Vec<(String, FirestoreBatchGetResult<T>)>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    full_doc_ids
+        .into_iter()
+        .map(|full_doc_id| {
+            let doc_id = full_doc_id
+                .split('/')
+                .next_back()
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| full_doc_id.clone());
+            let result = match found_docs.get(&full_doc_id) {
+                Some(doc) => match FirestoreDb::deserialize_doc_to::<T>(doc) {
+                    Ok(obj) => FirestoreBatchGetResult::Found(obj),
+                    Err(err) => FirestoreBatchGetResult::Error(err),
+                },
+                None => FirestoreBatchGetResult::Missing(full_doc_id),
+            };
+            (doc_id, result)
+        })
+        .collect()
 }
 
 impl FirestoreDb {
@@ -576,15 +704,18 @@ impl FirestoreDb {
                 "Firestore Get Doc",
                 "/firestore/collection_name" = collection_id,
                 "/firestore/response_time" = field::Empty,
-                "/firestore/document_name" = document_path.as_str()
+                "/firestore/document_name" = document_path.as_str(),
+                "/firestore/operation" = field::Empty,
+                "/firestore/document_count" = field::Empty
             );
+            let metrics =
+                self.record_operation(FirestoreOperationKind::Get, Some(&collection_id), &span);
             let begin_query_utc: DateTime<Utc> = Utc::now();
 
-            let request = gcloud_sdk::tonic::Request::new(GetDocumentRequest {
+            let request = self.create_api_request(GetDocumentRequest {
                 name: document_path.clone(),
                 consistency_selector: self
-                    .session_params
-                    .consistency_selector
+                    .read_consistency_selector(None)?
                     .as_ref()
                     .map(|selector| selector.try_into())
                     .transpose()?,
@@ -593,7 +724,7 @@ impl FirestoreDb {
                         field_paths: vf.iter().map(|f| f.to_string()).collect(),
                     }
                 }),
-            });
+            })?;
 
             let response = self
                 .client()
@@ -601,6 +732,7 @@ impl FirestoreDb {
                 .get_document(request)
                 .map_err(|e| e.into())
                 .await;
+            metrics.record_result(&response, 1);
 
             let end_query_utc: DateTime<Utc> = Utc::now();
             let query_duration = end_query_utc.signed_duration_since(begin_query_utc);
@@ -621,24 +753,25 @@ impl FirestoreDb {
                     });
 
                     let doc = doc_response.into_inner();
+                    self.notify_response_middlewares(FirestoreApiResponse::GetDocument(&doc));
                     #[cfg(feature = "caching")]
-                    if _return_only_fields_empty {
+                    if _return_only_fields_empty && !self.reads_at_point_in_time(None) {
                         self.offer_doc_update_to_cache(&doc).await?;
                     }
                     Ok(doc)
                 }
-                Err(err) => match err {
-                    FirestoreError::DatabaseError(ref db_err)
-                    if db_err.retry_possible && retries < self.get_options().max_retries =>
+                Err(err) => {
+                    let retry_policy = FirestoreRetryPolicy::current(self.get_options());
+                    match err {
+                        FirestoreError::DatabaseError(ref db_err)
+                            if retry_policy.should_retry(&err, retries, true) =>
                         {
-                            let sleep_duration = tokio::time::Duration::from_millis(
-                                rand::rng().random_range(0..2u64.pow(retries as u32) * 1000 + 1),
-                            );
+                            let sleep_duration = retry_policy.backoff(retries);
                             span.in_scope(|| {
                                 warn!(
                                     err = %db_err,
                                     current_retry = retries + 1,
-                                    max_retries = self.get_options().max_retries,
+                                    max_attempts = retry_policy.max_attempts,
                                     delay = sleep_duration.as_millis(),
                                     "Failed to get document. Retrying up to the specified number of times.",
                                 );
@@ -649,8 +782,9 @@ impl FirestoreDb {
                             self.get_doc_by_path(collection_id, document_path, None, retries + 1)
                                 .await
                         }
-                    _ => Err(err),
-                },
+                        _ => Err(err),
+                    }
+                }
             }
         }
             .boxed()
@@ -680,15 +814,21 @@ impl FirestoreDb {
             Level::DEBUG,
             "Firestore Batch Get",
             "/firestore/collection_name" = collection_id.as_str(),
-            "/firestore/ids_count" = full_doc_ids.len()
+            "/firestore/ids_count" = full_doc_ids.len(),
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty
+        );
+        let metrics = self.record_operation(
+            FirestoreOperationKind::BatchGet,
+            Some(collection_id.as_str()),
+            &span,
         );
 
-        let request = gcloud_sdk::tonic::Request::new(BatchGetDocumentsRequest {
+        let request = self.create_api_request(BatchGetDocumentsRequest {
             database: self.get_database_path().clone(),
             documents: full_doc_ids,
             consistency_selector: self
-                .session_params
-                .consistency_selector
+                .read_consistency_selector(None)?
                 .as_ref()
                 .map(|selector| selector.try_into())
                 .transpose()?,
@@ -697,13 +837,21 @@ impl FirestoreDb {
                     field_paths: vf.iter().map(|f| f.to_string()).collect(),
                 }
             }),
-        });
+        })?;
+
+        #[cfg(feature = "caching")]
+        let offer_to_cache = !self.reads_at_point_in_time(None);
 
         match self.client().get().batch_get_documents(request).await {
             Ok(response) => {
                 span.in_scope(|| debug!("Start consuming a batch of documents by IDs."));
                 let stream = response
                     .into_inner()
+                    .inspect_ok(|response| {
+                        self.notify_response_middlewares(FirestoreApiResponse::BatchGetDocuments(
+                            response,
+                        ))
+                    })
                     .filter_map(move |r| async move {
                         match r {
                             Ok(doc_response) => match doc_response.result {
@@ -716,7 +864,9 @@ impl FirestoreDb {
                                         .unwrap_or_else(|| document.name.clone());
                                     #[cfg(feature = "caching")]
                                     {
-                                        self.offer_doc_update_to_cache(&document).await.ok();
+                                        if offer_to_cache {
+                                            self.offer_doc_update_to_cache(&document).await.ok();
+                                        }
 
                                         Some(Ok((doc_id, Some(document))))
                                     }
@@ -741,9 +891,9 @@ impl FirestoreDb {
                         }
                     })
                     .boxed();
-                Ok(stream)
+                Ok(metrics.record_stream(stream, |(_, doc)| usize::from(doc.is_some())))
             }
-            Err(err) => Err(err.into()),
+            Err(err) => metrics.record_stream_result(Err(err.into()), |_| 0),
         }
     }
 
@@ -754,6 +904,9 @@ impl FirestoreDb {
         document_path: &str,
         _return_only_fields: &Option<Vec<String>>,
     ) -> FirestoreResult<FirestoreCachedValue<FirestoreDocument>> {
+        if self.reads_at_point_in_time(None) {
+            return Ok(FirestoreCachedValue::SkipCache);
+        }
         if let FirestoreDbSessionCacheMode::ReadThroughCache(ref cache)
         | FirestoreDbSessionCacheMode::ReadCachedOnly(ref cache) = self.session_params.cache_mode
         {
@@ -783,6 +936,8 @@ impl FirestoreDb {
                     );
                 });
 
+                self.offer_cached_doc_revalidation(collection_id, document_path);
+
                 return Ok(FirestoreCachedValue::UseCached(doc));
             } else {
                 span.record("/firestore/cache_result", "miss");
@@ -811,8 +966,12 @@ impl FirestoreDb {
         collection_id: &str,
         full_doc_ids: &[String],
         _return_only_fields: &Option<Vec<String>>,
-    ) -> FirestoreResult<FirestoreCachedValue<BoxStream<FirestoreResult<(String, Option<Document>)>>>>
-    {
+    ) -> FirestoreResult<
+        FirestoreCachedValue<BoxStream<'_, FirestoreResult<(String, Option<Document>)>>>,
+    > {
+        if self.reads_at_point_in_time(None) {
+            return Ok(FirestoreCachedValue::SkipCache);
+        }
         if let FirestoreDbSessionCacheMode::ReadThroughCache(ref cache)
         | FirestoreDbSessionCacheMode::ReadCachedOnly(ref cache) = self.session_params.cache_mode
         {
@@ -878,8 +1037,81 @@ impl FirestoreDb {
         if let FirestoreDbSessionCacheMode::ReadThroughCache(ref cache) =
             self.session_params.cache_mode
         {
-            cache.update_doc_by_path(document).await?;
+            let (collection_path, _) = split_document_path(&document.name);
+            let read_through = cache
+                .collection_consistency(collection_path)
+                .map(|consistency| consistency.read_through)
+                .unwrap_or(true);
+            if read_through {
+                cache.update_doc_by_path(document).await?;
+            }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcloud_sdk::google::firestore::v1::{value, Value};
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct TestStructure {
+        some_num: i64,
+    }
+
+    fn test_doc(path: &str, value: value::ValueType) -> Document {
+        Document {
+            name: path.to_string(),
+            fields: [(
+                "some_num".to_string(),
+                Value {
+                    value_type: Some(value),
+                },
+            )]
+            .into(),
+            create_time: None,
+            update_time: None,
+        }
+    }
+
+    #[test]
+    fn ordered_batch_get_results() {
+        let found_docs: HashMap<String, Document> = [
+            test_doc("col/doc-3", value::ValueType::IntegerValue(3)),
+            test_doc("col/doc-1", value::ValueType::IntegerValue(1)),
+            test_doc(
+                "col/doc-4",
+                value::ValueType::StringValue("not a number".to_string()),
+            ),
+        ]
+        .into_iter()
+        .map(|doc| (doc.name.clone(), doc))
+        .collect();
+
+        let results: Vec<(String, FirestoreBatchGetResult<TestStructure>)> =
+            order_batch_get_results(
+                vec![
+                    "col/doc-1".to_string(),
+                    "col/doc-2".to_string(),
+                    "col/doc-3".to_string(),
+                    "col/doc-4".to_string(),
+                ],
+                &found_docs,
+            );
+
+        let ids: Vec<&str> = results.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["doc-1", "doc-2", "doc-3", "doc-4"]);
+
+        assert!(matches!(&results[0].1, FirestoreBatchGetResult::Found(obj) if obj.some_num == 1));
+        assert!(
+            matches!(&results[1].1, FirestoreBatchGetResult::Missing(path) if path == "col/doc-2")
+        );
+        assert!(matches!(&results[2].1, FirestoreBatchGetResult::Found(obj) if obj.some_num == 3));
+        assert!(matches!(
+            &results[3].1,
+            FirestoreBatchGetResult::Error(FirestoreError::DeserializeError(_))
+        ));
+    }
+}
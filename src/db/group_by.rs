@@ -0,0 +1,694 @@
+use crate::firestore_value::compare_field_values;
+use crate::{
+    firestore_doc_get_field_by_path, FirestoreQueryDirection, FirestoreQueryOrder,
+    FirestoreQueryParams, FirestoreQuerySupport, FirestoreResult, FirestoreValue,
+};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use futures::TryStreamExt;
+use gcloud_sdk::google::firestore::v1::*;
+use rsb_derive::*;
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// Parameters for a client-side group-by query.
+///
+/// Firestore has no server-side `GROUP BY`, so the documents matching `query_params`
+/// are streamed to the client and the aggregations are computed locally for every
+/// distinct combination of values of `group_by_fields`.
+#[derive(Debug, PartialEq, Clone, Builder)]
+pub struct FirestoreGroupByParams {
+    /// The query selecting the documents to group.
+    pub query_params: FirestoreQueryParams,
+    /// Field paths forming the group key. Missing fields are grouped as `null`,
+    /// except with [`FirestoreGroupByStrategy::SortedStream`] (see its documentation).
+    /// Integer and double values that are equal are grouped together as integers.
+    pub group_by_fields: Vec<String>,
+    /// Aggregations computed for every group.
+    pub aggregations: Vec<FirestoreGroupAggregation>,
+    /// How groups are accumulated while the documents are streamed.
+    #[default = "FirestoreGroupByStrategy::Hash"]
+    pub strategy: FirestoreGroupByStrategy,
+}
+
+/// A single aggregation computed for every group, stored under `alias` in the result.
+#[derive(Debug, PartialEq, Clone, Builder)]
+pub struct FirestoreGroupAggregation {
+    pub alias: String,
+    pub operator: FirestoreGroupAggregationOperator,
+}
+
+/// Aggregation operators supported by client-side group-by queries.
+///
+/// Operators with a field path ignore documents where the field is missing or has an
+/// unsupported type, similar to the server-side `sum` and `avg` aggregations.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum FirestoreGroupAggregationOperator {
+    /// Number of documents in the group.
+    Count,
+    /// Sum of numeric values. Stays an integer unless a double is seen or the sum overflows.
+    Sum(String),
+    /// Average of numeric values as a double, or `null` if there are none.
+    Avg(String),
+    /// Minimum value using the Firestore value ordering, or `null` if there are none.
+    Min(String),
+    /// Maximum value using the Firestore value ordering, or `null` if there are none.
+    Max(String),
+}
+
+/// Strategy used to accumulate groups while streaming documents.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FirestoreGroupByStrategy {
+    /// Keeps one accumulator per distinct group key, so memory is bounded by the number
+    /// of groups rather than the number of documents. Groups are returned after the
+    /// query has been fully consumed.
+    Hash,
+    /// Orders the query by the group fields and emits every group as soon as its key
+    /// changes, so only one group is held in memory at a time.
+    /// This requires an index supporting that ordering.
+    ///
+    /// Firestore excludes the documents missing any of the ordered fields from the results,
+    /// so these documents are not grouped at all (explicit `null` values are still grouped).
+    SortedStream,
+}
+
+impl FirestoreGroupByParams {
+    pub(crate) fn to_query_params(&self) -> FirestoreQueryParams {
+        match self.strategy {
+            FirestoreGroupByStrategy::Hash => self.query_params.clone(),
+            FirestoreGroupByStrategy::SortedStream => {
+                let existing_order_by = self.query_params.order_by.clone().unwrap_or_default();
+                let order_by: Vec<FirestoreQueryOrder> = self
+                    .group_by_fields
+                    .iter()
+                    .map(|field_name| {
+                        FirestoreQueryOrder::new(
+                            field_name.clone(),
+                            FirestoreQueryDirection::Ascending,
+                        )
+                    })
+                    .chain(
+                        existing_order_by
+                            .into_iter()
+                            .filter(|order| !self.group_by_fields.contains(&order.field_name)),
+                    )
+                    .collect();
+                self.query_params.clone().with_order_by(order_by)
+            }
+        }
+    }
+}
+
+pub(crate) async fn group_by_query_obj<D, K, T>(
+    db: &D,
+    params: FirestoreGroupByParams,
+) -> FirestoreResult<BTreeMap<K, T>>
+where
+    D: FirestoreQuerySupport,
+    for<'de> K: Deserialize<'de>,
+    K: Ord + Send,
+    for<'de> T: Deserialize<'de>,
+    T: Send,
+{
+    stream_group_by_query_obj_with_errors(db, params)
+        .await?
+        .try_collect()
+        .await
+}
+
+pub(crate) async fn stream_group_by_query_obj_with_errors<'b, D, K, T>(
+    db: &D,
+    params: FirestoreGroupByParams,
+) -> FirestoreResult<BoxStream<'b, FirestoreResult<(K, T)>>>
+where
+    D: FirestoreQuerySupport,
+    for<'de> K: Deserialize<'de>,
+    K: Ord + Send + 'b,
+    for<'de> T: Deserialize<'de>,
+    T: Send + 'b,
+{
+    let docs = db
+        .stream_query_doc_with_errors(params.to_query_params())
+        .await?;
+    group_docs_stream(
+        docs,
+        params.group_by_fields,
+        params.aggregations,
+        params.strategy,
+    )
+    .await
+}
+
+pub(crate) async fn group_docs_stream<'b, K, T>(
+    docs: BoxStream<'b, FirestoreResult<Document>>,
+    group_by_fields: Vec<String>,
+    aggregations: Vec<FirestoreGroupAggregation>,
+    strategy: FirestoreGroupByStrategy,
+) -> FirestoreResult<BoxStream<'b, FirestoreResult<(K, T)>>>
+where
+    for<'de> K: Deserialize<'de>,
+    K: Ord + Send + 'b,
+    for<'de> T: Deserialize<'de>,
+    T: Send + 'b,
+{
+    match strategy {
+        FirestoreGroupByStrategy::Hash => {
+            let groups: BTreeMap<K, FirestoreGroupState> = docs
+                .try_fold(BTreeMap::new(), |mut groups, doc| {
+                    let result =
+                        K::deserialize(group_key_value(&doc, &group_by_fields)).map(|key: K| {
+                            groups
+                                .entry(key)
+                                .or_insert_with(|| FirestoreGroupState::new(&aggregations))
+                                .accumulate(&doc);
+                            groups
+                        });
+                    futures::future::ready(result)
+                })
+                .await?;
+
+            Ok(futures::stream::iter(
+                groups
+                    .into_iter()
+                    .map(|(key, state)| T::deserialize(state.finish()).map(|value| (key, value))),
+            )
+            .boxed())
+        }
+        FirestoreGroupByStrategy::SortedStream => Ok(futures::stream::unfold(
+            (
+                docs,
+                None::<(FirestoreValue, FirestoreGroupState)>,
+                group_by_fields,
+                aggregations,
+            ),
+            |(mut docs, mut current, group_by_fields, aggregations)| async move {
+                loop {
+                    match docs.next().await {
+                        Some(Ok(doc)) => {
+                            let key = group_key_value(&doc, &group_by_fields);
+                            match current.take() {
+                                Some((current_key, mut state)) if current_key == key => {
+                                    state.accumulate(&doc);
+                                    current = Some((current_key, state));
+                                }
+                                previous => {
+                                    let mut state = FirestoreGroupState::new(&aggregations);
+                                    state.accumulate(&doc);
+                                    current = Some((key, state));
+                                    if let Some((previous_key, previous_state)) = previous {
+                                        return Some((
+                                            finish_group(previous_key, previous_state),
+                                            (docs, current, group_by_fields, aggregations),
+                                        ));
+                                    }
+                                }
+                            }
+                        }
+                        Some(Err(err)) => {
+                            return Some((Err(err), (docs, current, group_by_fields, aggregations)))
+                        }
+                        None => {
+                            return current.map(|(key, state)| {
+                                (
+                                    finish_group(key, state),
+                                    (docs, None, group_by_fields, aggregations),
+                                )
+                            })
+                        }
+                    }
+                }
+            },
+        )
+        .boxed()),
+    }
+}
+
+fn finish_group<K, T>(key: FirestoreValue, state: FirestoreGroupState) -> FirestoreResult<(K, T)>
+where
+    for<'de> K: Deserialize<'de>,
+    for<'de> T: Deserialize<'de>,
+{
+    Ok((K::deserialize(key)?, T::deserialize(state.finish())?))
+}
+
+fn null_value() -> Value {
+    Value {
+        value_type: Some(value::ValueType::NullValue(0)),
+    }
+}
+
+/// Builds the group key for a document: the field value itself for a single group field,
+/// or an array of the values for several fields (so it can be deserialized into a tuple).
+fn group_key_value(doc: &Document, group_by_fields: &[String]) -> FirestoreValue {
+    let mut values: Vec<Value> = group_by_fields
+        .iter()
+        .map(|field_path| {
+            firestore_doc_get_field_by_path(doc, field_path)
+                .map(|value_type| Value {
+                    value_type: Some(group_key_value_type(value_type)),
+                })
+                .unwrap_or_else(null_value)
+        })
+        .collect();
+
+    if values.len() == 1 {
+        FirestoreValue::from(values.remove(0))
+    } else {
+        FirestoreValue::from(Value {
+            value_type: Some(value::ValueType::ArrayValue(ArrayValue { values })),
+        })
+    }
+}
+
+/// Firestore treats integers and doubles with the same value as equal,
+/// so doubles without a fractional part are grouped as integers.
+fn group_key_value_type(value_type: &value::ValueType) -> value::ValueType {
+    match value_type {
+        value::ValueType::DoubleValue(v)
+            if v.fract() == 0.0 && *v >= i64::MIN as f64 && *v < i64::MAX as f64 =>
+        {
+            value::ValueType::IntegerValue(*v as i64)
+        }
+        other => other.clone(),
+    }
+}
+
+struct FirestoreGroupState {
+    accumulators: Vec<(String, FirestoreGroupAccumulator)>,
+}
+
+enum FirestoreGroupAccumulator {
+    Count(i64),
+    Sum {
+        field_name: String,
+        int_sum: i64,
+        double_sum: f64,
+        is_double: bool,
+    },
+    Avg {
+        field_name: String,
+        sum: f64,
+        count: i64,
+    },
+    Min {
+        field_name: String,
+        value: Option<Value>,
+    },
+    Max {
+        field_name: String,
+        value: Option<Value>,
+    },
+}
+
+impl FirestoreGroupState {
+    fn new(aggregations: &[FirestoreGroupAggregation]) -> Self {
+        Self {
+            accumulators: aggregations
+                .iter()
+                .map(|aggregation| {
+                    let accumulator = match &aggregation.operator {
+                        FirestoreGroupAggregationOperator::Count => {
+                            FirestoreGroupAccumulator::Count(0)
+                        }
+                        FirestoreGroupAggregationOperator::Sum(field_name) => {
+                            FirestoreGroupAccumulator::Sum {
+                                field_name: field_name.clone(),
+                                int_sum: 0,
+                                double_sum: 0.0,
+                                is_double: false,
+                            }
+                        }
+                        FirestoreGroupAggregationOperator::Avg(field_name) => {
+                            FirestoreGroupAccumulator::Avg {
+                                field_name: field_name.clone(),
+                                sum: 0.0,
+                                count: 0,
+                            }
+                        }
+                        FirestoreGroupAggregationOperator::Min(field_name) => {
+                            FirestoreGroupAccumulator::Min {
+                                field_name: field_name.clone(),
+                                value: None,
+                            }
+                        }
+                        FirestoreGroupAggregationOperator::Max(field_name) => {
+                            FirestoreGroupAccumulator::Max {
+                                field_name: field_name.clone(),
+                                value: None,
+                            }
+                        }
+                    };
+                    (aggregation.alias.clone(), accumulator)
+                })
+                .collect(),
+        }
+    }
+
+    fn accumulate(&mut self, doc: &Document) {
+        for (_, accumulator) in self.accumulators.iter_mut() {
+            match accumulator {
+                FirestoreGroupAccumulator::Count(count) => *count += 1,
+                FirestoreGroupAccumulator::Sum {
+                    field_name,
+                    int_sum,
+                    double_sum,
+                    is_double,
+                } => match firestore_doc_get_field_by_path(doc, field_name) {
+                    Some(value::ValueType::IntegerValue(v)) => {
+                        *double_sum += *v as f64;
+                        if !*is_double {
+                            match int_sum.checked_add(*v) {
+                                Some(sum) => *int_sum = sum,
+                                None => *is_double = true,
+                            }
+                        }
+                    }
+                    Some(value::ValueType::DoubleValue(v)) => {
+                        *double_sum += *v;
+                        *is_double = true;
+                    }
+                    _ => {}
+                },
+                FirestoreGroupAccumulator::Avg {
+                    field_name,
+                    sum,
+                    count,
+                } => match firestore_doc_get_field_by_path(doc, field_name) {
+                    Some(value::ValueType::IntegerValue(v)) => {
+                        *sum += *v as f64;
+                        *count += 1;
+                    }
+                    Some(value::ValueType::DoubleValue(v)) => {
+                        *sum += *v;
+                        *count += 1;
+                    }
+                    _ => {}
+                },
+                FirestoreGroupAccumulator::Min { field_name, value } => {
+                    if let Some(candidate) = comparable_field_value(doc, field_name) {
+                        if value
+                            .as_ref()
+                            .map(|current| compare_values(&candidate, current) == Ordering::Less)
+                            .unwrap_or(true)
+                        {
+                            *value = Some(candidate);
+                        }
+                    }
+                }
+                FirestoreGroupAccumulator::Max { field_name, value } => {
+                    if let Some(candidate) = comparable_field_value(doc, field_name) {
+                        if value
+                            .as_ref()
+                            .map(|current| compare_values(&candidate, current) == Ordering::Greater)
+                            .unwrap_or(true)
+                        {
+                            *value = Some(candidate);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> FirestoreValue {
+        FirestoreValue::from_map(self.accumulators.into_iter().map(|(alias, accumulator)| {
+            let value_type = match accumulator {
+                FirestoreGroupAccumulator::Count(count) => {
+                    Some(value::ValueType::IntegerValue(count))
+                }
+                FirestoreGroupAccumulator::Sum {
+                    int_sum,
+                    double_sum,
+                    is_double,
+                    ..
+                } => {
+                    if is_double {
+                        Some(value::ValueType::DoubleValue(double_sum))
+                    } else {
+                        Some(value::ValueType::IntegerValue(int_sum))
+                    }
+                }
+                FirestoreGroupAccumulator::Avg { sum, count, .. } if count > 0 => {
+                    Some(value::ValueType::DoubleValue(sum / count as f64))
+                }
+                FirestoreGroupAccumulator::Avg { .. } => None,
+                FirestoreGroupAccumulator::Min { value, .. }
+                | FirestoreGroupAccumulator::Max { value, .. } => value.and_then(|v| v.value_type),
+            };
+            (
+                alias,
+                FirestoreValue::from(Value {
+                    value_type: value_type.or_else(|| null_value().value_type),
+                }),
+            )
+        }))
+    }
+}
+
+fn comparable_field_value(doc: &Document, field_name: &str) -> Option<Value> {
+    match firestore_doc_get_field_by_path(doc, field_name) {
+        None | Some(value::ValueType::NullValue(_)) => None,
+        Some(value_type) => Some(Value {
+            value_type: Some(value_type.clone()),
+        }),
+    }
+}
+
+/// Compares two values following the Firestore value ordering.
+fn compare_values(left: &Value, right: &Value) -> Ordering {
+    match (&left.value_type, &right.value_type) {
+        (Some(left), Some(right)) => compare_field_values(left, right),
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Less,
+        (Some(_), None) => Ordering::Greater,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FirestoreQueryCollection;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct TestGroupStats {
+        cnt: i64,
+        total: i64,
+        avg_amount: Option<f64>,
+        max_amount: i64,
+    }
+
+    fn test_doc(id: &str, customer: &str, amount: i64) -> Document {
+        let customer: FirestoreValue = customer.into();
+        let amount: FirestoreValue = amount.into();
+        Document {
+            name: format!("projects/test/databases/(default)/documents/orders/{id}"),
+            fields: HashMap::from([
+                ("customer".to_string(), customer.value),
+                ("amount".to_string(), amount.value),
+            ]),
+            create_time: None,
+            update_time: None,
+        }
+    }
+
+    fn test_doc_with_fields(id: &str, fields: Vec<(&str, FirestoreValue)>) -> Document {
+        Document {
+            name: format!("projects/test/databases/(default)/documents/orders/{id}"),
+            fields: fields
+                .into_iter()
+                .map(|(field_name, value)| (field_name.to_string(), value.value))
+                .collect(),
+            create_time: None,
+            update_time: None,
+        }
+    }
+
+    async fn count_groups<K>(
+        docs: Vec<Document>,
+        group_by_field: &str,
+        strategy: FirestoreGroupByStrategy,
+    ) -> Vec<(K, i64)>
+    where
+        for<'de> K: Deserialize<'de>,
+        K: Ord + Send + 'static,
+    {
+        #[derive(Deserialize)]
+        struct Count {
+            cnt: i64,
+        }
+
+        group_docs_stream::<K, Count>(
+            futures::stream::iter(docs.into_iter().map(Ok)).boxed(),
+            vec![group_by_field.to_string()],
+            vec![FirestoreGroupAggregation::new(
+                "cnt".into(),
+                FirestoreGroupAggregationOperator::Count,
+            )],
+            strategy,
+        )
+        .await
+        .unwrap()
+        .map_ok(|(key, count)| (key, count.cnt))
+        .try_collect()
+        .await
+        .unwrap()
+    }
+
+    fn test_aggregations() -> Vec<FirestoreGroupAggregation> {
+        vec![
+            FirestoreGroupAggregation::new("cnt".into(), FirestoreGroupAggregationOperator::Count),
+            FirestoreGroupAggregation::new(
+                "total".into(),
+                FirestoreGroupAggregationOperator::Sum("amount".into()),
+            ),
+            FirestoreGroupAggregation::new(
+                "avg_amount".into(),
+                FirestoreGroupAggregationOperator::Avg("amount".into()),
+            ),
+            FirestoreGroupAggregation::new(
+                "max_amount".into(),
+                FirestoreGroupAggregationOperator::Max("amount".into()),
+            ),
+        ]
+    }
+
+    async fn group_test_docs(
+        docs: Vec<Document>,
+        strategy: FirestoreGroupByStrategy,
+    ) -> Vec<(String, TestGroupStats)> {
+        group_docs_stream(
+            futures::stream::iter(docs.into_iter().map(Ok)).boxed(),
+            vec!["customer".to_string()],
+            test_aggregations(),
+            strategy,
+        )
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn group_by_hash_strategy() {
+        let groups = group_test_docs(
+            vec![
+                test_doc("1", "b", 10),
+                test_doc("2", "a", 5),
+                test_doc("3", "b", 20),
+            ],
+            FirestoreGroupByStrategy::Hash,
+        )
+        .await;
+
+        assert_eq!(
+            groups,
+            vec![
+                (
+                    "a".to_string(),
+                    TestGroupStats {
+                        cnt: 1,
+                        total: 5,
+                        avg_amount: Some(5.0),
+                        max_amount: 5
+                    }
+                ),
+                (
+                    "b".to_string(),
+                    TestGroupStats {
+                        cnt: 2,
+                        total: 30,
+                        avg_amount: Some(15.0),
+                        max_amount: 20
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn group_by_sorted_stream_strategy() {
+        let groups = group_test_docs(
+            vec![
+                test_doc("1", "a", 5),
+                test_doc("2", "b", 10),
+                test_doc("3", "b", 20),
+            ],
+            FirestoreGroupByStrategy::SortedStream,
+        )
+        .await;
+
+        assert_eq!(
+            groups
+                .into_iter()
+                .map(|(k, v)| (k, v.cnt))
+                .collect::<Vec<_>>(),
+            vec![("a".to_string(), 1), ("b".to_string(), 2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn group_by_missing_fields_as_null() {
+        let groups = count_groups::<Option<String>>(
+            vec![
+                test_doc("1", "a", 5),
+                test_doc_with_fields("2", vec![("amount", 10.into())]),
+                test_doc_with_fields("3", vec![("customer", None::<String>.into())]),
+            ],
+            "customer",
+            FirestoreGroupByStrategy::Hash,
+        )
+        .await;
+
+        assert_eq!(groups, vec![(None, 2), (Some("a".to_string()), 1)]);
+    }
+
+    #[tokio::test]
+    async fn group_by_equal_integer_and_double_keys() {
+        for strategy in [
+            FirestoreGroupByStrategy::Hash,
+            FirestoreGroupByStrategy::SortedStream,
+        ] {
+            let groups = count_groups::<i64>(
+                vec![
+                    test_doc_with_fields("1", vec![("amount", 1.into())]),
+                    test_doc_with_fields("2", vec![("amount", 1.0.into())]),
+                    test_doc_with_fields("3", vec![("amount", 2.0.into())]),
+                ],
+                "amount",
+                strategy,
+            )
+            .await;
+
+            assert_eq!(groups, vec![(1, 2), (2, 1)]);
+        }
+    }
+
+    #[test]
+    fn sorted_stream_orders_by_group_fields() {
+        let params = FirestoreGroupByParams::new(
+            FirestoreQueryParams::new(FirestoreQueryCollection::Single("orders".into()))
+                .with_order_by(vec![
+                    FirestoreQueryOrder::new("amount".into(), FirestoreQueryDirection::Descending),
+                    FirestoreQueryOrder::new(
+                        "customer".into(),
+                        FirestoreQueryDirection::Descending,
+                    ),
+                ]),
+            vec!["customer".into()],
+            test_aggregations(),
+        )
+        .with_strategy(FirestoreGroupByStrategy::SortedStream);
+
+        assert_eq!(
+            params.to_query_params().order_by,
+            Some(vec![
+                FirestoreQueryOrder::new("customer".into(), FirestoreQueryDirection::Ascending),
+                FirestoreQueryOrder::new("amount".into(), FirestoreQueryDirection::Descending),
+            ])
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::db::FirestoreDbInner;
+use crate::db::{firestore_prepare_request, FirestoreDbInner};
 use crate::*;
 use async_trait::async_trait;
 use chrono::prelude::*;
@@ -9,7 +9,6 @@ use futures::StreamExt;
 use futures::TryFutureExt;
 use futures::TryStreamExt;
 use gcloud_sdk::google::firestore::v1::*;
-use rand::Rng;
 use rsb_derive::*;
 use serde::Deserialize;
 use std::future;
@@ -28,6 +27,10 @@ pub struct FirestoreListDocParams {
     pub page_token: Option<String>,
     pub order_by: Option<Vec<FirestoreQueryOrder>>,
     pub return_only_fields: Option<Vec<String>>,
+    pub consistency_selector: Option<FirestoreConsistencySelector>,
+    /// Lists the missing documents too: documents that don't exist, but have subcollections.
+    /// Missing documents have no fields and no create and update times.
+    pub show_missing: Option<bool>,
 }
 
 #[derive(Debug, PartialEq, Clone, Builder)]
@@ -43,6 +46,7 @@ pub struct FirestoreListCollectionIdsParams {
     #[default = "100"]
     pub page_size: usize,
     pub page_token: Option<String>,
+    pub consistency_selector: Option<FirestoreConsistencySelector>,
 }
 
 #[derive(Debug, PartialEq, Clone, Builder)]
@@ -108,7 +112,9 @@ impl FirestoreListingSupport for FirestoreDb {
             Level::DEBUG,
             "Firestore ListDocs",
             "/firestore/collection_name" = params.collection_id.as_str(),
-            "/firestore/response_time" = field::Empty
+            "/firestore/response_time" = field::Empty,
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty
         );
 
         self.list_doc_with_retries(params, 0, span).await
@@ -130,7 +136,7 @@ impl FirestoreListingSupport for FirestoreDb {
             future::ready(match doc_res {
                 Ok(doc) => Some(doc),
                 Err(err) => {
-                    error!(%err, "Error occurred while consuming documents.");
+                    error!(%err, "Error occurred while consuming collection IDs.");
                     None
                 }
             })
@@ -182,7 +188,9 @@ impl FirestoreListingSupport for FirestoreDb {
         let span = span!(
             Level::DEBUG,
             "Firestore ListCollectionIds",
-            "/firestore/response_time" = field::Empty
+            "/firestore/response_time" = field::Empty,
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty
         );
 
         self.list_collection_ids_with_retries(params, 0, &span)
@@ -209,33 +217,50 @@ impl FirestoreListingSupport for FirestoreDb {
         &self,
         params: FirestoreListCollectionIdsParams,
     ) -> FirestoreResult<BoxStream<FirestoreResult<String>>> {
+        // The settings of the operation are captured, since the pages are requested
+        // while the stream is consumed
+        let timeout = self.current_timeout();
+        let retry_policy = FirestoreRetryPolicy::current(&self.inner.options);
         let stream: BoxStream<FirestoreResult<String>> = Box::pin(
-            futures::stream::unfold(Some(params), move |maybe_params| async move {
-                if let Some(params) = maybe_params {
-                    let span = span!(
-                        Level::DEBUG,
-                        "Firestore Streaming ListCollections",
-                        "/firestore/response_time" = field::Empty
-                    );
+            futures::stream::unfold(Some(params), move |maybe_params| {
+                let retry_policy = retry_policy.clone();
+                async move {
+                    if let Some(params) = maybe_params {
+                        let span = span!(
+                            Level::DEBUG,
+                            "Firestore Streaming ListCollections",
+                            "/firestore/response_time" = field::Empty,
+                            "/firestore/operation" = field::Empty,
+                            "/firestore/document_count" = field::Empty
+                        );
 
-                    match self
-                        .list_collection_ids_with_retries(params.clone(), 0, &span)
+                        match FirestoreRetryPolicy::scope(
+                            Some(retry_policy),
+                            firestore_timeout_scope(
+                                timeout,
+                                self.list_collection_ids_with_retries(params.clone(), 0, &span),
+                            ),
+                        )
                         .await
-                    {
-                        Ok(results) => {
-                            if let Some(next_page_token) = results.page_token.clone() {
-                                Some((Ok(results), Some(params.with_page_token(next_page_token))))
-                            } else {
-                                Some((Ok(results), None))
+                        {
+                            Ok(results) => {
+                                if let Some(next_page_token) = results.page_token.clone() {
+                                    Some((
+                                        Ok(results),
+                                        Some(params.with_page_token(next_page_token)),
+                                    ))
+                                } else {
+                                    Some((Ok(results), None))
+                                }
+                            }
+                            Err(err) => {
+                                error!(%err, "Error occurred while consuming documents.");
+                                Some((Err(err), None))
                             }
                         }
-                        Err(err) => {
-                            error!(%err, "Error occurred while consuming documents.");
-                            Some((Err(err), None))
-                        }
+                    } else {
+                        None
                     }
-                } else {
-                    None
                 }
             })
             .flat_map(|doc_res| {
@@ -282,12 +307,11 @@ impl FirestoreDb {
                 .return_only_fields
                 .map(|masks| DocumentMask { field_paths: masks }),
             consistency_selector: self
-                .session_params
-                .consistency_selector
+                .read_consistency_selector(params.consistency_selector.as_ref())?
                 .as_ref()
                 .map(|selector| selector.try_into())
                 .transpose()?,
-            show_missing: false,
+            show_missing: params.show_missing.unwrap_or(false),
         })
     }
 
@@ -298,10 +322,14 @@ impl FirestoreDb {
         span: Span,
     ) -> BoxFuture<'b, FirestoreResult<FirestoreListDocResult>> {
         match self.create_list_doc_request(params) {
-            Ok(list_request) => {
-                Self::list_doc_with_retries_inner(self.inner.clone(), list_request, retries, span)
-                    .boxed()
-            }
+            Ok(list_request) => Self::list_doc_with_retries_inner(
+                self.inner.clone(),
+                list_request,
+                self.current_timeout(),
+                retries,
+                span,
+            )
+            .boxed(),
             Err(err) => futures::future::err(err).boxed(),
         }
     }
@@ -309,21 +337,38 @@ impl FirestoreDb {
     fn list_doc_with_retries_inner<'b>(
         db_inner: Arc<FirestoreDbInner>,
         list_request: ListDocumentsRequest,
+        timeout: Option<std::time::Duration>,
         retries: usize,
         span: Span,
     ) -> BoxFuture<'b, FirestoreResult<FirestoreListDocResult>> {
         async move {
             let begin_utc: DateTime<Utc> = Utc::now();
+            let metrics = FirestoreOperationRecorder::start(
+                db_inner.metrics_sink.clone(),
+                FirestoreOperationKind::ListDocuments,
+                Some(list_request.collection_id.as_str()),
+                &span,
+            );
 
-            match db_inner.client.get()
-                .list_documents(
-                    gcloud_sdk::tonic::Request::new(list_request.clone())
-                )
-                .map_err(|e| e.into())
-                .await
-            {
+            let request = firestore_prepare_request(
+                &db_inner,
+                firestore_request_with_timeout(list_request.clone(), timeout),
+            );
+            let response = match request {
+                Ok(request) => db_inner.client.get()
+                    .list_documents(request)
+                    .map_err(|e| e.into())
+                    .await,
+                Err(err) => Err(err),
+            };
+
+            match response {
                 Ok(listing_response) => {
                     let list_inner = listing_response.into_inner();
+                    firestore_notify_response_middlewares(
+                        &db_inner.middlewares,
+                        FirestoreApiResponse::ListDocuments(&list_inner),
+                    );
                     let result = FirestoreListDocResult::new(list_inner.documents).opt_page_token(
                         if !list_inner.next_page_token.is_empty() {
                             Some(list_inner.next_page_token)
@@ -331,6 +376,7 @@ impl FirestoreDb {
                             None
                         },
                     );
+                    metrics.record_success(result.documents.len());
                     let end_query_utc: DateTime<Utc> = Utc::now();
                     let listing_duration = end_query_utc.signed_duration_since(begin_utc);
 
@@ -349,28 +395,30 @@ impl FirestoreDb {
 
                     Ok(result)
                 }
-                Err(err) => match err {
-                    FirestoreError::DatabaseError(ref db_err)
-                    if db_err.retry_possible && retries < db_inner.options.max_retries =>
+                Err(err) => {
+                    metrics.record_failure(&err);
+                    let retry_policy = FirestoreRetryPolicy::current(&db_inner.options);
+                    match err {
+                        FirestoreError::DatabaseError(ref db_err)
+                            if retry_policy.should_retry(&err, retries, true) =>
                         {
-                            let sleep_duration = tokio::time::Duration::from_millis(
-                                rand::rng().random_range(0..2u64.pow(retries as u32) * 1000 + 1),
-                            );
+                            let sleep_duration = retry_policy.backoff(retries);
 
                             warn!(
                                 err = %db_err,
                                 current_retry = retries + 1,
-                                max_retries = db_inner.options.max_retries,
+                                max_attempts = retry_policy.max_attempts,
                                 delay = sleep_duration.as_millis(),
                                 "Failed to list documents. Retrying up to the specified number of times.",
                             );
 
                             tokio::time::sleep(sleep_duration).await;
 
-                            Self::list_doc_with_retries_inner(db_inner, list_request, retries + 1, span).await
+                            Self::list_doc_with_retries_inner(db_inner, list_request, timeout, retries + 1, span).await
                         }
-                    _ => Err(err),
-                },
+                        _ => Err(err),
+                    }
+                }
             }
         }
             .boxed()
@@ -389,47 +437,63 @@ impl FirestoreDb {
             }
         }
         let list_request = self.create_list_doc_request(params.clone())?;
-        Self::stream_list_doc_with_retries_inner(self.inner.clone(), list_request)
+        Self::stream_list_doc_with_retries_inner(
+            self.inner.clone(),
+            list_request,
+            self.current_timeout(),
+            FirestoreRetryPolicy::current(&self.inner.options),
+        )
     }
 
     fn stream_list_doc_with_retries_inner<'b>(
         db_inner: Arc<FirestoreDbInner>,
         list_request: ListDocumentsRequest,
+        timeout: Option<std::time::Duration>,
+        retry_policy: FirestoreRetryPolicy,
     ) -> FirestoreResult<BoxStream<'b, FirestoreResult<Document>>> {
         let stream: BoxStream<FirestoreResult<Document>> = Box::pin(
             futures::stream::unfold(
                 (db_inner, Some(list_request)),
-                move |(db_inner, list_request)| async move {
-                    if let Some(mut list_request) = list_request {
-                        let span = span!(
-                            Level::DEBUG,
-                            "Firestore Streaming ListDocs",
-                            "/firestore/collection_name" = list_request.collection_id.as_str(),
-                            "/firestore/response_time" = field::Empty
-                        );
-                        match Self::list_doc_with_retries_inner(
-                            db_inner.clone(),
-                            list_request.clone(),
-                            0,
-                            span,
-                        )
-                        .await
-                        {
-                            Ok(results) => {
-                                if let Some(next_page_token) = results.page_token.clone() {
-                                    list_request.page_token = next_page_token;
-                                    Some((Ok(results), (db_inner, Some(list_request))))
-                                } else {
-                                    Some((Ok(results), (db_inner, None)))
+                move |(db_inner, list_request)| {
+                    let retry_policy = retry_policy.clone();
+                    async move {
+                        if let Some(mut list_request) = list_request {
+                            let span = span!(
+                                Level::DEBUG,
+                                "Firestore Streaming ListDocs",
+                                "/firestore/collection_name" = list_request.collection_id.as_str(),
+                                "/firestore/response_time" = field::Empty,
+                                "/firestore/operation" = field::Empty,
+                                "/firestore/document_count" = field::Empty
+                            );
+                            match FirestoreRetryPolicy::scope(
+                                Some(retry_policy),
+                                Self::list_doc_with_retries_inner(
+                                    db_inner.clone(),
+                                    list_request.clone(),
+                                    timeout,
+                                    0,
+                                    span,
+                                ),
+                            )
+                            .await
+                            {
+                                Ok(results) => {
+                                    if let Some(next_page_token) = results.page_token.clone() {
+                                        list_request.page_token = next_page_token;
+                                        Some((Ok(results), (db_inner, Some(list_request))))
+                                    } else {
+                                        Some((Ok(results), (db_inner, None)))
+                                    }
+                                }
+                                Err(err) => {
+                                    error!(%err, "Error occurred while consuming documents.");
+                                    Some((Err(err), (db_inner, None)))
                                 }
                             }
-                            Err(err) => {
-                                error!(%err, "Error occurred while consuming documents.");
-                                Some((Err(err), (db_inner, None)))
-                            }
+                        } else {
+                            None
                         }
-                    } else {
-                        None
                     }
                 },
             )
@@ -452,7 +516,7 @@ impl FirestoreDb {
         &self,
         params: &FirestoreListCollectionIdsParams,
     ) -> FirestoreResult<gcloud_sdk::tonic::Request<ListCollectionIdsRequest>> {
-        Ok(gcloud_sdk::tonic::Request::new(ListCollectionIdsRequest {
+        self.create_api_request(ListCollectionIdsRequest {
             parent: params
                 .parent
                 .as_ref()
@@ -461,12 +525,11 @@ impl FirestoreDb {
             page_size: params.page_size as i32,
             page_token: params.page_token.clone().unwrap_or_default(),
             consistency_selector: self
-                .session_params
-                .consistency_selector
+                .read_consistency_selector(params.consistency_selector.as_ref())?
                 .as_ref()
                 .map(|selector| selector.try_into())
                 .transpose()?,
-        }))
+        })
     }
 
     fn list_collection_ids_with_retries<'a>(
@@ -478,6 +541,8 @@ impl FirestoreDb {
         async move {
             let list_request = self.create_list_collection_ids_request(&params)?;
             let begin_utc: DateTime<Utc> = Utc::now();
+            let metrics =
+                self.record_operation(FirestoreOperationKind::ListCollectionIds, None, span);
 
             match self
                 .client()
@@ -488,12 +553,16 @@ impl FirestoreDb {
             {
                 Ok(listing_response) => {
                     let list_inner = listing_response.into_inner();
+                    self.notify_response_middlewares(FirestoreApiResponse::ListCollectionIds(
+                        &list_inner,
+                    ));
                     let result = FirestoreListCollectionIdsResult::new(list_inner.collection_ids)
                         .opt_page_token(if !list_inner.next_page_token.is_empty() {
                             Some(list_inner.next_page_token)
                         } else {
                             None
                         });
+                    metrics.record_success(result.collection_ids.len());
                     let end_query_utc: DateTime<Utc> = Utc::now();
                     let listing_duration = end_query_utc.signed_duration_since(begin_utc);
 
@@ -510,17 +579,18 @@ impl FirestoreDb {
 
                     Ok(result)
                 }
-                Err(err) => match err {
-                    FirestoreError::DatabaseError(ref db_err)
-                    if db_err.retry_possible && retries < self.inner.options.max_retries =>
+                Err(err) => {
+                    metrics.record_failure(&err);
+                    let retry_policy = FirestoreRetryPolicy::current(&self.inner.options);
+                    match err {
+                        FirestoreError::DatabaseError(ref db_err)
+                            if retry_policy.should_retry(&err, retries, true) =>
                         {
-                            let sleep_duration = tokio::time::Duration::from_millis(
-                                rand::rng().random_range(0..2u64.pow(retries as u32) * 1000 + 1),
-                            );
+                            let sleep_duration = retry_policy.backoff(retries);
                             warn!(
                                 err = %db_err,
                                 current_retry = retries + 1,
-                                max_retries = self.inner.options.max_retries,
+                                max_attempts = retry_policy.max_attempts,
                                 delay = sleep_duration.as_millis(),
                                 "Failed to list collection IDs. Retrying up to the specified number of times.",
                             );
@@ -530,8 +600,9 @@ impl FirestoreDb {
                             self.list_collection_ids_with_retries(params, retries + 1, span)
                                 .await
                         }
-                    _ => Err(err),
-                },
+                        _ => Err(err),
+                    }
+                }
             }
         }
             .boxed()
@@ -544,6 +615,9 @@ impl FirestoreDb {
         params: &FirestoreListDocParams,
     ) -> FirestoreResult<FirestoreCachedValue<BoxStream<'b, FirestoreResult<FirestoreDocument>>>>
     {
+        if self.reads_at_point_in_time(params.consistency_selector.as_ref()) {
+            return Ok(FirestoreCachedValue::SkipCache);
+        }
         if let FirestoreDbSessionCacheMode::ReadCachedOnly(ref cache) =
             self.session_params.cache_mode
         {
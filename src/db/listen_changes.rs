@@ -1,17 +1,25 @@
-use crate::db::safe_document_path;
+use crate::db::{
+    firestore_shutdown_requested, is_unauthenticated_error, safe_document_path, FirestoreTaskScopes,
+};
 use crate::errors::*;
 use crate::timestamp_utils::to_timestamp;
-use crate::{FirestoreDb, FirestoreQueryParams, FirestoreResult, FirestoreResumeStateStorage};
+use crate::{
+    FirestoreApiResponse, FirestoreDb, FirestoreListenerHealth, FirestoreListenerStatus,
+    FirestoreListenerStatusReporter, FirestoreOperationKind, FirestoreQueryParams, FirestoreResult,
+    FirestoreResumeStateStorage, FirestoreRetryPolicy, FirestoreShutdownSignal,
+};
 pub use async_trait::async_trait;
 use chrono::prelude::*;
+use futures::future::BoxFuture;
 use futures::stream::BoxStream;
+use futures::Stream;
 use futures::StreamExt;
 use futures::TryFutureExt;
 use futures::TryStreamExt;
 use gcloud_sdk::google::firestore::v1::*;
 use rsb_derive::*;
 pub use rvstruct::ValueStruct;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
@@ -55,12 +63,60 @@ pub enum FirestoreListenerTargetResumeType {
     ReadTime(DateTime<Utc>),
 }
 
+/// A change of the targets of an open listen stream.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub enum FirestoreListenerTargetUpdate {
+    /// Starts listening to the target. Target IDs must be unique among the active targets,
+    /// so a target is replaced by removing it first.
+    Add(FirestoreListenerTargetParams),
+    /// Stops listening to the target.
+    Remove(FirestoreListenerTarget),
+}
+
 #[async_trait]
 pub trait FirestoreListenSupport {
     async fn listen_doc_changes<'a, 'b>(
         &'a self,
         targets: Vec<FirestoreListenerTargetParams>,
     ) -> FirestoreResult<BoxStream<'b, FirestoreResult<ListenResponse>>>;
+
+    /// Listens to the targets, applying the target updates to the open stream.
+    ///
+    /// The default implementation ignores the updates.
+    async fn listen_doc_changes_with_updates<'a, 'b>(
+        &'a self,
+        targets: Vec<FirestoreListenerTargetParams>,
+        _updates: futures::channel::mpsc::UnboundedReceiver<FirestoreListenerTargetUpdate>,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<ListenResponse>>>
+    where
+        Self: Sync,
+    {
+        self.listen_doc_changes(targets).await
+    }
+
+    /// Refreshes the credentials before restarting a listen stream failed with `UNAUTHENTICATED`.
+    ///
+    /// The default implementation does nothing.
+    async fn refresh_credentials(&self)
+    where
+        Self: Sync,
+    {
+    }
+
+    /// Returns whether the credentials are refreshed when a listen stream fails with `UNAUTHENTICATED`.
+    ///
+    /// The default implementation doesn't refresh them.
+    fn reauthenticate(&self) -> bool {
+        false
+    }
+
+    /// Returns the shutdown signal listeners drain their targets on.
+    ///
+    /// The default implementation has no signal.
+    fn shutdown_signal(&self) -> Option<FirestoreShutdownSignal> {
+        None
+    }
 }
 
 #[async_trait]
@@ -68,19 +124,77 @@ impl FirestoreListenSupport for FirestoreDb {
     async fn listen_doc_changes<'a, 'b>(
         &'a self,
         targets: Vec<FirestoreListenerTargetParams>,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<ListenResponse>>> {
+        // The updates stream ends right away, since the sender is dropped
+        let (_, updates) = futures::channel::mpsc::unbounded();
+        self.listen_doc_changes_with_updates(targets, updates).await
+    }
+
+    async fn refresh_credentials(&self) {
+        FirestoreDb::refresh_credentials(self).await
+    }
+
+    fn reauthenticate(&self) -> bool {
+        FirestoreRetryPolicy::current(self.get_options()).reauthenticate
+    }
+
+    fn shutdown_signal(&self) -> Option<FirestoreShutdownSignal> {
+        Some(FirestoreDb::shutdown_signal(self))
+    }
+
+    async fn listen_doc_changes_with_updates<'a, 'b>(
+        &'a self,
+        targets: Vec<FirestoreListenerTargetParams>,
+        updates: futures::channel::mpsc::UnboundedReceiver<FirestoreListenerTargetUpdate>,
     ) -> FirestoreResult<BoxStream<'b, FirestoreResult<ListenResponse>>> {
         let listen_requests = targets
             .into_iter()
-            .map(|target_params| self.create_listen_request(target_params))
+            .map(|target_params| {
+                let mut request = self.create_listen_request(target_params)?;
+                self.apply_message_middlewares(&mut request)?;
+                Ok(request)
+            })
             .collect::<FirestoreResult<Vec<ListenRequest>>>()?;
 
         let request = gcloud_sdk::tonic::Request::new(
-            futures::stream::iter(listen_requests).chain(futures::stream::pending()),
+            futures::stream::iter(listen_requests)
+                .chain(self.create_listen_update_requests(updates)),
         );
 
-        let response = self.client().get().listen(request).await?;
+        let span = span!(
+            Level::DEBUG,
+            "Firestore Listen",
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty
+        );
+        let metrics = self.record_operation(FirestoreOperationKind::Listen, None, &span);
+        let response = self
+            .client()
+            .get()
+            .listen(request)
+            .await
+            .map_err(FirestoreError::from);
 
-        Ok(response.into_inner().map_err(|e| e.into()).boxed())
+        let db = self.clone();
+        metrics.record_stream_result(
+            response.map(|response| {
+                response
+                    .into_inner()
+                    .map_err(|e| e.into())
+                    .inspect_ok(move |response| {
+                        db.notify_response_middlewares(FirestoreApiResponse::Listen(response))
+                    })
+                    .boxed()
+            }),
+            |response| {
+                usize::from(matches!(
+                    response.response_type,
+                    Some(listen_response::ResponseType::DocumentChange(_))
+                        | Some(listen_response::ResponseType::DocumentDelete(_))
+                        | Some(listen_response::ResponseType::DocumentRemove(_))
+                ))
+            },
+        )
     }
 }
 
@@ -147,7 +261,7 @@ impl TryFrom<i32> for FirestoreListenerTarget {
     }
 }
 
-#[derive(Clone, Debug, ValueStruct)]
+#[derive(Clone, Debug, Eq, PartialEq, ValueStruct)]
 pub struct FirestoreListenerToken(Vec<u8>);
 
 impl FirestoreDb {
@@ -173,6 +287,51 @@ impl FirestoreDb {
         FirestoreListener::new(self.clone(), storage, params).await
     }
 
+    fn create_listen_update_requests(
+        &self,
+        updates: futures::channel::mpsc::UnboundedReceiver<FirestoreListenerTargetUpdate>,
+    ) -> impl Stream<Item = ListenRequest> + Send + Sync + 'static {
+        let db = self.clone();
+        updates
+            .filter_map(move |update| {
+                let request = db.create_listen_update_request(update);
+                if let Err(ref err) = request {
+                    error!(%err, "Invalid listener target update. Skipping...");
+                }
+                futures::future::ready(request.ok())
+            })
+            .chain(futures::stream::pending())
+    }
+
+    fn create_listen_update_request(
+        &self,
+        update: FirestoreListenerTargetUpdate,
+    ) -> FirestoreResult<ListenRequest> {
+        let mut request = match update {
+            FirestoreListenerTargetUpdate::Add(target_params) => {
+                self.create_listen_request(target_params)
+            }
+            FirestoreListenerTargetUpdate::Remove(target) => {
+                self.create_remove_target_request(target)
+            }
+        }?;
+        self.apply_message_middlewares(&mut request)?;
+        Ok(request)
+    }
+
+    fn create_remove_target_request(
+        &self,
+        target: FirestoreListenerTarget,
+    ) -> FirestoreResult<ListenRequest> {
+        Ok(ListenRequest {
+            database: self.get_database_path().to_string(),
+            labels: HashMap::new(),
+            target_change: Some(listen_request::TargetChange::RemoveTarget(
+                target.try_into()?,
+            )),
+        })
+    }
+
     fn create_listen_request(
         &self,
         target_params: FirestoreListenerTargetParams,
@@ -192,7 +351,9 @@ impl FirestoreDb {
                                 .unwrap_or_else(|| self.get_documents_path())
                                 .clone(),
                             query_type: Some(target::query_target::QueryType::StructuredQuery(
-                                query_params.try_into()?,
+                                query_params
+                                    .resolve_document_ids(self.get_documents_path())
+                                    .try_into()?,
                             )),
                         })
                     }
@@ -236,8 +397,54 @@ pub type FirestoreListenEvent = listen_response::ResponseType;
 #[derive(Debug, Clone, Eq, PartialEq, Builder)]
 pub struct FirestoreListenerParams {
     pub retry_delay: Option<std::time::Duration>,
+    pub error_policy: Option<FirestoreListenerErrorPolicy>,
+    /// Passes the target changes carrying resume tokens to the callback as well,
+    /// e.g. to observe the `CURRENT` changes and the consistency points of the targets.
+    /// The listener stores the resume tokens itself, so they are kept internal by default.
+    #[default = "false"]
+    pub resume_token_events: bool,
 }
 
+/// What a listener does with a change the callback failed to handle.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FirestoreListenerFailedChangeAction {
+    /// Reopens the listen stream from the last stored resume token,
+    /// so the change is received and handled again.
+    Reconnect,
+    /// Skips the change and continues with the next one.
+    Skip,
+    /// Passes the change to the deadletter handler of the listener and continues with the next one.
+    Deadletter,
+}
+
+/// How a listener handles the errors of the callback, e.g. for documents that fail to deserialize.
+#[derive(Debug, Clone, Eq, PartialEq, Builder)]
+pub struct FirestoreListenerErrorPolicy {
+    /// The number of times the callback is called again with the failed change.
+    #[default = "0"]
+    pub max_retries: usize,
+    /// The delay before calling the callback again.
+    pub retry_delay: Option<std::time::Duration>,
+    /// What to do with the change when there are no retries left.
+    #[default = "FirestoreListenerFailedChangeAction::Reconnect"]
+    pub on_failure: FirestoreListenerFailedChangeAction,
+}
+
+/// A change the listener callback failed to handle.
+#[derive(Debug, Clone)]
+pub struct FirestoreListenerDeadletter {
+    pub event: FirestoreListenEvent,
+    /// The error of the last attempt.
+    pub error: String,
+    /// The number of times the callback was called with the change.
+    pub attempts: usize,
+}
+
+/// A handler of the changes the listener callback failed to handle.
+pub type FirestoreListenerDeadletterHandler = Arc<
+    dyn Fn(FirestoreListenerDeadletter) -> BoxFuture<'static, AnyBoxedErrResult<()>> + Send + Sync,
+>;
+
 pub struct FirestoreListener<D, S>
 where
     D: FirestoreListenSupport,
@@ -250,6 +457,9 @@ where
     shutdown_flag: Arc<AtomicBool>,
     shutdown_handle: Option<JoinHandle<()>>,
     shutdown_writer: Option<Arc<UnboundedSender<i8>>>,
+    target_updates_writer: Option<UnboundedSender<FirestoreListenerTargetUpdate>>,
+    deadletter_handler: Option<FirestoreListenerDeadletterHandler>,
+    status: FirestoreListenerStatusReporter,
 }
 
 impl<D, S> FirestoreListener<D, S>
@@ -270,18 +480,192 @@ where
             shutdown_flag: Arc::new(AtomicBool::new(false)),
             shutdown_handle: None,
             shutdown_writer: None,
+            target_updates_writer: None,
+            deadletter_handler: None,
+            status: FirestoreListenerStatusReporter::default(),
         })
     }
 
+    /// Sets the handler of the changes the callback failed to handle,
+    /// used with [`FirestoreListenerFailedChangeAction::Deadletter`].
+    ///
+    /// If the handler fails, the listen stream is reopened and the change is received again.
+    pub fn set_deadletter_handler<FN, F>(&mut self, handler: FN)
+    where
+        FN: Fn(FirestoreListenerDeadletter) -> F + Send + Sync + 'static,
+        F: Future<Output = AnyBoxedErrResult<()>> + Send + 'static,
+    {
+        self.deadletter_handler = Some(Arc::new(move |deadletter| {
+            Box::pin(handler(deadletter)) as BoxFuture<_>
+        }));
+    }
+
+    /// Sets the handler of the lifecycle events of the listen stream, e.g. to export them as metrics.
+    /// The handler is called on the listener task, so it shouldn't block.
+    ///
+    /// It has to be set before the listener is started.
+    pub fn set_status_handler<FN>(&mut self, handler: FN)
+    where
+        FN: Fn(FirestoreListenerStatus) + Send + Sync + 'static,
+    {
+        self.status = self.status.clone().with_handler(Some(Arc::new(handler)));
+    }
+
+    /// Returns the current health of the listener, e.g. for readiness probes.
+    pub fn health(&self) -> FirestoreListenerHealth {
+        self.status.health()
+    }
+
+    /// Passes the target changes carrying resume tokens to the callback,
+    /// for the consumers tracking the state of the targets.
+    pub(crate) fn enable_resume_token_events(&mut self) {
+        self.listener_params.resume_token_events = true;
+    }
+
+    /// Adds the target. If the listener is already started, the target is added to the open stream.
     pub fn add_target(
         &mut self,
         target_params: FirestoreListenerTargetParams,
     ) -> FirestoreResult<()> {
         target_params.validate()?;
+        self.send_target_update(FirestoreListenerTargetUpdate::Add(target_params.clone()));
         self.targets.push(target_params);
         Ok(())
     }
 
+    /// Adds or replaces the target with the same ID.
+    /// If the listener is already started, the target is replaced without reopening the stream.
+    pub fn update_target(
+        &mut self,
+        target_params: FirestoreListenerTargetParams,
+    ) -> FirestoreResult<()> {
+        target_params.validate()?;
+        self.send_target_update(FirestoreListenerTargetUpdate::Add(target_params.clone()));
+        match self
+            .targets
+            .iter_mut()
+            .find(|existing| existing.target == target_params.target)
+        {
+            Some(existing) => *existing = target_params,
+            None => self.targets.push(target_params),
+        }
+        Ok(())
+    }
+
+    /// Stops listening to the target.
+    pub fn remove_target(&mut self, target: &FirestoreListenerTarget) -> FirestoreResult<()> {
+        self.send_target_update(FirestoreListenerTargetUpdate::Remove(target.clone()));
+        self.targets.retain(|existing| existing.target != *target);
+        Ok(())
+    }
+
+    /// Adds the documents to the target listening to documents by IDs.
+    ///
+    /// A started listener receives the current state of all the documents of the target again,
+    /// since the target is replaced on the open stream.
+    pub fn add_target_documents<DS, I>(
+        &mut self,
+        target: &FirestoreListenerTarget,
+        document_ids: I,
+    ) -> FirestoreResult<()>
+    where
+        DS: AsRef<str>,
+        I: IntoIterator<Item = DS>,
+    {
+        let mut collection_documents = self.target_documents(target)?;
+        for document_id in document_ids {
+            let document_id = document_id.as_ref();
+            if !collection_documents
+                .documents
+                .iter()
+                .any(|existing| existing == document_id)
+            {
+                collection_documents.documents.push(document_id.to_string());
+            }
+        }
+        self.update_target_documents(target, collection_documents)
+    }
+
+    /// Removes the documents from the target listening to documents by IDs.
+    /// The target is removed when no documents are left.
+    pub fn remove_target_documents<DS, I>(
+        &mut self,
+        target: &FirestoreListenerTarget,
+        document_ids: I,
+    ) -> FirestoreResult<()>
+    where
+        DS: AsRef<str>,
+        I: IntoIterator<Item = DS>,
+    {
+        let mut collection_documents = self.target_documents(target)?;
+        let removed_ids: Vec<DS> = document_ids.into_iter().collect();
+        collection_documents.documents.retain(|existing| {
+            !removed_ids
+                .iter()
+                .any(|removed_id| removed_id.as_ref() == existing)
+        });
+        if collection_documents.documents.is_empty() {
+            self.remove_target(target)
+        } else {
+            self.update_target_documents(target, collection_documents)
+        }
+    }
+
+    fn target_documents(
+        &self,
+        target: &FirestoreListenerTarget,
+    ) -> FirestoreResult<FirestoreCollectionDocuments> {
+        match self
+            .targets
+            .iter()
+            .find(|existing| existing.target == *target)
+            .map(|existing| &existing.target_type)
+        {
+            Some(FirestoreTargetType::Documents(collection_documents)) => {
+                Ok(collection_documents.clone())
+            }
+            _ => Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "target_id".to_string(),
+                    format!(
+                        "Listener target {} isn't a target listening to documents by IDs",
+                        target.value()
+                    ),
+                )),
+            )),
+        }
+    }
+
+    fn update_target_documents(
+        &mut self,
+        target: &FirestoreListenerTarget,
+        collection_documents: FirestoreCollectionDocuments,
+    ) -> FirestoreResult<()> {
+        let target_params = self
+            .targets
+            .iter()
+            .find(|existing| existing.target == *target)
+            .cloned()
+            .map(|existing| FirestoreListenerTargetParams {
+                target_type: FirestoreTargetType::Documents(collection_documents),
+                // Without resume tokens the documents added to the target are delivered too
+                resume_type: None,
+                ..existing
+            });
+        match target_params {
+            Some(target_params) => self.update_target(target_params),
+            None => Ok(()),
+        }
+    }
+
+    fn send_target_update(&self, update: FirestoreListenerTargetUpdate) {
+        if let Some(target_updates_writer) = &self.target_updates_writer {
+            if target_updates_writer.send(update).is_err() {
+                debug!("Firestore listener is stopped. The target update is applied on the next start.");
+            }
+        }
+    }
+
     pub async fn start<FN, F>(&mut self, cb: FN) -> FirestoreResult<()>
     where
         FN: Fn(FirestoreListenEvent) -> F + Send + Sync + 'static,
@@ -292,6 +676,22 @@ where
             "Starting a Firestore listener for targets...",
         );
 
+        if self.deadletter_handler.is_none()
+            && self
+                .listener_params
+                .error_policy
+                .as_ref()
+                .map(|policy| policy.on_failure)
+                == Some(FirestoreListenerFailedChangeAction::Deadletter)
+        {
+            return Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "error_policy".to_string(),
+                    "Deadletter error policy requires a deadletter handler".to_string(),
+                )),
+            ));
+        }
+
         let mut initial_states: HashMap<FirestoreListenerTarget, FirestoreListenerTargetParams> =
             HashMap::new();
         for target_params in &self.targets {
@@ -328,16 +728,24 @@ where
 
         let (tx, rx): (UnboundedSender<i8>, UnboundedReceiver<i8>) =
             tokio::sync::mpsc::unbounded_channel();
+        let (target_updates_tx, target_updates_rx) = tokio::sync::mpsc::unbounded_channel();
 
         self.shutdown_writer = Some(Arc::new(tx));
-        self.shutdown_handle = Some(tokio::spawn(Self::listener_loop(
-            self.db.clone(),
-            self.storage.clone(),
-            self.shutdown_flag.clone(),
-            initial_states,
-            self.listener_params.clone(),
-            rx,
-            cb,
+        self.target_updates_writer = Some(target_updates_tx);
+        // The retry policy of the operation starting the listener applies to the listener as well
+        self.shutdown_handle = Some(tokio::spawn(FirestoreTaskScopes::capture().scope(
+            Self::listener_loop(
+                self.db.clone(),
+                self.storage.clone(),
+                self.shutdown_flag.clone(),
+                initial_states,
+                self.listener_params.clone(),
+                rx,
+                target_updates_rx,
+                self.deadletter_handler.clone(),
+                self.status.clone(),
+                cb,
+            ),
         )));
         Ok(())
     }
@@ -348,6 +756,7 @@ where
         if let Some(shutdown_writer) = self.shutdown_writer.take() {
             shutdown_writer.send(1).ok();
         }
+        self.target_updates_writer = None;
         if let Some(signaller) = self.shutdown_handle.take() {
             if let Err(err) = signaller.await {
                 warn!(%err, "Firestore listener exit error!");
@@ -364,6 +773,9 @@ where
         mut targets_state: HashMap<FirestoreListenerTarget, FirestoreListenerTargetParams>,
         listener_params: FirestoreListenerParams,
         mut shutdown_receiver: UnboundedReceiver<i8>,
+        mut target_updates_receiver: UnboundedReceiver<FirestoreListenerTargetUpdate>,
+        deadletter_handler: Option<FirestoreListenerDeadletterHandler>,
+        status: FirestoreListenerStatusReporter,
         cb: FN,
     ) where
         D: FirestoreListenSupport + Clone + Send + Sync,
@@ -373,105 +785,357 @@ where
         let effective_delay = listener_params
             .retry_delay
             .unwrap_or_else(|| std::time::Duration::from_secs(5));
+        let error_policy = listener_params
+            .error_policy
+            .unwrap_or_else(FirestoreListenerErrorPolicy::new);
+        let resume_token_events = listener_params.resume_token_events;
+
+        let shutdown_signal = db.shutdown_signal();
+        // The shutdown of the client waits for the listener to remove its targets
+        let _drain_guard = shutdown_signal
+            .as_ref()
+            .map(|shutdown_signal| shutdown_signal.drain_guard());
 
         while !shutdown_flag.load(Ordering::Relaxed) {
+            if shutdown_signal
+                .as_ref()
+                .map_or(false, |shutdown_signal| shutdown_signal.is_requested())
+            {
+                debug!("Firestore client is shutting down. Exiting from listener...");
+                shutdown_flag.store(true, Ordering::Relaxed);
+                break;
+            }
+
             debug!(
                 num_targets = targets_state.len(),
                 "Start listening on targets..."
             );
 
+            let (stream_updates_writer, stream_updates_receiver) =
+                futures::channel::mpsc::unbounded();
+
             match db
-                .listen_doc_changes(targets_state.values().cloned().collect())
+                .listen_doc_changes_with_updates(
+                    targets_state.values().cloned().collect(),
+                    stream_updates_receiver,
+                )
                 .await
             {
+                Err(_)
+                    if shutdown_signal
+                        .as_ref()
+                        .map_or(false, |shutdown_signal| shutdown_signal.is_requested()) =>
+                {
+                    debug!("Firestore client is shutting down. Exiting from listener...");
+                    shutdown_flag.store(true, Ordering::Relaxed);
+                }
                 Err(err) => {
-                    if Self::check_listener_if_permanent_error(err, effective_delay).await {
+                    if Self::check_listener_if_permanent_error(&db, err, effective_delay, &status)
+                        .await
+                    {
                         shutdown_flag.store(true, Ordering::Relaxed);
                     }
                 }
-                Ok(mut listen_stream) => loop {
-                    tokio::select! {
-                        shutdown_trigger = shutdown_receiver.recv() => {
-                            if shutdown_trigger.is_none() {
-                                debug!("Listener dropped. Exiting...");
-                                shutdown_flag.store(true, Ordering::Relaxed);
+                Ok(mut listen_stream) => {
+                    let resumed = targets_state.values().any(|target_params| {
+                        matches!(
+                            target_params.resume_type,
+                            Some(FirestoreListenerTargetResumeType::Token(_))
+                        )
+                    });
+                    status.report(if resumed {
+                        FirestoreListenerStatus::ResumedFromToken
+                    } else {
+                        FirestoreListenerStatus::Connected
+                    });
+
+                    // The targets removed on the shutdown of the client, until the removals are confirmed
+                    let mut draining_targets: Option<HashSet<FirestoreListenerTarget>> = None;
+                    let mut draining_deadline: Option<tokio::time::Instant> = None;
+                    loop {
+                        tokio::select! {
+                            deadline = firestore_shutdown_requested(&shutdown_signal), if draining_deadline.is_none() => {
+                                debug!(num_targets = targets_state.len(), "Firestore client is shutting down. Removing listener targets...");
+                                draining_deadline = Some(deadline);
+                                let targets: HashSet<FirestoreListenerTarget> = targets_state.keys().cloned().collect();
+                                for target in targets.iter() {
+                                    Self::apply_target_update(&mut targets_state, &stream_updates_writer, FirestoreListenerTargetUpdate::Remove(target.clone()));
+                                }
+                                stream_updates_writer.close_channel();
+                                if targets.is_empty() {
+                                    break;
+                                }
+                                draining_targets = Some(targets);
                             }
-                            debug!(num_targets = targets_state.len(), "Exiting from listener on targets...");
-                            shutdown_receiver.close();
-                            break;
-                        }
-                        tried = listen_stream.try_next() => {
-                            if shutdown_flag.load(Ordering::Relaxed) {
+                            _ = tokio::time::sleep_until(draining_deadline.unwrap_or_else(tokio::time::Instant::now)), if draining_deadline.is_some() => {
+                                warn!("Listener targets haven't been removed within the shutdown grace period. Exiting...");
+                                break;
+                            }
+                            shutdown_trigger = shutdown_receiver.recv() => {
+                                if shutdown_trigger.is_none() {
+                                    debug!("Listener dropped. Exiting...");
+                                    shutdown_flag.store(true, Ordering::Relaxed);
+                                }
+                                debug!(num_targets = targets_state.len(), "Exiting from listener on targets...");
+                                shutdown_receiver.close();
                                 break;
                             }
-                            else {
-                                match tried {
-                                    Ok(Some(event)) => {
-                                        trace!(?event, "Received a listen response event to handle.");
-
-                                        match event.response_type {
-                                            Some(listen_response::ResponseType::TargetChange(ref target_change))
-                                                if !target_change.resume_token.is_empty() =>
-                                            {
-                                                for target_id_num in &target_change.target_ids {
-                                                    match FirestoreListenerTarget::try_from(*target_id_num) {
-                                                        Ok(target_id) => {
-                                                            if let Some(target) = targets_state.get_mut(&target_id) {
-                                                                let new_token: FirestoreListenerToken = target_change.resume_token.clone().into();
-
-                                                                if let Err(err) = storage.update_resume_token(&target.target, new_token.clone()).await {
-                                                                    error!(%err, "Listener token storage error occurred.");
-                                                                    break;
-                                                                }
-                                                                else {
-                                                                    target.resume_type = Some(FirestoreListenerTargetResumeType::Token(new_token))
-                                                                }
-                                                            }
-                                                        },
-                                                        Err(err) => {
-                                                            error!(%err, target_id_num, "Listener system error - unexpected target ID.");
-                                                            break;
+                            Some(target_update) = target_updates_receiver.recv() => {
+                                Self::apply_target_update(&mut targets_state, &stream_updates_writer, target_update);
+                            }
+                            tried = listen_stream.try_next() => {
+                                if shutdown_flag.load(Ordering::Relaxed) {
+                                    break;
+                                }
+                                else {
+                                    match tried {
+                                        Ok(Some(event)) => {
+                                            trace!(?event, "Received a listen response event to handle.");
+
+                                            status.event_received(match &event.response_type {
+                                                Some(listen_response::ResponseType::TargetChange(target_change)) if !target_change.resume_token.is_empty() => {
+                                                    Some(target_change.resume_token.clone().into())
+                                                }
+                                                _ => None,
+                                            });
+
+                                            if let Some(listen_response::ResponseType::TargetChange(ref target_change)) = event.response_type {
+                                                if !target_change.resume_token.is_empty() {
+                                                    if let Err(err) = Self::update_targets_resume_token(&storage, &mut targets_state, target_change).await {
+                                                        error!(%err, "Listener token storage error occurred.");
+                                                        break;
+                                                    }
+                                                }
+                                            }
+
+                                            let removed_targets = match (&mut draining_targets, &event.response_type) {
+                                                (Some(targets), Some(listen_response::ResponseType::TargetChange(target_change)))
+                                                    if target_change.target_change_type == target_change::TargetChangeType::Remove as i32 =>
+                                                {
+                                                    for target_id_num in &target_change.target_ids {
+                                                        if let Ok(target) = FirestoreListenerTarget::try_from(*target_id_num) {
+                                                            targets.remove(&target);
                                                         }
                                                     }
+                                                    targets.is_empty()
                                                 }
+                                                _ => false,
+                                            };
 
-                                            }
-                                            Some(response_type) => {
-                                                if let Err(err) = cb(response_type).await {
-                                                    error!(%err, "Listener callback function error occurred.");
+                                            if let Some(response_type) = event.response_type {
+                                                if is_delivered_listen_event(&response_type, resume_token_events)
+                                                    && !Self::handle_listen_event(&cb, response_type, &error_policy, deadletter_handler.as_ref()).await
+                                                {
                                                     break;
                                                 }
                                             }
-                                            None  =>  {}
+
+                                            if removed_targets {
+                                                debug!("Listener targets have been removed on shutdown. Exiting...");
+                                                break;
+                                            }
                                         }
-                                    }
-                                    Ok(None) => break,
-                                    Err(err) => {
-                                        if Self::check_listener_if_permanent_error(err, effective_delay).await {
-                                            shutdown_flag.store(true, Ordering::Relaxed);
+                                        Ok(None) => break,
+                                        Err(err) => {
+                                            if Self::check_listener_if_permanent_error(&db, err, effective_delay, &status).await {
+                                                shutdown_flag.store(true, Ordering::Relaxed);
+                                            }
+                                            break;
                                         }
-                                        break;
                                     }
                                 }
                             }
                         }
                     }
-                },
+                }
+            }
+        }
+
+        if !matches!(
+            status.health().status,
+            FirestoreListenerStatus::Failed { .. }
+        ) {
+            status.report(FirestoreListenerStatus::Stopped);
+        }
+    }
+
+    fn apply_target_update(
+        targets_state: &mut HashMap<FirestoreListenerTarget, FirestoreListenerTargetParams>,
+        stream_updates_writer: &futures::channel::mpsc::UnboundedSender<
+            FirestoreListenerTargetUpdate,
+        >,
+        target_update: FirestoreListenerTargetUpdate,
+    ) {
+        debug!(?target_update, "Updating the listener targets...");
+        // Sending fails only when the stream is closed, and it is reopened with the updated targets
+        match target_update {
+            FirestoreListenerTargetUpdate::Add(target_params) => {
+                if targets_state.contains_key(&target_params.target) {
+                    stream_updates_writer
+                        .unbounded_send(FirestoreListenerTargetUpdate::Remove(
+                            target_params.target.clone(),
+                        ))
+                        .ok();
+                }
+                stream_updates_writer
+                    .unbounded_send(FirestoreListenerTargetUpdate::Add(target_params.clone()))
+                    .ok();
+                targets_state.insert(target_params.target.clone(), target_params);
+            }
+            FirestoreListenerTargetUpdate::Remove(target) => {
+                if targets_state.remove(&target).is_some() {
+                    stream_updates_writer
+                        .unbounded_send(FirestoreListenerTargetUpdate::Remove(target))
+                        .ok();
+                }
+            }
+        }
+    }
+
+    async fn update_targets_resume_token(
+        storage: &S,
+        targets_state: &mut HashMap<FirestoreListenerTarget, FirestoreListenerTargetParams>,
+        target_change: &TargetChange,
+    ) -> FirestoreResult<()> {
+        let new_token: FirestoreListenerToken = target_change.resume_token.clone().into();
+
+        for target_id_num in &target_change.target_ids {
+            let target_id = FirestoreListenerTarget::try_from(*target_id_num)?;
+            if let Some(target) = targets_state.get_mut(&target_id) {
+                storage
+                    .update_resume_token(&target.target, new_token.clone())
+                    .await
+                    .map_err(|err| {
+                        FirestoreError::SystemError(FirestoreSystemError::new(
+                            FirestoreErrorPublicGenericDetails::new("SystemError".into()),
+                            format!("Listener token storage error: {err}"),
+                        ))
+                    })?;
+                target.resume_type =
+                    Some(FirestoreListenerTargetResumeType::Token(new_token.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls the callback with the event applying the error policy.
+    /// Returns `false` if the listen stream has to be reopened.
+    async fn handle_listen_event<FN, F>(
+        cb: &FN,
+        event: FirestoreListenEvent,
+        error_policy: &FirestoreListenerErrorPolicy,
+        deadletter_handler: Option<&FirestoreListenerDeadletterHandler>,
+    ) -> bool
+    where
+        FN: Fn(FirestoreListenEvent) -> F + Send + Sync,
+        F: Future<Output = AnyBoxedErrResult<()>> + Send,
+    {
+        // The event is kept only if it may be needed after a failure
+        if error_policy.max_retries == 0
+            && error_policy.on_failure != FirestoreListenerFailedChangeAction::Deadletter
+        {
+            return match cb(event).await {
+                Ok(()) => true,
+                Err(err) => {
+                    Self::handle_failed_listen_event(
+                        None,
+                        err.to_string(),
+                        1,
+                        error_policy,
+                        deadletter_handler,
+                    )
+                    .await
+                }
+            };
+        }
+
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match cb(event.clone()).await {
+                Ok(()) => return true,
+                Err(err) if attempts <= error_policy.max_retries => {
+                    warn!(%err, attempts, "Listener callback function error occurred. Retrying...");
+                    if let Some(retry_delay) = error_policy.retry_delay {
+                        tokio::time::sleep(retry_delay).await;
+                    }
+                }
+                Err(err) => {
+                    return Self::handle_failed_listen_event(
+                        Some(event),
+                        err.to_string(),
+                        attempts,
+                        error_policy,
+                        deadletter_handler,
+                    )
+                    .await
+                }
+            }
+        }
+    }
+
+    async fn handle_failed_listen_event(
+        event: Option<FirestoreListenEvent>,
+        error: String,
+        attempts: usize,
+        error_policy: &FirestoreListenerErrorPolicy,
+        deadletter_handler: Option<&FirestoreListenerDeadletterHandler>,
+    ) -> bool {
+        match (error_policy.on_failure, event, deadletter_handler) {
+            (FirestoreListenerFailedChangeAction::Skip, _, _) => {
+                error!(%error, attempts, "Listener callback function error occurred. Skipping the change...");
+                true
+            }
+            (
+                FirestoreListenerFailedChangeAction::Deadletter,
+                Some(event),
+                Some(deadletter_handler),
+            ) => {
+                error!(%error, attempts, "Listener callback function error occurred. Sending the change to the deadletter handler...");
+                match deadletter_handler(FirestoreListenerDeadletter {
+                    event,
+                    error,
+                    attempts,
+                })
+                .await
+                {
+                    Ok(()) => true,
+                    Err(err) => {
+                        error!(%err, "Listener deadletter handler error occurred.");
+                        false
+                    }
+                }
+            }
+            _ => {
+                error!(%error, "Listener callback function error occurred.");
+                false
             }
         }
     }
 
     async fn check_listener_if_permanent_error(
+        db: &D,
         err: FirestoreError,
         delay: std::time::Duration,
-    ) -> bool {
-        match err {
+        status: &FirestoreListenerStatusReporter,
+    ) -> bool
+    where
+        D: FirestoreListenSupport + Sync,
+    {
+        let permanent = match err {
+            // The stream is restarted from the stored resume tokens with the new credentials
+            FirestoreError::DatabaseError(_)
+                if db.reauthenticate() && is_unauthenticated_error(&err) =>
+            {
+                warn!(%err, ?delay, "Listen error with invalid credentials. Refreshing the credentials and restarting after the specified delay...");
+                db.refresh_credentials().await;
+                false
+            }
             FirestoreError::DatabaseError(ref db_err)
                 if db_err.details.contains("unexpected end of file")
                     || db_err.details.contains("stream error received") =>
             {
                 debug!(%err, ?delay, "Listen EOF.. Restarting after the specified delay...");
-                tokio::time::sleep(delay).await;
                 false
             }
             FirestoreError::DatabaseError(ref db_err)
@@ -486,9 +1150,178 @@ where
             }
             _ => {
                 error!(%err, ?delay, "Listen error. Restarting after the specified delay...");
-                tokio::time::sleep(delay).await;
                 false
             }
+        };
+
+        if permanent {
+            status.report(FirestoreListenerStatus::Failed {
+                error: err.to_string(),
+            });
+        } else {
+            status.report_retry(delay, err.to_string());
+            tokio::time::sleep(delay).await;
         }
+        permanent
+    }
+}
+
+/// Returns `true` if the event is passed to the listener callback:
+/// the target changes carrying resume tokens only when requested.
+fn is_delivered_listen_event(event: &FirestoreListenEvent, resume_token_events: bool) -> bool {
+    match event {
+        listen_response::ResponseType::TargetChange(target_change) => {
+            resume_token_events || target_change.resume_token.is_empty()
+        }
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FirestoreMemListenStateStorage;
+
+    #[test]
+    fn resume_token_events_are_internal_by_default() {
+        let target_change = |resume_token: Vec<u8>| {
+            FirestoreListenEvent::TargetChange(TargetChange {
+                target_change_type: target_change::TargetChangeType::NoChange.into(),
+                resume_token,
+                ..Default::default()
+            })
+        };
+        let document_delete = FirestoreListenEvent::DocumentDelete(DocumentDelete::default());
+
+        assert!(!is_delivered_listen_event(&target_change(vec![1]), false));
+        assert!(is_delivered_listen_event(&target_change(vec![1]), true));
+        assert!(is_delivered_listen_event(&target_change(vec![]), false));
+        assert!(is_delivered_listen_event(&document_delete, false));
+    }
+
+    type TestListener = FirestoreListener<FirestoreDb, FirestoreMemListenStateStorage>;
+
+    fn documents_target(target: u32, documents: &[&str]) -> FirestoreListenerTargetParams {
+        FirestoreListenerTargetParams::new(
+            FirestoreListenerTarget::new(target),
+            FirestoreTargetType::Documents(FirestoreCollectionDocuments::new(
+                "test".to_string(),
+                documents.iter().map(|doc_id| doc_id.to_string()).collect(),
+            )),
+            HashMap::new(),
+        )
+    }
+
+    fn sent_updates(
+        receiver: &mut futures::channel::mpsc::UnboundedReceiver<FirestoreListenerTargetUpdate>,
+    ) -> Vec<String> {
+        std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|update| match update {
+                FirestoreListenerTargetUpdate::Add(params) => {
+                    format!("add {}", params.target.value())
+                }
+                FirestoreListenerTargetUpdate::Remove(target) => {
+                    format!("remove {}", target.value())
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn apply_target_updates_to_open_stream() {
+        let (writer, mut receiver) = futures::channel::mpsc::unbounded();
+        let mut targets_state = HashMap::new();
+
+        TestListener::apply_target_update(
+            &mut targets_state,
+            &writer,
+            FirestoreListenerTargetUpdate::Add(documents_target(1, &["a"])),
+        );
+        assert_eq!(sent_updates(&mut receiver), vec!["add 1"]);
+
+        TestListener::apply_target_update(
+            &mut targets_state,
+            &writer,
+            FirestoreListenerTargetUpdate::Add(documents_target(1, &["a", "b"])),
+        );
+        assert_eq!(sent_updates(&mut receiver), vec!["remove 1", "add 1"]);
+        assert!(matches!(
+            &targets_state[&FirestoreListenerTarget::new(1)].target_type,
+            FirestoreTargetType::Documents(docs) if docs.documents == vec!["a", "b"]
+        ));
+
+        TestListener::apply_target_update(
+            &mut targets_state,
+            &writer,
+            FirestoreListenerTargetUpdate::Remove(FirestoreListenerTarget::new(2)),
+        );
+        TestListener::apply_target_update(
+            &mut targets_state,
+            &writer,
+            FirestoreListenerTargetUpdate::Remove(FirestoreListenerTarget::new(1)),
+        );
+        assert_eq!(sent_updates(&mut receiver), vec!["remove 1"]);
+        assert!(targets_state.is_empty());
+    }
+
+    #[tokio::test]
+    async fn apply_error_policy_to_failed_changes() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let failing_cb = |calls: Arc<std::sync::atomic::AtomicUsize>| {
+            move |_event: FirestoreListenEvent| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                async move { Err::<(), _>("Poison document".into()) }
+            }
+        };
+        let event = FirestoreListenEvent::DocumentDelete(DocumentDelete::default());
+
+        let reconnect = FirestoreListenerErrorPolicy::new().with_max_retries(2);
+        assert!(
+            !TestListener::handle_listen_event(
+                &failing_cb(calls.clone()),
+                event.clone(),
+                &reconnect,
+                None
+            )
+            .await
+        );
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+
+        let skip = FirestoreListenerErrorPolicy::new()
+            .with_on_failure(FirestoreListenerFailedChangeAction::Skip);
+        assert!(
+            TestListener::handle_listen_event(
+                &failing_cb(calls.clone()),
+                event.clone(),
+                &skip,
+                None
+            )
+            .await
+        );
+        assert_eq!(calls.load(Ordering::Relaxed), 4);
+
+        let deadletters = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let handler_deadletters = deadletters.clone();
+        let deadletter_handler: FirestoreListenerDeadletterHandler =
+            Arc::new(move |deadletter: FirestoreListenerDeadletter| {
+                handler_deadletters.lock().unwrap().push(deadletter);
+                Box::pin(async { Ok(()) }) as BoxFuture<_>
+            });
+        let deadletter = FirestoreListenerErrorPolicy::new()
+            .with_max_retries(1)
+            .with_on_failure(FirestoreListenerFailedChangeAction::Deadletter);
+        assert!(
+            TestListener::handle_listen_event(
+                &failing_cb(calls.clone()),
+                event,
+                &deadletter,
+                Some(&deadletter_handler)
+            )
+            .await
+        );
+        let deadletters = deadletters.lock().unwrap();
+        assert_eq!(deadletters.len(), 1);
+        assert_eq!(deadletters[0].attempts, 2);
+        assert_eq!(deadletters[0].error, "Poison document");
     }
 }
@@ -0,0 +1,440 @@
+use crate::errors::*;
+use crate::{
+    FirestoreDb, FirestoreListenEvent, FirestoreListener, FirestoreListenerParams,
+    FirestoreListenerTarget, FirestoreListenerTargetParams, FirestoreListenerTargetResumeType,
+    FirestoreListenerToken, FirestoreResult, FirestoreResumeStateStorage, FirestoreTypedChange,
+    FirestoreTypedChangesTracker,
+};
+use async_trait::async_trait;
+use rsb_derive::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{watch, Mutex};
+use tracing::*;
+
+/// A destination of the typed document changes delivered by a [`FirestoreChangePump`],
+/// e.g. a Kafka topic or a Pub/Sub topic.
+///
+/// The pump retries failed deliveries, so the same changes may be sent again:
+/// after errors, and after restarts for the changes sent since the last checkpoint.
+/// Sinks should be idempotent or tolerate duplicates,
+/// e.g. using the document paths and update times as deduplication keys.
+///
+/// # Examples
+///
+/// ```rust
+/// use firestore::*;
+/// use serde::Deserialize;
+///
+/// #[derive(Debug, Clone, Deserialize)]
+/// struct MyTestStructure {
+///     some_id: String,
+/// }
+///
+/// struct LogSink;
+///
+/// #[async_trait]
+/// impl FirestoreChangeSink<MyTestStructure> for LogSink {
+///     async fn send_changes(
+///         &self,
+///         changes: &[FirestoreTypedChange<MyTestStructure>],
+///     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+///         for change in changes {
+///             println!("{:?} {}", change.change_type, change.document_path);
+///         }
+///         Ok(())
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait FirestoreChangeSink<T> {
+    /// Delivers a batch of changes. The batch is sent again if an error is returned.
+    async fn send_changes(&self, changes: &[FirestoreTypedChange<T>]) -> AnyBoxedErrResult<()>;
+}
+
+#[async_trait]
+impl<T, K> FirestoreChangeSink<T> for Arc<K>
+where
+    T: Send + Sync,
+    K: FirestoreChangeSink<T> + Send + Sync + ?Sized,
+{
+    async fn send_changes(&self, changes: &[FirestoreTypedChange<T>]) -> AnyBoxedErrResult<()> {
+        self.as_ref().send_changes(changes).await
+    }
+}
+
+/// Options of a [`FirestoreChangePump`].
+#[derive(Debug, Eq, PartialEq, Clone, Builder)]
+pub struct FirestoreChangePumpOptions {
+    /// The maximum number of changes sent to the sink at once.
+    #[default = "500"]
+    pub max_batch_size: usize,
+    /// The delay before the first retry of a failed delivery, doubled after every failed retry.
+    #[default = "Duration::from_millis(500)"]
+    pub sink_retry_delay: Duration,
+    /// The upper bound of the delays between the retries of a failed delivery.
+    #[default = "Duration::from_secs(60)"]
+    pub max_sink_retry_delay: Duration,
+    /// Parameters of the underlying listener.
+    pub listener_params: Option<FirestoreListenerParams>,
+}
+
+impl Default for FirestoreChangePumpOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Stores resume tokens only after the changes received before them are delivered to the sink.
+///
+/// The listener stores a new token before handling the event carrying it,
+/// so the tokens are kept pending until the pump commits them.
+#[derive(Clone)]
+struct FirestoreChangePumpCheckpoints<S> {
+    storage: S,
+    pending: Arc<Mutex<HashMap<FirestoreListenerTarget, FirestoreListenerToken>>>,
+}
+
+impl<S> FirestoreChangePumpCheckpoints<S>
+where
+    S: FirestoreResumeStateStorage + Send + Sync,
+{
+    fn new(storage: S) -> Self {
+        Self {
+            storage,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    async fn commit(&self) -> AnyBoxedErrResult<()> {
+        let mut pending = self.pending.lock().await;
+        for (target, token) in pending.drain() {
+            self.storage.update_resume_token(&target, token).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<S> FirestoreResumeStateStorage for FirestoreChangePumpCheckpoints<S>
+where
+    S: FirestoreResumeStateStorage + Send + Sync,
+{
+    async fn read_resume_state(
+        &self,
+        target: &FirestoreListenerTarget,
+    ) -> AnyBoxedErrResult<Option<FirestoreListenerTargetResumeType>> {
+        self.storage.read_resume_state(target).await
+    }
+
+    async fn update_resume_token(
+        &self,
+        target: &FirestoreListenerTarget,
+        token: FirestoreListenerToken,
+    ) -> AnyBoxedErrResult<()> {
+        self.pending.lock().await.insert(target.clone(), token);
+        Ok(())
+    }
+}
+
+struct FirestoreChangePumpHandler<T, K, S> {
+    sink: K,
+    checkpoints: FirestoreChangePumpCheckpoints<S>,
+    options: FirestoreChangePumpOptions,
+    tracker: FirestoreTypedChangesTracker,
+    buffer: Vec<FirestoreTypedChange<T>>,
+    shutdown: watch::Receiver<bool>,
+}
+
+impl<T, K, S> FirestoreChangePumpHandler<T, K, S>
+where
+    for<'de> T: Deserialize<'de>,
+    T: Send + Sync,
+    K: FirestoreChangeSink<T> + Send + Sync,
+    S: FirestoreResumeStateStorage + Send + Sync,
+{
+    async fn handle_event(&mut self, event: FirestoreListenEvent) -> FirestoreResult<()> {
+        // The listener has already passed the resume token of the event to the checkpoints
+        let checkpoint = match &event {
+            FirestoreListenEvent::TargetChange(target_change) => {
+                !target_change.resume_token.is_empty()
+            }
+            _ => false,
+        };
+
+        for change in self.tracker.process_event::<T>(event) {
+            match change {
+                Ok(change) => self.buffer.push(change),
+                Err(err) => error!(%err, "Skipping a document change that can't be deserialized."),
+            }
+        }
+
+        while self.buffer.len() >= self.options.max_batch_size.max(1) {
+            let batch: Vec<FirestoreTypedChange<T>> = self
+                .buffer
+                .drain(..self.options.max_batch_size.max(1))
+                .collect();
+            self.send_with_retries(&batch).await?;
+        }
+
+        if checkpoint {
+            if !self.buffer.is_empty() {
+                let batch = std::mem::take(&mut self.buffer);
+                self.send_with_retries(&batch).await?;
+            }
+            self.checkpoints.commit().await.map_err(|err| {
+                FirestoreError::SystemError(FirestoreSystemError::new(
+                    FirestoreErrorPublicGenericDetails::new("SystemError".into()),
+                    format!("Change pump checkpoint error: {err}"),
+                ))
+            })?;
+        }
+        Ok(())
+    }
+
+    // Retries until the changes are delivered or the pump is shut down,
+    // blocking the listener to keep the changes in order
+    async fn send_with_retries(
+        &mut self,
+        batch: &[FirestoreTypedChange<T>],
+    ) -> FirestoreResult<()> {
+        let mut delay = self.options.sink_retry_delay;
+        loop {
+            match self.sink.send_changes(batch).await {
+                Ok(()) => return Ok(()),
+                Err(err) => {
+                    warn!(%err, ?delay, batch_size = batch.len(), "Change sink error. Retrying after the delay...");
+                }
+            }
+
+            let shutdown = tokio::select! {
+                _ = tokio::time::sleep(delay) => false,
+                changed = self.shutdown.changed() => changed.is_err() || *self.shutdown.borrow(),
+            };
+            if shutdown {
+                return Err(FirestoreError::SystemError(FirestoreSystemError::new(
+                    FirestoreErrorPublicGenericDetails::new("SystemError".into()),
+                    "The change pump has been shut down before the changes were delivered"
+                        .to_string(),
+                )));
+            }
+            delay = (delay * 2).min(self.options.max_sink_retry_delay);
+        }
+    }
+}
+
+/// Feeds the typed document changes of listener targets into a [`FirestoreChangeSink`],
+/// e.g. to export the changes to Kafka or Pub/Sub.
+///
+/// Delivery is at-least-once: the resume tokens are stored in the storage only after all the changes
+/// received before them are delivered, so listening resumes from the last delivered changes after restarts.
+/// Failed deliveries are retried with exponential backoff and the listener doesn't receive
+/// new changes until then, so a slow or unavailable sink applies backpressure.
+/// Documents that can't be deserialized are logged and skipped.
+pub struct FirestoreChangePump<S>
+where
+    S: FirestoreResumeStateStorage + Clone + Send + Sync + 'static,
+{
+    listener: FirestoreListener<FirestoreDb, FirestoreChangePumpCheckpoints<S>>,
+    shutdown: watch::Sender<bool>,
+}
+
+impl<S> FirestoreChangePump<S>
+where
+    S: FirestoreResumeStateStorage + Clone + Send + Sync + 'static,
+{
+    /// Starts listening to the targets and delivering the changes to the sink.
+    pub async fn start<T, K>(
+        db: &FirestoreDb,
+        storage: S,
+        targets: Vec<FirestoreListenerTargetParams>,
+        sink: K,
+        options: FirestoreChangePumpOptions,
+    ) -> FirestoreResult<Self>
+    where
+        for<'de> T: Deserialize<'de>,
+        T: Send + Sync + 'static,
+        K: FirestoreChangeSink<T> + Send + Sync + 'static,
+    {
+        let checkpoints = FirestoreChangePumpCheckpoints::new(storage);
+        let (shutdown, shutdown_receiver) = watch::channel(false);
+
+        let mut listener = db
+            .create_listener_with_params(
+                checkpoints.clone(),
+                options
+                    .listener_params
+                    .clone()
+                    .unwrap_or_else(FirestoreListenerParams::new),
+            )
+            .await?;
+        for target in targets {
+            listener.add_target(target)?;
+        }
+        listener.enable_resume_token_events();
+
+        let handler = Arc::new(Mutex::new(FirestoreChangePumpHandler {
+            sink,
+            checkpoints,
+            options,
+            tracker: FirestoreTypedChangesTracker::new(),
+            buffer: Vec::new(),
+            shutdown: shutdown_receiver,
+        }));
+
+        listener
+            .start(move |event| {
+                let handler = handler.clone();
+                async move {
+                    handler.lock().await.handle_event(event).await?;
+                    Ok(())
+                }
+            })
+            .await?;
+
+        Ok(Self { listener, shutdown })
+    }
+
+    /// Stops listening. The changes not delivered yet are sent again on the next start.
+    pub async fn shutdown(mut self) -> FirestoreResult<()> {
+        self.shutdown.send(true).ok();
+        self.listener.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FirestoreMemListenStateStorage, FirestoreTypedChangeType};
+    use gcloud_sdk::google::firestore::v1::*;
+    use rvstruct::ValueStruct;
+    use serde::Serialize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestStructure {
+        some_num: u64,
+    }
+
+    #[derive(Default)]
+    struct TestSink {
+        failures: AtomicUsize,
+        delivered: std::sync::Mutex<Vec<(FirestoreTypedChangeType, String)>>,
+    }
+
+    #[async_trait]
+    impl FirestoreChangeSink<TestStructure> for TestSink {
+        async fn send_changes(
+            &self,
+            changes: &[FirestoreTypedChange<TestStructure>],
+        ) -> AnyBoxedErrResult<()> {
+            if self.failures.load(Ordering::SeqCst) > 0 {
+                self.failures.fetch_sub(1, Ordering::SeqCst);
+                return Err("Sink is unavailable".into());
+            }
+            self.delivered.lock().unwrap().extend(
+                changes
+                    .iter()
+                    .map(|change| (change.change_type, change.document_path.clone())),
+            );
+            Ok(())
+        }
+    }
+
+    fn doc_change(name: &str, some_num: u64) -> FirestoreListenEvent {
+        let mut doc = FirestoreDb::serialize_to_doc(name, &TestStructure { some_num }).unwrap();
+        doc.update_time = Some(gcloud_sdk::prost_types::Timestamp {
+            seconds: some_num as i64,
+            nanos: 0,
+        });
+        FirestoreListenEvent::DocumentChange(DocumentChange {
+            document: Some(doc),
+            target_ids: vec![1],
+            removed_target_ids: vec![],
+        })
+    }
+
+    fn token_change(token: u8) -> FirestoreListenEvent {
+        FirestoreListenEvent::TargetChange(TargetChange {
+            target_change_type: target_change::TargetChangeType::Current.into(),
+            target_ids: vec![1],
+            resume_token: vec![token],
+            ..Default::default()
+        })
+    }
+
+    async fn stored_token(storage: &FirestoreMemListenStateStorage) -> Option<Vec<u8>> {
+        storage
+            .get_token(&FirestoreListenerTarget::new(1))
+            .await
+            .map(|token| token.value().clone())
+    }
+
+    #[tokio::test]
+    async fn change_pump_checkpoints_after_delivery() {
+        let storage = FirestoreMemListenStateStorage::new();
+        let sink = Arc::new(TestSink::default());
+        let (_shutdown, shutdown_receiver) = watch::channel(false);
+        let mut handler = FirestoreChangePumpHandler {
+            sink: sink.clone(),
+            checkpoints: FirestoreChangePumpCheckpoints::new(storage.clone()),
+            options: FirestoreChangePumpOptions::new()
+                .with_max_batch_size(2)
+                .with_sink_retry_delay(Duration::from_millis(1)),
+            tracker: FirestoreTypedChangesTracker::new(),
+            buffer: Vec::new(),
+            shutdown: shutdown_receiver,
+        };
+
+        handler.handle_event(doc_change("docs/a", 1)).await.unwrap();
+        assert!(sink.delivered.lock().unwrap().is_empty());
+
+        // The listener stores the token before handling the event
+        handler
+            .checkpoints
+            .update_resume_token(
+                &FirestoreListenerTarget::new(1),
+                FirestoreListenerToken::new(vec![1]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(stored_token(&storage).await, None);
+        sink.failures.store(2, Ordering::SeqCst);
+        handler.handle_event(token_change(1)).await.unwrap();
+        assert_eq!(
+            sink.delivered.lock().unwrap().clone(),
+            vec![(FirestoreTypedChangeType::Added, "docs/a".to_string())]
+        );
+        assert_eq!(stored_token(&storage).await, Some(vec![1]));
+
+        // Full batches are delivered without waiting for a checkpoint
+        handler.handle_event(doc_change("docs/a", 2)).await.unwrap();
+        handler.handle_event(doc_change("docs/b", 3)).await.unwrap();
+        assert_eq!(sink.delivered.lock().unwrap().len(), 3);
+        assert_eq!(stored_token(&storage).await, Some(vec![1]));
+    }
+
+    #[tokio::test]
+    async fn change_pump_stops_retrying_on_shutdown() {
+        let sink = TestSink {
+            failures: AtomicUsize::new(usize::MAX),
+            ..Default::default()
+        };
+        let (shutdown, shutdown_receiver) = watch::channel(false);
+        let mut handler = FirestoreChangePumpHandler {
+            sink,
+            checkpoints: FirestoreChangePumpCheckpoints::new(FirestoreMemListenStateStorage::new()),
+            options: FirestoreChangePumpOptions::new().with_max_batch_size(1),
+            tracker: FirestoreTypedChangesTracker::new(),
+            buffer: Vec::new(),
+            shutdown: shutdown_receiver,
+        };
+
+        let handled =
+            tokio::spawn(async move { handler.handle_event(doc_change("docs/a", 1)).await });
+        shutdown.send(true).unwrap();
+        assert!(handled.await.unwrap().is_err());
+    }
+}
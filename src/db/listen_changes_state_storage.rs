@@ -7,6 +7,52 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::*;
 
+/// A storage for the resume tokens of listener targets.
+///
+/// Listeners read the initial state of every target from the storage on start and store
+/// a new token every time Firestore reports a consistent snapshot, so listening can be resumed
+/// after restarts without receiving all the matching documents again.
+///
+/// Besides the provided [`FirestoreTempFilesListenStateStorage`] and [`FirestoreMemListenStateStorage`],
+/// you can implement the trait for any external storage (e.g. Redis or a database table):
+///
+/// ```rust
+/// use firestore::*;
+/// use std::collections::HashMap;
+/// use tokio::sync::Mutex;
+///
+/// struct MyExternalStorage {
+///     // A client of your storage
+///     tokens: Mutex<HashMap<u32, Vec<u8>>>,
+/// }
+///
+/// #[async_trait]
+/// impl FirestoreResumeStateStorage for MyExternalStorage {
+///     async fn read_resume_state(
+///         &self,
+///         target: &FirestoreListenerTarget,
+///     ) -> Result<Option<FirestoreListenerTargetResumeType>, Box<dyn std::error::Error + Send + Sync>> {
+///         Ok(self
+///             .tokens
+///             .lock()
+///             .await
+///             .get(target.value())
+///             .cloned()
+///             .map(|token| FirestoreListenerTargetResumeType::Token(token.into())))
+///     }
+///
+///     async fn update_resume_token(
+///         &self,
+///         target: &FirestoreListenerTarget,
+///         token: FirestoreListenerToken,
+///     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+///         self.tokens.lock().await.insert(*target.value(), token.into_value());
+///         Ok(())
+///     }
+/// }
+/// ```
+///
+/// Storages shared between several listeners can be wrapped into an `Arc`.
 #[async_trait]
 pub trait FirestoreResumeStateStorage {
     async fn read_resume_state(
@@ -21,6 +67,29 @@ pub trait FirestoreResumeStateStorage {
     ) -> AnyBoxedErrResult<()>;
 }
 
+#[async_trait]
+impl<S> FirestoreResumeStateStorage for Arc<S>
+where
+    S: FirestoreResumeStateStorage + Send + Sync + ?Sized,
+{
+    async fn read_resume_state(
+        &self,
+        target: &FirestoreListenerTarget,
+    ) -> AnyBoxedErrResult<Option<FirestoreListenerTargetResumeType>> {
+        self.as_ref().read_resume_state(target).await
+    }
+
+    async fn update_resume_token(
+        &self,
+        target: &FirestoreListenerTarget,
+        token: FirestoreListenerToken,
+    ) -> AnyBoxedErrResult<()> {
+        self.as_ref().update_resume_token(target, token).await
+    }
+}
+
+/// A storage keeping the resume tokens in files (one file per target),
+/// either in the current directory or in the specified one.
 #[derive(Clone, Debug)]
 pub struct FirestoreTempFilesListenStateStorage {
     temp_dir: Option<std::path::PathBuf>,
@@ -51,6 +120,12 @@ impl FirestoreTempFilesListenStateStorage {
     }
 }
 
+impl Default for FirestoreTempFilesListenStateStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 const TOKEN_FILENAME_PREFIX: &str = "firestore-listen-token";
 
 #[async_trait]
@@ -81,13 +156,19 @@ impl FirestoreResumeStateStorage for FirestoreTempFilesListenStateStorage {
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let target_state_file_name = self.get_file_path(target);
 
-        Ok(std::fs::write(
+        // Writing to a new file and renaming it to avoid corrupted tokens if the process is interrupted
+        let mut new_state_file_name = target_state_file_name.clone().into_os_string();
+        new_state_file_name.push(".new");
+        std::fs::write(&new_state_file_name, hex::encode(token.value()))?;
+        Ok(std::fs::rename(
+            new_state_file_name,
             target_state_file_name,
-            hex::encode(token.value()),
         )?)
     }
 }
 
+/// A storage keeping the resume tokens in memory.
+/// The tokens are lost on restarts, so all the matching documents are received again.
 #[derive(Clone, Debug)]
 pub struct FirestoreMemListenStateStorage {
     tokens: Arc<RwLock<HashMap<FirestoreListenerTarget, FirestoreListenerToken>>>,
@@ -108,6 +189,12 @@ impl FirestoreMemListenStateStorage {
     }
 }
 
+impl Default for FirestoreMemListenStateStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[async_trait]
 impl FirestoreResumeStateStorage for FirestoreMemListenStateStorage {
     async fn read_resume_state(
@@ -130,3 +217,35 @@ impl FirestoreResumeStateStorage for FirestoreMemListenStateStorage {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn temp_files_storage_roundtrip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(FirestoreTempFilesListenStateStorage::with_temp_dir(
+            temp_dir.path(),
+        ));
+        let target = FirestoreListenerTarget::new(42);
+
+        assert!(storage.read_resume_state(&target).await.unwrap().is_none());
+
+        storage
+            .update_resume_token(&target, FirestoreListenerToken::new(vec![1, 2, 3]))
+            .await
+            .unwrap();
+        storage
+            .update_resume_token(&target, FirestoreListenerToken::new(vec![4, 5]))
+            .await
+            .unwrap();
+
+        match storage.read_resume_state(&target).await.unwrap() {
+            Some(FirestoreListenerTargetResumeType::Token(token)) => {
+                assert_eq!(token.value(), &vec![4, 5])
+            }
+            other => panic!("Unexpected resume state: {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::FirestoreListenerToken;
+use chrono::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A lifecycle event of a listener stream, see [`FirestoreListener::set_status_handler`](crate::FirestoreListener::set_status_handler).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum FirestoreListenerStatus {
+    /// The listener hasn't been started yet.
+    NotStarted,
+    /// The listen stream has been opened without resume tokens.
+    Connected,
+    /// The listen stream has been opened again from the resume tokens of the targets.
+    ResumedFromToken,
+    /// The listen stream has failed and is reopened after the delay.
+    Retrying {
+        /// The number of failures since the last event has been received.
+        attempt: usize,
+        delay: Duration,
+        error: String,
+    },
+    /// The listen stream has failed with an error that can't be retried, and the listener has stopped.
+    Failed { error: String },
+    /// The listener has been shut down.
+    Stopped,
+}
+
+impl FirestoreListenerStatus {
+    /// Checks if the listen stream is open.
+    pub fn is_connected(&self) -> bool {
+        matches!(
+            self,
+            FirestoreListenerStatus::Connected | FirestoreListenerStatus::ResumedFromToken
+        )
+    }
+}
+
+/// The current health of a listener, see [`FirestoreListener::health`](crate::FirestoreListener::health).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FirestoreListenerHealth {
+    /// The last lifecycle event of the listen stream.
+    pub status: FirestoreListenerStatus,
+    /// The time the status has changed.
+    pub status_time: Option<DateTime<Utc>>,
+    /// The time the last event has been received from the server.
+    /// Open streams also receive target changes without changes periodically,
+    /// so it can be used to check if the stream is alive.
+    pub last_event_time: Option<DateTime<Utc>>,
+    /// The last resume token received from the server.
+    pub resume_token: Option<FirestoreListenerToken>,
+}
+
+impl FirestoreListenerHealth {
+    /// Checks if the listen stream is open and an event has been received within `max_idle`,
+    /// e.g. for readiness probes.
+    pub fn is_live(&self, max_idle: Duration, now: DateTime<Utc>) -> bool {
+        let last_activity = self.last_event_time.max(self.status_time);
+        self.status.is_connected()
+            && last_activity.map_or(false, |last_activity| {
+                now.signed_duration_since(last_activity)
+                    .to_std()
+                    .map_or(true, |idle| idle <= max_idle)
+            })
+    }
+}
+
+impl Default for FirestoreListenerHealth {
+    fn default() -> Self {
+        Self {
+            status: FirestoreListenerStatus::NotStarted,
+            status_time: None,
+            last_event_time: None,
+            resume_token: None,
+        }
+    }
+}
+
+/// A handler of the lifecycle events of a listener.
+pub type FirestoreListenerStatusHandler = Arc<dyn Fn(FirestoreListenerStatus) + Send + Sync>;
+
+/// Tracks the health of a listener and reports its lifecycle events to the handler.
+#[derive(Clone, Default)]
+pub(crate) struct FirestoreListenerStatusReporter {
+    health: Arc<Mutex<FirestoreListenerHealth>>,
+    handler: Option<FirestoreListenerStatusHandler>,
+    /// The number of failures since the last received event.
+    failures: Arc<AtomicUsize>,
+}
+
+impl FirestoreListenerStatusReporter {
+    pub(crate) fn with_handler(self, handler: Option<FirestoreListenerStatusHandler>) -> Self {
+        Self { handler, ..self }
+    }
+
+    pub(crate) fn health(&self) -> FirestoreListenerHealth {
+        self.health
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    pub(crate) fn report(&self, status: FirestoreListenerStatus) {
+        {
+            let mut health = self
+                .health
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            health.status = status.clone();
+            health.status_time = Some(Utc::now());
+        }
+        if let Some(handler) = &self.handler {
+            handler(status);
+        }
+    }
+
+    /// Reports the next retry after a failure, counting the failures since the last received event.
+    pub(crate) fn report_retry(&self, delay: Duration, error: String) {
+        let attempt = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        self.report(FirestoreListenerStatus::Retrying {
+            attempt,
+            delay,
+            error,
+        });
+    }
+
+    pub(crate) fn event_received(&self, resume_token: Option<FirestoreListenerToken>) {
+        self.failures.store(0, Ordering::Relaxed);
+        let mut health = self
+            .health
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        health.last_event_time = Some(Utc::now());
+        if resume_token.is_some() {
+            health.resume_token = resume_token;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_retry_attempts_until_event_received() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let handler_events = events.clone();
+        let reporter = FirestoreListenerStatusReporter::default().with_handler(Some(Arc::new(
+            move |status: FirestoreListenerStatus| handler_events.lock().unwrap().push(status),
+        )));
+        let delay = Duration::from_secs(1);
+
+        reporter.report(FirestoreListenerStatus::Connected);
+        reporter.report_retry(delay, "EOF".to_string());
+        reporter.report_retry(delay, "EOF".to_string());
+        reporter.report(FirestoreListenerStatus::ResumedFromToken);
+        reporter.report_retry(delay, "EOF".to_string());
+        reporter.report(FirestoreListenerStatus::ResumedFromToken);
+        reporter.event_received(None);
+        reporter.report_retry(delay, "EOF".to_string());
+
+        let attempts: Vec<usize> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|status| match status {
+                FirestoreListenerStatus::Retrying { attempt, .. } => Some(*attempt),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(attempts, vec![1, 2, 3, 1]);
+        assert_eq!(events.lock().unwrap().len(), 7);
+    }
+
+    #[test]
+    fn live_when_connected_with_recent_events() {
+        let reporter = FirestoreListenerStatusReporter::default();
+        let max_idle = Duration::from_secs(60);
+        assert!(!reporter.health().is_live(max_idle, Utc::now()));
+
+        reporter.report(FirestoreListenerStatus::ResumedFromToken);
+        reporter.event_received(Some(FirestoreListenerToken::new(vec![1])));
+        let health = reporter.health();
+        assert!(health.is_live(max_idle, Utc::now()));
+        assert!(!health.is_live(max_idle, Utc::now() + chrono::Duration::minutes(2)));
+        assert_eq!(
+            health.resume_token,
+            Some(FirestoreListenerToken::new(vec![1]))
+        );
+
+        reporter.event_received(None);
+        assert_eq!(
+            reporter.health().resume_token,
+            Some(FirestoreListenerToken::new(vec![1]))
+        );
+
+        reporter.report_retry(max_idle, "EOF".to_string());
+        assert!(!reporter.health().is_live(max_idle, Utc::now()));
+    }
+}
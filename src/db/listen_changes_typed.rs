@@ -0,0 +1,392 @@
+use crate::errors::*;
+use crate::timestamp_utils::from_timestamp;
+use crate::{
+    FirestoreDb, FirestoreListenEvent, FirestoreListenSupport, FirestoreListener,
+    FirestoreListenerTarget, FirestoreResult, FirestoreResumeStateStorage,
+};
+use chrono::prelude::*;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use gcloud_sdk::google::firestore::v1::*;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tracing::*;
+
+/// The kind of change of a document in a listener target.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum FirestoreTypedChangeType {
+    /// The document started matching the target.
+    Added,
+    /// The document already matching the target has been updated.
+    Modified,
+    /// The document has been deleted or no longer matches the target.
+    Removed,
+}
+
+/// A typed change of a document in a listener target, produced by [`FirestoreTypedChangesTracker`].
+#[derive(Debug, PartialEq, Clone)]
+pub struct FirestoreTypedChange<T> {
+    pub change_type: FirestoreTypedChangeType,
+    /// The target the change belongs to.
+    pub target: FirestoreListenerTarget,
+    /// The full path of the document.
+    pub document_path: String,
+    /// The deserialized document. Always `None` for removed documents.
+    pub object: Option<T>,
+    /// The time the document was last updated, if known.
+    pub update_time: Option<DateTime<Utc>>,
+    /// The time the change was observed by the server, if known.
+    pub read_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default)]
+struct FirestoreTypedTargetState {
+    // Documents currently matching the target with their update times
+    documents: HashMap<String, Option<gcloud_sdk::prost_types::Timestamp>>,
+    // Documents received after a RESET of the target and until it is CURRENT again
+    resynced: Option<HashSet<String>>,
+}
+
+/// Converts raw listener events into [`FirestoreTypedChange`]s.
+///
+/// The tracker keeps the set of documents matching every target to distinguish added and modified
+/// documents, and handles `RESET`/`CURRENT` target changes: documents not sent again after a reset
+/// of the target are reported as removed, and documents sent again without changes are skipped.
+#[derive(Debug, Default)]
+pub struct FirestoreTypedChangesTracker {
+    targets: HashMap<FirestoreListenerTarget, FirestoreTypedTargetState>,
+}
+
+impl FirestoreTypedChangesTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Processes a listener event and returns the resulting typed changes.
+    pub fn process_event<T>(
+        &mut self,
+        event: FirestoreListenEvent,
+    ) -> Vec<FirestoreResult<FirestoreTypedChange<T>>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        match event {
+            FirestoreListenEvent::DocumentChange(doc_change) => {
+                self.process_document_change(doc_change)
+            }
+            FirestoreListenEvent::DocumentDelete(doc_delete) => self.process_document_removed(
+                doc_delete.document,
+                doc_delete.removed_target_ids,
+                doc_delete.read_time,
+            ),
+            FirestoreListenEvent::DocumentRemove(doc_remove) => self.process_document_removed(
+                doc_remove.document,
+                doc_remove.removed_target_ids,
+                doc_remove.read_time,
+            ),
+            FirestoreListenEvent::TargetChange(target_change) => {
+                self.process_target_change(target_change)
+            }
+            FirestoreListenEvent::Filter(_) => Vec::new(),
+        }
+    }
+
+    fn process_document_change<T>(
+        &mut self,
+        doc_change: DocumentChange,
+    ) -> Vec<FirestoreResult<FirestoreTypedChange<T>>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        let mut changes = Vec::new();
+
+        if let Some(doc) = doc_change.document {
+            let update_time = doc
+                .update_time
+                .map(from_timestamp)
+                .transpose()
+                .ok()
+                .flatten();
+
+            for target_id in doc_change.target_ids {
+                let target = match FirestoreListenerTarget::try_from(target_id) {
+                    Ok(target) => target,
+                    Err(err) => {
+                        changes.push(Err(err));
+                        continue;
+                    }
+                };
+                let state = self.targets.entry(target.clone()).or_default();
+
+                if let Some(resynced) = state.resynced.as_mut() {
+                    resynced.insert(doc.name.clone());
+                }
+
+                let change_type = match state.documents.get(&doc.name) {
+                    Some(known_update_time) if *known_update_time == doc.update_time => continue,
+                    Some(_) => FirestoreTypedChangeType::Modified,
+                    None => FirestoreTypedChangeType::Added,
+                };
+                state.documents.insert(doc.name.clone(), doc.update_time);
+
+                changes.push(FirestoreDb::deserialize_doc_to::<T>(&doc).map(|obj| {
+                    FirestoreTypedChange {
+                        change_type,
+                        target,
+                        document_path: doc.name.clone(),
+                        object: Some(obj),
+                        update_time,
+                        read_time: None,
+                    }
+                }));
+            }
+
+            changes.extend(self.process_document_removed(
+                doc.name,
+                doc_change.removed_target_ids,
+                None,
+            ));
+        }
+
+        changes
+    }
+
+    fn process_document_removed<T>(
+        &mut self,
+        document_path: String,
+        removed_target_ids: Vec<i32>,
+        read_time: Option<gcloud_sdk::prost_types::Timestamp>,
+    ) -> Vec<FirestoreResult<FirestoreTypedChange<T>>> {
+        let read_time = read_time.map(from_timestamp).transpose().ok().flatten();
+
+        removed_target_ids
+            .into_iter()
+            .filter_map(
+                |target_id| match FirestoreListenerTarget::try_from(target_id) {
+                    Ok(target) => self
+                        .targets
+                        .get_mut(&target)
+                        .and_then(|state| state.documents.remove(&document_path))
+                        .map(|_| {
+                            Ok(FirestoreTypedChange {
+                                change_type: FirestoreTypedChangeType::Removed,
+                                target,
+                                document_path: document_path.clone(),
+                                object: None,
+                                update_time: None,
+                                read_time,
+                            })
+                        }),
+                    Err(err) => Some(Err(err)),
+                },
+            )
+            .collect()
+    }
+
+    fn process_target_change<T>(
+        &mut self,
+        target_change: TargetChange,
+    ) -> Vec<FirestoreResult<FirestoreTypedChange<T>>> {
+        let targets: Vec<FirestoreListenerTarget> = if target_change.target_ids.is_empty() {
+            self.targets.keys().cloned().collect()
+        } else {
+            match target_change
+                .target_ids
+                .iter()
+                .map(|target_id| FirestoreListenerTarget::try_from(*target_id))
+                .collect::<FirestoreResult<Vec<FirestoreListenerTarget>>>()
+            {
+                Ok(targets) => targets,
+                Err(err) => return vec![Err(err)],
+            }
+        };
+        let read_time = target_change
+            .read_time
+            .map(from_timestamp)
+            .transpose()
+            .ok()
+            .flatten();
+
+        match target_change::TargetChangeType::try_from(target_change.target_change_type) {
+            Ok(target_change::TargetChangeType::Reset) => {
+                for target in targets {
+                    self.targets.entry(target).or_default().resynced = Some(HashSet::new());
+                }
+                Vec::new()
+            }
+            Ok(target_change::TargetChangeType::Current) => {
+                let mut changes = Vec::new();
+                for target in targets {
+                    if let Some(state) = self.targets.get_mut(&target) {
+                        if let Some(resynced) = state.resynced.take() {
+                            let removed: Vec<String> = state
+                                .documents
+                                .keys()
+                                .filter(|document_path| !resynced.contains(*document_path))
+                                .cloned()
+                                .collect();
+                            for document_path in removed {
+                                state.documents.remove(&document_path);
+                                changes.push(Ok(FirestoreTypedChange {
+                                    change_type: FirestoreTypedChangeType::Removed,
+                                    target: target.clone(),
+                                    document_path,
+                                    object: None,
+                                    update_time: None,
+                                    read_time,
+                                }));
+                            }
+                        }
+                    }
+                }
+                changes
+            }
+            Ok(target_change::TargetChangeType::Remove) => {
+                for target in targets {
+                    self.targets.remove(&target);
+                }
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl<D, S> FirestoreListener<D, S>
+where
+    D: FirestoreListenSupport + Clone + Send + Sync + 'static,
+    S: FirestoreResumeStateStorage + Clone + Send + Sync + 'static,
+{
+    /// Starts the listener and returns a stream of typed document changes for all the targets.
+    ///
+    /// Target IDs, resume tokens and `RESET`/`CURRENT` events are handled internally
+    /// (see [`FirestoreTypedChangesTracker`]).
+    /// The stream ends when the listener is shut down.
+    pub async fn start_typed_stream<'b, T>(
+        &mut self,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreTypedChange<T>>>>
+    where
+        for<'de> T: Deserialize<'de>,
+        T: Send + 'static,
+    {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let tracker = Arc::new(Mutex::new(FirestoreTypedChangesTracker::new()));
+
+        self.enable_resume_token_events();
+        self.start(move |event| {
+            let tx = tx.clone();
+            let tracker = tracker.clone();
+            async move {
+                let changes = tracker
+                    .lock()
+                    .map_err(|err| {
+                        FirestoreError::SystemError(FirestoreSystemError::new(
+                            FirestoreErrorPublicGenericDetails::new("SystemError".into()),
+                            format!("Listener changes tracker error: {err}"),
+                        ))
+                    })?
+                    .process_event::<T>(event);
+
+                for change in changes {
+                    if tx.send(change).is_err() {
+                        debug!("Typed changes stream has been dropped. Ignoring changes.");
+                        break;
+                    }
+                }
+                Ok(())
+            }
+        })
+        .await?;
+
+        Ok(tokio_stream::wrappers::UnboundedReceiverStream::new(rx).boxed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestStructure {
+        some_num: u64,
+    }
+
+    fn doc_change(name: &str, some_num: u64, seconds: i64) -> FirestoreListenEvent {
+        let mut doc = FirestoreDb::serialize_to_doc(name, &TestStructure { some_num }).unwrap();
+        doc.update_time = Some(gcloud_sdk::prost_types::Timestamp { seconds, nanos: 0 });
+        FirestoreListenEvent::DocumentChange(DocumentChange {
+            document: Some(doc),
+            target_ids: vec![1],
+            removed_target_ids: vec![],
+        })
+    }
+
+    fn target_change(change_type: target_change::TargetChangeType) -> FirestoreListenEvent {
+        FirestoreListenEvent::TargetChange(TargetChange {
+            target_change_type: change_type.into(),
+            target_ids: vec![1],
+            ..Default::default()
+        })
+    }
+
+    fn change_types(
+        changes: Vec<FirestoreResult<FirestoreTypedChange<TestStructure>>>,
+    ) -> Vec<(FirestoreTypedChangeType, String)> {
+        changes
+            .into_iter()
+            .map(|change| {
+                let change = change.unwrap();
+                (change.change_type, change.document_path)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn typed_changes_tracking() {
+        let mut tracker = FirestoreTypedChangesTracker::new();
+
+        let changes = tracker.process_event::<TestStructure>(doc_change("docs/a", 1, 1));
+        assert_eq!(
+            changes[0].as_ref().unwrap().object,
+            Some(TestStructure { some_num: 1 })
+        );
+        assert_eq!(
+            change_types(changes),
+            vec![(FirestoreTypedChangeType::Added, "docs/a".to_string())]
+        );
+
+        tracker.process_event::<TestStructure>(doc_change("docs/b", 2, 1));
+
+        assert_eq!(
+            change_types(tracker.process_event(doc_change("docs/a", 3, 2))),
+            vec![(FirestoreTypedChangeType::Modified, "docs/a".to_string())]
+        );
+
+        // After a reset, unchanged documents are skipped and missing documents are removed
+        assert!(tracker
+            .process_event::<TestStructure>(target_change(target_change::TargetChangeType::Reset))
+            .is_empty());
+        assert!(tracker
+            .process_event::<TestStructure>(doc_change("docs/a", 3, 2))
+            .is_empty());
+        assert_eq!(
+            change_types(
+                tracker.process_event(target_change(target_change::TargetChangeType::Current))
+            ),
+            vec![(FirestoreTypedChangeType::Removed, "docs/b".to_string())]
+        );
+
+        assert_eq!(
+            change_types(tracker.process_event(FirestoreListenEvent::DocumentDelete(
+                DocumentDelete {
+                    document: "docs/a".to_string(),
+                    removed_target_ids: vec![1],
+                    read_time: None,
+                }
+            ))),
+            vec![(FirestoreTypedChangeType::Removed, "docs/a".to_string())]
+        );
+    }
+}
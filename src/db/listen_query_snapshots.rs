@@ -0,0 +1,427 @@
+use crate::errors::*;
+use crate::timestamp_utils::from_timestamp;
+use crate::{
+    FirestoreDb, FirestoreListenEvent, FirestoreListener, FirestoreListenerTarget,
+    FirestoreListenerTargetParams, FirestoreMemListenStateStorage, FirestoreQueryParams,
+    FirestoreResult, FirestoreTargetType, FirestoreTypedChange, FirestoreTypedChangeType,
+    FirestoreTypedChangesTracker,
+};
+use chrono::prelude::*;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use gcloud_sdk::google::firestore::v1::target_change;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tracing::*;
+
+/// A consistent view of the documents matching a query at some point in time,
+/// produced by [`FirestoreQuerySnapshotListener`].
+#[derive(Debug, PartialEq, Clone)]
+pub struct FirestoreQuerySnapshot<T> {
+    /// All the documents matching the query with their full paths, ordered by their paths.
+    pub documents: Vec<(String, T)>,
+    /// The changes since the previous snapshot, ordered by the document paths.
+    /// For the first snapshot all the documents are reported as added.
+    pub changes: Vec<FirestoreTypedChange<T>>,
+    /// The time the snapshot is consistent at.
+    pub read_time: Option<DateTime<Utc>>,
+    /// `true` for the first snapshot of the listener.
+    pub is_initial: bool,
+}
+
+impl<T> FirestoreQuerySnapshot<T> {
+    /// The deserialized documents of the snapshot, ordered by their paths.
+    pub fn objects(&self) -> impl Iterator<Item = &T> {
+        self.documents.iter().map(|(_, object)| object)
+    }
+
+    /// The number of documents in the snapshot.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// `true` if no documents match the query.
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+}
+
+/// Coalesces raw listener events of a single query target into [`FirestoreQuerySnapshot`]s.
+///
+/// Document changes are accumulated and a snapshot is produced only at consistency points:
+/// when the target is `CURRENT` and the server reports a global read time
+/// (a `NO_CHANGE` target change without target IDs). Several changes of a document between
+/// snapshots are coalesced into one, e.g. a document added and then removed is not reported at all.
+#[derive(Debug)]
+pub struct FirestoreQuerySnapshotTracker<T> {
+    tracker: FirestoreTypedChangesTracker,
+    documents: BTreeMap<String, T>,
+    pending: BTreeMap<String, FirestoreTypedChange<T>>,
+    current: bool,
+    initial_emitted: bool,
+}
+
+impl<T> Default for FirestoreQuerySnapshotTracker<T> {
+    fn default() -> Self {
+        Self {
+            tracker: FirestoreTypedChangesTracker::new(),
+            documents: BTreeMap::new(),
+            pending: BTreeMap::new(),
+            current: false,
+            initial_emitted: false,
+        }
+    }
+}
+
+impl<T> FirestoreQuerySnapshotTracker<T>
+where
+    for<'de> T: Deserialize<'de>,
+    T: Clone,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Processes a listener event and returns the snapshot if the event is a consistency point.
+    /// Errors of deserializing documents are returned as well, the documents are skipped.
+    pub fn process_event(
+        &mut self,
+        event: FirestoreListenEvent,
+    ) -> Vec<FirestoreResult<FirestoreQuerySnapshot<T>>> {
+        let consistency_point = match &event {
+            FirestoreListenEvent::TargetChange(target_change) => {
+                match target_change::TargetChangeType::try_from(target_change.target_change_type) {
+                    Ok(target_change::TargetChangeType::Current) => {
+                        self.current = true;
+                        None
+                    }
+                    Ok(target_change::TargetChangeType::Reset) => {
+                        self.current = false;
+                        None
+                    }
+                    Ok(target_change::TargetChangeType::NoChange)
+                        if target_change.target_ids.is_empty() =>
+                    {
+                        target_change.read_time
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        let mut results = Vec::new();
+        for change in self.tracker.process_event::<T>(event) {
+            match change {
+                Ok(change) => self.apply_change(change),
+                Err(err) => results.push(Err(err)),
+            }
+        }
+
+        if let Some(read_time) = consistency_point {
+            if self.current && (!self.initial_emitted || !self.pending.is_empty()) {
+                results.push(
+                    from_timestamp(read_time).map(|read_time| self.take_snapshot(Some(read_time))),
+                );
+            }
+        }
+        results
+    }
+
+    fn apply_change(&mut self, change: FirestoreTypedChange<T>) {
+        match &change.object {
+            Some(object) if change.change_type != FirestoreTypedChangeType::Removed => {
+                self.documents
+                    .insert(change.document_path.clone(), object.clone());
+            }
+            _ => {
+                self.documents.remove(&change.document_path);
+            }
+        }
+
+        let coalesced = match self.pending.remove(&change.document_path) {
+            None => Some(change),
+            Some(previous) => match (previous.change_type, change.change_type) {
+                (FirestoreTypedChangeType::Added, FirestoreTypedChangeType::Removed) => None,
+                (FirestoreTypedChangeType::Added, _) => Some(FirestoreTypedChange {
+                    change_type: FirestoreTypedChangeType::Added,
+                    ..change
+                }),
+                (FirestoreTypedChangeType::Removed, FirestoreTypedChangeType::Added) => {
+                    Some(FirestoreTypedChange {
+                        change_type: FirestoreTypedChangeType::Modified,
+                        ..change
+                    })
+                }
+                _ => Some(change),
+            },
+        };
+        if let Some(change) = coalesced {
+            self.pending.insert(change.document_path.clone(), change);
+        }
+    }
+
+    fn take_snapshot(&mut self, read_time: Option<DateTime<Utc>>) -> FirestoreQuerySnapshot<T> {
+        let is_initial = !self.initial_emitted;
+        self.initial_emitted = true;
+        FirestoreQuerySnapshot {
+            documents: self
+                .documents
+                .iter()
+                .map(|(path, object)| (path.clone(), object.clone()))
+                .collect(),
+            changes: std::mem::take(&mut self.pending).into_values().collect(),
+            read_time,
+            is_initial,
+        }
+    }
+}
+
+/// Listens to a query and delivers consistent [`FirestoreQuerySnapshot`]s:
+/// the full result set with the list of changes since the previous snapshot.
+///
+/// Unlike raw listener events, a snapshot never contains a partially applied change,
+/// since it's produced only at the consistency points reported by the server
+/// (see [`FirestoreQuerySnapshotTracker`]).
+/// The listener resumes after connection errors without losing its state.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use firestore::*;
+/// use serde::Deserialize;
+///
+/// #[derive(Debug, Clone, Deserialize)]
+/// struct Order {
+///     total: u64,
+/// }
+///
+/// # async fn run(db: &FirestoreDb) -> FirestoreResult<()> {
+/// let mut listener: FirestoreQuerySnapshotListener<Order> = FirestoreQuerySnapshotListener::start(
+///     db,
+///     FirestoreListenerTarget::new(42),
+///     FirestoreQueryParams::new("orders".into()),
+/// )
+/// .await?;
+///
+/// while let Some(snapshot) = listener.next_snapshot().await {
+///     let snapshot = snapshot?;
+///     let total: u64 = snapshot.objects().map(|order| order.total).sum();
+///     println!("{} orders, {} changed, total: {total}", snapshot.len(), snapshot.changes.len());
+/// }
+///
+/// listener.shutdown().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct FirestoreQuerySnapshotListener<T> {
+    snapshots: UnboundedReceiver<FirestoreResult<FirestoreQuerySnapshot<T>>>,
+    listener: FirestoreListener<FirestoreDb, FirestoreMemListenStateStorage>,
+}
+
+impl<T> FirestoreQuerySnapshotListener<T>
+where
+    for<'de> T: Deserialize<'de>,
+    T: Clone + Send + 'static,
+{
+    /// Starts listening to the query using the specified listener target.
+    ///
+    /// The target ID must be unique among the listeners of the application.
+    pub async fn start(
+        db: &FirestoreDb,
+        target: FirestoreListenerTarget,
+        params: FirestoreQueryParams,
+    ) -> FirestoreResult<Self> {
+        let (tx, snapshots) = tokio::sync::mpsc::unbounded_channel();
+        let tracker = Arc::new(Mutex::new(FirestoreQuerySnapshotTracker::<T>::new()));
+
+        let mut listener = db
+            .create_listener(FirestoreMemListenStateStorage::new())
+            .await?;
+        listener.add_target(FirestoreListenerTargetParams::new(
+            target,
+            FirestoreTargetType::Query(params),
+            Default::default(),
+        ))?;
+
+        listener.enable_resume_token_events();
+        listener
+            .start(move |event| {
+                let tx = tx.clone();
+                let tracker = tracker.clone();
+                async move {
+                    let results = tracker
+                        .lock()
+                        .map_err(|err| {
+                            FirestoreError::SystemError(FirestoreSystemError::new(
+                                FirestoreErrorPublicGenericDetails::new("SystemError".into()),
+                                format!("Query snapshot tracker error: {err}"),
+                            ))
+                        })?
+                        .process_event(event);
+
+                    for result in results {
+                        if tx.send(result).is_err() {
+                            debug!(
+                                "Query snapshots receiver has been dropped. Ignoring snapshots."
+                            );
+                            break;
+                        }
+                    }
+                    Ok(())
+                }
+            })
+            .await?;
+
+        Ok(Self {
+            snapshots,
+            listener,
+        })
+    }
+
+    /// Waits for the next snapshot. Returns `None` after the listener is shut down.
+    pub async fn next_snapshot(&mut self) -> Option<FirestoreResult<FirestoreQuerySnapshot<T>>> {
+        self.snapshots.recv().await
+    }
+
+    /// The stream of the next snapshots.
+    pub fn snapshots(&mut self) -> BoxStream<'_, FirestoreResult<FirestoreQuerySnapshot<T>>> {
+        futures::stream::unfold(&mut self.snapshots, |snapshots| async move {
+            snapshots.recv().await.map(|snapshot| (snapshot, snapshots))
+        })
+        .boxed()
+    }
+
+    /// Stops listening to the query.
+    pub async fn shutdown(mut self) -> FirestoreResult<()> {
+        self.listener.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcloud_sdk::google::firestore::v1::{DocumentChange, DocumentDelete, TargetChange};
+    use serde::Serialize;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct TestStructure {
+        some_num: u64,
+    }
+
+    fn doc_change(name: &str, some_num: u64, seconds: i64) -> FirestoreListenEvent {
+        let mut doc = FirestoreDb::serialize_to_doc(name, &TestStructure { some_num }).unwrap();
+        doc.update_time = Some(gcloud_sdk::prost_types::Timestamp { seconds, nanos: 0 });
+        FirestoreListenEvent::DocumentChange(DocumentChange {
+            document: Some(doc),
+            target_ids: vec![1],
+            removed_target_ids: vec![],
+        })
+    }
+
+    fn doc_delete(name: &str) -> FirestoreListenEvent {
+        FirestoreListenEvent::DocumentDelete(DocumentDelete {
+            document: name.to_string(),
+            removed_target_ids: vec![1],
+            read_time: None,
+        })
+    }
+
+    fn target_change(
+        change_type: target_change::TargetChangeType,
+        target_ids: Vec<i32>,
+        seconds: Option<i64>,
+    ) -> FirestoreListenEvent {
+        FirestoreListenEvent::TargetChange(TargetChange {
+            target_change_type: change_type.into(),
+            target_ids,
+            read_time: seconds
+                .map(|seconds| gcloud_sdk::prost_types::Timestamp { seconds, nanos: 0 }),
+            ..Default::default()
+        })
+    }
+
+    fn consistency_point(seconds: i64) -> FirestoreListenEvent {
+        target_change(
+            target_change::TargetChangeType::NoChange,
+            vec![],
+            Some(seconds),
+        )
+    }
+
+    fn snapshot_changes(
+        snapshot: &FirestoreQuerySnapshot<TestStructure>,
+    ) -> Vec<(FirestoreTypedChangeType, &str)> {
+        snapshot
+            .changes
+            .iter()
+            .map(|change| (change.change_type, change.document_path.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn query_snapshots_at_consistency_points() {
+        let mut tracker = FirestoreQuerySnapshotTracker::<TestStructure>::new();
+
+        assert!(tracker.process_event(doc_change("docs/b", 2, 1)).is_empty());
+        assert!(tracker.process_event(doc_change("docs/a", 1, 1)).is_empty());
+        // Not consistent before the target is current
+        assert!(tracker.process_event(consistency_point(1)).is_empty());
+        assert!(tracker
+            .process_event(target_change(
+                target_change::TargetChangeType::Current,
+                vec![1],
+                None
+            ))
+            .is_empty());
+
+        let mut snapshots = tracker.process_event(consistency_point(2));
+        assert_eq!(snapshots.len(), 1);
+        let snapshot = snapshots.remove(0).unwrap();
+        assert!(snapshot.is_initial);
+        assert_eq!(
+            snapshot.objects().cloned().collect::<Vec<_>>(),
+            vec![TestStructure { some_num: 1 }, TestStructure { some_num: 2 }]
+        );
+        assert_eq!(
+            snapshot_changes(&snapshot),
+            vec![
+                (FirestoreTypedChangeType::Added, "docs/a"),
+                (FirestoreTypedChangeType::Added, "docs/b")
+            ]
+        );
+
+        // Nothing changed
+        assert!(tracker.process_event(consistency_point(3)).is_empty());
+
+        // Changes between consistency points are coalesced
+        tracker.process_event(doc_change("docs/c", 3, 4));
+        tracker.process_event(doc_delete("docs/c"));
+        tracker.process_event(doc_change("docs/a", 4, 4));
+        tracker.process_event(doc_change("docs/a", 5, 5));
+        tracker.process_event(doc_delete("docs/b"));
+
+        let snapshot = tracker
+            .process_event(consistency_point(5))
+            .remove(0)
+            .unwrap();
+        assert!(!snapshot.is_initial);
+        assert_eq!(
+            snapshot.documents,
+            vec![("docs/a".to_string(), TestStructure { some_num: 5 })]
+        );
+        assert_eq!(
+            snapshot_changes(&snapshot),
+            vec![
+                (FirestoreTypedChangeType::Modified, "docs/a"),
+                (FirestoreTypedChangeType::Removed, "docs/b")
+            ]
+        );
+        assert_eq!(
+            snapshot.changes[0].object,
+            Some(TestStructure { some_num: 5 })
+        );
+    }
+}
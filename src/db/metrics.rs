@@ -0,0 +1,289 @@
+use crate::errors::*;
+use crate::*;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use rsb_derive::*;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::*;
+
+/// The type of a Firestore operation reported to metrics and tracing spans.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum FirestoreOperationKind {
+    Get,
+    BatchGet,
+    Create,
+    Update,
+    Delete,
+    Query,
+    AggregatedQuery,
+    ListDocuments,
+    ListCollectionIds,
+    BatchWrite,
+    Commit,
+    BeginTransaction,
+    Rollback,
+    PartitionQuery,
+    Listen,
+}
+
+impl FirestoreOperationKind {
+    /// The name of the operation, as used in the `/firestore/operation` span field.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FirestoreOperationKind::Get => "get",
+            FirestoreOperationKind::BatchGet => "batch_get",
+            FirestoreOperationKind::Create => "create",
+            FirestoreOperationKind::Update => "update",
+            FirestoreOperationKind::Delete => "delete",
+            FirestoreOperationKind::Query => "query",
+            FirestoreOperationKind::AggregatedQuery => "aggregated_query",
+            FirestoreOperationKind::ListDocuments => "list_documents",
+            FirestoreOperationKind::ListCollectionIds => "list_collection_ids",
+            FirestoreOperationKind::BatchWrite => "batch_write",
+            FirestoreOperationKind::Commit => "commit",
+            FirestoreOperationKind::BeginTransaction => "begin_transaction",
+            FirestoreOperationKind::Rollback => "rollback",
+            FirestoreOperationKind::PartitionQuery => "partition_query",
+            FirestoreOperationKind::Listen => "listen",
+        }
+    }
+}
+
+impl Display for FirestoreOperationKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Metrics of a single request to Firestore.
+#[derive(Debug, Clone, Builder)]
+pub struct FirestoreOperationMetrics {
+    /// The type of the operation.
+    pub operation: FirestoreOperationKind,
+    /// The collection ID of the operation, if it targets a collection.
+    pub collection_id: Option<String>,
+    /// The number of documents read, written or deleted.
+    pub document_count: usize,
+    /// The time from sending the request until the response has been consumed.
+    pub duration: Duration,
+    /// The code of the error if the operation failed, e.g. `Unavailable`.
+    pub error_code: Option<String>,
+}
+
+/// A sink for the metrics of Firestore operations, e.g. to export them to Prometheus.
+///
+/// The sink is called once for each request sent to Firestore, including retries.
+/// Requests returning streams are recorded when the stream is finished or dropped,
+/// e.g. listen streams when they are restarted, counting the changed documents.
+/// Implementations should be cheap, since they are called on the request path.
+pub trait FirestoreMetricsSink {
+    fn record_operation(&self, metrics: &FirestoreOperationMetrics);
+}
+
+/// A thread-safe, shareable metrics sink.
+pub type FirestoreSharedMetricsSink = Arc<dyn FirestoreMetricsSink + Send + Sync + 'static>;
+
+/// Returns the short code of the error reported to metrics.
+pub fn firestore_error_code(err: &FirestoreError) -> String {
+    match err {
+        FirestoreError::SystemError(err) => err.public.code.clone(),
+        FirestoreError::DatabaseError(err) => err.public.code.clone(),
+        FirestoreError::DataConflictError(err) => err.public.code.clone(),
+        FirestoreError::DataNotFoundError(err) => err.public.code.clone(),
+        FirestoreError::InvalidParametersError(_) => "InvalidParameters".to_string(),
+        FirestoreError::SerializeError(_) => "SerializeError".to_string(),
+        FirestoreError::DeserializeError(_) => "DeserializeError".to_string(),
+        FirestoreError::NetworkError(err) => err.public.code.clone(),
+        FirestoreError::ErrorInTransaction(_) => "ErrorInTransaction".to_string(),
+        FirestoreError::CacheError(err) => err.public.code.clone(),
+        FirestoreError::ConditionFailedError(err) => err.public.code.clone(),
+        FirestoreError::MissingIndexError(_) => "MissingIndex".to_string(),
+        FirestoreError::ProjectionError(_) => "ProjectionError".to_string(),
+    }
+}
+
+/// Records the metrics of a request when dropped: to the span fields
+/// `/firestore/operation` and `/firestore/document_count`, and to the metrics sink.
+pub(crate) struct FirestoreOperationRecorder {
+    sink: Option<FirestoreSharedMetricsSink>,
+    span: Span,
+    operation: FirestoreOperationKind,
+    collection_id: Option<String>,
+    started: Instant,
+    document_count: usize,
+    error_code: Option<String>,
+}
+
+impl FirestoreOperationRecorder {
+    /// Starts recording the metrics of a request to the sink.
+    pub(crate) fn start(
+        sink: Option<FirestoreSharedMetricsSink>,
+        operation: FirestoreOperationKind,
+        collection_id: Option<&str>,
+        span: &Span,
+    ) -> Self {
+        Self {
+            sink,
+            span: span.clone(),
+            operation,
+            collection_id: collection_id.map(|collection_id| collection_id.to_string()),
+            started: Instant::now(),
+            document_count: 0,
+            error_code: None,
+        }
+    }
+
+    /// Records the result of a request with the number of affected documents.
+    pub(crate) fn record_result<T>(mut self, result: &FirestoreResult<T>, document_count: usize) {
+        match result {
+            Ok(_) => self.document_count = document_count,
+            Err(err) => self.record_error(err),
+        }
+    }
+
+    /// Records a successful request with the number of affected documents.
+    pub(crate) fn record_success(mut self, document_count: usize) {
+        self.document_count = document_count;
+    }
+
+    /// Records the error of a failed request.
+    pub(crate) fn record_failure(mut self, err: &FirestoreError) {
+        self.record_error(err);
+    }
+
+    fn record_error(&mut self, err: &FirestoreError) {
+        if self.error_code.is_none() {
+            self.error_code = Some(firestore_error_code(err));
+        }
+    }
+
+    /// Counts the documents of the response stream, recording the metrics when
+    /// the stream is finished or dropped.
+    pub(crate) fn record_stream<'b, T>(
+        mut self,
+        stream: BoxStream<'b, FirestoreResult<T>>,
+        document_count: fn(&T) -> usize,
+    ) -> BoxStream<'b, FirestoreResult<T>>
+    where
+        T: Send + 'b,
+    {
+        stream
+            .map(move |item| {
+                match &item {
+                    Ok(value) => self.document_count += document_count(value),
+                    Err(err) => self.record_error(err),
+                }
+                item
+            })
+            .boxed()
+    }
+
+    /// Records the result of a request returning a response stream.
+    pub(crate) fn record_stream_result<'b, T>(
+        mut self,
+        result: FirestoreResult<BoxStream<'b, FirestoreResult<T>>>,
+        document_count: fn(&T) -> usize,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<T>>>
+    where
+        T: Send + 'b,
+    {
+        match result {
+            Ok(stream) => Ok(self.record_stream(stream, document_count)),
+            Err(err) => {
+                self.record_error(&err);
+                Err(err)
+            }
+        }
+    }
+}
+
+impl Drop for FirestoreOperationRecorder {
+    fn drop(&mut self) {
+        self.span
+            .record("/firestore/operation", self.operation.as_str());
+        self.span
+            .record("/firestore/document_count", self.document_count);
+
+        if let Some(sink) = &self.sink {
+            sink.record_operation(&FirestoreOperationMetrics {
+                operation: self.operation,
+                collection_id: self.collection_id.take(),
+                document_count: self.document_count,
+                duration: self.started.elapsed(),
+                error_code: self.error_code.take(),
+            });
+        }
+    }
+}
+
+impl FirestoreDb {
+    /// Starts recording the metrics of a request to Firestore.
+    pub(crate) fn record_operation(
+        &self,
+        operation: FirestoreOperationKind,
+        collection_id: Option<&str>,
+        span: &Span,
+    ) -> FirestoreOperationRecorder {
+        FirestoreOperationRecorder::start(
+            self.inner.metrics_sink.clone(),
+            operation,
+            collection_id,
+            span,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct TestMetricsSink {
+        recorded: Mutex<Vec<FirestoreOperationMetrics>>,
+    }
+
+    impl FirestoreMetricsSink for TestMetricsSink {
+        fn record_operation(&self, metrics: &FirestoreOperationMetrics) {
+            self.recorded.lock().unwrap().push(metrics.clone());
+        }
+    }
+
+    fn recorder(sink: &Arc<TestMetricsSink>) -> FirestoreOperationRecorder {
+        FirestoreOperationRecorder::start(
+            Some(sink.clone()),
+            FirestoreOperationKind::Query,
+            Some("test"),
+            &Span::none(),
+        )
+    }
+
+    #[tokio::test]
+    async fn records_results_and_streams() {
+        let sink = Arc::new(TestMetricsSink::default());
+
+        recorder(&sink).record_result::<()>(
+            &Err(FirestoreError::DataNotFoundError(
+                FirestoreDataNotFoundError::new(
+                    FirestoreErrorPublicGenericDetails::new("NotFound".to_string()),
+                    "test".to_string(),
+                ),
+            )),
+            1,
+        );
+
+        let stream =
+            recorder(&sink).record_stream(futures::stream::iter(vec![Ok(1), Ok(2)]).boxed(), |_| 1);
+        assert_eq!(stream.collect::<Vec<_>>().await.len(), 2);
+
+        let recorded = sink.recorded.lock().unwrap();
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].error_code.as_deref(), Some("NotFound"));
+        assert_eq!(recorded[0].document_count, 0);
+        assert_eq!(recorded[1].error_code, None);
+        assert_eq!(recorded[1].document_count, 2);
+        assert_eq!(recorded[1].collection_id.as_deref(), Some("test"));
+    }
+}
@@ -0,0 +1,322 @@
+use crate::{FirestoreDb, FirestoreResult};
+use gcloud_sdk::google::firestore::v1::*;
+use gcloud_sdk::tonic::metadata::MetadataMap;
+use std::sync::Arc;
+
+/// A request to the Firestore API passed to middlewares before it is sent.
+#[derive(Debug)]
+pub enum FirestoreApiRequest<'a> {
+    GetDocument(&'a mut GetDocumentRequest),
+    BatchGetDocuments(&'a mut BatchGetDocumentsRequest),
+    RunQuery(&'a mut RunQueryRequest),
+    RunAggregationQuery(&'a mut RunAggregationQueryRequest),
+    PartitionQuery(&'a mut PartitionQueryRequest),
+    ListDocuments(&'a mut ListDocumentsRequest),
+    ListCollectionIds(&'a mut ListCollectionIdsRequest),
+    CreateDocument(&'a mut CreateDocumentRequest),
+    UpdateDocument(&'a mut UpdateDocumentRequest),
+    DeleteDocument(&'a mut DeleteDocumentRequest),
+    Commit(&'a mut CommitRequest),
+    BatchWrite(&'a mut BatchWriteRequest),
+    Write(&'a mut WriteRequest),
+    Listen(&'a mut ListenRequest),
+    BeginTransaction(&'a mut BeginTransactionRequest),
+    Rollback(&'a mut RollbackRequest),
+}
+
+/// A response of the Firestore API passed to middlewares.
+/// Streaming responses are passed message by message.
+#[derive(Debug, Clone, Copy)]
+pub enum FirestoreApiResponse<'a> {
+    GetDocument(&'a Document),
+    BatchGetDocuments(&'a BatchGetDocumentsResponse),
+    RunQuery(&'a RunQueryResponse),
+    RunAggregationQuery(&'a RunAggregationQueryResponse),
+    PartitionQuery(&'a PartitionQueryResponse),
+    ListDocuments(&'a ListDocumentsResponse),
+    ListCollectionIds(&'a ListCollectionIdsResponse),
+    CreateDocument(&'a Document),
+    UpdateDocument(&'a Document),
+    DeleteDocument,
+    Commit(&'a CommitResponse),
+    BatchWrite(&'a BatchWriteResponse),
+    Write(&'a WriteResponse),
+    Listen(&'a ListenResponse),
+    BeginTransaction(&'a BeginTransactionResponse),
+    Rollback,
+}
+
+/// A middleware between the library and the Firestore API, e.g. to add routing headers,
+/// check that document paths belong to a tenant or audit writes.
+///
+/// Middlewares are called in the order they are added with [`FirestoreDb::with_middleware`]
+/// for each request sent to Firestore, including retries, and for each successful response.
+/// Admin API requests aren't passed to middlewares.
+pub trait FirestoreMiddleware {
+    /// Inspects or mutates a request before it is sent.
+    /// Returning an error fails the operation without sending the request.
+    ///
+    /// `metadata` contains the gRPC headers of the request. It is `None` for the messages
+    /// of streaming requests (listening to changes and streaming batch writes).
+    fn on_request(
+        &self,
+        _request: FirestoreApiRequest<'_>,
+        _metadata: Option<&mut MetadataMap>,
+    ) -> FirestoreResult<()> {
+        Ok(())
+    }
+
+    /// Inspects a response received from Firestore.
+    fn on_response(&self, _response: FirestoreApiResponse<'_>) {}
+}
+
+/// A thread-safe, shareable middleware.
+pub type FirestoreSharedMiddleware = Arc<dyn FirestoreMiddleware + Send + Sync + 'static>;
+
+/// A request message of the Firestore API that can be passed to middlewares.
+pub(crate) trait FirestoreApiRequestMessage {
+    fn as_api_request(&mut self) -> FirestoreApiRequest<'_>;
+}
+
+macro_rules! firestore_api_request_messages {
+    ($($message:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl FirestoreApiRequestMessage for $message {
+                fn as_api_request(&mut self) -> FirestoreApiRequest<'_> {
+                    FirestoreApiRequest::$variant(self)
+                }
+            }
+        )*
+    };
+}
+
+firestore_api_request_messages! {
+    GetDocumentRequest => GetDocument,
+    BatchGetDocumentsRequest => BatchGetDocuments,
+    RunQueryRequest => RunQuery,
+    RunAggregationQueryRequest => RunAggregationQuery,
+    PartitionQueryRequest => PartitionQuery,
+    ListDocumentsRequest => ListDocuments,
+    ListCollectionIdsRequest => ListCollectionIds,
+    CreateDocumentRequest => CreateDocument,
+    UpdateDocumentRequest => UpdateDocument,
+    DeleteDocumentRequest => DeleteDocument,
+    CommitRequest => Commit,
+    BatchWriteRequest => BatchWrite,
+    WriteRequest => Write,
+    ListenRequest => Listen,
+    BeginTransactionRequest => BeginTransaction,
+    RollbackRequest => Rollback,
+}
+
+/// Passes the request with its metadata to the middlewares.
+pub(crate) fn firestore_apply_request_middlewares<T>(
+    middlewares: &[FirestoreSharedMiddleware],
+    request: gcloud_sdk::tonic::Request<T>,
+) -> FirestoreResult<gcloud_sdk::tonic::Request<T>>
+where
+    T: FirestoreApiRequestMessage,
+{
+    if middlewares.is_empty() {
+        return Ok(request);
+    }
+
+    let (mut metadata, extensions, mut message) = request.into_parts();
+    for middleware in middlewares {
+        middleware.on_request(message.as_api_request(), Some(&mut metadata))?;
+    }
+    Ok(gcloud_sdk::tonic::Request::from_parts(
+        metadata, extensions, message,
+    ))
+}
+
+/// Checks that the client accepts the request and passes it to the middlewares.
+pub(super) fn firestore_prepare_request<T>(
+    db_inner: &crate::db::FirestoreDbInner,
+    mut request: gcloud_sdk::tonic::Request<T>,
+) -> FirestoreResult<gcloud_sdk::tonic::Request<T>>
+where
+    T: FirestoreApiRequestMessage,
+{
+    db_inner
+        .shutdown
+        .check_request(request.get_mut().as_api_request())?;
+    firestore_apply_request_middlewares(&db_inner.middlewares, request)
+}
+
+/// Passes the response to the middlewares.
+pub(crate) fn firestore_notify_response_middlewares(
+    middlewares: &[FirestoreSharedMiddleware],
+    response: FirestoreApiResponse<'_>,
+) {
+    for middleware in middlewares {
+        middleware.on_response(response);
+    }
+}
+
+impl FirestoreDb {
+    /// Clones the `FirestoreDb` instance passing all requests to Firestore and their responses
+    /// to the middleware, after the middlewares already added.
+    ///
+    /// The returned instance shares the underlying gRPC connection and authentication
+    /// with the original one.
+    ///
+    /// # Arguments
+    /// * `middleware`: The [`FirestoreMiddleware`] to add.
+    pub fn with_middleware(&self, middleware: FirestoreSharedMiddleware) -> Self {
+        let mut middlewares = self.inner.middlewares.clone();
+        middlewares.push(middleware);
+        Self {
+            inner: Arc::new(crate::db::FirestoreDbInner {
+                database_path: self.inner.database_path.clone(),
+                doc_path: self.inner.doc_path.clone(),
+                options: self.inner.options.clone(),
+                client: self.inner.client.clone(),
+                metrics_sink: self.inner.metrics_sink.clone(),
+                middlewares,
+                shutdown: self.inner.shutdown.clone(),
+                #[cfg(feature = "caching")]
+                cache_revalidations: Default::default(),
+            }),
+            session_params: self.session_params.clone(),
+        }
+    }
+
+    /// Creates a request to the Firestore API with the current timeout,
+    /// passing it to the middlewares.
+    pub(crate) fn create_api_request<T>(
+        &self,
+        message: T,
+    ) -> FirestoreResult<gcloud_sdk::tonic::Request<T>>
+    where
+        T: FirestoreApiRequestMessage,
+    {
+        firestore_prepare_request(&self.inner, self.create_request(message))
+    }
+
+    /// Passes a message of a streaming request to the middlewares.
+    pub(crate) fn apply_message_middlewares<T>(&self, message: &mut T) -> FirestoreResult<()>
+    where
+        T: FirestoreApiRequestMessage,
+    {
+        self.inner
+            .shutdown
+            .check_request(message.as_api_request())?;
+        for middleware in &self.inner.middlewares {
+            middleware.on_request(message.as_api_request(), None)?;
+        }
+        Ok(())
+    }
+
+    /// Passes a response of the Firestore API to the middlewares.
+    pub(crate) fn notify_response_middlewares(&self, response: FirestoreApiResponse<'_>) {
+        firestore_notify_response_middlewares(&self.inner.middlewares, response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct TenantMiddleware {
+        written: Mutex<Vec<String>>,
+    }
+
+    impl FirestoreMiddleware for TenantMiddleware {
+        fn on_request(
+            &self,
+            request: FirestoreApiRequest<'_>,
+            metadata: Option<&mut MetadataMap>,
+        ) -> FirestoreResult<()> {
+            if let Some(metadata) = metadata {
+                metadata.insert("x-tenant", "tenant-1".parse().unwrap());
+            }
+            match request {
+                FirestoreApiRequest::GetDocument(request)
+                    if !request.name.contains("/tenants/tenant-1/") =>
+                {
+                    Err(FirestoreError::InvalidParametersError(
+                        FirestoreInvalidParametersError::new(
+                            FirestoreInvalidParametersPublicDetails::new(
+                                "name".to_string(),
+                                "Document of another tenant".to_string(),
+                            ),
+                        ),
+                    ))
+                }
+                FirestoreApiRequest::GetDocument(request) => {
+                    request.mask = Some(DocumentMask {
+                        field_paths: vec!["name".to_string()],
+                    });
+                    Ok(())
+                }
+                _ => Ok(()),
+            }
+        }
+
+        fn on_response(&self, response: FirestoreApiResponse<'_>) {
+            if let FirestoreApiResponse::Commit(response) = response {
+                self.written
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} writes", response.write_results.len()));
+            }
+        }
+    }
+
+    #[test]
+    fn middlewares_mutate_and_reject_requests() {
+        let middleware = Arc::new(TenantMiddleware::default());
+        let middlewares: Vec<FirestoreSharedMiddleware> = vec![middleware.clone()];
+
+        let request = firestore_apply_request_middlewares(
+            &middlewares,
+            gcloud_sdk::tonic::Request::new(GetDocumentRequest {
+                name: "projects/p/databases/d/documents/tenants/tenant-1/users/u1".to_string(),
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+        assert_eq!(
+            request
+                .metadata()
+                .get("x-tenant")
+                .unwrap()
+                .to_str()
+                .unwrap(),
+            "tenant-1"
+        );
+        assert_eq!(
+            request
+                .get_ref()
+                .mask
+                .as_ref()
+                .map(|mask| &mask.field_paths),
+            Some(&vec!["name".to_string()])
+        );
+
+        let rejected = firestore_apply_request_middlewares(
+            &middlewares,
+            gcloud_sdk::tonic::Request::new(GetDocumentRequest {
+                name: "projects/p/databases/d/documents/tenants/tenant-2/users/u1".to_string(),
+                ..Default::default()
+            }),
+        );
+        assert!(matches!(
+            rejected,
+            Err(FirestoreError::InvalidParametersError(_))
+        ));
+
+        firestore_notify_response_middlewares(
+            &middlewares,
+            FirestoreApiResponse::Commit(&CommitResponse {
+                write_results: vec![WriteResult::default()],
+                commit_time: None,
+            }),
+        );
+        assert_eq!(*middleware.written.lock().unwrap(), vec!["1 writes"]);
+    }
+}
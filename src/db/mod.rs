@@ -22,6 +22,9 @@ pub use delete::*;
 mod query_models;
 pub use query_models::*;
 
+/// Module for normalizing queries and generating their cache keys.
+mod query_normalization;
+
 /// Module defining models for preconditions (e.g., last update time).
 mod precondition_models;
 pub use precondition_models::*;
@@ -30,10 +33,22 @@ pub use precondition_models::*;
 mod query;
 pub use query::*;
 
+/// Module for queries split by the filters exceeding the Firestore limits on the number of values.
+mod query_split;
+pub use query_split::*;
+
 /// Module for aggregated query execution.
 mod aggregated_query;
 pub use aggregated_query::*;
 
+/// Module for aggregations computed separately for ranges of field values.
+mod aggregated_query_buckets;
+pub use aggregated_query_buckets::*;
+
+/// Module for client-side group-by aggregations over query results.
+mod group_by;
+pub use group_by::*;
+
 /// Module for listing documents or collections.
 mod list;
 pub use list::*;
@@ -46,8 +61,35 @@ pub use listen_changes::*;
 mod listen_changes_state_storage;
 pub use listen_changes_state_storage::*;
 
+/// Module for the lifecycle events and the health of listeners.
+mod listen_changes_status;
+pub use listen_changes_status::*;
+
+/// Module for typed document change streams built on top of listeners.
+mod listen_changes_typed;
+pub use listen_changes_typed::*;
+
+/// Module for delivering listener changes to external sinks with checkpointing.
+mod listen_changes_sink;
+pub use listen_changes_sink::*;
+
+/// Module for in-memory views of collections kept up to date with listeners.
+mod collection_mirror;
+pub use collection_mirror::*;
+
+/// Module for consistent query snapshots built on top of listeners.
+mod listen_query_snapshots;
+pub use listen_query_snapshots::*;
+
+/// Module for comparing the states of documents at two read times.
+mod document_diff;
+pub use document_diff::*;
+
+/// Module for streaming the descendants of documents recursively.
+mod document_tree;
+pub use document_tree::*;
+
 use crate::*;
-use gcloud_sdk::google::firestore::v1::firestore_client::FirestoreClient;
 use gcloud_sdk::google::firestore::v1::*;
 use gcloud_sdk::*;
 // Re-export serde for convenience as it's often used with Firestore documents.
@@ -94,6 +136,18 @@ pub use batch_streaming_writer::*;
 mod batch_simple_writer;
 pub use batch_simple_writer::*;
 
+/// Module for parallel bulk write operations with per-document retries.
+mod batch_bulk_writer;
+pub use batch_bulk_writer::*;
+
+/// Module for atomic batch writes committed with a single request.
+mod batch_commit_writer;
+pub use batch_commit_writer::*;
+
+/// Module for ramping up the traffic of batch writers.
+mod write_ramp_up;
+pub use write_ramp_up::*;
+
 use crate::errors::{
     FirestoreError, FirestoreInvalidParametersError, FirestoreInvalidParametersPublicDetails,
 };
@@ -104,13 +158,61 @@ use std::sync::Arc;
 mod transform_models;
 pub use transform_models::*;
 
+/// Module for the policy of retrying failed operations.
+mod retry_policy;
+pub use retry_policy::*;
+
+/// Module for the deadlines of Firestore requests.
+mod timeout;
+pub(crate) use timeout::*;
+
+/// Internal module carrying the settings of fluent operations over to spawned tasks.
+mod task_scopes;
+pub(crate) use task_scopes::*;
+
+/// Module for the metrics of Firestore operations.
+mod metrics;
+pub use metrics::*;
+
+/// Module for middlewares inspecting and mutating the requests to Firestore.
+mod middleware;
+pub use middleware::*;
+
+/// Module for the gRPC client with a pool of channels.
+mod api_client;
+pub use api_client::*;
+
+/// Module for custom sources of authentication tokens.
+mod token_source;
+pub use token_source::*;
+
+/// Module for the graceful shutdown of the client.
+mod shutdown;
+pub use shutdown::*;
+
+/// Module for tokens impersonating service accounts.
+/// This is only available if the `impersonation` feature is enabled.
+#[cfg(feature = "impersonation")]
+mod token_source_impersonation;
+#[cfg(feature = "impersonation")]
+pub use token_source_impersonation::*;
+
+/// Internal module applying cache consistency strategies for sessions with a cache.
+#[cfg(feature = "caching")]
+mod session_cache;
+
 /// Internal struct holding the core components of the Firestore database client.
 /// This includes the database path, document path prefix, options, and the gRPC client.
 struct FirestoreDbInner {
     database_path: String,
     doc_path: String,
     options: FirestoreDbOptions,
-    client: GoogleApi<FirestoreClient<GoogleAuthMiddleware>>,
+    client: FirestoreApiClient,
+    metrics_sink: Option<FirestoreSharedMetricsSink>,
+    middlewares: Vec<FirestoreSharedMiddleware>,
+    shutdown: Arc<FirestoreDbShutdownState>,
+    #[cfg(feature = "caching")]
+    cache_revalidations: session_cache::FirestoreCacheRevalidations,
 }
 
 /// The main entry point for interacting with a Google Firestore database.
@@ -129,6 +231,7 @@ pub struct FirestoreDb {
 
 const GOOGLE_FIREBASE_API_URL: &str = "https://firestore.googleapis.com";
 const GOOGLE_FIRESTORE_EMULATOR_HOST_ENV: &str = "FIRESTORE_EMULATOR_HOST";
+const GOOGLE_FIRESTORE_EMULATOR_DEFAULT_URL: &str = "http://localhost:8080";
 
 impl FirestoreDb {
     /// Creates a new `FirestoreDb` instance with the specified Google Project ID.
@@ -176,6 +279,37 @@ impl FirestoreDb {
         .await
     }
 
+    /// Creates a new `FirestoreDb` instance connected to the Firestore emulator.
+    ///
+    /// Connections use plaintext and no authentication tokens are acquired,
+    /// so it works without GCP credentials in the environment.
+    ///
+    /// # Arguments
+    /// * `google_project_id`: The project ID to use in the emulator.
+    /// * `emulator_host`: The emulator host and port, e.g. `localhost:8080`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use firestore::*;
+    ///
+    /// # async fn run() -> FirestoreResult<()> {
+    /// let db = FirestoreDb::for_emulator("test-project", "localhost:8080").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn for_emulator<S, H>(google_project_id: S, emulator_host: H) -> FirestoreResult<Self>
+    where
+        S: AsRef<str>,
+        H: AsRef<str>,
+    {
+        Self::with_options(
+            FirestoreDbOptions::new(google_project_id.as_ref().to_string())
+                .with_firebase_api_url(ensure_url_scheme(emulator_host.as_ref().to_string()))
+                .with_emulator(true),
+        )
+        .await
+    }
+
     /// Creates a new `FirestoreDb` instance attempting to infer the Google Project ID
     /// from the environment (e.g., Application Default Credentials).
     ///
@@ -213,6 +347,28 @@ impl FirestoreDb {
         .await
     }
 
+    /// Creates a new `FirestoreDb` instance authenticated with a custom [`FirestoreTokenSource`]
+    /// instead of the credentials detected from the environment,
+    /// e.g. with [`FirestoreCredentialsTokenSource`] for a service account key stored in a secrets manager.
+    ///
+    /// # Arguments
+    /// * `options`: The [`FirestoreDbOptions`] to configure the client.
+    /// * `token_source`: The source of the access tokens.
+    pub async fn with_options_custom_token_source<S>(
+        options: FirestoreDbOptions,
+        token_source: S,
+    ) -> FirestoreResult<Self>
+    where
+        S: FirestoreTokenSource + Send + Sync + 'static,
+    {
+        Self::with_options_token_source(
+            options,
+            GCP_DEFAULT_SCOPES.clone(),
+            FirestoreTokenSourceAdapter::token_source_type(Arc::new(token_source)),
+        )
+        .await
+    }
+
     /// Creates a new `FirestoreDb` instance with full control over options, token scopes,
     /// and token source type.
     ///
@@ -229,35 +385,33 @@ impl FirestoreDb {
         token_scopes: Vec<String>,
         token_source_type: TokenSourceType,
     ) -> FirestoreResult<Self> {
-        let firestore_database_path = format!(
-            "projects/{}/databases/{}",
-            options.google_project_id, options.database_id
+        let (firestore_database_path, firestore_database_doc_path) = database_paths(&options);
+
+        let (effective_firebase_api_url, emulator) = resolve_api_endpoint(
+            &options,
+            std::env::var(GOOGLE_FIRESTORE_EMULATOR_HOST_ENV).ok(),
         );
-        let firestore_database_doc_path = format!("{firestore_database_path}/documents");
 
-        let effective_firebase_api_url = options
-            .firebase_api_url
-            .clone()
-            .or_else(|| {
-                std::env::var(GOOGLE_FIRESTORE_EMULATOR_HOST_ENV)
-                    .ok()
-                    .map(ensure_url_scheme)
-            })
-            .unwrap_or_else(|| GOOGLE_FIREBASE_API_URL.to_string());
+        let token_source_type = if emulator {
+            emulator_token_source()
+        } else {
+            token_source_type
+        };
 
         info!(
             database_path = firestore_database_path,
             api_url = effective_firebase_api_url,
             token_scopes = token_scopes.join(", "),
+            emulator,
             "Creating a new database client.",
         );
 
-        let client = GoogleApiClient::from_function_with_token_source(
-            FirestoreClient::new,
+        let client = FirestoreApiClient::connect(
             effective_firebase_api_url,
-            Some(firestore_database_path.clone()),
+            firestore_database_path.clone(),
             token_scopes,
             token_source_type,
+            &options.connection,
         )
         .await?;
 
@@ -266,6 +420,11 @@ impl FirestoreDb {
             doc_path: firestore_database_doc_path,
             client,
             options,
+            metrics_sink: None,
+            middlewares: Vec::new(),
+            shutdown: Default::default(),
+            #[cfg(feature = "caching")]
+            cache_revalidations: Default::default(),
         };
 
         Ok(Self {
@@ -397,7 +556,7 @@ impl FirestoreDb {
     /// * `document_id`: The ID of the parent document.
     ///
     /// # Errors
-    /// Returns [`FirestoreError::InvalidParametersError`] if the `document_id` is invalid.
+    /// Returns [`FirestoreError::InvalidParametersError`] if the `collection_name` or `document_id` is invalid.
     #[inline]
     pub fn parent_path<S>(
         &self,
@@ -407,11 +566,7 @@ impl FirestoreDb {
     where
         S: AsRef<str>,
     {
-        Ok(ParentPathBuilder::new(safe_document_path(
-            self.inner.doc_path.as_str(),
-            collection_name,
-            document_id.as_ref(),
-        )?))
+        ParentPathBuilder::root(self.inner.doc_path.as_str(), collection_name, document_id)
     }
 
     /// Returns a reference to the [`FirestoreDbOptions`] used to configure this client.
@@ -431,12 +586,90 @@ impl FirestoreDb {
     /// Returns a reference to the underlying gRPC client.
     ///
     /// This provides access to the raw `FirestoreClient` from the `gcloud-sdk`
-    /// if direct interaction with the gRPC layer is needed.
+    /// via [`FirestoreApiClient::get`] if direct interaction with the gRPC layer is needed.
     #[inline]
-    pub fn client(&self) -> &GoogleApi<FirestoreClient<GoogleAuthMiddleware>> {
+    pub fn client(&self) -> &FirestoreApiClient {
         &self.inner.client
     }
 
+    /// Drops the cached access token, so the following requests authenticate with a new one
+    /// from the token source.
+    ///
+    /// The token is refreshed automatically when a request, a query stream or a listener
+    /// fails with `UNAUTHENTICATED` (see [`FirestoreRetryPolicy::reauthenticate`]),
+    /// e.g. when the token was revoked or the credentials were rotated before it expired.
+    pub async fn refresh_credentials(&self) {
+        self.inner.client.clear_token_cache().await
+    }
+
+    /// Clones the `FirestoreDb` instance targeting another database in the same project.
+    ///
+    /// The returned instance shares the underlying gRPC connection and authentication with
+    /// the original one, so it is cheap to create. All paths (documents, parents, listeners
+    /// and caches configured with it) and the requests use the specified database ID.
+    ///
+    /// The session parameters (e.g. a transaction or a cache mode) aren't carried over,
+    /// since they refer to the original database.
+    ///
+    /// # Arguments
+    /// * `database_id`: The ID of the database, e.g. `"my-db"` or
+    ///   [`FIREBASE_DEFAULT_DATABASE_ID`](crate::FIREBASE_DEFAULT_DATABASE_ID).
+    pub fn with_database<S>(&self, database_id: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        let options = self
+            .inner
+            .options
+            .clone()
+            .with_database_id(database_id.as_ref().to_string());
+        let (database_path, doc_path) = database_paths(&options);
+        let client = self
+            .inner
+            .client
+            .with_cloud_resource_prefix(database_path.clone());
+
+        Self {
+            inner: Arc::new(FirestoreDbInner {
+                database_path,
+                doc_path,
+                options,
+                client,
+                metrics_sink: self.inner.metrics_sink.clone(),
+                middlewares: self.inner.middlewares.clone(),
+                shutdown: self.inner.shutdown.clone(),
+                #[cfg(feature = "caching")]
+                cache_revalidations: Default::default(),
+            }),
+            session_params: Arc::new(FirestoreDbSessionParams::new()),
+        }
+    }
+
+    /// Clones the `FirestoreDb` instance reporting the metrics of all operations
+    /// to the specified sink.
+    ///
+    /// The returned instance shares the underlying gRPC connection and authentication
+    /// with the original one.
+    ///
+    /// # Arguments
+    /// * `metrics_sink`: The [`FirestoreMetricsSink`] to report metrics to.
+    pub fn with_metrics_sink(&self, metrics_sink: FirestoreSharedMetricsSink) -> Self {
+        Self {
+            inner: Arc::new(FirestoreDbInner {
+                database_path: self.inner.database_path.clone(),
+                doc_path: self.inner.doc_path.clone(),
+                options: self.inner.options.clone(),
+                client: self.inner.client.clone(),
+                metrics_sink: Some(metrics_sink),
+                middlewares: self.inner.middlewares.clone(),
+                shutdown: self.inner.shutdown.clone(),
+                #[cfg(feature = "caching")]
+                cache_revalidations: Default::default(),
+            }),
+            session_params: self.session_params.clone(),
+        }
+    }
+
     /// Clones the `FirestoreDb` instance, replacing its session parameters.
     ///
     /// This is useful for creating a new client instance that shares the same
@@ -542,6 +775,45 @@ impl FirestoreDb {
     }
 }
 
+/// Returns the database path and the documents path for the configured project and database IDs.
+fn database_paths(options: &FirestoreDbOptions) -> (String, String) {
+    let database_path = format!(
+        "projects/{}/databases/{}",
+        options.google_project_id, options.database_id
+    );
+    let doc_path = format!("{database_path}/documents");
+    (database_path, doc_path)
+}
+
+/// Resolves the API URL and whether the emulator is used, from the options and
+/// the value of the `FIRESTORE_EMULATOR_HOST` environment variable.
+fn resolve_api_endpoint(
+    options: &FirestoreDbOptions,
+    emulator_host_env: Option<String>,
+) -> (String, bool) {
+    match (&options.firebase_api_url, emulator_host_env) {
+        (Some(api_url), _) => (api_url.clone(), options.emulator),
+        (None, Some(emulator_host)) => (ensure_url_scheme(emulator_host), true),
+        (None, None) if options.emulator => {
+            (GOOGLE_FIRESTORE_EMULATOR_DEFAULT_URL.to_string(), true)
+        }
+        (None, None) => (GOOGLE_FIREBASE_API_URL.to_string(), false),
+    }
+}
+
+/// The emulator doesn't verify tokens, but expects the `owner` token for admin access.
+pub(crate) fn emulator_token_source() -> TokenSourceType {
+    TokenSourceType::ExternalSource(Box::new(gcloud_sdk::ExternalJwtFunctionSource::new(
+        || async {
+            Ok(gcloud_sdk::Token::new(
+                "Bearer".to_string(),
+                "owner".into(),
+                chrono::Utc::now() + chrono::Duration::days(1),
+            ))
+        },
+    )))
+}
+
 /// Ensures that a URL string has a scheme (e.g., "http://").
 /// If no scheme is present, "http://" is prepended.
 fn ensure_url_scheme(url: String) -> String {
@@ -595,10 +867,110 @@ pub(crate) fn split_document_path(path: &str) -> (&str, &str) {
     }
 }
 
+#[cfg(test)]
+impl FirestoreDb {
+    /// Creates a database for unit tests, the requests fail unless the emulator is running.
+    pub(crate) async fn for_unit_tests() -> Self {
+        let options = FirestoreDbOptions::new("test-project".to_string());
+        let (database_path, doc_path) = database_paths(&options);
+        let client = FirestoreApiClient::connect_lazy_for_tests(database_path.clone()).await;
+        Self {
+            inner: Arc::new(FirestoreDbInner {
+                database_path,
+                doc_path,
+                client,
+                options,
+                metrics_sink: None,
+                middlewares: Vec::new(),
+                shutdown: Default::default(),
+                #[cfg(feature = "caching")]
+                cache_revalidations: Default::default(),
+            }),
+            session_params: Arc::new(FirestoreDbSessionParams::new()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_database_paths() {
+        assert_eq!(
+            database_paths(&FirestoreDbOptions::new("test-project".to_string())),
+            (
+                "projects/test-project/databases/(default)".to_string(),
+                "projects/test-project/databases/(default)/documents".to_string()
+            )
+        );
+
+        assert_eq!(
+            database_paths(
+                &FirestoreDbOptions::new("test-project".to_string())
+                    .with_database_id("my-db".to_string())
+            ),
+            (
+                "projects/test-project/databases/my-db".to_string(),
+                "projects/test-project/databases/my-db/documents".to_string()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn with_database_targets_the_other_database() {
+        let db = FirestoreDb::for_unit_tests()
+            .await
+            .clone_with_consistency_selector(FirestoreConsistencySelector::Transaction(vec![
+                1, 2, 3,
+            ]));
+
+        let other = db.with_database("other");
+
+        assert_eq!(
+            other.get_database_path(),
+            "projects/test-project/databases/other"
+        );
+        assert_eq!(
+            other.get_documents_path(),
+            "projects/test-project/databases/other/documents"
+        );
+        assert_eq!(
+            other.client().cloud_resource_prefix(),
+            "projects/test-project/databases/other"
+        );
+        assert!(other.session_params.consistency_selector.is_none());
+        assert_eq!(
+            db.client().cloud_resource_prefix(),
+            "projects/test-project/databases/(default)"
+        );
+    }
+
+    #[test]
+    fn test_resolve_api_endpoint() {
+        let options = FirestoreDbOptions::new("test-project".to_string());
+
+        assert_eq!(
+            resolve_api_endpoint(&options, None),
+            (GOOGLE_FIREBASE_API_URL.to_string(), false)
+        );
+        assert_eq!(
+            resolve_api_endpoint(&options, Some("localhost:8081".to_string())),
+            ("http://localhost:8081".to_string(), true)
+        );
+        assert_eq!(
+            resolve_api_endpoint(&options.clone().with_emulator(true), None),
+            (GOOGLE_FIRESTORE_EMULATOR_DEFAULT_URL.to_string(), true)
+        );
+        assert_eq!(
+            resolve_api_endpoint(
+                &options.with_firebase_api_url("https://custom.example.com".to_string()),
+                Some("localhost:8081".to_string())
+            ),
+            ("https://custom.example.com".to_string(), false)
+        );
+    }
+
     #[test]
     fn test_safe_document_path() {
         assert_eq!(
@@ -1,5 +1,6 @@
 use gcloud_sdk::GoogleEnvironment;
 use rsb_derive::Builder;
+use std::time::Duration;
 
 /// Configuration options for the [`FirestoreDb`](crate::FirestoreDb) client.
 ///
@@ -33,15 +34,48 @@ pub struct FirestoreDbOptions {
 
     /// The maximum number of times to retry a failed operation. Defaults to `3`.
     /// Retries are typically applied to transient errors.
+    /// Ignored if `retry_policy` is specified.
     #[default = "3"]
     pub max_retries: usize,
 
+    /// The policy of retrying failed operations.
+    /// If `None`, the default [`FirestoreRetryPolicy`](crate::FirestoreRetryPolicy)
+    /// with `max_retries` is used.
+    pub retry_policy: Option<crate::FirestoreRetryPolicy>,
+
+    /// The default timeout of an operation, sent to Firestore as the gRPC deadline of
+    /// each request. For queries returning a stream, the deadline applies to the whole stream.
+    /// Listeners aren't affected. If `None`, requests have no deadline.
+    /// Can be overridden for a fluent operation with `.timeout(duration)`.
+    pub timeout: Option<Duration>,
+
     /// An optional custom URL for the Firestore API.
     /// If `None`, the default Google Firestore API endpoint is used.
     /// This can be useful for targeting a Firestore emulator.
     /// If the `FIRESTORE_EMULATOR_HOST` environment variable is set, it will
     /// typically override this and the default URL.
     pub firebase_api_url: Option<String>,
+
+    /// Connects to the Firestore emulator: uses plaintext connections and skips
+    /// token acquisition, so no GCP credentials are required. Defaults to `false`.
+    ///
+    /// The emulator is also used automatically when the `FIRESTORE_EMULATOR_HOST`
+    /// environment variable is set and `firebase_api_url` is not specified.
+    #[default = "false"]
+    pub emulator: bool,
+
+    /// Settings for the gRPC connections to Firestore: the channel pool size,
+    /// keepalive, timeouts and message size limits.
+    #[default = "FirestoreDbConnectionOptions::new()"]
+    pub connection: FirestoreDbConnectionOptions,
+
+    /// The maximum age of read times for point-in-time reads set with `read_at()`. Defaults to 1 hour.
+    /// Read times of session consistency selectors aren't checked.
+    ///
+    /// Set it to 7 days if point-in-time recovery (PITR) is enabled for the database.
+    /// Read times older than 1 hour must then be whole minutes.
+    #[default = "Duration::from_secs(60 * 60)"]
+    pub point_in_time_read_window: Duration,
 }
 
 impl FirestoreDbOptions {
@@ -74,3 +108,52 @@ impl FirestoreDbOptions {
 
 /// The default database ID for Firestore, which is `"(default)"`.
 pub const FIREBASE_DEFAULT_DATABASE_ID: &str = "(default)";
+
+/// Connection settings for the gRPC channels used by [`FirestoreDb`](crate::FirestoreDb).
+///
+/// # Examples
+///
+/// ```rust
+/// use firestore::{FirestoreDbConnectionOptions, FirestoreDbOptions};
+/// use std::time::Duration;
+///
+/// let options = FirestoreDbOptions::new("my-gcp-project-id".to_string()).with_connection(
+///     FirestoreDbConnectionOptions::new()
+///         .with_channels(4)
+///         .with_request_timeout(Duration::from_secs(10))
+///         .with_max_decoding_message_size(32 * 1024 * 1024),
+/// );
+/// assert_eq!(options.connection.channels, 4);
+/// ```
+#[derive(Debug, Eq, PartialEq, Clone, Builder)]
+pub struct FirestoreDbConnectionOptions {
+    /// The number of gRPC channels (separate HTTP/2 connections) in the pool. Defaults to `1`.
+    ///
+    /// Requests are distributed across the channels in round-robin order, which helps
+    /// high-throughput workloads to avoid the concurrent streams limit of a single connection.
+    #[default = "1"]
+    pub channels: usize,
+
+    /// The timeout for establishing a connection. Defaults to 30 seconds.
+    #[default = "Duration::from_secs(30)"]
+    pub connect_timeout: Duration,
+
+    /// The timeout for each request. Defaults to no timeout.
+    pub request_timeout: Option<Duration>,
+
+    /// The interval of HTTP/2 and TCP keepalive pings. Defaults to 60 seconds.
+    #[default = "Duration::from_secs(60)"]
+    pub keepalive_interval: Duration,
+
+    /// How long to wait for a keepalive ping acknowledgement before closing
+    /// the connection. Defaults to 60 seconds.
+    #[default = "Duration::from_secs(60)"]
+    pub keepalive_timeout: Duration,
+
+    /// The maximum size of a decoded response message.
+    /// Defaults to the gRPC default of 4MB.
+    pub max_decoding_message_size: Option<usize>,
+
+    /// The maximum size of an encoded request message. Defaults to no limit.
+    pub max_encoding_message_size: Option<usize>,
+}
@@ -1,7 +1,13 @@
 use crate::db::safe_document_path;
+use crate::errors::*;
 use crate::{FirestoreReference, FirestoreResult};
 use std::fmt::{Display, Formatter};
 
+/// The maximum depth of nested collections supported by Firestore.
+pub const FIRESTORE_MAX_COLLECTION_DEPTH: usize = 100;
+
+const FIRESTORE_MAX_PATH_SEGMENT_BYTES: usize = 1500;
+
 /// A builder for constructing Firestore document paths, typically for parent documents
 /// when dealing with sub-collections.
 ///
@@ -27,12 +33,17 @@ use std::fmt::{Display, Formatter};
 /// // Path to "my-collection/my-doc/sub-collection/sub-doc"
 /// let sub_collection_path = parent_path.at("sub-collection", "sub-doc")?;
 /// assert_eq!(sub_collection_path.to_string(), "projects/my-project/databases/(default)/documents/my-collection/my-doc/sub-collection/sub-doc");
+///
+/// // Path to the "items" collection under "my-collection/my-doc/sub-collection/sub-doc"
+/// let items_path = sub_collection_path.collection("items")?;
+/// assert_eq!(items_path.collection_id(), "items");
 /// # Ok(())
 /// # }
 /// ```
 #[derive(Debug, Clone)]
 pub struct ParentPathBuilder {
     value: String,
+    depth: usize,
 }
 
 impl ParentPathBuilder {
@@ -40,7 +51,35 @@ impl ParentPathBuilder {
     /// This is typically called internally by [`FirestoreDb::parent_path()`](crate::FirestoreDb::parent_path).
     #[inline]
     pub(crate) fn new(initial: String) -> Self {
-        Self { value: initial }
+        Self {
+            value: initial,
+            depth: 1,
+        }
+    }
+
+    /// Creates a path to a top-level document under the specified documents path,
+    /// validating the collection and document IDs.
+    pub(crate) fn root<S>(
+        documents_path: &str,
+        collection_name: &str,
+        document_id: S,
+    ) -> FirestoreResult<Self>
+    where
+        S: AsRef<str>,
+    {
+        validate_path_segment("collection_id", collection_name)?;
+        validate_path_segment("document_id", document_id.as_ref())?;
+        Ok(Self::new(safe_document_path(
+            documents_path,
+            collection_name,
+            document_id.as_ref(),
+        )?))
+    }
+
+    /// The number of nested collections in the path, `1` for a top-level document.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth
     }
 
     /// Appends a collection name and document ID to the current path.
@@ -53,17 +92,143 @@ impl ParentPathBuilder {
     ///
     /// # Errors
     /// Returns [`FirestoreError::InvalidParametersError`](crate::errors::FirestoreError::InvalidParametersError)
-    /// if the `document_id` is invalid (e.g., contains `/`).
+    /// if the `collection_name` or `document_id` is invalid (e.g., contains `/`),
+    /// or the path would exceed [`FIRESTORE_MAX_COLLECTION_DEPTH`].
     #[inline]
     pub fn at<S>(self, collection_name: &str, document_id: S) -> FirestoreResult<Self>
     where
         S: AsRef<str>,
     {
-        Ok(Self::new(safe_document_path(
-            self.value.as_str(),
-            collection_name,
-            document_id.as_ref(),
-        )?))
+        let depth = self.nested_depth()?;
+        validate_path_segment("collection_id", collection_name)?;
+        validate_path_segment("document_id", document_id.as_ref())?;
+        Ok(Self {
+            value: safe_document_path(self.value.as_str(), collection_name, document_id.as_ref())?,
+            depth,
+        })
+    }
+
+    /// Creates a path to a sub-collection of the current document.
+    ///
+    /// The resulting [`FirestoreCollectionPath`] can be passed to the fluent API builders
+    /// instead of specifying a collection ID and a parent separately,
+    /// e.g. `db.fluent().select().from_path(&path)`.
+    ///
+    /// # Errors
+    /// Returns [`FirestoreError::InvalidParametersError`](crate::errors::FirestoreError::InvalidParametersError)
+    /// if the `collection_id` is invalid or the path would exceed [`FIRESTORE_MAX_COLLECTION_DEPTH`].
+    pub fn collection<S>(self, collection_id: S) -> FirestoreResult<FirestoreCollectionPath>
+    where
+        S: AsRef<str>,
+    {
+        let depth = self.nested_depth()?;
+        validate_path_segment("collection_id", collection_id.as_ref())?;
+        Ok(FirestoreCollectionPath {
+            parent: self.value,
+            collection_id: collection_id.as_ref().to_string(),
+            depth,
+        })
+    }
+
+    fn nested_depth(&self) -> FirestoreResult<usize> {
+        if self.depth < FIRESTORE_MAX_COLLECTION_DEPTH {
+            Ok(self.depth + 1)
+        } else {
+            Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "parent".to_string(),
+                    format!(
+                        "Collections can be nested up to {FIRESTORE_MAX_COLLECTION_DEPTH} levels: {}",
+                        self.value
+                    ),
+                )),
+            ))
+        }
+    }
+}
+
+/// A validated path to a (sub)collection: the parent document path and the collection ID.
+///
+/// Created with [`ParentPathBuilder::collection`] and accepted by the fluent API builders
+/// with the `*_path` methods, such as `from_path()`, `into_path()` and `in_col_path()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirestoreCollectionPath {
+    parent: String,
+    collection_id: String,
+    depth: usize,
+}
+
+impl FirestoreCollectionPath {
+    /// Creates a path to a collection under the specified parent document or documents root path.
+    pub(crate) fn new(parent: String, collection_id: String) -> Self {
+        // "projects/{project_id}/databases/{database_id}/documents" followed by pairs of collection and document IDs
+        let nested_segments = parent.split('/').skip(5).count();
+        Self {
+            parent,
+            collection_id,
+            depth: nested_segments / 2 + 1,
+        }
+    }
+
+    /// The full path to the parent document.
+    #[inline]
+    pub fn parent(&self) -> &str {
+        self.parent.as_str()
+    }
+
+    /// The ID of the collection.
+    #[inline]
+    pub fn collection_id(&self) -> &str {
+        self.collection_id.as_str()
+    }
+
+    /// Creates a path to a document in this collection, to continue nesting collections.
+    pub fn doc<S>(self, document_id: S) -> FirestoreResult<ParentPathBuilder>
+    where
+        S: AsRef<str>,
+    {
+        validate_path_segment("document_id", document_id.as_ref())?;
+        Ok(ParentPathBuilder {
+            value: safe_document_path(
+                self.parent.as_str(),
+                self.collection_id.as_str(),
+                document_id.as_ref(),
+            )?,
+            depth: self.depth,
+        })
+    }
+}
+
+impl Display for FirestoreCollectionPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.parent, self.collection_id)
+    }
+}
+
+// Restrictions described here: https://firebase.google.com/docs/firestore/quotas#collections_documents_and_fields
+fn validate_path_segment(field: &str, segment: &str) -> FirestoreResult<()> {
+    let reason = if segment.is_empty() {
+        Some("must not be empty")
+    } else if segment.contains('/') {
+        Some("must not contain '/'")
+    } else if segment == "." || segment == ".." {
+        Some("must not be '.' or '..'")
+    } else if segment.len() > 2 && segment.starts_with("__") && segment.ends_with("__") {
+        Some("must not match the reserved pattern '__.*__'")
+    } else if segment.len() > FIRESTORE_MAX_PATH_SEGMENT_BYTES {
+        Some("must be no longer than 1500 bytes")
+    } else {
+        None
+    };
+
+    match reason {
+        Some(reason) => Err(FirestoreError::InvalidParametersError(
+            FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                field.to_string(),
+                format!("Invalid path segment '{segment}': {reason}"),
+            )),
+        )),
+        None => Ok(()),
     }
 }
 
@@ -96,3 +261,67 @@ impl From<ParentPathBuilder> for FirestoreReference {
         FirestoreReference(pb.value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCS_PATH: &str = "projects/test/databases/(default)/documents";
+
+    #[test]
+    fn builds_nested_collection_paths() {
+        let path = ParentPathBuilder::root(DOCS_PATH, "users", "u1")
+            .unwrap()
+            .at("orders", "o1")
+            .unwrap()
+            .collection("items")
+            .unwrap();
+        assert_eq!(path.parent(), format!("{DOCS_PATH}/users/u1/orders/o1"));
+        assert_eq!(path.collection_id(), "items");
+        assert_eq!(
+            path.to_string(),
+            format!("{DOCS_PATH}/users/u1/orders/o1/items")
+        );
+
+        let item = path.doc("i1").unwrap();
+        assert_eq!(item.depth(), 3);
+        assert_eq!(
+            item.to_string(),
+            format!("{DOCS_PATH}/users/u1/orders/o1/items/i1")
+        );
+    }
+
+    #[test]
+    fn collection_path_depth() {
+        let root = FirestoreCollectionPath::new(DOCS_PATH.to_string(), "users".to_string());
+        assert_eq!(root.doc("u1").unwrap().depth(), 1);
+
+        let nested = FirestoreCollectionPath::new(
+            format!("{DOCS_PATH}/users/u1/orders/o1"),
+            "items".to_string(),
+        );
+        assert_eq!(nested.doc("i1").unwrap().depth(), 3);
+    }
+
+    #[test]
+    fn rejects_invalid_segments() {
+        for segment in ["", "a/b", ".", "..", "__id__"] {
+            assert!(ParentPathBuilder::root(DOCS_PATH, "users", segment).is_err());
+            assert!(ParentPathBuilder::root(DOCS_PATH, segment, "u1").is_err());
+        }
+        let user = ParentPathBuilder::root(DOCS_PATH, "users", "u1").unwrap();
+        assert!(user.clone().collection("__items__").is_err());
+        assert!(user.at("orders", "__").is_ok());
+    }
+
+    #[test]
+    fn rejects_too_deep_paths() {
+        let mut path = ParentPathBuilder::root(DOCS_PATH, "c", "d").unwrap();
+        for _ in 1..FIRESTORE_MAX_COLLECTION_DEPTH {
+            path = path.at("c", "d").unwrap();
+        }
+        assert_eq!(path.depth(), FIRESTORE_MAX_COLLECTION_DEPTH);
+        assert!(path.clone().at("c", "d").is_err());
+        assert!(path.collection("c").is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use crate::db::{firestore_prepare_request, is_unauthenticated_error, order_by_with_document_name};
+use crate::errors::firestore_query_status_error;
 use crate::*;
 use async_trait::async_trait;
 use chrono::prelude::*;
@@ -8,7 +10,6 @@ use futures::TryFutureExt;
 use futures::TryStreamExt;
 use futures::{future, StreamExt};
 use gcloud_sdk::google::firestore::v1::*;
-use rand::Rng;
 use serde::Deserialize;
 use tokio::sync::mpsc;
 use tracing::*;
@@ -34,6 +35,11 @@ pub trait FirestoreQuerySupport {
         params: FirestoreQueryParams,
     ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<FirestoreDocument>>>>;
 
+    async fn stream_query_doc_with_meta<'b>(
+        &self,
+        params: FirestoreQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocWithMetaItem<FirestoreDocument>>>>;
+
     async fn query_obj<T>(&self, params: FirestoreQueryParams) -> FirestoreResult<Vec<T>>
     where
         for<'de> T: Deserialize<'de>;
@@ -61,6 +67,14 @@ pub trait FirestoreQuerySupport {
         for<'de> T: Deserialize<'de>,
         T: Send + 'b;
 
+    async fn stream_query_obj_with_meta<'b, T>(
+        &self,
+        params: FirestoreQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocWithMetaItem<T>>>>
+    where
+        for<'de> T: Deserialize<'de>,
+        T: Send + 'b;
+
     fn stream_partition_cursors_with_errors(
         &self,
         params: FirestorePartitionQueryParams,
@@ -83,19 +97,136 @@ pub trait FirestoreQuerySupport {
 }
 
 impl FirestoreDb {
+    /// Runs a raw [`StructuredQuery`] under the documents root of the database.
+    ///
+    /// This is an escape hatch for queries defined outside of the fluent API,
+    /// e.g. stored query definitions or queries authored with other SDK tooling.
+    /// Cache is not used for raw queries.
+    pub async fn query_raw(
+        &self,
+        structured_query: StructuredQuery,
+    ) -> FirestoreResult<Vec<Document>> {
+        self.query_raw_at(self.get_documents_path().as_str(), structured_query)
+            .await
+    }
+
+    /// Runs a raw [`StructuredQuery`] under the specified parent document path.
+    pub async fn query_raw_at(
+        &self,
+        parent: &str,
+        structured_query: StructuredQuery,
+    ) -> FirestoreResult<Vec<Document>> {
+        let collection_id = structured_query
+            .from
+            .iter()
+            .map(|selector| selector.collection_id.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let span = span!(
+            Level::DEBUG,
+            "Firestore Raw Query",
+            "/firestore/collection_name" = collection_id.as_str(),
+            "/firestore/response_time" = field::Empty,
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty,
+        );
+
+        let query_request = RunQueryRequest {
+            parent: parent.to_string(),
+            consistency_selector: self
+                .read_consistency_selector(None)?
+                .as_ref()
+                .map(|selector| selector.try_into())
+                .transpose()?,
+            explain_options: None,
+            query_type: Some(run_query_request::QueryType::StructuredQuery(
+                structured_query,
+            )),
+        };
+
+        let begin_query_utc: DateTime<Utc> = Utc::now();
+        let docs = self
+            .execute_with_retries("Running a raw query", true, || {
+                let query_request = query_request.clone();
+                let metrics = self.record_operation(
+                    FirestoreOperationKind::Query,
+                    Some(collection_id.as_str()),
+                    &span,
+                );
+                async move {
+                    let result = async {
+                        self.client()
+                            .get()
+                            .run_query(self.create_api_request(query_request)?)
+                            .await
+                            .map_err(firestore_query_status_error)?
+                            .into_inner()
+                            .map_err(firestore_query_status_error)
+                            .inspect_ok(|response| {
+                                self.notify_response_middlewares(FirestoreApiResponse::RunQuery(
+                                    response,
+                                ))
+                            })
+                            .try_filter_map(|response| future::ready(Ok(response.document)))
+                            .try_collect::<Vec<Document>>()
+                            .await
+                    }
+                    .await;
+                    metrics.record_result(
+                        &result,
+                        result.as_ref().map(|docs| docs.len()).unwrap_or(0),
+                    );
+                    result
+                }
+            })
+            .await?;
+        let end_query_utc: DateTime<Utc> = Utc::now();
+        let query_duration = end_query_utc.signed_duration_since(begin_query_utc);
+
+        span.record(
+            "/firestore/response_time",
+            query_duration.num_milliseconds(),
+        );
+        span.in_scope(|| {
+            debug!(
+                collection_id,
+                documents = docs.len(),
+                duration_milliseconds = query_duration.num_milliseconds(),
+                "Queried documents with a raw query.",
+            );
+        });
+
+        Ok(docs)
+    }
+
+    /// Runs a raw [`StructuredQuery`] under the documents root and deserializes the results.
+    pub async fn query_raw_obj<T>(
+        &self,
+        structured_query: StructuredQuery,
+    ) -> FirestoreResult<Vec<T>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        self.query_raw(structured_query)
+            .await?
+            .iter()
+            .map(|doc| Self::deserialize_doc_to(doc))
+            .collect()
+    }
+
     fn create_query_request(
         &self,
         params: FirestoreQueryParams,
     ) -> FirestoreResult<gcloud_sdk::tonic::Request<RunQueryRequest>> {
-        Ok(gcloud_sdk::tonic::Request::new(RunQueryRequest {
+        self.create_api_request(RunQueryRequest {
             parent: params
                 .parent
                 .as_ref()
                 .unwrap_or_else(|| self.get_documents_path())
                 .clone(),
             consistency_selector: self
-                .session_params
-                .consistency_selector
+                .read_consistency_selector(params.consistency_selector.as_ref())?
                 .as_ref()
                 .map(|selector| selector.try_into())
                 .transpose()?,
@@ -105,9 +236,11 @@ impl FirestoreDb {
                 .map(|eo| eo.try_into())
                 .transpose()?,
             query_type: Some(run_query_request::QueryType::StructuredQuery(
-                params.try_into()?,
+                params
+                    .resolve_document_ids(self.get_documents_path())
+                    .try_into()?,
             )),
-        }))
+        })
     }
 
     fn stream_query_doc_with_retries<'b>(
@@ -120,18 +253,29 @@ impl FirestoreDb {
         async move {
             let query_request = self.create_query_request(params.clone())?;
             let begin_query_utc: DateTime<Utc> = Utc::now();
+            let metrics = self.record_operation(
+                FirestoreOperationKind::Query,
+                Some(params.collection_id.to_string().as_str()),
+                &span,
+            );
 
             match self
                 .client()
                 .get()
                 .run_query(query_request)
-                .map_err(|e| e.into())
+                .map_err(firestore_query_status_error)
                 .await
             {
                 Ok(query_response) => {
+                    let db = self.clone();
                     let query_stream = query_response
                         .into_inner()
-                        .map_err(|e| e.into())
+                        .map_err(firestore_query_status_error)
+                        .inspect_ok(move |response| {
+                            db.notify_response_middlewares(FirestoreApiResponse::RunQuery(
+                                response,
+                            ))
+                        })
                         .map(|r| r.and_then(|r| r.try_into()))
                         .boxed();
 
@@ -150,35 +294,139 @@ impl FirestoreDb {
                         );
                     });
 
-                    Ok(query_stream)
+                    Ok(metrics.record_stream(
+                        query_stream,
+                        |res: &FirestoreWithMetadata<Document>| usize::from(res.document.is_some()),
+                    ))
                 }
-                Err(err) => match err {
-                    FirestoreError::DatabaseError(ref db_err)
-                        if db_err.retry_possible && retries < self.inner.options.max_retries =>
-                    {
-                        let sleep_duration = tokio::time::Duration::from_millis(
-                            rand::rng().random_range(0..2u64.pow(retries as u32) * 1000 + 1),
-                        );
-                        warn!(
-                            err = %db_err,
-                            current_retry = retries + 1,
-                            max_retries = self.inner.options.max_retries,
-                            delay = sleep_duration.as_millis(),
-                            "Failed to stream query. Retrying up to the specified number of times."
-                        );
+                Err(err) => {
+                    metrics.record_failure(&err);
+                    let retry_policy = FirestoreRetryPolicy::current(&self.inner.options);
+                    match err {
+                        FirestoreError::DatabaseError(ref db_err)
+                            if retry_policy.should_retry(&err, retries, true) =>
+                        {
+                            let sleep_duration = retry_policy.backoff(retries);
+                            warn!(
+                                err = %db_err,
+                                current_retry = retries + 1,
+                                max_attempts = retry_policy.max_attempts,
+                                delay = sleep_duration.as_millis(),
+                                "Failed to stream query. Retrying up to the specified number of times."
+                            );
 
-                        tokio::time::sleep(sleep_duration).await;
+                            tokio::time::sleep(sleep_duration).await;
 
-                        self.stream_query_doc_with_retries(params, retries + 1, span)
-                            .await
+                            self.stream_query_doc_with_retries(params, retries + 1, span)
+                                .await
+                        }
+                        _ => Err(err),
                     }
-                    _ => Err(err),
-                },
+                }
             }
         }
         .boxed()
     }
 
+    /// Streams the query results, refreshing the credentials and resuming the query after
+    /// the last received document when the stream fails with `UNAUTHENTICATED`,
+    /// e.g. when the access token is revoked during a long-running stream.
+    /// Outside of transactions, the resumed query reads at the read time of the first response,
+    /// so the results stay consistent with the documents received before.
+    async fn stream_query_doc_resuming<'b>(
+        &self,
+        params: FirestoreQueryParams,
+        span: Span,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<Document>>>> {
+        let retry_policy = FirestoreRetryPolicy::current(&self.inner.options);
+        let stream = match self
+            .stream_query_doc_with_retries(params.clone(), 0, span.clone())
+            .await
+        {
+            Err(err) if retry_policy.reauthenticate && is_unauthenticated_error(&err) => {
+                warn!(
+                    %err,
+                    "Failed to stream query with invalid credentials. Refreshing the credentials and retrying."
+                );
+                self.refresh_credentials().await;
+                self.stream_query_doc_with_retries(params.clone(), 0, span.clone())
+                    .await?
+            }
+            result => result?,
+        };
+
+        if !retry_policy.reauthenticate || !is_resumable_query(&params) {
+            return Ok(stream);
+        }
+
+        let pin_read_time = !matches!(
+            self.read_consistency_selector(params.consistency_selector.as_ref())?,
+            Some(FirestoreConsistencySelector::Transaction(_))
+        );
+
+        let state = FirestoreQueryResumeState {
+            db: self.clone(),
+            pin_read_time,
+            read_time: None,
+            order_by: order_by_with_document_name(params.order_by.clone()),
+            params,
+            span,
+            stream,
+            received: 0,
+            cursor: None,
+            resumed: false,
+        };
+
+        Ok(futures::stream::unfold(Some(state), |state| async move {
+            let mut state = state?;
+            loop {
+                match state.stream.next().await {
+                    Some(Ok(res)) => {
+                        if state.pin_read_time && state.read_time.is_none() {
+                            state.read_time = res.metadata.read_time;
+                        }
+                        if let Some(doc) = &res.document {
+                            state.received += 1;
+                            state.cursor =
+                                Some(FirestoreQueryCursor::after_document(doc, &state.order_by));
+                            state.resumed = false;
+                        }
+                        return Some((Ok(res), Some(state)));
+                    }
+                    // Resuming once after a document was received to avoid looping
+                    // with credentials that are rejected anyway
+                    Some(Err(err)) if !state.resumed && is_unauthenticated_error(&err) => {
+                        warn!(
+                            %err,
+                            received = state.received,
+                            "Query stream failed with invalid credentials. Refreshing the credentials and resuming the query."
+                        );
+                        state.db.refresh_credentials().await;
+                        state.resumed = true;
+                        let params = resume_query_params(
+                            &state.params,
+                            &state.order_by,
+                            state.received,
+                            state.cursor.clone(),
+                            state.read_time,
+                        )?;
+                        match state
+                            .db
+                            .stream_query_doc_with_retries(params, 0, state.span.clone())
+                            .await
+                        {
+                            Ok(stream) => state.stream = stream,
+                            Err(err) => return Some((Err(err), None)),
+                        }
+                    }
+                    Some(Err(err)) => return Some((Err(err), Some(state))),
+                    None => return None,
+                }
+            }
+        })
+        .boxed())
+    }
+
     #[cfg(feature = "caching")]
     #[inline]
     async fn query_docs_from_cache<'b>(
@@ -186,8 +434,16 @@ impl FirestoreDb {
         params: &FirestoreQueryParams,
     ) -> FirestoreResult<FirestoreCachedValue<BoxStream<'b, FirestoreResult<FirestoreDocument>>>>
     {
+        if self.reads_at_point_in_time(params.consistency_selector.as_ref()) {
+            return Ok(FirestoreCachedValue::SkipCache);
+        }
         match &params.collection_id {
+            // Collection group queries span collections at any depth, so they must never
+            // be served from a cached collection with the same ID.
             FirestoreQueryCollection::Group(_) => Ok(FirestoreCachedValue::SkipCache),
+            FirestoreQueryCollection::Single(_) if params.all_descendants == Some(true) => {
+                Ok(FirestoreCachedValue::SkipCache)
+            }
             FirestoreQueryCollection::Single(collection_id) => {
                 if let FirestoreDbSessionCacheMode::ReadCachedOnly(ref cache) =
                     self.session_params.cache_mode
@@ -208,7 +464,10 @@ impl FirestoreDb {
                         format!("{}/{}", self.get_documents_path(), collection_id.as_str())
                     };
 
-                    let result = cache.query_docs(&collection_path, params).await?;
+                    let params = params
+                        .clone()
+                        .resolve_document_ids(self.get_documents_path());
+                    let result = cache.query_docs(&collection_path, &params).await?;
 
                     let end_query_utc: DateTime<Utc> = Utc::now();
                     let query_duration = end_query_utc.signed_duration_since(begin_query_utc);
@@ -287,6 +546,13 @@ impl FirestoreQuerySupport for FirestoreDb {
         &self,
         params: FirestoreQueryParams,
     ) -> FirestoreResult<BoxStream<'b, FirestoreResult<Document>>> {
+        if params.limit_to_last.is_some() {
+            let doc_stream = self
+                .stream_query_doc_with_errors(params.resolve_limit_to_last()?)
+                .await?;
+            return Ok(reverse_stream(doc_stream, |_| true).await);
+        }
+
         #[cfg(feature = "caching")]
         {
             if let FirestoreCachedValue::UseCached(stream) =
@@ -302,10 +568,12 @@ impl FirestoreQuerySupport for FirestoreDb {
             Level::DEBUG,
             "Firestore Streaming Query",
             "/firestore/collection_name" = collection_str.as_str(),
-            "/firestore/response_time" = field::Empty
+            "/firestore/response_time" = field::Empty,
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty
         );
 
-        let doc_stream = self.stream_query_doc_with_retries(params, 0, span).await?;
+        let doc_stream = self.stream_query_doc_resuming(params, span).await?;
 
         Ok(Box::pin(doc_stream.filter_map(|doc_res| {
             future::ready(match doc_res {
@@ -328,10 +596,36 @@ impl FirestoreQuerySupport for FirestoreDb {
             Level::DEBUG,
             "Firestore Streaming Query with Metadata",
             "/firestore/collection_name" = collection_str.as_str(),
-            "/firestore/response_time" = field::Empty
+            "/firestore/response_time" = field::Empty,
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty
         );
 
-        self.stream_query_doc_with_retries(params, 0, span).await
+        if params.limit_to_last.is_some() {
+            let res_stream = self
+                .stream_query_doc_resuming(params.resolve_limit_to_last()?, span)
+                .await?;
+            return Ok(reverse_stream(res_stream, |res| res.document.is_some()).await);
+        }
+
+        self.stream_query_doc_resuming(params, span).await
+    }
+
+    async fn stream_query_doc_with_meta<'b>(
+        &self,
+        params: FirestoreQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocWithMetaItem<Document>>>> {
+        let consistency_selector =
+            self.read_consistency_selector(params.consistency_selector.as_ref())?;
+        let res_stream = self.stream_query_doc_with_metadata(params).await?;
+        Ok(Box::pin(res_stream.filter_map(move |res| {
+            future::ready(
+                res.and_then(|with_meta| {
+                    FirestoreDocWithMetaItem::from_metadata(with_meta, consistency_selector.clone())
+                })
+                .transpose(),
+            )
+        })))
     }
 
     async fn query_obj<T>(&self, params: FirestoreQueryParams) -> FirestoreResult<Vec<T>>
@@ -405,6 +699,22 @@ impl FirestoreQuerySupport for FirestoreDb {
         })))
     }
 
+    async fn stream_query_obj_with_meta<'b, T>(
+        &self,
+        params: FirestoreQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocWithMetaItem<T>>>>
+    where
+        for<'de> T: Deserialize<'de>,
+        T: Send + 'b,
+    {
+        let doc_stream = self.stream_query_doc_with_meta(params).await?;
+        Ok(Box::pin(doc_stream.map(|res| {
+            res.and_then(|doc_with_meta| {
+                doc_with_meta.try_map(|document| Self::deserialize_doc_to::<T>(&document))
+            })
+        })))
+    }
+
     fn stream_partition_cursors_with_errors(
         &self,
         params: FirestorePartitionQueryParams,
@@ -413,21 +723,28 @@ impl FirestoreQuerySupport for FirestoreDb {
             let consistency_selector: Option<
                 gcloud_sdk::google::firestore::v1::partition_query_request::ConsistencySelector,
             > = self
-                .session_params
-                .consistency_selector
+                .read_consistency_selector(params.query_params.consistency_selector.as_ref())?
                 .as_ref()
                 .map(|selector| selector.try_into())
                 .transpose()?;
+            let timeout = self.current_timeout();
 
             let stream: PeekableBoxStream<FirestoreResult<FirestoreQueryCursor>> =
                 futures::stream::unfold(
                     Some((params, consistency_selector)),
                     move |maybe_params| async move {
                         if let Some((params, maybe_consistency_selector)) = maybe_params {
-                            match params.query_params.clone().try_into() {
+                            match params
+                                .query_params
+                                .clone()
+                                .resolve_document_ids(self.get_documents_path())
+                                .try_into()
+                            {
                                 Ok(query_params) => {
-                                    let request =
-                                        gcloud_sdk::tonic::Request::new(PartitionQueryRequest {
+                                    let request = firestore_prepare_request(
+                                        &self.inner,
+                                        firestore_request_with_timeout(
+                                        PartitionQueryRequest {
                                             page_size: params.page_size as i32,
                                             partition_count: params.partition_count as i64,
                                             parent: params
@@ -446,11 +763,49 @@ impl FirestoreQuerySupport for FirestoreDb {
                                                 .page_token
                                                 .clone()
                                                 .unwrap_or_default(),
-                                        });
+                                        },
+                                        timeout,
+                                        ),
+                                    );
+
+                                    let collection_str =
+                                        params.query_params.collection_id.to_string();
+                                    let span = span!(
+                                        Level::DEBUG,
+                                        "Firestore Partition Query",
+                                        "/firestore/collection_name" = collection_str.as_str(),
+                                        "/firestore/operation" = field::Empty,
+                                        "/firestore/document_count" = field::Empty
+                                    );
+                                    let metrics = self.record_operation(
+                                        FirestoreOperationKind::PartitionQuery,
+                                        Some(collection_str.as_str()),
+                                        &span,
+                                    );
+                                    let response = match request {
+                                        Ok(request) => self
+                                            .client()
+                                            .get()
+                                            .partition_query(request)
+                                            .await
+                                            .map_err(firestore_query_status_error),
+                                        Err(err) => Err(err),
+                                    };
+                                    metrics.record_result(
+                                        &response,
+                                        response.as_ref().map_or(0, |response| {
+                                            response.get_ref().partitions.len()
+                                        }),
+                                    );
 
-                                    match self.client().get().partition_query(request).await {
+                                    match response {
                                         Ok(response) => {
                                             let partition_response = response.into_inner();
+                                            self.notify_response_middlewares(
+                                                FirestoreApiResponse::PartitionQuery(
+                                                    &partition_response,
+                                                ),
+                                            );
                                             let firestore_cursors: Vec<FirestoreQueryCursor> =
                                                 partition_response
                                                     .partitions
@@ -472,7 +827,7 @@ impl FirestoreQuerySupport for FirestoreDb {
                                                 Some((Ok(firestore_cursors), None))
                                             }
                                         }
-                                        Err(err) => Some((Err(FirestoreError::from(err)), None)),
+                                        Err(err) => Some((Err(err), None)),
                                     }
                                 }
                                 Err(err) => Some((Err(err), None)),
@@ -633,3 +988,137 @@ impl FirestoreQuerySupport for FirestoreDb {
         })))
     }
 }
+
+struct FirestoreQueryResumeState<'b> {
+    db: FirestoreDb,
+    params: FirestoreQueryParams,
+    order_by: Vec<FirestoreQueryOrder>,
+    span: Span,
+    stream: BoxStream<'b, FirestoreResult<FirestoreWithMetadata<Document>>>,
+    received: u32,
+    cursor: Option<FirestoreQueryCursor>,
+    resumed: bool,
+    pin_read_time: bool,
+    read_time: Option<DateTime<Utc>>,
+}
+
+/// Returns `true` if the query can be resumed after a document with a cursor.
+///
+/// Without an explicit ordering, the results of a query with inequality filters are ordered
+/// by the filtered fields, so only unfiltered queries are resumed ordering by the document name.
+fn is_resumable_query(params: &FirestoreQueryParams) -> bool {
+    params.limit_to_last.is_none()
+        && params.find_nearest.is_none()
+        && (params.order_by.is_some() || params.filter.is_none())
+}
+
+/// Returns the parameters of the query continuing after the received documents
+/// at the read time of the first response (if any),
+/// or `None` if the limit of the query is already reached.
+fn resume_query_params(
+    params: &FirestoreQueryParams,
+    order_by: &[FirestoreQueryOrder],
+    received: u32,
+    cursor: Option<FirestoreQueryCursor>,
+    read_time: Option<DateTime<Utc>>,
+) -> Option<FirestoreQueryParams> {
+    let params = match read_time {
+        Some(read_time) => params
+            .clone()
+            .with_consistency_selector(FirestoreConsistencySelector::ReadTime(read_time)),
+        None => params.clone(),
+    };
+    let cursor = match cursor {
+        Some(cursor) => cursor,
+        None => return Some(params),
+    };
+    let limit = match params.limit {
+        Some(limit) if limit <= received => return None,
+        limit => limit.map(|limit| limit - received),
+    };
+    Some(
+        params
+            .with_order_by(order_by.to_vec())
+            .with_start_at(cursor)
+            .opt_offset(None)
+            .opt_limit(limit),
+    )
+}
+
+/// Collects the results of a `limit_to_last` query, which is sent to Firestore in the reversed order,
+/// and reverses the items matching `reversible`, keeping other items, like metadata, at the end.
+async fn reverse_stream<'b, T, FN>(
+    stream: BoxStream<'b, FirestoreResult<T>>,
+    reversible: FN,
+) -> BoxStream<'b, FirestoreResult<T>>
+where
+    T: Send + 'b,
+    FN: Fn(&T) -> bool,
+{
+    match stream.try_collect::<Vec<T>>().await {
+        Ok(items) => {
+            let (mut reversed, rest): (Vec<T>, Vec<T>) = items.into_iter().partition(reversible);
+            reversed.reverse();
+            futures::stream::iter(reversed.into_iter().chain(rest).map(Ok)).boxed()
+        }
+        Err(err) => futures::stream::once(future::ready(Err(err))).boxed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_query_after_received_documents() {
+        let params = FirestoreQueryParams::new("items".into())
+            .with_limit(10)
+            .with_offset(5)
+            .with_start_at(FirestoreQueryCursor::BeforeValue(vec![1.into()]));
+        let order_by = order_by_with_document_name(params.order_by.clone());
+
+        assert_eq!(
+            resume_query_params(&params, &order_by, 0, None, None),
+            Some(params.clone())
+        );
+
+        let cursor = FirestoreQueryCursor::AfterValue(vec!["items/a".into()]);
+        let resumed =
+            resume_query_params(&params, &order_by, 4, Some(cursor.clone()), None).unwrap();
+        assert_eq!(resumed.limit, Some(6));
+        assert_eq!(resumed.offset, None);
+        assert_eq!(resumed.start_at, Some(cursor.clone()));
+        assert_eq!(resumed.order_by, Some(order_by.clone()));
+        assert_eq!(resumed.consistency_selector, None);
+
+        let read_time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        for cursor in [None, Some(cursor.clone())] {
+            let resumed =
+                resume_query_params(&params, &order_by, 4, cursor, Some(read_time)).unwrap();
+            assert_eq!(
+                resumed.consistency_selector,
+                Some(FirestoreConsistencySelector::ReadTime(read_time))
+            );
+        }
+
+        assert_eq!(
+            resume_query_params(&params, &order_by, 10, Some(cursor), None),
+            None
+        );
+    }
+
+    #[test]
+    fn resumable_queries() {
+        let params = FirestoreQueryParams::new("items".into());
+        assert!(is_resumable_query(&params));
+        assert!(!is_resumable_query(&params.clone().with_limit_to_last(1)));
+
+        let filtered = params.with_filter(FirestoreQueryFilter::Compare(Some(
+            FirestoreQueryFilterCompare::GreaterThan("num".to_string(), 1.into()),
+        )));
+        assert!(!is_resumable_query(&filtered));
+        assert!(is_resumable_query(&filtered.with_order_by(vec![
+            FirestoreQueryOrder::new("num".to_string(), FirestoreQueryDirection::Ascending)
+        ])));
+    }
+}
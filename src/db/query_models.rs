@@ -5,12 +5,18 @@
 use crate::errors::{
     FirestoreError, FirestoreInvalidParametersError, FirestoreInvalidParametersPublicDetails,
 };
-use crate::{FirestoreValue, FirestoreVector};
+use crate::select_filter_builder::{
+    FirestoreQueryFilterBuilder, FirestoreQueryFilterDocumentIdExpr,
+};
+use crate::{FirestoreConsistencySelector, FirestoreResult, FirestoreValue, FirestoreVector};
 use gcloud_sdk::google::firestore::v1::*;
+use gcloud_sdk::prost::Message;
 use rsb_derive::Builder;
+use rvstruct::ValueStruct;
+use serde::{Deserialize, Serialize};
 
 /// Specifies the target collection(s) for a Firestore query.
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub enum FirestoreQueryCollection {
     /// Queries a single collection identified by its ID.
     Single(String),
@@ -41,7 +47,10 @@ impl From<&str> for FirestoreQueryCollection {
 /// This struct encapsulates all configurable aspects of a query, such as the
 /// target collection, filters, ordering, limits, offsets, cursors, and projections.
 /// It is used by the fluent API and direct query methods to define the query to be sent to Firestore.
-#[derive(Debug, PartialEq, Clone, Builder)]
+///
+/// The parameters can be serialized (e.g. as JSON) to store query definitions and run them later.
+/// Filter and cursor values keep their Firestore types.
+#[derive(Debug, PartialEq, Clone, Builder, Serialize, Deserialize)]
 pub struct FirestoreQueryParams {
     /// The parent resource path. For top-level collections, this is typically
     /// the database path (e.g., "projects/my-project/databases/(default)/documents").
@@ -56,6 +65,11 @@ pub struct FirestoreQueryParams {
     /// The maximum number of results to return.
     pub limit: Option<u32>,
 
+    /// Returns only the last matching results, still in the requested order.
+    /// The query is sent to Firestore with the reversed ordering and cursors,
+    /// and the results are reversed back on the client. Requires `order_by`.
+    pub limit_to_last: Option<u32>,
+
     /// The number of results to skip.
     pub offset: Option<u32>,
 
@@ -86,12 +100,18 @@ pub struct FirestoreQueryParams {
 
     /// Options for performing a vector similarity search (find nearest neighbors).
     pub find_nearest: Option<FirestoreFindNearestOptions>,
+
+    /// The consistency of the read, e.g. a point in time to read the documents at.
+    /// If `None`, the consistency selector of the session is used.
+    pub consistency_selector: Option<FirestoreConsistencySelector>,
 }
 
 impl TryFrom<FirestoreQueryParams> for StructuredQuery {
     type Error = FirestoreError;
 
     fn try_from(params: FirestoreQueryParams) -> Result<Self, Self::Error> {
+        params.validate_cursors()?;
+        let params = params.resolve_limit_to_last()?;
         let query_filter = params.filter.map(|f| f.into());
 
         Ok(StructuredQuery {
@@ -141,7 +161,7 @@ impl TryFrom<FirestoreQueryParams> for StructuredQuery {
 ///
 /// Filters are used to narrow down the documents returned by a query based on
 /// conditions applied to their fields.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum FirestoreQueryFilter {
     /// A composite filter that combines multiple sub-filters using an operator (AND/OR).
     Composite(FirestoreQueryFilterComposite),
@@ -153,6 +173,118 @@ pub enum FirestoreQueryFilter {
     Compare(Option<FirestoreQueryFilterCompare>),
 }
 
+impl FirestoreQueryFilter {
+    /// The fields of the inequality filters, which Firestore orders the results by implicitly.
+    pub(crate) fn inequality_fields(&self) -> Vec<&str> {
+        match self {
+            FirestoreQueryFilter::Composite(composite) => composite
+                .for_all_filters
+                .iter()
+                .flat_map(|filter| filter.inequality_fields())
+                .collect(),
+            FirestoreQueryFilter::Unary(
+                FirestoreQueryFilterUnary::IsNotNan(field_name)
+                | FirestoreQueryFilterUnary::IsNotNull(field_name),
+            ) => vec![field_name.as_str()],
+            FirestoreQueryFilter::Compare(Some(
+                FirestoreQueryFilterCompare::LessThan(field_name, _)
+                | FirestoreQueryFilterCompare::LessThanOrEqual(field_name, _)
+                | FirestoreQueryFilterCompare::GreaterThan(field_name, _)
+                | FirestoreQueryFilterCompare::GreaterThanOrEqual(field_name, _)
+                | FirestoreQueryFilterCompare::NotEqual(field_name, _)
+                | FirestoreQueryFilterCompare::NotIn(field_name, _),
+            )) => vec![field_name.as_str()],
+            _ => vec![],
+        }
+    }
+
+    /// Converts the relative document IDs compared with the document name to full document paths.
+    fn resolve_document_ids(self, document_ids: &FirestoreQueryFilterDocumentIdExpr) -> Self {
+        match self {
+            FirestoreQueryFilter::Composite(composite) => {
+                FirestoreQueryFilter::Composite(FirestoreQueryFilterComposite {
+                    for_all_filters: composite
+                        .for_all_filters
+                        .into_iter()
+                        .map(|filter| filter.resolve_document_ids(document_ids))
+                        .collect(),
+                    ..composite
+                })
+            }
+            FirestoreQueryFilter::Compare(Some(compare)) => {
+                let resolve = |field_name: String, value: FirestoreValue| {
+                    if field_name == FIRESTORE_DOCUMENT_NAME_FIELD {
+                        (field_name, resolve_document_id_values(value, document_ids))
+                    } else {
+                        (field_name, value)
+                    }
+                };
+                use FirestoreQueryFilterCompare::*;
+                FirestoreQueryFilter::Compare(Some(match compare {
+                    LessThan(field_name, value) => {
+                        let (field_name, value) = resolve(field_name, value);
+                        LessThan(field_name, value)
+                    }
+                    LessThanOrEqual(field_name, value) => {
+                        let (field_name, value) = resolve(field_name, value);
+                        LessThanOrEqual(field_name, value)
+                    }
+                    GreaterThan(field_name, value) => {
+                        let (field_name, value) = resolve(field_name, value);
+                        GreaterThan(field_name, value)
+                    }
+                    GreaterThanOrEqual(field_name, value) => {
+                        let (field_name, value) = resolve(field_name, value);
+                        GreaterThanOrEqual(field_name, value)
+                    }
+                    Equal(field_name, value) => {
+                        let (field_name, value) = resolve(field_name, value);
+                        Equal(field_name, value)
+                    }
+                    NotEqual(field_name, value) => {
+                        let (field_name, value) = resolve(field_name, value);
+                        NotEqual(field_name, value)
+                    }
+                    In(field_name, value) => {
+                        let (field_name, value) = resolve(field_name, value);
+                        In(field_name, value)
+                    }
+                    NotIn(field_name, value) => {
+                        let (field_name, value) = resolve(field_name, value);
+                        NotIn(field_name, value)
+                    }
+                    compare => compare,
+                }))
+            }
+            filter => filter,
+        }
+    }
+}
+
+/// Resolves the reference values, or the reference values in an array, against the parent of the IDs.
+fn resolve_document_id_values(
+    value: FirestoreValue,
+    document_ids: &FirestoreQueryFilterDocumentIdExpr,
+) -> FirestoreValue {
+    match value.value.value_type {
+        Some(value::ValueType::ReferenceValue(document_id)) => {
+            document_ids.reference_value(document_id)
+        }
+        Some(value::ValueType::ArrayValue(array)) => FirestoreValue::from(Value {
+            value_type: Some(value::ValueType::ArrayValue(ArrayValue {
+                values: array
+                    .values
+                    .into_iter()
+                    .map(|value| {
+                        resolve_document_id_values(FirestoreValue::from(value), document_ids).value
+                    })
+                    .collect(),
+            })),
+        }),
+        value_type => FirestoreValue::from(Value { value_type }),
+    }
+}
+
 impl From<FirestoreQueryFilter> for structured_query::Filter {
     fn from(filter: FirestoreQueryFilter) -> Self {
         let filter_type = match filter {
@@ -323,7 +455,7 @@ impl From<FirestoreQueryFilter> for structured_query::Filter {
 }
 
 /// Specifies an ordering for query results based on a field.
-#[derive(Debug, Eq, PartialEq, Clone, Builder)]
+#[derive(Debug, Eq, PartialEq, Clone, Builder, Serialize, Deserialize)]
 pub struct FirestoreQueryOrder {
     /// The path to the field to order by (e.g., "name", "address.city").
     pub field_name: String,
@@ -336,6 +468,11 @@ impl FirestoreQueryOrder {
     pub fn to_string_format(&self) -> String {
         format!("{} {}", self.field_name, self.direction.to_string())
     }
+
+    /// Orders by the document ID (the `__name__` field), i.e. by the full document path.
+    pub fn document_id(direction: FirestoreQueryDirection) -> Self {
+        Self::new(FIRESTORE_DOCUMENT_NAME_FIELD.to_string(), direction)
+    }
 }
 
 impl<S> From<(S, FirestoreQueryDirection)> for FirestoreQueryOrder
@@ -364,7 +501,7 @@ impl From<FirestoreQueryOrder> for structured_query::Order {
 }
 
 /// The direction for ordering query results.
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub enum FirestoreQueryDirection {
     /// Sort results in ascending order.
     Ascending,
@@ -383,7 +520,7 @@ impl ToString for FirestoreQueryDirection {
 }
 
 /// A composite filter that combines multiple [`FirestoreQueryFilter`]s.
-#[derive(Debug, PartialEq, Clone, Builder)]
+#[derive(Debug, PartialEq, Clone, Builder, Serialize, Deserialize)]
 pub struct FirestoreQueryFilterComposite {
     /// The list of sub-filters to combine.
     pub for_all_filters: Vec<FirestoreQueryFilter>,
@@ -392,7 +529,7 @@ pub struct FirestoreQueryFilterComposite {
 }
 
 /// The operator for combining filters in a [`FirestoreQueryFilterComposite`].
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub enum FirestoreQueryFilterCompositeOperator {
     /// Logical AND: all sub-filters must be true.
     And,
@@ -414,7 +551,7 @@ impl From<FirestoreQueryFilterCompositeOperator> for structured_query::composite
 }
 
 /// A unary filter that applies an operation to a single field.
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub enum FirestoreQueryFilterUnary {
     /// Checks if a field's value is NaN (Not a Number).
     /// The string argument is the field path.
@@ -433,43 +570,73 @@ pub enum FirestoreQueryFilterUnary {
 /// A field filter that compares a field to a value using a specific operator.
 /// The first `String` argument in each variant is the field path.
 /// The `FirestoreValue` is the value to compare against.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum FirestoreQueryFilterCompare {
     /// Field is less than the value.
-    LessThan(String, FirestoreValue),
+    LessThan(
+        String,
+        #[serde(with = "crate::serialize_as_typed_value")] FirestoreValue,
+    ),
     /// Field is less than or equal to the value.
-    LessThanOrEqual(String, FirestoreValue),
+    LessThanOrEqual(
+        String,
+        #[serde(with = "crate::serialize_as_typed_value")] FirestoreValue,
+    ),
     /// Field is greater than the value.
-    GreaterThan(String, FirestoreValue),
+    GreaterThan(
+        String,
+        #[serde(with = "crate::serialize_as_typed_value")] FirestoreValue,
+    ),
     /// Field is greater than or equal to the value.
-    GreaterThanOrEqual(String, FirestoreValue),
+    GreaterThanOrEqual(
+        String,
+        #[serde(with = "crate::serialize_as_typed_value")] FirestoreValue,
+    ),
     /// Field is equal to the value.
-    Equal(String, FirestoreValue),
+    Equal(
+        String,
+        #[serde(with = "crate::serialize_as_typed_value")] FirestoreValue,
+    ),
     /// Field is not equal to the value.
-    NotEqual(String, FirestoreValue),
+    NotEqual(
+        String,
+        #[serde(with = "crate::serialize_as_typed_value")] FirestoreValue,
+    ),
     /// Field (which must be an array) contains the value.
-    ArrayContains(String, FirestoreValue),
+    ArrayContains(
+        String,
+        #[serde(with = "crate::serialize_as_typed_value")] FirestoreValue,
+    ),
     /// Field's value is IN the given array value. The `FirestoreValue` should be an array.
-    In(String, FirestoreValue),
+    In(
+        String,
+        #[serde(with = "crate::serialize_as_typed_value")] FirestoreValue,
+    ),
     /// Field (which must be an array) contains any of the values in the given array value.
     /// The `FirestoreValue` should be an array.
-    ArrayContainsAny(String, FirestoreValue),
+    ArrayContainsAny(
+        String,
+        #[serde(with = "crate::serialize_as_typed_value")] FirestoreValue,
+    ),
     /// Field's value is NOT IN the given array value. The `FirestoreValue` should be an array.
-    NotIn(String, FirestoreValue),
+    NotIn(
+        String,
+        #[serde(with = "crate::serialize_as_typed_value")] FirestoreValue,
+    ),
 }
 
 /// Represents a cursor for paginating query results.
 ///
 /// Cursors define a starting or ending point for a query based on the values
 /// of the fields being ordered by.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum FirestoreQueryCursor {
     /// Starts the query results before the document that has these field values.
     /// The `Vec<FirestoreValue>` corresponds to the values of the ordered fields.
-    BeforeValue(Vec<FirestoreValue>),
+    BeforeValue(#[serde(with = "crate::serialize_as_typed_values")] Vec<FirestoreValue>),
     /// Starts the query results after the document that has these field values.
     /// The `Vec<FirestoreValue>` corresponds to the values of the ordered fields.
-    AfterValue(Vec<FirestoreValue>),
+    AfterValue(#[serde(with = "crate::serialize_as_typed_values")] Vec<FirestoreValue>),
 }
 
 impl From<FirestoreQueryCursor> for gcloud_sdk::google::firestore::v1::Cursor {
@@ -504,6 +671,224 @@ impl From<gcloud_sdk::google::firestore::v1::Cursor> for FirestoreQueryCursor {
     }
 }
 
+/// The special field path referring to the document name (full path) in orderings and filters.
+pub const FIRESTORE_DOCUMENT_NAME_FIELD: &str = "__name__";
+
+impl FirestoreQueryCursor {
+    /// The cursor including the documents this cursor excludes at the same position,
+    /// used when the query direction is reversed.
+    fn inverted(self) -> Self {
+        match self {
+            FirestoreQueryCursor::BeforeValue(values) => FirestoreQueryCursor::AfterValue(values),
+            FirestoreQueryCursor::AfterValue(values) => FirestoreQueryCursor::BeforeValue(values),
+        }
+    }
+
+    /// The values of the ordered fields the cursor is positioned at.
+    pub fn values(&self) -> &[FirestoreValue] {
+        match self {
+            FirestoreQueryCursor::BeforeValue(values)
+            | FirestoreQueryCursor::AfterValue(values) => values,
+        }
+    }
+
+    /// Builds a cursor positioned right after the given document, using the values
+    /// of the document fields listed in `order_by`.
+    ///
+    /// The ordering should end with the document name (see [`FIRESTORE_DOCUMENT_NAME_FIELD`])
+    /// to make the cursor unique. Missing fields are represented as `null` values.
+    pub fn after_document(doc: &Document, order_by: &[FirestoreQueryOrder]) -> Self {
+        FirestoreQueryCursor::AfterValue(
+            order_by
+                .iter()
+                .map(|order| {
+                    let value_type = if order.field_name == FIRESTORE_DOCUMENT_NAME_FIELD {
+                        Some(value::ValueType::ReferenceValue(doc.name.clone()))
+                    } else {
+                        crate::firestore_doc_get_field_by_path(doc, &order.field_name)
+                            .cloned()
+                            .or(Some(value::ValueType::NullValue(0)))
+                    };
+                    FirestoreValue::from(Value { value_type })
+                })
+                .collect(),
+        )
+    }
+}
+
+impl FirestoreQueryParams {
+    /// Converts a `limit_to_last` query to the equivalent query sent to Firestore:
+    /// the ordering directions are reversed, the cursors are swapped and inverted,
+    /// and the limit is applied. The results of the returned query must be reversed.
+    pub(crate) fn resolve_limit_to_last(self) -> FirestoreResult<Self> {
+        let limit_to_last = match self.limit_to_last {
+            Some(limit_to_last) => limit_to_last,
+            None => return Ok(self),
+        };
+
+        if self
+            .order_by
+            .as_ref()
+            .map_or(true, |order_by| order_by.is_empty())
+        {
+            return Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "limit_to_last".to_string(),
+                    "limit_to_last requires at least one order_by field".to_string(),
+                )),
+            ));
+        }
+        if self.limit.is_some() {
+            return Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "limit_to_last".to_string(),
+                    "limit and limit_to_last can't be used together".to_string(),
+                )),
+            ));
+        }
+
+        // The implicit ordering by the document name must be reversed as well
+        let order_by = order_by_with_document_name(self.order_by)
+            .into_iter()
+            .map(|order| FirestoreQueryOrder {
+                direction: match order.direction {
+                    FirestoreQueryDirection::Ascending => FirestoreQueryDirection::Descending,
+                    FirestoreQueryDirection::Descending => FirestoreQueryDirection::Ascending,
+                },
+                ..order
+            })
+            .collect();
+
+        Ok(Self {
+            order_by: Some(order_by),
+            start_at: self.end_at.map(FirestoreQueryCursor::inverted),
+            end_at: self.start_at.map(FirestoreQueryCursor::inverted),
+            limit: Some(limit_to_last),
+            limit_to_last: None,
+            ..self
+        })
+    }
+
+    /// Checks that the cursors have no more values than the fields the results are ordered by:
+    /// the `order_by` fields, the fields of inequality filters (ordered implicitly by Firestore)
+    /// and the document name.
+    pub(crate) fn validate_cursors(&self) -> FirestoreResult<()> {
+        let mut ordered_fields: Vec<&str> = self
+            .order_by
+            .iter()
+            .flatten()
+            .map(|order| order.field_name.as_str())
+            .collect();
+        if let Some(filter) = &self.filter {
+            for field_name in filter.inequality_fields() {
+                if !ordered_fields.contains(&field_name) {
+                    ordered_fields.push(field_name);
+                }
+            }
+        }
+        if !ordered_fields.contains(&FIRESTORE_DOCUMENT_NAME_FIELD) {
+            ordered_fields.push(FIRESTORE_DOCUMENT_NAME_FIELD);
+        }
+
+        for (cursor_name, cursor) in [("start_at", &self.start_at), ("end_at", &self.end_at)] {
+            if let Some(cursor) = cursor {
+                if cursor.values().len() > ordered_fields.len() {
+                    return Err(FirestoreError::InvalidParametersError(
+                        FirestoreInvalidParametersError::new(
+                            FirestoreInvalidParametersPublicDetails::new(
+                                cursor_name.to_string(),
+                                format!(
+                                    "The cursor has {} values, but the results are ordered only by {} fields: {}",
+                                    cursor.values().len(),
+                                    ordered_fields.len(),
+                                    ordered_fields.join(", ")
+                                ),
+                            ),
+                        ),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolves the relative document IDs in the filter against the collection of the query,
+    /// or the parent of the query for collection groups, when the query is sent,
+    /// so they follow the parent even if it is set after the filter.
+    pub(crate) fn resolve_document_ids(self, documents_path: &str) -> Self {
+        let document_ids =
+            FirestoreQueryFilterBuilder::for_query(documents_path, &self).document_id();
+        Self {
+            filter: self
+                .filter
+                .map(|filter| filter.resolve_document_ids(&document_ids)),
+            ..self
+        }
+    }
+}
+
+/// Returns the ordering extended with the document name, so that cursors built from it
+/// identify documents uniquely. The document name uses the direction of the last ordering,
+/// as Firestore does implicitly.
+pub(crate) fn order_by_with_document_name(
+    order_by: Option<Vec<FirestoreQueryOrder>>,
+) -> Vec<FirestoreQueryOrder> {
+    let mut order_by = order_by.unwrap_or_default();
+    match order_by.last() {
+        Some(last) if last.field_name == FIRESTORE_DOCUMENT_NAME_FIELD => {}
+        last => {
+            let direction = last
+                .map(|order| order.direction.clone())
+                .unwrap_or(FirestoreQueryDirection::Ascending);
+            order_by.push(FirestoreQueryOrder::new(
+                FIRESTORE_DOCUMENT_NAME_FIELD.to_string(),
+                direction,
+            ));
+        }
+    }
+    order_by
+}
+
+/// An opaque and serializable token pointing to the next page of query results.
+///
+/// Tokens can be sent to clients (e.g. in REST API responses) and passed back
+/// to fetch the next page with the same query.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, ValueStruct, Serialize, Deserialize)]
+pub struct FirestorePageToken(String);
+
+impl FirestorePageToken {
+    /// Encodes a cursor into a page token.
+    pub fn from_cursor(cursor: FirestoreQueryCursor) -> Self {
+        let cursor: Cursor = cursor.into();
+        Self(hex::encode(cursor.encode_to_vec()))
+    }
+
+    /// Decodes the cursor from a page token.
+    pub fn to_cursor(&self) -> FirestoreResult<FirestoreQueryCursor> {
+        let invalid_token_error = |err: String| {
+            FirestoreError::InvalidParametersError(FirestoreInvalidParametersError::new(
+                FirestoreInvalidParametersPublicDetails::new(
+                    "page_token".to_string(),
+                    format!("Invalid page token: {err}"),
+                ),
+            ))
+        };
+        let bytes = hex::decode(&self.0).map_err(|err| invalid_token_error(err.to_string()))?;
+        let cursor =
+            Cursor::decode(bytes.as_slice()).map_err(|err| invalid_token_error(err.to_string()))?;
+        Ok(cursor.into())
+    }
+}
+
+/// A page of query results with a token to fetch the next page.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FirestorePage<T> {
+    /// The items of the page, at most the requested page size.
+    pub items: Vec<T>,
+    /// The token for the next page, or `None` if this is the last page.
+    pub next_page_token: Option<FirestorePageToken>,
+}
+
 /// Parameters for a partitioned query.
 ///
 /// Partitioned queries allow you to divide a large query into smaller, parallelizable chunks.
@@ -533,11 +918,32 @@ pub struct FirestorePartition {
     pub end_at: Option<FirestoreQueryCursor>,
 }
 
+impl FirestorePartition {
+    /// Builds the partitions delimited by the split points returned by a `PartitionQuery`.
+    ///
+    /// `N` cursors produce `N + 1` partitions, the first one without a start cursor and the last
+    /// one without an end cursor. No cursors produce a single partition covering the whole query.
+    pub fn from_cursors(cursors: Vec<FirestoreQueryCursor>) -> Vec<FirestorePartition> {
+        let mut start_at: Option<FirestoreQueryCursor> = None;
+        let mut partitions = Vec::with_capacity(cursors.len() + 1);
+        for cursor in cursors {
+            partitions.push(
+                FirestorePartition::new()
+                    .opt_start_at(start_at.take())
+                    .with_end_at(cursor.clone()),
+            );
+            start_at = Some(cursor);
+        }
+        partitions.push(FirestorePartition::new().opt_start_at(start_at));
+        partitions
+    }
+}
+
 /// Options for requesting query execution analysis from Firestore.
 ///
 /// When `analyze` is true, Firestore will return detailed information about
 /// how the query was executed, including index usage and performance metrics.
-#[derive(Debug, PartialEq, Clone, Builder)]
+#[derive(Debug, PartialEq, Clone, Builder, Serialize, Deserialize)]
 pub struct FirestoreExplainOptions {
     /// If `true`, Firestore will analyze the query and return execution details.
     /// Defaults to `false` if not specified.
@@ -556,7 +962,7 @@ impl TryFrom<&FirestoreExplainOptions> for gcloud_sdk::google::firestore::v1::Ex
 /// Options for performing a vector similarity search (find nearest neighbors).
 ///
 /// This is used to find documents whose vector field is closest to a given query vector.
-#[derive(Debug, PartialEq, Clone, Builder)]
+#[derive(Debug, PartialEq, Clone, Builder, Serialize, Deserialize)]
 pub struct FirestoreFindNearestOptions {
     /// The path to the vector field in your documents to search against.
     pub field_name: String,
@@ -610,7 +1016,7 @@ impl TryFrom<FirestoreFindNearestOptions>
 }
 
 /// Specifies the distance measure for vector similarity searches.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum FirestoreFindNearestDistanceMeasure {
     /// Euclidean distance.
     Euclidean,
@@ -639,3 +1045,98 @@ impl TryFrom<FirestoreFindNearestDistanceMeasure>
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_limit_to_last() {
+        let params = FirestoreQueryParams::new("test".into())
+            .with_order_by(vec![FirestoreQueryOrder::new(
+                "created_at".to_string(),
+                FirestoreQueryDirection::Ascending,
+            )])
+            .with_start_at(FirestoreQueryCursor::AfterValue(vec![1.into()]))
+            .with_end_at(FirestoreQueryCursor::BeforeValue(vec![10.into()]))
+            .with_limit_to_last(3);
+
+        let resolved = params.clone().resolve_limit_to_last().unwrap();
+        assert_eq!(resolved.limit, Some(3));
+        assert_eq!(resolved.limit_to_last, None);
+        assert_eq!(
+            resolved.order_by,
+            Some(vec![
+                FirestoreQueryOrder::new(
+                    "created_at".to_string(),
+                    FirestoreQueryDirection::Descending
+                ),
+                FirestoreQueryOrder::new(
+                    FIRESTORE_DOCUMENT_NAME_FIELD.to_string(),
+                    FirestoreQueryDirection::Descending
+                ),
+            ])
+        );
+        assert_eq!(
+            resolved.start_at,
+            Some(FirestoreQueryCursor::AfterValue(vec![10.into()]))
+        );
+        assert_eq!(
+            resolved.end_at,
+            Some(FirestoreQueryCursor::BeforeValue(vec![1.into()]))
+        );
+
+        assert!(params
+            .clone()
+            .opt_order_by(None)
+            .resolve_limit_to_last()
+            .is_err());
+        assert!(params.with_limit(5).resolve_limit_to_last().is_err());
+    }
+
+    #[test]
+    fn validates_cursor_values() {
+        let params =
+            FirestoreQueryParams::new("test".into()).with_order_by(vec![FirestoreQueryOrder::new(
+                "city".to_string(),
+                FirestoreQueryDirection::Ascending,
+            )]);
+
+        // The ordered field and the implicit document name
+        assert!(params
+            .clone()
+            .with_start_at(FirestoreQueryCursor::BeforeValue(vec!["Dublin".into()]))
+            .validate_cursors()
+            .is_ok());
+        assert!(params
+            .clone()
+            .with_end_at(FirestoreQueryCursor::AfterValue(vec![
+                "Dublin".into(),
+                "cities/dublin".into()
+            ]))
+            .validate_cursors()
+            .is_ok());
+        assert!(params
+            .clone()
+            .with_start_at(FirestoreQueryCursor::BeforeValue(vec![
+                "Dublin".into(),
+                2024.into(),
+                "cities/dublin".into()
+            ]))
+            .validate_cursors()
+            .is_err());
+
+        // Inequality filters are ordered implicitly
+        assert!(params
+            .with_filter(FirestoreQueryFilter::Compare(Some(
+                FirestoreQueryFilterCompare::GreaterThan("population".to_string(), 1000.into())
+            )))
+            .with_start_at(FirestoreQueryCursor::BeforeValue(vec![
+                "Dublin".into(),
+                2000.into(),
+                "cities/dublin".into()
+            ]))
+            .validate_cursors()
+            .is_ok());
+    }
+}
@@ -0,0 +1,480 @@
+use crate::db::query_models::FIRESTORE_DOCUMENT_NAME_FIELD;
+use crate::{
+    FirestoreQueryCollection, FirestoreQueryCursor, FirestoreQueryDirection, FirestoreQueryFilter,
+    FirestoreQueryFilterCompare, FirestoreQueryFilterComposite,
+    FirestoreQueryFilterCompositeOperator, FirestoreQueryFilterUnary, FirestoreQueryParams,
+    FirestoreValue,
+};
+use gcloud_sdk::google::firestore::v1::{value, ArrayValue, Value};
+use std::fmt::Write;
+
+impl FirestoreQueryParams {
+    /// Returns the canonical form of the query, selecting the same documents in the same order:
+    /// - nested composite filters with the same operator are flattened, composite filters with
+    ///   a single filter are replaced by the filter, and empty filters are removed;
+    /// - the filters of composite filters and the values of `in`, `not-in` and
+    ///   `array-contains-any` filters are sorted and deduplicated;
+    /// - cursors without values, a zero offset and the implicit ordering by the document name are removed;
+    /// - the projection fields and the collection group IDs are sorted and deduplicated.
+    pub fn normalized(&self) -> Self {
+        let mut params = self.clone();
+
+        params.filter = params.filter.as_ref().and_then(normalize_filter);
+
+        if let FirestoreQueryCollection::Group(collection_ids) = &mut params.collection_id {
+            collection_ids.sort();
+            collection_ids.dedup();
+        }
+
+        if let Some(fields) = &mut params.return_only_fields {
+            fields.sort();
+            fields.dedup();
+        }
+
+        params.start_at = params.start_at.filter(|cursor| !cursor.values().is_empty());
+        params.end_at = params.end_at.filter(|cursor| !cursor.values().is_empty());
+        params.offset = params.offset.filter(|offset| *offset > 0);
+        params.all_descendants = params.all_descendants.filter(|all| *all);
+
+        // Firestore orders by the document name implicitly, in the direction of the last ordering
+        params.order_by = params
+            .order_by
+            .map(|mut order_by| {
+                let implicit_direction = match order_by.len() {
+                    0 | 1 => FirestoreQueryDirection::Ascending,
+                    len => order_by[len - 2].direction.clone(),
+                };
+                if order_by.last().map_or(false, |last| {
+                    last.field_name == FIRESTORE_DOCUMENT_NAME_FIELD
+                        && last.direction == implicit_direction
+                }) {
+                    order_by.pop();
+                }
+                order_by
+            })
+            .filter(|order_by| !order_by.is_empty());
+
+        params
+    }
+
+    /// Returns the key identifying the query in caches. The key is generated from the
+    /// [normalized](FirestoreQueryParams::normalized) query, so queries built differently
+    /// (e.g. with the filters in another order) have the same key if they are equivalent.
+    pub fn cache_key(&self) -> String {
+        let params = self.normalized();
+        let mut key = String::new();
+
+        key.push_str("parent=");
+        if let Some(parent) = &params.parent {
+            write_string(parent, &mut key);
+        }
+        key.push_str(";from=");
+        match &params.collection_id {
+            FirestoreQueryCollection::Single(collection_id) => {
+                write_string(collection_id, &mut key)
+            }
+            FirestoreQueryCollection::Group(collection_ids) => {
+                key.push_str("group(");
+                write_strings(collection_ids, &mut key);
+                key.push(')');
+            }
+        }
+        if params.all_descendants.is_some() {
+            key.push_str(";all_descendants");
+        }
+        if let Some(filter) = &params.filter {
+            key.push_str(";where=");
+            key.push_str(&filter_key(filter));
+        }
+        if let Some(order_by) = &params.order_by {
+            key.push_str(";order_by=");
+            for (index, order) in order_by.iter().enumerate() {
+                if index > 0 {
+                    key.push(',');
+                }
+                write_string(&order.field_name, &mut key);
+                key.push(' ');
+                key.push_str(&order.direction.to_string());
+            }
+        }
+        for (name, cursor) in [("start_at", &params.start_at), ("end_at", &params.end_at)] {
+            if let Some(cursor) = cursor {
+                let _ = write!(key, ";{name}=");
+                key.push_str(match cursor {
+                    FirestoreQueryCursor::BeforeValue(_) => "before(",
+                    FirestoreQueryCursor::AfterValue(_) => "after(",
+                });
+                for (index, cursor_value) in cursor.values().iter().enumerate() {
+                    if index > 0 {
+                        key.push(',');
+                    }
+                    write_value(&cursor_value.value, &mut key);
+                }
+                key.push(')');
+            }
+        }
+        if let Some(limit) = params.limit {
+            let _ = write!(key, ";limit={limit}");
+        }
+        if let Some(limit_to_last) = params.limit_to_last {
+            let _ = write!(key, ";limit_to_last={limit_to_last}");
+        }
+        if let Some(offset) = params.offset {
+            let _ = write!(key, ";offset={offset}");
+        }
+        if let Some(fields) = &params.return_only_fields {
+            key.push_str(";select=");
+            write_strings(fields, &mut key);
+        }
+        if let Some(find_nearest) = &params.find_nearest {
+            let _ = write!(key, ";find_nearest={find_nearest:?}");
+        }
+        if let Some(explain_options) = &params.explain_options {
+            let _ = write!(key, ";explain={explain_options:?}");
+        }
+        if let Some(consistency_selector) = &params.consistency_selector {
+            let _ = write!(key, ";consistency={consistency_selector:?}");
+        }
+
+        key
+    }
+}
+
+fn normalize_filter(filter: &FirestoreQueryFilter) -> Option<FirestoreQueryFilter> {
+    match filter {
+        FirestoreQueryFilter::Composite(composite) => {
+            let mut filters: Vec<(String, FirestoreQueryFilter)> = Vec::new();
+            for filter in composite
+                .for_all_filters
+                .iter()
+                .filter_map(normalize_filter)
+            {
+                match filter {
+                    FirestoreQueryFilter::Composite(nested)
+                        if nested.operator == composite.operator =>
+                    {
+                        filters.extend(
+                            nested
+                                .for_all_filters
+                                .into_iter()
+                                .map(|filter| (filter_key(&filter), filter)),
+                        )
+                    }
+                    filter => filters.push((filter_key(&filter), filter)),
+                }
+            }
+            filters.sort_by(|(key_a, _), (key_b, _)| key_a.cmp(key_b));
+            filters.dedup_by(|(key_a, _), (key_b, _)| key_a == key_b);
+
+            match filters.len() {
+                0 => None,
+                1 => filters.pop().map(|(_, filter)| filter),
+                _ => Some(FirestoreQueryFilter::Composite(
+                    FirestoreQueryFilterComposite::new(
+                        filters.into_iter().map(|(_, filter)| filter).collect(),
+                        composite.operator.clone(),
+                    ),
+                )),
+            }
+        }
+        FirestoreQueryFilter::Unary(_) => Some(filter.clone()),
+        FirestoreQueryFilter::Compare(None) => None,
+        FirestoreQueryFilter::Compare(Some(compare)) => {
+            Some(FirestoreQueryFilter::Compare(Some(match compare {
+                FirestoreQueryFilterCompare::In(field_name, values) => {
+                    FirestoreQueryFilterCompare::In(field_name.clone(), normalize_values(values))
+                }
+                FirestoreQueryFilterCompare::NotIn(field_name, values) => {
+                    FirestoreQueryFilterCompare::NotIn(field_name.clone(), normalize_values(values))
+                }
+                FirestoreQueryFilterCompare::ArrayContainsAny(field_name, values) => {
+                    FirestoreQueryFilterCompare::ArrayContainsAny(
+                        field_name.clone(),
+                        normalize_values(values),
+                    )
+                }
+                compare => compare.clone(),
+            })))
+        }
+    }
+}
+
+/// Sorts and deduplicates the values of an array used as a set of values.
+fn normalize_values(values: &FirestoreValue) -> FirestoreValue {
+    match &values.value.value_type {
+        Some(value::ValueType::ArrayValue(array)) => {
+            let mut keyed_values: Vec<(String, Value)> = array
+                .values
+                .iter()
+                .map(|array_value| {
+                    let mut key = String::new();
+                    write_value(array_value, &mut key);
+                    (key, array_value.clone())
+                })
+                .collect();
+            keyed_values.sort_by(|(key_a, _), (key_b, _)| key_a.cmp(key_b));
+            keyed_values.dedup_by(|(key_a, _), (key_b, _)| key_a == key_b);
+            FirestoreValue::from(Value {
+                value_type: Some(value::ValueType::ArrayValue(ArrayValue {
+                    values: keyed_values.into_iter().map(|(_, value)| value).collect(),
+                })),
+            })
+        }
+        _ => values.clone(),
+    }
+}
+
+fn filter_key(filter: &FirestoreQueryFilter) -> String {
+    let mut key = String::new();
+    write_filter(filter, &mut key);
+    key
+}
+
+fn write_filter(filter: &FirestoreQueryFilter, key: &mut String) {
+    match filter {
+        FirestoreQueryFilter::Composite(composite) => {
+            key.push_str(match composite.operator {
+                FirestoreQueryFilterCompositeOperator::And => "and(",
+                FirestoreQueryFilterCompositeOperator::Or => "or(",
+            });
+            for (index, filter) in composite.for_all_filters.iter().enumerate() {
+                if index > 0 {
+                    key.push(',');
+                }
+                write_filter(filter, key);
+            }
+            key.push(')');
+        }
+        FirestoreQueryFilter::Unary(unary) => {
+            let (op, field_name) = match unary {
+                FirestoreQueryFilterUnary::IsNan(field_name) => ("is_nan", field_name),
+                FirestoreQueryFilterUnary::IsNull(field_name) => ("is_null", field_name),
+                FirestoreQueryFilterUnary::IsNotNan(field_name) => ("is_not_nan", field_name),
+                FirestoreQueryFilterUnary::IsNotNull(field_name) => ("is_not_null", field_name),
+            };
+            key.push_str(op);
+            key.push('(');
+            write_string(field_name, key);
+            key.push(')');
+        }
+        FirestoreQueryFilter::Compare(None) => key.push_str("none"),
+        FirestoreQueryFilter::Compare(Some(compare)) => {
+            let (op, field_name, compare_value) = match compare {
+                FirestoreQueryFilterCompare::LessThan(field_name, value) => {
+                    ("<", field_name, value)
+                }
+                FirestoreQueryFilterCompare::LessThanOrEqual(field_name, value) => {
+                    ("<=", field_name, value)
+                }
+                FirestoreQueryFilterCompare::GreaterThan(field_name, value) => {
+                    (">", field_name, value)
+                }
+                FirestoreQueryFilterCompare::GreaterThanOrEqual(field_name, value) => {
+                    (">=", field_name, value)
+                }
+                FirestoreQueryFilterCompare::Equal(field_name, value) => ("==", field_name, value),
+                FirestoreQueryFilterCompare::NotEqual(field_name, value) => {
+                    ("!=", field_name, value)
+                }
+                FirestoreQueryFilterCompare::ArrayContains(field_name, value) => {
+                    ("array-contains", field_name, value)
+                }
+                FirestoreQueryFilterCompare::In(field_name, value) => ("in", field_name, value),
+                FirestoreQueryFilterCompare::ArrayContainsAny(field_name, value) => {
+                    ("array-contains-any", field_name, value)
+                }
+                FirestoreQueryFilterCompare::NotIn(field_name, value) => {
+                    ("not-in", field_name, value)
+                }
+            };
+            write_string(field_name, key);
+            key.push(' ');
+            key.push_str(op);
+            key.push(' ');
+            write_value(&compare_value.value, key);
+        }
+    }
+}
+
+fn write_string(str: &str, key: &mut String) {
+    let _ = write!(key, "{str:?}");
+}
+
+fn write_strings(strs: &[String], key: &mut String) {
+    for (index, str) in strs.iter().enumerate() {
+        if index > 0 {
+            key.push(',');
+        }
+        write_string(str, key);
+    }
+}
+
+/// Writes the value with the fields of maps sorted, since the order of the fields
+/// of equal maps isn't stable.
+fn write_value(value: &Value, key: &mut String) {
+    match &value.value_type {
+        Some(value::ValueType::ArrayValue(array)) => {
+            key.push('[');
+            for (index, array_value) in array.values.iter().enumerate() {
+                if index > 0 {
+                    key.push(',');
+                }
+                write_value(array_value, key);
+            }
+            key.push(']');
+        }
+        Some(value::ValueType::MapValue(map)) => {
+            let mut fields: Vec<(&String, &Value)> = map.fields.iter().collect();
+            fields.sort_by_key(|(field, _)| *field);
+            key.push('{');
+            for (index, (field, field_value)) in fields.into_iter().enumerate() {
+                if index > 0 {
+                    key.push(',');
+                }
+                write_string(field, key);
+                key.push(':');
+                write_value(field_value, key);
+            }
+            key.push('}');
+        }
+        Some(value_type) => {
+            let _ = write!(key, "{value_type:?}");
+        }
+        None => key.push_str("None"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::select_filter_builder::FirestoreQueryFilterBuilder;
+    use crate::FirestoreQueryOrder;
+    use std::collections::HashMap;
+
+    fn query() -> FirestoreQueryParams {
+        FirestoreQueryParams::new("users".into())
+    }
+
+    #[test]
+    fn equivalent_filters_have_the_same_key() {
+        let q = FirestoreQueryFilterBuilder::new();
+        let a = query().with_filter(
+            q.for_all([
+                q.field("age").greater_than(18),
+                q.for_all([q.field("city").eq("Paris"), q.field("active").eq(true)]),
+            ])
+            .unwrap(),
+        );
+        let b = query().with_filter(
+            q.for_all([
+                q.field("active").eq(true),
+                q.field("age").greater_than(18),
+                q.field("city").eq("Paris"),
+                q.field("city").eq("Paris"),
+            ])
+            .unwrap(),
+        );
+        assert_eq!(a.cache_key(), b.cache_key());
+        assert_eq!(a.normalized(), b.normalized());
+
+        let single = query().with_filter(FirestoreQueryFilter::Composite(
+            FirestoreQueryFilterComposite::new(
+                vec![
+                    q.field("age").greater_than(18).unwrap(),
+                    FirestoreQueryFilter::Compare(None),
+                ],
+                FirestoreQueryFilterCompositeOperator::Or,
+            ),
+        ));
+        assert_eq!(
+            single.normalized().filter,
+            Some(FirestoreQueryFilter::Compare(Some(
+                FirestoreQueryFilterCompare::GreaterThan("age".to_string(), 18.into())
+            )))
+        );
+
+        let or = query().with_filter(
+            q.for_any([
+                q.field("age").greater_than(18),
+                q.for_all([q.field("city").eq("Paris"), q.field("active").eq(true)]),
+            ])
+            .unwrap(),
+        );
+        assert_ne!(a.cache_key(), or.cache_key());
+
+        let in_a = query().with_filter(q.field("city").is_in(["Paris", "Rome"]).unwrap());
+        let in_b = query().with_filter(q.field("city").is_in(["Rome", "Paris", "Rome"]).unwrap());
+        assert_eq!(in_a.cache_key(), in_b.cache_key());
+        assert_ne!(query().cache_key(), in_a.cache_key());
+    }
+
+    #[test]
+    fn equivalent_cursors_and_orders_have_the_same_key() {
+        let a = query()
+            .with_order_by(vec![FirestoreQueryOrder::new(
+                "age".to_string(),
+                FirestoreQueryDirection::Descending,
+            )])
+            .with_start_at(FirestoreQueryCursor::AfterValue(vec![]))
+            .with_offset(0)
+            .with_return_only_fields(vec!["name".to_string(), "age".to_string()]);
+        let b = query()
+            .with_order_by(vec![
+                FirestoreQueryOrder::new("age".to_string(), FirestoreQueryDirection::Descending),
+                FirestoreQueryOrder::new(
+                    FIRESTORE_DOCUMENT_NAME_FIELD.to_string(),
+                    FirestoreQueryDirection::Descending,
+                ),
+            ])
+            .with_return_only_fields(vec!["age".to_string(), "name".to_string()]);
+        assert_eq!(a.cache_key(), b.cache_key());
+
+        let reversed_name = query().with_order_by(vec![
+            FirestoreQueryOrder::new("age".to_string(), FirestoreQueryDirection::Descending),
+            FirestoreQueryOrder::new(
+                FIRESTORE_DOCUMENT_NAME_FIELD.to_string(),
+                FirestoreQueryDirection::Ascending,
+            ),
+        ]);
+        assert_ne!(b.cache_key(), reversed_name.cache_key());
+
+        let after = query().with_start_at(FirestoreQueryCursor::AfterValue(vec![10.into()]));
+        let before = query().with_start_at(FirestoreQueryCursor::BeforeValue(vec![10.into()]));
+        assert_ne!(after.cache_key(), before.cache_key());
+    }
+
+    #[test]
+    fn map_values_have_stable_keys() {
+        let map = |fields: &[(&str, i64)]| {
+            FirestoreValue::from(Value {
+                value_type: Some(value::ValueType::MapValue(
+                    gcloud_sdk::google::firestore::v1::MapValue {
+                        fields: fields
+                            .iter()
+                            .map(|(field, num)| {
+                                (
+                                    field.to_string(),
+                                    Value {
+                                        value_type: Some(value::ValueType::IntegerValue(*num)),
+                                    },
+                                )
+                            })
+                            .collect::<HashMap<String, Value>>(),
+                    },
+                )),
+            })
+        };
+        let fields: Vec<(String, i64)> = (0..20).map(|i| (format!("f{i}"), i)).collect();
+        let fields: Vec<(&str, i64)> = fields.iter().map(|(f, i)| (f.as_str(), *i)).collect();
+        let mut reversed = fields.clone();
+        reversed.reverse();
+
+        let a = query().with_filter(FirestoreQueryFilter::Compare(Some(
+            FirestoreQueryFilterCompare::Equal("settings".to_string(), map(&fields)),
+        )));
+        let b = query().with_filter(FirestoreQueryFilter::Compare(Some(
+            FirestoreQueryFilterCompare::Equal("settings".to_string(), map(&reversed)),
+        )));
+        assert_eq!(a.cache_key(), b.cache_key());
+    }
+}
@@ -0,0 +1,459 @@
+use crate::db::order_by_with_document_name;
+use crate::errors::*;
+use crate::firestore_value::compare_field_values;
+use crate::{
+    firestore_doc_get_field_by_path, FirestoreDb, FirestoreQueryDirection, FirestoreQueryFilter,
+    FirestoreQueryFilterCompare, FirestoreQueryFilterComposite,
+    FirestoreQueryFilterCompositeOperator, FirestoreQueryOrder, FirestoreQueryParams,
+    FirestoreQuerySupport, FirestoreResult, FirestoreValue, FIRESTORE_DOCUMENT_NAME_FIELD,
+};
+use futures::{StreamExt, TryStreamExt};
+use gcloud_sdk::google::firestore::v1::{value, ArrayValue, Document, Value};
+use rsb_derive::*;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// The maximum number of values of `IN` and `ARRAY_CONTAINS_ANY` filters in a single Firestore query.
+pub const FIRESTORE_QUERY_DISJUNCTION_LIMIT: usize = 30;
+
+/// The maximum number of values of a `NOT_IN` filter in a single Firestore query.
+pub const FIRESTORE_QUERY_NOT_IN_LIMIT: usize = 10;
+
+/// The default number of split queries running at the same time.
+pub const FIRESTORE_QUERY_SPLIT_DEFAULT_CONCURRENCY: usize = 10;
+
+/// Options for queries with `IN`, `NOT_IN` and `ARRAY_CONTAINS_ANY` filters exceeding
+/// the Firestore limits on the number of values.
+///
+/// The values of an oversized `IN` or `ARRAY_CONTAINS_ANY` filter are split into chunks
+/// queried separately, with up to `max_concurrency` queries running at the same time.
+/// The results are deduplicated and merged in the order of the query.
+/// Only the first values of an oversized `NOT_IN` filter are sent to Firestore,
+/// and the documents matching the rest of them are excluded on the client.
+#[derive(Debug, PartialEq, Clone, Builder)]
+pub struct FirestoreQuerySplitOptions {
+    /// The maximum number of `IN` and `ARRAY_CONTAINS_ANY` values in a single query.
+    #[default = "FIRESTORE_QUERY_DISJUNCTION_LIMIT"]
+    pub max_values_per_query: usize,
+    /// The maximum number of `NOT_IN` values in a single query.
+    #[default = "FIRESTORE_QUERY_NOT_IN_LIMIT"]
+    pub max_not_in_values: usize,
+    /// The maximum number of split queries running at the same time.
+    #[default = "FIRESTORE_QUERY_SPLIT_DEFAULT_CONCURRENCY"]
+    pub max_concurrency: usize,
+}
+
+impl Default for FirestoreQuerySplitOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The queries a query with oversized filters is split into.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct FirestoreSplitQuery {
+    pub queries: Vec<FirestoreQueryParams>,
+    /// The `NOT_IN` field and values excluded on the client.
+    pub excluded: Option<(String, Vec<Value>)>,
+}
+
+fn array_values(value: &FirestoreValue) -> Option<&Vec<Value>> {
+    match &value.value.value_type {
+        Some(value::ValueType::ArrayValue(array)) => Some(&array.values),
+        _ => None,
+    }
+}
+
+fn array_value(values: &[Value]) -> FirestoreValue {
+    FirestoreValue::from(Value {
+        value_type: Some(value::ValueType::ArrayValue(ArrayValue {
+            values: values.to_vec(),
+        })),
+    })
+}
+
+fn conjunction(mut filters: Vec<FirestoreQueryFilter>) -> Option<FirestoreQueryFilter> {
+    match filters.len() {
+        0 => None,
+        1 => filters.pop(),
+        _ => Some(FirestoreQueryFilter::Composite(
+            FirestoreQueryFilterComposite::new(filters, FirestoreQueryFilterCompositeOperator::And),
+        )),
+    }
+}
+
+impl FirestoreQuerySplitOptions {
+    /// Splits the query by the oversized filters at the top level of the query
+    /// or in its top-level `AND` filter.
+    pub(crate) fn split_query(
+        &self,
+        params: &FirestoreQueryParams,
+    ) -> FirestoreResult<FirestoreSplitQuery> {
+        let max_values = self.max_values_per_query.max(1);
+        let mut filters = match &params.filter {
+            Some(FirestoreQueryFilter::Composite(composite))
+                if composite.operator == FirestoreQueryFilterCompositeOperator::And =>
+            {
+                composite.for_all_filters.clone()
+            }
+            Some(filter) => vec![filter.clone()],
+            None => vec![],
+        };
+
+        let mut excluded = None;
+        let mut split_by: Option<(usize, Vec<Value>)> = None;
+        for (index, filter) in filters.iter_mut().enumerate() {
+            match filter {
+                FirestoreQueryFilter::Compare(Some(
+                    FirestoreQueryFilterCompare::In(field_name, values)
+                    | FirestoreQueryFilterCompare::ArrayContainsAny(field_name, values),
+                )) => match array_values(values) {
+                    Some(values) if values.len() > max_values => {
+                        if split_by.is_some() {
+                            return Err(FirestoreError::InvalidParametersError(
+                                FirestoreInvalidParametersError::new(
+                                    FirestoreInvalidParametersPublicDetails::new(
+                                        field_name.clone(),
+                                        "Only one IN or ARRAY_CONTAINS_ANY filter exceeding the limit of values can be split".to_string(),
+                                    ),
+                                ),
+                            ));
+                        }
+                        split_by = Some((index, values.clone()));
+                    }
+                    _ => {}
+                },
+                FirestoreQueryFilter::Compare(Some(FirestoreQueryFilterCompare::NotIn(
+                    field_name,
+                    values,
+                ))) => {
+                    let max_not_in_values = self.max_not_in_values.max(1);
+                    if let Some(all_values) = array_values(values)
+                        .filter(|all_values| all_values.len() > max_not_in_values)
+                        .cloned()
+                    {
+                        *values = array_value(&all_values[..max_not_in_values]);
+                        excluded =
+                            Some((field_name.clone(), all_values[max_not_in_values..].to_vec()));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let split_params = FirestoreQueryParams {
+            offset: None,
+            limit: match (&excluded, params.limit) {
+                (None, Some(limit)) => Some(limit + params.offset.unwrap_or(0)),
+                _ => None,
+            },
+            limit_to_last: match (&excluded, params.limit_to_last) {
+                (None, Some(limit)) => Some(limit + params.offset.unwrap_or(0)),
+                _ => None,
+            },
+            ..params.clone()
+        };
+
+        let queries = match split_by {
+            Some((index, values)) => values
+                .chunks(max_values)
+                .map(|chunk| {
+                    let mut chunk_filters = filters.clone();
+                    if let FirestoreQueryFilter::Compare(Some(
+                        FirestoreQueryFilterCompare::In(_, values)
+                        | FirestoreQueryFilterCompare::ArrayContainsAny(_, values),
+                    )) = &mut chunk_filters[index]
+                    {
+                        *values = array_value(chunk);
+                    }
+                    split_params.clone().opt_filter(conjunction(chunk_filters))
+                })
+                .collect(),
+            None => vec![split_params.opt_filter(conjunction(filters))],
+        };
+
+        Ok(FirestoreSplitQuery { queries, excluded })
+    }
+}
+
+fn doc_order_value<'d>(doc: &'d Document, field_name: &str) -> Option<Cow<'d, value::ValueType>> {
+    if field_name == FIRESTORE_DOCUMENT_NAME_FIELD {
+        Some(Cow::Owned(value::ValueType::ReferenceValue(
+            doc.name.clone(),
+        )))
+    } else {
+        firestore_doc_get_field_by_path(doc, field_name).map(Cow::Borrowed)
+    }
+}
+
+fn compare_docs(left: &Document, right: &Document, order_by: &[FirestoreQueryOrder]) -> Ordering {
+    order_by
+        .iter()
+        .map(|order| {
+            let ordering = match (
+                doc_order_value(left, &order.field_name),
+                doc_order_value(right, &order.field_name),
+            ) {
+                (Some(left), Some(right)) => compare_field_values(&left, &right),
+                _ => Ordering::Equal,
+            };
+            match order.direction {
+                FirestoreQueryDirection::Ascending => ordering,
+                FirestoreQueryDirection::Descending => ordering.reverse(),
+            }
+        })
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+fn field_values_equal(left: &value::ValueType, right: &value::ValueType) -> bool {
+    match (left, right) {
+        (value::ValueType::IntegerValue(_), value::ValueType::DoubleValue(_))
+        | (value::ValueType::DoubleValue(_), value::ValueType::IntegerValue(_)) => {
+            compare_field_values(left, right) == Ordering::Equal
+        }
+        _ => left == right,
+    }
+}
+
+/// Merges the results of the split queries: removes the duplicates and the excluded documents,
+/// orders them as the original query and applies its offset and limits.
+pub(crate) fn merge_split_query_results(
+    params: &FirestoreQueryParams,
+    excluded: Option<&(String, Vec<Value>)>,
+    results: Vec<Vec<Document>>,
+) -> Vec<Document> {
+    let mut seen = HashSet::new();
+    let mut docs: Vec<Document> = results
+        .into_iter()
+        .flatten()
+        .filter(|doc| seen.insert(doc.name.clone()))
+        .filter(|doc| match excluded {
+            Some((field_name, values)) => match firestore_doc_get_field_by_path(doc, field_name) {
+                Some(field_value) => !values.iter().any(|value| {
+                    value
+                        .value_type
+                        .as_ref()
+                        .map(|value| field_values_equal(field_value, value))
+                        .unwrap_or(false)
+                }),
+                None => true,
+            },
+            None => true,
+        })
+        .collect();
+
+    let order_by = order_by_with_document_name(params.order_by.clone().or_else(|| {
+        let mut inequality_fields = params
+            .filter
+            .as_ref()
+            .map(|filter| filter.inequality_fields())
+            .unwrap_or_default();
+        inequality_fields.sort();
+        inequality_fields.dedup();
+        Some(
+            inequality_fields
+                .into_iter()
+                .map(|field_name| {
+                    FirestoreQueryOrder::new(
+                        field_name.to_string(),
+                        FirestoreQueryDirection::Ascending,
+                    )
+                })
+                .collect(),
+        )
+    }));
+    docs.sort_by(|left, right| compare_docs(left, right, &order_by));
+
+    let offset = params.offset.unwrap_or(0) as usize;
+    match (params.limit, params.limit_to_last) {
+        (_, Some(limit_to_last)) => {
+            // The offset of the last results is counted from the end
+            let end = docs.len().saturating_sub(offset);
+            let start = end.saturating_sub(limit_to_last as usize);
+            docs.into_iter().skip(start).take(end - start).collect()
+        }
+        (Some(limit), None) => docs.into_iter().skip(offset).take(limit as usize).collect(),
+        (None, None) => docs.into_iter().skip(offset).collect(),
+    }
+}
+
+pub(crate) async fn query_doc_split<D>(
+    db: &D,
+    params: FirestoreQueryParams,
+    options: &FirestoreQuerySplitOptions,
+) -> FirestoreResult<Vec<Document>>
+where
+    D: FirestoreQuerySupport + Sync,
+{
+    let split_query = options.split_query(&params)?;
+    let max_concurrency = options.max_concurrency.max(1);
+    let results: Vec<Vec<Document>> = futures::stream::iter(
+        split_query
+            .queries
+            .into_iter()
+            .map(|query| db.query_doc(query)),
+    )
+    .buffer_unordered(max_concurrency)
+    .try_collect()
+    .await?;
+
+    Ok(merge_split_query_results(
+        &params,
+        split_query.excluded.as_ref(),
+        results,
+    ))
+}
+
+pub(crate) async fn query_obj_split<D, T>(
+    db: &D,
+    params: FirestoreQueryParams,
+    options: &FirestoreQuerySplitOptions,
+) -> FirestoreResult<Vec<T>>
+where
+    D: FirestoreQuerySupport + Sync,
+    for<'de> T: Deserialize<'de>,
+{
+    query_doc_split(db, params, options)
+        .await?
+        .iter()
+        .map(FirestoreDb::deserialize_doc_to)
+        .collect()
+}
+
+impl FirestoreDb {
+    /// Runs the query splitting its `IN`, `NOT_IN` and `ARRAY_CONTAINS_ANY` filters
+    /// with more values than Firestore supports in a single query.
+    /// See [`FirestoreQuerySplitOptions`] for the details.
+    pub async fn query_doc_split(
+        &self,
+        params: FirestoreQueryParams,
+        options: &FirestoreQuerySplitOptions,
+    ) -> FirestoreResult<Vec<Document>> {
+        query_doc_split(self, params, options).await
+    }
+
+    /// Runs the query splitting its oversized filters as [`Self::query_doc_split`]
+    /// and deserializes the results.
+    pub async fn query_obj_split<T>(
+        &self,
+        params: FirestoreQueryParams,
+        options: &FirestoreQuerySplitOptions,
+    ) -> FirestoreResult<Vec<T>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        query_obj_split(self, params, options).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn test_doc(id: &str, rank: i64) -> Document {
+        let rank: FirestoreValue = rank.into();
+        Document {
+            name: format!("projects/test/databases/(default)/documents/test/{id}"),
+            fields: HashMap::from([("rank".to_string(), rank.value)]),
+            create_time: None,
+            update_time: None,
+        }
+    }
+
+    fn in_filter(values: Vec<i64>) -> FirestoreQueryFilter {
+        FirestoreQueryFilter::Compare(Some(FirestoreQueryFilterCompare::In(
+            "rank".to_string(),
+            values.into(),
+        )))
+    }
+
+    #[test]
+    fn splits_oversized_filters() {
+        let status_filter = FirestoreQueryFilter::Compare(Some(
+            FirestoreQueryFilterCompare::Equal("status".to_string(), "active".into()),
+        ));
+        let params = FirestoreQueryParams::new("test".into())
+            .with_filter(FirestoreQueryFilter::Composite(
+                FirestoreQueryFilterComposite::new(
+                    vec![status_filter.clone(), in_filter((0..65).collect())],
+                    FirestoreQueryFilterCompositeOperator::And,
+                ),
+            ))
+            .with_limit(10)
+            .with_offset(5);
+
+        let split_query = FirestoreQuerySplitOptions::new()
+            .split_query(&params)
+            .unwrap();
+        assert_eq!(split_query.queries.len(), 3);
+        assert_eq!(
+            split_query.queries[2].filter,
+            Some(FirestoreQueryFilter::Composite(
+                FirestoreQueryFilterComposite::new(
+                    vec![status_filter, in_filter((60..65).collect())],
+                    FirestoreQueryFilterCompositeOperator::And,
+                )
+            ))
+        );
+        assert_eq!(split_query.queries[0].limit, Some(15));
+        assert_eq!(split_query.queries[0].offset, None);
+        assert_eq!(split_query.excluded, None);
+
+        let not_in_params = FirestoreQueryParams::new("test".into()).with_filter(
+            FirestoreQueryFilter::Compare(Some(FirestoreQueryFilterCompare::NotIn(
+                "rank".to_string(),
+                (0..12).collect::<Vec<i64>>().into(),
+            ))),
+        );
+        let split_query = FirestoreQuerySplitOptions::new()
+            .split_query(&not_in_params)
+            .unwrap();
+        assert_eq!(split_query.queries.len(), 1);
+        assert_eq!(
+            split_query.excluded.map(|(_, values)| values.len()),
+            Some(2)
+        );
+
+        let two_splits = FirestoreQueryParams::new("test".into()).with_filter(
+            FirestoreQueryFilter::Composite(FirestoreQueryFilterComposite::new(
+                vec![in_filter((0..40).collect()), in_filter((0..40).collect())],
+                FirestoreQueryFilterCompositeOperator::And,
+            )),
+        );
+        assert!(FirestoreQuerySplitOptions::new()
+            .split_query(&two_splits)
+            .is_err());
+    }
+
+    #[test]
+    fn merges_split_results() {
+        let params = FirestoreQueryParams::new("test".into())
+            .with_order_by(vec![FirestoreQueryOrder::new(
+                "rank".to_string(),
+                FirestoreQueryDirection::Descending,
+            )])
+            .with_offset(1)
+            .with_limit(3);
+        let excluded_value: FirestoreValue = 4.into();
+        let excluded = ("rank".to_string(), vec![excluded_value.value]);
+
+        let docs = merge_split_query_results(
+            &params,
+            Some(&excluded),
+            vec![
+                vec![test_doc("a", 5), test_doc("b", 3), test_doc("c", 1)],
+                vec![test_doc("d", 4), test_doc("b", 3), test_doc("e", 2)],
+            ],
+        );
+        assert_eq!(
+            docs.iter()
+                .map(|doc| doc.name.rsplit('/').next().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["b", "e", "c"]
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use crate::errors::*;
+use crate::*;
+use futures::Future;
+use gcloud_sdk::tonic::Code;
+use rand::Rng;
+use rsb_derive::*;
+use std::time::Duration;
+use tracing::*;
+
+tokio::task_local! {
+    static FIRESTORE_RETRY_POLICY_OVERRIDE: FirestoreRetryPolicy;
+}
+
+/// The policy of retrying failed Firestore operations.
+///
+/// The policy is configured globally with [`FirestoreDbOptions::retry_policy`] and can be
+/// overridden for a single fluent operation with `.retry(policy)`.
+///
+/// Errors are retried with an exponential backoff with full jitter when their gRPC status code
+/// is one of `retryable_codes`. Connection errors are retried as `UNAVAILABLE`.
+/// Operations that may have a different result when applied twice (e.g. creating documents
+/// or applying transformations) are retried only if `retry_non_idempotent` is enabled.
+///
+/// # Examples
+///
+/// ```rust
+/// use firestore::FirestoreRetryPolicy;
+/// use std::time::Duration;
+///
+/// let policy = FirestoreRetryPolicy::new()
+///     .with_max_attempts(5)
+///     .with_initial_backoff(Duration::from_millis(200))
+///     .with_max_backoff(Duration::from_secs(10));
+/// assert!(!policy.retry_non_idempotent);
+/// ```
+#[derive(Debug, Eq, PartialEq, Clone, Builder)]
+pub struct FirestoreRetryPolicy {
+    /// The maximum number of attempts, including the first one. Defaults to `4`.
+    /// Use `1` to disable retries.
+    #[default = "4"]
+    pub max_attempts: usize,
+
+    /// The upper bound of the delay before the first retry. Defaults to 1 second.
+    #[default = "Duration::from_secs(1)"]
+    pub initial_backoff: Duration,
+
+    /// The maximum upper bound of the delay between retries. Defaults to 30 seconds.
+    #[default = "Duration::from_secs(30)"]
+    pub max_backoff: Duration,
+
+    /// The multiplier of the delay upper bound for each subsequent retry. Defaults to `2`.
+    #[default = "2"]
+    pub backoff_multiplier: u32,
+
+    /// The gRPC status codes to retry. Defaults to `UNAVAILABLE`, `RESOURCE_EXHAUSTED`,
+    /// `ABORTED` and `CANCELLED`.
+    #[default = "FirestoreRetryPolicy::DEFAULT_RETRYABLE_CODES.to_vec()"]
+    pub retryable_codes: Vec<Code>,
+
+    /// Retry operations that aren't idempotent. Defaults to `false`.
+    #[default = "false"]
+    pub retry_non_idempotent: bool,
+
+    /// Refresh the credentials and retry once when an operation fails with `UNAUTHENTICATED`,
+    /// resuming query streams after the last received document and restarting listeners.
+    /// Defaults to `true`.
+    #[default = "true"]
+    pub reauthenticate: bool,
+}
+
+impl FirestoreRetryPolicy {
+    /// The gRPC status codes retried by default.
+    pub const DEFAULT_RETRYABLE_CODES: [Code; 4] = [
+        Code::Unavailable,
+        Code::ResourceExhausted,
+        Code::Aborted,
+        Code::Cancelled,
+    ];
+
+    /// A policy that doesn't retry failed operations.
+    pub fn no_retries() -> Self {
+        Self::new().with_max_attempts(1)
+    }
+
+    /// Returns `true` if the error may be resolved by retrying the operation.
+    pub fn is_retryable(&self, err: &FirestoreError) -> bool {
+        match err {
+            FirestoreError::DatabaseError(db_err) => {
+                let code = if db_err.retry_possible && db_err.public.code.starts_with("CONNECTION_")
+                {
+                    format!("{:?}", Code::Unavailable)
+                } else {
+                    db_err.public.code.clone()
+                };
+                self.retryable_codes
+                    .iter()
+                    .any(|retryable_code| format!("{retryable_code:?}") == code)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the operation failed with the error after the specified number
+    /// of retries should be retried again.
+    pub fn should_retry(&self, err: &FirestoreError, retries: usize, idempotent: bool) -> bool {
+        retries + 1 < self.max_attempts
+            && (idempotent || self.retry_non_idempotent)
+            && self.is_retryable(err)
+    }
+
+    /// Returns a random delay before the next retry after the specified number of retries.
+    pub fn backoff(&self, retries: usize) -> Duration {
+        let upper_bound = self
+            .backoff_multiplier
+            .checked_pow(retries as u32)
+            .and_then(|multiplier| self.initial_backoff.checked_mul(multiplier))
+            .map(|backoff| backoff.min(self.max_backoff))
+            .unwrap_or(self.max_backoff);
+
+        Duration::from_millis(rand::rng().random_range(0..=upper_bound.as_millis() as u64))
+    }
+
+    /// Returns the policy for the current operation: the policy overridden for
+    /// a fluent operation, the configured one or the default one with
+    /// [`FirestoreDbOptions::max_retries`].
+    pub(crate) fn current(options: &FirestoreDbOptions) -> Self {
+        FIRESTORE_RETRY_POLICY_OVERRIDE
+            .try_with(|policy| policy.clone())
+            .ok()
+            .or_else(|| options.retry_policy.clone())
+            .unwrap_or_else(|| Self::new().with_max_attempts(options.max_retries + 1))
+    }
+
+    /// Returns the policy overridden for the current fluent operation, if any.
+    pub(crate) fn current_override() -> Option<Self> {
+        FIRESTORE_RETRY_POLICY_OVERRIDE
+            .try_with(|policy| policy.clone())
+            .ok()
+    }
+
+    /// Runs the future with the policy overriding the configured one.
+    pub(crate) async fn scope<F>(policy: Option<Self>, future: F) -> F::Output
+    where
+        F: Future,
+    {
+        match policy {
+            Some(policy) => FIRESTORE_RETRY_POLICY_OVERRIDE.scope(policy, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// Returns `true` if the request was rejected because of invalid or expired credentials.
+pub(crate) fn is_unauthenticated_error(err: &FirestoreError) -> bool {
+    matches!(err, FirestoreError::DatabaseError(db_err)
+        if db_err.public.code == format!("{:?}", Code::Unauthenticated))
+}
+
+impl FirestoreDb {
+    /// Executes the operation, retrying it according to the current retry policy.
+    pub(crate) async fn execute_with_retries<T, F, FR>(
+        &self,
+        operation: &str,
+        idempotent: bool,
+        mut execute: F,
+    ) -> FirestoreResult<T>
+    where
+        F: FnMut() -> FR,
+        FR: Future<Output = FirestoreResult<T>>,
+    {
+        let retry_policy = FirestoreRetryPolicy::current(self.get_options());
+        let mut retries = 0;
+        let mut reauthenticated = false;
+        loop {
+            match execute().await {
+                // The rejected request wasn't applied, so it's retried even if not idempotent
+                Err(err)
+                    if retry_policy.reauthenticate
+                        && !reauthenticated
+                        && is_unauthenticated_error(&err) =>
+                {
+                    warn!(
+                        %err,
+                        "{operation} failed with invalid credentials. Refreshing the credentials and retrying.",
+                    );
+                    self.refresh_credentials().await;
+                    reauthenticated = true;
+                }
+                Err(err) if retry_policy.should_retry(&err, retries, idempotent) => {
+                    let sleep_duration = retry_policy.backoff(retries);
+                    warn!(
+                        %err,
+                        current_retry = retries + 1,
+                        max_attempts = retry_policy.max_attempts,
+                        delay = sleep_duration.as_millis(),
+                        "{operation} failed. Retrying according to the retry policy.",
+                    );
+                    tokio::time::sleep(sleep_duration).await;
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database_error(code: &str, retry_possible: bool) -> FirestoreError {
+        FirestoreError::DatabaseError(FirestoreDatabaseError::new(
+            FirestoreErrorPublicGenericDetails::new(code.to_string()),
+            "test".to_string(),
+            retry_possible,
+        ))
+    }
+
+    #[test]
+    fn retries_configured_codes() {
+        let policy = FirestoreRetryPolicy::new().with_max_attempts(3);
+
+        assert!(policy.should_retry(&database_error("Unavailable", true), 0, true));
+        assert!(policy.should_retry(&database_error("CONNECTION_CLOSED", true), 1, true));
+        assert!(!policy.should_retry(&database_error("Unavailable", true), 2, true));
+        assert!(!policy.should_retry(&database_error("Unavailable", true), 0, false));
+        assert!(!policy.should_retry(&database_error("DeadlineExceeded", false), 0, true));
+
+        let policy = policy
+            .with_retryable_codes(vec![Code::DeadlineExceeded])
+            .with_retry_non_idempotent(true);
+        assert!(policy.should_retry(&database_error("DeadlineExceeded", false), 0, false));
+        assert!(!policy.should_retry(&database_error("CONNECTION_CLOSED", true), 0, true));
+    }
+
+    #[test]
+    fn detects_unauthenticated_errors() {
+        assert!(is_unauthenticated_error(&database_error(
+            "Unauthenticated",
+            false
+        )));
+        assert!(!is_unauthenticated_error(&database_error(
+            "Unavailable",
+            true
+        )));
+    }
+
+    #[test]
+    fn backoff_is_bounded() {
+        let policy = FirestoreRetryPolicy::new()
+            .with_initial_backoff(Duration::from_millis(100))
+            .with_max_backoff(Duration::from_millis(500));
+
+        for retries in 0..100 {
+            let upper_bound =
+                Duration::from_millis(2u64.saturating_pow(retries as u32).saturating_mul(100))
+                    .min(Duration::from_millis(500));
+            assert!(policy.backoff(retries) <= upper_bound);
+        }
+    }
+
+    #[tokio::test]
+    async fn overrides_configured_policy() {
+        let options = FirestoreDbOptions::new("test".to_string()).with_max_retries(5);
+        assert_eq!(FirestoreRetryPolicy::current(&options).max_attempts, 6);
+
+        let options = options.with_retry_policy(FirestoreRetryPolicy::new());
+        assert_eq!(FirestoreRetryPolicy::current(&options).max_attempts, 4);
+
+        let max_attempts =
+            FirestoreRetryPolicy::scope(Some(FirestoreRetryPolicy::no_retries()), async {
+                FirestoreRetryPolicy::current(&options).max_attempts
+            })
+            .await;
+        assert_eq!(max_attempts, 1);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::db::split_document_path;
+use crate::errors::*;
+use crate::*;
+use gcloud_sdk::google::firestore::v1::Document;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::*;
+
+const FIRESTORE_CACHE_REVALIDATIONS_PRUNE_SIZE: usize = 10000;
+
+/// Tracks when cached documents were last revalidated for the stale-while-revalidate strategy.
+#[derive(Debug, Default)]
+pub(crate) struct FirestoreCacheRevalidations {
+    revalidated_at: Mutex<HashMap<String, Instant>>,
+}
+
+impl FirestoreCacheRevalidations {
+    /// Returns `true` and marks the document as revalidated if it hasn't been revalidated
+    /// within the specified interval.
+    fn start_revalidation(&self, document_path: &str, interval: Duration) -> bool {
+        let now = Instant::now();
+        let mut revalidated_at = self
+            .revalidated_at
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let is_stale = match revalidated_at.get(document_path) {
+            Some(last_revalidated) => now.duration_since(*last_revalidated) >= interval,
+            None => true,
+        };
+
+        if is_stale {
+            if revalidated_at.len() >= FIRESTORE_CACHE_REVALIDATIONS_PRUNE_SIZE {
+                revalidated_at
+                    .retain(|_, last_revalidated| now.duration_since(*last_revalidated) < interval);
+            }
+            revalidated_at.insert(document_path.to_string(), now);
+        }
+        is_stale
+    }
+}
+
+impl FirestoreDb {
+    fn write_through_cache(&self, document_path: &str) -> Option<&FirestoreSharedCacheBackend> {
+        match self.session_params.cache_mode {
+            FirestoreDbSessionCacheMode::ReadThroughCache(ref cache)
+            | FirestoreDbSessionCacheMode::ReadCachedOnly(ref cache) => {
+                let (collection_path, _) = split_document_path(document_path);
+                cache
+                    .collection_consistency(collection_path)
+                    .filter(|consistency| consistency.write_through)
+                    .map(|_| cache)
+            }
+            FirestoreDbSessionCacheMode::None => None,
+        }
+    }
+
+    /// Writes the document to the cache if the collection is configured as write-through.
+    ///
+    /// Partial documents (e.g. returned with a field mask) are removed from the cache instead,
+    /// so the following reads load them from Firestore.
+    pub(crate) async fn offer_doc_write_to_cache(&self, document: &Document, full_document: bool) {
+        if let Some(cache) = self.write_through_cache(&document.name) {
+            let result = if full_document {
+                cache.update_doc_by_path(document).await
+            } else {
+                cache.delete_doc_by_path(&document.name).await
+            };
+            if let Err(err) = result {
+                warn!(%err, document_path = document.name, "Unable to write document to cache.");
+            }
+        }
+    }
+
+    /// Removes the document from the cache if the collection is configured as write-through.
+    pub(crate) async fn offer_doc_delete_to_cache(&self, document_path: &str) {
+        if let Some(cache) = self.write_through_cache(document_path) {
+            if let Err(err) = cache.delete_doc_by_path(document_path).await {
+                warn!(%err, document_path, "Unable to remove document from cache.");
+            }
+        }
+    }
+
+    /// Refreshes a cached document in the background if the collection is configured
+    /// as stale-while-revalidate and the document is stale.
+    pub(crate) fn offer_cached_doc_revalidation(&self, collection_id: &str, document_path: &str) {
+        if let FirestoreDbSessionCacheMode::ReadThroughCache(ref cache) =
+            self.session_params.cache_mode
+        {
+            let (collection_path, _) = split_document_path(document_path);
+            let interval = match cache
+                .collection_consistency(collection_path)
+                .and_then(|consistency| consistency.stale_while_revalidate)
+            {
+                Some(interval) => interval,
+                None => return,
+            };
+
+            if !self
+                .inner
+                .cache_revalidations
+                .start_revalidation(document_path, interval)
+            {
+                return;
+            }
+
+            let cache = cache.clone();
+            let db = self.clone_with_session_params(FirestoreDbSessionParams::new());
+            let collection_id = collection_id.to_string();
+            let document_path = document_path.to_string();
+
+            tokio::spawn(async move {
+                debug!(document_path, "Revalidating cached document.");
+                let result = match db
+                    .get_doc_by_path(collection_id, document_path.clone(), None, 0)
+                    .await
+                {
+                    Ok(doc) => cache.update_doc_by_path(&doc).await,
+                    Err(FirestoreError::DataNotFoundError(_)) => {
+                        cache.delete_doc_by_path(&document_path).await
+                    }
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = result {
+                    warn!(%err, document_path, "Unable to revalidate cached document.");
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revalidations_are_throttled() {
+        let revalidations = FirestoreCacheRevalidations::default();
+
+        assert!(revalidations.start_revalidation("col/doc-1", Duration::from_secs(60)));
+        assert!(!revalidations.start_revalidation("col/doc-1", Duration::from_secs(60)));
+        assert!(revalidations.start_revalidation("col/doc-2", Duration::from_secs(60)));
+        assert!(revalidations.start_revalidation("col/doc-1", Duration::ZERO));
+    }
+}
@@ -0,0 +1,410 @@
+use crate::errors::*;
+use crate::{FirestoreApiRequest, FirestoreDb, FirestoreResult, FirestoreTransactionId};
+use futures::Future;
+use gcloud_sdk::google::firestore::v1::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+use tracing::*;
+
+/// The shutdown state shared by the clones of a [`FirestoreDb`].
+pub(crate) struct FirestoreDbShutdownState {
+    /// The deadline of the grace period, set when the shutdown is requested.
+    deadline: watch::Sender<Option<Instant>>,
+    /// The number of listeners, writers and transactions still draining.
+    drains: watch::Sender<usize>,
+    transactions: Mutex<HashMap<u64, FirestoreShutdownTransaction>>,
+    transaction_counter: AtomicU64,
+}
+
+struct FirestoreShutdownTransaction {
+    database_path: String,
+    transaction_id: FirestoreTransactionId,
+}
+
+impl Default for FirestoreDbShutdownState {
+    fn default() -> Self {
+        Self {
+            deadline: watch::channel(None).0,
+            drains: watch::channel(0).0,
+            transactions: Mutex::new(HashMap::new()),
+            transaction_counter: AtomicU64::new(0),
+        }
+    }
+}
+
+impl FirestoreDbShutdownState {
+    fn is_requested(&self) -> bool {
+        self.deadline.borrow().is_some()
+    }
+
+    /// Rejects the requests starting new operations after the shutdown is requested.
+    /// The requests finishing the operations in progress are still sent: reads, commits
+    /// and rollbacks of the open transactions, the writes of the writers holding drain guards
+    /// (see [`firestore_draining_writer_scope`]) and listener target removals.
+    pub(crate) fn check_request(&self, request: FirestoreApiRequest<'_>) -> FirestoreResult<()> {
+        if !self.is_requested() {
+            return Ok(());
+        }
+
+        let draining_writer = FIRESTORE_SHUTDOWN_DRAINING_WRITER.try_with(|_| ()).is_ok();
+        let drains_operation = match request {
+            FirestoreApiRequest::Commit(request) => {
+                !request.transaction.is_empty() || draining_writer
+            }
+            FirestoreApiRequest::BatchWrite(_) => draining_writer,
+            FirestoreApiRequest::Rollback(_) => true,
+            FirestoreApiRequest::Listen(request) => matches!(
+                request.target_change,
+                Some(listen_request::TargetChange::RemoveTarget(_))
+            ),
+            FirestoreApiRequest::GetDocument(request) => matches!(
+                request.consistency_selector,
+                Some(get_document_request::ConsistencySelector::Transaction(_))
+            ),
+            FirestoreApiRequest::BatchGetDocuments(request) => matches!(
+                request.consistency_selector,
+                Some(batch_get_documents_request::ConsistencySelector::Transaction(_))
+            ),
+            FirestoreApiRequest::RunQuery(request) => matches!(
+                request.consistency_selector,
+                Some(run_query_request::ConsistencySelector::Transaction(_))
+            ),
+            FirestoreApiRequest::RunAggregationQuery(request) => matches!(
+                request.consistency_selector,
+                Some(run_aggregation_query_request::ConsistencySelector::Transaction(_))
+            ),
+            _ => false,
+        };
+
+        if drains_operation {
+            Ok(())
+        } else {
+            Err(FirestoreError::SystemError(FirestoreSystemError::new(
+                FirestoreErrorPublicGenericDetails::new("ShuttingDown".into()),
+                "The Firestore client is shutting down and doesn't accept new operations"
+                    .to_string(),
+            )))
+        }
+    }
+}
+
+tokio::task_local! {
+    static FIRESTORE_SHUTDOWN_DRAINING_WRITER: ();
+}
+
+/// Runs a writer holding a drain guard, so the writes already added to it
+/// are still sent after the shutdown is requested.
+pub(crate) async fn firestore_draining_writer_scope<F>(future: F) -> F::Output
+where
+    F: Future,
+{
+    FIRESTORE_SHUTDOWN_DRAINING_WRITER.scope((), future).await
+}
+
+/// A signal of the graceful shutdown of a [`FirestoreDb`], see [`FirestoreDb::shutdown`].
+///
+/// Components built on top of the client can wait for the signal to stop their work,
+/// and hold [drain guards](FirestoreShutdownSignal::drain_guard) so the shutdown waits for them.
+#[derive(Clone)]
+pub struct FirestoreShutdownSignal {
+    state: Arc<FirestoreDbShutdownState>,
+}
+
+impl FirestoreShutdownSignal {
+    /// Checks if the shutdown has been requested.
+    pub fn is_requested(&self) -> bool {
+        self.state.is_requested()
+    }
+
+    /// Resolves when the shutdown is requested, returning the end of the grace period.
+    pub async fn requested(&self) -> Instant {
+        let mut deadline = self.state.deadline.subscribe();
+        loop {
+            if let Some(deadline) = *deadline.borrow_and_update() {
+                return deadline;
+            }
+            // The sender lives as long as the state
+            if deadline.changed().await.is_err() {
+                return Instant::now();
+            }
+        }
+    }
+
+    /// Registers an operation the shutdown waits for until the guard is dropped.
+    pub fn drain_guard(&self) -> FirestoreShutdownDrainGuard {
+        self.state.drains.send_modify(|drains| *drains += 1);
+        FirestoreShutdownDrainGuard {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Registers an open transaction, rolled back if it is still open at the end of the grace period.
+    pub(crate) fn transaction_guard(
+        &self,
+        database_path: &str,
+        transaction_id: &FirestoreTransactionId,
+    ) -> FirestoreShutdownTransactionGuard {
+        let registration = self
+            .state
+            .transaction_counter
+            .fetch_add(1, Ordering::Relaxed);
+        self.state
+            .transactions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(
+                registration,
+                FirestoreShutdownTransaction {
+                    database_path: database_path.to_string(),
+                    transaction_id: transaction_id.clone(),
+                },
+            );
+        FirestoreShutdownTransactionGuard {
+            registration,
+            _drain_guard: self.drain_guard(),
+        }
+    }
+}
+
+/// Resolves when the shutdown of the optional signal is requested, never without the signal.
+pub(crate) async fn firestore_shutdown_requested(
+    signal: &Option<FirestoreShutdownSignal>,
+) -> Instant {
+    match signal {
+        Some(signal) => signal.requested().await,
+        None => futures::future::pending().await,
+    }
+}
+
+/// An operation the shutdown waits for, until the guard is dropped.
+pub struct FirestoreShutdownDrainGuard {
+    state: Arc<FirestoreDbShutdownState>,
+}
+
+impl Drop for FirestoreShutdownDrainGuard {
+    fn drop(&mut self) {
+        self.state
+            .drains
+            .send_modify(|drains| *drains = drains.saturating_sub(1));
+    }
+}
+
+/// An open transaction, see [`FirestoreShutdownSignal::transaction_guard`].
+pub(crate) struct FirestoreShutdownTransactionGuard {
+    registration: u64,
+    // Dropped after the transaction is unregistered
+    _drain_guard: FirestoreShutdownDrainGuard,
+}
+
+impl Drop for FirestoreShutdownTransactionGuard {
+    fn drop(&mut self) {
+        self._drain_guard
+            .state
+            .transactions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&self.registration);
+    }
+}
+
+impl FirestoreDb {
+    /// Returns the signal of the graceful shutdown of the client, shared by all its clones.
+    pub fn shutdown_signal(&self) -> FirestoreShutdownSignal {
+        FirestoreShutdownSignal {
+            state: self.inner.shutdown.clone(),
+        }
+    }
+
+    /// Shuts down the client gracefully, e.g. when the process receives `SIGTERM`.
+    ///
+    /// The client, including all its clones, stops accepting new operations, and the operations
+    /// in progress are drained:
+    /// - listeners remove their targets and stop after the final events are handled;
+    /// - bulk writers send the writes already added, and streaming batch writers wait for
+    ///   the results of the writes already sent;
+    /// - open transactions can still read and commit, and the transactions not finished
+    ///   within the grace period are rolled back.
+    ///
+    /// Resolves when everything is drained, or returns an error at the end of the grace period
+    /// with the number of the operations still in progress.
+    pub async fn shutdown(&self, grace: Duration) -> FirestoreResult<()> {
+        let state = &self.inner.shutdown;
+        state.deadline.send_if_modified(|deadline| {
+            if deadline.is_none() {
+                *deadline = Some(Instant::now() + grace);
+                true
+            } else {
+                false
+            }
+        });
+        let deadline = self.shutdown_signal().requested().await;
+
+        info!(
+            drains = *state.drains.borrow(),
+            ?grace,
+            "Shutting down the Firestore client..."
+        );
+
+        let mut drains = state.drains.subscribe();
+        if tokio::time::timeout_at(deadline, drains.wait_for(|drains| *drains == 0))
+            .await
+            .is_ok()
+        {
+            info!("The Firestore client has been shut down.");
+            return Ok(());
+        }
+
+        let open_transactions: Vec<FirestoreShutdownTransaction> = state
+            .transactions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .drain()
+            .map(|(_, transaction)| transaction)
+            .collect();
+        let mut rolled_back = 0;
+        for transaction in open_transactions {
+            let request = self.create_api_request(RollbackRequest {
+                database: transaction.database_path,
+                transaction: transaction.transaction_id,
+            })?;
+            match self.client().get().rollback(request).await {
+                Ok(_) => rolled_back += 1,
+                Err(err) => warn!(%err, "Unable to roll back a transaction on shutdown."),
+            }
+        }
+
+        let pending = *state.drains.borrow();
+        warn!(
+            pending,
+            rolled_back, "The Firestore client hasn't been drained within the grace period."
+        );
+        Err(FirestoreError::SystemError(FirestoreSystemError::new(
+            FirestoreErrorPublicGenericDetails::new("ShutdownTimeout".into()),
+            format!(
+                "{pending} operations haven't been drained within the grace period, {rolled_back} open transactions have been rolled back"
+            ),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signal() -> FirestoreShutdownSignal {
+        FirestoreShutdownSignal {
+            state: Arc::new(FirestoreDbShutdownState::default()),
+        }
+    }
+
+    #[test]
+    fn rejects_new_operations_after_shutdown_requested() {
+        let signal = signal();
+        let mut query = RunQueryRequest::default();
+        let mut transaction_query = RunQueryRequest {
+            consistency_selector: Some(run_query_request::ConsistencySelector::Transaction(vec![
+                1,
+            ])),
+            ..Default::default()
+        };
+        let mut transaction_commit = CommitRequest {
+            transaction: vec![1],
+            ..Default::default()
+        };
+        let mut add_target = ListenRequest {
+            target_change: Some(listen_request::TargetChange::AddTarget(Target::default())),
+            ..Default::default()
+        };
+        let mut remove_target = ListenRequest {
+            target_change: Some(listen_request::TargetChange::RemoveTarget(1)),
+            ..Default::default()
+        };
+
+        assert!(signal
+            .state
+            .check_request(FirestoreApiRequest::RunQuery(&mut query))
+            .is_ok());
+
+        signal
+            .state
+            .deadline
+            .send_replace(Some(Instant::now() + Duration::from_secs(1)));
+        assert!(signal.is_requested());
+
+        assert!(signal
+            .state
+            .check_request(FirestoreApiRequest::RunQuery(&mut query))
+            .is_err());
+        assert!(signal
+            .state
+            .check_request(FirestoreApiRequest::Listen(&mut add_target))
+            .is_err());
+        assert!(signal
+            .state
+            .check_request(FirestoreApiRequest::RunQuery(&mut transaction_query))
+            .is_ok());
+        assert!(signal
+            .state
+            .check_request(FirestoreApiRequest::Commit(&mut transaction_commit))
+            .is_ok());
+        assert!(signal
+            .state
+            .check_request(FirestoreApiRequest::Listen(&mut remove_target))
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_plain_writes_outside_draining_writers() {
+        let signal = signal();
+        signal
+            .state
+            .deadline
+            .send_replace(Some(Instant::now() + Duration::from_secs(1)));
+        let state = &signal.state;
+
+        let mut commit = CommitRequest::default();
+        let mut batch_write = BatchWriteRequest::default();
+        assert!(state
+            .check_request(FirestoreApiRequest::Commit(&mut commit))
+            .is_err());
+        assert!(state
+            .check_request(FirestoreApiRequest::BatchWrite(&mut batch_write))
+            .is_err());
+
+        firestore_draining_writer_scope(async {
+            assert!(state
+                .check_request(FirestoreApiRequest::Commit(&mut commit))
+                .is_ok());
+            assert!(state
+                .check_request(FirestoreApiRequest::BatchWrite(&mut batch_write))
+                .is_ok());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn guards_track_draining_operations() {
+        let signal = signal();
+        let transaction = signal.transaction_guard("db", &vec![1, 2]);
+        let guard = signal.drain_guard();
+        assert_eq!(*signal.state.drains.borrow(), 2);
+        assert_eq!(signal.state.transactions.lock().unwrap().len(), 1);
+
+        drop(transaction);
+        assert_eq!(*signal.state.drains.borrow(), 1);
+        assert!(signal.state.transactions.lock().unwrap().is_empty());
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+        let waiting_signal = signal.clone();
+        let requested = tokio::spawn(async move { waiting_signal.requested().await });
+        signal.state.deadline.send_replace(Some(deadline));
+        assert_eq!(requested.await.unwrap(), deadline);
+
+        drop(guard);
+        assert_eq!(*signal.state.drains.borrow(), 0);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::db::{firestore_timeout_override, firestore_timeout_scope};
+use crate::{
+    FirestoreConsistencySelector, FirestoreRetryPolicy, FirestoreSerializerOptions,
+    FirestoreValueTransformers,
+};
+use futures::Future;
+use std::time::Duration;
+
+/// The task-local settings of the current operation: the retry policy, the consistency selector,
+/// the timeout, the serializer options and the value transformers.
+///
+/// Tasks don't inherit the task-local settings of the task spawning them,
+/// so they are captured before spawning a task and applied inside it.
+#[derive(Clone, Default)]
+pub(crate) struct FirestoreTaskScopes {
+    retry_policy: Option<FirestoreRetryPolicy>,
+    consistency_selector: Option<FirestoreConsistencySelector>,
+    timeout: Option<Duration>,
+    serializer_options: Option<FirestoreSerializerOptions>,
+    value_transformers: Option<FirestoreValueTransformers>,
+}
+
+impl FirestoreTaskScopes {
+    /// Captures the settings of the current task.
+    pub(crate) fn capture() -> Self {
+        Self {
+            retry_policy: FirestoreRetryPolicy::current_override(),
+            consistency_selector: FirestoreConsistencySelector::current_override(),
+            timeout: firestore_timeout_override(),
+            serializer_options: FirestoreSerializerOptions::current_override(),
+            value_transformers: FirestoreValueTransformers::current_override(),
+        }
+    }
+
+    /// Runs the future with the captured settings.
+    pub(crate) async fn scope<F>(self, future: F) -> F::Output
+    where
+        F: Future,
+    {
+        let future = FirestoreSerializerOptions::opt_scope(self.serializer_options, future);
+        let future = firestore_timeout_scope(self.timeout, future);
+        let future = FirestoreConsistencySelector::scope(self.consistency_selector, future);
+        let future = FirestoreRetryPolicy::scope(self.retry_policy, future);
+        match self.value_transformers {
+            Some(transformers) => FirestoreValueTransformers::scope(transformers, future).await,
+            None => future.await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn spawned_tasks_keep_captured_scopes() {
+        let policy = FirestoreRetryPolicy::new().with_max_attempts(7);
+        let (spawned_policy, spawned_timeout) = FirestoreRetryPolicy::scope(
+            Some(policy.clone()),
+            firestore_timeout_scope(Some(Duration::from_secs(3)), async {
+                let scopes = FirestoreTaskScopes::capture();
+                tokio::spawn(scopes.scope(async {
+                    (
+                        FirestoreRetryPolicy::current_override(),
+                        firestore_timeout_override(),
+                    )
+                }))
+                .await
+                .unwrap()
+            }),
+        )
+        .await;
+
+        assert_eq!(spawned_policy, Some(policy));
+        assert_eq!(spawned_timeout, Some(Duration::from_secs(3)));
+    }
+}
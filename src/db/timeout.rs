@@ -0,0 +1,79 @@
+use crate::*;
+use futures::Future;
+use std::time::Duration;
+
+tokio::task_local! {
+    static FIRESTORE_TIMEOUT_OVERRIDE: Duration;
+}
+
+/// Runs the future with the timeout overriding the configured
+/// [`FirestoreDbOptions::timeout`] for the requests it sends.
+pub(crate) async fn firestore_timeout_scope<F>(timeout: Option<Duration>, future: F) -> F::Output
+where
+    F: Future,
+{
+    match timeout {
+        Some(timeout) => FIRESTORE_TIMEOUT_OVERRIDE.scope(timeout, future).await,
+        None => future.await,
+    }
+}
+
+/// Returns the timeout overridden for the current fluent operation, if any.
+pub(crate) fn firestore_timeout_override() -> Option<Duration> {
+    FIRESTORE_TIMEOUT_OVERRIDE.try_with(|timeout| *timeout).ok()
+}
+
+impl FirestoreDb {
+    /// Returns the timeout for the current operation: the timeout overridden for
+    /// a fluent operation or the configured one.
+    pub(crate) fn current_timeout(&self) -> Option<Duration> {
+        firestore_timeout_override().or(self.get_options().timeout)
+    }
+
+    /// Creates a gRPC request with the deadline of the current operation.
+    ///
+    /// The deadline is sent to Firestore as the `grpc-timeout` header, so the server cancels
+    /// the request (or terminates the response stream) with `DEADLINE_EXCEEDED` when it expires.
+    pub(crate) fn create_request<T>(&self, message: T) -> gcloud_sdk::tonic::Request<T> {
+        firestore_request_with_timeout(message, self.current_timeout())
+    }
+}
+
+/// Creates a gRPC request with the specified deadline.
+pub(crate) fn firestore_request_with_timeout<T>(
+    message: T,
+    timeout: Option<Duration>,
+) -> gcloud_sdk::tonic::Request<T> {
+    let mut request = gcloud_sdk::tonic::Request::new(message);
+    if let Some(timeout) = timeout {
+        request.set_timeout(timeout);
+    }
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn overrides_configured_timeout() {
+        let options = FirestoreDbOptions::new("test".to_string());
+        assert_eq!(options.timeout, None);
+
+        let timeout = firestore_timeout_scope(Some(Duration::from_secs(5)), async {
+            FIRESTORE_TIMEOUT_OVERRIDE.try_with(|timeout| *timeout).ok()
+        })
+        .await;
+        assert_eq!(timeout, Some(Duration::from_secs(5)));
+        assert!(FIRESTORE_TIMEOUT_OVERRIDE.try_with(|_| ()).is_err());
+
+        let request = firestore_request_with_timeout((), Some(Duration::from_secs(5)));
+        assert_eq!(
+            request
+                .metadata()
+                .get("grpc-timeout")
+                .and_then(|value| value.to_str().ok()),
+            Some("5000000u")
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use crate::FirestoreResult;
+use async_trait::async_trait;
+use gcloud_sdk::{GoogleAuthTokenGenerator, Token, TokenSourceType, GCP_DEFAULT_SCOPES};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A source of OAuth2 access tokens used to authenticate the requests to Firestore,
+/// instead of the credentials detected from the environment.
+///
+/// Tokens are cached by the client and requested again shortly before they expire,
+/// so implementations don't need to cache them.
+/// See [`FirestoreDb::with_options_custom_token_source`](crate::FirestoreDb::with_options_custom_token_source).
+///
+/// # Examples
+///
+/// ```rust
+/// use firestore::*;
+/// use async_trait::async_trait;
+///
+/// struct VaultTokenSource;
+///
+/// #[async_trait]
+/// impl FirestoreTokenSource for VaultTokenSource {
+///     async fn token(&self) -> FirestoreResult<gcloud_sdk::Token> {
+///         Ok(gcloud_sdk::Token::new(
+///             "Bearer".to_string(),
+///             "token-from-vault".into(),
+///             chrono::Utc::now() + chrono::Duration::minutes(30),
+///         ))
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait FirestoreTokenSource {
+    /// Returns a new access token.
+    async fn token(&self) -> FirestoreResult<Token>;
+}
+
+/// A thread-safe, shareable token source.
+pub type FirestoreSharedTokenSource = Arc<dyn FirestoreTokenSource + Send + Sync + 'static>;
+
+/// A token source calling an async function, e.g. to fetch tokens from a secrets manager.
+pub struct FirestoreTokenFunctionSource<FN> {
+    token_fn: FN,
+}
+
+impl<FN, F> FirestoreTokenFunctionSource<FN>
+where
+    FN: Fn() -> F + Send + Sync,
+    F: Future<Output = FirestoreResult<Token>> + Send,
+{
+    pub fn new(token_fn: FN) -> Self {
+        Self { token_fn }
+    }
+}
+
+#[async_trait]
+impl<FN, F> FirestoreTokenSource for FirestoreTokenFunctionSource<FN>
+where
+    FN: Fn() -> F + Send + Sync,
+    F: Future<Output = FirestoreResult<Token>> + Send,
+{
+    async fn token(&self) -> FirestoreResult<Token> {
+        (self.token_fn)().await
+    }
+}
+
+/// A token source for explicitly specified Google credentials:
+/// service account keys, workload identity federation (`external_account`) configurations,
+/// impersonated service account configurations, or the metadata server.
+pub struct FirestoreCredentialsTokenSource {
+    generator: GoogleAuthTokenGenerator,
+}
+
+impl FirestoreCredentialsTokenSource {
+    /// Creates a token source for the specified credentials and scopes.
+    pub async fn new(
+        token_source_type: TokenSourceType,
+        token_scopes: Vec<String>,
+    ) -> FirestoreResult<Self> {
+        Ok(Self {
+            generator: GoogleAuthTokenGenerator::new(token_source_type, token_scopes).await?,
+        })
+    }
+
+    /// Creates a token source from the content of a JSON credentials file,
+    /// e.g. a service account key stored in a secrets manager.
+    pub async fn from_json<S>(json: S) -> FirestoreResult<Self>
+    where
+        S: Into<String>,
+    {
+        Self::new(
+            TokenSourceType::Json(json.into()),
+            GCP_DEFAULT_SCOPES.clone(),
+        )
+        .await
+    }
+
+    /// Creates a token source from a JSON credentials file.
+    pub async fn from_file(path: PathBuf) -> FirestoreResult<Self> {
+        Self::new(TokenSourceType::File(path), GCP_DEFAULT_SCOPES.clone()).await
+    }
+}
+
+#[async_trait]
+impl FirestoreTokenSource for FirestoreCredentialsTokenSource {
+    async fn token(&self) -> FirestoreResult<Token> {
+        Ok(self.generator.create_token().await?)
+    }
+}
+
+/// Adapts a [`FirestoreTokenSource`] to the token sources of the gRPC clients.
+pub(crate) struct FirestoreTokenSourceAdapter {
+    token_source: FirestoreSharedTokenSource,
+}
+
+impl FirestoreTokenSourceAdapter {
+    pub(crate) fn token_source_type(token_source: FirestoreSharedTokenSource) -> TokenSourceType {
+        TokenSourceType::ExternalSource(Box::new(Self { token_source }))
+    }
+}
+
+#[async_trait]
+impl gcloud_sdk::Source for FirestoreTokenSourceAdapter {
+    async fn token(&self) -> gcloud_sdk::error::Result<Token> {
+        self.token_source.token().await.map_err(|err| {
+            gcloud_sdk::error::ErrorKind::ExternalCredsSourceError(err.to_string()).into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::*;
+
+    #[tokio::test]
+    async fn token_source_adapter() {
+        let token_source: FirestoreSharedTokenSource =
+            Arc::new(FirestoreTokenFunctionSource::new(|| async {
+                Ok(Token::new(
+                    "Bearer".to_string(),
+                    "test-token".into(),
+                    chrono::Utc::now() + chrono::Duration::minutes(5),
+                ))
+            }));
+        let adapter = FirestoreTokenSourceAdapter { token_source };
+        assert_eq!(
+            gcloud_sdk::Source::token(&adapter)
+                .await
+                .unwrap()
+                .header_value(),
+            "Bearer test-token"
+        );
+
+        let failing: FirestoreSharedTokenSource =
+            Arc::new(FirestoreTokenFunctionSource::new(|| async {
+                Err(FirestoreError::SystemError(FirestoreSystemError::new(
+                    FirestoreErrorPublicGenericDetails::new("TokenError".into()),
+                    "No token for the tenant".to_string(),
+                )))
+            }));
+        let adapter = FirestoreTokenSourceAdapter {
+            token_source: failing,
+        };
+        assert!(gcloud_sdk::Source::token(&adapter)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("No token for the tenant"));
+    }
+}
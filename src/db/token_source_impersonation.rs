@@ -0,0 +1,163 @@
+use crate::db::token_source::FirestoreTokenSourceAdapter;
+use crate::errors::*;
+use crate::timestamp_utils::from_timestamp;
+use crate::{FirestoreResult, FirestoreSharedTokenSource, FirestoreTokenSource};
+use async_trait::async_trait;
+use gcloud_sdk::google::iam::credentials::v1::iam_credentials_client::IamCredentialsClient;
+use gcloud_sdk::google::iam::credentials::v1::GenerateAccessTokenRequest;
+use gcloud_sdk::{GoogleApi, GoogleAuthMiddleware, Token, GCP_DEFAULT_SCOPES};
+use rsb_derive::*;
+use std::time::Duration;
+
+const GOOGLE_IAM_CREDENTIALS_API_URL: &str = "https://iamcredentials.googleapis.com";
+
+/// Options of the tokens issued by a [`FirestoreImpersonatedTokenSource`].
+#[derive(Debug, Eq, PartialEq, Clone, Builder)]
+pub struct FirestoreImpersonationOptions {
+    /// The email of the service account to impersonate.
+    pub target_service_account: String,
+    /// The chain of service accounts to delegate the impersonation through, if any.
+    #[default = "Vec::new()"]
+    pub delegates: Vec<String>,
+    /// The OAuth2 scopes of the issued tokens.
+    #[default = "GCP_DEFAULT_SCOPES.clone()"]
+    pub scopes: Vec<String>,
+    /// The lifetime of the issued tokens, one hour by default.
+    pub lifetime: Option<Duration>,
+}
+
+/// A token source impersonating a service account with the IAM Credentials API,
+/// e.g. to access the databases of different tenants with their own service accounts.
+///
+/// The source credentials need the `roles/iam.serviceAccountTokenCreator` role
+/// on the impersonated service account.
+///
+/// This is only available if the `impersonation` feature is enabled.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use firestore::*;
+/// use std::sync::Arc;
+///
+/// # async fn run() -> FirestoreResult<()> {
+/// let source: FirestoreSharedTokenSource = Arc::new(
+///     FirestoreCredentialsTokenSource::new(
+///         gcloud_sdk::TokenSourceType::Default,
+///         gcloud_sdk::GCP_DEFAULT_SCOPES.clone(),
+///     )
+///     .await?,
+/// );
+///
+/// let tenant_token_source = FirestoreImpersonatedTokenSource::new(
+///     source,
+///     FirestoreImpersonationOptions::new("tenant-a@my-project.iam.gserviceaccount.com".into()),
+/// )
+/// .await?;
+///
+/// let db = FirestoreDb::with_options_custom_token_source(
+///     FirestoreDbOptions::new("tenant-a-project".into()),
+///     tenant_token_source,
+/// )
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct FirestoreImpersonatedTokenSource {
+    client: GoogleApi<IamCredentialsClient<GoogleAuthMiddleware>>,
+    options: FirestoreImpersonationOptions,
+}
+
+impl FirestoreImpersonatedTokenSource {
+    /// Creates a token source impersonating the service account with the source credentials.
+    pub async fn new(
+        source: FirestoreSharedTokenSource,
+        options: FirestoreImpersonationOptions,
+    ) -> FirestoreResult<Self> {
+        let client = GoogleApi::from_function_with_token_source(
+            IamCredentialsClient::new,
+            GOOGLE_IAM_CREDENTIALS_API_URL,
+            None,
+            GCP_DEFAULT_SCOPES.clone(),
+            FirestoreTokenSourceAdapter::token_source_type(source),
+        )
+        .await?;
+        Ok(Self { client, options })
+    }
+
+    pub fn options(&self) -> &FirestoreImpersonationOptions {
+        &self.options
+    }
+}
+
+#[async_trait]
+impl FirestoreTokenSource for FirestoreImpersonatedTokenSource {
+    async fn token(&self) -> FirestoreResult<Token> {
+        let response =
+            self.client
+                .get()
+                .generate_access_token(GenerateAccessTokenRequest {
+                    name: service_account_resource_name(&self.options.target_service_account),
+                    delegates: self
+                        .options
+                        .delegates
+                        .iter()
+                        .map(|delegate| service_account_resource_name(delegate))
+                        .collect(),
+                    scope: self.options.scopes.clone(),
+                    lifetime: self.options.lifetime.map(|lifetime| {
+                        gcloud_sdk::prost_types::Duration {
+                            seconds: lifetime.as_secs() as i64,
+                            nanos: 0,
+                        }
+                    }),
+                })
+                .await?
+                .into_inner();
+
+        let expiry = response
+            .expire_time
+            .map(from_timestamp)
+            .transpose()?
+            .ok_or_else(|| {
+                FirestoreError::SystemError(FirestoreSystemError::new(
+                    FirestoreErrorPublicGenericDetails::new("TokenError".into()),
+                    format!(
+                        "No expiration time of the token impersonating {}",
+                        self.options.target_service_account
+                    ),
+                ))
+            })?;
+
+        Ok(Token::new(
+            "Bearer".to_string(),
+            response.access_token.into(),
+            expiry,
+        ))
+    }
+}
+
+fn service_account_resource_name(service_account: &str) -> String {
+    if service_account.starts_with("projects/") {
+        service_account.to_string()
+    } else {
+        format!("projects/-/serviceAccounts/{service_account}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn service_account_resource_names() {
+        assert_eq!(
+            service_account_resource_name("tenant@my-project.iam.gserviceaccount.com"),
+            "projects/-/serviceAccounts/tenant@my-project.iam.gserviceaccount.com"
+        );
+        assert_eq!(
+            service_account_resource_name("projects/-/serviceAccounts/tenant@p.iam"),
+            "projects/-/serviceAccounts/tenant@p.iam"
+        );
+    }
+}
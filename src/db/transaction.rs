@@ -1,14 +1,17 @@
 use crate::errors::*;
 use crate::timestamp_utils::from_timestamp;
 use crate::{
-    FirestoreConsistencySelector, FirestoreDb, FirestoreError, FirestoreResult,
-    FirestoreTransactionId, FirestoreTransactionMode, FirestoreTransactionOptions,
-    FirestoreTransactionResponse, FirestoreWriteResult,
+    FirestoreApiResponse, FirestoreConsistencySelector, FirestoreDb, FirestoreDbSessionParams,
+    FirestoreError, FirestoreGetByIdSupport, FirestoreOperationKind, FirestoreResult,
+    FirestoreShutdownTransactionGuard, FirestoreTransactionId, FirestoreTransactionMode,
+    FirestoreTransactionOptions, FirestoreTransactionResponse, FirestoreWriteResult,
 };
 use backoff::future::retry;
 use backoff::ExponentialBackoffBuilder;
 use futures::future::BoxFuture;
 use gcloud_sdk::google::firestore::v1::{BeginTransactionRequest, CommitRequest, RollbackRequest};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
 use tracing::*;
 
@@ -18,6 +21,8 @@ pub struct FirestoreTransaction<'a> {
     pub transaction_span: Span,
     writes: Vec<gcloud_sdk::google::firestore::v1::Write>,
     finished: bool,
+    // Keeps the shutdown waiting for the transaction until it is finished
+    shutdown_guard: Option<FirestoreShutdownTransactionGuard>,
 }
 
 impl<'a> FirestoreTransaction<'a> {
@@ -32,17 +37,25 @@ impl<'a> FirestoreTransaction<'a> {
             "/firestore/commit_time" = field::Empty
         );
 
-        let request = gcloud_sdk::tonic::Request::new(BeginTransactionRequest {
+        let request = db.create_api_request(BeginTransactionRequest {
             database: db.get_database_path().clone(),
             options: Some(options.clone().try_into()?),
-        });
+        })?;
 
+        let metrics = db.record_operation(
+            FirestoreOperationKind::BeginTransaction,
+            None,
+            &transaction_span,
+        );
         let response = db
             .client()
             .get()
             .begin_transaction(request)
-            .await?
-            .into_inner();
+            .await
+            .map_err(FirestoreError::from);
+        metrics.record_result(&response, 0);
+        let response = response?.into_inner();
+        db.notify_response_middlewares(FirestoreApiResponse::BeginTransaction(&response));
 
         let mut hex_trans_id = hex::encode(&response.transaction);
         hex_trans_id.truncate(16);
@@ -53,12 +66,17 @@ impl<'a> FirestoreTransaction<'a> {
             debug!(mode = ?options.mode, "Created a new transaction.");
         });
 
+        let shutdown_guard = db
+            .shutdown_signal()
+            .transaction_guard(db.get_database_path(), &response.transaction);
+
         Ok(Self {
             db,
             transaction_id: response.transaction,
             transaction_span,
             writes: Vec::new(),
             finished: false,
+            shutdown_guard: Some(shutdown_guard),
         })
     }
 
@@ -85,13 +103,27 @@ impl<'a> FirestoreTransaction<'a> {
             });
         }
 
-        let request = gcloud_sdk::tonic::Request::new(CommitRequest {
+        let writes_count = self.writes.len();
+        let request = self.db.create_api_request(CommitRequest {
             database: self.db.get_database_path().clone(),
             writes: self.writes.drain(..).collect(),
             transaction: self.transaction_id.clone(),
-        });
+        })?;
 
-        let response = self.db.client().get().commit(request).await?.into_inner();
+        let metrics =
+            self.db
+                .record_operation(FirestoreOperationKind::Commit, None, &self.transaction_span);
+        let response = self
+            .db
+            .client()
+            .get()
+            .commit(request)
+            .await
+            .map_err(FirestoreError::from);
+        metrics.record_result(&response, writes_count);
+        let response = response?.into_inner();
+        self.db
+            .notify_response_middlewares(FirestoreApiResponse::Commit(&response));
 
         let result = FirestoreTransactionResponse::new(
             response
@@ -116,12 +148,27 @@ impl<'a> FirestoreTransaction<'a> {
 
     pub async fn rollback(mut self) -> FirestoreResult<()> {
         self.finished = true;
-        let request = gcloud_sdk::tonic::Request::new(RollbackRequest {
+        let request = self.db.create_api_request(RollbackRequest {
             database: self.db.get_database_path().clone(),
             transaction: self.transaction_id.clone(),
-        });
+        })?;
 
-        self.db.client().get().rollback(request).await?;
+        let metrics = self.db.record_operation(
+            FirestoreOperationKind::Rollback,
+            None,
+            &self.transaction_span,
+        );
+        let response = self
+            .db
+            .client()
+            .get()
+            .rollback(request)
+            .await
+            .map_err(FirestoreError::from);
+        metrics.record_result(&response, 0);
+        response?;
+        self.db
+            .notify_response_middlewares(FirestoreApiResponse::Rollback);
 
         self.transaction_span.in_scope(|| {
             debug!("Transaction has been rolled back.");
@@ -132,6 +179,7 @@ impl<'a> FirestoreTransaction<'a> {
 
     pub fn finish(&mut self) -> FirestoreResult<()> {
         self.finished = true;
+        self.shutdown_guard = None;
         self.transaction_span.in_scope(|| {
             debug!("Transaction has been finished locally without rolling back to be able to retry it again.");
         });
@@ -190,11 +238,12 @@ impl FirestoreDb {
     {
         // Perform our initial attempt. If this fails and the backend tells us we can retry,
         // we'll try again with exponential backoff using the first attempt's transaction ID.
-        let (transaction_id, transaction_span, initial_backoff_duration) = {
+        let (transaction_id, transaction_span, initial_backoff_duration, initial_err) = {
             let mut transaction = self.begin_transaction_with_options(options.clone()).await?;
             let transaction_id = transaction.transaction_id().clone();
             let transaction_span = transaction.transaction_span.clone();
             let mut initial_backoff_duration: Option<Duration> = None;
+            let initial_err: FirestoreError;
 
             let cdb = self.clone_with_consistency_selector(
                 FirestoreConsistencySelector::Transaction(transaction_id.clone()),
@@ -212,7 +261,8 @@ impl FirestoreDb {
                                         "Transient error occurred while committing transaction.",
                                     )
                                 });
-                                // Ignore; we'll try again below
+                                // We'll try again below if attempts are left
+                                initial_err = err;
                             }
                             other => return Err(other),
                         },
@@ -225,6 +275,9 @@ impl FirestoreDb {
                         });
                         initial_backoff_duration = retry_after;
                         transaction.finish().ok();
+                        initial_err = FirestoreError::ErrorInTransaction(
+                            FirestoreErrorInTransaction::new(transaction_id.clone(), Box::new(err)),
+                        );
                     }
                     BackoffError::Permanent(err) => {
                         transaction.rollback().await.ok();
@@ -235,9 +288,19 @@ impl FirestoreDb {
                 },
             }
 
-            (transaction_id, transaction_span, initial_backoff_duration)
+            (
+                transaction_id,
+                transaction_span,
+                initial_backoff_duration,
+                initial_err,
+            )
         };
 
+        if matches!(options.max_attempts, Some(max_attempts) if max_attempts <= 1) {
+            return Err(initial_err);
+        }
+        let attempts = AtomicUsize::new(1);
+
         // We failed the first time. Now we must change the transaction mode to signal that we're retrying with the original transaction ID.
         let backoff = ExponentialBackoffBuilder::new()
             .with_max_elapsed_time(
@@ -247,69 +310,100 @@ impl FirestoreDb {
                     .map(|v| v.to_std())
                     .transpose()?,
             )
-            .with_initial_interval(initial_backoff_duration.unwrap_or(Duration::from_millis(
-                backoff::default::INITIAL_INTERVAL_MILLIS,
-            )))
+            .with_initial_interval(
+                initial_backoff_duration
+                    .or(options
+                        .retry_initial_interval
+                        .map(|v| v.to_std())
+                        .transpose()?)
+                    .unwrap_or(Duration::from_millis(
+                        backoff::default::INITIAL_INTERVAL_MILLIS,
+                    )),
+            )
+            .with_max_interval(
+                options
+                    .retry_max_interval
+                    .map(|v| v.to_std())
+                    .transpose()?
+                    .unwrap_or(Duration::from_millis(backoff::default::MAX_INTERVAL_MILLIS)),
+            )
             .build();
 
         let retry_result = retry(backoff, || async {
-            let options = FirestoreTransactionOptions {
-                mode: FirestoreTransactionMode::ReadWriteRetry(transaction_id.clone()),
-                ..options
-            };
-            let mut transaction = self
-                .begin_transaction_with_options(options)
-                .await
-                .map_err(firestore_err_to_backoff)?;
-            let transaction_id = transaction.transaction_id().clone();
-
-            let cdb = self.clone_with_consistency_selector(
-                FirestoreConsistencySelector::Transaction(transaction_id.clone()),
-            );
-
-            let ret_val = func(cdb, &mut transaction).await.map_err(|backoff_err| {
-                transaction.finish().ok();
-                match backoff_err {
-                    BackoffError::Transient { err, retry_after } => {
-                        transaction_span.in_scope(|| {
-                            warn!(%err, delay = ?retry_after, "Transient error occurred in transaction function. Retrying after the specified delay.");
-                        });
-
-                        let firestore_err = FirestoreError::ErrorInTransaction(
-                            FirestoreErrorInTransaction::new(
-                                transaction_id.clone(),
-                                Box::new(err)
-                            ),
-                        );
-
-                        if let Some(retry_after_duration) = retry_after {
-                            backoff::Error::retry_after(
-                                firestore_err,
-                                retry_after_duration
-                            )
-                        } else {
-                            backoff::Error::transient(firestore_err)
-                        }
-                    }
-                    BackoffError::Permanent(err) => {
-                        backoff::Error::permanent(
-                            FirestoreError::ErrorInTransaction(
+            let attempt = attempts.fetch_add(1, Ordering::Relaxed) + 1;
+
+            let attempt_result: Result<T, backoff::Error<FirestoreError>> = async {
+                let options = FirestoreTransactionOptions {
+                    mode: FirestoreTransactionMode::ReadWriteRetry(transaction_id.clone()),
+                    ..options
+                };
+                let mut transaction = self
+                    .begin_transaction_with_options(options)
+                    .await
+                    .map_err(firestore_err_to_backoff)?;
+                let transaction_id = transaction.transaction_id().clone();
+
+                let cdb = self.clone_with_consistency_selector(
+                    FirestoreConsistencySelector::Transaction(transaction_id.clone()),
+                );
+
+                let ret_val = func(cdb, &mut transaction).await.map_err(|backoff_err| {
+                    transaction.finish().ok();
+                    match backoff_err {
+                        BackoffError::Transient { err, retry_after } => {
+                            transaction_span.in_scope(|| {
+                                warn!(%err, delay = ?retry_after, "Transient error occurred in transaction function. Retrying after the specified delay.");
+                            });
+
+                            let firestore_err = FirestoreError::ErrorInTransaction(
                                 FirestoreErrorInTransaction::new(
                                     transaction_id.clone(),
                                     Box::new(err)
                                 ),
+                            );
+
+                            if let Some(retry_after_duration) = retry_after {
+                                backoff::Error::retry_after(
+                                    firestore_err,
+                                    retry_after_duration
+                                )
+                            } else {
+                                backoff::Error::transient(firestore_err)
+                            }
+                        }
+                        BackoffError::Permanent(err) => {
+                            backoff::Error::permanent(
+                                FirestoreError::ErrorInTransaction(
+                                    FirestoreErrorInTransaction::new(
+                                        transaction_id.clone(),
+                                        Box::new(err)
+                                    ),
+                                )
                             )
-                        )
+                        }
                     }
-                }
-            })?;
+                })?;
 
-            transaction
-                .commit()
-                .await
-                .map_err(firestore_err_to_backoff)?;
+                transaction
+                    .commit()
+                    .await
+                    .map_err(firestore_err_to_backoff)?;
 
-            Ok(ret_val)
+                Ok(ret_val)
+            }
+            .await;
+
+            attempt_result.map_err(|err| match err {
+                backoff::Error::Transient { err, .. }
+                    if matches!(options.max_attempts, Some(max_attempts) if attempt >= max_attempts) =>
+                {
+                    transaction_span.in_scope(|| {
+                        warn!(%err, attempt, "Transaction failed after the maximum number of attempts.");
+                    });
+                    backoff::Error::permanent(err)
+                }
+                other => other,
+            })
         })
         .await;
 
@@ -332,4 +426,45 @@ impl FirestoreDb {
 
         retry_result
     }
+
+    /// Reads the document and applies the writes added by `write` in a single transaction,
+    /// returning the state of the document before the writes, or `None` if it didn't exist.
+    ///
+    /// The transaction is run with [`run_transaction`](FirestoreDb::run_transaction),
+    /// so it is retried when a concurrent write to the document aborts it.
+    pub(crate) async fn write_returning_previous<T, FN>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_id: &str,
+        write: FN,
+    ) -> FirestoreResult<Option<T>>
+    where
+        for<'de> T: Deserialize<'de> + Send,
+        FN: Fn(&mut FirestoreTransaction<'_>) -> FirestoreResult<()>,
+    {
+        self.run_transaction(|_, transaction| {
+            // The previous state is read from Firestore rather than a cache to lock the document
+            let transaction_db = self.clone_with_session_params(
+                FirestoreDbSessionParams::new().with_consistency_selector(
+                    FirestoreConsistencySelector::Transaction(transaction.transaction_id().clone()),
+                ),
+            );
+            // Writes are only sent on commit, so they can be added before the read
+            let written = write(transaction);
+            let (parent, collection_id, document_id) = (
+                parent.to_string(),
+                collection_id.to_string(),
+                document_id.to_string(),
+            );
+            Box::pin(async move {
+                written.map_err(BackoffError::permanent)?;
+                transaction_db
+                    .get_obj_at_if_exists::<T, _>(&parent, &collection_id, document_id, None)
+                    .await
+                    .map_err(firestore_err_to_backoff)
+            })
+        })
+        .await
+    }
 }
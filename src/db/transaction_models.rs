@@ -18,6 +18,15 @@ pub struct FirestoreTransactionOptions {
     /// If set, the transaction will attempt to complete within this duration.
     /// If `None`, default retry policies of the underlying gRPC client or Firestore service apply.
     pub max_elapsed_time: Option<Duration>,
+    /// An optional maximum number of attempts to run the transaction function
+    /// in [`FirestoreDb::run_transaction`](crate::FirestoreDb::run_transaction), including the first one.
+    /// If `None`, the number of retries is limited only by `max_elapsed_time`.
+    pub max_attempts: Option<usize>,
+    /// An optional delay before the first retry. Following delays increase exponentially.
+    /// If `None`, the default initial interval of the exponential backoff is used.
+    pub retry_initial_interval: Option<Duration>,
+    /// An optional upper bound for the delay between retries.
+    pub retry_max_interval: Option<Duration>,
 }
 
 impl Default for FirestoreTransactionOptions {
@@ -25,6 +34,9 @@ impl Default for FirestoreTransactionOptions {
         Self {
             mode: FirestoreTransactionMode::ReadWrite,
             max_elapsed_time: None,
+            max_attempts: None,
+            retry_initial_interval: None,
+            retry_max_interval: None,
         }
     }
 }
@@ -180,6 +180,49 @@ impl<'a> FirestoreTransaction<'a> {
         })
     }
 
+    /// Adds a write creating the document, failing the transaction if it already exists.
+    pub fn create_object<T, S>(
+        &mut self,
+        collection_id: &str,
+        document_id: S,
+        obj: &T,
+    ) -> FirestoreResult<&mut Self>
+    where
+        T: Serialize + Sync + Send,
+        S: AsRef<str>,
+    {
+        self.create_object_at(
+            self.db.get_documents_path(),
+            collection_id,
+            document_id,
+            obj,
+        )
+    }
+
+    /// Adds a write creating the document under the specified parent,
+    /// failing the transaction if it already exists.
+    pub fn create_object_at<T, S>(
+        &mut self,
+        parent: &str,
+        collection_id: &str,
+        document_id: S,
+        obj: &T,
+    ) -> FirestoreResult<&mut Self>
+    where
+        T: Serialize + Sync + Send,
+        S: AsRef<str>,
+    {
+        self.update_object_at(
+            parent,
+            collection_id,
+            document_id,
+            obj,
+            None,
+            Some(FirestoreWritePrecondition::Exists(false)),
+            vec![],
+        )
+    }
+
     pub fn delete_by_id<S>(
         &mut self,
         collection_id: &str,
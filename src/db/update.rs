@@ -1,5 +1,10 @@
 use crate::db::safe_document_path;
-use crate::{FirestoreDb, FirestoreResult, FirestoreWritePrecondition};
+use crate::db::transaction_ops::TransformObjectOperation;
+use crate::errors::*;
+use crate::{
+    FirestoreApiResponse, FirestoreDb, FirestoreFieldTransform, FirestoreOperationKind,
+    FirestoreResult, FirestoreWritePrecondition, FirestoreWriteResult,
+};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use gcloud_sdk::google::firestore::v1::*;
@@ -45,6 +50,27 @@ pub trait FirestoreUpdateSupport {
         return_only_fields: Option<Vec<String>>,
         precondition: Option<FirestoreWritePrecondition>,
     ) -> FirestoreResult<Document>;
+
+    async fn transform_doc<S>(
+        &self,
+        collection_id: &str,
+        document_id: S,
+        precondition: Option<FirestoreWritePrecondition>,
+        transforms: Vec<FirestoreFieldTransform>,
+    ) -> FirestoreResult<FirestoreWriteResult>
+    where
+        S: AsRef<str> + Send;
+
+    async fn transform_doc_at<S>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_id: S,
+        precondition: Option<FirestoreWritePrecondition>,
+        transforms: Vec<FirestoreFieldTransform>,
+    ) -> FirestoreResult<FirestoreWriteResult>
+    where
+        S: AsRef<str> + Send;
 }
 
 #[async_trait]
@@ -124,9 +150,14 @@ impl FirestoreUpdateSupport for FirestoreDb {
             "/firestore/collection_name" = collection_id,
             "/firestore/document_name" = document_id,
             "/firestore/response_time" = field::Empty,
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty,
         );
 
-        let update_document_request = gcloud_sdk::tonic::Request::new(UpdateDocumentRequest {
+        let has_precondition = precondition.is_some();
+        #[cfg(feature = "caching")]
+        let return_only_fields_empty = return_only_fields.is_none();
+        let update_document_request = UpdateDocumentRequest {
             update_mask: update_only.map({
                 |vf| DocumentMask {
                     field_paths: vf.iter().map(|f| f.to_string()).collect(),
@@ -137,13 +168,41 @@ impl FirestoreUpdateSupport for FirestoreDb {
                 field_paths: masks.clone(),
             }),
             current_document: precondition.map(|cond| cond.try_into()).transpose()?,
-        });
+        };
 
         let begin_query_utc: DateTime<Utc> = Utc::now();
         let update_response = self
-            .client()
-            .get()
-            .update_document(update_document_request)
+            .execute_with_retries("Updating a document", !has_precondition, || {
+                let update_document_request = update_document_request.clone();
+                let metrics = self.record_operation(
+                    FirestoreOperationKind::Update,
+                    Some(collection_id),
+                    &span,
+                );
+                async move {
+                    let result = async {
+                        let response = self
+                            .client()
+                            .get()
+                            .update_document(self.create_api_request(update_document_request)?)
+                            .await
+                            .map_err(|status| {
+                                if has_precondition {
+                                    firestore_precondition_status_error(status)
+                                } else {
+                                    status.into()
+                                }
+                            })?;
+                        self.notify_response_middlewares(FirestoreApiResponse::UpdateDocument(
+                            response.get_ref(),
+                        ));
+                        Ok(response)
+                    }
+                    .await;
+                    metrics.record_result(&result, 1);
+                    result
+                }
+            })
             .await?;
         let end_query_utc: DateTime<Utc> = Utc::now();
         let query_duration = end_query_utc.signed_duration_since(begin_query_utc);
@@ -157,6 +216,139 @@ impl FirestoreUpdateSupport for FirestoreDb {
             debug!(collection_id, document_id, "Updated the document.");
         });
 
-        Ok(update_response.into_inner())
+        let updated_doc = update_response.into_inner();
+
+        #[cfg(feature = "caching")]
+        self.offer_doc_write_to_cache(&updated_doc, return_only_fields_empty)
+            .await;
+
+        Ok(updated_doc)
+    }
+
+    async fn transform_doc<S>(
+        &self,
+        collection_id: &str,
+        document_id: S,
+        precondition: Option<FirestoreWritePrecondition>,
+        transforms: Vec<FirestoreFieldTransform>,
+    ) -> FirestoreResult<FirestoreWriteResult>
+    where
+        S: AsRef<str> + Send,
+    {
+        self.transform_doc_at(
+            self.get_documents_path().as_str(),
+            collection_id,
+            document_id,
+            precondition,
+            transforms,
+        )
+        .await
+    }
+
+    async fn transform_doc_at<S>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_id: S,
+        precondition: Option<FirestoreWritePrecondition>,
+        transforms: Vec<FirestoreFieldTransform>,
+    ) -> FirestoreResult<FirestoreWriteResult>
+    where
+        S: AsRef<str> + Send,
+    {
+        let document_path = safe_document_path(parent, collection_id, document_id.as_ref())?;
+
+        let span = span!(
+            Level::DEBUG,
+            "Firestore Transform Document",
+            "/firestore/collection_name" = collection_id,
+            "/firestore/document_name" = document_path.as_str(),
+            "/firestore/response_time" = field::Empty,
+            "/firestore/operation" = field::Empty,
+            "/firestore/document_count" = field::Empty,
+        );
+
+        let has_precondition = precondition.is_some();
+        let write: Write = TransformObjectOperation {
+            parent: parent.to_string(),
+            collection_id: collection_id.to_string(),
+            document_id,
+            precondition,
+            transforms,
+        }
+        .try_into()?;
+
+        let commit_request = CommitRequest {
+            database: self.get_database_path().clone(),
+            writes: vec![write],
+            transaction: vec![],
+        };
+
+        let begin_query_utc: DateTime<Utc> = Utc::now();
+        let commit_response = self
+            .execute_with_retries("Transforming a document", false, || {
+                let commit_request = commit_request.clone();
+                let metrics = self.record_operation(
+                    FirestoreOperationKind::Update,
+                    Some(collection_id),
+                    &span,
+                );
+                async move {
+                    let result = async {
+                        let response = self
+                            .client()
+                            .get()
+                            .commit(self.create_api_request(commit_request)?)
+                            .await
+                            .map_err(|status| {
+                                if has_precondition {
+                                    firestore_precondition_status_error(status)
+                                } else {
+                                    status.into()
+                                }
+                            })?;
+                        self.notify_response_middlewares(FirestoreApiResponse::Commit(
+                            response.get_ref(),
+                        ));
+                        Ok(response)
+                    }
+                    .await;
+                    metrics.record_result(&result, 1);
+                    result
+                }
+            })
+            .await?
+            .into_inner();
+        let end_query_utc: DateTime<Utc> = Utc::now();
+        let query_duration = end_query_utc.signed_duration_since(begin_query_utc);
+
+        span.record(
+            "/firestore/response_time",
+            query_duration.num_milliseconds(),
+        );
+
+        span.in_scope(|| {
+            debug!(
+                collection_id,
+                document_path, "Applied transformations to the document."
+            );
+        });
+
+        #[cfg(feature = "caching")]
+        self.offer_doc_delete_to_cache(&document_path).await;
+
+        commit_response
+            .write_results
+            .into_iter()
+            .next()
+            .map(|write_result| write_result.try_into())
+            .unwrap_or_else(|| {
+                Err(FirestoreError::SystemError(FirestoreSystemError::new(
+                    FirestoreErrorPublicGenericDetails::new("SystemError".into()),
+                    format!(
+                        "No write results received for the transformed document {document_path}"
+                    ),
+                )))
+            })
     }
 }
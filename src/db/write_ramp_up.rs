@@ -0,0 +1,137 @@
+use rsb_derive::*;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// Options of ramping up the write traffic, following the "500/50/5" rule recommended by Firestore:
+/// start at 500 operations per second and increase the rate by 50% every 5 minutes.
+///
+/// Ramping up the traffic gradually gives Firestore time to split the ranges of hot documents
+/// and avoids the hotspotting errors and latencies on large migrations and imports.
+#[derive(Debug, Eq, PartialEq, Clone, Builder)]
+pub struct FirestoreWriteRampUpOptions {
+    /// The number of write operations per second at the start.
+    #[default = "500"]
+    pub initial_ops_per_second: u32,
+    /// The increase of the rate in percents after every interval.
+    #[default = "50"]
+    pub increase_percent: u32,
+    /// The interval between the increases of the rate.
+    #[default = "Duration::from_secs(5 * 60)"]
+    pub increase_interval: Duration,
+    /// The upper bound of the rate, unlimited by default.
+    pub max_ops_per_second: Option<u32>,
+}
+
+impl Default for FirestoreWriteRampUpOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FirestoreWriteRampUpOptions {
+    /// The allowed number of operations per second after the specified time since the first write.
+    pub fn ops_per_second_at(&self, elapsed: Duration) -> f64 {
+        let increases = if self.increase_interval.is_zero() {
+            0
+        } else {
+            (elapsed.as_secs_f64() / self.increase_interval.as_secs_f64()) as i32
+        };
+        let rate = self.initial_ops_per_second.max(1) as f64
+            * (1.0 + self.increase_percent as f64 / 100.0).powi(increases);
+        match self.max_ops_per_second {
+            Some(max_ops_per_second) => rate.min(max_ops_per_second.max(1) as f64),
+            None => rate,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct FirestoreWriteRampUpState {
+    started: Option<Instant>,
+    next_available: Option<Instant>,
+}
+
+impl FirestoreWriteRampUpState {
+    // Reserves the time slot for the operations and returns the time they may be sent at
+    fn reserve(
+        &mut self,
+        options: &FirestoreWriteRampUpOptions,
+        now: Instant,
+        ops: usize,
+    ) -> Instant {
+        let started = *self.started.get_or_insert(now);
+        let send_at = self.next_available.map_or(now, |next| next.max(now));
+        let ops_per_second = options.ops_per_second_at(send_at.duration_since(started));
+        self.next_available = Some(send_at + Duration::from_secs_f64(ops as f64 / ops_per_second));
+        send_at
+    }
+}
+
+/// Paces write operations according to [`FirestoreWriteRampUpOptions`].
+///
+/// The ramp-up starts with the first write. The throttler is shared by all the concurrent
+/// requests of a writer, so the rate limits the writer as a whole.
+#[derive(Debug)]
+pub struct FirestoreWriteRampUpThrottler {
+    pub options: FirestoreWriteRampUpOptions,
+    state: Mutex<FirestoreWriteRampUpState>,
+}
+
+impl FirestoreWriteRampUpThrottler {
+    pub fn new(options: FirestoreWriteRampUpOptions) -> Self {
+        Self {
+            options,
+            state: Mutex::new(FirestoreWriteRampUpState::default()),
+        }
+    }
+
+    /// Waits until the specified number of write operations may be sent.
+    pub async fn acquire(&self, ops: usize) {
+        let send_at = self
+            .state
+            .lock()
+            .await
+            .reserve(&self.options, Instant::now(), ops);
+        tokio::time::sleep_until(send_at).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramp_up_rate() {
+        let options = FirestoreWriteRampUpOptions::new();
+        assert_eq!(options.ops_per_second_at(Duration::ZERO), 500.0);
+        assert_eq!(options.ops_per_second_at(Duration::from_secs(299)), 500.0);
+        assert_eq!(options.ops_per_second_at(Duration::from_secs(300)), 750.0);
+        assert_eq!(options.ops_per_second_at(Duration::from_secs(600)), 1125.0);
+
+        let capped = options.with_max_ops_per_second(1000);
+        assert_eq!(capped.ops_per_second_at(Duration::from_secs(3600)), 1000.0);
+    }
+
+    #[test]
+    fn ramp_up_reservations() {
+        let options = FirestoreWriteRampUpOptions::new();
+        let mut state = FirestoreWriteRampUpState::default();
+        let now = Instant::now();
+
+        // The first writes are sent immediately, the next ones are paced at 500 ops/sec
+        assert_eq!(state.reserve(&options, now, 250), now);
+        assert_eq!(
+            state.reserve(&options, now, 500),
+            now + Duration::from_millis(500)
+        );
+        assert_eq!(
+            state.reserve(&options, now, 1),
+            now + Duration::from_millis(1500)
+        );
+
+        // Idle time isn't accumulated
+        let later = now + Duration::from_secs(10);
+        assert_eq!(state.reserve(&options, later, 1), later);
+    }
+}
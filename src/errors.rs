@@ -10,7 +10,9 @@ use std::fmt::Formatter;
 ///
 /// This enum consolidates various specific error types that can occur
 /// during interactions with Google Firestore.
+/// New kinds of errors may be added, so matches on it need a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum FirestoreError {
     /// An error originating from the underlying system or a dependency, not directly
     /// from a Firestore API interaction. This could include issues with the gRPC client,
@@ -37,6 +39,13 @@ pub enum FirestoreError {
     ErrorInTransaction(FirestoreErrorInTransaction),
     /// An error related to the caching layer, if enabled and used.
     CacheError(FirestoreCacheError),
+    /// A write precondition (e.g. the document must exist or must have a specific
+    /// update time) was not met.
+    ConditionFailedError(FirestoreConditionFailedError),
+    /// A query requires a composite index that doesn't exist.
+    MissingIndexError(FirestoreMissingIndexError),
+    /// A projection doesn't include the fields required to deserialize the documents.
+    ProjectionError(FirestoreProjectionError),
 }
 
 impl Display for FirestoreError {
@@ -52,6 +61,9 @@ impl Display for FirestoreError {
             FirestoreError::NetworkError(ref err) => err.fmt(f),
             FirestoreError::ErrorInTransaction(ref err) => err.fmt(f),
             FirestoreError::CacheError(ref err) => err.fmt(f),
+            FirestoreError::ConditionFailedError(ref err) => err.fmt(f),
+            FirestoreError::MissingIndexError(ref err) => err.fmt(f),
+            FirestoreError::ProjectionError(ref err) => err.fmt(f),
         }
     }
 }
@@ -69,6 +81,9 @@ impl Error for FirestoreError {
             FirestoreError::NetworkError(ref err) => Some(err),
             FirestoreError::ErrorInTransaction(ref err) => Some(err),
             FirestoreError::CacheError(ref err) => Some(err),
+            FirestoreError::ConditionFailedError(ref err) => Some(err),
+            FirestoreError::MissingIndexError(ref err) => Some(err),
+            FirestoreError::ProjectionError(ref err) => Some(err),
         }
     }
 }
@@ -163,6 +178,118 @@ impl Display for FirestoreDataConflictError {
 
 impl std::error::Error for FirestoreDataConflictError {}
 
+/// Represents an error indicating that a write precondition was not met.
+///
+/// This is returned for optimistic concurrency checks, for example when the document
+/// was modified since the update time specified in the precondition, or when the
+/// document doesn't exist and the precondition requires it to exist.
+#[derive(Debug, Clone, Builder)]
+pub struct FirestoreConditionFailedError {
+    /// Generic public details about the error.
+    pub public: FirestoreErrorPublicGenericDetails,
+    /// Specific details about the failed precondition.
+    pub details: String,
+}
+
+impl Display for FirestoreConditionFailedError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Write precondition failed: {}. {}",
+            self.public, self.details
+        )
+    }
+}
+
+impl std::error::Error for FirestoreConditionFailedError {}
+
+/// Represents an error indicating that a query requires a composite index that doesn't exist.
+///
+/// Firestore reports it as `FAILED_PRECONDITION` with a link to create the index,
+/// the collection and the fields of the index are decoded from the link.
+#[derive(Debug, Eq, PartialEq, Clone, Builder)]
+pub struct FirestoreMissingIndexError {
+    /// Generic public details about the error.
+    pub public: FirestoreErrorPublicGenericDetails,
+    /// Specific details about the error as reported by Firestore.
+    pub details: String,
+    /// The collection group of the required index.
+    pub collection: Option<String>,
+    /// The field paths of the required index in order.
+    pub fields: Vec<String>,
+    /// The Firebase console URL to create the required index.
+    pub index_creation_url: Option<String>,
+}
+
+impl FirestoreMissingIndexError {
+    /// Parses the error from a status message, if the status reports a missing index.
+    pub fn from_status_message(message: &str) -> Option<Self> {
+        if !message.contains("requires an index") && !message.contains("create_composite=") {
+            return None;
+        }
+        let index_creation_url = message
+            .split_whitespace()
+            .find(|word| word.starts_with("https://"))
+            .map(|url| url.trim_end_matches(['.', ',', ')']).to_string());
+        let (collection, fields) = index_creation_url
+            .as_deref()
+            .and_then(parse_create_composite_index)
+            .map(|(collection, fields)| (Some(collection), fields))
+            .unwrap_or_default();
+
+        Some(FirestoreMissingIndexError {
+            public: FirestoreErrorPublicGenericDetails::new(format!(
+                "{:?}",
+                gcloud_sdk::tonic::Code::FailedPrecondition
+            )),
+            details: message.to_string(),
+            collection,
+            fields,
+            index_creation_url,
+        })
+    }
+}
+
+impl Display for FirestoreMissingIndexError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Query requires an index on {} ({}): {}. {}",
+            self.collection.as_deref().unwrap_or("-"),
+            self.fields.join(", "),
+            self.public,
+            self.details
+        )
+    }
+}
+
+impl std::error::Error for FirestoreMissingIndexError {}
+
+/// Represents an error indicating that the fields selected by a projection
+/// don't include the non-optional fields of the type the documents are deserialized into.
+#[derive(Debug, Eq, PartialEq, Clone, Builder)]
+pub struct FirestoreProjectionError {
+    /// Generic public details about the error.
+    pub public: FirestoreErrorPublicGenericDetails,
+    /// The name of the type the documents are deserialized into.
+    pub type_name: String,
+    /// The non-optional fields of the type missing from the projection.
+    pub missing_fields: Vec<String>,
+}
+
+impl Display for FirestoreProjectionError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Projection doesn't include the non-optional fields of {}: {}. Add them to the projection or make them optional",
+            self.type_name,
+            self.missing_fields.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for FirestoreProjectionError {}
+
 /// Represents an error indicating that requested data was not found.
 ///
 /// This is typically returned when trying to access a document or resource
@@ -291,6 +418,7 @@ impl From<gcloud_sdk::tonic::Status> for FirestoreError {
                     true,
                 ))
             }
+            gcloud_sdk::tonic::Code::InvalidArgument => invalid_argument_error(&status),
             gcloud_sdk::tonic::Code::Unknown => check_hyper_errors(status),
             _ => FirestoreError::DatabaseError(FirestoreDatabaseError::new(
                 FirestoreErrorPublicGenericDetails::new(format!("{:?}", status.code())),
@@ -301,6 +429,160 @@ impl From<gcloud_sdk::tonic::Status> for FirestoreError {
     }
 }
 
+/// Converts the status of a query, reporting a missing composite index as a `MissingIndexError`.
+pub(crate) fn firestore_query_status_error(status: gcloud_sdk::tonic::Status) -> FirestoreError {
+    match status.code() {
+        gcloud_sdk::tonic::Code::FailedPrecondition => {
+            match FirestoreMissingIndexError::from_status_message(status.message()) {
+                Some(missing_index) => FirestoreError::MissingIndexError(missing_index),
+                None => status.into(),
+            }
+        }
+        _ => status.into(),
+    }
+}
+
+/// Converts the status of a write with a precondition, reporting failed preconditions and documents
+/// that don't exist or already exist as a failed precondition rather than as a database error
+/// or a missing or conflicting document.
+pub(crate) fn firestore_precondition_status_error(
+    status: gcloud_sdk::tonic::Status,
+) -> FirestoreError {
+    match status.code() {
+        gcloud_sdk::tonic::Code::FailedPrecondition
+        | gcloud_sdk::tonic::Code::NotFound
+        | gcloud_sdk::tonic::Code::AlreadyExists => {
+            FirestoreError::ConditionFailedError(FirestoreConditionFailedError::new(
+                FirestoreErrorPublicGenericDetails::new(format!("{:?}", status.code())),
+                format!("{status}"),
+            ))
+        }
+        _ => status.into(),
+    }
+}
+
+/// Converts an `INVALID_ARGUMENT` status, taking the invalid fields from its `BadRequest` details.
+fn invalid_argument_error(status: &gcloud_sdk::tonic::Status) -> FirestoreError {
+    use gcloud_sdk::prost::Message;
+
+    let violations: Vec<gcloud_sdk::google::rpc::bad_request::FieldViolation> =
+        gcloud_sdk::google::rpc::Status::decode(status.details())
+            .map(|details| {
+                details
+                    .details
+                    .iter()
+                    .filter(|detail| detail.type_url.ends_with("google.rpc.BadRequest"))
+                    .filter_map(|detail| {
+                        gcloud_sdk::google::rpc::BadRequest::decode(detail.value.as_slice()).ok()
+                    })
+                    .flat_map(|bad_request| bad_request.field_violations)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+    let public = if violations.is_empty() {
+        FirestoreInvalidParametersPublicDetails::new(
+            "request".to_string(),
+            status.message().to_string(),
+        )
+    } else {
+        FirestoreInvalidParametersPublicDetails::new(
+            violations
+                .iter()
+                .map(|violation| violation.field.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            violations
+                .iter()
+                .map(|violation| violation.description.as_str())
+                .collect::<Vec<_>>()
+                .join("; "),
+        )
+    };
+    FirestoreError::InvalidParametersError(FirestoreInvalidParametersError::new(public))
+}
+
+/// Decodes the collection group and the field paths of the index from the `create_composite`
+/// parameter of an index creation URL, which is an encoded `google.firestore.admin.v1.Index`.
+fn parse_create_composite_index(url: &str) -> Option<(String, Vec<String>)> {
+    use base64::Engine;
+
+    let encoded = url
+        .split(['?', '&'])
+        .find_map(|param| param.strip_prefix("create_composite="))?
+        .replace("%2B", "+")
+        .replace("%2F", "/")
+        .replace("%3D", "=");
+    let engine = base64::engine::GeneralPurpose::new(
+        &base64::alphabet::STANDARD,
+        base64::engine::GeneralPurposeConfig::new()
+            .with_decode_padding_mode(base64::engine::DecodePaddingMode::Indifferent),
+    );
+    let index = engine
+        .decode(encoded.replace('-', "+").replace('_', "/"))
+        .ok()?;
+
+    let mut collection = None;
+    let mut fields = Vec::new();
+    for (tag, value) in protobuf_length_delimited_fields(&index)? {
+        match tag {
+            1 => {
+                let name = std::str::from_utf8(value).ok()?;
+                collection = name
+                    .split("/collectionGroups/")
+                    .nth(1)
+                    .and_then(|rest| rest.split('/').next())
+                    .map(|collection| collection.to_string());
+            }
+            3 => {
+                if let Some((_, field_path)) = protobuf_length_delimited_fields(value)?
+                    .into_iter()
+                    .find(|(tag, _)| *tag == 1)
+                {
+                    fields.push(std::str::from_utf8(field_path).ok()?.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    collection.map(|collection| (collection, fields))
+}
+
+/// Reads the length-delimited fields of a protobuf message, skipping other wire types.
+fn protobuf_length_delimited_fields(mut bytes: &[u8]) -> Option<Vec<(u64, &[u8])>> {
+    fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (byte, rest) = bytes.split_first()?;
+            *bytes = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    let mut fields = Vec::new();
+    while !bytes.is_empty() {
+        let key = read_varint(&mut bytes)?;
+        match key & 0x7 {
+            0 => {
+                read_varint(&mut bytes)?;
+            }
+            1 => bytes = bytes.get(8..)?,
+            2 => {
+                let len = read_varint(&mut bytes)? as usize;
+                fields.push((key >> 3, bytes.get(..len)?));
+                bytes = bytes.get(len..)?;
+            }
+            5 => bytes = bytes.get(4..)?,
+            _ => return None,
+        }
+    }
+    Some(fields)
+}
+
 fn check_hyper_errors(status: gcloud_sdk::tonic::Status) -> FirestoreError {
     match status.source() {
         Some(hyper_error) => match hyper_error.downcast_ref::<hyper::Error>() {
@@ -362,6 +644,37 @@ impl serde::de::Error for FirestoreError {
     {
         FirestoreError::DeserializeError(FirestoreSerializationError::from_message(msg.to_string()))
     }
+
+    fn invalid_type(unexp: serde::de::Unexpected, exp: &dyn serde::de::Expected) -> Self {
+        FirestoreError::DeserializeError(
+            FirestoreSerializationError::from_message(format!(
+                "invalid type: {unexp}, expected {exp}"
+            ))
+            .with_field_details(Box::new(
+                FirestoreSerializationFieldDetails::new().with_expected_type(exp.to_string()),
+            )),
+        )
+    }
+
+    fn invalid_value(unexp: serde::de::Unexpected, exp: &dyn serde::de::Expected) -> Self {
+        FirestoreError::DeserializeError(
+            FirestoreSerializationError::from_message(format!(
+                "invalid value: {unexp}, expected {exp}"
+            ))
+            .with_field_details(Box::new(
+                FirestoreSerializationFieldDetails::new().with_expected_type(exp.to_string()),
+            )),
+        )
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        FirestoreError::DeserializeError(
+            FirestoreSerializationError::from_message(format!("missing field `{field}`"))
+                .with_field_details(Box::new(
+                    FirestoreSerializationFieldDetails::new().with_field_path(field.to_string()),
+                )),
+        )
+    }
 }
 
 /// Represents an error that occurred during data serialization or deserialization.
@@ -376,6 +689,51 @@ pub struct FirestoreSerializationError {
     pub message: String,
     /// The path of the document being processed when the error occurred, if applicable.
     pub document_path: Option<String>,
+    /// The field the error occurred in and the mismatched types, if known.
+    pub field_details: Option<Box<FirestoreSerializationFieldDetails>>,
+}
+
+/// Describes the field of a document that could not be deserialized.
+#[derive(Debug, Clone, Default, Builder)]
+pub struct FirestoreSerializationFieldDetails {
+    /// The path of the offending field in the document, e.g. `items[2].price`.
+    pub field_path: Option<String>,
+    /// The Rust type expected by the deserialized type, if the error is a type or value mismatch.
+    pub expected_type: Option<String>,
+    /// The type of the Firestore value that could not be deserialized, e.g. `string` or `map`.
+    pub actual_value_type: Option<String>,
+    /// The number that doesn't fit into the target type, if the error is a numeric overflow.
+    pub numeric_overflow: Option<FirestoreNumericOverflow>,
+}
+
+/// Describes a number that doesn't fit into the target type without losing precision,
+/// see [`FirestoreNumericOverflowPolicy`](crate::FirestoreNumericOverflowPolicy).
+#[derive(Debug, Eq, PartialEq, Clone, Builder)]
+pub struct FirestoreNumericOverflow {
+    /// The number formatted as a decimal string.
+    pub value: String,
+    /// The type the number doesn't fit into, e.g. `i64` for Firestore integers.
+    pub target_type: String,
+}
+
+impl Display for FirestoreNumericOverflow {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{} is out of the {} range", self.value, self.target_type)
+    }
+}
+
+impl FirestoreNumericOverflow {
+    pub(crate) fn into_serialization_error(self) -> FirestoreSerializationError {
+        FirestoreSerializationError::new(
+            FirestoreErrorPublicGenericDetails::new("NumericOverflow".to_string()),
+            format!("Numeric overflow: {self}"),
+        )
+        .with_field_details(Box::new(
+            FirestoreSerializationFieldDetails::new()
+                .with_expected_type(self.target_type.clone())
+                .with_numeric_overflow(self),
+        ))
+    }
 }
 
 impl FirestoreSerializationError {
@@ -387,6 +745,11 @@ impl FirestoreSerializationError {
             message_str,
         )
     }
+
+    /// Returns the field details of the error, creating empty ones if there are none yet.
+    pub(crate) fn field_details_mut(&mut self) -> &mut FirestoreSerializationFieldDetails {
+        self.field_details.get_or_insert_with(Default::default)
+    }
 }
 
 impl Display for FirestoreSerializationError {
@@ -397,7 +760,19 @@ impl Display for FirestoreSerializationError {
             self.public,
             self.message,
             self.document_path.as_deref().unwrap_or("-")
-        )
+        )?;
+        if let Some(details) = &self.field_details {
+            if let Some(field_path) = &details.field_path {
+                write!(f, ". Field path: {field_path}")?;
+            }
+            if let Some(expected_type) = &details.expected_type {
+                write!(f, ". Expected: {expected_type}")?;
+            }
+            if let Some(actual_value_type) = &details.actual_value_type {
+                write!(f, ". Firestore value type: {actual_value_type}")?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -574,3 +949,141 @@ impl From<gcloud_sdk::prost::DecodeError> for FirestoreError {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use gcloud_sdk::prost::Message;
+
+    #[test]
+    fn precondition_failures_are_typed() {
+        let status = || {
+            gcloud_sdk::tonic::Status::failed_precondition(
+                "the stored version does not match the required base version",
+            )
+        };
+        assert!(matches!(
+            firestore_precondition_status_error(status()),
+            FirestoreError::ConditionFailedError(_)
+        ));
+        assert!(matches!(
+            FirestoreError::from(status()),
+            FirestoreError::DatabaseError(_)
+        ));
+
+        assert!(matches!(
+            firestore_precondition_status_error(gcloud_sdk::tonic::Status::not_found(
+                "no entity to update"
+            )),
+            FirestoreError::ConditionFailedError(_)
+        ));
+
+        let err: FirestoreError = gcloud_sdk::tonic::Status::not_found("no entity").into();
+        assert!(matches!(err, FirestoreError::DataNotFoundError(_)));
+    }
+
+    fn length_delimited(tag: u8, value: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![tag << 3 | 2, value.len() as u8];
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    #[test]
+    fn missing_index_from_status() {
+        let mut index = length_delimited(
+            1,
+            b"projects/test/databases/(default)/collectionGroups/orders/indexes/_",
+        );
+        index.extend([2 << 3, 1]);
+        for field_path in ["status", "created_at", "__name__"] {
+            let mut field = length_delimited(1, field_path.as_bytes());
+            field.extend([2 << 3, 1]);
+            index.extend(length_delimited(3, &field));
+        }
+        let url = format!(
+            "https://console.firebase.google.com/v1/r/project/test/firestore/indexes?create_composite={}",
+            base64::engine::general_purpose::STANDARD_NO_PAD.encode(index)
+        );
+        let status = gcloud_sdk::tonic::Status::failed_precondition(format!(
+            "The query requires an index. You can create it here: {url}"
+        ));
+
+        match firestore_query_status_error(status) {
+            FirestoreError::MissingIndexError(err) => {
+                assert_eq!(err.collection.as_deref(), Some("orders"));
+                assert_eq!(err.fields, vec!["status", "created_at", "__name__"]);
+                assert_eq!(err.index_creation_url, Some(url));
+            }
+            other => panic!("Unexpected error: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn missing_index_from_server_messages() {
+        let url = "https://console.firebase.google.com/v1/r/project/my-project/firestore/indexes?create_composite=Cklwcm9qZWN0cy9teS1wcm9qZWN0L2RhdGFiYXNlcy8oZGVmYXVsdCkvY29sbGVjdGlvbkdyb3Vwcy9vcmRlcnMvaW5kZXhlcy9fEAEaCgoGc3RhdHVzEAEaDQoJY3JlYXRlZEF0EAIaDAoIX19uYW1lX18QAg";
+
+        for message in [
+            format!("The query requires an index. You can create it here: {url}"),
+            format!("The query requires an index. That index is currently building and cannot be used yet. See its status here: {url}"),
+        ] {
+            let status = || gcloud_sdk::tonic::Status::failed_precondition(message.clone());
+            match firestore_query_status_error(status()) {
+                FirestoreError::MissingIndexError(err) => {
+                    assert_eq!(err.collection.as_deref(), Some("orders"));
+                    assert_eq!(err.fields, vec!["status", "createdAt", "__name__"]);
+                    assert_eq!(err.index_creation_url.as_deref(), Some(url));
+                }
+                other => panic!("Unexpected error: {other:?}"),
+            }
+            assert!(matches!(
+                FirestoreError::from(status()),
+                FirestoreError::DatabaseError(_)
+            ));
+        }
+
+        for message in [
+            "The Cloud Firestore API is not available for Firestore in Datastore Mode database projects/my-project/databases/(default).",
+            "the stored version (1700000000000000) does not match the required base version (0)",
+        ] {
+            assert!(matches!(
+                firestore_query_status_error(gcloud_sdk::tonic::Status::failed_precondition(
+                    message
+                )),
+                FirestoreError::DatabaseError(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn invalid_argument_with_field_violations() {
+        let details = gcloud_sdk::google::rpc::Status {
+            code: gcloud_sdk::tonic::Code::InvalidArgument as i32,
+            message: "Invalid request".to_string(),
+            details: vec![gcloud_sdk::prost_types::Any {
+                type_url: "type.googleapis.com/google.rpc.BadRequest".to_string(),
+                value: gcloud_sdk::google::rpc::BadRequest {
+                    field_violations: vec![gcloud_sdk::google::rpc::bad_request::FieldViolation {
+                        field: "structured_query.limit".to_string(),
+                        description: "must be positive".to_string(),
+                        ..Default::default()
+                    }],
+                }
+                .encode_to_vec(),
+            }],
+        };
+        let status = gcloud_sdk::tonic::Status::with_details(
+            gcloud_sdk::tonic::Code::InvalidArgument,
+            "Invalid request",
+            details.encode_to_vec().into(),
+        );
+
+        match FirestoreError::from(status) {
+            FirestoreError::InvalidParametersError(err) => {
+                assert_eq!(err.public.field, "structured_query.limit");
+                assert_eq!(err.public.error, "must be positive");
+            }
+            other => panic!("Unexpected error: {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,389 @@
+//! Exporting collections as newline-delimited JSON (NDJSON) or Parquet (the `export-parquet` feature).
+//!
+//! Exports are snapshots of collections for other systems, e.g. analytics ingestion,
+//! without managed exports.
+
+use crate::errors::*;
+use crate::firestore_serde::proto_json::document_to_json;
+use crate::{
+    FirestoreConsistencySelector, FirestoreDb, FirestorePartitionQueryParams, FirestoreQueryParams,
+    FirestoreQuerySupport, FirestoreResult,
+};
+use chrono::{DateTime, SubsecRound, Utc};
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use gcloud_sdk::google::firestore::v1::Document;
+use rsb_derive::Builder;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tracing::*;
+
+/// Parameters of an export of documents.
+#[derive(Debug, PartialEq, Clone, Builder)]
+pub struct FirestoreExportParams {
+    /// The query selecting the exported documents, usually a whole collection.
+    pub query_params: FirestoreQueryParams,
+    /// Exports the documents as they were at the time, so the export is a consistent snapshot
+    /// even when the collection is read with several partitions.
+    /// Must be within [`FirestoreDbOptions::point_in_time_read_window`](crate::FirestoreDbOptions::point_in_time_read_window).
+    /// Partitioned exports are read at the time the export started if not specified.
+    pub read_time: Option<DateTime<Utc>>,
+    /// The number of partitions of the collection read concurrently,
+    /// `1` to read the documents with a single query.
+    #[default = "1"]
+    pub partitions: usize,
+}
+
+/// The statistics of a finished export.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct FirestoreExportStats {
+    /// The number of exported documents.
+    pub documents: usize,
+    /// The number of bytes written.
+    pub bytes: u64,
+}
+
+async fn export_documents_stream<D>(
+    db: &D,
+    params: FirestoreExportParams,
+) -> FirestoreResult<BoxStream<'_, FirestoreResult<Document>>>
+where
+    D: FirestoreQuerySupport + Sync,
+{
+    // The partitions are read with separate queries, so they need the same read time to be consistent
+    let read_time = match params.read_time {
+        None if params.partitions > 1 => Some(Utc::now().trunc_subsecs(6)),
+        read_time => read_time,
+    };
+    let query_params = match read_time {
+        Some(read_time) => params
+            .query_params
+            .with_consistency_selector(FirestoreConsistencySelector::ReadTime(read_time)),
+        None => params.query_params,
+    };
+
+    if params.partitions > 1 {
+        Ok(db
+            .stream_partition_query_doc_with_errors(
+                params.partitions,
+                FirestorePartitionQueryParams::new(
+                    query_params,
+                    params.partitions as u32,
+                    params.partitions as u32,
+                ),
+            )
+            .await?
+            .map_ok(|(_, doc)| doc)
+            .boxed())
+    } else {
+        db.stream_query_doc_with_errors(query_params).await
+    }
+}
+
+pub(crate) async fn export_ndjson<D, W>(
+    db: &D,
+    params: FirestoreExportParams,
+    writer: &mut W,
+) -> FirestoreResult<FirestoreExportStats>
+where
+    D: FirestoreQuerySupport + Sync,
+    W: AsyncWrite + Unpin + Send,
+{
+    let mut documents = export_documents_stream(db, params).await?;
+
+    let mut stats = FirestoreExportStats::default();
+    while let Some(doc) = documents.try_next().await? {
+        let mut line = serde_json::to_vec(&document_to_json(&doc)?).map_err(|err| {
+            FirestoreError::SerializeError(FirestoreSerializationError::from_message(format!(
+                "Unable to serialize document {}: {err}",
+                doc.name
+            )))
+        })?;
+        line.push(b'\n');
+        writer.write_all(&line).await?;
+        stats.documents += 1;
+        stats.bytes += line.len() as u64;
+    }
+    writer.flush().await?;
+
+    debug!(
+        documents = stats.documents,
+        bytes = stats.bytes,
+        "Exported documents."
+    );
+
+    Ok(stats)
+}
+
+impl FirestoreDb {
+    /// Streams the documents selected by the query into the writer as newline-delimited JSON,
+    /// one document per line.
+    ///
+    /// Every line is a document in the JSON representation of the Firestore REST API
+    /// (the proto3 JSON mapping), keeping the Firestore types of the values:
+    ///
+    /// ```json
+    /// {"name":"projects/p/databases/(default)/documents/users/u1","createTime":"2024-01-01T00:00:00Z","updateTime":"2024-01-02T00:00:00Z","fields":{"name":{"stringValue":"Ann"},"age":{"integerValue":"30"},"joined":{"timestampValue":"2024-01-01T00:00:00Z"},"team":{"referenceValue":"projects/p/databases/(default)/documents/teams/t1"},"location":{"geoPointValue":{"latitude":51.5,"longitude":-0.1}},"avatar":{"bytesValue":"iVBORw0K"}}}
+    /// ```
+    ///
+    /// Integers are encoded as strings to keep their precision, bytes as base64 strings,
+    /// timestamps as RFC 3339 strings, and `NaN`/`Infinity` doubles as strings.
+    ///
+    /// The writer isn't buffered, so wrap files and sockets with `tokio::io::BufWriter`.
+    pub async fn export_ndjson<W>(
+        &self,
+        params: FirestoreExportParams,
+        writer: &mut W,
+    ) -> FirestoreResult<FirestoreExportStats>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        export_ndjson(self, params, writer).await
+    }
+}
+
+/// The number of documents written to Parquet files in a row group.
+#[cfg(feature = "export-parquet")]
+const FIRESTORE_EXPORT_PARQUET_ROW_GROUP_DOCUMENTS: usize = 10_000;
+
+#[cfg(feature = "export-parquet")]
+const FIRESTORE_EXPORT_PARQUET_SCHEMA: &str = "
+message firestore_document {
+    REQUIRED BYTE_ARRAY name (UTF8);
+    OPTIONAL INT64 create_time (TIMESTAMP(MICROS,true));
+    OPTIONAL INT64 update_time (TIMESTAMP(MICROS,true));
+    REQUIRED BYTE_ARRAY fields (JSON);
+}
+";
+
+#[cfg(feature = "export-parquet")]
+fn parquet_export_error(err: parquet::errors::ParquetError) -> FirestoreError {
+    FirestoreError::SerializeError(FirestoreSerializationError::from_message(format!(
+        "Unable to write Parquet: {err}"
+    )))
+}
+
+#[cfg(feature = "export-parquet")]
+pub(crate) async fn export_parquet<D, W>(
+    db: &D,
+    params: FirestoreExportParams,
+    writer: &mut W,
+) -> FirestoreResult<FirestoreExportStats>
+where
+    D: FirestoreQuerySupport + Sync,
+    W: AsyncWrite + Unpin + Send,
+{
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use std::sync::Arc;
+
+    let schema = parquet::schema::parser::parse_message_type(FIRESTORE_EXPORT_PARQUET_SCHEMA)
+        .map_err(parquet_export_error)?;
+    let properties = WriterProperties::builder()
+        .set_compression(parquet::basic::Compression::SNAPPY)
+        .build();
+    // Row groups are written into the buffer and then moved to the async writer
+    let mut file_writer =
+        SerializedFileWriter::new(Vec::new(), Arc::new(schema), Arc::new(properties))
+            .map_err(parquet_export_error)?;
+
+    let mut documents = export_documents_stream(db, params).await?;
+    let mut stats = FirestoreExportStats::default();
+    let mut row_group: Vec<Document> = Vec::new();
+    while let Some(doc) = documents.try_next().await? {
+        row_group.push(doc);
+        if row_group.len() >= FIRESTORE_EXPORT_PARQUET_ROW_GROUP_DOCUMENTS {
+            write_parquet_row_group(&mut file_writer, &row_group)?;
+            stats.documents += row_group.len();
+            row_group.clear();
+            let buffer = std::mem::take(file_writer.inner_mut());
+            writer.write_all(&buffer).await?;
+            stats.bytes += buffer.len() as u64;
+        }
+    }
+    if !row_group.is_empty() {
+        write_parquet_row_group(&mut file_writer, &row_group)?;
+        stats.documents += row_group.len();
+    }
+    let buffer = file_writer.into_inner().map_err(parquet_export_error)?;
+    writer.write_all(&buffer).await?;
+    stats.bytes += buffer.len() as u64;
+    writer.flush().await?;
+
+    debug!(
+        documents = stats.documents,
+        bytes = stats.bytes,
+        "Exported documents to Parquet."
+    );
+
+    Ok(stats)
+}
+
+#[cfg(feature = "export-parquet")]
+fn write_parquet_row_group(
+    file_writer: &mut parquet::file::writer::SerializedFileWriter<Vec<u8>>,
+    docs: &[Document],
+) -> FirestoreResult<()> {
+    use crate::firestore_serde::proto_json::fields_to_json;
+    use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+
+    let names: Vec<ByteArray> = docs
+        .iter()
+        .map(|doc| ByteArray::from(doc.name.as_str()))
+        .collect();
+    let fields = docs
+        .iter()
+        .map(|doc| {
+            serde_json::to_vec(&fields_to_json(&doc.fields)?)
+                .map(ByteArray::from)
+                .map_err(|err| {
+                    FirestoreError::SerializeError(FirestoreSerializationError::from_message(
+                        format!("Unable to serialize document {}: {err}", doc.name),
+                    ))
+                })
+        })
+        .collect::<FirestoreResult<Vec<ByteArray>>>()?;
+    let timestamps = |timestamp: fn(&Document) -> &Option<gcloud_sdk::prost_types::Timestamp>| {
+        let def_levels: Vec<i16> = docs
+            .iter()
+            .map(|doc| i16::from(timestamp(doc).is_some()))
+            .collect();
+        let micros: Vec<i64> = docs
+            .iter()
+            .filter_map(|doc| timestamp(doc).as_ref())
+            .map(|ts| ts.seconds * 1_000_000 + i64::from(ts.nanos) / 1_000)
+            .collect();
+        (micros, def_levels)
+    };
+    let (create_times, create_time_levels) = timestamps(|doc| &doc.create_time);
+    let (update_times, update_time_levels) = timestamps(|doc| &doc.update_time);
+
+    let mut row_group_writer = file_writer.next_row_group().map_err(parquet_export_error)?;
+    let mut column_index = 0;
+    while let Some(mut column_writer) = row_group_writer
+        .next_column()
+        .map_err(parquet_export_error)?
+    {
+        match column_index {
+            0 => column_writer
+                .typed::<ByteArrayType>()
+                .write_batch(&names, None, None),
+            1 => column_writer.typed::<Int64Type>().write_batch(
+                &create_times,
+                Some(&create_time_levels),
+                None,
+            ),
+            2 => column_writer.typed::<Int64Type>().write_batch(
+                &update_times,
+                Some(&update_time_levels),
+                None,
+            ),
+            _ => column_writer
+                .typed::<ByteArrayType>()
+                .write_batch(&fields, None, None),
+        }
+        .map_err(parquet_export_error)?;
+        column_writer.close().map_err(parquet_export_error)?;
+        column_index += 1;
+    }
+    row_group_writer.close().map_err(parquet_export_error)?;
+    Ok(())
+}
+
+#[cfg(feature = "export-parquet")]
+impl FirestoreDb {
+    /// Streams the documents selected by the query into the writer as a Parquet file
+    /// (the `export-parquet` feature), compressed with Snappy.
+    ///
+    /// The file has a row per document with the columns:
+    /// - `name`: the full path of the document (`UTF8`);
+    /// - `create_time`, `update_time`: the document timestamps (`TIMESTAMP(MICROS)`, UTC);
+    /// - `fields`: the fields of the document as JSON (`JSON`), in the same encoding
+    ///   as the `fields` of [`FirestoreDb::export_ndjson`], keeping the Firestore types of the values.
+    ///
+    /// The documents are written in row groups of 10 000 documents, which are buffered in memory.
+    pub async fn export_parquet<W>(
+        &self,
+        params: FirestoreExportParams,
+        writer: &mut W,
+    ) -> FirestoreResult<FirestoreExportStats>
+    where
+        W: AsyncWrite + Unpin + Send,
+    {
+        export_parquet(self, params, writer).await
+    }
+}
+
+#[cfg(all(test, feature = "export-parquet"))]
+mod tests {
+    use super::*;
+    use gcloud_sdk::google::firestore::v1::{value, Value};
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::record::Field;
+    use std::collections::HashMap;
+    use std::io::{Seek, SeekFrom, Write};
+    use std::sync::Arc;
+
+    #[test]
+    fn write_parquet_documents() -> FirestoreResult<()> {
+        let schema =
+            parquet::schema::parser::parse_message_type(FIRESTORE_EXPORT_PARQUET_SCHEMA).unwrap();
+        let mut file_writer =
+            SerializedFileWriter::new(Vec::new(), Arc::new(schema), Default::default()).unwrap();
+        let docs = vec![
+            Document {
+                name: "projects/p/databases/(default)/documents/users/u1".to_string(),
+                fields: HashMap::from([(
+                    "age".to_string(),
+                    Value {
+                        value_type: Some(value::ValueType::IntegerValue(30)),
+                    },
+                )]),
+                create_time: Some(gcloud_sdk::prost_types::Timestamp {
+                    seconds: 1,
+                    nanos: 2_000,
+                }),
+                update_time: None,
+            },
+            Document {
+                name: "projects/p/databases/(default)/documents/users/u2".to_string(),
+                ..Default::default()
+            },
+        ];
+        write_parquet_row_group(&mut file_writer, &docs)?;
+
+        let mut file = tempfile::tempfile()?;
+        file.write_all(&file_writer.into_inner().unwrap())?;
+        file.seek(SeekFrom::Start(0))?;
+        let reader = SerializedFileReader::new(file).unwrap();
+        let rows: Vec<Vec<Field>> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| {
+                row.unwrap()
+                    .get_column_iter()
+                    .map(|(_, field)| field.clone())
+                    .collect()
+            })
+            .collect();
+
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    Field::Str("projects/p/databases/(default)/documents/users/u1".to_string()),
+                    Field::TimestampMicros(1_000_002),
+                    Field::Null,
+                    Field::Str(r#"{"age":{"integerValue":"30"}}"#.to_string()),
+                ],
+                vec![
+                    Field::Str("projects/p/databases/(default)/documents/users/u2".to_string()),
+                    Field::Null,
+                    Field::Null,
+                    Field::Str("{}".to_string()),
+                ],
+            ]
+        );
+        Ok(())
+    }
+}
@@ -85,3 +85,127 @@ fn firestore_doc_get_field_by_path_arr<'d>(
         })
     })
 }
+
+/// Escapes a single field name (or map key) for use in a Firestore field path.
+///
+/// Simple names (letters, digits and underscores, not starting with a digit) are returned
+/// as is. Other names are quoted with backticks, escaping backticks and backslashes inside them.
+///
+/// # Examples
+/// ```rust
+/// use firestore::firestore_field_path_escape;
+///
+/// assert_eq!(firestore_field_path_escape("email"), "email");
+/// assert_eq!(firestore_field_path_escape("e-mail"), "`e-mail`");
+/// assert_eq!(firestore_field_path_escape("a.b"), "`a.b`");
+/// assert_eq!(firestore_field_path_escape("`x`"), "`\\`x\\``");
+/// ```
+pub fn firestore_field_path_escape(segment: &str) -> String {
+    let is_simple = segment
+        .chars()
+        .next()
+        .map(|c| c.is_ascii_alphabetic() || c == '_')
+        .unwrap_or(false)
+        && segment
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if is_simple {
+        segment.to_string()
+    } else {
+        format!("`{}`", segment.replace('\\', "\\\\").replace('`', "\\`"))
+    }
+}
+
+/// Builds a field path to an entry of a map field, escaping each of the map keys.
+///
+/// This can be used to update a single entry of a map without rewriting the whole map.
+///
+/// # Examples
+/// ```rust
+/// use firestore::firestore_map_key_field_path;
+///
+/// assert_eq!(
+///     firestore_map_key_field_path("settings", ["notifications", "e-mail"]),
+///     "settings.notifications.`e-mail`"
+/// );
+/// ```
+pub fn firestore_map_key_field_path<I>(field_path: &str, keys: I) -> String
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    keys.into_iter().fold(field_path.to_string(), |path, key| {
+        format!("{}.{}", path, firestore_field_path_escape(key.as_ref()))
+    })
+}
+
+/// Collects the field paths of all the values in the fields, descending into nested maps.
+///
+/// The paths are the update mask merging the fields into a document: only the present values
+/// are written and the other fields of the document (including the other entries of nested maps)
+/// are kept. Empty maps are written as a whole. The paths are escaped and sorted.
+///
+/// # Examples
+/// ```rust
+/// use firestore::firestore_merge_field_paths;
+/// use gcloud_sdk::google::firestore::v1::{value::ValueType, MapValue, Value};
+/// use std::collections::HashMap;
+///
+/// let string_value = |s: &str| Value {
+///     value_type: Some(ValueType::StringValue(s.to_string())),
+/// };
+/// let map_value = |fields: Vec<(&str, Value)>| Value {
+///     value_type: Some(ValueType::MapValue(MapValue {
+///         fields: fields
+///             .into_iter()
+///             .map(|(key, value)| (key.to_string(), value))
+///             .collect(),
+///     })),
+/// };
+///
+/// let fields: HashMap<String, Value> = [
+///     ("name".to_string(), string_value("Alice")),
+///     (
+///         "settings".to_string(),
+///         map_value(vec![("theme", string_value("dark")), ("e-mail", map_value(vec![]))]),
+///     ),
+/// ]
+/// .into_iter()
+/// .collect();
+///
+/// assert_eq!(
+///     firestore_merge_field_paths(&fields),
+///     vec!["name", "settings.`e-mail`", "settings.theme"]
+/// );
+/// ```
+pub fn firestore_merge_field_paths(
+    fields: &HashMap<String, gcloud_sdk::google::firestore::v1::Value>,
+) -> Vec<String> {
+    let mut field_paths = Vec::new();
+    collect_merge_field_paths(None, fields, &mut field_paths);
+    field_paths.sort();
+    field_paths
+}
+
+/// Internal helper function to recursively collect the field paths of the map values.
+fn collect_merge_field_paths(
+    parent_path: Option<&str>,
+    fields: &HashMap<String, gcloud_sdk::google::firestore::v1::Value>,
+    field_paths: &mut Vec<String>,
+) {
+    for (field_name, field_value) in fields {
+        let field_path = match parent_path {
+            Some(parent_path) => firestore_map_key_field_path(parent_path, [field_name]),
+            None => firestore_field_path_escape(field_name),
+        };
+        match field_value.value_type {
+            Some(gcloud_sdk::google::firestore::v1::value::ValueType::MapValue(ref map_value))
+                if !map_value.fields.is_empty() =>
+            {
+                collect_merge_field_paths(Some(&field_path), &map_value.fields, field_paths)
+            }
+            _ => field_paths.push(field_path),
+        }
+    }
+}
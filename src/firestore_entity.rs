@@ -0,0 +1,91 @@
+use crate::errors::*;
+use crate::timestamp_utils::to_timestamp;
+use crate::{
+    FirestoreResult, FirestoreValue, FirestoreValueTransformContext, FirestoreValueTransformer,
+    FirestoreValueTransformers,
+};
+use gcloud_sdk::google::firestore::v1::{value, Value};
+
+/// A type stored in a collection, with the document ID as one of its fields.
+///
+/// Entities can be written without specifying the collection and the document ID,
+/// e.g. `db.fluent().insert().entity(&user)`. The trait is usually implemented with
+/// `#[derive(FirestoreEntity)]` (the `derive` feature):
+///
+/// ```rust,ignore
+/// #[derive(Serialize, Deserialize, FirestoreEntity)]
+/// #[firestore(collection = "users", id = "user_id")]
+/// struct User {
+///     user_id: String,
+///     name: String,
+///     #[firestore(timestamp)]
+///     created_at: DateTime<Utc>,
+/// }
+/// ```
+pub trait FirestoreEntity {
+    /// The ID of the collection of the entities.
+    const COLLECTION_ID: &'static str;
+
+    /// The fields written by the entity operations as Firestore timestamps,
+    /// as with [`serialize_as_timestamp`](crate::serialize_as_timestamp).
+    const TIMESTAMP_FIELDS: &'static [&'static str] = &[];
+
+    /// The ID of the document of the entity.
+    fn document_id(&self) -> String;
+}
+
+/// Returns the transformers of the current scope extended to write the timestamp fields of an entity.
+pub(crate) fn firestore_entity_value_transformers(
+    collection_id: &str,
+    timestamp_fields: &[&str],
+) -> Option<FirestoreValueTransformers> {
+    if timestamp_fields.is_empty() {
+        None
+    } else {
+        Some(
+            FirestoreValueTransformers::current_override()
+                .unwrap_or_default()
+                .with_transformer(
+                    collection_id,
+                    timestamp_fields,
+                    FirestoreEntityTimestampTransformer,
+                ),
+        )
+    }
+}
+
+/// Converts the RFC 3339 strings of serialized `DateTime`s into timestamp values.
+/// Timestamps are read back into `DateTime`s by the deserializer as is.
+struct FirestoreEntityTimestampTransformer;
+
+impl FirestoreValueTransformer for FirestoreEntityTimestampTransformer {
+    fn on_serialize(
+        &self,
+        context: &FirestoreValueTransformContext<'_>,
+        value: FirestoreValue,
+    ) -> FirestoreResult<FirestoreValue> {
+        match value.value.value_type {
+            Some(value::ValueType::StringValue(ref str_value)) => {
+                let timestamp = chrono::DateTime::parse_from_rfc3339(str_value).map_err(|err| {
+                    FirestoreError::SerializeError(FirestoreSerializationError::from_message(
+                        format!("Field `{}` isn't a timestamp: {err}", context.field_path),
+                    ))
+                })?;
+                Ok(FirestoreValue::from(Value {
+                    value_type: Some(value::ValueType::TimestampValue(to_timestamp(
+                        timestamp.with_timezone(&chrono::Utc),
+                    ))),
+                }))
+            }
+            _ => Ok(value),
+        }
+    }
+
+    fn on_deserialize(
+        &self,
+        _context: &FirestoreValueTransformContext<'_>,
+        value: FirestoreValue,
+    ) -> FirestoreResult<FirestoreValue> {
+        Ok(value)
+    }
+}
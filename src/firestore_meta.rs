@@ -1,8 +1,10 @@
 use crate::errors::FirestoreError;
 use crate::timestamp_utils::{from_duration, from_timestamp};
-use crate::FirestoreTransactionId;
+use crate::{FirestoreConsistencySelector, FirestoreResult, FirestoreTransactionId};
 use chrono::{DateTime, Duration, Utc};
-use gcloud_sdk::google::firestore::v1::{Document, ExplainMetrics, RunQueryResponse};
+use gcloud_sdk::google::firestore::v1::{
+    Document, ExplainMetrics, RunAggregationQueryResponse, RunQueryResponse,
+};
 use gcloud_sdk::prost_types::value::Kind;
 use rsb_derive::Builder;
 use std::collections::BTreeMap;
@@ -41,6 +43,147 @@ pub struct FirestoreDocumentMetadata {
     pub explain_metrics: Option<FirestoreExplainMetrics>,
 }
 
+/// A document returned by a query together with its timestamps and the consistency of the read.
+///
+/// Unlike [`FirestoreWithMetadata`], it always contains a document. Query streams yield it
+/// within [`FirestoreDocWithMetaItem`], together with the read times of responses without
+/// documents, e.g. to track `read_time` as a watermark for incremental sync.
+///
+/// # Type Parameters
+/// * `T`: The type of the document. Typically `gcloud_sdk::google::firestore::v1::Document`
+///   or a user-defined struct after deserialization.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FirestoreDocWithMeta<T> {
+    /// The document itself.
+    pub document: T,
+    /// The full path of the document.
+    pub document_path: String,
+    /// The time at which the document was created.
+    pub create_time: Option<DateTime<Utc>>,
+    /// The time at which the document was last changed.
+    pub update_time: Option<DateTime<Utc>>,
+    /// The time at which the document was read.
+    pub read_time: Option<DateTime<Utc>>,
+    /// The transaction or the point in time the query was executed with, if any.
+    pub consistency_selector: Option<FirestoreConsistencySelector>,
+}
+
+impl FirestoreDocWithMeta<Document> {
+    /// Creates the document with metadata from a query response item.
+    /// Returns `None` for responses that don't contain a document.
+    pub fn from_metadata(
+        with_metadata: FirestoreWithMetadata<Document>,
+        consistency_selector: Option<FirestoreConsistencySelector>,
+    ) -> FirestoreResult<Option<Self>> {
+        with_metadata
+            .document
+            .map(|document| {
+                Ok(FirestoreDocWithMeta {
+                    document_path: document.name.clone(),
+                    create_time: document.create_time.map(from_timestamp).transpose()?,
+                    update_time: document.update_time.map(from_timestamp).transpose()?,
+                    read_time: with_metadata.metadata.read_time,
+                    consistency_selector,
+                    document,
+                })
+            })
+            .transpose()
+    }
+}
+
+/// An item of a query stream with document metadata.
+///
+/// Besides the documents, Firestore sends responses without documents, e.g. for queries
+/// without results. Their read time is still yielded, so a watermark can advance
+/// even when nothing matched.
+#[derive(Debug, PartialEq, Clone)]
+pub enum FirestoreDocWithMetaItem<T> {
+    /// A document matching the query.
+    Document(FirestoreDocWithMeta<T>),
+    /// The read time of a response without documents.
+    ReadTime {
+        /// The time at which the query was read.
+        read_time: DateTime<Utc>,
+        /// The transaction or the point in time the query was executed with, if any.
+        consistency_selector: Option<FirestoreConsistencySelector>,
+    },
+}
+
+impl FirestoreDocWithMetaItem<Document> {
+    /// Creates the item from a query response item.
+    /// Returns `None` for responses with neither a document nor a read time.
+    pub fn from_metadata(
+        with_metadata: FirestoreWithMetadata<Document>,
+        consistency_selector: Option<FirestoreConsistencySelector>,
+    ) -> FirestoreResult<Option<Self>> {
+        let read_time = with_metadata.metadata.read_time;
+        Ok(
+            match FirestoreDocWithMeta::from_metadata(with_metadata, consistency_selector.clone())?
+            {
+                Some(doc_with_meta) => Some(Self::Document(doc_with_meta)),
+                None => read_time.map(|read_time| Self::ReadTime {
+                    read_time,
+                    consistency_selector,
+                }),
+            },
+        )
+    }
+}
+
+impl<T> FirestoreDocWithMetaItem<T> {
+    /// Returns the read time of the item.
+    pub fn read_time(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Document(doc_with_meta) => doc_with_meta.read_time,
+            Self::ReadTime { read_time, .. } => Some(*read_time),
+        }
+    }
+
+    /// Returns the document, if the item contains one.
+    pub fn into_document(self) -> Option<FirestoreDocWithMeta<T>> {
+        match self {
+            Self::Document(doc_with_meta) => Some(doc_with_meta),
+            Self::ReadTime { .. } => None,
+        }
+    }
+
+    /// Converts the document (if any), keeping the metadata.
+    pub fn try_map<R, F>(self, f: F) -> FirestoreResult<FirestoreDocWithMetaItem<R>>
+    where
+        F: FnOnce(T) -> FirestoreResult<R>,
+    {
+        Ok(match self {
+            Self::Document(doc_with_meta) => {
+                FirestoreDocWithMetaItem::Document(doc_with_meta.try_map(f)?)
+            }
+            Self::ReadTime {
+                read_time,
+                consistency_selector,
+            } => FirestoreDocWithMetaItem::ReadTime {
+                read_time,
+                consistency_selector,
+            },
+        })
+    }
+}
+
+impl<T> FirestoreDocWithMeta<T> {
+    /// Converts the document, keeping the metadata.
+    pub fn try_map<R, F>(self, f: F) -> FirestoreResult<FirestoreDocWithMeta<R>>
+    where
+        F: FnOnce(T) -> FirestoreResult<R>,
+    {
+        Ok(FirestoreDocWithMeta {
+            document: f(self.document)?,
+            document_path: self.document_path,
+            create_time: self.create_time,
+            update_time: self.update_time,
+            read_time: self.read_time,
+            consistency_selector: self.consistency_selector,
+        })
+    }
+}
+
 /// Detailed metrics about query execution, if requested via [`FirestoreExplainOptions`](crate::FirestoreExplainOptions).
 ///
 /// This includes a summary of the query plan and statistics about the execution.
@@ -146,6 +289,31 @@ impl TryFrom<RunQueryResponse> for FirestoreWithMetadata<Document> {
     }
 }
 
+impl TryFrom<RunAggregationQueryResponse> for FirestoreWithMetadata<Document> {
+    type Error = FirestoreError;
+
+    fn try_from(value: RunAggregationQueryResponse) -> Result<Self, Self::Error> {
+        Ok(FirestoreWithMetadata {
+            document: value.result.map(|agg_res_doc| Document {
+                name: "".to_string(),
+                fields: agg_res_doc.aggregate_fields,
+                create_time: None,
+                update_time: None,
+            }),
+            metadata: FirestoreDocumentMetadata {
+                transaction_id: if !value.transaction.is_empty() {
+                    Some(value.transaction)
+                } else {
+                    None
+                },
+                read_time: value.read_time.map(from_timestamp).transpose()?,
+                skipped_results: 0,
+                explain_metrics: value.explain_metrics.map(|v| v.try_into()).transpose()?,
+            },
+        })
+    }
+}
+
 impl TryFrom<ExplainMetrics> for FirestoreExplainMetrics {
     type Error = FirestoreError;
 
@@ -189,3 +357,63 @@ impl TryFrom<gcloud_sdk::google::firestore::v1::ExecutionStats> for FirestoreExe
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestamp_utils::to_timestamp;
+
+    #[test]
+    fn doc_with_meta_from_query_response() {
+        let create_time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let read_time = DateTime::from_timestamp(1_700_000_100, 0).unwrap();
+
+        let with_metadata = FirestoreWithMetadata {
+            document: Some(Document {
+                name: "projects/test/databases/(default)/documents/test/doc-1".to_string(),
+                fields: Default::default(),
+                create_time: Some(to_timestamp(create_time)),
+                update_time: Some(to_timestamp(create_time)),
+            }),
+            metadata: FirestoreDocumentMetadata::new(0).with_read_time(read_time),
+        };
+
+        let doc_with_meta = FirestoreDocWithMeta::from_metadata(
+            with_metadata,
+            Some(FirestoreConsistencySelector::ReadTime(read_time)),
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(doc_with_meta.create_time, Some(create_time));
+        assert_eq!(doc_with_meta.read_time, Some(read_time));
+        assert!(doc_with_meta.document_path.ends_with("/test/doc-1"));
+        assert_eq!(
+            doc_with_meta.consistency_selector,
+            Some(FirestoreConsistencySelector::ReadTime(read_time))
+        );
+
+        let empty = FirestoreWithMetadata {
+            document: None,
+            metadata: FirestoreDocumentMetadata::new(0).with_read_time(read_time),
+        };
+        assert!(FirestoreDocWithMeta::from_metadata(empty.clone(), None)
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            FirestoreDocWithMetaItem::from_metadata(empty, None).unwrap(),
+            Some(FirestoreDocWithMetaItem::ReadTime {
+                read_time,
+                consistency_selector: None
+            })
+        );
+
+        let no_read_time = FirestoreWithMetadata {
+            document: None,
+            metadata: FirestoreDocumentMetadata::new(0),
+        };
+        assert!(FirestoreDocWithMetaItem::from_metadata(no_read_time, None)
+            .unwrap()
+            .is_none());
+    }
+}
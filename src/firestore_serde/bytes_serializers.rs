@@ -0,0 +1,217 @@
+use base64::Engine;
+use serde::de::{SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::Formatter;
+
+/// Binary data stored as a native Firestore bytes value instead of an array of integers.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, PartialOrd, Ord, Default)]
+pub struct FirestoreBytes(pub Vec<u8>);
+
+impl FirestoreBytes {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        FirestoreBytes(bytes)
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl From<Vec<u8>> for FirestoreBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        FirestoreBytes(bytes)
+    }
+}
+
+impl From<&[u8]> for FirestoreBytes {
+    fn from(bytes: &[u8]) -> Self {
+        FirestoreBytes(bytes.to_vec())
+    }
+}
+
+impl AsRef<[u8]> for FirestoreBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Serialize for FirestoreBytes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for FirestoreBytes {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer
+            .deserialize_byte_buf(FirestoreBytesVisitor)
+            .map(FirestoreBytes)
+    }
+}
+
+/// Reads native bytes values, base64 strings (the REST/JSON representation of bytes values)
+/// and arrays of integers (how binary fields were stored without this support).
+struct FirestoreBytesVisitor;
+
+impl<'de> Visitor<'de> for FirestoreBytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str("bytes, a base64 string or an array of bytes")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(v.to_vec())
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(v)
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        base64::engine::general_purpose::STANDARD
+            .decode(v)
+            .map_err(|err| E::custom(format!("Invalid base64 bytes value: {err}")))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element::<u8>()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+/// Serializes `Vec<u8>`, `bytes::Bytes` or any other `AsRef<[u8]>` type as a native Firestore bytes value.
+pub mod serialize_as_bytes {
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<T, S>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: AsRef<[u8]>,
+        S: Serializer,
+    {
+        serializer.serialize_bytes(bytes.as_ref())
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: From<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        deserializer
+            .deserialize_byte_buf(super::FirestoreBytesVisitor)
+            .map(T::from)
+    }
+}
+
+pub mod serialize_as_optional_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<T, S>(bytes: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: AsRef<[u8]>,
+        S: Serializer,
+    {
+        match bytes {
+            Some(v) => serializer.serialize_bytes(v.as_ref()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: From<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        Option::<super::FirestoreBytes>::deserialize(deserializer)
+            .map(|bytes| bytes.map(|v| T::from(v.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{firestore_document_from_serializable, firestore_document_to_serializable};
+    use gcloud_sdk::google::firestore::v1::value;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Attachment {
+        #[serde(with = "crate::serialize_as_bytes")]
+        payload: Vec<u8>,
+        #[serde(default, with = "crate::serialize_as_optional_bytes")]
+        thumbnail: Option<Vec<u8>>,
+        checksum: FirestoreBytes,
+    }
+
+    #[test]
+    fn test_bytes_serialization() {
+        let attachment = Attachment {
+            payload: vec![0, 1, 255],
+            thumbnail: None,
+            checksum: FirestoreBytes::new(vec![42]),
+        };
+
+        let doc = firestore_document_from_serializable("attachments/a1", &attachment).unwrap();
+        assert_eq!(
+            doc.fields.get("payload").unwrap().value_type,
+            Some(value::ValueType::BytesValue(vec![0, 1, 255]))
+        );
+        assert_eq!(
+            doc.fields.get("checksum").unwrap().value_type,
+            Some(value::ValueType::BytesValue(vec![42]))
+        );
+        assert!(!doc.fields.contains_key("thumbnail"));
+
+        let restored: Attachment = firestore_document_to_serializable(&doc).unwrap();
+        assert_eq!(restored, attachment);
+    }
+
+    #[test]
+    fn test_bytes_deserialization_from_legacy_values() {
+        #[derive(Serialize)]
+        struct Legacy {
+            payload: Vec<u8>,
+            thumbnail: String,
+            checksum: Vec<u8>,
+        }
+
+        let doc = firestore_document_from_serializable(
+            "attachments/a1",
+            &Legacy {
+                payload: vec![0, 1, 255],
+                thumbnail: "AAH/".to_string(),
+                checksum: vec![42],
+            },
+        )
+        .unwrap();
+
+        let restored: Attachment = firestore_document_to_serializable(&doc).unwrap();
+        assert_eq!(restored.payload, vec![0, 1, 255]);
+        assert_eq!(restored.thumbnail, Some(vec![0, 1, 255]));
+        assert_eq!(restored.checksum, FirestoreBytes::new(vec![42]));
+    }
+}
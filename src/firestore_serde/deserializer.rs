@@ -1,11 +1,22 @@
-use crate::errors::FirestoreSerializationError;
+use crate::errors::{
+    FirestoreNumericOverflow, FirestoreSerializationError, FirestoreSerializationFieldDetails,
+};
+use crate::firestore_serde::document_metadata::{
+    FIRESTORE_CREATED_FIELD, FIRESTORE_FULL_ID_FIELD, FIRESTORE_ID_FIELD, FIRESTORE_UPDATED_FIELD,
+};
+use crate::firestore_serde::value_transformers::{
+    firestore_transform_document_fields, FirestoreValueTransformDirection,
+};
 use crate::timestamp_utils::from_timestamp;
-use crate::{FirestoreError, FirestoreValue};
+use crate::{
+    FirestoreError, FirestoreNumericOverflowPolicy, FirestoreSerializerOptions, FirestoreValue,
+};
 use gcloud_sdk::google::firestore::v1::value;
 use serde::de::{DeserializeSeed, Visitor};
 use serde::Deserialize;
 use std::collections::HashMap;
-use std::fmt::Formatter;
+use std::fmt::{Display, Formatter};
+use std::num::IntErrorKind;
 
 impl<'de> Deserialize<'de> for FirestoreValue {
     #[inline]
@@ -141,8 +152,57 @@ impl<'de> Deserialize<'de> for FirestoreValue {
     }
 }
 
+enum FirestoreFieldPathSegment<'a> {
+    Field(&'a str),
+    Index(usize),
+}
+
+fn firestore_value_type_name(value: &FirestoreValue) -> &'static str {
+    match value.value.value_type {
+        Some(value::ValueType::NullValue(_)) | None => "null",
+        Some(value::ValueType::BooleanValue(_)) => "boolean",
+        Some(value::ValueType::IntegerValue(_)) => "integer",
+        Some(value::ValueType::DoubleValue(_)) => "double",
+        Some(value::ValueType::TimestampValue(_)) => "timestamp",
+        Some(value::ValueType::StringValue(_)) => "string",
+        Some(value::ValueType::BytesValue(_)) => "bytes",
+        Some(value::ValueType::ReferenceValue(_)) => "reference",
+        Some(value::ValueType::GeoPointValue(_)) => "geo point",
+        Some(value::ValueType::ArrayValue(_)) => "array",
+        Some(value::ValueType::MapValue(_)) => "map",
+    }
+}
+
+// Prepends the field or array index the error occurred in to the field path of the error,
+// so nested errors end up with the full path, e.g. `items[2].price`.
+fn with_field_path_segment(
+    err: FirestoreError,
+    segment: FirestoreFieldPathSegment,
+    value_type: &'static str,
+) -> FirestoreError {
+    match err {
+        FirestoreError::DeserializeError(mut e) => {
+            let segment = match segment {
+                FirestoreFieldPathSegment::Field(field) => field.to_string(),
+                FirestoreFieldPathSegment::Index(index) => format!("[{index}]"),
+            };
+            let details = e.field_details_mut();
+            details.field_path = Some(match details.field_path.take() {
+                Some(path) if path.starts_with('[') => format!("{segment}{path}"),
+                Some(path) => format!("{segment}.{path}"),
+                None => segment,
+            });
+            if details.expected_type.is_some() && details.actual_value_type.is_none() {
+                details.actual_value_type = Some(value_type.to_string());
+            }
+            FirestoreError::DeserializeError(e)
+        }
+        _ => err,
+    }
+}
+
 struct FirestoreValueSeqAccess {
-    iter: std::vec::IntoIter<FirestoreValue>,
+    iter: std::iter::Enumerate<std::vec::IntoIter<FirestoreValue>>,
 }
 
 impl FirestoreValueSeqAccess {
@@ -152,7 +212,8 @@ impl FirestoreValueSeqAccess {
                 .into_iter()
                 .map(FirestoreValue::from)
                 .collect::<Vec<FirestoreValue>>()
-                .into_iter(),
+                .into_iter()
+                .enumerate(),
         }
     }
 }
@@ -165,7 +226,16 @@ impl<'de> serde::de::SeqAccess<'de> for FirestoreValueSeqAccess {
         T: DeserializeSeed<'de>,
     {
         match self.iter.next() {
-            Some(value) => seed.deserialize(value).map(Some),
+            Some((index, value)) => {
+                let value_type = firestore_value_type_name(&value);
+                seed.deserialize(value).map(Some).map_err(|err| {
+                    with_field_path_segment(
+                        err,
+                        FirestoreFieldPathSegment::Index(index),
+                        value_type,
+                    )
+                })
+            }
             None => Ok(None),
         }
     }
@@ -180,7 +250,7 @@ impl<'de> serde::de::SeqAccess<'de> for FirestoreValueSeqAccess {
 
 struct FirestoreValueMapAccess {
     iter: <HashMap<String, FirestoreValue> as IntoIterator>::IntoIter,
-    value: Option<FirestoreValue>,
+    value: Option<(String, FirestoreValue)>,
 }
 
 impl FirestoreValueMapAccess {
@@ -205,7 +275,7 @@ impl<'de> serde::de::MapAccess<'de> for FirestoreValueMapAccess {
     {
         match self.iter.next() {
             Some((key, value)) => {
-                self.value = Some(value);
+                self.value = Some((key.clone(), value));
                 seed.deserialize(FirestoreValue::from(
                     gcloud_sdk::google::firestore::v1::Value {
                         value_type: Some(value::ValueType::StringValue(key)),
@@ -222,7 +292,12 @@ impl<'de> serde::de::MapAccess<'de> for FirestoreValueMapAccess {
         T: DeserializeSeed<'de>,
     {
         match self.value.take() {
-            Some(value) => seed.deserialize(value),
+            Some((key, value)) => {
+                let value_type = firestore_value_type_name(&value);
+                seed.deserialize(value).map_err(|err| {
+                    with_field_path_segment(err, FirestoreFieldPathSegment::Field(&key), value_type)
+                })
+            }
             None => Err(serde::de::Error::custom("value is missing")),
         }
     }
@@ -336,6 +411,139 @@ impl<'de> serde::de::VariantAccess<'de> for FirestoreValue {
     }
 }
 
+tokio::task_local! {
+    static FIRESTORE_INTEGRAL_DOUBLES_AS_INTEGERS: ();
+}
+
+/// Deserializes aggregation results, accepting doubles without a fractional part for integer fields,
+/// since Firestore may return them for integer-like results (e.g. `sum()` over double fields).
+pub(crate) fn firestore_aggregation_result_scope<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    FIRESTORE_INTEGRAL_DOUBLES_AS_INTEGERS.sync_scope((), f)
+}
+
+/// Checks if a double has no fractional part and fits into the 64-bit integer range.
+fn integral_f64(v: f64) -> bool {
+    v.is_finite() && v.fract() == 0.0 && v >= i64::MIN as f64 && v <= u64::MAX as f64
+}
+
+/// An integer read from any of the [`FirestoreLargeIntegerFormat`](crate::FirestoreLargeIntegerFormat)s.
+enum FirestoreLargeInteger {
+    Negative(i128),
+    NonNegative(u128),
+    /// A number out of the `i128` and `u128` ranges, formatted as a decimal string.
+    OutOfRange {
+        value: String,
+        negative: bool,
+    },
+}
+
+/// Handles a number that doesn't fit into the target type according to the overflow policy of the options.
+fn numeric_overflow<T, N>(
+    value: N,
+    negative: bool,
+    min: T,
+    max: T,
+    target_type: &str,
+) -> Result<T, FirestoreError>
+where
+    N: Display,
+{
+    match FirestoreSerializerOptions::current().numeric_overflow {
+        FirestoreNumericOverflowPolicy::Saturate if negative => Ok(min),
+        FirestoreNumericOverflowPolicy::Saturate => Ok(max),
+        FirestoreNumericOverflowPolicy::Error | FirestoreNumericOverflowPolicy::Wrap => {
+            Err(FirestoreError::DeserializeError(
+                FirestoreNumericOverflow::new(value.to_string(), target_type.to_string())
+                    .into_serialization_error(),
+            ))
+        }
+    }
+}
+
+impl FirestoreValue {
+    /// Reads an integer written as an integer, a decimal string or a double without a fractional part.
+    fn large_integer(&self, target_type: &str) -> Result<FirestoreLargeInteger, FirestoreError> {
+        match self.value.value_type {
+            Some(value::ValueType::IntegerValue(v)) if v < 0 => {
+                Ok(FirestoreLargeInteger::Negative(v.into()))
+            }
+            Some(value::ValueType::IntegerValue(v)) => {
+                Ok(FirestoreLargeInteger::NonNegative(v as u128))
+            }
+            Some(value::ValueType::DoubleValue(v)) if v.is_finite() && v.fract() == 0.0 => {
+                if v >= u128::MAX as f64 || v < i128::MIN as f64 {
+                    Ok(FirestoreLargeInteger::OutOfRange {
+                        value: format!("{v}"),
+                        negative: v < 0.0,
+                    })
+                } else if v < 0.0 {
+                    Ok(FirestoreLargeInteger::Negative(v as i128))
+                } else {
+                    Ok(FirestoreLargeInteger::NonNegative(v as u128))
+                }
+            }
+            Some(value::ValueType::StringValue(ref v)) => {
+                let parsed = if v.starts_with('-') {
+                    v.parse::<i128>().map(FirestoreLargeInteger::Negative)
+                } else {
+                    v.parse::<u128>().map(FirestoreLargeInteger::NonNegative)
+                };
+                parsed.or_else(|err| match err.kind() {
+                    IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+                        Ok(FirestoreLargeInteger::OutOfRange {
+                            value: v.clone(),
+                            negative: v.starts_with('-'),
+                        })
+                    }
+                    _ => Err(FirestoreError::DeserializeError(
+                        FirestoreSerializationError::from_message(format!(
+                            "invalid value: string {v:?}, expected {target_type}"
+                        ))
+                        .with_field_details(Box::new(
+                            FirestoreSerializationFieldDetails::new()
+                                .with_expected_type(target_type.to_string()),
+                        )),
+                    )),
+                })
+            }
+            _ => Err(FirestoreError::DeserializeError(
+                FirestoreSerializationError::from_message(format!(
+                    "Unexpected field type for {target_type} deserialization"
+                ))
+                .with_field_details(Box::new(
+                    FirestoreSerializationFieldDetails::new()
+                        .with_expected_type(target_type.to_string()),
+                )),
+            )),
+        }
+    }
+
+    /// Deserializes a value into an integer type.
+    ///
+    /// Doubles without a fractional part are accepted as well for aggregation results
+    /// (see [`firestore_aggregation_result_scope`]).
+    fn deserialize_integer<'de, V>(self, visitor: V) -> Result<V::Value, FirestoreError>
+    where
+        V: Visitor<'de>,
+    {
+        match self.value.value_type {
+            Some(value::ValueType::DoubleValue(v))
+                if integral_f64(v)
+                    && v < i64::MAX as f64
+                    && FIRESTORE_INTEGRAL_DOUBLES_AS_INTEGERS
+                        .try_with(|_| ())
+                        .is_ok() =>
+            {
+                visitor.visit_i64(v as i64)
+            }
+            _ => serde::Deserializer::deserialize_any(self, visitor),
+        }
+    }
+}
+
 impl<'de> serde::Deserializer<'de> for FirestoreValue {
     type Error = FirestoreError;
 
@@ -391,64 +599,109 @@ impl<'de> serde::Deserializer<'de> for FirestoreValue {
     where
         V: Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        self.deserialize_integer(visitor)
     }
 
     fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        self.deserialize_integer(visitor)
     }
 
     fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        self.deserialize_integer(visitor)
     }
 
     fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        self.deserialize_integer(visitor)
     }
 
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        self.deserialize_integer(visitor)
     }
 
     fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        self.deserialize_integer(visitor)
     }
 
     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        self.deserialize_integer(visitor)
     }
 
     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        match self.value.value_type {
-            Some(value::ValueType::IntegerValue(v)) => visitor.visit_u64(v as u64),
+        let large_integer = match self.value.value_type {
+            // Written by wrapping, the format of the previous versions of the library
+            Some(value::ValueType::IntegerValue(v))
+                if v < 0
+                    && FirestoreSerializerOptions::current().numeric_overflow
+                        != FirestoreNumericOverflowPolicy::Saturate =>
+            {
+                return visitor.visit_u64(v as u64);
+            }
+            _ => self.large_integer("u64")?,
+        };
+        visitor.visit_u64(match large_integer {
+            FirestoreLargeInteger::NonNegative(v) => match u64::try_from(v) {
+                Ok(v) => v,
+                Err(_) => numeric_overflow(v, false, u64::MIN, u64::MAX, "u64")?,
+            },
+            FirestoreLargeInteger::Negative(v) => {
+                numeric_overflow(v, true, u64::MIN, u64::MAX, "u64")?
+            }
+            FirestoreLargeInteger::OutOfRange { value, negative } => {
+                numeric_overflow(value, negative, u64::MIN, u64::MAX, "u64")?
+            }
+        })
+    }
 
-            _ => Err(FirestoreError::DeserializeError(
-                FirestoreSerializationError::from_message(
-                    "Unexpected field type for u64 deserialization",
-                ),
-            )),
-        }
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i128(match self.large_integer("i128")? {
+            FirestoreLargeInteger::Negative(v) => v,
+            FirestoreLargeInteger::NonNegative(v) => match i128::try_from(v) {
+                Ok(v) => v,
+                Err(_) => numeric_overflow(v, false, i128::MIN, i128::MAX, "i128")?,
+            },
+            FirestoreLargeInteger::OutOfRange { value, negative } => {
+                numeric_overflow(value, negative, i128::MIN, i128::MAX, "i128")?
+            }
+        })
+    }
+
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u128(match self.large_integer("u128")? {
+            FirestoreLargeInteger::NonNegative(v) => v,
+            FirestoreLargeInteger::Negative(v) => {
+                numeric_overflow(v, true, u128::MIN, u128::MAX, "u128")?
+            }
+            FirestoreLargeInteger::OutOfRange { value, negative } => {
+                numeric_overflow(value, negative, u128::MIN, u128::MAX, "u128")?
+            }
+        })
     }
 
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -627,6 +880,13 @@ where
 
     let doc_name = document.name.clone();
 
+    firestore_transform_document_fields(
+        &mut fields,
+        &doc_name,
+        None,
+        FirestoreValueTransformDirection::Deserialize,
+    )?;
+
     let doc_id = doc_name
         .split('/')
         .next_back()
@@ -634,14 +894,14 @@ where
         .unwrap_or_else(|| doc_name.clone());
 
     fields.insert(
-        "_firestore_id".to_string(),
+        FIRESTORE_ID_FIELD.to_string(),
         gcloud_sdk::google::firestore::v1::Value {
             value_type: Some(value::ValueType::StringValue(doc_id)),
         },
     );
 
     fields.insert(
-        "_firestore_full_id".to_string(),
+        FIRESTORE_FULL_ID_FIELD.to_string(),
         gcloud_sdk::google::firestore::v1::Value {
             value_type: Some(value::ValueType::StringValue(doc_name.clone())),
         },
@@ -649,7 +909,7 @@ where
 
     if let Some(created_time) = &document.create_time {
         fields.insert(
-            "_firestore_created".to_string(),
+            FIRESTORE_CREATED_FIELD.to_string(),
             gcloud_sdk::google::firestore::v1::Value {
                 value_type: Some(value::ValueType::TimestampValue(*created_time)),
             },
@@ -658,7 +918,7 @@ where
 
     if let Some(updated_time) = &document.update_time {
         fields.insert(
-            "_firestore_updated".to_string(),
+            FIRESTORE_UPDATED_FIELD.to_string(),
             gcloud_sdk::google::firestore::v1::Value {
                 value_type: Some(value::ValueType::TimestampValue(*updated_time)),
             },
@@ -678,3 +938,124 @@ where
         _ => err,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::firestore_document_from_serializable;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum StoredPrice {
+        Amount(u32),
+        Text(String),
+    }
+
+    #[derive(Serialize)]
+    struct StoredItem {
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        price: Option<StoredPrice>,
+    }
+
+    #[derive(Serialize)]
+    struct StoredOrder {
+        id: String,
+        items: Vec<StoredItem>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[allow(dead_code)]
+    struct OrderItem {
+        name: String,
+        price: u32,
+    }
+
+    #[derive(Deserialize, Debug)]
+    #[allow(dead_code)]
+    struct Order {
+        id: String,
+        items: Vec<OrderItem>,
+    }
+
+    fn deserialize_error(doc: &gcloud_sdk::google::firestore::v1::Document) -> FirestoreError {
+        firestore_document_to_serializable::<Order>(doc).unwrap_err()
+    }
+
+    #[test]
+    fn deserialize_errors_with_field_paths() {
+        let item = |name: &str, price: Option<StoredPrice>| StoredItem {
+            name: name.to_string(),
+            price,
+        };
+        let order = StoredOrder {
+            id: "order-1".to_string(),
+            items: vec![
+                item("a", Some(StoredPrice::Amount(10))),
+                item("b", Some(StoredPrice::Text("ten".to_string()))),
+            ],
+        };
+        let doc = firestore_document_from_serializable("orders/order-1", &order).unwrap();
+
+        match deserialize_error(&doc) {
+            FirestoreError::DeserializeError(err) => {
+                assert_eq!(err.document_path.as_deref(), Some("orders/order-1"));
+                let details = err.field_details.unwrap();
+                assert_eq!(details.field_path.as_deref(), Some("items[1].price"));
+                assert_eq!(details.expected_type.as_deref(), Some("u32"));
+                assert_eq!(details.actual_value_type.as_deref(), Some("string"));
+            }
+            err => panic!("Unexpected error: {err}"),
+        }
+
+        let missing = StoredOrder {
+            id: "order-2".to_string(),
+            items: vec![item("a", None)],
+        };
+        let doc = firestore_document_from_serializable("orders/order-2", &missing).unwrap();
+        match deserialize_error(&doc) {
+            FirestoreError::DeserializeError(err) => {
+                let details = err.field_details.unwrap();
+                assert_eq!(details.field_path.as_deref(), Some("items[0].price"));
+                assert_eq!(details.actual_value_type, None);
+            }
+            err => panic!("Unexpected error: {err}"),
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Note {
+        #[serde(rename = "_firestore_id")]
+        id: String,
+        #[serde(rename = "_firestore_created")]
+        created_at: Option<chrono::DateTime<chrono::Utc>>,
+        #[serde(rename = "_firestore_updated")]
+        updated_at: chrono::DateTime<chrono::Utc>,
+        text: String,
+    }
+
+    #[test]
+    fn deserialize_document_metadata_fields() {
+        let note = Note {
+            id: "n1".to_string(),
+            created_at: Some(chrono::Utc::now()),
+            updated_at: chrono::Utc::now(),
+            text: "hello".to_string(),
+        };
+        let mut doc = firestore_document_from_serializable("notes/n1", &note).unwrap();
+        assert_eq!(doc.fields.keys().collect::<Vec<_>>(), vec!["text"]);
+
+        let update_time = gcloud_sdk::prost_types::Timestamp {
+            seconds: 1_700_000_000,
+            nanos: 0,
+        };
+        doc.update_time = Some(update_time);
+
+        let restored: Note = firestore_document_to_serializable(&doc).unwrap();
+        assert_eq!(restored.id, "n1");
+        assert_eq!(restored.created_at, None);
+        assert_eq!(restored.updated_at, from_timestamp(update_time).unwrap());
+        assert_eq!(restored.text, "hello");
+    }
+}
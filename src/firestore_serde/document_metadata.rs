@@ -0,0 +1,16 @@
+pub(crate) const FIRESTORE_ID_FIELD: &str = "_firestore_id";
+pub(crate) const FIRESTORE_FULL_ID_FIELD: &str = "_firestore_full_id";
+pub(crate) const FIRESTORE_CREATED_FIELD: &str = "_firestore_created";
+pub(crate) const FIRESTORE_UPDATED_FIELD: &str = "_firestore_updated";
+
+/// Checks if the field is one of the reserved fields filled from the document metadata
+/// on deserialization. These fields are never written to the documents.
+pub(crate) fn is_firestore_metadata_field(field: &str) -> bool {
+    matches!(
+        field,
+        FIRESTORE_ID_FIELD
+            | FIRESTORE_FULL_ID_FIELD
+            | FIRESTORE_CREATED_FIELD
+            | FIRESTORE_UPDATED_FIELD
+    )
+}
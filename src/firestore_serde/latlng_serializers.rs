@@ -17,6 +17,87 @@ pub struct FirestoreGeoPoint {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, PartialOrd, Default)]
 pub struct FirestoreLatLng(pub FirestoreGeoPoint);
 
+impl FirestoreGeoPoint {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        FirestoreGeoPoint {
+            latitude,
+            longitude,
+        }
+    }
+}
+
+impl From<FirestoreGeoPoint> for FirestoreLatLng {
+    fn from(point: FirestoreGeoPoint) -> Self {
+        FirestoreLatLng(point)
+    }
+}
+
+impl From<gcloud_sdk::google::r#type::LatLng> for FirestoreGeoPoint {
+    fn from(lat_lng: gcloud_sdk::google::r#type::LatLng) -> Self {
+        FirestoreGeoPoint::new(lat_lng.latitude, lat_lng.longitude)
+    }
+}
+
+impl From<FirestoreGeoPoint> for gcloud_sdk::google::r#type::LatLng {
+    fn from(point: FirestoreGeoPoint) -> Self {
+        gcloud_sdk::google::r#type::LatLng {
+            latitude: point.latitude,
+            longitude: point.longitude,
+        }
+    }
+}
+
+/// Serializes a struct with `latitude` and `longitude` fields (such as [`FirestoreGeoPoint`])
+/// as a native Firestore GeoPoint value instead of a map.
+pub mod serialize_as_geopoint {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T, S>(point: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize,
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(
+            crate::firestore_serde::FIRESTORE_LATLNG_TYPE_TAG_TYPE,
+            point,
+        )
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        T::deserialize(deserializer)
+    }
+}
+
+pub mod serialize_as_optional_geopoint {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T, S>(point: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize,
+        S: Serializer,
+    {
+        match point {
+            Some(v) => serializer.serialize_newtype_struct(
+                crate::firestore_serde::FIRESTORE_LATLNG_TYPE_TAG_TYPE,
+                v,
+            ),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Option::<T>::deserialize(deserializer)
+    }
+}
+
 pub fn serialize_latlng_for_firestore<T: ?Sized + Serialize>(
     value: &T,
 ) -> Result<FirestoreValue, FirestoreError> {
@@ -35,9 +116,7 @@ pub fn serialize_latlng_for_firestore<T: ?Sized + Serialize>(
             key: &'static str,
             value: &T,
         ) -> Result<(), Self::Error> {
-            let serializer = FirestoreValueSerializer {
-                none_as_null: false,
-            };
+            let serializer = FirestoreValueSerializer::new();
             let serialized_value = value.serialize(serializer)?.value;
             if serialized_value.value_type.is_some() {
                 self.fields.insert(key.to_string(), serialized_value);
@@ -328,3 +407,40 @@ pub fn serialize_latlng_for_firestore<T: ?Sized + Serialize>(
 
     value.serialize(LatLngSerializer {})
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{firestore_document_from_serializable, firestore_document_to_serializable};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Place {
+        #[serde(with = "crate::serialize_as_geopoint")]
+        location: FirestoreGeoPoint,
+        #[serde(default, with = "crate::serialize_as_optional_geopoint")]
+        entrance: Option<FirestoreGeoPoint>,
+    }
+
+    #[test]
+    fn test_geopoint_serialization() {
+        let place = Place {
+            location: FirestoreGeoPoint::new(51.5, -0.12),
+            entrance: None,
+        };
+
+        let doc = firestore_document_from_serializable("places/london", &place).unwrap();
+        assert_eq!(
+            doc.fields.get("location").unwrap().value_type,
+            Some(value::ValueType::GeoPointValue(
+                gcloud_sdk::google::r#type::LatLng {
+                    latitude: 51.5,
+                    longitude: -0.12,
+                }
+            ))
+        );
+        assert!(!doc.fields.contains_key("entrance"));
+
+        let restored: Place = firestore_document_to_serializable(&doc).unwrap();
+        assert_eq!(restored, place);
+    }
+}
@@ -26,11 +26,33 @@
 mod deserializer;
 mod serializer;
 
+/// Internal module with the names of the document metadata fields (e.g. `_firestore_id`),
+/// which are filled in when reading documents and skipped when writing them.
+mod document_metadata;
+
+/// Internal module collecting the fields a type requires, to validate projections of partial reads.
+mod required_fields;
+
+/// Provides options controlling the serialization of objects to Firestore documents,
+/// e.g. writing `None` values as `null`s.
+mod serializer_options;
+pub use serializer_options::*;
+
+/// Provides interceptors transforming field values during serialization and deserialization,
+/// e.g. to encrypt sensitive fields client-side.
+mod value_transformers;
+pub use value_transformers::*;
+
 /// Provides `#[serde(with = "...")]` serializers and deserializers for Firestore Timestamps
 /// (converting between `chrono::DateTime<Utc>` and `google::protobuf::Timestamp`).
 mod timestamp_serializers;
 pub use timestamp_serializers::*;
 
+/// Provides `#[serde(with = "...")]` serializers and deserializers for the expiry fields
+/// of Firestore TTL policies.
+mod ttl_serializers;
+pub use ttl_serializers::*;
+
 /// Provides `#[serde(with = "...")]` serializers and deserializers for Firestore Null values,
 /// particularly for handling `Option<T>` where `None` maps to a NullValue.
 mod null_serializers;
@@ -51,12 +73,36 @@ pub use reference_serializers::*;
 mod vector_serializers;
 pub use vector_serializers::*;
 
+/// Provides `#[serde(with = "...")]` serializers and deserializers for Firestore bytes values
+/// (converting between `Vec<u8>`-like types or `FirestoreBytes` and native bytes values).
+mod bytes_serializers;
+pub use bytes_serializers::*;
+
+/// Provides `#[serde(with = "...")]` serializers and deserializers keeping the Firestore types
+/// of [`FirestoreValue`]s, e.g. to store query definitions as JSON.
+mod typed_value_serializers;
+pub use typed_value_serializers::*;
+
+/// Provides `#[serde(with = "...")]` serializers and deserializers writing numbers as decimal strings
+/// to keep their precision, e.g. for `rust_decimal::Decimal` or `i128`.
+mod numeric_serializers;
+pub use numeric_serializers::*;
+
+/// Provides conversions between Firestore protobuf messages and their proto3 JSON representation,
+/// e.g. for structured queries, bundles and exports.
+pub(crate) mod proto_json;
+pub use proto_json::{firestore_structured_query_from_json, firestore_structured_query_to_json};
+
 use crate::FirestoreValue;
 use gcloud_sdk::google::firestore::v1::Value;
 
+pub(crate) use deserializer::firestore_aggregation_result_scope;
 pub use deserializer::firestore_document_to_serializable;
+pub(crate) use document_metadata::is_firestore_metadata_field;
+pub(crate) use required_fields::firestore_required_fields;
 pub use serializer::firestore_document_from_map;
 pub use serializer::firestore_document_from_serializable;
+pub(crate) use serializer::firestore_new_document_from_serializable;
 
 /// Generic conversion from any `serde::Serialize` type into a [`FirestoreValue`].
 ///
@@ -0,0 +1,292 @@
+use serde::de::{Error, Visitor};
+use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+/// Parses numbers written as strings, integers or doubles using their `FromStr` implementation,
+/// so the fields written as numbers before can still be read after switching to strings.
+struct FirestoreNumericStringVisitor<T>(PhantomData<T>);
+
+impl<T> FirestoreNumericStringVisitor<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    fn parse<E: Error>(value: &str) -> Result<T, E> {
+        value
+            .parse()
+            .map_err(|err| E::custom(format!("invalid number {value:?}: {err}")))
+    }
+}
+
+impl<'de, T> Visitor<'de> for FirestoreNumericStringVisitor<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str("a number or a string with a number")
+    }
+
+    fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+        Self::parse(v)
+    }
+
+    fn visit_i64<E: Error>(self, v: i64) -> Result<Self::Value, E> {
+        Self::parse(&v.to_string())
+    }
+
+    fn visit_u64<E: Error>(self, v: u64) -> Result<Self::Value, E> {
+        Self::parse(&v.to_string())
+    }
+
+    fn visit_f64<E: Error>(self, v: f64) -> Result<Self::Value, E> {
+        Self::parse(&v.to_string())
+    }
+}
+
+/// Serializes numbers that don't fit into Firestore integers and doubles without losing precision,
+/// e.g. `rust_decimal::Decimal`, `i128` or big integers, as decimal strings.
+/// Fields written as integers or doubles are accepted when reading documents.
+pub mod serialize_as_string {
+    use serde::{Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::marker::PhantomData;
+    use std::str::FromStr;
+
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Display,
+        S: Serializer,
+    {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(super::FirestoreNumericStringVisitor(PhantomData))
+    }
+}
+
+pub mod serialize_as_optional_string {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    /// Wraps the deserialization of the values to use it with `Option::deserialize`.
+    struct FirestoreNumericString<T>(T);
+
+    impl<'de, T> Deserialize<'de> for FirestoreNumericString<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            super::serialize_as_string::deserialize(deserializer).map(FirestoreNumericString)
+        }
+    }
+
+    pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Display,
+        S: Serializer,
+    {
+        match value {
+            Some(v) => serializer.collect_str(v),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        Option::<FirestoreNumericString<T>>::deserialize(deserializer)
+            .map(|value| value.map(|v| v.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::FirestoreNumericOverflow;
+    use crate::{
+        firestore_document_from_serializable, firestore_document_to_serializable, FirestoreError,
+        FirestoreLargeIntegerFormat, FirestoreNumericOverflowPolicy, FirestoreSerializerOptions,
+    };
+    use gcloud_sdk::google::firestore::v1::{value, Value};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Account {
+        balance: u64,
+        total: i128,
+        #[serde(with = "crate::serialize_as_string")]
+        price: f64,
+        #[serde(default, with = "crate::serialize_as_optional_string")]
+        limit: Option<u128>,
+    }
+
+    fn overflow(err: FirestoreError) -> Option<FirestoreNumericOverflow> {
+        match err {
+            FirestoreError::SerializeError(err) | FirestoreError::DeserializeError(err) => err
+                .field_details
+                .and_then(|details| details.numeric_overflow),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_large_integer_formats() {
+        let account = Account {
+            balance: 42,
+            total: -7,
+            price: 9.99,
+            limit: Some(u128::MAX),
+        };
+        let doc = firestore_document_from_serializable("accounts/a1", &account).unwrap();
+        assert_eq!(
+            doc.fields.get("total").unwrap().value_type,
+            Some(value::ValueType::IntegerValue(-7))
+        );
+        assert_eq!(
+            doc.fields.get("price").unwrap().value_type,
+            Some(value::ValueType::StringValue("9.99".to_string()))
+        );
+        assert_eq!(
+            doc.fields.get("limit").unwrap().value_type,
+            Some(value::ValueType::StringValue(u128::MAX.to_string()))
+        );
+        let restored: Account = firestore_document_to_serializable(&doc).unwrap();
+        assert_eq!(restored, account);
+
+        let account = Account {
+            balance: u64::MAX,
+            total: i128::MIN,
+            price: 1.0,
+            limit: None,
+        };
+        let options = FirestoreSerializerOptions::new()
+            .with_large_integer_format(FirestoreLargeIntegerFormat::String);
+        let doc = FirestoreSerializerOptions::sync_scope(options, || {
+            firestore_document_from_serializable("accounts/a1", &account)
+        })
+        .unwrap();
+        assert_eq!(
+            doc.fields.get("balance").unwrap().value_type,
+            Some(value::ValueType::StringValue(u64::MAX.to_string()))
+        );
+        assert!(!doc.fields.contains_key("limit"));
+        let restored: Account = firestore_document_to_serializable(&doc).unwrap();
+        assert_eq!(restored, account);
+
+        let account = Account {
+            balance: 1 << 53,
+            ..account
+        };
+        let options = FirestoreSerializerOptions::new()
+            .with_large_integer_format(FirestoreLargeIntegerFormat::Double);
+        let doc = FirestoreSerializerOptions::sync_scope(options, || {
+            firestore_document_from_serializable("accounts/a1", &account)
+        })
+        .unwrap();
+        assert_eq!(
+            doc.fields.get("total").unwrap().value_type,
+            Some(value::ValueType::DoubleValue(i128::MIN as f64))
+        );
+        let restored: Account = firestore_document_to_serializable(&doc).unwrap();
+        assert_eq!(restored, account);
+    }
+
+    #[test]
+    fn test_numeric_overflow_policies() {
+        let account = Account {
+            balance: u64::MAX,
+            total: i128::MAX,
+            price: 0.0,
+            limit: None,
+        };
+
+        let err = firestore_document_from_serializable("accounts/a1", &account).unwrap_err();
+        assert_eq!(
+            overflow(err),
+            Some(FirestoreNumericOverflow::new(
+                u64::MAX.to_string(),
+                "i64".to_string()
+            ))
+        );
+
+        let options = FirestoreSerializerOptions::new()
+            .with_numeric_overflow(FirestoreNumericOverflowPolicy::Saturate);
+        let doc = FirestoreSerializerOptions::sync_scope(options, || {
+            firestore_document_from_serializable("accounts/a1", &account)
+        })
+        .unwrap();
+        assert_eq!(
+            doc.fields.get("balance").unwrap().value_type,
+            Some(value::ValueType::IntegerValue(i64::MAX))
+        );
+        assert_eq!(
+            doc.fields.get("total").unwrap().value_type,
+            Some(value::ValueType::IntegerValue(i64::MAX))
+        );
+
+        let legacy = Account {
+            balance: u64::MAX,
+            total: 0,
+            price: 0.0,
+            limit: None,
+        };
+        let options = FirestoreSerializerOptions::new()
+            .with_numeric_overflow(FirestoreNumericOverflowPolicy::Wrap);
+        let doc = FirestoreSerializerOptions::sync_scope(options, || {
+            firestore_document_from_serializable("accounts/a1", &legacy)
+        })
+        .unwrap();
+        assert_eq!(
+            doc.fields.get("balance").unwrap().value_type,
+            Some(value::ValueType::IntegerValue(-1))
+        );
+        let restored: Account = FirestoreSerializerOptions::sync_scope(options, || {
+            firestore_document_to_serializable(&doc)
+        })
+        .unwrap();
+        assert_eq!(restored, legacy);
+
+        let restored: Account = firestore_document_to_serializable(&doc).unwrap();
+        assert_eq!(restored, legacy);
+
+        let mut negative_string = doc.clone();
+        negative_string.fields.insert(
+            "balance".to_string(),
+            Value {
+                value_type: Some(value::ValueType::StringValue("-1".to_string())),
+            },
+        );
+        let err = firestore_document_to_serializable::<Account>(&negative_string).unwrap_err();
+        assert_eq!(
+            overflow(err),
+            Some(FirestoreNumericOverflow::new(
+                "-1".to_string(),
+                "u64".to_string()
+            ))
+        );
+        let options = FirestoreSerializerOptions::new()
+            .with_numeric_overflow(FirestoreNumericOverflowPolicy::Saturate);
+        let restored: Account = FirestoreSerializerOptions::sync_scope(options, || {
+            firestore_document_to_serializable(&doc)
+        })
+        .unwrap();
+        assert_eq!(restored.balance, 0);
+    }
+}
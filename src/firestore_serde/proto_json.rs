@@ -0,0 +1,679 @@
+//! Conversions between Firestore protobuf messages and their JSON representations
+//! (the proto3 JSON mapping used by the Firestore REST API, bundles and exports).
+
+use crate::errors::*;
+use crate::timestamp_utils::{from_timestamp, to_timestamp};
+use crate::FirestoreResult;
+use base64::Engine;
+use chrono::{DateTime, SecondsFormat, Utc};
+#[cfg(feature = "bundles")]
+use gcloud_sdk::google::firestore::v1::Document;
+use gcloud_sdk::google::firestore::v1::{
+    structured_query, value, ArrayValue, Cursor, MapValue, StructuredQuery, Value,
+};
+use gcloud_sdk::google::r#type::LatLng;
+use gcloud_sdk::prost_types::Timestamp;
+use serde_json::{json, Map, Value as JsonValue};
+use std::collections::HashMap;
+
+fn json_format_error<S: AsRef<str>>(message: S) -> FirestoreError {
+    FirestoreError::DeserializeError(FirestoreSerializationError::from_message(format!(
+        "Invalid JSON representation: {}",
+        message.as_ref()
+    )))
+}
+
+pub(crate) fn datetime_to_json(dt: &DateTime<Utc>) -> JsonValue {
+    JsonValue::String(dt.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+pub(crate) fn datetime_from_json(json: &JsonValue) -> FirestoreResult<DateTime<Utc>> {
+    json.as_str()
+        .and_then(|str_value| DateTime::parse_from_rfc3339(str_value).ok())
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok_or_else(|| json_format_error(format!("Invalid timestamp: {json}")))
+}
+
+fn timestamp_to_json(ts: &Timestamp) -> FirestoreResult<JsonValue> {
+    Ok(datetime_to_json(&from_timestamp(*ts)?))
+}
+
+fn timestamp_from_json(json: &JsonValue) -> FirestoreResult<Timestamp> {
+    Ok(to_timestamp(datetime_from_json(json)?))
+}
+
+fn double_to_json(value: f64) -> JsonValue {
+    if value.is_nan() {
+        json!("NaN")
+    } else if value.is_infinite() && value > 0.0 {
+        json!("Infinity")
+    } else if value.is_infinite() {
+        json!("-Infinity")
+    } else {
+        json!(value)
+    }
+}
+
+fn double_from_json(json: &JsonValue) -> FirestoreResult<f64> {
+    match json {
+        JsonValue::String(str_value) if str_value == "NaN" => Ok(f64::NAN),
+        JsonValue::String(str_value) if str_value == "Infinity" => Ok(f64::INFINITY),
+        JsonValue::String(str_value) if str_value == "-Infinity" => Ok(f64::NEG_INFINITY),
+        JsonValue::String(str_value) => str_value
+            .parse()
+            .map_err(|_| json_format_error(format!("Invalid double: {str_value}"))),
+        _ => json
+            .as_f64()
+            .ok_or_else(|| json_format_error(format!("Invalid double: {json}"))),
+    }
+}
+
+pub(crate) fn value_to_json(value: &Value) -> FirestoreResult<JsonValue> {
+    Ok(match &value.value_type {
+        None | Some(value::ValueType::NullValue(_)) => json!({ "nullValue": "NULL_VALUE" }),
+        Some(value::ValueType::BooleanValue(v)) => json!({ "booleanValue": v }),
+        Some(value::ValueType::IntegerValue(v)) => json!({ "integerValue": v.to_string() }),
+        Some(value::ValueType::DoubleValue(v)) => json!({ "doubleValue": double_to_json(*v) }),
+        Some(value::ValueType::TimestampValue(v)) => {
+            json!({ "timestampValue": timestamp_to_json(v)? })
+        }
+        Some(value::ValueType::StringValue(v)) => json!({ "stringValue": v }),
+        Some(value::ValueType::BytesValue(v)) => json!({
+            "bytesValue": base64::engine::general_purpose::STANDARD.encode(v)
+        }),
+        Some(value::ValueType::ReferenceValue(v)) => json!({ "referenceValue": v }),
+        Some(value::ValueType::GeoPointValue(v)) => json!({
+            "geoPointValue": {
+                "latitude": double_to_json(v.latitude),
+                "longitude": double_to_json(v.longitude)
+            }
+        }),
+        Some(value::ValueType::ArrayValue(v)) => json!({
+            "arrayValue": {
+                "values": v.values.iter().map(value_to_json).collect::<FirestoreResult<Vec<_>>>()?
+            }
+        }),
+        Some(value::ValueType::MapValue(v)) => json!({
+            "mapValue": { "fields": fields_to_json(&v.fields)? }
+        }),
+    })
+}
+
+pub(crate) fn value_from_json(json: &JsonValue) -> FirestoreResult<Value> {
+    let json_obj = json
+        .as_object()
+        .ok_or_else(|| json_format_error(format!("Invalid value: {json}")))?;
+
+    let (value_type_name, json_value) = json_obj
+        .iter()
+        .next()
+        .ok_or_else(|| json_format_error("Empty value"))?;
+
+    let value_type = match value_type_name.as_str() {
+        "nullValue" => value::ValueType::NullValue(0),
+        "booleanValue" => value::ValueType::BooleanValue(
+            json_value
+                .as_bool()
+                .ok_or_else(|| json_format_error(format!("Invalid boolean: {json_value}")))?,
+        ),
+        "integerValue" => value::ValueType::IntegerValue(
+            match json_value {
+                JsonValue::String(str_value) => str_value.parse().ok(),
+                _ => json_value.as_i64(),
+            }
+            .ok_or_else(|| json_format_error(format!("Invalid integer: {json_value}")))?,
+        ),
+        "doubleValue" => value::ValueType::DoubleValue(double_from_json(json_value)?),
+        "timestampValue" => value::ValueType::TimestampValue(timestamp_from_json(json_value)?),
+        "stringValue" => value::ValueType::StringValue(json_str(json_value)?.to_string()),
+        "bytesValue" => value::ValueType::BytesValue(
+            base64::engine::general_purpose::STANDARD
+                .decode(json_str(json_value)?)
+                .map_err(|err| json_format_error(format!("Invalid bytes: {err}")))?,
+        ),
+        "referenceValue" => value::ValueType::ReferenceValue(json_str(json_value)?.to_string()),
+        "geoPointValue" => value::ValueType::GeoPointValue(LatLng {
+            latitude: json_value
+                .get("latitude")
+                .map(double_from_json)
+                .transpose()?
+                .unwrap_or_default(),
+            longitude: json_value
+                .get("longitude")
+                .map(double_from_json)
+                .transpose()?
+                .unwrap_or_default(),
+        }),
+        "arrayValue" => value::ValueType::ArrayValue(ArrayValue {
+            values: json_value
+                .get("values")
+                .and_then(|values| values.as_array())
+                .map(|values| values.iter().map(value_from_json).collect())
+                .transpose()?
+                .unwrap_or_default(),
+        }),
+        "mapValue" => value::ValueType::MapValue(MapValue {
+            fields: fields_from_json(json_value.get("fields"))?,
+        }),
+        other => return Err(json_format_error(format!("Unknown value type: {other}"))),
+    };
+
+    Ok(Value {
+        value_type: Some(value_type),
+    })
+}
+
+fn json_str(json: &JsonValue) -> FirestoreResult<&str> {
+    json.as_str()
+        .ok_or_else(|| json_format_error(format!("Expected string: {json}")))
+}
+
+pub(crate) fn fields_to_json(fields: &HashMap<String, Value>) -> FirestoreResult<JsonValue> {
+    Ok(JsonValue::Object(
+        fields
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), value_to_json(value)?)))
+            .collect::<FirestoreResult<Map<String, JsonValue>>>()?,
+    ))
+}
+
+fn fields_from_json(json: Option<&JsonValue>) -> FirestoreResult<HashMap<String, Value>> {
+    match json.and_then(|fields| fields.as_object()) {
+        Some(fields) => fields
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), value_from_json(value)?)))
+            .collect(),
+        None => Ok(HashMap::new()),
+    }
+}
+
+#[cfg(feature = "bundles")]
+pub(crate) fn document_to_json(doc: &Document) -> FirestoreResult<JsonValue> {
+    let mut json_obj = Map::new();
+    json_obj.insert("name".to_string(), json!(doc.name));
+    json_obj.insert("fields".to_string(), fields_to_json(&doc.fields)?);
+    if let Some(create_time) = &doc.create_time {
+        json_obj.insert("createTime".to_string(), timestamp_to_json(create_time)?);
+    }
+    if let Some(update_time) = &doc.update_time {
+        json_obj.insert("updateTime".to_string(), timestamp_to_json(update_time)?);
+    }
+    Ok(JsonValue::Object(json_obj))
+}
+
+#[cfg(feature = "bundles")]
+pub(crate) fn document_from_json(json: &JsonValue) -> FirestoreResult<Document> {
+    Ok(Document {
+        name: json
+            .get("name")
+            .map(json_str)
+            .transpose()?
+            .ok_or_else(|| json_format_error("Document without a name"))?
+            .to_string(),
+        fields: fields_from_json(json.get("fields"))?,
+        create_time: json
+            .get("createTime")
+            .map(timestamp_from_json)
+            .transpose()?,
+        update_time: json
+            .get("updateTime")
+            .map(timestamp_from_json)
+            .transpose()?,
+    })
+}
+
+fn field_reference_to_json(field: &structured_query::FieldReference) -> JsonValue {
+    json!({ "fieldPath": field.field_path })
+}
+
+fn filter_to_json(filter: &structured_query::Filter) -> FirestoreResult<JsonValue> {
+    Ok(match &filter.filter_type {
+        Some(structured_query::filter::FilterType::CompositeFilter(composite)) => json!({
+            "compositeFilter": {
+                "op": structured_query::composite_filter::Operator::try_from(composite.op)
+                    .unwrap_or(structured_query::composite_filter::Operator::Unspecified)
+                    .as_str_name(),
+                "filters": composite
+                    .filters
+                    .iter()
+                    .map(filter_to_json)
+                    .collect::<FirestoreResult<Vec<_>>>()?
+            }
+        }),
+        Some(structured_query::filter::FilterType::FieldFilter(field_filter)) => {
+            let mut json_obj = Map::new();
+            if let Some(field) = &field_filter.field {
+                json_obj.insert("field".to_string(), field_reference_to_json(field));
+            }
+            json_obj.insert(
+                "op".to_string(),
+                json!(
+                    structured_query::field_filter::Operator::try_from(field_filter.op)
+                        .unwrap_or(structured_query::field_filter::Operator::Unspecified)
+                        .as_str_name()
+                ),
+            );
+            if let Some(value) = &field_filter.value {
+                json_obj.insert("value".to_string(), value_to_json(value)?);
+            }
+            json!({ "fieldFilter": json_obj })
+        }
+        Some(structured_query::filter::FilterType::UnaryFilter(unary_filter)) => {
+            let mut json_obj = Map::new();
+            json_obj.insert(
+                "op".to_string(),
+                json!(
+                    structured_query::unary_filter::Operator::try_from(unary_filter.op)
+                        .unwrap_or(structured_query::unary_filter::Operator::Unspecified)
+                        .as_str_name()
+                ),
+            );
+            if let Some(structured_query::unary_filter::OperandType::Field(field)) =
+                &unary_filter.operand_type
+            {
+                json_obj.insert("field".to_string(), field_reference_to_json(field));
+            }
+            json!({ "unaryFilter": json_obj })
+        }
+        None => json!({}),
+    })
+}
+
+fn cursor_to_json(cursor: &Cursor) -> FirestoreResult<JsonValue> {
+    Ok(json!({
+        "values": cursor.values.iter().map(value_to_json).collect::<FirestoreResult<Vec<_>>>()?,
+        "before": cursor.before
+    }))
+}
+
+pub(crate) fn structured_query_to_json(query: &StructuredQuery) -> FirestoreResult<JsonValue> {
+    if query.find_nearest.is_some() {
+        return Err(FirestoreError::InvalidParametersError(
+            FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                "find_nearest".to_string(),
+                "Vector search queries can't be stored in bundles".to_string(),
+            )),
+        ));
+    }
+
+    let mut json_obj = Map::new();
+    if let Some(select) = &query.select {
+        json_obj.insert(
+            "select".to_string(),
+            json!({ "fields": select.fields.iter().map(field_reference_to_json).collect::<Vec<_>>() }),
+        );
+    }
+    json_obj.insert(
+        "from".to_string(),
+        JsonValue::Array(
+            query
+                .from
+                .iter()
+                .map(|selector| {
+                    json!({
+                        "collectionId": selector.collection_id,
+                        "allDescendants": selector.all_descendants
+                    })
+                })
+                .collect(),
+        ),
+    );
+    if let Some(filter) = &query.r#where {
+        json_obj.insert("where".to_string(), filter_to_json(filter)?);
+    }
+    if !query.order_by.is_empty() {
+        json_obj.insert(
+            "orderBy".to_string(),
+            JsonValue::Array(
+                query
+                    .order_by
+                    .iter()
+                    .map(|order| {
+                        let mut order_obj = Map::new();
+                        if let Some(field) = &order.field {
+                            order_obj.insert("field".to_string(), field_reference_to_json(field));
+                        }
+                        order_obj.insert(
+                            "direction".to_string(),
+                            json!(structured_query::Direction::try_from(order.direction)
+                                .unwrap_or(structured_query::Direction::Unspecified)
+                                .as_str_name()),
+                        );
+                        JsonValue::Object(order_obj)
+                    })
+                    .collect(),
+            ),
+        );
+    }
+    if let Some(start_at) = &query.start_at {
+        json_obj.insert("startAt".to_string(), cursor_to_json(start_at)?);
+    }
+    if let Some(end_at) = &query.end_at {
+        json_obj.insert("endAt".to_string(), cursor_to_json(end_at)?);
+    }
+    if query.offset != 0 {
+        json_obj.insert("offset".to_string(), json!(query.offset));
+    }
+    if let Some(limit) = query.limit {
+        json_obj.insert("limit".to_string(), json!(limit));
+    }
+    Ok(JsonValue::Object(json_obj))
+}
+
+fn query_format_error<S: AsRef<str>>(message: S) -> FirestoreError {
+    FirestoreError::DeserializeError(FirestoreSerializationError::from_message(format!(
+        "Invalid structured query: {}",
+        message.as_ref()
+    )))
+}
+
+fn field_reference_from_json(
+    json: &JsonValue,
+) -> FirestoreResult<structured_query::FieldReference> {
+    Ok(structured_query::FieldReference {
+        field_path: json
+            .get("fieldPath")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| query_format_error(format!("Invalid field reference: {json}")))?
+            .to_string(),
+    })
+}
+
+fn operator_from_json<T, F>(json: Option<&JsonValue>, from_str_name: F) -> FirestoreResult<i32>
+where
+    T: Into<i32>,
+    F: Fn(&str) -> Option<T>,
+{
+    let name = json.and_then(|v| v.as_str()).unwrap_or_default();
+    from_str_name(name)
+        .map(|op| op.into())
+        .ok_or_else(|| query_format_error(format!("Unknown operator: {name}")))
+}
+
+fn filter_from_json(json: &JsonValue) -> FirestoreResult<structured_query::Filter> {
+    let filter_type = if let Some(composite) = json.get("compositeFilter") {
+        structured_query::filter::FilterType::CompositeFilter(structured_query::CompositeFilter {
+            op: operator_from_json(
+                composite.get("op"),
+                structured_query::composite_filter::Operator::from_str_name,
+            )?,
+            filters: composite
+                .get("filters")
+                .and_then(|filters| filters.as_array())
+                .map(|filters| filters.iter().map(filter_from_json).collect())
+                .transpose()?
+                .unwrap_or_default(),
+        })
+    } else if let Some(field_filter) = json.get("fieldFilter") {
+        structured_query::filter::FilterType::FieldFilter(structured_query::FieldFilter {
+            field: field_filter
+                .get("field")
+                .map(field_reference_from_json)
+                .transpose()?,
+            op: operator_from_json(
+                field_filter.get("op"),
+                structured_query::field_filter::Operator::from_str_name,
+            )?,
+            value: field_filter.get("value").map(value_from_json).transpose()?,
+        })
+    } else if let Some(unary_filter) = json.get("unaryFilter") {
+        structured_query::filter::FilterType::UnaryFilter(structured_query::UnaryFilter {
+            op: operator_from_json(
+                unary_filter.get("op"),
+                structured_query::unary_filter::Operator::from_str_name,
+            )?,
+            operand_type: unary_filter
+                .get("field")
+                .map(field_reference_from_json)
+                .transpose()?
+                .map(structured_query::unary_filter::OperandType::Field),
+        })
+    } else {
+        return Err(query_format_error(format!("Unknown filter: {json}")));
+    };
+
+    Ok(structured_query::Filter {
+        filter_type: Some(filter_type),
+    })
+}
+
+fn cursor_from_json(json: &JsonValue) -> FirestoreResult<Cursor> {
+    Ok(Cursor {
+        values: json
+            .get("values")
+            .and_then(|values| values.as_array())
+            .map(|values| values.iter().map(value_from_json).collect())
+            .transpose()?
+            .unwrap_or_default(),
+        before: json
+            .get("before")
+            .and_then(|v| v.as_bool())
+            .unwrap_or_default(),
+    })
+}
+
+fn i32_from_json(json: &JsonValue) -> FirestoreResult<i32> {
+    // Int32Value wrappers may be represented as plain numbers or `{ "value": n }`
+    json.get("value")
+        .unwrap_or(json)
+        .as_i64()
+        .and_then(|v| i32::try_from(v).ok())
+        .ok_or_else(|| query_format_error(format!("Invalid number: {json}")))
+}
+
+pub(crate) fn structured_query_from_json(json: &JsonValue) -> FirestoreResult<StructuredQuery> {
+    if !json.is_object() {
+        return Err(query_format_error(format!("Expected an object: {json}")));
+    }
+    if json.get("findNearest").is_some() {
+        return Err(query_format_error(
+            "Vector search queries are not supported in JSON",
+        ));
+    }
+
+    Ok(StructuredQuery {
+        select: json
+            .get("select")
+            .map(|select| {
+                Ok::<_, FirestoreError>(structured_query::Projection {
+                    fields: select
+                        .get("fields")
+                        .and_then(|fields| fields.as_array())
+                        .map(|fields| fields.iter().map(field_reference_from_json).collect())
+                        .transpose()?
+                        .unwrap_or_default(),
+                })
+            })
+            .transpose()?,
+        from: json
+            .get("from")
+            .and_then(|from| from.as_array())
+            .map(|from| {
+                from.iter()
+                    .map(|selector| structured_query::CollectionSelector {
+                        collection_id: selector
+                            .get("collectionId")
+                            .and_then(|v| v.as_str())
+                            .unwrap_or_default()
+                            .to_string(),
+                        all_descendants: selector
+                            .get("allDescendants")
+                            .and_then(|v| v.as_bool())
+                            .unwrap_or_default(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+        r#where: json.get("where").map(filter_from_json).transpose()?,
+        order_by: json
+            .get("orderBy")
+            .and_then(|order_by| order_by.as_array())
+            .map(|order_by| {
+                order_by
+                    .iter()
+                    .map(|order| {
+                        Ok(structured_query::Order {
+                            field: order
+                                .get("field")
+                                .map(field_reference_from_json)
+                                .transpose()?,
+                            direction: match order.get("direction") {
+                                Some(direction) => operator_from_json(
+                                    Some(direction),
+                                    structured_query::Direction::from_str_name,
+                                )?,
+                                None => structured_query::Direction::Ascending.into(),
+                            },
+                        })
+                    })
+                    .collect::<FirestoreResult<Vec<_>>>()
+            })
+            .transpose()?
+            .unwrap_or_default(),
+        start_at: json.get("startAt").map(cursor_from_json).transpose()?,
+        end_at: json.get("endAt").map(cursor_from_json).transpose()?,
+        offset: json
+            .get("offset")
+            .map(i32_from_json)
+            .transpose()?
+            .unwrap_or_default(),
+        limit: json.get("limit").map(i32_from_json).transpose()?,
+        find_nearest: None,
+    })
+}
+
+/// Converts a [`StructuredQuery`] into its JSON representation
+/// (the proto3 JSON mapping used by the Firestore REST API and other SDK tooling).
+pub fn firestore_structured_query_to_json(query: &StructuredQuery) -> FirestoreResult<JsonValue> {
+    structured_query_to_json(query)
+}
+
+/// Parses a [`StructuredQuery`] from its JSON representation
+/// (the proto3 JSON mapping used by the Firestore REST API and other SDK tooling).
+pub fn firestore_structured_query_from_json(json: &JsonValue) -> FirestoreResult<StructuredQuery> {
+    structured_query_from_json(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_json_roundtrip() {
+        let value = Value {
+            value_type: Some(value::ValueType::MapValue(MapValue {
+                fields: [
+                    (
+                        "int".to_string(),
+                        Value {
+                            value_type: Some(value::ValueType::IntegerValue(42)),
+                        },
+                    ),
+                    (
+                        "bytes".to_string(),
+                        Value {
+                            value_type: Some(value::ValueType::BytesValue(vec![1, 2, 3])),
+                        },
+                    ),
+                    (
+                        "ts".to_string(),
+                        Value {
+                            value_type: Some(value::ValueType::TimestampValue(Timestamp {
+                                seconds: 1_700_000_000,
+                                nanos: 123_000,
+                            })),
+                        },
+                    ),
+                    (
+                        "arr".to_string(),
+                        Value {
+                            value_type: Some(value::ValueType::ArrayValue(ArrayValue {
+                                values: vec![
+                                    Value {
+                                        value_type: Some(value::ValueType::DoubleValue(1.5)),
+                                    },
+                                    Value {
+                                        value_type: Some(value::ValueType::NullValue(0)),
+                                    },
+                                ],
+                            })),
+                        },
+                    ),
+                ]
+                .into(),
+            })),
+        };
+
+        let json_value = value_to_json(&value).unwrap();
+        assert_eq!(
+            json_value["mapValue"]["fields"]["int"],
+            json!({ "integerValue": "42" })
+        );
+        assert_eq!(
+            json_value["mapValue"]["fields"]["bytes"],
+            json!({ "bytesValue": "AQID" })
+        );
+        assert_eq!(
+            json_value["mapValue"]["fields"]["ts"],
+            json!({ "timestampValue": "2023-11-14T22:13:20.000123Z" })
+        );
+        assert_eq!(value_from_json(&json_value).unwrap(), value);
+    }
+
+    #[test]
+    fn structured_query_json_roundtrip() {
+        let query: StructuredQuery = crate::FirestoreQueryParams::new("users".into())
+            .with_filter(crate::FirestoreQueryFilter::Composite(
+                crate::FirestoreQueryFilterComposite::new(
+                    vec![
+                        crate::FirestoreQueryFilter::Compare(Some(
+                            crate::FirestoreQueryFilterCompare::GreaterThan(
+                                "age".to_string(),
+                                18.into(),
+                            ),
+                        )),
+                        crate::FirestoreQueryFilter::Unary(
+                            crate::FirestoreQueryFilterUnary::IsNotNull("email".to_string()),
+                        ),
+                    ],
+                    crate::FirestoreQueryFilterCompositeOperator::And,
+                ),
+            ))
+            .with_order_by(vec![crate::FirestoreQueryOrder::new(
+                "age".to_string(),
+                crate::FirestoreQueryDirection::Descending,
+            )])
+            .with_limit(10)
+            .with_offset(5)
+            .try_into()
+            .unwrap();
+
+        let json_value = structured_query_to_json(&query).unwrap();
+        assert_eq!(
+            json_value["from"],
+            json!([{ "collectionId": "users", "allDescendants": false }])
+        );
+        assert_eq!(structured_query_from_json(&json_value).unwrap(), query);
+
+        let rest_json = json!({
+            "from": [{ "collectionId": "users" }],
+            "where": {
+                "fieldFilter": {
+                    "field": { "fieldPath": "status" },
+                    "op": "EQUAL",
+                    "value": { "stringValue": "active" }
+                }
+            },
+            "orderBy": [{ "field": { "fieldPath": "name" } }],
+            "limit": { "value": 3 }
+        });
+        let rest_query = structured_query_from_json(&rest_json).unwrap();
+        assert_eq!(rest_query.limit, Some(3));
+        assert_eq!(
+            rest_query.order_by[0].direction,
+            structured_query::Direction::Ascending as i32
+        );
+
+        assert!(structured_query_from_json(&json!({ "where": { "unknown": {} } })).is_err());
+    }
+}
@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize, Serializer};
 
 use crate::db::split_document_path;
 use crate::errors::*;
-use crate::FirestoreValue;
+use crate::{FirestoreResult, FirestoreValue};
 
 pub(crate) const FIRESTORE_REFERENCE_TYPE_TAG_TYPE: &str = "FirestoreReference";
 
@@ -39,6 +39,39 @@ impl FirestoreReference {
             )
         }
     }
+
+    /// Splits the reference into the full parent path, collection id and document id,
+    /// e.g. `projects/p/databases/d/documents/parents/p1`, `children` and `c1`.
+    pub fn document_location(&self) -> FirestoreResult<(&str, &str, &str)> {
+        let (collection_path, document_id) = split_document_path(self.as_str());
+        let (parent_path, collection_id) = split_document_path(collection_path);
+
+        if document_id.is_empty()
+            || collection_id.is_empty()
+            || !(parent_path.ends_with("/documents") || parent_path.contains("/documents/"))
+        {
+            return Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "reference".to_string(),
+                    format!("Invalid document reference: {}", self.0),
+                )),
+            ));
+        }
+
+        Ok((parent_path, collection_id, document_id))
+    }
+}
+
+impl From<String> for FirestoreReference {
+    fn from(reference: String) -> Self {
+        FirestoreReference(reference)
+    }
+}
+
+impl From<&str> for FirestoreReference {
+    fn from(reference: &str) -> Self {
+        FirestoreReference(reference.to_string())
+    }
 }
 
 pub mod serialize_as_reference {
@@ -62,6 +95,30 @@ pub mod serialize_as_reference {
     }
 }
 
+pub mod serialize_as_optional_reference {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(str: &Option<String>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match str {
+            Some(v) => serializer.serialize_newtype_struct(
+                crate::firestore_serde::FIRESTORE_REFERENCE_TYPE_TAG_TYPE,
+                v,
+            ),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)
+    }
+}
+
 pub fn serialize_reference_for_firestore<T: ?Sized + Serialize>(
     value: &T,
     none_as_null: bool,
@@ -335,6 +392,16 @@ pub fn serialize_reference_for_firestore<T: ?Sized + Serialize>(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{firestore_document_from_serializable, firestore_document_to_serializable};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Order {
+        customer: FirestoreReference,
+        #[serde(with = "crate::serialize_as_reference")]
+        product: String,
+        #[serde(default, with = "crate::serialize_as_optional_reference")]
+        coupon: Option<String>,
+    }
 
     #[test]
     fn test_reference_split() {
@@ -352,4 +419,51 @@ mod tests {
         assert_eq!(collection_name, "child-collection");
         assert_eq!(document_id, "child-document-id");
     }
+
+    #[test]
+    fn test_reference_serialization() {
+        let order = Order {
+            customer: FirestoreReference::new(
+                "projects/p/databases/(default)/documents/customers/c1".to_string(),
+            ),
+            product: "projects/p/databases/(default)/documents/products/p1".to_string(),
+            coupon: None,
+        };
+
+        let doc = firestore_document_from_serializable("orders/o1", &order).unwrap();
+        assert_eq!(
+            doc.fields.get("customer").unwrap().value_type,
+            Some(value::ValueType::ReferenceValue(
+                "projects/p/databases/(default)/documents/customers/c1".to_string()
+            ))
+        );
+        assert_eq!(
+            doc.fields.get("product").unwrap().value_type,
+            Some(value::ValueType::ReferenceValue(
+                "projects/p/databases/(default)/documents/products/p1".to_string()
+            ))
+        );
+        assert!(!doc.fields.contains_key("coupon"));
+
+        let restored: Order = firestore_document_to_serializable(&doc).unwrap();
+        assert_eq!(restored, order);
+    }
+
+    #[test]
+    fn test_reference_document_location() {
+        let reference = FirestoreReference::from(
+            "projects/p/databases/(default)/documents/parents/p1/children/c1",
+        );
+        assert_eq!(
+            reference.document_location().unwrap(),
+            (
+                "projects/p/databases/(default)/documents/parents/p1",
+                "children",
+                "c1"
+            )
+        );
+
+        let reference = FirestoreReference::from("projects/p/databases/(default)/documents/c1");
+        assert!(reference.document_location().is_err());
+    }
 }
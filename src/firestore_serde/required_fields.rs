@@ -0,0 +1,329 @@
+use serde::de::{
+    DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+
+// Parses as a timestamp as well, since timestamps are deserialized from strings
+const PROBE_STRING: &str = "1970-01-01T00:00:00Z";
+
+const MAX_PROBE_ATTEMPTS: usize = 1000;
+
+/// Finds the fields of a struct that must be present to deserialize it,
+/// i.e. the fields that are neither `Option` nor have a default.
+///
+/// The fields are discovered one by one, deserializing the struct from synthetic values.
+/// Types that can't be deserialized from the synthetic values stop the discovery,
+/// so the result may be incomplete, but never contains optional fields.
+pub(crate) fn firestore_required_fields<T>() -> Vec<&'static str>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    let mut required: Vec<&'static str> = Vec::new();
+    for _ in 0..MAX_PROBE_ATTEMPTS {
+        match T::deserialize(ProbeDeserializer {
+            present_fields: Some(&required),
+        }) {
+            Err(ProbeError::MissingField(field)) if !required.contains(&field) => {
+                required.push(field)
+            }
+            _ => break,
+        }
+    }
+    required
+}
+
+#[derive(Debug)]
+enum ProbeError {
+    MissingField(&'static str),
+    Other(String),
+}
+
+impl Display for ProbeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProbeError::MissingField(field) => write!(f, "missing field `{field}`"),
+            ProbeError::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+impl serde::de::Error for ProbeError {
+    fn custom<M: Display>(msg: M) -> Self {
+        ProbeError::Other(msg.to_string())
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        ProbeError::MissingField(field)
+    }
+}
+
+/// Deserializes any type from synthetic values.
+/// At the top level, structs contain only `present_fields`, nested structs contain all the fields.
+struct ProbeDeserializer<'a> {
+    present_fields: Option<&'a [&'static str]>,
+}
+
+impl ProbeDeserializer<'_> {
+    fn nested() -> Self {
+        Self {
+            present_fields: None,
+        }
+    }
+}
+
+macro_rules! probe_with {
+    ($($method:ident => $visit:ident($($value:expr)?)),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                visitor.$visit($($value)?)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for ProbeDeserializer<'_> {
+    type Error = ProbeError;
+
+    probe_with! {
+        deserialize_any => visit_unit(),
+        deserialize_bool => visit_bool(false),
+        deserialize_i8 => visit_i64(0),
+        deserialize_i16 => visit_i64(0),
+        deserialize_i32 => visit_i64(0),
+        deserialize_i64 => visit_i64(0),
+        deserialize_u8 => visit_u64(0),
+        deserialize_u16 => visit_u64(0),
+        deserialize_u32 => visit_u64(0),
+        deserialize_u64 => visit_u64(0),
+        deserialize_f32 => visit_f64(0.0),
+        deserialize_f64 => visit_f64(0.0),
+        deserialize_char => visit_char('a'),
+        deserialize_str => visit_str(PROBE_STRING),
+        deserialize_string => visit_str(PROBE_STRING),
+        deserialize_identifier => visit_str(PROBE_STRING),
+        deserialize_bytes => visit_bytes(&[]),
+        deserialize_byte_buf => visit_bytes(&[]),
+        deserialize_option => visit_none(),
+        deserialize_unit => visit_unit(),
+        deserialize_ignored_any => visit_unit(),
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(ProbeDeserializer::nested())
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(ProbeSeq { remaining: 0 })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(ProbeSeq { remaining: len })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(ProbeSeq { remaining: len })
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(ProbeMap { fields: &[] })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(ProbeMap {
+            fields: self.present_fields.unwrap_or(fields),
+        })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match variants.first() {
+            Some(variant) => visitor.visit_enum(ProbeEnum { variant }),
+            None => Err(ProbeError::Other("Enum without variants".to_string())),
+        }
+    }
+}
+
+struct ProbeSeq {
+    remaining: usize,
+}
+
+impl<'de> SeqAccess<'de> for ProbeSeq {
+    type Error = ProbeError;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, Self::Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(ProbeDeserializer::nested()).map(Some)
+    }
+}
+
+struct ProbeMap<'a> {
+    fields: &'a [&'static str],
+}
+
+impl<'de> MapAccess<'de> for ProbeMap<'_> {
+    type Error = ProbeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.fields.split_first() {
+            Some((field, rest)) => {
+                self.fields = rest;
+                seed.deserialize(field.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        seed.deserialize(ProbeDeserializer::nested())
+    }
+}
+
+struct ProbeEnum {
+    variant: &'static str,
+}
+
+impl<'de> EnumAccess<'de> for ProbeEnum {
+    type Error = ProbeError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for ProbeEnum {
+    type Error = ProbeError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        seed.deserialize(ProbeDeserializer::nested())
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(ProbeSeq { remaining: len })
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(ProbeMap { fields })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+    use std::collections::HashMap;
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    enum Status {
+        Active,
+        Blocked { reason: String },
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct Address {
+        city: String,
+        zip: Option<String>,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Deserialize)]
+    struct User {
+        id: String,
+        #[serde(rename = "displayName")]
+        display_name: String,
+        email: Option<String>,
+        #[serde(default)]
+        tags: Vec<String>,
+        age: u32,
+        created_at: DateTime<Utc>,
+        address: Address,
+        status: Status,
+        attributes: HashMap<String, String>,
+        scores: (u8, f64),
+    }
+
+    #[test]
+    fn finds_required_fields() {
+        assert_eq!(
+            firestore_required_fields::<User>(),
+            vec![
+                "id",
+                "displayName",
+                "age",
+                "created_at",
+                "address",
+                "status",
+                "attributes",
+                "scores"
+            ]
+        );
+        assert!(firestore_required_fields::<Option<User>>().is_empty());
+    }
+}
@@ -1,40 +1,102 @@
 use crate::errors::*;
-use crate::{FirestoreError, FirestoreValue};
+use crate::firestore_serde::value_transformers::{
+    firestore_transform_document_fields, FirestoreValueTransformDirection,
+};
+use crate::{
+    FirestoreError, FirestoreSerializerOptions, FirestoreUnitVariantFormat, FirestoreValue,
+};
+use crate::{FirestoreLargeIntegerFormat, FirestoreNumericOverflowPolicy};
 use gcloud_sdk::google::firestore::v1::value;
 use serde::Serialize;
 use std::collections::HashMap;
+use std::fmt::Display;
 
 pub struct FirestoreValueSerializer {
-    pub none_as_null: bool,
+    pub options: FirestoreSerializerOptions,
 }
 
 impl FirestoreValueSerializer {
     pub fn new() -> Self {
         Self {
-            none_as_null: false,
+            options: FirestoreSerializerOptions::current(),
         }
     }
 }
 
+impl FirestoreValueSerializer {
+    /// Serializes a `u64`, `i128` or `u128` value in the large integer format of the options.
+    fn serialize_large_integer<T>(
+        self,
+        v: T,
+        as_double: f64,
+    ) -> Result<FirestoreValue, FirestoreError>
+    where
+        T: Copy + Default + PartialOrd + Display + TryInto<i64>,
+    {
+        let value_type = match self.options.large_integer_format {
+            FirestoreLargeIntegerFormat::String => value::ValueType::StringValue(v.to_string()),
+            FirestoreLargeIntegerFormat::Double => value::ValueType::DoubleValue(as_double),
+            FirestoreLargeIntegerFormat::Integer => match v.try_into() {
+                Ok(v) => value::ValueType::IntegerValue(v),
+                Err(_) => match self.options.numeric_overflow {
+                    FirestoreNumericOverflowPolicy::Saturate if v < T::default() => {
+                        value::ValueType::IntegerValue(i64::MIN)
+                    }
+                    FirestoreNumericOverflowPolicy::Saturate => {
+                        value::ValueType::IntegerValue(i64::MAX)
+                    }
+                    FirestoreNumericOverflowPolicy::Error
+                    | FirestoreNumericOverflowPolicy::Wrap => {
+                        return Err(FirestoreError::SerializeError(
+                            FirestoreNumericOverflow::new(v.to_string(), "i64".to_string())
+                                .into_serialization_error(),
+                        ))
+                    }
+                },
+            },
+        };
+        Ok(FirestoreValue::from(
+            gcloud_sdk::google::firestore::v1::Value {
+                value_type: Some(value_type),
+            },
+        ))
+    }
+}
+
+fn is_empty_collection(value: &gcloud_sdk::google::firestore::v1::Value) -> bool {
+    match &value.value_type {
+        Some(value::ValueType::ArrayValue(array)) => array.values.is_empty(),
+        Some(value::ValueType::MapValue(map)) => map.fields.is_empty(),
+        _ => false,
+    }
+}
+
+impl FirestoreSerializerOptions {
+    /// Whether a serialized field value should be written to the document.
+    fn writes_field(&self, value: &gcloud_sdk::google::firestore::v1::Value) -> bool {
+        value.value_type.is_some() && !(self.skip_empty_collections && is_empty_collection(value))
+    }
+}
+
 pub struct SerializeVec {
-    pub none_as_null: bool,
+    pub options: FirestoreSerializerOptions,
     pub vec: Vec<gcloud_sdk::google::firestore::v1::Value>,
 }
 
 pub struct SerializeTupleVariant {
-    none_as_null: bool,
+    options: FirestoreSerializerOptions,
     name: String,
     vec: Vec<gcloud_sdk::google::firestore::v1::Value>,
 }
 
 pub struct SerializeMap {
-    none_as_null: bool,
+    options: FirestoreSerializerOptions,
     fields: HashMap<String, gcloud_sdk::google::firestore::v1::Value>,
     next_key: Option<String>,
 }
 
 pub struct SerializeStructVariant {
-    none_as_null: bool,
+    options: FirestoreSerializerOptions,
     name: String,
     fields: HashMap<String, gcloud_sdk::google::firestore::v1::Value>,
 }
@@ -115,11 +177,25 @@ impl serde::Serializer for FirestoreValueSerializer {
     }
 
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
-        Ok(FirestoreValue::from(
-            gcloud_sdk::google::firestore::v1::Value {
-                value_type: Some(value::ValueType::IntegerValue(v as i64)),
-            },
-        ))
+        match (
+            self.options.large_integer_format,
+            self.options.numeric_overflow,
+        ) {
+            (FirestoreLargeIntegerFormat::Integer, FirestoreNumericOverflowPolicy::Wrap) => Ok(
+                FirestoreValue::from(gcloud_sdk::google::firestore::v1::Value {
+                    value_type: Some(value::ValueType::IntegerValue(v as i64)),
+                }),
+            ),
+            _ => self.serialize_large_integer(v, v as f64),
+        }
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        self.serialize_large_integer(v, v as f64)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        self.serialize_large_integer(v, v as f64)
     }
 
     fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
@@ -163,7 +239,7 @@ impl serde::Serializer for FirestoreValueSerializer {
     }
 
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
-        if self.none_as_null {
+        if self.options.none_as_null {
             Ok(FirestoreValue::from(
                 gcloud_sdk::google::firestore::v1::Value {
                     value_type: Some(value::ValueType::NullValue(0)),
@@ -196,7 +272,27 @@ impl serde::Serializer for FirestoreValueSerializer {
         _variant_index: u32,
         variant: &'static str,
     ) -> Result<Self::Ok, Self::Error> {
-        self.serialize_str(variant)
+        match self.options.unit_variant_format {
+            FirestoreUnitVariantFormat::String => self.serialize_str(variant),
+            FirestoreUnitVariantFormat::Map => {
+                let mut fields = HashMap::new();
+                fields.insert(
+                    String::from(variant),
+                    gcloud_sdk::google::firestore::v1::Value {
+                        value_type: Some(value::ValueType::MapValue(
+                            gcloud_sdk::google::firestore::v1::MapValue::default(),
+                        )),
+                    },
+                );
+                Ok(FirestoreValue::from(
+                    gcloud_sdk::google::firestore::v1::Value {
+                        value_type: Some(value::ValueType::MapValue(
+                            gcloud_sdk::google::firestore::v1::MapValue { fields },
+                        )),
+                    },
+                ))
+            }
+        }
     }
 
     fn serialize_newtype_struct<T: ?Sized + Serialize>(
@@ -215,9 +311,10 @@ impl serde::Serializer for FirestoreValueSerializer {
                     value, true,
                 )
             }
-            crate::firestore_serde::null_serializers::FIRESTORE_NULL_TYPE_TAG_TYPE => {
-                value.serialize(Self { none_as_null: true })
-            }
+            crate::firestore_serde::null_serializers::FIRESTORE_NULL_TYPE_TAG_TYPE => value
+                .serialize(Self {
+                    options: self.options.with_none_as_null(true),
+                }),
             crate::firestore_serde::latlng_serializers::FIRESTORE_LATLNG_TYPE_TAG_TYPE => {
                 crate::firestore_serde::latlng_serializers::serialize_latlng_for_firestore(value)
             }
@@ -255,7 +352,7 @@ impl serde::Serializer for FirestoreValueSerializer {
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
         Ok(SerializeVec {
-            none_as_null: self.none_as_null,
+            options: self.options,
             vec: Vec::with_capacity(len.unwrap_or(0)),
         })
     }
@@ -280,7 +377,7 @@ impl serde::Serializer for FirestoreValueSerializer {
         len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
         Ok(SerializeTupleVariant {
-            none_as_null: self.none_as_null,
+            options: self.options,
             name: String::from(variant),
             vec: Vec::with_capacity(len),
         })
@@ -288,7 +385,7 @@ impl serde::Serializer for FirestoreValueSerializer {
 
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
         Ok(SerializeMap {
-            none_as_null: self.none_as_null,
+            options: self.options,
             fields: HashMap::with_capacity(len.unwrap_or(0)),
             next_key: None,
         })
@@ -310,7 +407,7 @@ impl serde::Serializer for FirestoreValueSerializer {
         len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
         Ok(SerializeStructVariant {
-            none_as_null: self.none_as_null,
+            options: self.options,
             name: String::from(variant),
             fields: HashMap::with_capacity(len),
         })
@@ -324,7 +421,7 @@ impl serde::ser::SerializeSeq for SerializeVec {
     fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
         let serialized_value = value
             .serialize(FirestoreValueSerializer {
-                none_as_null: self.none_as_null,
+                options: self.options,
             })?
             .value;
         if serialized_value.value_type.is_some() {
@@ -377,7 +474,7 @@ impl serde::ser::SerializeTupleVariant for SerializeTupleVariant {
     fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Self::Error> {
         let serialized_value = value
             .serialize(FirestoreValueSerializer {
-                none_as_null: self.none_as_null,
+                options: self.options,
             })?
             .value;
         if serialized_value.value_type.is_some() {
@@ -413,7 +510,7 @@ impl serde::ser::SerializeMap for SerializeMap {
 
     fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Self::Error> {
         let serializer = FirestoreValueSerializer {
-            none_as_null: self.none_as_null,
+            options: self.options,
         };
         match key.serialize(serializer)?.value.value_type {
             Some(value::ValueType::StringValue(str)) => {
@@ -434,10 +531,10 @@ impl serde::ser::SerializeMap for SerializeMap {
         match self.next_key.take() {
             Some(key) => {
                 let serializer = FirestoreValueSerializer {
-                    none_as_null: self.none_as_null,
+                    options: self.options,
                 };
                 let serialized_value = value.serialize(serializer)?.value;
-                if serialized_value.value_type.is_some() {
+                if self.options.writes_field(&serialized_value) {
                     self.fields.insert(key, serialized_value);
                 }
                 Ok(())
@@ -471,10 +568,10 @@ impl serde::ser::SerializeStruct for SerializeMap {
         value: &T,
     ) -> Result<(), Self::Error> {
         let serializer = FirestoreValueSerializer {
-            none_as_null: self.none_as_null,
+            options: self.options,
         };
         let serialized_value = value.serialize(serializer)?.value;
-        if serialized_value.value_type.is_some() {
+        if self.options.writes_field(&serialized_value) {
             self.fields.insert(key.to_string(), serialized_value);
         }
         Ok(())
@@ -503,10 +600,10 @@ impl serde::ser::SerializeStructVariant for SerializeStructVariant {
         value: &T,
     ) -> Result<(), Self::Error> {
         let serializer = FirestoreValueSerializer {
-            none_as_null: self.none_as_null,
+            options: self.options,
         };
         let serialized_value = value.serialize(serializer)?.value;
-        if serialized_value.value_type.is_some() {
+        if self.options.writes_field(&serialized_value) {
             self.fields.insert(key.to_string(), serialized_value);
         }
         Ok(())
@@ -543,22 +640,52 @@ where
     S: AsRef<str>,
     T: Serialize,
 {
-    let serializer = crate::firestore_serde::serializer::FirestoreValueSerializer {
-        none_as_null: false,
-    };
+    serialize_document(document_path.as_ref(), None, object)
+}
+
+/// Serializes an object to a document without a path, to be created in the collection.
+pub(crate) fn firestore_new_document_from_serializable<T>(
+    collection_id: &str,
+    object: &T,
+) -> Result<gcloud_sdk::google::firestore::v1::Document, FirestoreError>
+where
+    T: Serialize,
+{
+    serialize_document("", Some(collection_id), object)
+}
+
+fn serialize_document<T>(
+    document_path: &str,
+    collection_id: Option<&str>,
+    object: &T,
+) -> Result<gcloud_sdk::google::firestore::v1::Document, FirestoreError>
+where
+    T: Serialize,
+{
+    let serializer = crate::firestore_serde::serializer::FirestoreValueSerializer::new();
     let document_value = object.serialize(serializer).map_err(|err| match err {
         FirestoreError::SerializeError(e) => {
-            FirestoreError::SerializeError(e.with_document_path(document_path.as_ref().to_string()))
+            FirestoreError::SerializeError(e.with_document_path(document_path.to_string()))
         }
         _ => err,
     })?;
 
     match document_value.value.value_type {
-        Some(value::ValueType::MapValue(mv)) => Ok(gcloud_sdk::google::firestore::v1::Document {
-            fields: mv.fields,
-            name: document_path.as_ref().to_string(),
-            ..Default::default()
-        }),
+        Some(value::ValueType::MapValue(mut mv)) => {
+            mv.fields
+                .retain(|field, _| !crate::firestore_serde::is_firestore_metadata_field(field));
+            firestore_transform_document_fields(
+                &mut mv.fields,
+                document_path,
+                collection_id,
+                FirestoreValueTransformDirection::Serialize,
+            )?;
+            Ok(gcloud_sdk::google::firestore::v1::Document {
+                fields: mv.fields,
+                name: document_path.to_string(),
+                ..Default::default()
+            })
+        }
         _ => Err(FirestoreError::SystemError(FirestoreSystemError::new(
             FirestoreErrorPublicGenericDetails::new("SystemError".into()),
             "Unable to create document from value. No object found".into(),
@@ -0,0 +1,220 @@
+use rsb_derive::Builder;
+use std::future::Future;
+
+tokio::task_local! {
+    static FIRESTORE_SERIALIZER_OPTIONS_OVERRIDE: FirestoreSerializerOptions;
+}
+
+/// How unit enum variants (variants without data) are serialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FirestoreUnitVariantFormat {
+    /// As a string with the variant name: `"Active"`. The default.
+    #[default]
+    String,
+    /// As a map with the variant name as the only key and an empty map as the value: `{"Active": {}}`.
+    Map,
+}
+
+/// How `u64`, `i128` and `u128` values are serialized, since Firestore integers are 64-bit signed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FirestoreLargeIntegerFormat {
+    /// As integers, applying the [`FirestoreNumericOverflowPolicy`] to values out of the `i64` range. The default.
+    #[default]
+    Integer,
+    /// As decimal strings keeping all the digits. Note that strings are ordered lexicographically in queries.
+    String,
+    /// As doubles, rounding values with more than 53 significant bits.
+    Double,
+}
+
+/// What happens to numbers that don't fit into the target type:
+/// Firestore integers on writes, or the fields of the deserialized type on reads.
+///
+/// Negative integers read into `u64` fields are reinterpreted with the same bits unless the policy
+/// is [`Self::Saturate`], so the documents written by the previous versions of the library can be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FirestoreNumericOverflowPolicy {
+    /// Fails with a [`FirestoreNumericOverflow`](crate::errors::FirestoreNumericOverflow) error. The default.
+    #[default]
+    Error,
+    /// Clamps the numbers to the nearest value of the target type.
+    Saturate,
+    /// Stores `u64` values out of the `i64` range as negative integers with the same bits,
+    /// the format of the previous versions of the library. Other types fail as with [`Self::Error`].
+    Wrap,
+}
+
+/// Options controlling how objects are serialized to Firestore documents.
+///
+/// The defaults match the format of the previous versions of the library,
+/// except that writing `u64` values out of the `i64` range fails with an overflow error instead of wrapping them.
+/// Other conventions are useful to interoperate with documents written by other SDKs.
+/// Both formats of unit enum variants and explicit `null`s are always accepted when reading documents,
+/// as well as integers written as strings or doubles.
+/// The numeric overflow policy also applies to reading documents.
+///
+/// The options are specified for a fluent insert or update with `.serializer_options(options)`,
+/// or for all the writes of a future with [`FirestoreSerializerOptions::scope`].
+///
+/// # Examples
+///
+/// ```rust
+/// use firestore::*;
+///
+/// let options = FirestoreSerializerOptions::new()
+///     .with_none_as_null(true)
+///     .with_skip_empty_collections(true)
+///     .with_unit_variant_format(FirestoreUnitVariantFormat::Map);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Builder)]
+pub struct FirestoreSerializerOptions {
+    /// Writes `None` values as explicit `null`s instead of omitting the fields.
+    #[default = "false"]
+    pub none_as_null: bool,
+
+    /// Omits fields with empty arrays and maps instead of writing them.
+    #[default = "false"]
+    pub skip_empty_collections: bool,
+
+    /// How unit enum variants are serialized.
+    #[default = "FirestoreUnitVariantFormat::String"]
+    pub unit_variant_format: FirestoreUnitVariantFormat,
+
+    /// How `u64`, `i128` and `u128` values are serialized.
+    #[default = "FirestoreLargeIntegerFormat::Integer"]
+    pub large_integer_format: FirestoreLargeIntegerFormat,
+
+    /// What happens to numbers that don't fit into the target type.
+    #[default = "FirestoreNumericOverflowPolicy::Error"]
+    pub numeric_overflow: FirestoreNumericOverflowPolicy,
+}
+
+impl Default for FirestoreSerializerOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FirestoreSerializerOptions {
+    /// Returns the options for the current serialization: the options of the enclosing scope or the default ones.
+    pub(crate) fn current() -> Self {
+        FIRESTORE_SERIALIZER_OPTIONS_OVERRIDE
+            .try_with(|options| *options)
+            .unwrap_or_default()
+    }
+
+    /// Returns the options of the enclosing scope, if any.
+    pub(crate) fn current_override() -> Option<Self> {
+        FIRESTORE_SERIALIZER_OPTIONS_OVERRIDE
+            .try_with(|options| *options)
+            .ok()
+    }
+
+    /// Runs the future serializing objects with the specified options.
+    pub async fn scope<F>(options: Self, future: F) -> F::Output
+    where
+        F: Future,
+    {
+        FIRESTORE_SERIALIZER_OPTIONS_OVERRIDE
+            .scope(options, future)
+            .await
+    }
+
+    /// Runs the function serializing objects with the specified options.
+    pub fn sync_scope<F, R>(options: Self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        FIRESTORE_SERIALIZER_OPTIONS_OVERRIDE.sync_scope(options, f)
+    }
+
+    /// Runs the future with the options if they are specified, used by the fluent builders.
+    pub(crate) async fn opt_scope<F>(options: Option<Self>, future: F) -> F::Output
+    where
+        F: Future,
+    {
+        match options {
+            Some(options) => Self::scope(options, future).await,
+            None => future.await,
+        }
+    }
+
+    /// Runs the function with the options if they are specified, used by the fluent builders.
+    pub(crate) fn opt_sync_scope<F, R>(options: Option<Self>, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        match options {
+            Some(options) => Self::sync_scope(options, f),
+            None => f(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{firestore_document_from_serializable, firestore_document_to_serializable};
+    use gcloud_sdk::google::firestore::v1::value;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Status {
+        Active,
+        Blocked,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct User {
+        email: Option<String>,
+        #[serde(default)]
+        tags: Vec<String>,
+        #[serde(default)]
+        attributes: HashMap<String, String>,
+        status: Status,
+    }
+
+    #[test]
+    fn test_serializer_options() {
+        let user = User {
+            email: None,
+            tags: vec![],
+            attributes: HashMap::new(),
+            status: Status::Active,
+        };
+
+        let doc = firestore_document_from_serializable("users/u1", &user).unwrap();
+        assert!(!doc.fields.contains_key("email"));
+        assert!(doc.fields.contains_key("tags"));
+        assert!(doc.fields.contains_key("attributes"));
+        assert_eq!(
+            doc.fields.get("status").unwrap().value_type,
+            Some(value::ValueType::StringValue("Active".to_string()))
+        );
+
+        let options = FirestoreSerializerOptions::new()
+            .with_none_as_null(true)
+            .with_skip_empty_collections(true)
+            .with_unit_variant_format(FirestoreUnitVariantFormat::Map);
+        let doc = FirestoreSerializerOptions::sync_scope(options, || {
+            firestore_document_from_serializable("users/u1", &user)
+        })
+        .unwrap();
+        assert_eq!(
+            doc.fields.get("email").unwrap().value_type,
+            Some(value::ValueType::NullValue(0))
+        );
+        assert!(!doc.fields.contains_key("tags"));
+        assert!(!doc.fields.contains_key("attributes"));
+        match doc.fields.get("status").unwrap().value_type {
+            Some(value::ValueType::MapValue(ref map)) => {
+                assert_eq!(map.fields.keys().collect::<Vec<_>>(), vec!["Active"]);
+            }
+            ref other => panic!("Unexpected status value: {other:?}"),
+        }
+
+        let restored: User = firestore_document_to_serializable(&doc).unwrap();
+        assert_eq!(restored, user);
+    }
+}
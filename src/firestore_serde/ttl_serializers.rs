@@ -0,0 +1,11 @@
+/// Serializes the expiry time of a document for a Firestore TTL policy.
+///
+/// TTL policies only delete documents with a native Timestamp in the field, while `DateTime<Utc>`
+/// serializes as a string by default, so the field has to be written as a timestamp.
+/// The same field is used to delete the expired documents immediately with
+/// [`FirestoreDeleteDocIdBuilder::expired`](crate::delete_builder::FirestoreDeleteDocIdBuilder::expired).
+pub use crate::serialize_as_timestamp as serialize_as_ttl_expiry;
+
+/// Serializes the optional expiry time of a document for a Firestore TTL policy.
+/// Documents without the field (`None`) never expire.
+pub use crate::serialize_as_optional_timestamp as serialize_as_optional_ttl_expiry;
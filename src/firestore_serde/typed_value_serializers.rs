@@ -0,0 +1,60 @@
+/// Serializes a [`FirestoreValue`](crate::FirestoreValue) keeping its Firestore type, e.g. to store it as JSON.
+///
+/// Values are written in the Firestore JSON representation used by the REST API,
+/// e.g. `{"timestampValue":"2024-01-01T00:00:00Z"}`, so that timestamps, references
+/// and bytes aren't read back as strings.
+pub mod serialize_as_typed_value {
+    use crate::firestore_serde::proto_json::{value_from_json, value_to_json};
+    use crate::FirestoreValue;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde_json::Value as JsonValue;
+
+    pub fn serialize<S>(value: &FirestoreValue, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        value_to_json(&value.value)
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<FirestoreValue, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        value_from_json(&JsonValue::deserialize(deserializer)?)
+            .map(FirestoreValue::from)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serializes a list of [`FirestoreValue`](crate::FirestoreValue)s keeping their Firestore types.
+pub mod serialize_as_typed_values {
+    use crate::firestore_serde::proto_json::{value_from_json, value_to_json};
+    use crate::FirestoreValue;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde_json::Value as JsonValue;
+
+    pub fn serialize<S>(values: &[FirestoreValue], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        values
+            .iter()
+            .map(|value| value_to_json(&value.value))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<FirestoreValue>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Vec::<JsonValue>::deserialize(deserializer)?
+            .iter()
+            .map(|json| value_from_json(json).map(FirestoreValue::from))
+            .collect::<Result<_, _>>()
+            .map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,422 @@
+use crate::db::split_document_path;
+use crate::{FirestoreResult, FirestoreValue};
+use gcloud_sdk::google::firestore::v1::{value, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+tokio::task_local! {
+    static FIRESTORE_VALUE_TRANSFORMERS: FirestoreValueTransformers;
+}
+
+/// The location of a value passed to a [`FirestoreValueTransformer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FirestoreValueTransformContext<'a> {
+    /// The ID of the collection of the document.
+    pub collection_id: &'a str,
+    /// The full path of the document, empty for documents being created without a path.
+    pub document_path: &'a str,
+    /// The configured field path the value belongs to.
+    pub field_path: &'a str,
+}
+
+/// Transforms field values when objects are serialized to documents and back,
+/// e.g. to encrypt and decrypt or to redact sensitive fields.
+///
+/// Transformers are registered for field paths of collections in [`FirestoreValueTransformers`].
+/// They are invoked for every scalar value under the configured paths: maps and arrays are
+/// traversed by the library, and `null`s are passed through as is.
+pub trait FirestoreValueTransformer: Send + Sync {
+    /// Transforms a value of a serialized object before it is written to Firestore.
+    fn on_serialize(
+        &self,
+        context: &FirestoreValueTransformContext<'_>,
+        value: FirestoreValue,
+    ) -> FirestoreResult<FirestoreValue>;
+
+    /// Transforms a value read from Firestore before it is deserialized into an object.
+    fn on_deserialize(
+        &self,
+        context: &FirestoreValueTransformContext<'_>,
+        value: FirestoreValue,
+    ) -> FirestoreResult<FirestoreValue>;
+}
+
+#[derive(Clone)]
+struct FirestoreValueTransformerRule {
+    field_path: String,
+    segments: Vec<String>,
+    transformer: Arc<dyn FirestoreValueTransformer>,
+}
+
+/// A set of [`FirestoreValueTransformer`]s configured for field paths of collections.
+///
+/// The transformers are applied by the document serialization functions
+/// ([`FirestoreDb::serialize_to_doc`](crate::FirestoreDb::serialize_to_doc),
+/// [`FirestoreDb::deserialize_doc_to`](crate::FirestoreDb::deserialize_doc_to)) and so by all the
+/// object-level operations running in [`FirestoreValueTransformers::scope`].
+/// Values in query filters and field transforms aren't transformed.
+///
+/// As with other task-local settings, the scope doesn't cover tasks spawned from it.
+///
+/// # Examples
+///
+/// ```rust
+/// use firestore::*;
+///
+/// struct Redact;
+///
+/// impl FirestoreValueTransformer for Redact {
+///     fn on_serialize(
+///         &self,
+///         _context: &FirestoreValueTransformContext<'_>,
+///         value: FirestoreValue,
+///     ) -> FirestoreResult<FirestoreValue> {
+///         Ok(value)
+///     }
+///
+///     fn on_deserialize(
+///         &self,
+///         _context: &FirestoreValueTransformContext<'_>,
+///         _value: FirestoreValue,
+///     ) -> FirestoreResult<FirestoreValue> {
+///         Ok("<redacted>".into())
+///     }
+/// }
+///
+/// let transformers = FirestoreValueTransformers::new()
+///     .with_transformer("users", ["email", "addresses.street"], Redact);
+/// ```
+#[derive(Clone, Default)]
+pub struct FirestoreValueTransformers {
+    collections: HashMap<String, Vec<FirestoreValueTransformerRule>>,
+}
+
+impl std::fmt::Debug for FirestoreValueTransformers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug_map = f.debug_map();
+        for (collection_id, rules) in self.collections.iter() {
+            let field_paths: Vec<&str> =
+                rules.iter().map(|rule| rule.field_path.as_str()).collect();
+            debug_map.entry(collection_id, &field_paths);
+        }
+        debug_map.finish()
+    }
+}
+
+impl FirestoreValueTransformers {
+    /// Creates an empty set of transformers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the transformer for the field paths of the documents in the collections with the specified ID.
+    /// Nested fields are separated by dots, e.g. `address.street`.
+    pub fn with_transformer<I, S, T>(
+        mut self,
+        collection_id: &str,
+        field_paths: I,
+        transformer: T,
+    ) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+        T: FirestoreValueTransformer + 'static,
+    {
+        let transformer: Arc<dyn FirestoreValueTransformer> = Arc::new(transformer);
+        let rules = self
+            .collections
+            .entry(collection_id.to_string())
+            .or_default();
+        for field_path in field_paths {
+            let field_path = field_path.as_ref();
+            rules.push(FirestoreValueTransformerRule {
+                field_path: field_path.to_string(),
+                segments: field_path
+                    .split('.')
+                    .map(|segment| segment.replace('`', ""))
+                    .collect(),
+                transformer: transformer.clone(),
+            });
+        }
+        self
+    }
+
+    /// Runs the future serializing and deserializing objects with the transformers.
+    pub async fn scope<F>(transformers: Self, future: F) -> F::Output
+    where
+        F: Future,
+    {
+        FIRESTORE_VALUE_TRANSFORMERS
+            .scope(transformers, future)
+            .await
+    }
+
+    /// Runs the function serializing and deserializing objects with the transformers.
+    pub fn sync_scope<F, R>(transformers: Self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        FIRESTORE_VALUE_TRANSFORMERS.sync_scope(transformers, f)
+    }
+
+    /// Returns the transformers of the enclosing scope, if any.
+    pub(crate) fn current_override() -> Option<Self> {
+        FIRESTORE_VALUE_TRANSFORMERS
+            .try_with(|transformers| transformers.clone())
+            .ok()
+    }
+
+    /// Runs the future with the transformers if they are specified, used by the fluent builders.
+    pub(crate) async fn opt_scope<F>(transformers: Option<Self>, future: F) -> F::Output
+    where
+        F: Future,
+    {
+        match transformers {
+            Some(transformers) => Self::scope(transformers, future).await,
+            None => future.await,
+        }
+    }
+
+    /// Runs the function with the transformers if they are specified, used by the fluent builders.
+    pub(crate) fn opt_sync_scope<F, R>(transformers: Option<Self>, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        match transformers {
+            Some(transformers) => Self::sync_scope(transformers, f),
+            None => f(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FirestoreValueTransformDirection {
+    Serialize,
+    Deserialize,
+}
+
+/// Applies the transformers of the current scope to the fields of a document
+/// of the collection, or of the collection in the document path if it isn't specified.
+pub(crate) fn firestore_transform_document_fields(
+    fields: &mut HashMap<String, Value>,
+    document_path: &str,
+    collection_id: Option<&str>,
+    direction: FirestoreValueTransformDirection,
+) -> FirestoreResult<()> {
+    FIRESTORE_VALUE_TRANSFORMERS
+        .try_with(|transformers| {
+            transformers.transform_document_fields(fields, document_path, collection_id, direction)
+        })
+        .unwrap_or(Ok(()))
+}
+
+impl FirestoreValueTransformers {
+    fn transform_document_fields(
+        &self,
+        fields: &mut HashMap<String, Value>,
+        document_path: &str,
+        collection_id: Option<&str>,
+        direction: FirestoreValueTransformDirection,
+    ) -> FirestoreResult<()> {
+        let collection_id = collection_id
+            .unwrap_or_else(|| split_document_path(split_document_path(document_path).0).1);
+
+        if let Some(rules) = self.collections.get(collection_id) {
+            for rule in rules {
+                let context = FirestoreValueTransformContext {
+                    collection_id,
+                    document_path,
+                    field_path: rule.field_path.as_str(),
+                };
+                transform_fields_at(fields, &rule.segments, &|value| match direction {
+                    FirestoreValueTransformDirection::Serialize => {
+                        rule.transformer.on_serialize(&context, value)
+                    }
+                    FirestoreValueTransformDirection::Deserialize => {
+                        rule.transformer.on_deserialize(&context, value)
+                    }
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+type FirestoreValueTransformFn<'a> = dyn Fn(FirestoreValue) -> FirestoreResult<FirestoreValue> + 'a;
+
+fn transform_fields_at(
+    fields: &mut HashMap<String, Value>,
+    segments: &[String],
+    transform: &FirestoreValueTransformFn<'_>,
+) -> FirestoreResult<()> {
+    if let Some((first, rest)) = segments.split_first() {
+        if let Some(value) = fields.get_mut(first) {
+            transform_value_at(value, rest, transform)?;
+        }
+    }
+    Ok(())
+}
+
+fn transform_value_at(
+    value: &mut Value,
+    segments: &[String],
+    transform: &FirestoreValueTransformFn<'_>,
+) -> FirestoreResult<()> {
+    match &mut value.value_type {
+        Some(value::ValueType::ArrayValue(array)) => {
+            for element in array.values.iter_mut() {
+                transform_value_at(element, segments, transform)?;
+            }
+        }
+        Some(value::ValueType::MapValue(map)) => {
+            if segments.is_empty() {
+                for field_value in map.fields.values_mut() {
+                    transform_value_at(field_value, segments, transform)?;
+                }
+            } else {
+                transform_fields_at(&mut map.fields, segments, transform)?;
+            }
+        }
+        None | Some(value::ValueType::NullValue(_)) => {}
+        Some(_) if segments.is_empty() => {
+            let transformed = transform(FirestoreValue::from(std::mem::take(value)))?;
+            *value = transformed.value;
+        }
+        Some(_) => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{firestore_document_from_serializable, firestore_document_to_serializable};
+    use serde::{Deserialize, Serialize};
+
+    struct Reverse;
+
+    fn reverse(value: FirestoreValue) -> FirestoreValue {
+        match value.value.value_type {
+            Some(value::ValueType::StringValue(s)) => s.chars().rev().collect::<String>().into(),
+            _ => value,
+        }
+    }
+
+    impl FirestoreValueTransformer for Reverse {
+        fn on_serialize(
+            &self,
+            _context: &FirestoreValueTransformContext<'_>,
+            value: FirestoreValue,
+        ) -> FirestoreResult<FirestoreValue> {
+            Ok(reverse(value))
+        }
+
+        fn on_deserialize(
+            &self,
+            _context: &FirestoreValueTransformContext<'_>,
+            value: FirestoreValue,
+        ) -> FirestoreResult<FirestoreValue> {
+            Ok(reverse(value))
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Address {
+        street: String,
+        city: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct User {
+        name: String,
+        email: Option<String>,
+        phones: Vec<String>,
+        addresses: Vec<Address>,
+    }
+
+    fn string_field<'a>(fields: &'a HashMap<String, Value>, name: &str) -> &'a value::ValueType {
+        fields.get(name).unwrap().value_type.as_ref().unwrap()
+    }
+
+    #[test]
+    fn transforms_configured_fields() {
+        let user = User {
+            name: "Alice".to_string(),
+            email: Some("alice@example.com".to_string()),
+            phones: vec!["123".to_string(), "456".to_string()],
+            addresses: vec![Address {
+                street: "Main".to_string(),
+                city: "Paris".to_string(),
+            }],
+        };
+        let transformers = FirestoreValueTransformers::new().with_transformer(
+            "users",
+            ["email", "phones", "addresses.street"],
+            Reverse,
+        );
+
+        let doc = FirestoreValueTransformers::sync_scope(transformers.clone(), || {
+            firestore_document_from_serializable("projects/p/databases/d/documents/users/u1", &user)
+        })
+        .unwrap();
+
+        assert_eq!(
+            string_field(&doc.fields, "name"),
+            &value::ValueType::StringValue("Alice".to_string())
+        );
+        assert_eq!(
+            string_field(&doc.fields, "email"),
+            &value::ValueType::StringValue("moc.elpmaxe@ecila".to_string())
+        );
+        match string_field(&doc.fields, "addresses") {
+            value::ValueType::ArrayValue(addresses) => match &addresses.values[0].value_type {
+                Some(value::ValueType::MapValue(address)) => {
+                    assert_eq!(
+                        string_field(&address.fields, "street"),
+                        &value::ValueType::StringValue("niaM".to_string())
+                    );
+                    assert_eq!(
+                        string_field(&address.fields, "city"),
+                        &value::ValueType::StringValue("Paris".to_string())
+                    );
+                }
+                other => panic!("Unexpected address: {other:?}"),
+            },
+            other => panic!("Unexpected addresses: {other:?}"),
+        }
+
+        let raw: User = firestore_document_to_serializable(&doc).unwrap();
+        assert_eq!(raw.phones, vec!["321".to_string(), "654".to_string()]);
+
+        let restored: User = FirestoreValueTransformers::sync_scope(transformers, || {
+            firestore_document_to_serializable(&doc)
+        })
+        .unwrap();
+        assert_eq!(restored, user);
+    }
+
+    #[test]
+    fn ignores_other_collections() {
+        let user = User {
+            name: "Bob".to_string(),
+            email: None,
+            phones: vec![],
+            addresses: vec![],
+        };
+        let transformers =
+            FirestoreValueTransformers::new().with_transformer("users", ["name"], Reverse);
+
+        let doc = FirestoreValueTransformers::sync_scope(transformers, || {
+            firestore_document_from_serializable(
+                "projects/p/databases/d/documents/admins/a1",
+                &user,
+            )
+        })
+        .unwrap();
+        assert_eq!(
+            string_field(&doc.fields, "name"),
+            &value::ValueType::StringValue("Bob".to_string())
+        );
+    }
+}
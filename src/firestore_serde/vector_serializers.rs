@@ -128,3 +128,32 @@ impl<'de> serde::Deserialize<'de> for FirestoreVector {
         deserializer.deserialize_any(FirestoreVectorVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcloud_sdk::google::firestore::v1::value::ValueType;
+
+    #[test]
+    fn test_vector_value_representation() {
+        let value = FirestoreValue::from_vector(vec![1.0, 2.5]);
+
+        match &value.value.value_type {
+            Some(ValueType::MapValue(map_value)) => {
+                assert_eq!(
+                    map_value.fields.get("__type__").unwrap().value_type,
+                    Some(ValueType::StringValue("__vector__".to_string()))
+                );
+                assert!(map_value.fields.contains_key("value"));
+            }
+            other => panic!("Unexpected vector value: {:?}", other),
+        }
+
+        assert_eq!(
+            value.as_vector(),
+            Some(FirestoreVector::new(vec![1.0, 2.5]))
+        );
+        let number: FirestoreValue = 1.0.into();
+        assert_eq!(number.as_vector(), None);
+    }
+}
@@ -1,6 +1,16 @@
-use gcloud_sdk::google::firestore::v1::Value;
+use crate::FirestoreVector;
+use gcloud_sdk::google::firestore::v1::value::ValueType;
+use gcloud_sdk::google::firestore::v1::{ArrayValue, Value};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 
+/// A Firestore vector value (an embedding) as stored in vector fields and used as the
+/// query vector of `find_nearest` queries.
+///
+/// This is the same type as [`FirestoreVector`]; it serializes to the Firestore
+/// `{ "__type__": "__vector__", "value": [...] }` map representation.
+pub type FirestoreVectorValue = FirestoreVector;
+
 /// Represents a Firestore value, wrapping the underlying gRPC `Value` type.
 ///
 /// This struct provides a convenient way to work with Firestore's native data types
@@ -62,4 +72,136 @@ impl FirestoreValue {
             ),
         })
     }
+
+    /// Creates a `FirestoreValue` representing a Firestore vector value.
+    ///
+    /// # Arguments
+    /// * `vector`: The vector components, convertible to [`FirestoreVector`].
+    pub fn from_vector<V>(vector: V) -> Self
+    where
+        V: Into<FirestoreVector>,
+    {
+        vector.into().into()
+    }
+
+    /// Returns the vector if this value is a Firestore vector value
+    /// (a map with `__type__: __vector__`), otherwise `None`.
+    pub fn as_vector(&self) -> Option<FirestoreVector> {
+        match &self.value.value_type {
+            Some(gcloud_sdk::google::firestore::v1::value::ValueType::MapValue(map_value))
+                if matches!(
+                    map_value.fields.get("__type__").and_then(|v| v.value_type.as_ref()),
+                    Some(gcloud_sdk::google::firestore::v1::value::ValueType::StringValue(tag)) if tag == "__vector__"
+                ) =>
+            {
+                serde::Deserialize::deserialize(self.clone()).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Compares values using the Firestore ordering across all value types.
+pub(crate) fn compare_field_values(a: &ValueType, b: &ValueType) -> Ordering {
+    match (a, b) {
+        (ValueType::BooleanValue(a_val), ValueType::BooleanValue(b_val)) => a_val.cmp(b_val),
+        (ValueType::IntegerValue(a_val), ValueType::IntegerValue(b_val)) => a_val.cmp(b_val),
+        (ValueType::IntegerValue(a_val), ValueType::DoubleValue(b_val)) => {
+            compare_numbers(*a_val as f64, *b_val)
+        }
+        (ValueType::DoubleValue(a_val), ValueType::IntegerValue(b_val)) => {
+            compare_numbers(*a_val, *b_val as f64)
+        }
+        (ValueType::DoubleValue(a_val), ValueType::DoubleValue(b_val)) => {
+            compare_numbers(*a_val, *b_val)
+        }
+        (ValueType::TimestampValue(a_val), ValueType::TimestampValue(b_val)) => {
+            (a_val.seconds, a_val.nanos).cmp(&(b_val.seconds, b_val.nanos))
+        }
+        (ValueType::StringValue(a_val), ValueType::StringValue(b_val)) => a_val.cmp(b_val),
+        (ValueType::BytesValue(a_val), ValueType::BytesValue(b_val)) => a_val.cmp(b_val),
+        (ValueType::ReferenceValue(a_val), ValueType::ReferenceValue(b_val)) => {
+            a_val.split('/').cmp(b_val.split('/'))
+        }
+        (ValueType::GeoPointValue(a_val), ValueType::GeoPointValue(b_val)) => {
+            compare_numbers(a_val.latitude, b_val.latitude)
+                .then_with(|| compare_numbers(a_val.longitude, b_val.longitude))
+        }
+        (ValueType::ArrayValue(a_val), ValueType::ArrayValue(b_val)) => {
+            compare_value_iters(array_values(a_val), array_values(b_val))
+        }
+        (ValueType::MapValue(a_val), ValueType::MapValue(b_val)) => {
+            let mut a_fields: Vec<_> = a_val.fields.iter().collect();
+            let mut b_fields: Vec<_> = b_val.fields.iter().collect();
+            a_fields.sort_by_key(|(key, _)| *key);
+            b_fields.sort_by_key(|(key, _)| *key);
+            a_fields
+                .iter()
+                .zip(b_fields.iter())
+                .map(|((a_key, a_value), (b_key, b_value))| {
+                    a_key.cmp(b_key).then_with(|| {
+                        match (&a_value.value_type, &b_value.value_type) {
+                            (Some(a_value), Some(b_value)) => {
+                                compare_field_values(a_value, b_value)
+                            }
+                            (a_value, b_value) => a_value.is_some().cmp(&b_value.is_some()),
+                        }
+                    })
+                })
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or_else(|| a_fields.len().cmp(&b_fields.len()))
+        }
+        (a, b) => value_type_order(a).cmp(&value_type_order(b)),
+    }
+}
+
+/// Rank of the value type in the Firestore cross-type value ordering.
+pub(crate) fn value_type_order(value_type: &ValueType) -> u8 {
+    match value_type {
+        ValueType::NullValue(_) => 0,
+        ValueType::BooleanValue(_) => 1,
+        ValueType::IntegerValue(_) | ValueType::DoubleValue(_) => 2,
+        ValueType::TimestampValue(_) => 3,
+        ValueType::StringValue(_) => 4,
+        ValueType::BytesValue(_) => 5,
+        ValueType::ReferenceValue(_) => 6,
+        ValueType::GeoPointValue(_) => 7,
+        ValueType::ArrayValue(_) => 8,
+        ValueType::MapValue(_) => 9,
+    }
+}
+
+/// NaN is ordered before all other numbers and equal to itself, as in Firestore.
+fn compare_numbers(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+    }
+}
+
+fn compare_value_iters<'a, I>(a: I, b: I) -> Ordering
+where
+    I: Iterator<Item = &'a ValueType>,
+{
+    let mut b = b;
+    for a_val in a {
+        match b.next() {
+            Some(b_val) => match compare_field_values(a_val, b_val) {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            },
+            None => return Ordering::Greater,
+        }
+    }
+    if b.next().is_some() {
+        Ordering::Less
+    } else {
+        Ordering::Equal
+    }
+}
+
+pub(crate) fn array_values(array_value: &ArrayValue) -> impl Iterator<Item = &ValueType> {
+    array_value.values.iter().flat_map(|v| &v.value_type)
 }
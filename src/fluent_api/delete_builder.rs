@@ -2,12 +2,29 @@
 //!
 //! This module provides a fluent API to specify the document to be deleted,
 //! optionally including a parent path for sub-collections and preconditions
-//! for the delete operation.
+//! for the delete operation. It also supports deleting all documents matching
+//! a filter in batches.
 
+use crate::db::firestore_timeout_scope;
+use crate::errors::*;
+use crate::select_filter_builder::FirestoreQueryFilterBuilder;
 use crate::{
-    FirestoreBatch, FirestoreBatchWriter, FirestoreDeleteSupport, FirestoreResult,
-    FirestoreTransaction, FirestoreWritePrecondition,
+    FirestoreBatch, FirestoreBatchWriter, FirestoreCollectionPath, FirestoreDb,
+    FirestoreDeleteSupport, FirestoreEntity, FirestoreQueryCollection, FirestoreQueryFilter,
+    FirestoreQueryParams, FirestoreQuerySupport, FirestoreResult, FirestoreRetryPolicy,
+    FirestoreTimestamp, FirestoreTransaction, FirestoreWritePrecondition,
 };
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use gcloud_sdk::google::firestore::v1::{precondition, write, Document, Precondition, Write};
+use gcloud_sdk::google::rpc::Status;
+use rsb_derive::Builder;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// The maximum number of deletes in a single batch, limited by Firestore `BatchWrite`.
+pub const FIRESTORE_MAX_BATCH_DELETE_SIZE: usize = 500;
 
 /// The initial builder for a Firestore delete operation.
 ///
@@ -41,6 +58,28 @@ where
     pub fn from(self, collection_id: &str) -> FirestoreDeleteDocIdBuilder<'a, D> {
         FirestoreDeleteDocIdBuilder::new(self.db, collection_id.to_string())
     }
+
+    /// Specifies the (sub)collection to delete the document from, including its parent document.
+    ///
+    /// # Arguments
+    /// * `path`: The [`FirestoreCollectionPath`] created with [`ParentPathBuilder::collection`](crate::ParentPathBuilder::collection).
+    ///
+    /// # Returns
+    /// A [`FirestoreDeleteDocIdBuilder`] to specify the document ID and other options.
+    #[inline]
+    pub fn from_path(self, path: &FirestoreCollectionPath) -> FirestoreDeleteDocIdBuilder<'a, D> {
+        self.from(path.collection_id()).parent(path.parent())
+    }
+
+    /// Deletes the document of the entity from its collection (see [`FirestoreEntity`]).
+    #[inline]
+    pub fn entity<T>(self, entity: &T) -> FirestoreDeleteExecuteBuilder<'a, D>
+    where
+        T: FirestoreEntity,
+    {
+        self.from(T::COLLECTION_ID)
+            .document_id(entity.document_id())
+    }
 }
 
 /// A builder for specifying the document ID and options for a delete operation.
@@ -105,6 +144,27 @@ where
         }
     }
 
+    /// Only applies the delete if the document exists. A shortcut for
+    /// `.precondition(FirestoreWritePrecondition::Exists(true))`.
+    ///
+    /// Fails with [`FirestoreError::ConditionFailedError`](crate::errors::FirestoreError::ConditionFailedError)
+    /// if the document doesn't exist.
+    #[inline]
+    pub fn only_if_exists(self) -> Self {
+        self.precondition(FirestoreWritePrecondition::Exists(true))
+    }
+
+    /// Only applies the delete if the document was last updated at `update_time`, providing
+    /// compare-and-swap semantics for document versions without transactions.
+    /// A shortcut for `.precondition(FirestoreWritePrecondition::UpdateTime(update_time))`.
+    ///
+    /// Fails with [`FirestoreError::ConditionFailedError`](crate::errors::FirestoreError::ConditionFailedError)
+    /// if the document was modified since then or doesn't exist.
+    #[inline]
+    pub fn only_if_update_time(self, update_time: DateTime<Utc>) -> Self {
+        self.precondition(FirestoreWritePrecondition::UpdateTime(update_time))
+    }
+
     /// Specifies the ID of the document to delete.
     ///
     /// # Arguments
@@ -127,6 +187,222 @@ where
     }
 }
 
+impl<'a> FirestoreDeleteDocIdBuilder<'a, FirestoreDb> {
+    /// Deletes all documents matching the filter instead of a single document.
+    ///
+    /// # Arguments
+    /// * `filter`: A closure building the filter of the documents to delete.
+    ///
+    /// # Returns
+    /// A [`FirestoreDeleteByQueryBuilder`] to configure and execute the batch delete.
+    #[inline]
+    pub fn filter<FN>(self, filter: FN) -> FirestoreDeleteByQueryBuilder<'a>
+    where
+        FN: Fn(FirestoreQueryFilterBuilder) -> Option<FirestoreQueryFilter>,
+    {
+        let params =
+            FirestoreQueryParams::new(FirestoreQueryCollection::Single(self.collection_id))
+                .opt_parent(self.parent);
+        let filter_builder =
+            FirestoreQueryFilterBuilder::for_query(self.db.get_documents_path(), &params);
+        FirestoreDeleteByQueryBuilder::new(self.db, params.opt_filter(filter(filter_builder)))
+    }
+
+    /// Deletes the documents with the TTL expiry time in `field` at or before now,
+    /// e.g. to delete them immediately rather than waiting for the TTL policy
+    /// (see [`serialize_as_ttl_expiry`](crate::serialize_as_ttl_expiry)).
+    ///
+    /// # Returns
+    /// A [`FirestoreDeleteByQueryBuilder`] to configure and execute the batch delete.
+    #[inline]
+    pub fn expired<S>(self, field: S) -> FirestoreDeleteByQueryBuilder<'a>
+    where
+        S: AsRef<str>,
+    {
+        self.expired_at(field, Utc::now())
+    }
+
+    /// Deletes the documents with the TTL expiry time in `field` at or before `time`.
+    /// Documents without a timestamp in the field are ignored, the same way as by TTL policies.
+    #[inline]
+    pub fn expired_at<S>(self, field: S, time: DateTime<Utc>) -> FirestoreDeleteByQueryBuilder<'a>
+    where
+        S: AsRef<str>,
+    {
+        self.filter(|q| {
+            q.field(field.as_ref())
+                .less_than_or_equal(FirestoreTimestamp(time))
+        })
+    }
+}
+
+/// A document that couldn't be deleted by a batch delete.
+#[derive(Debug, PartialEq, Clone, Builder)]
+pub struct FirestoreBatchDeleteFailure {
+    /// The full path of the document.
+    pub document_path: String,
+    /// The error returned by Firestore.
+    pub status: Status,
+}
+
+/// Progress of a batch delete, reported after each batch.
+#[derive(Debug, PartialEq, Clone, Builder)]
+pub struct FirestoreBatchDeleteProgress {
+    /// Documents deleted by this batch.
+    pub deleted: usize,
+    /// Documents of this batch that couldn't be deleted.
+    pub failures: Vec<FirestoreBatchDeleteFailure>,
+    /// Documents deleted so far.
+    pub total_deleted: usize,
+    /// Documents that couldn't be deleted so far.
+    pub total_failed: usize,
+}
+
+impl FirestoreBatchDeleteProgress {
+    fn next_batch(
+        previous: Option<&FirestoreBatchDeleteProgress>,
+        document_paths: Vec<String>,
+        statuses: Vec<Status>,
+    ) -> FirestoreResult<Self> {
+        // A missing status would otherwise count the document as deleted
+        if statuses.len() != document_paths.len() {
+            return Err(FirestoreError::SystemError(FirestoreSystemError::new(
+                FirestoreErrorPublicGenericDetails::new("InvalidBatchWriteResponse".into()),
+                format!(
+                    "Batch write returned {} statuses for {} deletes",
+                    statuses.len(),
+                    document_paths.len()
+                ),
+            )));
+        }
+        let batch_size = document_paths.len();
+        let failures: Vec<FirestoreBatchDeleteFailure> = document_paths
+            .into_iter()
+            .zip(statuses)
+            .filter(|(_, status)| status.code != gcloud_sdk::tonic::Code::Ok as i32)
+            .map(|(document_path, status)| FirestoreBatchDeleteFailure::new(document_path, status))
+            .collect();
+        let deleted = batch_size - failures.len();
+        let (total_deleted, total_failed) = previous
+            .map(|progress| (progress.total_deleted, progress.total_failed))
+            .unwrap_or((0, 0));
+        Ok(Self {
+            deleted,
+            total_deleted: total_deleted + deleted,
+            total_failed: total_failed + failures.len(),
+            failures,
+        })
+    }
+}
+
+/// A builder for deleting all documents matching a filter in batches.
+///
+/// Created by calling [`FirestoreDeleteDocIdBuilder::filter`].
+#[derive(Clone, Debug)]
+pub struct FirestoreDeleteByQueryBuilder<'a> {
+    db: &'a FirestoreDb,
+    params: FirestoreQueryParams,
+    throttle: Option<Duration>,
+}
+
+impl<'a> FirestoreDeleteByQueryBuilder<'a> {
+    #[inline]
+    pub(crate) fn new(db: &'a FirestoreDb, params: FirestoreQueryParams) -> Self {
+        Self {
+            db,
+            params,
+            throttle: None,
+        }
+    }
+
+    /// Waits for the given duration between batches to limit the write rate.
+    #[inline]
+    pub fn throttle(self, delay: Duration) -> Self {
+        Self {
+            throttle: Some(delay),
+            ..self
+        }
+    }
+
+    /// Streams the ids of the matching documents and deletes them in batches of `batch_size`
+    /// (up to [`FIRESTORE_MAX_BATCH_DELETE_SIZE`]).
+    ///
+    /// Batches are written one by one when the returned stream is polled,
+    /// and the stream yields the progress after each batch.
+    /// Failed deletes of individual documents are reported in the progress and don't stop the stream.
+    ///
+    /// Documents are only deleted if they haven't changed since they were matched, so documents
+    /// updated concurrently (e.g. an expiry time extended after matching [`FirestoreDeleteDocIdBuilder::expired`])
+    /// are kept and reported as failures with a `FAILED_PRECONDITION` status.
+    pub async fn batch_delete(
+        self,
+        batch_size: usize,
+    ) -> FirestoreResult<BoxStream<'a, FirestoreResult<FirestoreBatchDeleteProgress>>> {
+        if batch_size == 0 || batch_size > FIRESTORE_MAX_BATCH_DELETE_SIZE {
+            return Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "batch_size".to_string(),
+                    format!(
+                        "Batch size must be between 1 and {FIRESTORE_MAX_BATCH_DELETE_SIZE}, got {batch_size}"
+                    ),
+                )),
+            ));
+        }
+
+        let writer = self.db.create_simple_batch_writer().await?;
+        let documents = self
+            .db
+            .stream_query_doc_with_errors(
+                self.params
+                    .with_return_only_fields(vec!["__name__".to_string()]),
+            )
+            .await?;
+        let throttle = self.throttle;
+
+        Ok(futures::stream::unfold(
+            (documents.chunks(batch_size), writer, None),
+            move |(mut batches, writer, previous)| async move {
+                let batch = batches.next().await?;
+                if let (Some(delay), Some(_)) = (throttle, &previous) {
+                    tokio::time::sleep(delay).await;
+                }
+
+                let result = async {
+                    let documents = batch
+                        .into_iter()
+                        .collect::<FirestoreResult<Vec<Document>>>()?;
+                    let writes = documents.iter().map(batch_delete_write).collect();
+                    let document_paths = documents.into_iter().map(|doc| doc.name).collect();
+                    let response = writer.write(writes).await?;
+                    FirestoreBatchDeleteProgress::next_batch(
+                        previous.as_ref(),
+                        document_paths,
+                        response.statuses,
+                    )
+                }
+                .await;
+
+                let next = match &result {
+                    Ok(progress) => Some(progress.clone()),
+                    Err(_) => previous,
+                };
+                Some((result, (batches, writer, next)))
+            },
+        )
+        .boxed())
+    }
+}
+
+fn batch_delete_write(doc: &Document) -> Write {
+    Write {
+        operation: Some(write::Operation::Delete(doc.name.clone())),
+        current_document: doc.update_time.map(|update_time| Precondition {
+            condition_type: Some(precondition::ConditionType::UpdateTime(update_time)),
+        }),
+        ..Default::default()
+    }
+}
+
 /// A builder for executing a Firestore delete operation or adding it to a batch/transaction.
 #[derive(Clone, Debug)]
 pub struct FirestoreDeleteExecuteBuilder<'a, D>
@@ -138,6 +414,8 @@ where
     document_id: String,
     parent: Option<String>,
     precondition: Option<FirestoreWritePrecondition>,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
 }
 
 impl<'a, D> FirestoreDeleteExecuteBuilder<'a, D>
@@ -159,6 +437,28 @@ where
             document_id,
             parent,
             precondition,
+            retry_policy: None,
+            timeout: None,
+        }
+    }
+
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of this operation, overriding [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    ///
+    /// The timeout is sent to Firestore as the gRPC deadline of the requests.
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
         }
     }
 
@@ -185,29 +485,57 @@ where
         }
     }
 
+    /// Only applies the delete if the document exists. A shortcut for
+    /// `.precondition(FirestoreWritePrecondition::Exists(true))`.
+    ///
+    /// Fails with [`FirestoreError::ConditionFailedError`](crate::errors::FirestoreError::ConditionFailedError)
+    /// if the document doesn't exist.
+    #[inline]
+    pub fn only_if_exists(self) -> Self {
+        self.precondition(FirestoreWritePrecondition::Exists(true))
+    }
+
+    /// Only applies the delete if the document was last updated at `update_time`, providing
+    /// compare-and-swap semantics for document versions without transactions.
+    /// A shortcut for `.precondition(FirestoreWritePrecondition::UpdateTime(update_time))`.
+    ///
+    /// Fails with [`FirestoreError::ConditionFailedError`](crate::errors::FirestoreError::ConditionFailedError)
+    /// if the document was modified since then or doesn't exist.
+    #[inline]
+    pub fn only_if_update_time(self, update_time: DateTime<Utc>) -> Self {
+        self.precondition(FirestoreWritePrecondition::UpdateTime(update_time))
+    }
+
     /// Executes the configured delete operation.
     ///
     /// # Returns
     /// A `FirestoreResult` indicating success or failure.
     pub async fn execute(self) -> FirestoreResult<()> {
-        if let Some(parent) = self.parent {
-            self.db
-                .delete_by_id_at(
-                    parent.as_str(),
-                    self.collection_id.as_str(),
-                    self.document_id,
-                    self.precondition,
-                )
-                .await
-        } else {
-            self.db
-                .delete_by_id(
-                    self.collection_id.as_str(),
-                    self.document_id,
-                    self.precondition,
-                )
-                .await
-        }
+        let retry_policy = self.retry_policy.clone();
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(retry_policy, async move {
+                if let Some(parent) = self.parent {
+                    self.db
+                        .delete_by_id_at(
+                            parent.as_str(),
+                            self.collection_id.as_str(),
+                            self.document_id,
+                            self.precondition,
+                        )
+                        .await
+                } else {
+                    self.db
+                        .delete_by_id(
+                            self.collection_id.as_str(),
+                            self.document_id,
+                            self.precondition,
+                        )
+                        .await
+                }
+            }),
+        )
+        .await
     }
 
     /// Adds this delete operation to a [`FirestoreTransaction`].
@@ -272,3 +600,137 @@ where
         }
     }
 }
+
+impl<'a> FirestoreDeleteExecuteBuilder<'a, FirestoreDb> {
+    /// Deletes the document in a transaction with a read of its current state, returning the deleted
+    /// document deserialized into type `T`, or `None` if it didn't exist.
+    ///
+    /// Useful to emit audit or outbox events with the deleted payload without racing a separate read.
+    /// The transaction is retried if the document is modified concurrently.
+    pub async fn execute_returning_previous<T>(self) -> FirestoreResult<Option<T>>
+    where
+        for<'de> T: Deserialize<'de> + Send,
+    {
+        let retry_policy = self.retry_policy.clone();
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(retry_policy, async move {
+                let parent = self
+                    .parent
+                    .unwrap_or_else(|| self.db.get_documents_path().clone());
+                self.db
+                    .write_returning_previous(
+                        parent.as_str(),
+                        self.collection_id.as_str(),
+                        self.document_id.as_str(),
+                        |transaction| {
+                            transaction
+                                .delete_by_id_at(
+                                    parent.as_str(),
+                                    self.collection_id.as_str(),
+                                    self.document_id.as_str(),
+                                    self.precondition.clone(),
+                                )
+                                .map(|_| ())
+                        },
+                    )
+                    .await
+            }),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FirestoreQueryFilterCompare;
+
+    #[test]
+    fn batch_delete_progress_accumulates() {
+        let first = FirestoreBatchDeleteProgress::next_batch(
+            None,
+            vec!["a".to_string(), "b".to_string()],
+            vec![Status::default(), Status::default()],
+        )
+        .unwrap();
+        assert_eq!(first.deleted, 2);
+        assert_eq!(first.total_deleted, 2);
+        assert!(first.failures.is_empty());
+
+        let failed = Status {
+            code: gcloud_sdk::tonic::Code::FailedPrecondition as i32,
+            ..Default::default()
+        };
+        let second = FirestoreBatchDeleteProgress::next_batch(
+            Some(&first),
+            vec!["c".to_string(), "d".to_string()],
+            vec![Status::default(), failed.clone()],
+        )
+        .unwrap();
+        assert_eq!(second.deleted, 1);
+        assert_eq!(second.total_deleted, 3);
+        assert_eq!(second.total_failed, 1);
+        assert_eq!(
+            second.failures,
+            vec![FirestoreBatchDeleteFailure::new("d".to_string(), failed)]
+        );
+    }
+
+    #[test]
+    fn batch_delete_progress_requires_status_per_document() {
+        assert!(FirestoreBatchDeleteProgress::next_batch(
+            None,
+            vec!["a".to_string(), "b".to_string()],
+            vec![Status::default()],
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn expired_at_filters_by_timestamp() {
+        let db = FirestoreDb::for_unit_tests().await;
+        let time = Utc::now();
+        let builder = db
+            .fluent()
+            .delete()
+            .from("sessions")
+            .expired_at("expires_at", time);
+
+        assert_eq!(
+            builder.params.filter,
+            Some(FirestoreQueryFilter::Compare(Some(
+                FirestoreQueryFilterCompare::LessThanOrEqual(
+                    "expires_at".to_string(),
+                    FirestoreTimestamp(time).into()
+                )
+            )))
+        );
+    }
+
+    #[test]
+    fn batch_delete_write_requires_unchanged_documents() {
+        let update_time = gcloud_sdk::prost_types::Timestamp {
+            seconds: 1_700_000_000,
+            nanos: 0,
+        };
+        let write = batch_delete_write(&Document {
+            name: "projects/p/databases/(default)/documents/sessions/s1".to_string(),
+            update_time: Some(update_time),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            write.operation,
+            Some(write::Operation::Delete(
+                "projects/p/databases/(default)/documents/sessions/s1".to_string()
+            ))
+        );
+        assert_eq!(
+            write.current_document,
+            Some(Precondition {
+                condition_type: Some(precondition::ConditionType::UpdateTime(update_time)),
+            })
+        );
+    }
+}
@@ -200,6 +200,16 @@ impl FirestoreTransformFieldExpr {
         ))
     }
 
+    /// A shorter alias for [`Self::append_missing_elements`].
+    #[inline]
+    pub fn append_missing<I>(self, values: I) -> Option<FirestoreFieldTransform>
+    where
+        I: IntoIterator,
+        I::Item: Into<FirestoreValue>,
+    {
+        self.append_missing_elements(values)
+    }
+
     /// Specifies a "remove all from array" transformation for an array field.
     ///
     /// Atomically removes all instances of the given elements from an array field.
@@ -242,3 +252,39 @@ where
         self.and_then(|expr| expr.build_transform())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path;
+
+    #[allow(dead_code)]
+    struct TestStructure {
+        counter: u64,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn typed_transforms_with_paths() {
+        let t = FirestoreTransformBuilder::new();
+        let transforms = t.fields([
+            t.field(path!(TestStructure::counter)).increment(1),
+            t.field(path!(TestStructure::tags)).append_missing(["a"]),
+            None,
+        ]);
+
+        assert_eq!(
+            transforms,
+            vec![
+                FirestoreFieldTransform::new(
+                    "counter".to_string(),
+                    FirestoreFieldTransformType::Increment(1.into())
+                ),
+                FirestoreFieldTransform::new(
+                    "tags".to_string(),
+                    FirestoreFieldTransformType::AppendMissingElements(vec!["a".into()])
+                ),
+            ]
+        );
+    }
+}
@@ -4,9 +4,17 @@
 //! and the data to be inserted into Firestore. It supports inserting both raw
 //! [`Document`](gcloud_sdk::google::firestore::v1::Document) types and serializable Rust objects.
 
-use crate::{FirestoreCreateSupport, FirestoreResult};
+use crate::db::{firestore_timeout_scope, split_document_path};
+use crate::errors::*;
+use crate::firestore_entity::firestore_entity_value_transformers;
+use crate::{
+    FirestoreBatch, FirestoreBatchWriter, FirestoreCollectionPath, FirestoreCreateSupport,
+    FirestoreCreated, FirestoreDb, FirestoreEntity, FirestoreResult, FirestoreRetryPolicy,
+    FirestoreSerializerOptions, FirestoreTransaction, FirestoreValueTransformers,
+};
 use gcloud_sdk::google::firestore::v1::Document;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 /// The initial builder for a Firestore insert operation.
 ///
@@ -38,7 +46,36 @@ where
     /// A [`FirestoreInsertDocIdBuilder`] to specify the document ID or have it auto-generated.
     #[inline]
     pub fn into(self, collection_id: &str) -> FirestoreInsertDocIdBuilder<'a, D> {
-        FirestoreInsertDocIdBuilder::new(self.db, collection_id.to_string())
+        FirestoreInsertDocIdBuilder::new(self.db, collection_id.to_string(), None)
+    }
+
+    /// Specifies the (sub)collection to insert the document into, including its parent document.
+    ///
+    /// # Arguments
+    /// * `path`: The [`FirestoreCollectionPath`] created with [`ParentPathBuilder::collection`](crate::ParentPathBuilder::collection).
+    ///
+    /// # Returns
+    /// A [`FirestoreInsertDocIdBuilder`] to specify the document ID or have it auto-generated.
+    #[inline]
+    pub fn into_path(self, path: &FirestoreCollectionPath) -> FirestoreInsertDocIdBuilder<'a, D> {
+        FirestoreInsertDocIdBuilder::new(
+            self.db,
+            path.collection_id().to_string(),
+            Some(path.parent().to_string()),
+        )
+    }
+
+    /// Inserts the entity into its collection with its document ID (see [`FirestoreEntity`]).
+    #[inline]
+    pub fn entity<T>(self, entity: &'a T) -> FirestoreInsertObjExecuteBuilder<'a, D, T>
+    where
+        T: FirestoreEntity + Serialize + Sync + Send,
+        for<'de> T: Deserialize<'de>,
+    {
+        self.into(T::COLLECTION_ID)
+            .document_id(entity.document_id())
+            .object(entity)
+            .timestamp_fields(T::TIMESTAMP_FIELDS)
     }
 }
 
@@ -53,6 +90,7 @@ where
 {
     db: &'a D,
     collection_id: String,
+    parent: Option<String>,
 }
 
 impl<'a, D> FirestoreInsertDocIdBuilder<'a, D>
@@ -61,8 +99,12 @@ where
 {
     /// Creates a new `FirestoreInsertDocIdBuilder`.
     #[inline]
-    pub(crate) fn new(db: &'a D, collection_id: String) -> Self {
-        Self { db, collection_id }
+    pub(crate) fn new(db: &'a D, collection_id: String, parent: Option<String>) -> Self {
+        Self {
+            db,
+            collection_id,
+            parent,
+        }
     }
 
     /// Specifies a user-defined ID for the new document.
@@ -83,6 +125,7 @@ where
             self.db,
             self.collection_id,
             Some(document_id.as_ref().to_string()),
+            self.parent,
         )
     }
 
@@ -92,7 +135,7 @@ where
     /// A [`FirestoreInsertDocObjBuilder`] to specify the document data.
     #[inline]
     pub fn generate_document_id(self) -> FirestoreInsertDocObjBuilder<'a, D> {
-        FirestoreInsertDocObjBuilder::new(self.db, self.collection_id, None)
+        FirestoreInsertDocObjBuilder::new(self.db, self.collection_id, None, self.parent)
     }
 }
 
@@ -118,12 +161,17 @@ where
 {
     /// Creates a new `FirestoreInsertDocObjBuilder`.
     #[inline]
-    pub(crate) fn new(db: &'a D, collection_id: String, document_id: Option<String>) -> Self {
+    pub(crate) fn new(
+        db: &'a D,
+        collection_id: String,
+        document_id: Option<String>,
+        parent: Option<String>,
+    ) -> Self {
         Self {
             db,
             collection_id,
             document_id,
-            parent: None,
+            parent,
             return_only_fields: None,
         }
     }
@@ -232,6 +280,8 @@ where
     parent: Option<String>,
     document: Document,
     return_only_fields: Option<Vec<String>>,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
 }
 
 impl<'a, D> FirestoreInsertDocExecuteBuilder<'a, D>
@@ -255,6 +305,28 @@ where
             parent,
             document,
             return_only_fields,
+            retry_policy: None,
+            timeout: None,
+        }
+    }
+
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of this operation, overriding [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    ///
+    /// The timeout is sent to Firestore as the gRPC deadline of the requests.
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
         }
     }
 
@@ -263,26 +335,33 @@ where
     /// # Returns
     /// A `FirestoreResult` containing the created [`Document`].
     pub async fn execute(self) -> FirestoreResult<Document> {
-        if let Some(parent) = self.parent {
-            self.db
-                .create_doc_at(
-                    parent.as_str(),
-                    self.collection_id.as_str(),
-                    self.document_id,
-                    self.document,
-                    self.return_only_fields,
-                )
-                .await
-        } else {
-            self.db
-                .create_doc(
-                    self.collection_id.as_str(),
-                    self.document_id,
-                    self.document,
-                    self.return_only_fields,
-                )
-                .await
-        }
+        let retry_policy = self.retry_policy.clone();
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(retry_policy, async move {
+                if let Some(parent) = self.parent {
+                    self.db
+                        .create_doc_at(
+                            parent.as_str(),
+                            self.collection_id.as_str(),
+                            self.document_id,
+                            self.document,
+                            self.return_only_fields,
+                        )
+                        .await
+                } else {
+                    self.db
+                        .create_doc(
+                            self.collection_id.as_str(),
+                            self.document_id,
+                            self.document,
+                            self.return_only_fields,
+                        )
+                        .await
+                }
+            }),
+        )
+        .await
     }
 }
 
@@ -299,6 +378,10 @@ where
     document_id: Option<String>,
     object: &'a T,
     return_only_fields: Option<Vec<String>>,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
+    serializer_options: Option<FirestoreSerializerOptions>,
+    timestamp_fields: &'static [&'static str],
 }
 
 impl<'a, D, T> FirestoreInsertObjExecuteBuilder<'a, D, T>
@@ -323,6 +406,48 @@ where
             document_id,
             object,
             return_only_fields,
+            retry_policy: None,
+            timeout: None,
+            serializer_options: None,
+            timestamp_fields: &[],
+        }
+    }
+
+    /// Writes the fields of the entity as timestamps (see [`FirestoreEntity::TIMESTAMP_FIELDS`]).
+    #[inline]
+    pub(crate) fn timestamp_fields(self, timestamp_fields: &'static [&'static str]) -> Self {
+        Self {
+            timestamp_fields,
+            ..self
+        }
+    }
+
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of this operation, overriding [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    ///
+    /// The timeout is sent to Firestore as the gRPC deadline of the requests.
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Sets the options of serializing the object, e.g. to write `None` values as `null`s.
+    #[inline]
+    pub fn serializer_options(self, options: FirestoreSerializerOptions) -> Self {
+        Self {
+            serializer_options: Some(options),
+            ..self
         }
     }
 
@@ -338,25 +463,151 @@ where
     where
         for<'de> O: Deserialize<'de>,
     {
-        if let Some(parent) = self.parent {
-            self.db
-                .create_obj_at(
-                    parent.as_str(),
-                    self.collection_id.as_str(),
-                    self.document_id,
-                    self.object,
-                    self.return_only_fields,
-                )
-                .await
-        } else {
-            self.db
-                .create_obj(
-                    self.collection_id.as_str(),
-                    self.document_id,
-                    self.object,
-                    self.return_only_fields,
-                )
-                .await
-        }
+        Ok(self.execute_with_id().await?.object)
+    }
+
+    /// Executes the configured insert operation and returns the created document
+    /// together with its ID, which is useful when the ID is generated by Firestore.
+    ///
+    /// # Type Parameters
+    /// * `O`: The type to deserialize the result into. Must implement `serde::Deserialize`.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing the [`FirestoreCreated`] document.
+    pub async fn execute_with_id<O>(self) -> FirestoreResult<FirestoreCreated<O>>
+    where
+        for<'de> O: Deserialize<'de>,
+    {
+        let retry_policy = self.retry_policy.clone();
+        let value_transformers =
+            firestore_entity_value_transformers(&self.collection_id, self.timestamp_fields);
+        let serializer_options = self.serializer_options;
+        FirestoreValueTransformers::opt_scope(
+            value_transformers,
+            FirestoreSerializerOptions::opt_scope(
+                serializer_options,
+                firestore_timeout_scope(
+                    self.timeout,
+                    FirestoreRetryPolicy::scope(retry_policy, async move {
+                        let input_doc =
+                            crate::firestore_serde::firestore_new_document_from_serializable(
+                                self.collection_id.as_str(),
+                                self.object,
+                            )?;
+                        let doc = if let Some(parent) = self.parent {
+                            self.db
+                                .create_doc_at(
+                                    parent.as_str(),
+                                    self.collection_id.as_str(),
+                                    self.document_id,
+                                    input_doc,
+                                    self.return_only_fields,
+                                )
+                                .await?
+                        } else {
+                            self.db
+                                .create_doc(
+                                    self.collection_id.as_str(),
+                                    self.document_id,
+                                    input_doc,
+                                    self.return_only_fields,
+                                )
+                                .await?
+                        };
+
+                        let (_, document_id) = split_document_path(doc.name.as_str());
+                        Ok(FirestoreCreated {
+                            document_id: document_id.to_string(),
+                            object: FirestoreDb::deserialize_doc_to(&doc)?,
+                            document_path: doc.name,
+                        })
+                    }),
+                ),
+            ),
+        )
+        .await
+    }
+
+    /// Adds this insert operation to a [`FirestoreTransaction`].
+    ///
+    /// The document ID must be specified, the transaction fails if the document already exists.
+    ///
+    /// # Arguments
+    /// * `transaction`: A mutable reference to the transaction to add this operation to.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing the mutable reference to the transaction.
+    #[inline]
+    pub fn add_to_transaction<'t>(
+        self,
+        transaction: &'a mut FirestoreTransaction<'t>,
+    ) -> FirestoreResult<&'a mut FirestoreTransaction<'t>> {
+        let document_id = self.document_id.ok_or_else(|| {
+            FirestoreError::InvalidParametersError(FirestoreInvalidParametersError::new(
+                FirestoreInvalidParametersPublicDetails::new(
+                    "document_id".to_string(),
+                    "Document ID is required to insert a document in a transaction".to_string(),
+                ),
+            ))
+        })?;
+        let value_transformers =
+            firestore_entity_value_transformers(&self.collection_id, self.timestamp_fields);
+        FirestoreValueTransformers::opt_sync_scope(value_transformers, move || {
+            FirestoreSerializerOptions::opt_sync_scope(self.serializer_options, move || {
+                if let Some(parent) = self.parent {
+                    transaction.create_object_at(
+                        parent.as_str(),
+                        self.collection_id.as_str(),
+                        document_id,
+                        self.object,
+                    )
+                } else {
+                    transaction.create_object(self.collection_id.as_str(), document_id, self.object)
+                }
+            })
+        })
+    }
+
+    /// Adds this insert operation to a [`FirestoreBatch`].
+    ///
+    /// The document ID must be specified, the batch fails if the document already exists.
+    ///
+    /// # Arguments
+    /// * `batch`: A mutable reference to the batch writer to add this operation to.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing the mutable reference to the batch, allowing for chaining.
+    #[inline]
+    pub fn add_to_batch<'t, W>(
+        self,
+        batch: &'a mut FirestoreBatch<'t, W>,
+    ) -> FirestoreResult<&'a mut FirestoreBatch<'t, W>>
+    where
+        W: FirestoreBatchWriter,
+    {
+        let document_id = self.document_id.ok_or_else(|| {
+            FirestoreError::InvalidParametersError(FirestoreInvalidParametersError::new(
+                FirestoreInvalidParametersPublicDetails::new(
+                    "document_id".to_string(),
+                    "Document ID is required to insert a document in a batch".to_string(),
+                ),
+            ))
+        })?;
+        let value_transformers =
+            firestore_entity_value_transformers(&self.collection_id, self.timestamp_fields);
+        FirestoreValueTransformers::opt_sync_scope(value_transformers, move || {
+            FirestoreSerializerOptions::opt_sync_scope(self.serializer_options, move || {
+                if let Some(parent) = self.parent {
+                    batch.create_object_at(
+                        parent.as_str(),
+                        self.collection_id.as_str(),
+                        document_id,
+                        self.object,
+                    )
+                } else {
+                    batch.create_object(self.collection_id.as_str(), document_id, self.object)
+                }
+            })
+        })
     }
 }
@@ -4,14 +4,20 @@
 //! or listing collection IDs under a parent document (or the database root).
 //! It supports pagination, ordering (for document listing), and projections.
 
+use crate::db::firestore_timeout_scope;
 use crate::{
+    FirestoreCollectionPath, FirestoreConsistencySelector, FirestoreDb,
     FirestoreListCollectionIdsParams, FirestoreListCollectionIdsResult, FirestoreListDocParams,
     FirestoreListDocResult, FirestoreListingSupport, FirestoreQueryOrder, FirestoreResult,
+    FirestoreRetryPolicy,
 };
+use chrono::{DateTime, Utc};
 use futures::stream::BoxStream;
+use futures::{future, StreamExt, TryStreamExt};
 use gcloud_sdk::google::firestore::v1::Document;
 use serde::Deserialize;
 use std::marker::PhantomData;
+use std::time::Duration;
 
 /// The initial builder for a Firestore list operation.
 ///
@@ -80,6 +86,18 @@ where
         FirestoreListingDocBuilder::new(self.db, params)
     }
 
+    /// Specifies the (sub)collection to list documents from, including its parent document.
+    ///
+    /// # Arguments
+    /// * `path`: The [`FirestoreCollectionPath`] created with [`ParentPathBuilder::collection`](crate::ParentPathBuilder::collection).
+    ///
+    /// # Returns
+    /// A [`FirestoreListingDocBuilder`] to further configure and execute the document listing.
+    #[inline]
+    pub fn from_path(self, path: &FirestoreCollectionPath) -> FirestoreListingDocBuilder<'a, D> {
+        self.from(path.collection_id()).parent(path.parent())
+    }
+
     /// Specifies that collection IDs should be listed.
     ///
     /// # Returns
@@ -98,6 +116,8 @@ where
 {
     db: &'a D,
     params: FirestoreListDocParams,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
 }
 
 impl<'a, D> FirestoreListingDocBuilder<'a, D>
@@ -107,7 +127,12 @@ where
     /// Creates a new `FirestoreListingDocBuilder`.
     #[inline]
     pub(crate) fn new(db: &'a D, params: FirestoreListDocParams) -> Self {
-        Self { db, params }
+        Self {
+            db,
+            params,
+            retry_policy: None,
+            timeout: None,
+        }
     }
 
     /// Specifies that the listed documents should be deserialized into a specific Rust type `T`.
@@ -123,7 +148,7 @@ where
         T: Send,
         for<'de> T: Deserialize<'de>,
     {
-        FirestoreListingObjBuilder::new(self.db, self.params)
+        FirestoreListingObjBuilder::new(self.db, self.params, self.retry_policy, self.timeout)
     }
 
     /// Specifies the parent document path for listing documents in a sub-collection.
@@ -159,6 +184,45 @@ where
         }
     }
 
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of each listing request, overriding
+    /// [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Lists the documents as they were at the specified time (a point-in-time read).
+    ///
+    /// The read time must be within
+    /// [`FirestoreDbOptions::point_in_time_read_window`](crate::FirestoreDbOptions::point_in_time_read_window).
+    ///
+    /// # Arguments
+    /// * `read_time`: The time to list the documents at.
+    ///
+    /// # Returns
+    /// The builder instance with the read time set.
+    #[inline]
+    pub fn read_at(self, read_time: DateTime<Utc>) -> Self {
+        Self {
+            params: self
+                .params
+                .with_consistency_selector(FirestoreConsistencySelector::ReadTime(read_time)),
+            ..self
+        }
+    }
+
     /// Specifies the order in which to sort the documents.
     ///
     /// # Arguments
@@ -186,7 +250,11 @@ where
     /// A `FirestoreResult` containing a [`FirestoreListDocResult`], which includes the documents
     /// for the current page and a potential next page token.
     pub async fn get_page(self) -> FirestoreResult<FirestoreListDocResult> {
-        self.db.list_doc(self.params).await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(self.retry_policy, self.db.list_doc(self.params)),
+        )
+        .await
     }
 
     /// Streams all documents matching the configuration, handling pagination automatically.
@@ -196,7 +264,11 @@ where
     /// # Returns
     /// A `FirestoreResult` containing a `BoxStream` of [`Document`]s.
     pub async fn stream_all<'b>(self) -> FirestoreResult<BoxStream<'b, Document>> {
-        self.db.stream_list_doc(self.params).await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(self.retry_policy, self.db.stream_list_doc(self.params)),
+        )
+        .await
     }
 
     /// Streams all documents matching the configuration, handling pagination automatically.
@@ -209,7 +281,14 @@ where
     pub async fn stream_all_with_errors<'b>(
         self,
     ) -> FirestoreResult<BoxStream<'b, FirestoreResult<Document>>> {
-        self.db.stream_list_doc_with_errors(self.params).await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.stream_list_doc_with_errors(self.params),
+            ),
+        )
+        .await
     }
 }
 
@@ -223,6 +302,8 @@ where
 {
     db: &'a D,
     params: FirestoreListDocParams,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
     _pd: PhantomData<T>,
 }
 
@@ -236,10 +317,14 @@ where
     pub(crate) fn new(
         db: &'a D,
         params: FirestoreListDocParams,
+        retry_policy: Option<FirestoreRetryPolicy>,
+        timeout: Option<Duration>,
     ) -> FirestoreListingObjBuilder<'a, D, T> {
         Self {
             db,
             params,
+            retry_policy,
+            timeout,
             _pd: PhantomData,
         }
     }
@@ -255,7 +340,11 @@ where
     where
         T: 'b,
     {
-        self.db.stream_list_obj(self.params).await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(self.retry_policy, self.db.stream_list_obj(self.params)),
+        )
+        .await
     }
 
     /// Streams all documents matching the configuration, deserializing them into type `T`
@@ -272,7 +361,14 @@ where
     where
         T: 'b,
     {
-        self.db.stream_list_obj_with_errors(self.params).await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.stream_list_obj_with_errors(self.params),
+            ),
+        )
+        .await
     }
 }
 
@@ -284,6 +380,8 @@ where
 {
     db: &'a D,
     params: FirestoreListCollectionIdsParams,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
 }
 
 impl<'a, D> FirestoreListCollectionIdsBuilder<'a, D>
@@ -296,6 +394,8 @@ where
         Self {
             db,
             params: FirestoreListCollectionIdsParams::new(),
+            retry_policy: None,
+            timeout: None,
         }
     }
 
@@ -334,13 +434,59 @@ where
         }
     }
 
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of each listing request, overriding
+    /// [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Lists the collections as they were at the specified time (a point-in-time read).
+    ///
+    /// The read time must be within
+    /// [`FirestoreDbOptions::point_in_time_read_window`](crate::FirestoreDbOptions::point_in_time_read_window).
+    ///
+    /// # Arguments
+    /// * `read_time`: The time to list the collections at.
+    ///
+    /// # Returns
+    /// The builder instance with the read time set.
+    #[inline]
+    pub fn read_at(self, read_time: DateTime<Utc>) -> Self {
+        Self {
+            params: self
+                .params
+                .with_consistency_selector(FirestoreConsistencySelector::ReadTime(read_time)),
+            ..self
+        }
+    }
+
     /// Retrieves a single page of collection IDs.
     ///
     /// # Returns
     /// A `FirestoreResult` containing a [`FirestoreListCollectionIdsResult`], which includes
     /// the collection IDs for the current page and a potential next page token.
     pub async fn get_page(self) -> FirestoreResult<FirestoreListCollectionIdsResult> {
-        self.db.list_collection_ids(self.params).await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.list_collection_ids(self.params),
+            ),
+        )
+        .await
     }
 
     /// Streams all collection IDs matching the configuration, handling pagination automatically.
@@ -350,7 +496,14 @@ where
     /// # Returns
     /// A `FirestoreResult` containing a `BoxStream` of `String` (collection IDs).
     pub async fn stream_all(self) -> FirestoreResult<BoxStream<'a, String>> {
-        self.db.stream_list_collection_ids(self.params).await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.stream_list_collection_ids(self.params),
+            ),
+        )
+        .await
     }
 
     /// Streams all collection IDs matching the configuration, handling pagination automatically.
@@ -362,8 +515,82 @@ where
     pub async fn stream_all_with_errors(
         self,
     ) -> FirestoreResult<BoxStream<'a, FirestoreResult<String>>> {
-        self.db
-            .stream_list_collection_ids_with_errors(self.params)
-            .await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.stream_list_collection_ids_with_errors(self.params),
+            ),
+        )
+        .await
+    }
+}
+
+impl<'a> FirestoreListCollectionIdsBuilder<'a, FirestoreDb> {
+    /// Streams the paths of all collections under the parent document (or the database root),
+    /// handling pagination automatically.
+    ///
+    /// The paths can be passed to the fluent builders with `from_path()`.
+    pub async fn stream_all_paths(
+        self,
+    ) -> FirestoreResult<BoxStream<'a, FirestoreResult<FirestoreCollectionPath>>> {
+        let parent = self
+            .params
+            .parent
+            .clone()
+            .unwrap_or_else(|| self.db.get_documents_path().clone());
+        self.stream_all_paths_of([parent]).await
+    }
+
+    /// Streams the paths of all collections under each of the specified parent documents,
+    /// one parent after another, handling pagination automatically.
+    ///
+    /// This is useful to enumerate sub-collections of many documents, e.g. of every user.
+    /// The parent specified with [`Self::parent`] is ignored.
+    ///
+    /// # Arguments
+    /// * `parents`: The full paths to the parent documents.
+    pub async fn stream_all_paths_of<I, S>(
+        self,
+        parents: I,
+    ) -> FirestoreResult<BoxStream<'a, FirestoreResult<FirestoreCollectionPath>>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let db = self.db;
+        let params = self.params;
+        let retry_policy = self.retry_policy;
+        let timeout = self.timeout;
+        let parents: Vec<String> = parents
+            .into_iter()
+            .map(|parent| parent.as_ref().to_string())
+            .collect();
+
+        Ok(futures::stream::iter(parents)
+            .then(move |parent| {
+                let params = params.clone().with_parent(parent.clone());
+                let retry_policy = retry_policy.clone();
+                async move {
+                    let collection_ids = firestore_timeout_scope(
+                        timeout,
+                        FirestoreRetryPolicy::scope(
+                            retry_policy,
+                            db.stream_list_collection_ids_with_errors(params),
+                        ),
+                    )
+                    .await;
+                    (parent, collection_ids)
+                }
+            })
+            .flat_map(|(parent, collection_ids)| match collection_ids {
+                Ok(collection_ids) => collection_ids
+                    .map_ok(move |collection_id| {
+                        FirestoreCollectionPath::new(parent.clone(), collection_id)
+                    })
+                    .boxed(),
+                Err(err) => futures::stream::once(future::ready(Err(err))).boxed(),
+            })
+            .boxed())
     }
 }
@@ -13,10 +13,15 @@
 //! - [`document_transform_builder`]: For specifying field transformations in update operations.
 //! - [`insert_builder`]: For constructing insert/create operations.
 //! - [`listing_builder`]: For listing documents or collection IDs.
+//! - [`multi_builder`]: For running several independent reads concurrently.
 //! - [`select_aggregation_builder`]: For building aggregation queries (e.g., count, sum, avg).
 //! - [`select_builder`]: For constructing query/select operations.
 //! - [`select_filter_builder`]: For building complex filter conditions for queries.
+//! - [`select_group_by_builder`]: For client-side group-by aggregations over query results.
+//! - [`select_split_builder`]: For queries split by filters with too many values.
+//! - [`select_page_builder`]: For fetching query results page by page with page tokens.
 //! - [`update_builder`]: For constructing update operations.
+//! - [`typed_collection`]: For collection handles bound to the type of their documents.
 //! ```
 
 // Linter allowance for functions that might have many arguments,
@@ -27,21 +32,28 @@ pub mod delete_builder;
 pub mod document_transform_builder;
 pub mod insert_builder;
 pub mod listing_builder;
+pub mod multi_builder;
 pub mod select_aggregation_builder;
 pub mod select_builder;
 pub mod select_filter_builder;
+pub mod select_group_by_builder;
+pub mod select_page_builder;
+pub mod select_split_builder;
+pub mod typed_collection;
 pub mod update_builder;
 
 use crate::delete_builder::FirestoreDeleteInitialBuilder;
 use crate::fluent_api::select_builder::FirestoreSelectInitialBuilder;
 use crate::insert_builder::FirestoreInsertInitialBuilder;
 use crate::listing_builder::FirestoreListingInitialBuilder;
+use crate::multi_builder::FirestoreMultiBuilder;
 use crate::update_builder::FirestoreUpdateInitialBuilder;
 use crate::{
     FirestoreAggregatedQuerySupport, FirestoreCreateSupport, FirestoreDb, FirestoreDeleteSupport,
     FirestoreGetByIdSupport, FirestoreListenSupport, FirestoreListingSupport,
     FirestoreQuerySupport, FirestoreUpdateSupport,
 };
+pub use typed_collection::FirestoreTypedCollection;
 
 /// The entry point for building fluent Firestore expressions.
 ///
@@ -118,6 +130,15 @@ where
     pub fn list(self) -> FirestoreListingInitialBuilder<'a, D> {
         FirestoreListingInitialBuilder::new(self.db)
     }
+
+    /// Begins building several independent reads run concurrently, with their typed results
+    /// returned together and optionally read at the same point in time.
+    ///
+    /// Returns a [`FirestoreMultiBuilder`] to add the reads to.
+    #[inline]
+    pub fn multi(self) -> FirestoreMultiBuilder<()> {
+        FirestoreMultiBuilder::new()
+    }
 }
 
 impl FirestoreDb {
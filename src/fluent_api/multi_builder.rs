@@ -0,0 +1,238 @@
+//! Builder for running several independent fluent reads concurrently.
+//!
+//! The reads are added as the futures returned by the other fluent builders
+//! (e.g. `.obj().query()` or `.obj().one(id)`), and their typed results are returned together
+//! as a tuple in the order they were added. The reads can share a single read time, so
+//! all of them see the same consistent snapshot of the database.
+//!
+//! ```rust,no_run
+//! use firestore::*;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Order { total: f64 }
+//! #[derive(Deserialize)]
+//! struct Customer { name: String }
+//!
+//! # async fn run(db: &FirestoreDb) -> FirestoreResult<()> {
+//! let (orders, customer): (Vec<Order>, Option<Customer>) = db
+//!     .fluent()
+//!     .multi()
+//!     .read_at(chrono::Utc::now() - chrono::Duration::seconds(1))
+//!     .add(db.fluent().select().from("orders").obj().query())
+//!     .add(db.fluent().select().by_id_in("customers").obj().one("customer-1"))
+//!     .execute()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{FirestoreConsistencySelector, FirestoreError, FirestoreResult};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use futures::{FutureExt, TryFuture, TryFutureExt};
+
+/// A builder for running several independent fluent reads concurrently.
+///
+/// Created by calling [`FirestoreExprBuilder::multi()`](crate::FirestoreExprBuilder::multi).
+/// The type parameter `T` is the tuple of the reads added so far.
+#[derive(Debug)]
+pub struct FirestoreMultiBuilder<T> {
+    reads: T,
+    consistency_selector: Option<FirestoreConsistencySelector>,
+}
+
+impl FirestoreMultiBuilder<()> {
+    /// Creates a new `FirestoreMultiBuilder` without reads.
+    #[inline]
+    pub(crate) fn new() -> Self {
+        Self {
+            reads: (),
+            consistency_selector: None,
+        }
+    }
+}
+
+impl<T> FirestoreMultiBuilder<T> {
+    /// Reads all documents as they were at the given time, so all the reads see
+    /// the same consistent snapshot of the database.
+    ///
+    /// The read time is used by the reads that don't specify their own consistency selector
+    /// (e.g. with `read_at` or `in_transaction` of their builders).
+    ///
+    /// # Arguments
+    /// * `read_time`: The point in time to read the documents at.
+    ///
+    /// # Returns
+    /// The builder instance with the shared read time set.
+    #[inline]
+    pub fn read_at(self, read_time: DateTime<Utc>) -> Self {
+        Self {
+            consistency_selector: Some(FirestoreConsistencySelector::ReadTime(read_time)),
+            ..self
+        }
+    }
+
+    /// Adds a read, e.g. the future returned by `.obj().query()` or `.obj().one(id)`
+    /// of the other fluent builders.
+    ///
+    /// The read isn't started until [`Self::execute`] is called.
+    /// Up to 8 reads can be added.
+    #[inline]
+    #[allow(clippy::should_implement_trait)]
+    pub fn add<F>(self, read: F) -> FirestoreMultiBuilder<<T as FirestoreMultiAdd<F>>::Output>
+    where
+        T: FirestoreMultiAdd<F>,
+        F: TryFuture<Error = FirestoreError>,
+    {
+        FirestoreMultiBuilder {
+            reads: self.reads.add(read),
+            consistency_selector: self.consistency_selector,
+        }
+    }
+
+    /// Runs all the reads concurrently and returns their results as a tuple
+    /// in the order the reads were added.
+    ///
+    /// Fails with the first error of the reads, cancelling the others.
+    pub async fn execute<'a>(self) -> FirestoreResult<T::Output>
+    where
+        T: FirestoreMultiReads<'a>,
+    {
+        self.reads.try_join(self.consistency_selector).await
+    }
+}
+
+/// Appends a read to the tuple of the reads of a [`FirestoreMultiBuilder`].
+pub trait FirestoreMultiAdd<F> {
+    /// The tuple with the read appended.
+    type Output;
+
+    /// Appends the read to the tuple.
+    fn add(self, read: F) -> Self::Output;
+}
+
+/// A tuple of reads that can be run concurrently by a [`FirestoreMultiBuilder`].
+pub trait FirestoreMultiReads<'a> {
+    /// The tuple of the results of the reads.
+    type Output;
+
+    /// Runs the reads concurrently with the optional consistency selector shared by them.
+    fn try_join(
+        self,
+        consistency_selector: Option<FirestoreConsistencySelector>,
+    ) -> BoxFuture<'a, FirestoreResult<Self::Output>>;
+}
+
+impl<'a> FirestoreMultiReads<'a> for () {
+    type Output = ();
+
+    fn try_join(
+        self,
+        _consistency_selector: Option<FirestoreConsistencySelector>,
+    ) -> BoxFuture<'a, FirestoreResult<Self::Output>> {
+        futures::future::ok(()).boxed()
+    }
+}
+
+macro_rules! firestore_multi_add_impl {
+    ($($read_type: ident $read: ident),*) => {
+        impl<$($read_type,)* F> FirestoreMultiAdd<F> for ($($read_type,)*) {
+            type Output = ($($read_type,)* F,);
+
+            fn add(self, read: F) -> Self::Output {
+                let ($($read,)*) = self;
+                ($($read,)* read,)
+            }
+        }
+    };
+}
+
+macro_rules! firestore_multi_reads_impl {
+    ($($read_type: ident $read: ident),+) => {
+        impl<'a, $($read_type,)+> FirestoreMultiReads<'a> for ($($read_type,)+)
+        where
+            $(
+                $read_type: TryFuture<Error = FirestoreError> + Send + 'a,
+                $read_type::Ok: Send + 'a,
+            )+
+        {
+            type Output = ($($read_type::Ok,)+);
+
+            fn try_join(
+                self,
+                consistency_selector: Option<FirestoreConsistencySelector>,
+            ) -> BoxFuture<'a, FirestoreResult<Self::Output>> {
+                let ($($read,)+) = self;
+                async move {
+                    futures::try_join!(
+                        $(
+                            FirestoreConsistencySelector::scope(
+                                consistency_selector.clone(),
+                                $read.into_future(),
+                            ),
+                        )+
+                    )
+                }
+                .boxed()
+            }
+        }
+    };
+}
+
+firestore_multi_add_impl!();
+firestore_multi_add_impl!(R1 r1);
+firestore_multi_add_impl!(R1 r1, R2 r2);
+firestore_multi_add_impl!(R1 r1, R2 r2, R3 r3);
+firestore_multi_add_impl!(R1 r1, R2 r2, R3 r3, R4 r4);
+firestore_multi_add_impl!(R1 r1, R2 r2, R3 r3, R4 r4, R5 r5);
+firestore_multi_add_impl!(R1 r1, R2 r2, R3 r3, R4 r4, R5 r5, R6 r6);
+firestore_multi_add_impl!(R1 r1, R2 r2, R3 r3, R4 r4, R5 r5, R6 r6, R7 r7);
+
+firestore_multi_reads_impl!(R1 r1);
+firestore_multi_reads_impl!(R1 r1, R2 r2);
+firestore_multi_reads_impl!(R1 r1, R2 r2, R3 r3);
+firestore_multi_reads_impl!(R1 r1, R2 r2, R3 r3, R4 r4);
+firestore_multi_reads_impl!(R1 r1, R2 r2, R3 r3, R4 r4, R5 r5);
+firestore_multi_reads_impl!(R1 r1, R2 r2, R3 r3, R4 r4, R5 r5, R6 r6);
+firestore_multi_reads_impl!(R1 r1, R2 r2, R3 r3, R4 r4, R5 r5, R6 r6, R7 r7);
+firestore_multi_reads_impl!(R1 r1, R2 r2, R3 r3, R4 r4, R5 r5, R6 r6, R7 r7, R8 r8);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::*;
+    use futures::future::{err, ok};
+
+    #[tokio::test]
+    async fn returns_results_in_order_of_reads() -> FirestoreResult<()> {
+        let (numbers, name, nothing): (Vec<u32>, Option<String>, Option<u32>) =
+            FirestoreMultiBuilder::new()
+                .add(ok(vec![1, 2]))
+                .add(async { Ok(Some("one".to_string())) })
+                .add(ok(None))
+                .execute()
+                .await?;
+
+        assert_eq!(numbers, vec![1, 2]);
+        assert_eq!(name, Some("one".to_string()));
+        assert_eq!(nothing, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fails_with_error_of_read() {
+        let result = FirestoreMultiBuilder::new()
+            .add(ok::<_, FirestoreError>(1))
+            .add(err::<u32, _>(FirestoreError::DataNotFoundError(
+                FirestoreDataNotFoundError::new(
+                    FirestoreErrorPublicGenericDetails::new("NotFound".into()),
+                    "Missing".to_string(),
+                ),
+            )))
+            .execute()
+            .await;
+
+        assert!(matches!(result, Err(FirestoreError::DataNotFoundError(_))));
+    }
+}
@@ -6,10 +6,13 @@
 //! The main entry point is [`FirestoreAggregationBuilder`], which is typically
 //! accessed via a method on a select/query builder (e.g., [`FirestoreSelectDocQueryBuilder::aggregate()`](crate::FirestoreSelectDocQueryBuilder::aggregate)).
 
+use crate::select_builder::{aggregated_count, aggregated_number};
 use crate::{
     FirestoreAggregation, FirestoreAggregationOperator, FirestoreAggregationOperatorAvg,
     FirestoreAggregationOperatorCount, FirestoreAggregationOperatorSum,
 };
+use gcloud_sdk::google::firestore::v1::{value, Value};
+use std::collections::{HashMap, HashSet};
 
 /// A builder for constructing a list of aggregations to apply to a query.
 ///
@@ -66,6 +69,88 @@ impl FirestoreAggregationBuilder {
     {
         FirestoreAggregationFieldExpr::new(field_name.as_ref().to_string())
     }
+
+    /// Specifies a "count" aggregation with a generated alias.
+    ///
+    /// The result can be read with [`FirestoreAggregationResults::count()`].
+    #[inline]
+    pub fn count(&self) -> Option<FirestoreAggregation> {
+        FirestoreAggregationFieldExpr::new(String::new()).count()
+    }
+
+    /// Specifies a "sum" aggregation over a (nested) field with an alias generated
+    /// from the field path, e.g. `a.sum(path!(MyStruct::totals.net))`.
+    ///
+    /// The generated aliases never collide with each other or with the explicit ones,
+    /// and the result can be read by the field path with [`FirestoreAggregationResults::sum()`].
+    #[inline]
+    pub fn sum<S>(&self, field_path: S) -> Option<FirestoreAggregation>
+    where
+        S: AsRef<str>,
+    {
+        FirestoreAggregationFieldExpr::new(String::new()).sum(field_path)
+    }
+
+    /// Specifies an "average" aggregation over a (nested) field with an alias generated
+    /// from the field path, e.g. `a.avg(path!(MyStruct::totals.net))`.
+    ///
+    /// The result can be read by the field path with [`FirestoreAggregationResults::avg()`].
+    #[inline]
+    pub fn avg<S>(&self, field_path: S) -> Option<FirestoreAggregation>
+    where
+        S: AsRef<str>,
+    {
+        FirestoreAggregationFieldExpr::new(String::new()).avg(field_path)
+    }
+}
+
+/// Generates the aliases for the aggregations without them, e.g. `sum_totals_net`
+/// for a sum over `totals.net`, adding a numeric suffix in case of collisions.
+pub(crate) fn with_generated_aliases(
+    aggregations: Vec<FirestoreAggregation>,
+) -> Vec<FirestoreAggregation> {
+    let mut used_aliases: HashSet<String> = aggregations
+        .iter()
+        .filter(|aggregation| !aggregation.alias.is_empty())
+        .map(|aggregation| aggregation.alias.clone())
+        .collect();
+
+    aggregations
+        .into_iter()
+        .map(|aggregation| {
+            if !aggregation.alias.is_empty() {
+                return aggregation;
+            }
+            let base_alias = match &aggregation.operator {
+                Some(FirestoreAggregationOperator::Count(_)) | None => "count".to_string(),
+                Some(FirestoreAggregationOperator::Sum(op)) => {
+                    format!("sum_{}", alias_for_field_path(&op.field_name))
+                }
+                Some(FirestoreAggregationOperator::Avg(op)) => {
+                    format!("avg_{}", alias_for_field_path(&op.field_name))
+                }
+            };
+            let mut alias = base_alias.clone();
+            let mut suffix = 2;
+            while used_aliases.contains(&alias) {
+                alias = format!("{base_alias}_{suffix}");
+                suffix += 1;
+            }
+            used_aliases.insert(alias.clone());
+            FirestoreAggregation {
+                alias,
+                ..aggregation
+            }
+        })
+        .collect()
+}
+
+fn alias_for_field_path(field_path: &str) -> String {
+    field_path
+        .chars()
+        .filter(|c| *c != '`')
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
 }
 
 /// A trait for types that can be converted into a [`FirestoreAggregation`].
@@ -191,3 +276,191 @@ where
         self.and_then(|expr| expr.build_aggregation())
     }
 }
+
+/// The results of an aggregation query, accessible by the aliases
+/// or by the field paths of the aggregations.
+///
+/// Returned by [`FirestoreAggregatedQueryDocBuilder::results()`](crate::select_builder::FirestoreAggregatedQueryDocBuilder::results).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FirestoreAggregationResults {
+    aggregations: Vec<FirestoreAggregation>,
+    fields: HashMap<String, Value>,
+}
+
+impl FirestoreAggregationResults {
+    pub(crate) fn new(
+        aggregations: Vec<FirestoreAggregation>,
+        fields: HashMap<String, Value>,
+    ) -> Self {
+        Self {
+            aggregations,
+            fields,
+        }
+    }
+
+    /// The value of the aggregation with the alias.
+    pub fn get(&self, alias: &str) -> Option<&Value> {
+        self.fields.get(alias)
+    }
+
+    /// The result of the first "count" aggregation.
+    pub fn count(&self) -> Option<usize> {
+        aggregated_count(
+            &self
+                .find(|operator| matches!(operator, FirestoreAggregationOperator::Count(_)))
+                .cloned(),
+        )
+    }
+
+    /// The result of the "sum" aggregation over the field.
+    pub fn sum<S>(&self, field_path: S) -> Option<FirestoreAggregatedSum>
+    where
+        S: AsRef<str>,
+    {
+        self.find(|operator| {
+            matches!(operator, FirestoreAggregationOperator::Sum(op) if op.field_name == field_path.as_ref())
+        })
+        .and_then(FirestoreAggregatedSum::from_value)
+    }
+
+    /// The result of the "average" aggregation over the field,
+    /// `None` if there were no numeric values.
+    pub fn avg<S>(&self, field_path: S) -> Option<f64>
+    where
+        S: AsRef<str>,
+    {
+        aggregated_number(
+            &self
+                .find(|operator| {
+                    matches!(operator, FirestoreAggregationOperator::Avg(op) if op.field_name == field_path.as_ref())
+                })
+                .cloned(),
+        )
+    }
+
+    fn find<P>(&self, predicate: P) -> Option<&Value>
+    where
+        P: Fn(&FirestoreAggregationOperator) -> bool,
+    {
+        self.aggregations
+            .iter()
+            .find(|aggregation| aggregation.operator.as_ref().map_or(false, &predicate))
+            .and_then(|aggregation| self.fields.get(&aggregation.alias))
+    }
+}
+
+/// The result of a "sum" aggregation.
+///
+/// Firestore returns the sums of integers as integers (unless they overflow `i64`),
+/// and the sums including doubles as doubles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FirestoreAggregatedSum {
+    Integer(i64),
+    Double(f64),
+}
+
+impl FirestoreAggregatedSum {
+    pub(crate) fn from_value(value: &Value) -> Option<Self> {
+        match value.value_type {
+            Some(value::ValueType::IntegerValue(v)) => Some(FirestoreAggregatedSum::Integer(v)),
+            Some(value::ValueType::DoubleValue(v)) => Some(FirestoreAggregatedSum::Double(v)),
+            _ => None,
+        }
+    }
+
+    /// The sum as `i64`, `None` for the sums of doubles.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            FirestoreAggregatedSum::Integer(v) => Some(*v),
+            FirestoreAggregatedSum::Double(_) => None,
+        }
+    }
+
+    /// The sum as `f64`, converting integer sums.
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            FirestoreAggregatedSum::Integer(v) => *v as f64,
+            FirestoreAggregatedSum::Double(v) => *v,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path;
+
+    #[allow(dead_code)]
+    struct Totals {
+        net: f64,
+        net_total: f64,
+    }
+
+    #[allow(dead_code)]
+    struct Order {
+        totals: Totals,
+        totals_net: f64,
+    }
+
+    #[test]
+    fn generates_collision_free_aliases() {
+        let builder = FirestoreAggregationBuilder::new();
+        let aggregations = with_generated_aliases(builder.fields([
+            builder.sum(path!(Order::totals.net)),
+            builder.sum(path!(Order::totals_net)),
+            builder.avg(path!(Order::totals.net)),
+            builder.field("sum_totals_net_2").count(),
+            builder.count(),
+        ]));
+
+        let aliases: Vec<&str> = aggregations.iter().map(|a| a.alias.as_str()).collect();
+        assert_eq!(
+            aliases,
+            vec![
+                "sum_totals_net",
+                "sum_totals_net_3",
+                "avg_totals_net",
+                "sum_totals_net_2",
+                "count"
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_results_by_field_paths() {
+        let builder = FirestoreAggregationBuilder::new();
+        let aggregations = with_generated_aliases(builder.fields([
+            builder.sum(path!(Order::totals.net)),
+            builder.avg(path!(Order::totals.net)),
+            builder.count(),
+        ]));
+        let number = |value_type| Value {
+            value_type: Some(value_type),
+        };
+        let results = FirestoreAggregationResults::new(
+            aggregations,
+            HashMap::from([
+                (
+                    "sum_totals_net".to_string(),
+                    number(value::ValueType::IntegerValue(30)),
+                ),
+                (
+                    "avg_totals_net".to_string(),
+                    number(value::ValueType::DoubleValue(15.0)),
+                ),
+                (
+                    "count".to_string(),
+                    number(value::ValueType::IntegerValue(2)),
+                ),
+            ]),
+        );
+
+        assert_eq!(
+            results.sum(path!(Order::totals.net)),
+            Some(FirestoreAggregatedSum::Integer(30))
+        );
+        assert_eq!(results.avg(path!(Order::totals.net)), Some(15.0));
+        assert_eq!(results.sum(path!(Order::totals_net)), None);
+        assert_eq!(results.count(), Some(2));
+    }
+}
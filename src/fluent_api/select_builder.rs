@@ -5,24 +5,40 @@
 //! projections, and fetching documents by ID. It also serves as a base for
 //! aggregation queries and real-time listeners.
 
-use crate::errors::FirestoreError;
-use crate::select_aggregation_builder::FirestoreAggregationBuilder;
+use crate::db::aggregate_by_range_obj;
+use crate::db::firestore_timeout_scope;
+use crate::db::order_by_with_document_name;
+use crate::errors::{FirestoreError, FirestoreErrorPublicGenericDetails, FirestoreProjectionError};
+use crate::firestore_serde::{firestore_required_fields, is_firestore_metadata_field};
+use crate::select_aggregation_builder::{
+    with_generated_aliases, FirestoreAggregatedSum, FirestoreAggregationBuilder,
+    FirestoreAggregationResults,
+};
 use crate::select_filter_builder::FirestoreQueryFilterBuilder;
+use crate::select_group_by_builder::FirestoreGroupByQueryBuilder;
+use crate::select_page_builder::{FirestorePageQueryDocBuilder, FirestorePageQueryObjBuilder};
+use crate::select_split_builder::FirestoreSplitQueryDocBuilder;
 use crate::{
     FirestoreAggregatedQueryParams, FirestoreAggregatedQuerySupport, FirestoreAggregation,
-    FirestoreCollectionDocuments, FirestoreExplainOptions, FirestoreFindNearestDistanceMeasure,
-    FirestoreFindNearestOptions, FirestoreGetByIdSupport, FirestoreListenSupport,
-    FirestoreListener, FirestoreListenerParams, FirestoreListenerTarget,
-    FirestoreListenerTargetParams, FirestorePartition, FirestorePartitionQueryParams,
-    FirestoreQueryCollection, FirestoreQueryCursor, FirestoreQueryFilter, FirestoreQueryOrder,
-    FirestoreQueryParams, FirestoreQuerySupport, FirestoreResult, FirestoreResumeStateStorage,
-    FirestoreTargetType, FirestoreVector, FirestoreWithMetadata,
+    FirestoreAggregationBucket, FirestoreBatchGetResult, FirestoreCollectionDocuments,
+    FirestoreCollectionPath, FirestoreConsistencySelector, FirestoreDb, FirestoreDocWithMetaItem,
+    FirestoreExplainOptions, FirestoreFindNearestDistanceMeasure, FirestoreFindNearestOptions,
+    FirestoreGetByIdSupport, FirestoreGroupByParams, FirestoreListenSupport, FirestoreListener,
+    FirestoreListenerParams, FirestoreListenerTarget, FirestoreListenerTargetParams,
+    FirestorePartition, FirestorePartitionQueryParams, FirestoreQueryCollection,
+    FirestoreQueryCursor, FirestoreQueryFilter, FirestoreQueryOrder, FirestoreQueryParams,
+    FirestoreQuerySupport, FirestoreRangeAggregationParams, FirestoreReference, FirestoreResult,
+    FirestoreResumeStateStorage, FirestoreRetryPolicy, FirestoreTargetType, FirestoreTransaction,
+    FirestoreValue, FirestoreVector, FirestoreWithMetadata,
 };
+use chrono::{DateTime, Utc};
 use futures::stream::BoxStream;
-use gcloud_sdk::google::firestore::v1::Document;
+use futures::{StreamExt, TryStreamExt};
+use gcloud_sdk::google::firestore::v1::{value, Document, StructuredQuery, Value};
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::marker::PhantomData;
+use std::time::Duration;
 
 /// The initial builder for a Firestore select/query operation.
 ///
@@ -41,6 +57,7 @@ where
 {
     db: &'a D,
     return_only_fields: Option<Vec<String>>,
+    explain_options: Option<FirestoreExplainOptions>,
 }
 
 impl<'a, D> FirestoreSelectInitialBuilder<'a, D>
@@ -60,6 +77,7 @@ where
         Self {
             db,
             return_only_fields: None,
+            explain_options: None,
         }
     }
 
@@ -89,6 +107,26 @@ where
         }
     }
 
+    /// Requests Firestore to explain the query plan, and optionally to execute the query
+    /// and report execution statistics (with `analyze` enabled).
+    ///
+    /// Applies to both regular and aggregation queries built from this builder.
+    /// The resulting [`FirestoreExplainMetrics`](crate::FirestoreExplainMetrics) are available
+    /// in the metadata of the `stream_query_with_metadata` results.
+    ///
+    /// # Arguments
+    /// * `options`: [`FirestoreExplainOptions`] specifying the analysis options.
+    ///
+    /// # Returns
+    /// The builder instance with the explain options set.
+    #[inline]
+    pub fn explain_options(self, options: FirestoreExplainOptions) -> Self {
+        Self {
+            explain_options: Some(options),
+            ..self
+        }
+    }
+
     /// Specifies the collection or collection group to query documents from.
     ///
     /// # Arguments
@@ -103,7 +141,69 @@ where
         C: Into<FirestoreQueryCollection>,
     {
         let params: FirestoreQueryParams = FirestoreQueryParams::new(collection.into())
-            .opt_return_only_fields(self.return_only_fields);
+            .opt_return_only_fields(self.return_only_fields)
+            .opt_explain_options(self.explain_options);
+        FirestoreSelectDocBuilder::new(self.db, params)
+    }
+
+    /// Continues building a query from the parameters built before, e.g. a stored query definition
+    /// (see [`FirestoreSelectDocBuilder::build_params`]).
+    ///
+    /// The projection and explain options of this builder are used if they aren't set in the parameters.
+    ///
+    /// # Arguments
+    /// * `params`: The [`FirestoreQueryParams`] of the query.
+    ///
+    /// # Returns
+    /// A [`FirestoreSelectDocBuilder`] to further configure or run the query.
+    #[inline]
+    pub fn from_params(self, params: FirestoreQueryParams) -> FirestoreSelectDocBuilder<'a, D> {
+        let return_only_fields = params
+            .return_only_fields
+            .clone()
+            .or(self.return_only_fields);
+        let explain_options = params.explain_options.clone().or(self.explain_options);
+        FirestoreSelectDocBuilder::new(
+            self.db,
+            params
+                .opt_return_only_fields(return_only_fields)
+                .opt_explain_options(explain_options),
+        )
+    }
+
+    /// Specifies the (sub)collection to query documents from, including its parent document.
+    ///
+    /// # Arguments
+    /// * `path`: The [`FirestoreCollectionPath`] created with [`ParentPathBuilder::collection`](crate::ParentPathBuilder::collection).
+    ///
+    /// # Returns
+    /// A [`FirestoreSelectDocBuilder`] to further configure the query (filters, ordering, etc.).
+    #[inline]
+    pub fn from_path(self, path: &FirestoreCollectionPath) -> FirestoreSelectDocBuilder<'a, D> {
+        self.from(path.collection_id()).parent(path.parent())
+    }
+
+    /// Specifies a collection group to query documents from: all collections with the
+    /// given ID, at any depth under the database root or under the parent document
+    /// set with [`FirestoreSelectDocBuilder::parent`].
+    ///
+    /// The resulting builder supports aggregations the same way as [`Self::from`].
+    ///
+    /// # Arguments
+    /// * `collection_id`: The ID of the collections in the group.
+    ///
+    /// # Returns
+    /// A [`FirestoreSelectDocBuilder`] configured for a collection group query.
+    #[inline]
+    pub fn from_collection_group<S>(self, collection_id: S) -> FirestoreSelectDocBuilder<'a, D>
+    where
+        S: AsRef<str>,
+    {
+        let collection = FirestoreQueryCollection::Group(vec![collection_id.as_ref().to_string()]);
+        let params: FirestoreQueryParams = FirestoreQueryParams::new(collection)
+            .with_all_descendants(true)
+            .opt_return_only_fields(self.return_only_fields)
+            .opt_explain_options(self.explain_options);
         FirestoreSelectDocBuilder::new(self.db, params)
     }
 
@@ -118,6 +218,62 @@ where
     pub fn by_id_in(self, collection: &str) -> FirestoreSelectByIdBuilder<'a, D> {
         FirestoreSelectByIdBuilder::new(self.db, collection.to_string(), self.return_only_fields)
     }
+
+    /// Specifies that documents should be fetched by their IDs from a specific (sub)collection,
+    /// including its parent document.
+    ///
+    /// # Arguments
+    /// * `path`: The [`FirestoreCollectionPath`] created with [`ParentPathBuilder::collection`](crate::ParentPathBuilder::collection).
+    ///
+    /// # Returns
+    /// A [`FirestoreSelectByIdBuilder`] to specify the document IDs and other options.
+    #[inline]
+    pub fn by_id_in_path(
+        self,
+        path: &FirestoreCollectionPath,
+    ) -> FirestoreSelectByIdBuilder<'a, D> {
+        self.by_id_in(path.collection_id()).parent(path.parent())
+    }
+
+    /// Specifies that a document should be fetched by a reference (its full document path),
+    /// e.g. a reference value read from another document.
+    ///
+    /// # Arguments
+    /// * `reference`: The reference to the document.
+    ///
+    /// # Returns
+    /// A [`FirestoreSelectByReferenceBuilder`] to fetch the document,
+    /// or an error if the reference isn't a valid document path.
+    pub fn by_reference(
+        self,
+        reference: &FirestoreReference,
+    ) -> FirestoreResult<FirestoreSelectByReferenceBuilder<'a, D>> {
+        let (parent, collection_id, document_id) = reference.document_location()?;
+        Ok(FirestoreSelectByReferenceBuilder {
+            by_id: FirestoreSelectByIdBuilder::new(
+                self.db,
+                collection_id.to_string(),
+                self.return_only_fields,
+            )
+            .parent(parent),
+            document_id: document_id.to_string(),
+        })
+    }
+}
+
+pub(crate) fn aggregated_number(value: &Option<Value>) -> Option<f64> {
+    match value.as_ref().and_then(|value| value.value_type.as_ref()) {
+        Some(value::ValueType::IntegerValue(v)) => Some(*v as f64),
+        Some(value::ValueType::DoubleValue(v)) => Some(*v),
+        _ => None,
+    }
+}
+
+pub(crate) fn aggregated_count(value: &Option<Value>) -> Option<usize> {
+    match value.as_ref().and_then(|value| value.value_type.as_ref()) {
+        Some(value::ValueType::IntegerValue(v)) => usize::try_from(*v).ok(),
+        _ => None,
+    }
 }
 
 /// A builder for configuring and executing a Firestore query on a collection.
@@ -136,6 +292,8 @@ where
 {
     db: &'a D,
     params: FirestoreQueryParams,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
 }
 
 impl<'a, D> FirestoreSelectDocBuilder<'a, D>
@@ -151,7 +309,70 @@ where
     /// Creates a new `FirestoreSelectDocBuilder`.
     #[inline]
     pub(crate) fn new(db: &'a D, params: FirestoreQueryParams) -> Self {
-        Self { db, params }
+        Self {
+            db,
+            params,
+            retry_policy: None,
+            timeout: None,
+        }
+    }
+
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of this operation, overriding [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    ///
+    /// The timeout is sent to Firestore as the gRPC deadline of the requests.
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Reads the documents as they were at the specified time (a point-in-time read).
+    ///
+    /// The read time must be within
+    /// [`FirestoreDbOptions::point_in_time_read_window`](crate::FirestoreDbOptions::point_in_time_read_window).
+    ///
+    /// # Arguments
+    /// * `read_time`: The time to read the documents at.
+    ///
+    /// # Returns
+    /// The builder instance with the read time set.
+    #[inline]
+    pub fn read_at(self, read_time: DateTime<Utc>) -> Self {
+        Self {
+            params: self
+                .params
+                .with_consistency_selector(FirestoreConsistencySelector::ReadTime(read_time)),
+            ..self
+        }
+    }
+
+    /// Reads the documents in the transaction, so the transaction fails to commit
+    /// if they are changed by others before the commit.
+    ///
+    /// # Arguments
+    /// * `transaction`: The transaction to read the documents in.
+    ///
+    /// # Returns
+    /// The builder instance with the transaction set.
+    #[inline]
+    pub fn in_transaction(self, transaction: &FirestoreTransaction<'_>) -> Self {
+        Self {
+            params: self.params.with_consistency_selector(
+                FirestoreConsistencySelector::Transaction(transaction.transaction_id().clone()),
+            ),
+            ..self
+        }
     }
 
     /// Specifies the parent document path for querying a sub-collection.
@@ -182,7 +403,26 @@ where
     #[inline]
     pub fn limit(self, value: u32) -> Self {
         Self {
-            params: self.params.with_limit(value),
+            params: self.params.with_limit(value).opt_limit_to_last(None),
+            ..self
+        }
+    }
+
+    /// Limits the results to the last `value` documents in the specified order.
+    ///
+    /// The query is sent to Firestore with the reversed ordering and cursors,
+    /// and the results are returned in the requested order.
+    /// At least one `order_by` field is required. Replaces a limit set with [`Self::limit`].
+    ///
+    /// # Arguments
+    /// * `value`: The number of the last documents to return.
+    ///
+    /// # Returns
+    /// The builder instance with the limit set.
+    #[inline]
+    pub fn limit_to_last(self, value: u32) -> Self {
+        Self {
+            params: self.params.with_limit_to_last(value).opt_limit(None),
             ..self
         }
     }
@@ -240,6 +480,27 @@ where
         }
     }
 
+    /// Starts the query results right after the given document, typically the last
+    /// document of the previous page.
+    ///
+    /// Should be called after [`Self::order_by`], since the cursor is built from the values
+    /// of the ordered fields. The ordering is extended with the document name.
+    ///
+    /// # Arguments
+    /// * `doc`: The document to start after.
+    ///
+    /// # Returns
+    /// The builder instance with the start cursor set.
+    #[inline]
+    pub fn start_after_document(self, doc: &Document) -> Self {
+        let order_by = order_by_with_document_name(self.params.order_by.clone());
+        let cursor = FirestoreQueryCursor::after_document(doc, &order_by);
+        Self {
+            params: self.params.with_order_by(order_by).with_start_at(cursor),
+            ..self
+        }
+    }
+
     /// Sets the ending point for the query results using a cursor.
     ///
     /// # Arguments
@@ -255,6 +516,68 @@ where
         }
     }
 
+    /// Starts the query results at the documents with the given values of the ordered fields (inclusive).
+    ///
+    /// The values are matched to the [`Self::order_by`] fields in order, and may specify only
+    /// the first of them. The query fails with an invalid parameters error if there are more values
+    /// than the fields the results are ordered by.
+    ///
+    /// # Arguments
+    /// * `values`: The values of the ordered fields. Values of different types are converted
+    ///   with `.into()`, e.g. `.start_at_values::<[FirestoreValue; 2]>(["Dublin".into(), 2024.into()])`.
+    ///
+    /// # Returns
+    /// The builder instance with the start cursor set.
+    #[inline]
+    pub fn start_at_values<I>(self, values: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<FirestoreValue>,
+    {
+        self.start_at(FirestoreQueryCursor::BeforeValue(
+            values.into_iter().map(|value| value.into()).collect(),
+        ))
+    }
+
+    /// Starts the query results after the documents with the given values of the ordered fields.
+    /// See [`Self::start_at_values`].
+    #[inline]
+    pub fn start_after_values<I>(self, values: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<FirestoreValue>,
+    {
+        self.start_at(FirestoreQueryCursor::AfterValue(
+            values.into_iter().map(|value| value.into()).collect(),
+        ))
+    }
+
+    /// Ends the query results before the documents with the given values of the ordered fields.
+    /// See [`Self::start_at_values`].
+    #[inline]
+    pub fn end_before_values<I>(self, values: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<FirestoreValue>,
+    {
+        self.end_at(FirestoreQueryCursor::BeforeValue(
+            values.into_iter().map(|value| value.into()).collect(),
+        ))
+    }
+
+    /// Ends the query results at the documents with the given values of the ordered fields (inclusive).
+    /// See [`Self::start_at_values`].
+    #[inline]
+    pub fn end_at_values<I>(self, values: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<FirestoreValue>,
+    {
+        self.end_at(FirestoreQueryCursor::AfterValue(
+            values.into_iter().map(|value| value.into()).collect(),
+        ))
+    }
+
     /// Configures the query to search all collections with the specified ID
     /// under the parent path (for collection group queries).
     ///
@@ -283,6 +606,7 @@ where
     where
         FN: Fn(FirestoreQueryFilterBuilder) -> Option<FirestoreQueryFilter>,
     {
+        // Relative document IDs are resolved against the parent when the query is sent
         let filter_builder = FirestoreQueryFilterBuilder::new();
 
         Self {
@@ -291,6 +615,21 @@ where
         }
     }
 
+    /// Builds the [`StructuredQuery`] proto of this query, e.g. to store the query definition.
+    ///
+    /// The parent path and the read options of the query are not part of the structured query.
+    pub fn build_structured_query(&self) -> FirestoreResult<StructuredQuery> {
+        self.params.clone().try_into()
+    }
+
+    /// Returns the parameters of this query, including the parent path and the read options.
+    ///
+    /// The parameters can be serialized to store the query definition and run it later
+    /// with [`FirestoreSelectInitialBuilder::from_params`].
+    pub fn build_params(&self) -> FirestoreQueryParams {
+        self.params.clone()
+    }
+
     /// Requests an explanation of the query execution plan from Firestore.
     ///
     /// The explanation metrics will be available in the metadata of the query response.
@@ -385,7 +724,51 @@ where
         T: Send,
         for<'de> T: Deserialize<'de>,
     {
-        FirestoreSelectObjBuilder::new(self.db, self.params)
+        FirestoreSelectObjBuilder {
+            retry_policy: self.retry_policy,
+            timeout: self.timeout,
+            ..FirestoreSelectObjBuilder::new(self.db, self.params)
+        }
+    }
+
+    /// Specifies that the query results should be deserialized into a type `T` one by one,
+    /// so documents failing to deserialize don't fail the whole query.
+    ///
+    /// Every document is returned as a `FirestoreResult<T>`, with the errors describing
+    /// the document, the field path and the types that failed to deserialize, e.g. to skip
+    /// or report malformed documents.
+    ///
+    /// # Returns
+    /// A [`FirestoreSelectObjLenientBuilder`] for executing the query.
+    #[inline]
+    pub fn obj_lenient<T>(self) -> FirestoreSelectObjLenientBuilder<'a, D, T>
+    where
+        T: Send,
+        for<'de> T: Deserialize<'de>,
+    {
+        FirestoreSelectObjLenientBuilder {
+            retry_policy: self.retry_policy,
+            timeout: self.timeout,
+            ..FirestoreSelectObjLenientBuilder::new(self.db, self.params)
+        }
+    }
+
+    /// Specifies that the results should be deserialized into a type `T` that may contain
+    /// only a part of the document fields, selected with `fields()`.
+    ///
+    /// Unlike [`Self::obj`], checks that the projection includes all the non-optional fields of `T`
+    /// before running the query, so fields not in the projection should be `Option`s or have defaults.
+    ///
+    /// # Errors
+    /// Returns [`FirestoreError::ProjectionError`](crate::errors::FirestoreError::ProjectionError)
+    /// listing the non-optional fields of `T` missing from the projection.
+    pub fn partial_obj<T>(self) -> FirestoreResult<FirestoreSelectObjBuilder<'a, D, T>>
+    where
+        T: Send,
+        for<'de> T: Deserialize<'de>,
+    {
+        ensure_projection_fields::<T>(self.params.return_only_fields.as_ref())?;
+        Ok(self.obj())
     }
 
     /// Configures the query as a partitioned query.
@@ -400,6 +783,28 @@ where
         FirestorePartitionQueryDocBuilder::new(self.db, self.params.with_all_descendants(true))
     }
 
+    /// Configures the query as a partitioned query split into (up to) `partition_count` partitions.
+    ///
+    /// A shortcut for `.partition_query().partition_count(partition_count)`.
+    ///
+    /// # Returns
+    /// A [`FirestorePartitionQueryDocBuilder`] to configure and stream partitions.
+    #[inline]
+    pub fn partition(self, partition_count: u32) -> FirestorePartitionQueryDocBuilder<'a, D> {
+        self.partition_query().partition_count(partition_count)
+    }
+
+    /// Fetches the query results page by page, `page_size` documents at a time.
+    ///
+    /// The ordering is extended with the document name to make the page tokens unique.
+    ///
+    /// # Returns
+    /// A [`FirestorePageQueryDocBuilder`] to set the page token and fetch the page.
+    #[inline]
+    pub fn page(self, page_size: u32) -> FirestorePageQueryDocBuilder<'a, D> {
+        FirestorePageQueryDocBuilder::new(self.db, self.params, page_size)
+    }
+
     /// Sets up a real-time listener for changes to the documents matching this query.
     ///
     /// # Returns
@@ -416,6 +821,8 @@ where
     ///
     /// The `aggregation` argument is a closure that receives a [`FirestoreAggregationBuilder`]
     /// and should return a `Vec<FirestoreAggregation>`.
+    /// Aggregations without aliases (e.g. `a.sum(path!(MyStruct::totals.net))`) get
+    /// generated collision-free aliases, while duplicate explicit aliases fail the query.
     ///
     /// # Arguments
     /// * `aggregation`: A closure to build the list of aggregations.
@@ -427,68 +834,233 @@ where
     where
         FN: Fn(FirestoreAggregationBuilder) -> Vec<FirestoreAggregation>,
     {
-        FirestoreAggregatedQueryDocBuilder::new(
-            self.db,
-            FirestoreAggregatedQueryParams::new(
-                self.params,
-                aggregation(FirestoreAggregationBuilder::new()),
-            ),
-        )
+        FirestoreAggregatedQueryDocBuilder {
+            retry_policy: self.retry_policy,
+            timeout: self.timeout,
+            ..FirestoreAggregatedQueryDocBuilder::new(
+                self.db,
+                FirestoreAggregatedQueryParams::new(
+                    self.params,
+                    with_generated_aliases(aggregation(FirestoreAggregationBuilder::new())),
+                ),
+            )
+        }
     }
 
-    /// Executes the configured query and retrieves all matching documents.
+    /// Counts the documents matching this query using an aggregation query.
     ///
     /// # Returns
-    /// A `FirestoreResult` containing a `Vec` of [`Document`]s.
-    pub async fn query(self) -> FirestoreResult<Vec<Document>> {
-        self.db.query_doc(self.params).await
+    /// A `FirestoreResult` containing the number of matching documents.
+    pub async fn count(self) -> FirestoreResult<usize> {
+        let value = self.aggregate_single(|a| a.field("count").count()).await?;
+        Ok(aggregated_count(&value).unwrap_or(0))
     }
 
-    /// Executes the configured query and returns a stream of matching documents.
+    /// Counts the documents matching this query, up to the specified limit.
     ///
-    /// Errors encountered during streaming will terminate the stream.
+    /// This is cheaper than [`count`](Self::count) when only a capped count is needed.
+    pub async fn count_up_to(self, up_to: usize) -> FirestoreResult<usize> {
+        let value = self
+            .aggregate_single(|a| a.field("count").count_up_to(up_to))
+            .await?;
+        Ok(aggregated_count(&value).unwrap_or(0))
+    }
+
+    /// Sums the values of a numeric field over the documents matching this query.
     ///
-    /// # Returns
-    /// A `FirestoreResult` containing a `BoxStream` of [`Document`]s.
-    pub async fn stream_query<'b>(self) -> FirestoreResult<BoxStream<'b, Document>> {
-        self.db.stream_query_doc(self.params).await
+    /// Non-numeric values are ignored. The sum of integers is returned as
+    /// [`FirestoreAggregatedSum::Integer`] unless it overflows.
+    ///
+    /// # Arguments
+    /// * `field_path`: The path of the numeric field, e.g. `path!(MyStruct::amount)`.
+    pub async fn sum<S>(self, field_path: S) -> FirestoreResult<FirestoreAggregatedSum>
+    where
+        S: AsRef<str>,
+    {
+        let value = self
+            .aggregate_single(|a| a.field("sum").sum(field_path.as_ref()))
+            .await?;
+        Ok(value
+            .as_ref()
+            .and_then(FirestoreAggregatedSum::from_value)
+            .unwrap_or(FirestoreAggregatedSum::Integer(0)))
     }
 
-    /// Executes the configured query and returns a stream of `FirestoreResult<Document>`.
+    /// Averages the values of a numeric field over the documents matching this query.
     ///
-    /// Errors encountered during streaming are yielded as `Err` items in the stream.
+    /// # Arguments
+    /// * `field_path`: The path of the numeric field, e.g. `path!(MyStruct::amount)`.
     ///
     /// # Returns
-    /// A `FirestoreResult` containing a `BoxStream` of `FirestoreResult<Document>`.
-    pub async fn stream_query_with_errors<'b>(
-        self,
-    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<Document>>> {
-        self.db.stream_query_doc_with_errors(self.params).await
+    /// A `FirestoreResult` containing the average, or `None` if there are no numeric values.
+    pub async fn avg<S>(self, field_path: S) -> FirestoreResult<Option<f64>>
+    where
+        S: AsRef<str>,
+    {
+        let value = self
+            .aggregate_single(|a| a.field("avg").avg(field_path.as_ref()))
+            .await?;
+        Ok(aggregated_number(&value))
     }
 
-    /// Executes the query and returns a stream of documents along with their metadata.
+    async fn aggregate_single<FN>(self, aggregation: FN) -> FirestoreResult<Option<Value>>
+    where
+        FN: Fn(FirestoreAggregationBuilder) -> Option<FirestoreAggregation>,
+    {
+        let mut docs = self
+            .aggregate(|a| aggregation(a).into_iter().collect())
+            .query()
+            .await?;
+        Ok(docs
+            .pop()
+            .and_then(|mut doc| doc.fields.drain().next().map(|(_, value)| value)))
+    }
+
+    /// Splits the query by its `IN`, `NOT_IN` and `ARRAY_CONTAINS_ANY` filters with more values
+    /// than Firestore supports in a single query, running the split queries concurrently
+    /// and merging their results in the order of the query.
     ///
-    /// Errors are yielded as `Err` items in the stream.
+    /// See [`FirestoreQuerySplitOptions`](crate::FirestoreQuerySplitOptions) for the details.
     ///
     /// # Returns
-    /// A `FirestoreResult` containing a `BoxStream` of `FirestoreResult<FirestoreWithMetadata<Document>>`.
-    pub async fn stream_query_with_metadata<'b>(
-        self,
-    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<Document>>>> {
-        self.db.stream_query_doc_with_metadata(self.params).await
+    /// A [`FirestoreSplitQueryDocBuilder`] to execute the split queries.
+    #[inline]
+    pub fn split_disjunctions(self) -> FirestoreSplitQueryDocBuilder<'a, D>
+    where
+        D: Sync,
+    {
+        FirestoreSplitQueryDocBuilder::new(self.db, self.params, self.retry_policy, self.timeout)
     }
-}
 
-/// A builder for executing a query and deserializing results into a Rust type `T`.
-#[derive(Clone, Debug)]
-pub struct FirestoreSelectObjBuilder<'a, D, T>
-where
+    /// Groups the documents matching this query by the given fields and computes
+    /// aggregations per group on the client side.
+    ///
+    /// Firestore has no server-side `GROUP BY`, so all matching documents are streamed.
+    /// Use [`FirestoreGroupByQueryBuilder::aggregate()`] to specify the aggregations.
+    ///
+    /// # Arguments
+    /// * `fields`: An iterator of field paths forming the group key.
+    ///
+    /// # Returns
+    /// A [`FirestoreGroupByQueryBuilder`] to configure and execute the group-by query.
+    #[inline]
+    pub fn group_by<I>(self, fields: I) -> FirestoreGroupByQueryBuilder<'a, D>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        FirestoreGroupByQueryBuilder::new(
+            self.db,
+            FirestoreGroupByParams::new(
+                self.params,
+                fields
+                    .into_iter()
+                    .map(|field| field.as_ref().to_string())
+                    .collect(),
+                vec![],
+            ),
+        )
+    }
+
+    /// Executes the configured query and retrieves all matching documents.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing a `Vec` of [`Document`]s.
+    pub async fn query(self) -> FirestoreResult<Vec<Document>> {
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(self.retry_policy, self.db.query_doc(self.params)),
+        )
+        .await
+    }
+
+    /// Executes the configured query and returns a stream of matching documents.
+    ///
+    /// Errors encountered during streaming will terminate the stream.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing a `BoxStream` of [`Document`]s.
+    pub async fn stream_query<'b>(self) -> FirestoreResult<BoxStream<'b, Document>> {
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(self.retry_policy, self.db.stream_query_doc(self.params)),
+        )
+        .await
+    }
+
+    /// Executes the configured query and returns a stream of `FirestoreResult<Document>`.
+    ///
+    /// Errors encountered during streaming are yielded as `Err` items in the stream.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing a `BoxStream` of `FirestoreResult<Document>`.
+    pub async fn stream_query_with_errors<'b>(
+        self,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<Document>>> {
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.stream_query_doc_with_errors(self.params),
+            ),
+        )
+        .await
+    }
+
+    /// Executes the query and returns a stream of documents along with their metadata.
+    ///
+    /// Errors are yielded as `Err` items in the stream.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing a `BoxStream` of `FirestoreResult<FirestoreWithMetadata<Document>>`.
+    pub async fn stream_query_with_metadata<'b>(
+        self,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<Document>>>> {
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.stream_query_doc_with_metadata(self.params),
+            ),
+        )
+        .await
+    }
+
+    /// Executes the query and returns a stream of documents with their create, update
+    /// and read times and the consistency selector of the read.
+    /// Responses without documents yield their read time as
+    /// [`FirestoreDocWithMetaItem::ReadTime`], so it's known even for queries without results.
+    ///
+    /// Errors are yielded as `Err` items in the stream.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing a `BoxStream` of `FirestoreResult<FirestoreDocWithMetaItem<Document>>`.
+    pub async fn stream_query_with_meta<'b>(
+        self,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocWithMetaItem<Document>>>> {
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.stream_query_doc_with_meta(self.params),
+            ),
+        )
+        .await
+    }
+}
+
+/// A builder for executing a query and deserializing results into a Rust type `T`.
+#[derive(Clone, Debug)]
+pub struct FirestoreSelectObjBuilder<'a, D, T>
+where
     D: FirestoreQuerySupport,
     T: Send,
     for<'de> T: Deserialize<'de>,
 {
     db: &'a D,
     params: FirestoreQueryParams,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
     _pd: PhantomData<T>,
 }
 
@@ -506,16 +1078,93 @@ where
         Self {
             db,
             params,
+            retry_policy: None,
+            timeout: None,
             _pd: PhantomData,
         }
     }
 
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of this operation, overriding [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    ///
+    /// The timeout is sent to Firestore as the gRPC deadline of the requests.
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Reads the documents as they were at the specified time (a point-in-time read).
+    ///
+    /// The read time must be within
+    /// [`FirestoreDbOptions::point_in_time_read_window`](crate::FirestoreDbOptions::point_in_time_read_window).
+    ///
+    /// # Arguments
+    /// * `read_time`: The time to read the documents at.
+    ///
+    /// # Returns
+    /// The builder instance with the read time set.
+    #[inline]
+    pub fn read_at(self, read_time: DateTime<Utc>) -> Self {
+        Self {
+            params: self
+                .params
+                .with_consistency_selector(FirestoreConsistencySelector::ReadTime(read_time)),
+            ..self
+        }
+    }
+
+    /// Reads the documents in the transaction,
+    /// see [`FirestoreSelectDocBuilder::in_transaction`].
+    #[inline]
+    pub fn in_transaction(self, transaction: &FirestoreTransaction<'_>) -> Self {
+        Self {
+            params: self.params.with_consistency_selector(
+                FirestoreConsistencySelector::Transaction(transaction.transaction_id().clone()),
+            ),
+            ..self
+        }
+    }
+
     /// Executes the query and deserializes all matching documents into a `Vec<T>`.
     ///
     /// # Returns
     /// A `FirestoreResult` containing a `Vec<T>`.
     pub async fn query(self) -> FirestoreResult<Vec<T>> {
-        self.db.query_obj(self.params).await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(self.retry_policy, self.db.query_obj(self.params)),
+        )
+        .await
+    }
+
+    /// Builds the [`StructuredQuery`] proto of this query, e.g. to store the query definition.
+    pub fn build_structured_query(&self) -> FirestoreResult<StructuredQuery> {
+        self.params.clone().try_into()
+    }
+
+    /// Returns the parameters of this query, including the parent path and the read options.
+    pub fn build_params(&self) -> FirestoreQueryParams {
+        self.params.clone()
+    }
+
+    /// Fetches the query results page by page, `page_size` objects at a time.
+    ///
+    /// # Returns
+    /// A [`FirestorePageQueryObjBuilder`] to set the page token and fetch the page.
+    #[inline]
+    pub fn page(self, page_size: u32) -> FirestorePageQueryObjBuilder<'a, D, T> {
+        FirestorePageQueryObjBuilder::new(self.db, self.params, page_size)
     }
 
     /// Executes the query and returns a stream of deserialized objects `T`.
@@ -528,7 +1177,11 @@ where
     where
         T: 'b,
     {
-        self.db.stream_query_obj(self.params).await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(self.retry_policy, self.db.stream_query_obj(self.params)),
+        )
+        .await
     }
 
     /// Executes the query and returns a stream of `FirestoreResult<T>`.
@@ -543,7 +1196,14 @@ where
     where
         T: 'b,
     {
-        self.db.stream_query_obj_with_errors(self.params).await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.stream_query_obj_with_errors(self.params),
+            ),
+        )
+        .await
     }
 
     /// Executes the query and returns a stream of deserialized objects `T` along with their metadata.
@@ -558,7 +1218,39 @@ where
     where
         T: 'b,
     {
-        self.db.stream_query_obj_with_metadata(self.params).await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.stream_query_obj_with_metadata(self.params),
+            ),
+        )
+        .await
+    }
+
+    /// Executes the query and returns a stream of deserialized objects `T` with their create,
+    /// update and read times and the consistency selector of the read.
+    /// Responses without documents yield their read time as
+    /// [`FirestoreDocWithMetaItem::ReadTime`], so it's known even for queries without results.
+    ///
+    /// Errors are yielded as `Err` items in the stream.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing a `BoxStream` of `FirestoreResult<FirestoreDocWithMetaItem<T>>`.
+    pub async fn stream_query_with_meta<'b>(
+        self,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocWithMetaItem<T>>>>
+    where
+        T: 'b,
+    {
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.stream_query_obj_with_meta(self.params),
+            ),
+        )
+        .await
     }
 
     /// Configures the query as a partitioned query for deserialized objects.
@@ -573,6 +1265,134 @@ where
     }
 }
 
+/// A builder for executing a query and deserializing every document into a Rust type `T`
+/// separately, returning the deserialization errors per document.
+#[derive(Clone, Debug)]
+pub struct FirestoreSelectObjLenientBuilder<'a, D, T>
+where
+    D: FirestoreQuerySupport,
+    T: Send,
+    for<'de> T: Deserialize<'de>,
+{
+    db: &'a D,
+    params: FirestoreQueryParams,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
+    _pd: PhantomData<T>,
+}
+
+impl<'a, D, T> FirestoreSelectObjLenientBuilder<'a, D, T>
+where
+    D: FirestoreQuerySupport,
+    T: Send,
+    for<'de> T: Deserialize<'de>,
+{
+    /// Creates a new `FirestoreSelectObjLenientBuilder`.
+    pub(crate) fn new(
+        db: &'a D,
+        params: FirestoreQueryParams,
+    ) -> FirestoreSelectObjLenientBuilder<'a, D, T> {
+        Self {
+            db,
+            params,
+            retry_policy: None,
+            timeout: None,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of this operation, overriding [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Reads the documents as they were at the specified time (a point-in-time read).
+    #[inline]
+    pub fn read_at(self, read_time: DateTime<Utc>) -> Self {
+        Self {
+            params: self
+                .params
+                .with_consistency_selector(FirestoreConsistencySelector::ReadTime(read_time)),
+            ..self
+        }
+    }
+
+    /// Reads the documents in the transaction.
+    #[inline]
+    pub fn in_transaction(self, transaction: &FirestoreTransaction<'_>) -> Self {
+        Self {
+            params: self.params.with_consistency_selector(
+                FirestoreConsistencySelector::Transaction(transaction.transaction_id().clone()),
+            ),
+            ..self
+        }
+    }
+
+    /// Executes the query and deserializes every matching document separately.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` failing only if the query itself fails, containing
+    /// the deserialization result of every document.
+    pub async fn query(self) -> FirestoreResult<Vec<FirestoreResult<T>>> {
+        let docs = firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(self.retry_policy, self.db.query_doc(self.params)),
+        )
+        .await?;
+        Ok(docs
+            .iter()
+            .map(FirestoreDb::deserialize_doc_to::<T>)
+            .collect())
+    }
+
+    /// Executes the query and returns a stream of the deserialization results of the documents.
+    ///
+    /// The documents failing to deserialize are yielded as `Err` items, and the stream
+    /// continues after them. An error while streaming the documents is yielded as the last
+    /// `Err` item, terminating the stream.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing a `BoxStream` of `FirestoreResult<T>`.
+    pub async fn stream_query<'b>(self) -> FirestoreResult<BoxStream<'b, FirestoreResult<T>>>
+    where
+        T: 'b,
+    {
+        let doc_stream = firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.stream_query_doc_with_errors(self.params),
+            ),
+        )
+        .await?;
+        Ok(Box::pin(doc_stream.scan(false, |failed, res| {
+            if *failed {
+                return futures::future::ready(None);
+            }
+            futures::future::ready(Some(match res {
+                Ok(doc) => FirestoreDb::deserialize_doc_to::<T>(&doc),
+                Err(err) => {
+                    *failed = true;
+                    Err(err)
+                }
+            }))
+        })))
+    }
+}
+
 /// A builder for selecting documents by their IDs from a collection.
 #[derive(Clone, Debug)]
 pub struct FirestoreSelectByIdBuilder<'a, D>
@@ -583,6 +1403,9 @@ where
     collection: String,
     parent: Option<String>,
     return_only_fields: Option<Vec<String>>,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
+    consistency_selector: Option<FirestoreConsistencySelector>,
 }
 
 impl<'a, D> FirestoreSelectByIdBuilder<'a, D>
@@ -600,6 +1423,59 @@ where
             collection,
             parent: None,
             return_only_fields,
+            retry_policy: None,
+            timeout: None,
+            consistency_selector: None,
+        }
+    }
+
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of this operation, overriding [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    ///
+    /// The timeout is sent to Firestore as the gRPC deadline of the requests.
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Reads the documents as they were at the specified time (a point-in-time read).
+    ///
+    /// The read time must be within
+    /// [`FirestoreDbOptions::point_in_time_read_window`](crate::FirestoreDbOptions::point_in_time_read_window).
+    ///
+    /// # Arguments
+    /// * `read_time`: The time to read the documents at.
+    ///
+    /// # Returns
+    /// The builder instance with the read time set.
+    #[inline]
+    pub fn read_at(self, read_time: DateTime<Utc>) -> Self {
+        Self {
+            consistency_selector: Some(FirestoreConsistencySelector::ReadTime(read_time)),
+            ..self
+        }
+    }
+
+    /// Reads the document in the transaction,
+    /// see [`FirestoreSelectDocBuilder::in_transaction`].
+    #[inline]
+    pub fn in_transaction(self, transaction: &FirestoreTransaction<'_>) -> Self {
+        Self {
+            consistency_selector: Some(FirestoreConsistencySelector::Transaction(
+                transaction.transaction_id().clone(),
+            )),
+            ..self
         }
     }
 
@@ -634,12 +1510,52 @@ where
         T: Send,
         for<'de> T: Deserialize<'de>,
     {
-        FirestoreSelectObjByIdBuilder::new(
-            self.db,
-            self.collection,
-            self.parent,
-            self.return_only_fields,
-        )
+        FirestoreSelectObjByIdBuilder {
+            retry_policy: self.retry_policy,
+            timeout: self.timeout,
+            consistency_selector: self.consistency_selector,
+            ..FirestoreSelectObjByIdBuilder::new(
+                self.db,
+                self.collection,
+                self.parent,
+                self.return_only_fields,
+            )
+        }
+    }
+
+    /// Specifies that the documents should be deserialized into a type `T` that may contain
+    /// only a part of the document fields, selected with `fields()`.
+    ///
+    /// # Errors
+    /// Returns [`FirestoreError::ProjectionError`](crate::errors::FirestoreError::ProjectionError)
+    /// listing the non-optional fields of `T` missing from the projection.
+    pub fn partial_obj<T>(self) -> FirestoreResult<FirestoreSelectObjByIdBuilder<'a, D, T>>
+    where
+        T: Send,
+        for<'de> T: Deserialize<'de>,
+    {
+        ensure_projection_fields::<T>(self.return_only_fields.as_ref())?;
+        Ok(self.obj())
+    }
+
+    /// Fetches a single document by its ID in the transaction.
+    /// A shortcut for `.in_transaction(transaction).one(document_id)`.
+    ///
+    /// # Arguments
+    /// * `document_id`: The ID of the document to fetch.
+    /// * `transaction`: The transaction to read the document in.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing an `Option<Document>`. `None` if the document doesn't exist.
+    pub async fn one_in_transaction<S>(
+        self,
+        document_id: S,
+        transaction: &FirestoreTransaction<'_>,
+    ) -> FirestoreResult<Option<Document>>
+    where
+        S: AsRef<str> + Send,
+    {
+        self.in_transaction(transaction).one(document_id).await
     }
 
     /// Fetches a single document by its ID.
@@ -653,40 +1569,51 @@ where
     where
         S: AsRef<str> + Send,
     {
-        if let Some(parent) = self.parent {
-            match self
-                .db
-                .get_doc_at::<S>(
-                    parent.as_str(),
-                    self.collection.as_str(),
-                    document_id,
-                    self.return_only_fields,
-                )
-                .await
-            {
-                Ok(doc) => Ok(Some(doc)),
-                Err(err) => match err {
-                    FirestoreError::DataNotFoundError(_) => Ok(None),
-                    _ => Err(err),
-                },
-            }
-        } else {
-            match self
-                .db
-                .get_doc::<S>(
-                    self.collection.as_str(),
-                    document_id,
-                    self.return_only_fields,
-                )
-                .await
-            {
-                Ok(doc) => Ok(Some(doc)),
-                Err(err) => match err {
-                    FirestoreError::DataNotFoundError(_) => Ok(None),
-                    _ => Err(err),
-                },
-            }
-        }
+        let retry_policy = self.retry_policy.clone();
+        let consistency_selector = self.consistency_selector.clone();
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                retry_policy,
+                FirestoreConsistencySelector::scope(consistency_selector, async move {
+                    if let Some(parent) = self.parent {
+                        match self
+                            .db
+                            .get_doc_at::<S>(
+                                parent.as_str(),
+                                self.collection.as_str(),
+                                document_id,
+                                self.return_only_fields,
+                            )
+                            .await
+                        {
+                            Ok(doc) => Ok(Some(doc)),
+                            Err(err) => match err {
+                                FirestoreError::DataNotFoundError(_) => Ok(None),
+                                _ => Err(err),
+                            },
+                        }
+                    } else {
+                        match self
+                            .db
+                            .get_doc::<S>(
+                                self.collection.as_str(),
+                                document_id,
+                                self.return_only_fields,
+                            )
+                            .await
+                        {
+                            Ok(doc) => Ok(Some(doc)),
+                            Err(err) => match err {
+                                FirestoreError::DataNotFoundError(_) => Ok(None),
+                                _ => Err(err),
+                            },
+                        }
+                    }
+                }),
+            ),
+        )
+        .await
     }
 
     /// Fetches multiple documents by their IDs in a batch.
@@ -707,24 +1634,35 @@ where
         S: AsRef<str> + Send,
         I: IntoIterator<Item = S> + Send,
     {
-        if let Some(parent) = self.parent {
-            self.db
-                .batch_stream_get_docs_at::<S, I>(
-                    parent.as_str(),
-                    self.collection.as_str(),
-                    document_ids,
-                    self.return_only_fields,
-                )
-                .await
-        } else {
-            self.db
-                .batch_stream_get_docs::<S, I>(
-                    self.collection.as_str(),
-                    document_ids,
-                    self.return_only_fields,
-                )
-                .await
-        }
+        let retry_policy = self.retry_policy.clone();
+        let consistency_selector = self.consistency_selector.clone();
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                retry_policy,
+                FirestoreConsistencySelector::scope(consistency_selector, async move {
+                    if let Some(parent) = self.parent {
+                        self.db
+                            .batch_stream_get_docs_at::<S, I>(
+                                parent.as_str(),
+                                self.collection.as_str(),
+                                document_ids,
+                                self.return_only_fields,
+                            )
+                            .await
+                    } else {
+                        self.db
+                            .batch_stream_get_docs::<S, I>(
+                                self.collection.as_str(),
+                                document_ids,
+                                self.return_only_fields,
+                            )
+                            .await
+                    }
+                }),
+            ),
+        )
+        .await
     }
 
     /// Fetches multiple documents by their IDs in a batch, yielding `FirestoreResult` for each.
@@ -744,24 +1682,35 @@ where
         S: AsRef<str> + Send,
         I: IntoIterator<Item = S> + Send,
     {
-        if let Some(parent) = self.parent {
-            self.db
-                .batch_stream_get_docs_at_with_errors::<S, I>(
-                    parent.as_str(),
-                    self.collection.as_str(),
-                    document_ids,
-                    self.return_only_fields,
-                )
-                .await
-        } else {
-            self.db
-                .batch_stream_get_docs_with_errors::<S, I>(
-                    self.collection.as_str(),
-                    document_ids,
-                    self.return_only_fields,
-                )
-                .await
-        }
+        let retry_policy = self.retry_policy.clone();
+        let consistency_selector = self.consistency_selector.clone();
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                retry_policy,
+                FirestoreConsistencySelector::scope(consistency_selector, async move {
+                    if let Some(parent) = self.parent {
+                        self.db
+                            .batch_stream_get_docs_at_with_errors::<S, I>(
+                                parent.as_str(),
+                                self.collection.as_str(),
+                                document_ids,
+                                self.return_only_fields,
+                            )
+                            .await
+                    } else {
+                        self.db
+                            .batch_stream_get_docs_with_errors::<S, I>(
+                                self.collection.as_str(),
+                                document_ids,
+                                self.return_only_fields,
+                            )
+                            .await
+                    }
+                }),
+            ),
+        )
+        .await
     }
 
     /// Sets up a real-time listener for changes to a specific set of documents by their IDs.
@@ -795,6 +1744,141 @@ where
     }
 }
 
+/// A builder for fetching a document by a [`FirestoreReference`].
+#[derive(Clone, Debug)]
+pub struct FirestoreSelectByReferenceBuilder<'a, D>
+where
+    D: FirestoreGetByIdSupport,
+{
+    by_id: FirestoreSelectByIdBuilder<'a, D>,
+    document_id: String,
+}
+
+impl<'a, D> FirestoreSelectByReferenceBuilder<'a, D>
+where
+    D: FirestoreGetByIdSupport + FirestoreListenSupport + Send + Sync + Clone + 'static,
+{
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            by_id: self.by_id.retry(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of this operation, overriding [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            by_id: self.by_id.timeout(timeout),
+            ..self
+        }
+    }
+
+    /// Reads the document as it was at the specified time (a point-in-time read).
+    #[inline]
+    pub fn read_at(self, read_time: DateTime<Utc>) -> Self {
+        Self {
+            by_id: self.by_id.read_at(read_time),
+            ..self
+        }
+    }
+
+    /// Reads the document in the transaction.
+    #[inline]
+    pub fn in_transaction(self, transaction: &FirestoreTransaction<'_>) -> Self {
+        Self {
+            by_id: self.by_id.in_transaction(transaction),
+            ..self
+        }
+    }
+
+    /// Specifies that the referenced document should be deserialized into a specific Rust type `T`.
+    #[inline]
+    pub fn obj<T>(self) -> FirestoreSelectObjByReferenceBuilder<'a, D, T>
+    where
+        T: Send,
+        for<'de> T: Deserialize<'de>,
+    {
+        FirestoreSelectObjByReferenceBuilder {
+            by_id: self.by_id.obj(),
+            document_id: self.document_id,
+        }
+    }
+
+    /// Fetches the referenced document.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing an `Option<Document>`. `None` if the document doesn't exist.
+    pub async fn one(self) -> FirestoreResult<Option<Document>> {
+        self.by_id.one(self.document_id).await
+    }
+}
+
+/// A builder for fetching a document by a [`FirestoreReference`] and deserializing it into a Rust type `T`.
+#[derive(Clone, Debug)]
+pub struct FirestoreSelectObjByReferenceBuilder<'a, D, T>
+where
+    D: FirestoreGetByIdSupport,
+    T: Send,
+    for<'de> T: Deserialize<'de>,
+{
+    by_id: FirestoreSelectObjByIdBuilder<'a, D, T>,
+    document_id: String,
+}
+
+impl<'a, D, T> FirestoreSelectObjByReferenceBuilder<'a, D, T>
+where
+    D: FirestoreGetByIdSupport,
+    T: Send,
+    for<'de> T: Deserialize<'de>,
+{
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            by_id: self.by_id.retry(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of this operation, overriding [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            by_id: self.by_id.timeout(timeout),
+            ..self
+        }
+    }
+
+    /// Reads the document as it was at the specified time (a point-in-time read).
+    #[inline]
+    pub fn read_at(self, read_time: DateTime<Utc>) -> Self {
+        Self {
+            by_id: self.by_id.read_at(read_time),
+            ..self
+        }
+    }
+
+    /// Reads the document in the transaction.
+    #[inline]
+    pub fn in_transaction(self, transaction: &FirestoreTransaction<'_>) -> Self {
+        Self {
+            by_id: self.by_id.in_transaction(transaction),
+            ..self
+        }
+    }
+
+    /// Fetches the referenced document and deserializes it into type `T`.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing an `Option<T>`. `None` if the document doesn't exist.
+    pub async fn one(self) -> FirestoreResult<Option<T>> {
+        self.by_id.one(self.document_id).await
+    }
+}
+
 /// A builder for fetching documents by ID and deserializing them into a Rust type `T`.
 #[derive(Clone, Debug)]
 pub struct FirestoreSelectObjByIdBuilder<'a, D, T>
@@ -807,6 +1891,9 @@ where
     collection: String,
     parent: Option<String>,
     return_only_fields: Option<Vec<String>>,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
+    consistency_selector: Option<FirestoreConsistencySelector>,
     _pd: PhantomData<T>,
 }
 
@@ -828,10 +1915,83 @@ where
             collection,
             parent,
             return_only_fields,
+            retry_policy: None,
+            timeout: None,
+            consistency_selector: None,
             _pd: PhantomData,
         }
     }
 
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of this operation, overriding [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    ///
+    /// The timeout is sent to Firestore as the gRPC deadline of the requests.
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Reads the documents as they were at the specified time (a point-in-time read).
+    ///
+    /// The read time must be within
+    /// [`FirestoreDbOptions::point_in_time_read_window`](crate::FirestoreDbOptions::point_in_time_read_window).
+    ///
+    /// # Arguments
+    /// * `read_time`: The time to read the documents at.
+    ///
+    /// # Returns
+    /// The builder instance with the read time set.
+    #[inline]
+    pub fn read_at(self, read_time: DateTime<Utc>) -> Self {
+        Self {
+            consistency_selector: Some(FirestoreConsistencySelector::ReadTime(read_time)),
+            ..self
+        }
+    }
+
+    /// Reads the document in the transaction,
+    /// see [`FirestoreSelectDocBuilder::in_transaction`].
+    #[inline]
+    pub fn in_transaction(self, transaction: &FirestoreTransaction<'_>) -> Self {
+        Self {
+            consistency_selector: Some(FirestoreConsistencySelector::Transaction(
+                transaction.transaction_id().clone(),
+            )),
+            ..self
+        }
+    }
+
+    /// Fetches a single document by its ID in the transaction.
+    /// A shortcut for `.in_transaction(transaction).one(document_id)`.
+    ///
+    /// # Arguments
+    /// * `document_id`: The ID of the document to fetch.
+    /// * `transaction`: The transaction to read the document in.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing an `Option<T>`. `None` if the document doesn't exist.
+    pub async fn one_in_transaction<S>(
+        self,
+        document_id: S,
+        transaction: &FirestoreTransaction<'_>,
+    ) -> FirestoreResult<Option<T>>
+    where
+        S: AsRef<str> + Send,
+    {
+        self.in_transaction(transaction).one(document_id).await
+    }
+
     /// Fetches a single document by its ID and deserializes it into type `T`.
     ///
     /// # Arguments
@@ -843,40 +2003,51 @@ where
     where
         S: AsRef<str> + Send,
     {
-        if let Some(parent) = self.parent {
-            match self
-                .db
-                .get_obj_at_return_fields::<T, S>(
-                    parent.as_str(),
-                    self.collection.as_str(),
-                    document_id,
-                    self.return_only_fields,
-                )
-                .await
-            {
-                Ok(doc) => Ok(Some(doc)),
-                Err(err) => match err {
-                    FirestoreError::DataNotFoundError(_) => Ok(None),
-                    _ => Err(err),
-                },
-            }
-        } else {
-            match self
-                .db
-                .get_obj_return_fields::<T, S>(
-                    self.collection.as_str(),
-                    document_id,
-                    self.return_only_fields,
-                )
-                .await
-            {
-                Ok(doc) => Ok(Some(doc)),
-                Err(err) => match err {
-                    FirestoreError::DataNotFoundError(_) => Ok(None),
-                    _ => Err(err),
-                },
-            }
-        }
+        let retry_policy = self.retry_policy.clone();
+        let consistency_selector = self.consistency_selector.clone();
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                retry_policy,
+                FirestoreConsistencySelector::scope(consistency_selector, async move {
+                    if let Some(parent) = self.parent {
+                        match self
+                            .db
+                            .get_obj_at_return_fields::<T, S>(
+                                parent.as_str(),
+                                self.collection.as_str(),
+                                document_id,
+                                self.return_only_fields,
+                            )
+                            .await
+                        {
+                            Ok(doc) => Ok(Some(doc)),
+                            Err(err) => match err {
+                                FirestoreError::DataNotFoundError(_) => Ok(None),
+                                _ => Err(err),
+                            },
+                        }
+                    } else {
+                        match self
+                            .db
+                            .get_obj_return_fields::<T, S>(
+                                self.collection.as_str(),
+                                document_id,
+                                self.return_only_fields,
+                            )
+                            .await
+                        {
+                            Ok(doc) => Ok(Some(doc)),
+                            Err(err) => match err {
+                                FirestoreError::DataNotFoundError(_) => Ok(None),
+                                _ => Err(err),
+                            },
+                        }
+                    }
+                }),
+            ),
+        )
+        .await
     }
 
     /// Fetches multiple documents by their IDs in a batch and deserializes them into type `T`.
@@ -898,24 +2069,35 @@ where
         I: IntoIterator<Item = S> + Send,
         T: Send + 'a,
     {
-        if let Some(parent) = self.parent {
-            self.db
-                .batch_stream_get_objects_at::<T, S, I>(
-                    parent.as_str(),
-                    self.collection.as_str(),
-                    document_ids,
-                    self.return_only_fields,
-                )
-                .await
-        } else {
-            self.db
-                .batch_stream_get_objects::<T, S, I>(
-                    self.collection.as_str(),
-                    document_ids,
-                    self.return_only_fields,
-                )
-                .await
-        }
+        let retry_policy = self.retry_policy.clone();
+        let consistency_selector = self.consistency_selector.clone();
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                retry_policy,
+                FirestoreConsistencySelector::scope(consistency_selector, async move {
+                    if let Some(parent) = self.parent {
+                        self.db
+                            .batch_stream_get_objects_at::<T, S, I>(
+                                parent.as_str(),
+                                self.collection.as_str(),
+                                document_ids,
+                                self.return_only_fields,
+                            )
+                            .await
+                    } else {
+                        self.db
+                            .batch_stream_get_objects::<T, S, I>(
+                                self.collection.as_str(),
+                                document_ids,
+                                self.return_only_fields,
+                            )
+                            .await
+                    }
+                }),
+            ),
+        )
+        .await
     }
 
     /// Fetches multiple documents by IDs in a batch, deserializing them and yielding `FirestoreResult`.
@@ -936,24 +2118,86 @@ where
         I: IntoIterator<Item = S> + Send,
         T: Send + 'a,
     {
-        if let Some(parent) = self.parent {
-            self.db
-                .batch_stream_get_objects_at_with_errors::<T, S, I>(
-                    parent.as_str(),
-                    self.collection.as_str(),
-                    document_ids,
-                    self.return_only_fields,
-                )
-                .await
-        } else {
-            self.db
-                .batch_stream_get_objects_with_errors::<T, S, I>(
-                    self.collection.as_str(),
-                    document_ids,
-                    self.return_only_fields,
-                )
-                .await
-        }
+        let retry_policy = self.retry_policy.clone();
+        let consistency_selector = self.consistency_selector.clone();
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                retry_policy,
+                FirestoreConsistencySelector::scope(consistency_selector, async move {
+                    if let Some(parent) = self.parent {
+                        self.db
+                            .batch_stream_get_objects_at_with_errors::<T, S, I>(
+                                parent.as_str(),
+                                self.collection.as_str(),
+                                document_ids,
+                                self.return_only_fields,
+                            )
+                            .await
+                    } else {
+                        self.db
+                            .batch_stream_get_objects_with_errors::<T, S, I>(
+                                self.collection.as_str(),
+                                document_ids,
+                                self.return_only_fields,
+                            )
+                            .await
+                    }
+                }),
+            ),
+        )
+        .await
+    }
+
+    /// Fetches multiple documents by IDs in a batch, returning an outcome for every
+    /// requested ID in the request order.
+    ///
+    /// Missing documents and documents that can't be deserialized are reported as
+    /// [`FirestoreBatchGetResult::Missing`] and [`FirestoreBatchGetResult::Error`].
+    ///
+    /// # Arguments
+    /// * `document_ids`: An iterator of document IDs to fetch.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing a `Vec` of `(String, FirestoreBatchGetResult<T>)`.
+    pub async fn batch_ordered<S, I>(
+        self,
+        document_ids: I,
+    ) -> FirestoreResult<Vec<(String, FirestoreBatchGetResult<T>)>>
+    where
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send,
+        T: Send,
+    {
+        let retry_policy = self.retry_policy.clone();
+        let consistency_selector = self.consistency_selector.clone();
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                retry_policy,
+                FirestoreConsistencySelector::scope(consistency_selector, async move {
+                    if let Some(parent) = self.parent {
+                        self.db
+                            .batch_get_objects_ordered_at::<T, S, I>(
+                                parent.as_str(),
+                                self.collection.as_str(),
+                                document_ids,
+                                self.return_only_fields,
+                            )
+                            .await
+                    } else {
+                        self.db
+                            .batch_get_objects_ordered::<T, S, I>(
+                                self.collection.as_str(),
+                                document_ids,
+                                self.return_only_fields,
+                            )
+                            .await
+                    }
+                }),
+            ),
+        )
+        .await
     }
 }
 
@@ -1043,17 +2287,75 @@ where
     /// A `FirestoreResult` containing a `BoxStream` of `FirestoreResult<(FirestorePartition, Document)>`.
     pub async fn stream_partitions_with_errors(
         self,
-    ) -> FirestoreResult<BoxStream<'a, FirestoreResult<(FirestorePartition, Document)>>> {
-        self.db
-            .stream_partition_query_doc_with_errors(
-                self.parallelism,
-                FirestorePartitionQueryParams::new(
-                    self.params,
-                    self.partition_count,
-                    self.page_size,
-                ),
-            )
-            .await
+    ) -> FirestoreResult<BoxStream<'a, FirestoreResult<(FirestorePartition, Document)>>> {
+        self.db
+            .stream_partition_query_doc_with_errors(
+                self.parallelism,
+                FirestorePartitionQueryParams::new(
+                    self.params,
+                    self.partition_count,
+                    self.page_size,
+                ),
+            )
+            .await
+    }
+
+    /// Requests the partitions of the query without running it.
+    ///
+    /// If the server detects the query has too few results to be partitioned,
+    /// a single partition covering the whole query is returned.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing a `Vec` of [`FirestorePartition`]s.
+    pub async fn get_partitions(self) -> FirestoreResult<Vec<FirestorePartition>> {
+        get_query_partitions(
+            self.db,
+            FirestorePartitionQueryParams::new(self.params, self.partition_count, self.page_size),
+        )
+        .await
+    }
+
+    /// Requests the partitions of the query and returns a stream of partitions, each with
+    /// its own stream of documents, so that they can be consumed concurrently
+    /// (e.g. with `for_each_concurrent` or by spawning a task per partition).
+    ///
+    /// A partition query is executed only when its documents stream is polled.
+    /// The `parallelism` setting is not used in this mode.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing a `BoxStream` of [`FirestorePartition`]s
+    /// paired with a `BoxStream` of `FirestoreResult<Document>`.
+    pub async fn stream_partitions(
+        self,
+    ) -> FirestoreResult<
+        BoxStream<'a, (FirestorePartition, BoxStream<'a, FirestoreResult<Document>>)>,
+    >
+    where
+        D: Sync,
+    {
+        let db = self.db;
+        let params = self.params.clone();
+        let partitions = get_query_partitions(
+            db,
+            FirestorePartitionQueryParams::new(self.params, self.partition_count, self.page_size),
+        )
+        .await?;
+
+        Ok(futures::stream::iter(partitions)
+            .map(move |partition| {
+                let params = params
+                    .clone()
+                    .opt_start_at(partition.start_at.clone())
+                    .opt_end_at(partition.end_at.clone());
+                let doc_stream =
+                    futures::stream::once(
+                        async move { db.stream_query_doc_with_errors(params).await },
+                    )
+                    .try_flatten()
+                    .boxed();
+                (partition, doc_stream)
+            })
+            .boxed())
     }
 }
 
@@ -1139,6 +2441,63 @@ where
             )
             .await
     }
+
+    /// Requests the partitions of the query without running it.
+    /// See [`FirestorePartitionQueryDocBuilder::get_partitions`].
+    pub async fn get_partitions(self) -> FirestoreResult<Vec<FirestorePartition>> {
+        get_query_partitions(
+            self.db,
+            FirestorePartitionQueryParams::new(self.params, self.partition_count, self.page_size),
+        )
+        .await
+    }
+
+    /// Returns a stream of partitions, each with its own stream of deserialized objects `T`.
+    /// See [`FirestorePartitionQueryDocBuilder::stream_partitions`].
+    pub async fn stream_partitions(
+        self,
+    ) -> FirestoreResult<BoxStream<'a, (FirestorePartition, BoxStream<'a, FirestoreResult<T>>)>>
+    where
+        D: Sync,
+    {
+        let db = self.db;
+        let params = self.params.clone();
+        let partitions = get_query_partitions(
+            db,
+            FirestorePartitionQueryParams::new(self.params, self.partition_count, self.page_size),
+        )
+        .await?;
+
+        Ok(futures::stream::iter(partitions)
+            .map(move |partition| {
+                let params = params
+                    .clone()
+                    .opt_start_at(partition.start_at.clone())
+                    .opt_end_at(partition.end_at.clone());
+                let obj_stream = futures::stream::once(async move {
+                    db.stream_query_obj_with_errors::<T>(params).await
+                })
+                .try_flatten()
+                .boxed();
+                (partition, obj_stream)
+            })
+            .boxed())
+    }
+}
+
+async fn get_query_partitions<D>(
+    db: &D,
+    params: FirestorePartitionQueryParams,
+) -> FirestoreResult<Vec<FirestorePartition>>
+where
+    D: FirestoreQuerySupport,
+{
+    let cursors: Vec<FirestoreQueryCursor> = db
+        .stream_partition_cursors_with_errors(params)
+        .await?
+        .try_collect()
+        .await?;
+    Ok(FirestorePartition::from_cursors(cursors))
 }
 
 /// Builder for initializing a Firestore document changes listener.
@@ -1238,6 +2597,8 @@ where
 {
     db: &'a D,
     params: FirestoreAggregatedQueryParams,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
 }
 
 impl<'a, D> FirestoreAggregatedQueryDocBuilder<'a, D>
@@ -1247,7 +2608,71 @@ where
     /// Creates a new `FirestoreAggregatedQueryDocBuilder`.
     #[inline]
     pub(crate) fn new(db: &'a D, params: FirestoreAggregatedQueryParams) -> Self {
-        Self { db, params }
+        Self {
+            db,
+            params,
+            retry_policy: None,
+            timeout: None,
+        }
+    }
+
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of this operation, overriding [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    ///
+    /// The timeout is sent to Firestore as the gRPC deadline of the requests.
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Reads the documents as they were at the specified time (a point-in-time read).
+    ///
+    /// The read time must be within
+    /// [`FirestoreDbOptions::point_in_time_read_window`](crate::FirestoreDbOptions::point_in_time_read_window).
+    ///
+    /// # Arguments
+    /// * `read_time`: The time to read the documents at.
+    ///
+    /// # Returns
+    /// The builder instance with the read time set.
+    #[inline]
+    pub fn read_at(self, read_time: DateTime<Utc>) -> Self {
+        Self {
+            params: FirestoreAggregatedQueryParams {
+                query_params: self
+                    .params
+                    .query_params
+                    .with_consistency_selector(FirestoreConsistencySelector::ReadTime(read_time)),
+                ..self.params
+            },
+            ..self
+        }
+    }
+
+    /// Reads the documents in the transaction,
+    /// see [`FirestoreSelectDocBuilder::in_transaction`].
+    #[inline]
+    pub fn in_transaction(self, transaction: &FirestoreTransaction<'_>) -> Self {
+        Self {
+            params: FirestoreAggregatedQueryParams {
+                query_params: self.params.query_params.with_consistency_selector(
+                    FirestoreConsistencySelector::Transaction(transaction.transaction_id().clone()),
+                ),
+                ..self.params
+            },
+            ..self
+        }
     }
 
     /// Specifies that the aggregation results should be deserialized into a specific Rust type `T`.
@@ -1265,7 +2690,11 @@ where
         T: Send,
         for<'de> T: Deserialize<'de>,
     {
-        FirestoreAggregatedQueryObjBuilder::new(self.db, self.params)
+        FirestoreAggregatedQueryObjBuilder {
+            retry_policy: self.retry_policy,
+            timeout: self.timeout,
+            ..FirestoreAggregatedQueryObjBuilder::new(self.db, self.params)
+        }
     }
 
     /// Executes the aggregation query and returns the results as raw documents.
@@ -1276,7 +2705,25 @@ where
     /// # Returns
     /// A `FirestoreResult` containing a `Vec` of [`Document`]s representing aggregation results.
     pub async fn query(self) -> FirestoreResult<Vec<Document>> {
-        self.db.aggregated_query_doc(self.params).await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.aggregated_query_doc(self.params),
+            ),
+        )
+        .await
+    }
+
+    /// Executes the aggregation query and returns its results, accessible by the aliases
+    /// or by the field paths of the aggregations, e.g. `results.sum(path!(MyStruct::totals.net))`.
+    pub async fn results(self) -> FirestoreResult<FirestoreAggregationResults> {
+        let aggregations = self.params.aggregations.clone();
+        let mut docs = self.query().await?;
+        Ok(FirestoreAggregationResults::new(
+            aggregations,
+            docs.pop().map(|doc| doc.fields).unwrap_or_default(),
+        ))
     }
 
     /// Executes the aggregation query and returns a stream of result documents.
@@ -1286,7 +2733,14 @@ where
     /// # Returns
     /// A `FirestoreResult` containing a `BoxStream` of [`Document`]s.
     pub async fn stream_query<'b>(self) -> FirestoreResult<BoxStream<'b, Document>> {
-        self.db.stream_aggregated_query_doc(self.params).await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.stream_aggregated_query_doc(self.params),
+            ),
+        )
+        .await
     }
 
     /// Executes the aggregation query and returns a stream of `FirestoreResult<Document>`.
@@ -1298,9 +2752,35 @@ where
     pub async fn stream_query_with_errors<'b>(
         self,
     ) -> FirestoreResult<BoxStream<'b, FirestoreResult<Document>>> {
-        self.db
-            .stream_aggregated_query_doc_with_errors(self.params)
-            .await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.stream_aggregated_query_doc_with_errors(self.params),
+            ),
+        )
+        .await
+    }
+
+    /// Executes the aggregation query and returns a stream of results with their metadata.
+    ///
+    /// Use this to access [`FirestoreExplainMetrics`](crate::FirestoreExplainMetrics)
+    /// when explain options are enabled.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing a `BoxStream` of `FirestoreResult<FirestoreWithMetadata<Document>>`.
+    pub async fn stream_query_with_metadata<'b>(
+        self,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<Document>>>> {
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db
+                    .stream_aggregated_query_doc_with_metadata(self.params),
+            ),
+        )
+        .await
     }
 }
 
@@ -1314,6 +2794,8 @@ where
 {
     db: &'a D,
     params: FirestoreAggregatedQueryParams,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
     _ph: PhantomData<T>,
 }
 
@@ -1329,16 +2811,120 @@ where
         Self {
             db,
             params,
+            retry_policy: None,
+            timeout: None,
             _ph: PhantomData,
         }
     }
 
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of this operation, overriding [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    ///
+    /// The timeout is sent to Firestore as the gRPC deadline of the requests.
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Reads the documents as they were at the specified time (a point-in-time read).
+    ///
+    /// The read time must be within
+    /// [`FirestoreDbOptions::point_in_time_read_window`](crate::FirestoreDbOptions::point_in_time_read_window).
+    ///
+    /// # Arguments
+    /// * `read_time`: The time to read the documents at.
+    ///
+    /// # Returns
+    /// The builder instance with the read time set.
+    #[inline]
+    pub fn read_at(self, read_time: DateTime<Utc>) -> Self {
+        Self {
+            params: FirestoreAggregatedQueryParams {
+                query_params: self
+                    .params
+                    .query_params
+                    .with_consistency_selector(FirestoreConsistencySelector::ReadTime(read_time)),
+                ..self.params
+            },
+            ..self
+        }
+    }
+
+    /// Reads the documents in the transaction,
+    /// see [`FirestoreSelectDocBuilder::in_transaction`].
+    #[inline]
+    pub fn in_transaction(self, transaction: &FirestoreTransaction<'_>) -> Self {
+        Self {
+            params: FirestoreAggregatedQueryParams {
+                query_params: self.params.query_params.with_consistency_selector(
+                    FirestoreConsistencySelector::Transaction(transaction.transaction_id().clone()),
+                ),
+                ..self.params
+            },
+            ..self
+        }
+    }
+
     /// Executes the aggregation query and deserializes all results into a `Vec<T>`.
     ///
     /// # Returns
     /// A `FirestoreResult` containing a `Vec<T>`.
     pub async fn query(self) -> FirestoreResult<Vec<T>> {
-        self.db.aggregated_query_obj(self.params).await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.aggregated_query_obj(self.params),
+            ),
+        )
+        .await
+    }
+
+    /// Executes the aggregation query separately for every bucket (range of values) of the field,
+    /// emulating `GROUP BY` on the server side. The queries run concurrently.
+    ///
+    /// # Arguments
+    /// * `field_path`: The field path the buckets are ranges of.
+    /// * `buckets`: The buckets, e.g. created with [`FirestoreAggregationBucket::time_buckets`].
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing the results by the bucket keys. Buckets without results are omitted.
+    pub async fn query_by_range<K, S>(
+        self,
+        field_path: S,
+        buckets: Vec<FirestoreAggregationBucket<K>>,
+    ) -> FirestoreResult<BTreeMap<K, T>>
+    where
+        D: Sync,
+        K: Ord + Send,
+        S: AsRef<str>,
+    {
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                aggregate_by_range_obj(
+                    self.db,
+                    FirestoreRangeAggregationParams::new(
+                        self.params,
+                        field_path.as_ref().to_string(),
+                    ),
+                    buckets,
+                ),
+            ),
+        )
+        .await
     }
 
     /// Executes the aggregation query and returns a stream of deserialized objects `T`.
@@ -1348,7 +2934,14 @@ where
     /// # Returns
     /// A `FirestoreResult` containing a `BoxStream` of `T`.
     pub async fn stream_query<'b>(self) -> FirestoreResult<BoxStream<'b, T>> {
-        self.db.stream_aggregated_query_obj(self.params).await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.stream_aggregated_query_obj(self.params),
+            ),
+        )
+        .await
     }
 
     /// Executes the aggregation query and returns a stream of `FirestoreResult<T>`.
@@ -1363,17 +2956,86 @@ where
     where
         T: 'b,
     {
-        self.db
-            .stream_aggregated_query_obj_with_errors(self.params)
-            .await
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db.stream_aggregated_query_obj_with_errors(self.params),
+            ),
+        )
+        .await
+    }
+
+    /// Executes the aggregation query and returns a stream of deserialized results with their metadata.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing a `BoxStream` of `FirestoreResult<FirestoreWithMetadata<T>>`.
+    pub async fn stream_query_with_metadata<'b>(
+        self,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<T>>>>
+    where
+        T: 'b,
+    {
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                self.db
+                    .stream_aggregated_query_obj_with_metadata(self.params),
+            ),
+        )
+        .await
+    }
+}
+
+fn ensure_projection_fields<T>(return_only_fields: Option<&Vec<String>>) -> FirestoreResult<()>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    let projection = match return_only_fields {
+        Some(projection) => projection,
+        None => return Ok(()),
+    };
+    let missing_fields: Vec<String> = firestore_required_fields::<T>()
+        .into_iter()
+        .filter(|field| !is_firestore_metadata_field(field))
+        .filter(|field| {
+            !projection.iter().any(|projected| {
+                projected == field
+                    || projected
+                        .strip_prefix(field)
+                        .map_or(false, |nested| nested.starts_with('.'))
+            })
+        })
+        .map(|field| field.to_string())
+        .collect();
+
+    if missing_fields.is_empty() {
+        Ok(())
+    } else {
+        Err(FirestoreError::ProjectionError(
+            FirestoreProjectionError::new(
+                FirestoreErrorPublicGenericDetails::new("ProjectionError".into()),
+                std::any::type_name::<T>().to_string(),
+                missing_fields,
+            ),
+        ))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::fluent_api::select_builder::{aggregated_count, aggregated_number};
     use crate::fluent_api::tests::*;
     use crate::fluent_api::FirestoreExprBuilder;
-    use crate::{path, paths, FirestoreQueryCollection};
+    use crate::{
+        path, paths, FirestoreConsistencySelector, FirestoreExplainOptions, FirestorePartition,
+        FirestoreQueryCollection, FirestoreQueryCursor, FirestoreQueryDirection,
+        FirestoreQueryFilter, FirestoreQueryFilterCompare, FirestoreQueryParams,
+        FirestoreReference, FirestoreTimestamp, FirestoreValue, FIRESTORE_DOCUMENT_NAME_FIELD,
+    };
+    use chrono::TimeZone;
+    use gcloud_sdk::google::firestore::v1::{value, Value};
 
     #[test]
     fn select_query_builder_test_fields() {
@@ -1392,6 +3054,294 @@ mod tests {
         )
     }
 
+    #[test]
+    fn select_query_builder_partial_obj() {
+        #[allow(dead_code)]
+        #[derive(serde::Deserialize)]
+        struct PartialStructure {
+            some_id: String,
+            some_num: u64,
+            one_more_string: Option<String>,
+        }
+
+        assert!(FirestoreExprBuilder::new(&mockdb::MockDatabase {})
+            .select()
+            .fields(paths!(TestStructure::{some_id, some_num}))
+            .from("test")
+            .partial_obj::<PartialStructure>()
+            .is_ok());
+
+        match FirestoreExprBuilder::new(&mockdb::MockDatabase {})
+            .select()
+            .fields(paths!(TestStructure::{one_more_string}))
+            .from("test")
+            .partial_obj::<PartialStructure>()
+        {
+            Err(crate::errors::FirestoreError::ProjectionError(err)) => {
+                assert_eq!(err.missing_fields, vec!["some_id", "some_num"]);
+            }
+            _ => panic!("Expected a projection error"),
+        }
+
+        #[allow(dead_code)]
+        #[derive(serde::Deserialize)]
+        struct PartialWithMetadata {
+            #[serde(rename = "_firestore_id")]
+            id: String,
+            #[serde(rename = "_firestore_updated")]
+            updated_at: chrono::DateTime<chrono::Utc>,
+            some_num: u64,
+        }
+
+        assert!(FirestoreExprBuilder::new(&mockdb::MockDatabase {})
+            .select()
+            .fields(paths!(TestStructure::{some_num}))
+            .from("test")
+            .partial_obj::<PartialWithMetadata>()
+            .is_ok());
+    }
+
+    #[test]
+    fn select_query_builder_explain_options() {
+        let select = FirestoreExprBuilder::new(&mockdb::MockDatabase {})
+            .select()
+            .explain_options(FirestoreExplainOptions::new().with_analyze(true))
+            .from("test");
+
+        assert_eq!(
+            select.params.explain_options,
+            Some(FirestoreExplainOptions::new().with_analyze(true))
+        );
+    }
+
+    #[test]
+    fn select_query_builder_collection_group() {
+        let select = FirestoreExprBuilder::new(&mockdb::MockDatabase {})
+            .select()
+            .from_collection_group("messages")
+            .parent("projects/test/databases/(default)/documents/rooms/room-1");
+
+        assert_eq!(
+            select.params.collection_id,
+            FirestoreQueryCollection::Group(vec!["messages".to_string()])
+        );
+        assert_eq!(select.params.all_descendants, Some(true));
+        assert_eq!(
+            select.params.parent.as_deref(),
+            Some("projects/test/databases/(default)/documents/rooms/room-1")
+        );
+    }
+
+    #[test]
+    fn select_query_builder_document_ids_follow_parent() {
+        let documents_path = "projects/test/databases/(default)/documents";
+        let params = FirestoreExprBuilder::new(&mockdb::MockDatabase {})
+            .select()
+            .from("messages")
+            .filter(|q| q.document_id().eq("m1"))
+            .parent(format!("{documents_path}/rooms/room-1"))
+            .build_params()
+            .resolve_document_ids(documents_path);
+
+        assert_eq!(
+            params.filter,
+            Some(FirestoreQueryFilter::Compare(Some(
+                FirestoreQueryFilterCompare::Equal(
+                    FIRESTORE_DOCUMENT_NAME_FIELD.to_string(),
+                    FirestoreValue::from(Value {
+                        value_type: Some(value::ValueType::ReferenceValue(format!(
+                            "{documents_path}/rooms/room-1/messages/m1"
+                        ))),
+                    })
+                )
+            )))
+        );
+    }
+
+    #[test]
+    fn select_query_builder_read_at() {
+        let read_time = chrono::Utc::now();
+        let select = FirestoreExprBuilder::new(&mockdb::MockDatabase {})
+            .select()
+            .from("test")
+            .read_at(read_time);
+
+        assert_eq!(
+            select.params.consistency_selector,
+            Some(FirestoreConsistencySelector::ReadTime(read_time))
+        );
+
+        let by_id = FirestoreExprBuilder::new(&mockdb::MockDatabase {})
+            .select()
+            .by_id_in("test")
+            .read_at(read_time)
+            .obj::<std::collections::HashMap<String, String>>();
+
+        assert_eq!(
+            by_id.consistency_selector,
+            Some(FirestoreConsistencySelector::ReadTime(read_time))
+        );
+    }
+
+    #[test]
+    fn select_query_builder_value_cursors() {
+        let select = FirestoreExprBuilder::new(&mockdb::MockDatabase {})
+            .select()
+            .from("test")
+            .order_by([
+                (
+                    path!(TestStructure::some_id),
+                    FirestoreQueryDirection::Ascending,
+                ),
+                (
+                    path!(TestStructure::some_num),
+                    FirestoreQueryDirection::Ascending,
+                ),
+            ])
+            .start_after_values::<[FirestoreValue; 2]>(["test-1".into(), 10.into()])
+            .end_before_values(["test-5"]);
+
+        assert_eq!(
+            select.params.start_at,
+            Some(FirestoreQueryCursor::AfterValue(vec![
+                "test-1".into(),
+                10.into()
+            ]))
+        );
+        assert_eq!(
+            select.params.end_at,
+            Some(FirestoreQueryCursor::BeforeValue(vec!["test-5".into()]))
+        );
+    }
+
+    #[test]
+    fn aggregated_number_values() {
+        let integer = Some(Value {
+            value_type: Some(value::ValueType::IntegerValue(42)),
+        });
+        let double = Some(Value {
+            value_type: Some(value::ValueType::DoubleValue(1.5)),
+        });
+        let null = Some(Value {
+            value_type: Some(value::ValueType::NullValue(0)),
+        });
+        assert_eq!(aggregated_number(&integer), Some(42.0));
+        assert_eq!(aggregated_number(&double), Some(1.5));
+        assert_eq!(aggregated_number(&null), None);
+        assert_eq!(aggregated_number(&None), None);
+    }
+
+    #[test]
+    fn aggregated_count_values() {
+        let count = |v: i64| {
+            Some(Value {
+                value_type: Some(value::ValueType::IntegerValue(v)),
+            })
+        };
+        // Above 2^53, where f64 can't represent all integers
+        assert_eq!(
+            aggregated_count(&count(9_007_199_254_740_993)),
+            Some(9_007_199_254_740_993)
+        );
+        assert_eq!(aggregated_count(&count(-1)), None);
+        assert_eq!(aggregated_count(&None), None);
+    }
+
+    #[test]
+    fn select_build_structured_query() {
+        let query = FirestoreExprBuilder::new(&mockdb::MockDatabase {})
+            .select()
+            .from("test")
+            .limit(5)
+            .build_structured_query()
+            .unwrap();
+
+        assert_eq!(query.from[0].collection_id, "test");
+        assert_eq!(query.limit, Some(5));
+    }
+
+    #[test]
+    fn select_build_params_round_trip() {
+        let created_at = chrono::Utc.with_ymd_and_hms(2024, 1, 1, 10, 0, 0).unwrap();
+        let select = FirestoreExprBuilder::new(&mockdb::MockDatabase {})
+            .select()
+            .fields(paths!(TestStructure::{some_id, some_num}))
+            .from("test")
+            .filter(|q| {
+                q.for_all([
+                    q.field(path!(TestStructure::some_num)).greater_than(10),
+                    q.field("created_at")
+                        .less_than(FirestoreTimestamp(created_at)),
+                    q.field("tags").array_contains_any(["a", "b"]),
+                ])
+            })
+            .order_by([(
+                path!(TestStructure::some_num),
+                FirestoreQueryDirection::Descending,
+            )])
+            .start_after_values([100])
+            .limit(5);
+
+        let json = serde_json::to_string(&select.build_params()).unwrap();
+        assert!(json.contains(r#"{"timestampValue":"2024-01-01T10:00:00Z"}"#));
+
+        let params: FirestoreQueryParams = serde_json::from_str(&json).unwrap();
+        assert_eq!(params, select.build_params());
+
+        let replayed = FirestoreExprBuilder::new(&mockdb::MockDatabase {})
+            .select()
+            .from_params(params);
+        assert_eq!(
+            replayed.build_structured_query().unwrap(),
+            select.build_structured_query().unwrap()
+        );
+    }
+
+    #[test]
+    fn select_by_reference_builder() {
+        let reference = FirestoreReference::new(
+            "projects/p/databases/(default)/documents/parents/p1/children/c1".to_string(),
+        );
+        let select = FirestoreExprBuilder::new(&mockdb::MockDatabase {})
+            .select()
+            .by_reference(&reference)
+            .unwrap();
+
+        assert_eq!(select.by_id.collection, "children");
+        assert_eq!(
+            select.by_id.parent.as_deref(),
+            Some("projects/p/databases/(default)/documents/parents/p1")
+        );
+        assert_eq!(select.document_id, "c1");
+
+        assert!(FirestoreExprBuilder::new(&mockdb::MockDatabase {})
+            .select()
+            .by_reference(&FirestoreReference::new("children".to_string()))
+            .is_err());
+    }
+
+    #[test]
+    fn partitions_from_cursors() {
+        let cursor_1 = FirestoreQueryCursor::BeforeValue(vec!["a".into()]);
+        let cursor_2 = FirestoreQueryCursor::BeforeValue(vec!["b".into()]);
+
+        assert_eq!(
+            FirestorePartition::from_cursors(vec![cursor_1.clone(), cursor_2.clone()]),
+            vec![
+                FirestorePartition::new().with_end_at(cursor_1.clone()),
+                FirestorePartition::new()
+                    .with_start_at(cursor_1)
+                    .with_end_at(cursor_2.clone()),
+                FirestorePartition::new().with_start_at(cursor_2),
+            ]
+        );
+
+        assert_eq!(
+            FirestorePartition::from_cursors(vec![]),
+            vec![FirestorePartition::new()]
+        );
+    }
+
     #[test]
     fn select_query_builder_from_collection() {
         let select_only_fields = FirestoreExprBuilder::new(&mockdb::MockDatabase {})
@@ -5,15 +5,19 @@
 //! - Simple field comparisons (e.g., equality, greater than).
 //! - Unary filters (e.g., IS NULL, IS NAN).
 //! - Composite filters (AND, OR) to combine multiple conditions.
+//! - Document ID (`__name__`) comparisons and key ranges.
 //!
 //! The main entry point is [`FirestoreQueryFilterBuilder`], which is typically
 //! accessed within a closure passed to the `.filter()` method of a query builder
 //! (e.g., [`FirestoreSelectDocBuilder::filter()`](crate::FirestoreSelectDocBuilder::filter)).
 
 use crate::{
-    FirestoreQueryFilter, FirestoreQueryFilterCompare, FirestoreQueryFilterComposite,
-    FirestoreQueryFilterCompositeOperator, FirestoreQueryFilterUnary, FirestoreValue,
+    FirestoreQueryCollection, FirestoreQueryFilter, FirestoreQueryFilterCompare,
+    FirestoreQueryFilterComposite, FirestoreQueryFilterCompositeOperator,
+    FirestoreQueryFilterUnary, FirestoreQueryParams, FirestoreValue, FIRESTORE_DOCUMENT_NAME_FIELD,
 };
+use gcloud_sdk::google::firestore::v1::{value, ArrayValue, Value};
+use std::ops::{Bound, RangeBounds};
 
 /// A builder for constructing Firestore query filters.
 ///
@@ -23,13 +27,33 @@ use crate::{
 /// to apply comparison or unary operators.
 
 #[derive(Clone, Debug)]
-pub struct FirestoreQueryFilterBuilder;
+pub struct FirestoreQueryFilterBuilder {
+    /// The path the document IDs are resolved against, usually the queried collection.
+    document_ids_parent: Option<String>,
+}
 
 impl FirestoreQueryFilterBuilder {
     /// Creates a new `FirestoreQueryFilterBuilder`.
     /// This is typically not called directly but provided within a `.filter()` closure.
     pub(crate) fn new() -> Self {
-        Self {}
+        Self {
+            document_ids_parent: None,
+        }
+    }
+
+    /// Creates a builder resolving the document IDs against the collection of the query.
+    /// For collection groups, the IDs are resolved against the parent of the query.
+    pub(crate) fn for_query(documents_path: &str, params: &FirestoreQueryParams) -> Self {
+        let parent = params.parent.as_deref().unwrap_or(documents_path);
+        let document_ids_parent = match params.collection_id {
+            FirestoreQueryCollection::Single(ref collection_id) => {
+                format!("{parent}/{collection_id}")
+            }
+            FirestoreQueryCollection::Group(_) => parent.to_string(),
+        };
+        Self {
+            document_ids_parent: Some(document_ids_parent),
+        }
     }
 
     /// Internal helper to build a composite filter (AND or OR).
@@ -105,6 +129,30 @@ impl FirestoreQueryFilterBuilder {
         )
     }
 
+    /// Creates a composite filter where all provided filter expressions must be true (logical AND).
+    /// Alias for [`for_all()`](#method.for_all).
+    #[inline]
+    pub fn and<I>(&self, filter_expressions: I) -> Option<FirestoreQueryFilter>
+    where
+        I: IntoIterator,
+        I::Item: FirestoreQueryFilterExpr,
+    {
+        self.for_all(filter_expressions)
+    }
+
+    /// Creates a composite filter where at least one of the provided filter expressions must be true (logical OR).
+    /// Alias for [`for_any()`](#method.for_any).
+    ///
+    /// Composite filters can be nested arbitrarily, e.g. `q.or([q.and([...]), q.field(...).eq(...)])`.
+    #[inline]
+    pub fn or<I>(&self, filter_expressions: I) -> Option<FirestoreQueryFilter>
+    where
+        I: IntoIterator,
+        I::Item: FirestoreQueryFilterExpr,
+    {
+        self.for_any(filter_expressions)
+    }
+
     /// Specifies a document field to apply a filter condition to.
     ///
     /// # Arguments
@@ -119,6 +167,20 @@ impl FirestoreQueryFilterBuilder {
     {
         FirestoreQueryFilterFieldExpr::new(field_name.as_ref().to_string())
     }
+
+    /// Specifies the document ID (the `__name__` field) to apply a filter condition to.
+    ///
+    /// Document IDs are converted to the reference values with the full document paths
+    /// Firestore expects. IDs are resolved against the queried collection; for collection
+    /// group queries, specify the paths relative to the parent of the query
+    /// (e.g. `"users/u1/orders/o1"`). Full paths (starting with `projects/`) are used as is.
+    ///
+    /// # Returns
+    /// A [`FirestoreQueryFilterDocumentIdExpr`] to specify the comparison.
+    #[inline]
+    pub fn document_id(&self) -> FirestoreQueryFilterDocumentIdExpr {
+        FirestoreQueryFilterDocumentIdExpr::new(self.document_ids_parent.clone())
+    }
 }
 
 /// A trait for types that can be converted into a [`FirestoreQueryFilter`].
@@ -313,6 +375,199 @@ impl FirestoreQueryFilterFieldExpr {
     }
 }
 
+/// Represents the document ID (the `__name__` field) targeted for a filter condition.
+///
+/// Created by [`FirestoreQueryFilterBuilder::document_id`]. Comparisons follow the order of
+/// the document paths, so key ranges (e.g. to shard scans of a collection) can be built
+/// with [`in_range()`](Self::in_range).
+pub struct FirestoreQueryFilterDocumentIdExpr {
+    document_ids_parent: Option<String>,
+}
+
+impl FirestoreQueryFilterDocumentIdExpr {
+    /// Creates a new `FirestoreQueryFilterDocumentIdExpr` resolving the IDs against the parent.
+    pub(crate) fn new(document_ids_parent: Option<String>) -> Self {
+        Self {
+            document_ids_parent,
+        }
+    }
+
+    /// Converts the document ID to the reference value with the full document path.
+    pub fn reference_value<S>(&self, document_id: S) -> FirestoreValue
+    where
+        S: AsRef<str>,
+    {
+        let document_id = document_id.as_ref();
+        let document_path = match self.document_ids_parent {
+            Some(ref parent) if !document_id.starts_with("projects/") => {
+                format!("{parent}/{document_id}")
+            }
+            _ => document_id.to_string(),
+        };
+        FirestoreValue::from(Value {
+            value_type: Some(value::ValueType::ReferenceValue(document_path)),
+        })
+    }
+
+    fn reference_values<I>(&self, document_ids: I) -> FirestoreValue
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        FirestoreValue::from(Value {
+            value_type: Some(value::ValueType::ArrayValue(ArrayValue {
+                values: document_ids
+                    .into_iter()
+                    .map(|document_id| self.reference_value(document_id).value)
+                    .collect(),
+            })),
+        })
+    }
+
+    #[inline]
+    fn compare<S, F>(&self, document_id: S, compare: F) -> Option<FirestoreQueryFilter>
+    where
+        S: AsRef<str>,
+        F: FnOnce(String, FirestoreValue) -> FirestoreQueryFilterCompare,
+    {
+        Some(FirestoreQueryFilter::Compare(Some(compare(
+            FIRESTORE_DOCUMENT_NAME_FIELD.to_string(),
+            self.reference_value(document_id),
+        ))))
+    }
+
+    /// Creates an "equal to" filter. Alias for [`equal()`](#method.equal).
+    #[inline]
+    pub fn eq<S>(self, document_id: S) -> Option<FirestoreQueryFilter>
+    where
+        S: AsRef<str>,
+    {
+        self.equal(document_id)
+    }
+
+    /// Creates a "not equal to" filter. Alias for [`not_equal()`](#method.not_equal).
+    #[inline]
+    pub fn neq<S>(self, document_id: S) -> Option<FirestoreQueryFilter>
+    where
+        S: AsRef<str>,
+    {
+        self.not_equal(document_id)
+    }
+
+    /// Creates a filter matching the document with the ID.
+    #[inline]
+    pub fn equal<S>(self, document_id: S) -> Option<FirestoreQueryFilter>
+    where
+        S: AsRef<str>,
+    {
+        self.compare(document_id, FirestoreQueryFilterCompare::Equal)
+    }
+
+    /// Creates a filter matching all documents except the one with the ID.
+    #[inline]
+    pub fn not_equal<S>(self, document_id: S) -> Option<FirestoreQueryFilter>
+    where
+        S: AsRef<str>,
+    {
+        self.compare(document_id, FirestoreQueryFilterCompare::NotEqual)
+    }
+
+    /// Creates a filter matching the documents ordered before the ID.
+    #[inline]
+    pub fn less_than<S>(self, document_id: S) -> Option<FirestoreQueryFilter>
+    where
+        S: AsRef<str>,
+    {
+        self.compare(document_id, FirestoreQueryFilterCompare::LessThan)
+    }
+
+    /// Creates a filter matching the documents ordered before the ID, and the document itself.
+    #[inline]
+    pub fn less_than_or_equal<S>(self, document_id: S) -> Option<FirestoreQueryFilter>
+    where
+        S: AsRef<str>,
+    {
+        self.compare(document_id, FirestoreQueryFilterCompare::LessThanOrEqual)
+    }
+
+    /// Creates a filter matching the documents ordered after the ID.
+    #[inline]
+    pub fn greater_than<S>(self, document_id: S) -> Option<FirestoreQueryFilter>
+    where
+        S: AsRef<str>,
+    {
+        self.compare(document_id, FirestoreQueryFilterCompare::GreaterThan)
+    }
+
+    /// Creates a filter matching the documents ordered after the ID, and the document itself.
+    #[inline]
+    pub fn greater_than_or_equal<S>(self, document_id: S) -> Option<FirestoreQueryFilter>
+    where
+        S: AsRef<str>,
+    {
+        self.compare(document_id, FirestoreQueryFilterCompare::GreaterThanOrEqual)
+    }
+
+    /// Creates a filter matching the documents with any of the IDs.
+    #[inline]
+    pub fn is_in<I>(self, document_ids: I) -> Option<FirestoreQueryFilter>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        Some(FirestoreQueryFilter::Compare(Some(
+            FirestoreQueryFilterCompare::In(
+                FIRESTORE_DOCUMENT_NAME_FIELD.to_string(),
+                self.reference_values(document_ids),
+            ),
+        )))
+    }
+
+    /// Creates a filter matching the documents with none of the IDs.
+    #[inline]
+    pub fn is_not_in<I>(self, document_ids: I) -> Option<FirestoreQueryFilter>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        Some(FirestoreQueryFilter::Compare(Some(
+            FirestoreQueryFilterCompare::NotIn(
+                FIRESTORE_DOCUMENT_NAME_FIELD.to_string(),
+                self.reference_values(document_ids),
+            ),
+        )))
+    }
+
+    /// Creates a filter matching the documents with the IDs in the key range,
+    /// e.g. `"a".."m"` or `"m"..`. Returns `None` for an unbounded range.
+    #[inline]
+    pub fn in_range<S, R>(self, range: R) -> Option<FirestoreQueryFilter>
+    where
+        S: AsRef<str>,
+        R: RangeBounds<S>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(document_id) => {
+                self.compare(document_id, FirestoreQueryFilterCompare::GreaterThanOrEqual)
+            }
+            Bound::Excluded(document_id) => {
+                self.compare(document_id, FirestoreQueryFilterCompare::GreaterThan)
+            }
+            Bound::Unbounded => None,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(document_id) => {
+                self.compare(document_id, FirestoreQueryFilterCompare::LessThanOrEqual)
+            }
+            Bound::Excluded(document_id) => {
+                self.compare(document_id, FirestoreQueryFilterCompare::LessThan)
+            }
+            Bound::Unbounded => None,
+        };
+        FirestoreQueryFilterBuilder::new().for_all([start, end])
+    }
+}
+
 impl FirestoreQueryFilterExpr for FirestoreQueryFilter {
     #[inline]
     fn build_filter(self) -> Option<FirestoreQueryFilter> {
@@ -331,3 +586,161 @@ where
         self.and_then(|expr| expr.build_filter())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcloud_sdk::google::firestore::v1::structured_query;
+
+    #[test]
+    fn nested_or_and_filters() {
+        let q = FirestoreQueryFilterBuilder::new();
+        let filter = q
+            .or([
+                q.and([q.field("a").eq(1), q.field("b").greater_than(2)]),
+                q.field("c").is_null(),
+                None,
+            ])
+            .unwrap();
+
+        match &filter {
+            FirestoreQueryFilter::Composite(composite) => {
+                assert_eq!(
+                    composite.operator,
+                    FirestoreQueryFilterCompositeOperator::Or
+                );
+                assert_eq!(composite.for_all_filters.len(), 2);
+                assert!(matches!(
+                    &composite.for_all_filters[0],
+                    FirestoreQueryFilter::Composite(inner)
+                        if inner.operator == FirestoreQueryFilterCompositeOperator::And
+                ));
+            }
+            other => panic!("Unexpected filter: {other:?}"),
+        }
+
+        let proto_filter: structured_query::Filter = filter.into();
+        match proto_filter.filter_type {
+            Some(structured_query::filter::FilterType::CompositeFilter(composite)) => {
+                assert_eq!(
+                    composite.op,
+                    structured_query::composite_filter::Operator::Or as i32
+                );
+                assert_eq!(composite.filters.len(), 2);
+            }
+            other => panic!("Unexpected filter: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn single_or_filter_is_unwrapped() {
+        let q = FirestoreQueryFilterBuilder::new();
+        assert!(matches!(
+            q.or([q.field("a").eq("x")]),
+            Some(FirestoreQueryFilter::Compare(_))
+        ));
+        assert_eq!(q.or(Vec::<Option<FirestoreQueryFilter>>::new()), None);
+    }
+
+    fn reference(path: &str) -> FirestoreValue {
+        FirestoreValue::from(Value {
+            value_type: Some(value::ValueType::ReferenceValue(path.to_string())),
+        })
+    }
+
+    #[test]
+    fn document_ids_are_resolved_to_references() {
+        let documents_path = "projects/p/databases/d/documents";
+        let q = FirestoreQueryFilterBuilder::for_query(
+            documents_path,
+            &FirestoreQueryParams::new("users".into()),
+        );
+
+        assert_eq!(
+            q.document_id().greater_than("u1"),
+            Some(FirestoreQueryFilter::Compare(Some(
+                FirestoreQueryFilterCompare::GreaterThan(
+                    "__name__".to_string(),
+                    reference("projects/p/databases/d/documents/users/u1")
+                )
+            )))
+        );
+        assert_eq!(
+            q.document_id()
+                .eq("projects/p/databases/d/documents/users/u2"),
+            Some(FirestoreQueryFilter::Compare(Some(
+                FirestoreQueryFilterCompare::Equal(
+                    "__name__".to_string(),
+                    reference("projects/p/databases/d/documents/users/u2")
+                )
+            )))
+        );
+        assert_eq!(
+            q.document_id().is_in(["u1", "u2"]),
+            Some(FirestoreQueryFilter::Compare(Some(
+                FirestoreQueryFilterCompare::In(
+                    "__name__".to_string(),
+                    FirestoreValue::from(Value {
+                        value_type: Some(value::ValueType::ArrayValue(ArrayValue {
+                            values: vec![
+                                reference("projects/p/databases/d/documents/users/u1").value,
+                                reference("projects/p/databases/d/documents/users/u2").value,
+                            ]
+                        }))
+                    })
+                )
+            )))
+        );
+
+        let group = FirestoreQueryFilterBuilder::for_query(
+            documents_path,
+            &FirestoreQueryParams::new(FirestoreQueryCollection::Group(vec!["orders".into()]))
+                .with_parent(format!("{documents_path}/users/u1")),
+        );
+        assert_eq!(
+            group.document_id().less_than("orders/o1"),
+            Some(FirestoreQueryFilter::Compare(Some(
+                FirestoreQueryFilterCompare::LessThan(
+                    "__name__".to_string(),
+                    reference("projects/p/databases/d/documents/users/u1/orders/o1")
+                )
+            )))
+        );
+    }
+
+    #[test]
+    fn document_id_key_ranges() {
+        let q = FirestoreQueryFilterBuilder::for_query(
+            "projects/p/databases/d/documents",
+            &FirestoreQueryParams::new("users".into()),
+        );
+
+        assert_eq!(
+            q.document_id().in_range("a".."m"),
+            Some(FirestoreQueryFilter::Composite(
+                FirestoreQueryFilterComposite::new(
+                    vec![
+                        FirestoreQueryFilter::Compare(Some(
+                            FirestoreQueryFilterCompare::GreaterThanOrEqual(
+                                "__name__".to_string(),
+                                reference("projects/p/databases/d/documents/users/a")
+                            )
+                        )),
+                        FirestoreQueryFilter::Compare(Some(FirestoreQueryFilterCompare::LessThan(
+                            "__name__".to_string(),
+                            reference("projects/p/databases/d/documents/users/m")
+                        ))),
+                    ],
+                    FirestoreQueryFilterCompositeOperator::And
+                )
+            ))
+        );
+        assert!(matches!(
+            q.document_id().in_range("m"..),
+            Some(FirestoreQueryFilter::Compare(Some(
+                FirestoreQueryFilterCompare::GreaterThanOrEqual(_, _)
+            )))
+        ));
+        assert_eq!(q.document_id().in_range::<&str, _>(..), None);
+    }
+}
@@ -0,0 +1,315 @@
+//! Builder for client-side group-by aggregations.
+//!
+//! Firestore does not support `GROUP BY`, so this module streams the documents matching
+//! a query and computes aggregations (count, sum, avg, min, max) per group key locally.
+//!
+//! The main entry point is [`FirestoreGroupByQueryBuilder`], which is accessed via
+//! [`FirestoreSelectDocBuilder::group_by()`](crate::select_builder::FirestoreSelectDocBuilder::group_by).
+
+use crate::{
+    group_by_query_obj, stream_group_by_query_obj_with_errors, FirestoreGroupAggregation,
+    FirestoreGroupAggregationOperator, FirestoreGroupByParams, FirestoreGroupByStrategy,
+    FirestoreQuerySupport, FirestoreResult,
+};
+use futures::stream::BoxStream;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+/// A builder for a client-side group-by query.
+#[derive(Clone, Debug)]
+pub struct FirestoreGroupByQueryBuilder<'a, D>
+where
+    D: FirestoreQuerySupport,
+{
+    db: &'a D,
+    params: FirestoreGroupByParams,
+}
+
+impl<'a, D> FirestoreGroupByQueryBuilder<'a, D>
+where
+    D: FirestoreQuerySupport,
+{
+    /// Creates a new `FirestoreGroupByQueryBuilder`.
+    #[inline]
+    pub(crate) fn new(db: &'a D, params: FirestoreGroupByParams) -> Self {
+        Self { db, params }
+    }
+
+    /// Specifies the aggregations computed for every group.
+    ///
+    /// The `aggregation` argument is a closure that receives a [`FirestoreGroupAggregationBuilder`]
+    /// and should return a `Vec<FirestoreGroupAggregation>`.
+    #[inline]
+    pub fn aggregate<FN>(self, aggregation: FN) -> Self
+    where
+        FN: Fn(FirestoreGroupAggregationBuilder) -> Vec<FirestoreGroupAggregation>,
+    {
+        Self {
+            params: self
+                .params
+                .with_aggregations(aggregation(FirestoreGroupAggregationBuilder::new())),
+            ..self
+        }
+    }
+
+    /// Specifies the strategy used to accumulate groups.
+    /// See [`FirestoreGroupByStrategy`] for the memory trade-offs.
+    #[inline]
+    pub fn strategy(self, strategy: FirestoreGroupByStrategy) -> Self {
+        Self {
+            params: self.params.with_strategy(strategy),
+            ..self
+        }
+    }
+
+    /// Uses [`FirestoreGroupByStrategy::SortedStream`], ordering the query by the group fields
+    /// and keeping only one group in memory at a time.
+    #[inline]
+    pub fn sorted_stream(self) -> Self {
+        self.strategy(FirestoreGroupByStrategy::SortedStream)
+    }
+
+    /// Specifies that group keys are deserialized into `K` and aggregation results into `T`.
+    ///
+    /// With a single group field `K` is deserialized from the field value, with several
+    /// fields it is deserialized from an array of the values (e.g. a tuple).
+    /// The fields of `T` should match the aliases defined in the aggregations.
+    #[inline]
+    pub fn obj<K, T>(self) -> FirestoreGroupByQueryObjBuilder<'a, D, K, T>
+    where
+        K: Ord + Send,
+        for<'de> K: Deserialize<'de>,
+        T: Send,
+        for<'de> T: Deserialize<'de>,
+    {
+        FirestoreGroupByQueryObjBuilder::new(self.db, self.params)
+    }
+}
+
+/// A builder for executing a group-by query and deserializing groups into `K` and `T`.
+#[derive(Clone, Debug)]
+pub struct FirestoreGroupByQueryObjBuilder<'a, D, K, T>
+where
+    D: FirestoreQuerySupport,
+    K: Ord + Send,
+    for<'de> K: Deserialize<'de>,
+    T: Send,
+    for<'de> T: Deserialize<'de>,
+{
+    db: &'a D,
+    params: FirestoreGroupByParams,
+    _ph: PhantomData<(K, T)>,
+}
+
+impl<'a, D, K, T> FirestoreGroupByQueryObjBuilder<'a, D, K, T>
+where
+    D: FirestoreQuerySupport,
+    K: Ord + Send,
+    for<'de> K: Deserialize<'de>,
+    T: Send,
+    for<'de> T: Deserialize<'de>,
+{
+    /// Creates a new `FirestoreGroupByQueryObjBuilder`.
+    #[inline]
+    pub(crate) fn new(db: &'a D, params: FirestoreGroupByParams) -> Self {
+        Self {
+            db,
+            params,
+            _ph: PhantomData,
+        }
+    }
+
+    /// Executes the query and returns a map of group keys to aggregation results.
+    pub async fn query(self) -> FirestoreResult<BTreeMap<K, T>> {
+        group_by_query_obj(self.db, self.params).await
+    }
+
+    /// Executes the query and returns a stream of `FirestoreResult<(K, T)>` groups.
+    ///
+    /// With [`FirestoreGroupByStrategy::SortedStream`] groups are yielded as soon as
+    /// they are complete, otherwise after the query has been fully consumed.
+    pub async fn stream_query_with_errors<'b>(
+        self,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<(K, T)>>>
+    where
+        K: 'b,
+        T: 'b,
+    {
+        stream_group_by_query_obj_with_errors(self.db, self.params).await
+    }
+}
+
+/// A builder for constructing a list of client-side group aggregations.
+pub struct FirestoreGroupAggregationBuilder {}
+
+impl FirestoreGroupAggregationBuilder {
+    /// Creates a new `FirestoreGroupAggregationBuilder`.
+    pub(crate) fn new() -> Self {
+        Self {}
+    }
+
+    /// Builds a `Vec` of [`FirestoreGroupAggregation`] from a collection of expressions.
+    /// `None` values are ignored.
+    #[inline]
+    pub fn fields<I>(&self, aggregation_field_expr: I) -> Vec<FirestoreGroupAggregation>
+    where
+        I: IntoIterator,
+        I::Item: FirestoreGroupAggregationExpr,
+    {
+        aggregation_field_expr
+            .into_iter()
+            .filter_map(|expr| expr.build_group_aggregation())
+            .collect()
+    }
+
+    /// Specifies the alias under which an aggregation result is returned.
+    #[inline]
+    pub fn field<S>(&self, alias: S) -> FirestoreGroupAggregationFieldExpr
+    where
+        S: AsRef<str>,
+    {
+        FirestoreGroupAggregationFieldExpr::new(alias.as_ref().to_string())
+    }
+}
+
+/// A trait for types that can be converted into a [`FirestoreGroupAggregation`].
+pub trait FirestoreGroupAggregationExpr {
+    /// Builds the [`FirestoreGroupAggregation`], or `None` for a no-op expression.
+    fn build_group_aggregation(self) -> Option<FirestoreGroupAggregation>;
+}
+
+/// Represents an alias targeted for a group aggregation.
+pub struct FirestoreGroupAggregationFieldExpr {
+    alias: String,
+}
+
+impl FirestoreGroupAggregationFieldExpr {
+    pub(crate) fn new(alias: String) -> Self {
+        Self { alias }
+    }
+
+    /// Counts the documents in the group.
+    #[inline]
+    pub fn count(self) -> Option<FirestoreGroupAggregation> {
+        Some(FirestoreGroupAggregation::new(
+            self.alias,
+            FirestoreGroupAggregationOperator::Count,
+        ))
+    }
+
+    /// Sums the numeric values of `field_name` in the group.
+    #[inline]
+    pub fn sum<S>(self, field_name: S) -> Option<FirestoreGroupAggregation>
+    where
+        S: AsRef<str>,
+    {
+        Some(FirestoreGroupAggregation::new(
+            self.alias,
+            FirestoreGroupAggregationOperator::Sum(field_name.as_ref().to_string()),
+        ))
+    }
+
+    /// Averages the numeric values of `field_name` in the group.
+    #[inline]
+    pub fn avg<S>(self, field_name: S) -> Option<FirestoreGroupAggregation>
+    where
+        S: AsRef<str>,
+    {
+        Some(FirestoreGroupAggregation::new(
+            self.alias,
+            FirestoreGroupAggregationOperator::Avg(field_name.as_ref().to_string()),
+        ))
+    }
+
+    /// Finds the minimum value of `field_name` in the group.
+    #[inline]
+    pub fn min<S>(self, field_name: S) -> Option<FirestoreGroupAggregation>
+    where
+        S: AsRef<str>,
+    {
+        Some(FirestoreGroupAggregation::new(
+            self.alias,
+            FirestoreGroupAggregationOperator::Min(field_name.as_ref().to_string()),
+        ))
+    }
+
+    /// Finds the maximum value of `field_name` in the group.
+    #[inline]
+    pub fn max<S>(self, field_name: S) -> Option<FirestoreGroupAggregation>
+    where
+        S: AsRef<str>,
+    {
+        Some(FirestoreGroupAggregation::new(
+            self.alias,
+            FirestoreGroupAggregationOperator::Max(field_name.as_ref().to_string()),
+        ))
+    }
+}
+
+impl FirestoreGroupAggregationExpr for FirestoreGroupAggregation {
+    #[inline]
+    fn build_group_aggregation(self) -> Option<FirestoreGroupAggregation> {
+        Some(self)
+    }
+}
+
+impl<F> FirestoreGroupAggregationExpr for Option<F>
+where
+    F: FirestoreGroupAggregationExpr,
+{
+    #[inline]
+    fn build_group_aggregation(self) -> Option<FirestoreGroupAggregation> {
+        self.and_then(|expr| expr.build_group_aggregation())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fluent_api::tests::*;
+    use crate::fluent_api::FirestoreExprBuilder;
+    use crate::{
+        path, paths, FirestoreGroupAggregation, FirestoreGroupAggregationOperator,
+        FirestoreGroupByStrategy,
+    };
+
+    #[test]
+    fn group_by_query_builder_params() {
+        let group_by = FirestoreExprBuilder::new(&mockdb::MockDatabase {})
+            .select()
+            .from("test")
+            .group_by(paths!(TestStructure::{some_id, one_more_string}))
+            .aggregate(|a| {
+                a.fields([
+                    a.field("cnt").count(),
+                    a.field("total").sum(path!(TestStructure::some_num)),
+                ])
+            })
+            .sorted_stream();
+
+        assert_eq!(
+            group_by.params.group_by_fields,
+            vec![
+                path!(TestStructure::some_id),
+                path!(TestStructure::one_more_string),
+            ]
+        );
+        assert_eq!(
+            group_by.params.aggregations,
+            vec![
+                FirestoreGroupAggregation::new(
+                    "cnt".to_string(),
+                    FirestoreGroupAggregationOperator::Count
+                ),
+                FirestoreGroupAggregation::new(
+                    "total".to_string(),
+                    FirestoreGroupAggregationOperator::Sum(path!(TestStructure::some_num))
+                ),
+            ]
+        );
+        assert_eq!(
+            group_by.params.strategy,
+            FirestoreGroupByStrategy::SortedStream
+        );
+    }
+}
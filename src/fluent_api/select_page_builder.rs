@@ -0,0 +1,385 @@
+//! Builder for paginated Firestore queries.
+//!
+//! Pages are fetched using cursors positioned after the last document of the previous page.
+//! The cursors are exposed as opaque [`FirestorePageToken`]s that can be passed to clients
+//! (e.g. in REST API responses) and sent back to fetch the following page.
+//!
+//! The pages can also be streamed in order, with the following pages fetched in the background
+//! while the current one is processed (see [`FirestorePageQueryDocBuilder::prefetch`]).
+
+use crate::db::{order_by_with_document_name, FirestoreTaskScopes};
+use crate::{
+    FirestoreDb, FirestorePage, FirestorePageToken, FirestoreQueryCursor, FirestoreQueryParams,
+    FirestoreQuerySupport, FirestoreResult,
+};
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use gcloud_sdk::google::firestore::v1::Document;
+use serde::Deserialize;
+use std::marker::PhantomData;
+
+/// A builder for fetching a page of documents of a query.
+///
+/// Created by calling [`FirestoreSelectDocBuilder::page()`](crate::select_builder::FirestoreSelectDocBuilder::page).
+#[derive(Clone, Debug)]
+pub struct FirestorePageQueryDocBuilder<'a, D>
+where
+    D: FirestoreQuerySupport,
+{
+    db: &'a D,
+    params: FirestoreQueryParams,
+    page_size: u32,
+    page_token: Option<FirestorePageToken>,
+    prefetch: usize,
+}
+
+impl<'a, D> FirestorePageQueryDocBuilder<'a, D>
+where
+    D: FirestoreQuerySupport,
+{
+    /// Creates a new `FirestorePageQueryDocBuilder`.
+    #[inline]
+    pub(crate) fn new(db: &'a D, params: FirestoreQueryParams, page_size: u32) -> Self {
+        Self {
+            db,
+            params,
+            page_size,
+            page_token: None,
+            prefetch: 1,
+        }
+    }
+
+    /// Continues from the page the token was returned with.
+    ///
+    /// # Arguments
+    /// * `page_token`: The `next_page_token` of the previous page.
+    ///
+    /// # Returns
+    /// The builder instance with the page token set.
+    #[inline]
+    pub fn page_token(self, page_token: FirestorePageToken) -> Self {
+        Self {
+            page_token: Some(page_token),
+            ..self
+        }
+    }
+
+    /// Same as [`Self::page_token`], but accepts an optional token (e.g. from a request
+    /// of the first page without a token).
+    #[inline]
+    pub fn opt_page_token(self, page_token: Option<FirestorePageToken>) -> Self {
+        Self { page_token, ..self }
+    }
+
+    /// Sets the number of pages fetched ahead in the background while streaming the pages,
+    /// so the latency of the requests is hidden behind processing the previous pages.
+    /// The pages are still streamed in order. Defaults to `1`.
+    ///
+    /// The background requests keep the settings of the fluent operation
+    /// (retry policy, consistency selector, timeout and serializer options).
+    #[inline]
+    pub fn prefetch(self, pages: usize) -> Self {
+        Self {
+            prefetch: pages.max(1),
+            ..self
+        }
+    }
+
+    /// Specifies that the page items should be deserialized into objects of type `T`.
+    #[inline]
+    pub fn obj<T>(self) -> FirestorePageQueryObjBuilder<'a, D, T>
+    where
+        T: Send,
+        for<'de> T: Deserialize<'de>,
+    {
+        FirestorePageQueryObjBuilder {
+            doc_builder: self,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Fetches the page of documents.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing the [`FirestorePage`] with documents and the next page token.
+    pub async fn query(self) -> FirestoreResult<FirestorePage<Document>> {
+        let order_by = order_by_with_document_name(self.params.order_by.clone());
+        let start_at = match self.page_token {
+            Some(page_token) => Some(page_token.to_cursor()?),
+            None => self.params.start_at.clone(),
+        };
+
+        let params = self
+            .params
+            .with_order_by(order_by.clone())
+            .opt_start_at(start_at)
+            .with_limit(self.page_size.saturating_add(1));
+
+        let docs = self.db.query_doc(params).await?;
+        Ok(page_from_docs(docs, self.page_size as usize, &order_by))
+    }
+
+    /// Streams the pages in order starting from the page token (if any),
+    /// fetching up to [`Self::prefetch`] following pages in the background.
+    ///
+    /// The stream ends after the last page or the first error.
+    /// The fetching stops when the stream is dropped.
+    pub async fn stream_pages_with_errors<'b>(
+        self,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestorePage<Document>>>>
+    where
+        D: Clone + Send + Sync + 'static,
+    {
+        let (tx, rx) = tokio::sync::mpsc::channel(self.prefetch);
+        let db = self.db.clone();
+        let params = self.params;
+        let page_size = self.page_size;
+        let mut page_token = self.page_token;
+        let scopes = FirestoreTaskScopes::capture();
+
+        tokio::spawn(scopes.scope(async move {
+            // A slot is reserved before each request, so the pages waiting in the channel
+            // and the page being fetched never exceed the prefetched pages.
+            while let Ok(permit) = tx.reserve().await {
+                let result = FirestorePageQueryDocBuilder::new(&db, params.clone(), page_size)
+                    .opt_page_token(page_token.take())
+                    .query()
+                    .await;
+                let last = match &result {
+                    Ok(page) => {
+                        page_token = page.next_page_token.clone();
+                        page_token.is_none()
+                    }
+                    Err(_) => true,
+                };
+                permit.send(result);
+                if last {
+                    break;
+                }
+            }
+        }));
+
+        Ok(tokio_stream::wrappers::ReceiverStream::new(rx).boxed())
+    }
+
+    /// Streams the documents of all the pages in order,
+    /// fetching up to [`Self::prefetch`] following pages in the background.
+    pub async fn stream_query_with_errors<'b>(
+        self,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<Document>>>
+    where
+        D: Clone + Send + Sync + 'static,
+    {
+        Ok(self
+            .stream_pages_with_errors()
+            .await?
+            .map_ok(|page| futures::stream::iter(page.items.into_iter().map(Ok)))
+            .try_flatten()
+            .boxed())
+    }
+}
+
+/// A builder for fetching a page of objects of a query.
+///
+/// Created by calling [`FirestorePageQueryDocBuilder::obj()`] or
+/// [`FirestoreSelectObjBuilder::page()`](crate::select_builder::FirestoreSelectObjBuilder::page).
+#[derive(Clone, Debug)]
+pub struct FirestorePageQueryObjBuilder<'a, D, T>
+where
+    D: FirestoreQuerySupport,
+    T: Send,
+    for<'de> T: Deserialize<'de>,
+{
+    doc_builder: FirestorePageQueryDocBuilder<'a, D>,
+    _pd: PhantomData<T>,
+}
+
+impl<'a, D, T> FirestorePageQueryObjBuilder<'a, D, T>
+where
+    D: FirestoreQuerySupport,
+    T: Send,
+    for<'de> T: Deserialize<'de>,
+{
+    /// Creates a new `FirestorePageQueryObjBuilder`.
+    #[inline]
+    pub(crate) fn new(db: &'a D, params: FirestoreQueryParams, page_size: u32) -> Self {
+        FirestorePageQueryDocBuilder::new(db, params, page_size).obj()
+    }
+
+    /// Continues from the page the token was returned with.
+    #[inline]
+    pub fn page_token(self, page_token: FirestorePageToken) -> Self {
+        Self {
+            doc_builder: self.doc_builder.page_token(page_token),
+            ..self
+        }
+    }
+
+    /// Same as [`Self::page_token`], but accepts an optional token.
+    #[inline]
+    pub fn opt_page_token(self, page_token: Option<FirestorePageToken>) -> Self {
+        Self {
+            doc_builder: self.doc_builder.opt_page_token(page_token),
+            ..self
+        }
+    }
+
+    /// Sets the number of pages fetched ahead in the background while streaming the pages.
+    #[inline]
+    pub fn prefetch(self, pages: usize) -> Self {
+        Self {
+            doc_builder: self.doc_builder.prefetch(pages),
+            ..self
+        }
+    }
+
+    /// Fetches the page and deserializes the documents into objects of type `T`.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing the [`FirestorePage`] with objects and the next page token.
+    pub async fn query(self) -> FirestoreResult<FirestorePage<T>> {
+        let page = self.doc_builder.query().await?;
+        Ok(FirestorePage {
+            items: page
+                .items
+                .iter()
+                .map(FirestoreDb::deserialize_doc_to)
+                .collect::<FirestoreResult<Vec<T>>>()?,
+            next_page_token: page.next_page_token,
+        })
+    }
+
+    /// Streams the objects of all the pages in order,
+    /// fetching up to [`Self::prefetch`] following pages in the background.
+    pub async fn stream_query_with_errors<'b>(
+        self,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<T>>>
+    where
+        D: Clone + Send + Sync + 'static,
+        T: 'b,
+    {
+        Ok(self
+            .doc_builder
+            .stream_query_with_errors()
+            .await?
+            .and_then(|doc| futures::future::ready(FirestoreDb::deserialize_doc_to(&doc)))
+            .boxed())
+    }
+}
+
+fn page_from_docs(
+    mut docs: Vec<Document>,
+    page_size: usize,
+    order_by: &[crate::FirestoreQueryOrder],
+) -> FirestorePage<Document> {
+    let next_page_token = if docs.len() > page_size {
+        docs.truncate(page_size);
+        docs.last().map(|last_doc| {
+            FirestorePageToken::from_cursor(FirestoreQueryCursor::after_document(
+                last_doc, order_by,
+            ))
+        })
+    } else {
+        None
+    };
+
+    FirestorePage {
+        items: docs,
+        next_page_token,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FirestoreQueryDirection, FirestoreQueryOrder, FirestoreValue};
+    use gcloud_sdk::google::firestore::v1::{value, Value};
+
+    fn test_doc(name: &str, num: i64) -> Document {
+        Document {
+            name: name.to_string(),
+            fields: [(
+                "num".to_string(),
+                Value {
+                    value_type: Some(value::ValueType::IntegerValue(num)),
+                },
+            )]
+            .into(),
+            create_time: None,
+            update_time: None,
+        }
+    }
+
+    #[test]
+    fn order_by_is_extended_with_document_name() {
+        assert_eq!(
+            order_by_with_document_name(Some(vec![FirestoreQueryOrder::new(
+                "num".to_string(),
+                FirestoreQueryDirection::Descending
+            )])),
+            vec![
+                FirestoreQueryOrder::new("num".to_string(), FirestoreQueryDirection::Descending),
+                FirestoreQueryOrder::new(
+                    "__name__".to_string(),
+                    FirestoreQueryDirection::Descending
+                ),
+            ]
+        );
+
+        assert_eq!(
+            order_by_with_document_name(None),
+            vec![FirestoreQueryOrder::new(
+                "__name__".to_string(),
+                FirestoreQueryDirection::Ascending
+            )]
+        );
+    }
+
+    #[test]
+    fn page_tokens_point_after_last_document() {
+        let order_by = order_by_with_document_name(Some(vec![FirestoreQueryOrder::new(
+            "num".to_string(),
+            FirestoreQueryDirection::Ascending,
+        )]));
+
+        let page = page_from_docs(
+            vec![
+                test_doc("col/a", 1),
+                test_doc("col/b", 2),
+                test_doc("col/c", 3),
+            ],
+            2,
+            &order_by,
+        );
+
+        assert_eq!(page.items.len(), 2);
+        let token = page.next_page_token.expect("next page token");
+
+        let serialized_token: FirestoreValue = token.clone().into();
+        assert_eq!(
+            FirestorePageToken::deserialize(serialized_token).unwrap(),
+            token
+        );
+
+        let num = FirestoreValue::from(Value {
+            value_type: Some(value::ValueType::IntegerValue(2)),
+        });
+        let name = FirestoreValue::from(Value {
+            value_type: Some(value::ValueType::ReferenceValue("col/b".to_string())),
+        });
+        assert_eq!(
+            token.to_cursor().unwrap(),
+            FirestoreQueryCursor::AfterValue(vec![num, name])
+        );
+
+        let last_page = page_from_docs(vec![test_doc("col/c", 3)], 2, &order_by);
+        assert_eq!(last_page.next_page_token, None);
+    }
+
+    #[test]
+    fn invalid_page_token_is_rejected() {
+        assert!(FirestorePageToken::new("not a token".to_string())
+            .to_cursor()
+            .is_err());
+    }
+}
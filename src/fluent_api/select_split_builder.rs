@@ -0,0 +1,125 @@
+//! Builder for queries with disjunctive filters exceeding the Firestore limits.
+//!
+//! Firestore limits the number of values of `IN` and `ARRAY_CONTAINS_ANY` (30)
+//! and `NOT_IN` (10) filters. This module splits such queries into several queries
+//! executed concurrently, and merges their results.
+//!
+//! The main entry point is [`FirestoreSplitQueryDocBuilder`], which is accessed via
+//! [`FirestoreSelectDocBuilder::split_disjunctions()`](crate::select_builder::FirestoreSelectDocBuilder::split_disjunctions).
+
+use crate::db::firestore_timeout_scope;
+use crate::{
+    query_doc_split, query_obj_split, FirestoreQueryParams, FirestoreQuerySplitOptions,
+    FirestoreQuerySupport, FirestoreResult, FirestoreRetryPolicy,
+};
+use gcloud_sdk::google::firestore::v1::Document;
+use serde::Deserialize;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// A builder for executing a query split by its oversized filters, returning raw documents.
+#[derive(Clone, Debug)]
+pub struct FirestoreSplitQueryDocBuilder<'a, D>
+where
+    D: FirestoreQuerySupport + Sync,
+{
+    db: &'a D,
+    params: FirestoreQueryParams,
+    options: FirestoreQuerySplitOptions,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
+}
+
+impl<'a, D> FirestoreSplitQueryDocBuilder<'a, D>
+where
+    D: FirestoreQuerySupport + Sync,
+{
+    /// Creates a new `FirestoreSplitQueryDocBuilder`.
+    #[inline]
+    pub(crate) fn new(
+        db: &'a D,
+        params: FirestoreQueryParams,
+        retry_policy: Option<FirestoreRetryPolicy>,
+        timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            db,
+            params,
+            options: FirestoreQuerySplitOptions::new(),
+            retry_policy,
+            timeout,
+        }
+    }
+
+    /// Overrides the limits and the concurrency of the split queries.
+    #[inline]
+    pub fn options(self, options: FirestoreQuerySplitOptions) -> Self {
+        Self { options, ..self }
+    }
+
+    /// Sets the maximum number of split queries running at the same time.
+    #[inline]
+    pub fn max_concurrency(self, max_concurrency: usize) -> Self {
+        Self {
+            options: self.options.with_max_concurrency(max_concurrency),
+            ..self
+        }
+    }
+
+    /// Executes the split queries and returns the merged documents.
+    pub async fn query(self) -> FirestoreResult<Vec<Document>> {
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(
+                self.retry_policy,
+                query_doc_split(self.db, self.params, &self.options),
+            ),
+        )
+        .await
+    }
+
+    /// Specifies that the merged documents are deserialized into `T`.
+    #[inline]
+    pub fn obj<T>(self) -> FirestoreSplitQueryObjBuilder<'a, D, T>
+    where
+        T: Send,
+        for<'de> T: Deserialize<'de>,
+    {
+        FirestoreSplitQueryObjBuilder {
+            inner: self,
+            _pd: PhantomData,
+        }
+    }
+}
+
+/// A builder for executing a query split by its oversized filters, deserializing documents into `T`.
+#[derive(Clone, Debug)]
+pub struct FirestoreSplitQueryObjBuilder<'a, D, T>
+where
+    D: FirestoreQuerySupport + Sync,
+    T: Send,
+    for<'de> T: Deserialize<'de>,
+{
+    inner: FirestoreSplitQueryDocBuilder<'a, D>,
+    _pd: PhantomData<T>,
+}
+
+impl<'a, D, T> FirestoreSplitQueryObjBuilder<'a, D, T>
+where
+    D: FirestoreQuerySupport + Sync,
+    T: Send,
+    for<'de> T: Deserialize<'de>,
+{
+    /// Executes the split queries and returns the merged objects.
+    pub async fn query(self) -> FirestoreResult<Vec<T>> {
+        let inner = self.inner;
+        firestore_timeout_scope(
+            inner.timeout,
+            FirestoreRetryPolicy::scope(
+                inner.retry_policy,
+                query_obj_split(inner.db, inner.params, &inner.options),
+            ),
+        )
+        .await
+    }
+}
@@ -37,6 +37,14 @@ impl FirestoreQuerySupport for MockDatabase {
         unreachable!()
     }
 
+    async fn stream_query_doc_with_meta<'b>(
+        &self,
+        _params: FirestoreQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocWithMetaItem<FirestoreDocument>>>>
+    {
+        unreachable!()
+    }
+
     async fn query_obj<T>(&self, _params: FirestoreQueryParams) -> FirestoreResult<Vec<T>>
     where
         for<'de> T: Deserialize<'de>,
@@ -77,6 +85,17 @@ impl FirestoreQuerySupport for MockDatabase {
         unreachable!()
     }
 
+    async fn stream_query_obj_with_meta<'b, T>(
+        &self,
+        _params: FirestoreQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocWithMetaItem<T>>>>
+    where
+        for<'de> T: Deserialize<'de>,
+        T: Send + 'b,
+    {
+        unreachable!()
+    }
+
     fn stream_partition_cursors_with_errors(
         &self,
         params: FirestorePartitionQueryParams,
@@ -215,6 +234,33 @@ impl FirestoreUpdateSupport for MockDatabase {
     ) -> FirestoreResult<Document> {
         unreachable!()
     }
+
+    async fn transform_doc<S>(
+        &self,
+        collection_id: &str,
+        document_id: S,
+        precondition: Option<FirestoreWritePrecondition>,
+        transforms: Vec<FirestoreFieldTransform>,
+    ) -> FirestoreResult<FirestoreWriteResult>
+    where
+        S: AsRef<str> + Send,
+    {
+        unreachable!()
+    }
+
+    async fn transform_doc_at<S>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_id: S,
+        precondition: Option<FirestoreWritePrecondition>,
+        transforms: Vec<FirestoreFieldTransform>,
+    ) -> FirestoreResult<FirestoreWriteResult>
+    where
+        S: AsRef<str> + Send,
+    {
+        unreachable!()
+    }
 }
 
 #[allow(unused)]
@@ -444,6 +490,35 @@ impl FirestoreGetByIdSupport for MockDatabase {
         unreachable!()
     }
 
+    async fn batch_get_objects_ordered<T, S, I>(
+        &self,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<Vec<(String, FirestoreBatchGetResult<T>)>>
+    where
+        for<'de> T: Deserialize<'de> + Send,
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send,
+    {
+        unreachable!()
+    }
+
+    async fn batch_get_objects_ordered_at<T, S, I>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<Vec<(String, FirestoreBatchGetResult<T>)>>
+    where
+        for<'de> T: Deserialize<'de> + Send,
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send,
+    {
+        unreachable!()
+    }
+
     async fn batch_stream_get_objects_with_errors<'a, T, S, I>(
         &'a self,
         collection_id: &str,
@@ -563,6 +638,13 @@ impl FirestoreAggregatedQuerySupport for MockDatabase {
         unreachable!()
     }
 
+    async fn stream_aggregated_query_doc_with_metadata<'b>(
+        &self,
+        params: FirestoreAggregatedQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<Document>>>> {
+        unreachable!()
+    }
+
     async fn aggregated_query_obj<T>(
         &self,
         params: FirestoreAggregatedQueryParams,
@@ -593,4 +675,15 @@ impl FirestoreAggregatedQuerySupport for MockDatabase {
     {
         unreachable!()
     }
+
+    async fn stream_aggregated_query_obj_with_metadata<'b, T>(
+        &self,
+        params: FirestoreAggregatedQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<T>>>>
+    where
+        for<'de> T: Deserialize<'de>,
+        T: Send + 'b,
+    {
+        unreachable!()
+    }
 }
@@ -0,0 +1,260 @@
+//! Collection handles bound to the type of their documents.
+//!
+//! A [`FirestoreTypedCollection`] keeps the collection path and the document type,
+//! so the common operations don't need to repeat them with every fluent expression.
+
+use crate::select_builder::{FirestoreSelectDocBuilder, FirestoreSelectObjBuilder};
+use crate::{
+    FirestoreAggregatedQuerySupport, FirestoreCollectionPath, FirestoreCreateSupport, FirestoreDb,
+    FirestoreDeleteSupport, FirestoreExprBuilder, FirestoreGetByIdSupport, FirestoreListenSupport,
+    FirestoreListener, FirestoreListenerParams, FirestoreListenerTarget,
+    FirestoreListenerTargetParams, FirestoreListingSupport, FirestoreQueryParams,
+    FirestoreQuerySupport, FirestoreResult, FirestoreResumeStateStorage, FirestoreTargetType,
+    FirestoreTypedChange, FirestoreUpdateSupport,
+};
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+
+/// A handle to a collection with documents of type `T`.
+///
+/// Created with [`FirestoreDb::typed_collection`] or [`FirestoreDb::typed_collection_path`]
+/// for subcollections. The handle is cheap to clone and can be kept for the lifetime
+/// of the application.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use firestore::*;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, Clone, Serialize, Deserialize)]
+/// struct User {
+///     name: String,
+///     age: u32,
+/// }
+///
+/// # async fn run(db: &FirestoreDb) -> FirestoreResult<()> {
+/// let users: FirestoreTypedCollection<User> = db.typed_collection("users");
+///
+/// users.insert("u1", &User { name: "Ann".into(), age: 30 }).await?;
+/// let user: Option<User> = users.get("u1").await?;
+///
+/// let adults: Vec<User> = users
+///     .query(|q| q.filter(|f| f.field(path!(User::age)).greater_than_or_equal(18)))
+///     .query()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct FirestoreTypedCollection<T, D = FirestoreDb> {
+    db: D,
+    path: FirestoreCollectionPath,
+    _pd: PhantomData<fn() -> T>,
+}
+
+impl<T, D> Clone for FirestoreTypedCollection<T, D>
+where
+    D: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            db: self.db.clone(),
+            path: self.path.clone(),
+            _pd: PhantomData,
+        }
+    }
+}
+
+impl<T, D> FirestoreTypedCollection<T, D>
+where
+    D: FirestoreQuerySupport
+        + FirestoreCreateSupport
+        + FirestoreDeleteSupport
+        + FirestoreUpdateSupport
+        + FirestoreListingSupport
+        + FirestoreGetByIdSupport
+        + FirestoreListenSupport
+        + FirestoreAggregatedQuerySupport
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    T: Serialize + Send + Sync,
+    for<'de> T: Deserialize<'de>,
+{
+    pub(crate) fn new(db: D, path: FirestoreCollectionPath) -> Self {
+        Self {
+            db,
+            path,
+            _pd: PhantomData,
+        }
+    }
+
+    /// The path of the collection.
+    #[inline]
+    pub fn path(&self) -> &FirestoreCollectionPath {
+        &self.path
+    }
+
+    /// The ID of the collection.
+    #[inline]
+    pub fn collection_id(&self) -> &str {
+        self.path.collection_id()
+    }
+
+    /// Provides access to the fluent API for the operations not covered by the handle.
+    #[inline]
+    pub fn fluent(&self) -> FirestoreExprBuilder<'_, D> {
+        FirestoreExprBuilder::new(&self.db)
+    }
+
+    /// Gets the document by its ID, `None` if it doesn't exist.
+    pub async fn get<S>(&self, document_id: S) -> FirestoreResult<Option<T>>
+    where
+        S: AsRef<str> + Send,
+    {
+        self.fluent()
+            .select()
+            .by_id_in_path(&self.path)
+            .obj()
+            .one(document_id)
+            .await
+    }
+
+    /// Creates the document, failing if it already exists.
+    pub async fn insert<S>(&self, document_id: S, obj: &T) -> FirestoreResult<T>
+    where
+        S: AsRef<str> + Send,
+    {
+        self.fluent()
+            .insert()
+            .into_path(&self.path)
+            .document_id(document_id)
+            .object(obj)
+            .execute()
+            .await
+    }
+
+    /// Replaces the document with the object, creating it if it doesn't exist.
+    pub async fn update<S>(&self, document_id: S, obj: &T) -> FirestoreResult<T>
+    where
+        S: AsRef<str> + Send,
+    {
+        self.fluent()
+            .update()
+            .in_col_path(&self.path)
+            .document_id(document_id)
+            .object(obj)
+            .execute()
+            .await
+    }
+
+    /// Updates only the specified fields of the document with the values of the object,
+    /// e.g. `paths!(User::{name, age})`.
+    pub async fn update_fields<S, I>(
+        &self,
+        document_id: S,
+        obj: &T,
+        fields: I,
+    ) -> FirestoreResult<T>
+    where
+        S: AsRef<str> + Send,
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.fluent()
+            .update()
+            .fields(fields)
+            .in_col_path(&self.path)
+            .document_id(document_id)
+            .object(obj)
+            .execute()
+            .await
+    }
+
+    /// Deletes the document. Deleting a document that doesn't exist succeeds.
+    pub async fn delete<S>(&self, document_id: S) -> FirestoreResult<()>
+    where
+        S: AsRef<str> + Send,
+    {
+        self.fluent()
+            .delete()
+            .from_path(&self.path)
+            .document_id(document_id)
+            .execute()
+            .await
+    }
+
+    /// Queries the documents of the collection, configuring the query with the function,
+    /// e.g. `|q| q.filter(...).order_by(...).limit(10)`.
+    pub fn query<'a, FN>(&'a self, configure: FN) -> FirestoreSelectObjBuilder<'a, D, T>
+    where
+        FN: FnOnce(FirestoreSelectDocBuilder<'a, D>) -> FirestoreSelectDocBuilder<'a, D>,
+    {
+        configure(self.fluent().select().from_path(&self.path)).obj()
+    }
+
+    /// Queries all the documents of the collection.
+    pub fn all(&self) -> FirestoreSelectObjBuilder<'_, D, T> {
+        self.query(|q| q)
+    }
+
+    /// Starts a listener of the changes of the documents of the collection,
+    /// returning the listener to shut it down and the stream of the changes.
+    ///
+    /// The target ID must be unique among the listeners of the application.
+    pub async fn listen<'b, S>(
+        &self,
+        storage: S,
+        target: FirestoreListenerTarget,
+    ) -> FirestoreResult<(
+        FirestoreListener<D, S>,
+        BoxStream<'b, FirestoreResult<FirestoreTypedChange<T>>>,
+    )>
+    where
+        S: FirestoreResumeStateStorage + Clone + Send + Sync + 'static,
+        T: 'static,
+    {
+        let mut listener =
+            FirestoreListener::new(self.db.clone(), storage, FirestoreListenerParams::new())
+                .await?;
+        listener.add_target(FirestoreListenerTargetParams::new(
+            target,
+            FirestoreTargetType::Query(
+                FirestoreQueryParams::new(self.path.collection_id().into())
+                    .with_parent(self.path.parent().to_string()),
+            ),
+            Default::default(),
+        ))?;
+        let changes = listener.start_typed_stream().await?;
+        Ok((listener, changes))
+    }
+}
+
+impl FirestoreDb {
+    /// Creates a handle to a top-level collection with documents of type `T`.
+    pub fn typed_collection<T>(&self, collection_id: &str) -> FirestoreTypedCollection<T>
+    where
+        T: Serialize + Send + Sync,
+        for<'de> T: Deserialize<'de>,
+    {
+        self.typed_collection_path(FirestoreCollectionPath::new(
+            self.get_documents_path().clone(),
+            collection_id.to_string(),
+        ))
+    }
+
+    /// Creates a handle to a (sub)collection with documents of type `T`,
+    /// e.g. with a path from [`FirestoreDb::parent_path`].
+    pub fn typed_collection_path<T>(
+        &self,
+        path: FirestoreCollectionPath,
+    ) -> FirestoreTypedCollection<T>
+    where
+        T: Serialize + Send + Sync,
+        for<'de> T: Deserialize<'de>,
+    {
+        FirestoreTypedCollection::new(self.clone(), path)
+    }
+}
@@ -4,19 +4,26 @@
 //! the data to update (either a full object, specific fields, or field transformations),
 //! and optional preconditions.
 
-use crate::document_transform_builder::FirestoreTransformBuilder;
+use crate::db::firestore_timeout_scope;
+use crate::document_transform_builder::{FirestoreTransformBuilder, FirestoreTransformExpr};
+use crate::firestore_entity::firestore_entity_value_transformers;
 use crate::{
-    FirestoreBatch, FirestoreBatchWriter, FirestoreFieldTransform, FirestoreResult,
-    FirestoreTransaction, FirestoreUpdateSupport, FirestoreWritePrecondition,
+    firestore_document_from_serializable, firestore_map_key_field_path,
+    firestore_merge_field_paths, FirestoreBatch, FirestoreBatchWriter, FirestoreCollectionPath,
+    FirestoreDb, FirestoreEntity, FirestoreFieldTransform, FirestoreResult, FirestoreRetryPolicy,
+    FirestoreSerializerOptions, FirestoreTransaction, FirestoreUpdateSupport,
+    FirestoreValueTransformers, FirestoreWritePrecondition, FirestoreWriteResult,
 };
+use chrono::{DateTime, Utc};
 use gcloud_sdk::google::firestore::v1::Document;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 /// The initial builder for a Firestore update operation.
 ///
 /// Created by calling [`FirestoreExprBuilder::update()`](crate::FirestoreExprBuilder::update).
 /// This builder allows specifying which fields to update. If no fields are specified,
-/// the entire object provided later will be merged with the existing document.
+/// the document is replaced with the object provided later, unless [`merge`](Self::merge) is used.
 #[derive(Clone, Debug)]
 pub struct FirestoreUpdateInitialBuilder<'a, D>
 where
@@ -24,6 +31,7 @@ where
 {
     db: &'a D,
     update_only_fields: Option<Vec<String>>,
+    merge: bool,
 }
 
 impl<'a, D> FirestoreUpdateInitialBuilder<'a, D>
@@ -36,6 +44,7 @@ where
         Self {
             db,
             update_only_fields: None,
+            merge: false,
         }
     }
 
@@ -43,9 +52,8 @@ where
     ///
     /// If this is set, only the fields listed here will be modified. Any other fields
     /// in the provided object/document will be ignored. If not set (the default),
-    /// the update acts as a merge: fields in the provided object will overwrite
-    /// existing fields, and new fields will be added. Fields not present in the
-    /// provided object will remain untouched in the document.
+    /// the document is replaced with the provided object/document,
+    /// or merged with it if [`merge`](Self::merge) is used.
     ///
     /// # Arguments
     /// * `update_only_fields`: An iterator of field paths (dot-separated for nested fields)
@@ -70,6 +78,54 @@ where
         }
     }
 
+    /// Adds fields targeting entries of map fields to the set of fields to update.
+    ///
+    /// Each item is a pair of a map field path and the path of keys inside the map,
+    /// one key per segment. Every key is escaped (including keys containing dots),
+    /// so only the specified map entries are updated instead of the whole map.
+    ///
+    /// # Arguments
+    /// * `fields`: An iterator of `(field_path, keys)` pairs,
+    ///   e.g. `(path!(MyStruct::settings), ["notifications", "email"])`.
+    ///
+    /// # Returns
+    /// The builder instance with the field mask extended.
+    #[inline]
+    pub fn fields_with_keys<I, F, K>(self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = (F, K)>,
+        F: AsRef<str>,
+        K: IntoIterator,
+        K::Item: AsRef<str>,
+    {
+        let mut update_only_fields = self.update_only_fields.unwrap_or_default();
+        update_only_fields.extend(
+            fields
+                .into_iter()
+                .map(|(field_path, keys)| firestore_map_key_field_path(field_path.as_ref(), keys)),
+        );
+        Self {
+            update_only_fields: Some(update_only_fields),
+            ..self
+        }
+    }
+
+    /// Merges the provided object/document into the existing document, similar to
+    /// `set(..., { merge: true })` in the other Firestore SDKs.
+    ///
+    /// The fields to update are the fields present in the serialized object, descending into
+    /// nested maps (see [`firestore_merge_field_paths`]), so the fields skipped in serialization
+    /// and the other entries of nested maps are kept. The document is created if it doesn't exist.
+    ///
+    /// The explicitly specified [`fields`](Self::fields) take precedence over the merge.
+    #[inline]
+    pub fn merge(self) -> Self {
+        Self {
+            merge: true,
+            ..self
+        }
+    }
+
     /// Specifies the collection ID where the document to update resides.
     ///
     /// # Arguments
@@ -83,8 +139,40 @@ where
             self.db,
             collection_id.to_string(),
             self.update_only_fields,
+            self.merge,
         )
     }
+
+    /// Specifies the (sub)collection where the document to update resides, including its parent document.
+    ///
+    /// # Arguments
+    /// * `path`: The [`FirestoreCollectionPath`] created with [`ParentPathBuilder::collection`](crate::ParentPathBuilder::collection).
+    ///
+    /// # Returns
+    /// A [`FirestoreUpdateDocObjBuilder`] to specify the document ID and data.
+    #[inline]
+    pub fn in_col_path(
+        self,
+        path: &FirestoreCollectionPath,
+    ) -> FirestoreUpdateDocObjBuilder<'a, D> {
+        FirestoreUpdateDocObjBuilder {
+            parent: Some(path.parent().to_string()),
+            ..self.in_col(path.collection_id())
+        }
+    }
+
+    /// Updates the entity in its collection by its document ID (see [`FirestoreEntity`]).
+    #[inline]
+    pub fn entity<T>(self, entity: &'a T) -> FirestoreUpdateObjExecuteBuilder<'a, D, T>
+    where
+        T: FirestoreEntity + Serialize + Sync + Send,
+        for<'de> T: Deserialize<'de>,
+    {
+        self.in_col(T::COLLECTION_ID)
+            .document_id(entity.document_id())
+            .object(entity)
+            .timestamp_fields(T::TIMESTAMP_FIELDS)
+    }
 }
 
 /// A builder for specifying the document ID and data for an update operation.
@@ -99,6 +187,7 @@ where
     db: &'a D,
     collection_id: String,
     update_only_fields: Option<Vec<String>>,
+    merge: bool,
     parent: Option<String>,
     return_only_fields: Option<Vec<String>>,
     precondition: Option<FirestoreWritePrecondition>,
@@ -115,11 +204,13 @@ where
         db: &'a D,
         collection_id: String,
         update_only_fields: Option<Vec<String>>,
+        merge: bool,
     ) -> Self {
         Self {
             db,
             collection_id,
             update_only_fields,
+            merge,
             parent: None,
             return_only_fields: None,
             precondition: None,
@@ -170,10 +261,32 @@ where
         }
     }
 
+    /// Only applies the update if the document exists. A shortcut for
+    /// `.precondition(FirestoreWritePrecondition::Exists(true))`.
+    ///
+    /// Fails with [`FirestoreError::ConditionFailedError`](crate::errors::FirestoreError::ConditionFailedError)
+    /// if the document doesn't exist.
+    #[inline]
+    pub fn only_if_exists(self) -> Self {
+        self.precondition(FirestoreWritePrecondition::Exists(true))
+    }
+
+    /// Only applies the update if the document was last updated at `update_time`, providing
+    /// compare-and-swap semantics for document versions without transactions.
+    /// A shortcut for `.precondition(FirestoreWritePrecondition::UpdateTime(update_time))`.
+    ///
+    /// Fails with [`FirestoreError::ConditionFailedError`](crate::errors::FirestoreError::ConditionFailedError)
+    /// if the document was modified since then or doesn't exist.
+    #[inline]
+    pub fn only_if_update_time(self, update_time: DateTime<Utc>) -> Self {
+        self.precondition(FirestoreWritePrecondition::UpdateTime(update_time))
+    }
+
     /// Specifies server-side field transformations to apply as part of the update.
     ///
     /// The `doc_transform` argument is a closure that receives a [`FirestoreTransformBuilder`]
-    /// and should return a `Vec<FirestoreFieldTransform>`.
+    /// and should return the field transformations, e.g. a `Vec` or an array of
+    /// expressions built with [`FirestoreTransformBuilder::field()`].
     ///
     /// # Arguments
     /// * `doc_transform`: A closure to build the list of field transformations.
@@ -181,12 +294,17 @@ where
     /// # Returns
     /// The builder instance with the field transformations set.
     #[inline]
-    pub fn transforms<FN>(self, doc_transform: FN) -> Self
+    pub fn transforms<FN, I>(self, doc_transform: FN) -> Self
     where
-        FN: Fn(FirestoreTransformBuilder) -> Vec<FirestoreFieldTransform>,
+        FN: Fn(FirestoreTransformBuilder) -> I,
+        I: IntoIterator,
+        I::Item: FirestoreTransformExpr,
     {
         Self {
-            transforms: doc_transform(FirestoreTransformBuilder::new()),
+            transforms: doc_transform(FirestoreTransformBuilder::new())
+                .into_iter()
+                .filter_map(|expr| expr.build_transform())
+                .collect(),
             ..self
         }
     }
@@ -206,6 +324,7 @@ where
             self.db,
             self.collection_id.to_string(),
             self.update_only_fields,
+            self.merge,
             document,
             self.return_only_fields,
             self.precondition,
@@ -230,6 +349,7 @@ where
             self.db,
             self.collection_id,
             self.update_only_fields,
+            self.merge,
             self.parent,
             document_id.as_ref().to_string(),
             self.return_only_fields,
@@ -248,9 +368,12 @@ where
     db: &'a D,
     collection_id: String,
     update_only_fields: Option<Vec<String>>,
+    merge: bool,
     document: Document,
     return_only_fields: Option<Vec<String>>,
     precondition: Option<FirestoreWritePrecondition>,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
 }
 
 impl<'a, D> FirestoreUpdateDocExecuteBuilder<'a, D>
@@ -263,6 +386,7 @@ where
         db: &'a D,
         collection_id: String,
         update_only_fields: Option<Vec<String>>,
+        merge: bool,
         document: Document,
         return_only_fields: Option<Vec<String>>,
         precondition: Option<FirestoreWritePrecondition>,
@@ -271,9 +395,32 @@ where
             db,
             collection_id,
             update_only_fields,
+            merge,
             document,
             return_only_fields,
             precondition,
+            retry_policy: None,
+            timeout: None,
+        }
+    }
+
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of this operation, overriding [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    ///
+    /// The timeout is sent to Firestore as the gRPC deadline of the requests.
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
         }
     }
 
@@ -282,18 +429,29 @@ where
     /// # Returns
     /// A `FirestoreResult` containing the updated [`Document`].
     pub async fn execute(self) -> FirestoreResult<Document> {
-        // Note: The `update_doc` method on `FirestoreUpdateSupport` expects the full document path
-        // to be in `self.document.name`. The `collection_id` here is somewhat redundant if
-        // `document.name` is correctly populated, but kept for consistency with other builders.
-        self.db
-            .update_doc(
-                self.collection_id.as_str(),
-                self.document,
-                self.update_only_fields,
-                self.return_only_fields,
-                self.precondition,
-            )
-            .await
+        let retry_policy = self.retry_policy.clone();
+        let update_only_fields = match self.update_only_fields {
+            None if self.merge => Some(firestore_merge_field_paths(&self.document.fields)),
+            update_only_fields => update_only_fields,
+        };
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(retry_policy, async move {
+                // Note: The `update_doc` method on `FirestoreUpdateSupport` expects the full document path
+                // to be in `self.document.name`. The `collection_id` here is somewhat redundant if
+                // `document.name` is correctly populated, but kept for consistency with other builders.
+                self.db
+                    .update_doc(
+                        self.collection_id.as_str(),
+                        self.document,
+                        update_only_fields,
+                        self.return_only_fields,
+                        self.precondition,
+                    )
+                    .await
+            }),
+        )
+        .await
     }
 }
 
@@ -307,6 +465,7 @@ where
     db: &'a D,
     collection_id: String,
     update_only_fields: Option<Vec<String>>,
+    merge: bool,
     parent: Option<String>,
     document_id: String,
     return_only_fields: Option<Vec<String>>,
@@ -324,6 +483,7 @@ where
         db: &'a D,
         collection_id: String,
         update_only_fields: Option<Vec<String>>,
+        merge: bool,
         parent: Option<String>,
         document_id: String,
         return_only_fields: Option<Vec<String>>,
@@ -334,6 +494,7 @@ where
             db,
             collection_id,
             update_only_fields,
+            merge,
             parent,
             document_id,
             return_only_fields,
@@ -376,6 +537,7 @@ where
             self.db,
             self.collection_id.to_string(),
             self.update_only_fields,
+            self.merge,
             self.parent,
             self.document_id,
             object,
@@ -395,12 +557,17 @@ where
     /// # Returns
     /// The builder instance with the field transformations set.
     #[inline]
-    pub fn transforms<FN>(self, doc_transform: FN) -> Self
+    pub fn transforms<FN, I>(self, doc_transform: FN) -> Self
     where
-        FN: Fn(FirestoreTransformBuilder) -> Vec<FirestoreFieldTransform>,
+        FN: Fn(FirestoreTransformBuilder) -> I,
+        I: IntoIterator,
+        I::Item: FirestoreTransformExpr,
     {
         Self {
-            transforms: doc_transform(FirestoreTransformBuilder::new()),
+            transforms: doc_transform(FirestoreTransformBuilder::new())
+                .into_iter()
+                .filter_map(|expr| expr.build_transform())
+                .collect(),
             ..self
         }
     }
@@ -411,7 +578,8 @@ where
     /// on the transformations defined via `.transforms()`.
     ///
     /// # Returns
-    /// A [`FirestoreUpdateOnlyTransformBuilder`] to add the transform-only operation to a batch or transaction.
+    /// A [`FirestoreUpdateOnlyTransformBuilder`] to execute the transform-only operation
+    /// or to add it to a batch or transaction.
     #[inline]
     pub fn only_transform(self) -> FirestoreUpdateOnlyTransformBuilder<'a, D> {
         FirestoreUpdateOnlyTransformBuilder::new(
@@ -435,12 +603,17 @@ where
     db: &'a D,
     collection_id: String,
     update_only_fields: Option<Vec<String>>,
+    merge: bool,
     parent: Option<String>,
     document_id: String,
     object: &'a T,
     return_only_fields: Option<Vec<String>>,
     precondition: Option<FirestoreWritePrecondition>,
     transforms: Vec<FirestoreFieldTransform>,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
+    serializer_options: Option<FirestoreSerializerOptions>,
+    timestamp_fields: &'static [&'static str],
 }
 
 impl<'a, D, T> FirestoreUpdateObjExecuteBuilder<'a, D, T>
@@ -454,6 +627,7 @@ where
         db: &'a D,
         collection_id: String,
         update_only_fields: Option<Vec<String>>,
+        merge: bool,
         parent: Option<String>,
         document_id: String,
         object: &'a T,
@@ -465,12 +639,66 @@ where
             db,
             collection_id,
             update_only_fields,
+            merge,
             parent,
             document_id,
             object,
             return_only_fields,
             precondition,
             transforms,
+            retry_policy: None,
+            timeout: None,
+            serializer_options: None,
+            timestamp_fields: &[],
+        }
+    }
+
+    /// Writes the fields of the entity as timestamps (see [`FirestoreEntity::TIMESTAMP_FIELDS`]).
+    #[inline]
+    pub(crate) fn timestamp_fields(self, timestamp_fields: &'static [&'static str]) -> Self {
+        Self {
+            timestamp_fields,
+            ..self
+        }
+    }
+
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of this operation, overriding [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    ///
+    /// The timeout is sent to Firestore as the gRPC deadline of the requests.
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Sets the options of serializing the object, e.g. to write `None` values as `null`s.
+    #[inline]
+    pub fn serializer_options(self, options: FirestoreSerializerOptions) -> Self {
+        Self {
+            serializer_options: Some(options),
+            ..self
+        }
+    }
+
+    /// The fields to update, collected from the serialized object in the merge mode.
+    fn effective_update_only_fields(&self) -> FirestoreResult<Option<Vec<String>>> {
+        match self.update_only_fields {
+            None if self.merge => {
+                let document = firestore_document_from_serializable("", self.object)?;
+                Ok(Some(firestore_merge_field_paths(&document.fields)))
+            }
+            ref update_only_fields => Ok(update_only_fields.clone()),
         }
     }
 
@@ -486,35 +714,52 @@ where
     where
         for<'de> O: Deserialize<'de>,
     {
-        if let Some(parent) = self.parent {
-            self.db
-                .update_obj_at(
-                    parent.as_str(),
-                    self.collection_id.as_str(),
-                    self.document_id,
-                    self.object,
-                    self.update_only_fields,
-                    self.return_only_fields,
-                    self.precondition,
-                    // Note: The current FirestoreUpdateSupport::update_obj_at doesn't take transforms.
-                    // This might be an oversight or transforms are handled differently for object updates.
-                    // If transforms are intended here, the trait method needs adjustment.
-                    // For now, passing an empty vec or ignoring self.transforms if not supported by the trait.
-                )
-                .await
-        } else {
-            self.db
-                .update_obj(
-                    self.collection_id.as_str(),
-                    self.document_id,
-                    self.object,
-                    self.update_only_fields,
-                    self.return_only_fields,
-                    self.precondition,
-                    // Similar note as above for transforms.
-                )
-                .await
-        }
+        let retry_policy = self.retry_policy.clone();
+        let value_transformers =
+            firestore_entity_value_transformers(&self.collection_id, self.timestamp_fields);
+        let serializer_options = self.serializer_options;
+        FirestoreValueTransformers::opt_scope(
+            value_transformers,
+            FirestoreSerializerOptions::opt_scope(
+                serializer_options,
+                firestore_timeout_scope(
+                    self.timeout,
+                    FirestoreRetryPolicy::scope(retry_policy, async move {
+                        let update_only_fields = self.effective_update_only_fields()?;
+                        if let Some(parent) = self.parent {
+                            self.db
+                                .update_obj_at(
+                                    parent.as_str(),
+                                    self.collection_id.as_str(),
+                                    self.document_id,
+                                    self.object,
+                                    update_only_fields,
+                                    self.return_only_fields,
+                                    self.precondition,
+                                    // Note: The current FirestoreUpdateSupport::update_obj_at doesn't take transforms.
+                                    // This might be an oversight or transforms are handled differently for object updates.
+                                    // If transforms are intended here, the trait method needs adjustment.
+                                    // For now, passing an empty vec or ignoring self.transforms if not supported by the trait.
+                                )
+                                .await
+                        } else {
+                            self.db
+                                .update_obj(
+                                    self.collection_id.as_str(),
+                                    self.document_id,
+                                    self.object,
+                                    update_only_fields,
+                                    self.return_only_fields,
+                                    self.precondition,
+                                    // Similar note as above for transforms.
+                                )
+                                .await
+                        }
+                    }),
+                ),
+            ),
+        )
+        .await
     }
 
     /// Adds server-side field transformations to the update operation.
@@ -528,12 +773,17 @@ where
     /// # Returns
     /// The builder instance with added transformations.
     #[inline]
-    pub fn transforms<FN>(self, transforms_builder: FN) -> Self
+    pub fn transforms<FN, I>(self, transforms_builder: FN) -> Self
     where
-        FN: Fn(FirestoreTransformBuilder) -> Vec<FirestoreFieldTransform>,
+        FN: Fn(FirestoreTransformBuilder) -> I,
+        I: IntoIterator,
+        I::Item: FirestoreTransformExpr,
     {
         Self {
-            transforms: transforms_builder(FirestoreTransformBuilder::new()),
+            transforms: transforms_builder(FirestoreTransformBuilder::new())
+                .into_iter()
+                .filter_map(|expr| expr.build_transform())
+                .collect(),
             ..self
         }
     }
@@ -550,26 +800,33 @@ where
         self,
         transaction: &'a mut FirestoreTransaction<'t>,
     ) -> FirestoreResult<&'a mut FirestoreTransaction<'t>> {
-        if let Some(parent) = self.parent {
-            transaction.update_object_at(
-                parent.as_str(),
-                self.collection_id.as_str(),
-                self.document_id,
-                self.object,
-                self.update_only_fields,
-                self.precondition,
-                self.transforms,
-            )
-        } else {
-            transaction.update_object(
-                self.collection_id.as_str(),
-                self.document_id,
-                self.object,
-                self.update_only_fields,
-                self.precondition,
-                self.transforms,
-            )
-        }
+        let value_transformers =
+            firestore_entity_value_transformers(&self.collection_id, self.timestamp_fields);
+        FirestoreValueTransformers::opt_sync_scope(value_transformers, move || {
+            FirestoreSerializerOptions::opt_sync_scope(self.serializer_options, move || {
+                let update_only_fields = self.effective_update_only_fields()?;
+                if let Some(parent) = self.parent {
+                    transaction.update_object_at(
+                        parent.as_str(),
+                        self.collection_id.as_str(),
+                        self.document_id,
+                        self.object,
+                        update_only_fields,
+                        self.precondition,
+                        self.transforms,
+                    )
+                } else {
+                    transaction.update_object(
+                        self.collection_id.as_str(),
+                        self.document_id,
+                        self.object,
+                        update_only_fields,
+                        self.precondition,
+                        self.transforms,
+                    )
+                }
+            })
+        })
     }
 
     /// Adds this update operation (object merge and/or transforms) to a [`FirestoreBatch`].
@@ -590,26 +847,89 @@ where
     where
         W: FirestoreBatchWriter,
     {
-        if let Some(parent) = self.parent {
-            batch.update_object_at(
-                parent.as_str(),
-                self.collection_id.as_str(),
-                self.document_id,
-                self.object,
-                self.update_only_fields,
-                self.precondition,
-                self.transforms,
-            )
-        } else {
-            batch.update_object(
-                self.collection_id.as_str(),
-                self.document_id,
-                self.object,
-                self.update_only_fields,
-                self.precondition,
-                self.transforms,
-            )
-        }
+        let value_transformers =
+            firestore_entity_value_transformers(&self.collection_id, self.timestamp_fields);
+        FirestoreValueTransformers::opt_sync_scope(value_transformers, move || {
+            FirestoreSerializerOptions::opt_sync_scope(self.serializer_options, move || {
+                let update_only_fields = self.effective_update_only_fields()?;
+                if let Some(parent) = self.parent {
+                    batch.update_object_at(
+                        parent.as_str(),
+                        self.collection_id.as_str(),
+                        self.document_id,
+                        self.object,
+                        update_only_fields,
+                        self.precondition,
+                        self.transforms,
+                    )
+                } else {
+                    batch.update_object(
+                        self.collection_id.as_str(),
+                        self.document_id,
+                        self.object,
+                        update_only_fields,
+                        self.precondition,
+                        self.transforms,
+                    )
+                }
+            })
+        })
+    }
+}
+
+impl<'a, T> FirestoreUpdateObjExecuteBuilder<'a, FirestoreDb, T>
+where
+    T: Serialize + Sync + Send,
+{
+    /// Updates the document in a transaction with a read of its current state, returning the document
+    /// as it was before the update deserialized into type `O`, or `None` if it didn't exist.
+    ///
+    /// Useful to emit audit or outbox events with the previous payload without racing a separate read.
+    /// The transaction is retried if the document is modified concurrently.
+    pub async fn execute_returning_previous<O>(self) -> FirestoreResult<Option<O>>
+    where
+        for<'de> O: Deserialize<'de> + Send,
+    {
+        let retry_policy = self.retry_policy.clone();
+        let value_transformers =
+            firestore_entity_value_transformers(&self.collection_id, self.timestamp_fields);
+        let serializer_options = self.serializer_options;
+        FirestoreValueTransformers::opt_scope(
+            value_transformers,
+            FirestoreSerializerOptions::opt_scope(
+                serializer_options,
+                firestore_timeout_scope(
+                    self.timeout,
+                    FirestoreRetryPolicy::scope(retry_policy, async move {
+                        let update_only_fields = self.effective_update_only_fields()?;
+                        let parent = self
+                            .parent
+                            .unwrap_or_else(|| self.db.get_documents_path().clone());
+                        self.db
+                            .write_returning_previous(
+                                parent.as_str(),
+                                self.collection_id.as_str(),
+                                self.document_id.as_str(),
+                                |transaction| {
+                                    transaction
+                                        .update_object_at(
+                                            parent.as_str(),
+                                            self.collection_id.as_str(),
+                                            self.document_id.as_str(),
+                                            self.object,
+                                            update_only_fields.clone(),
+                                            self.precondition.clone(),
+                                            self.transforms.clone(),
+                                        )
+                                        .map(|_| ())
+                                },
+                            )
+                            .await
+                    }),
+                ),
+            ),
+        )
+        .await
     }
 }
 
@@ -622,12 +942,14 @@ pub struct FirestoreUpdateOnlyTransformBuilder<'a, D>
 where
     D: FirestoreUpdateSupport,
 {
-    _db: &'a D,
+    db: &'a D,
     collection_id: String,
     parent: Option<String>,
     document_id: String,
     precondition: Option<FirestoreWritePrecondition>,
     transforms: Vec<FirestoreFieldTransform>,
+    retry_policy: Option<FirestoreRetryPolicy>,
+    timeout: Option<Duration>,
 }
 
 impl<'a, D> FirestoreUpdateOnlyTransformBuilder<'a, D>
@@ -645,15 +967,72 @@ where
         transforms: Vec<FirestoreFieldTransform>,
     ) -> Self {
         Self {
-            _db: db,
+            db,
             collection_id,
             parent,
             document_id,
             precondition,
             transforms,
+            retry_policy: None,
+            timeout: None,
+        }
+    }
+
+    /// Overrides the [`FirestoreRetryPolicy`] for this operation.
+    #[inline]
+    pub fn retry(self, retry_policy: FirestoreRetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sets the timeout of this operation, overriding [`FirestoreDbOptions::timeout`](crate::FirestoreDbOptions::timeout).
+    ///
+    /// The timeout is sent to Firestore as the gRPC deadline of the requests.
+    #[inline]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self
         }
     }
 
+    /// Executes the transform-only update on its own, without sending any document data.
+    ///
+    /// # Returns
+    /// A `FirestoreResult` containing the [`FirestoreWriteResult`] with the values of
+    /// the transformed fields after the transformations were applied.
+    pub async fn execute(self) -> FirestoreResult<FirestoreWriteResult> {
+        let retry_policy = self.retry_policy.clone();
+        firestore_timeout_scope(
+            self.timeout,
+            FirestoreRetryPolicy::scope(retry_policy, async move {
+                if let Some(parent) = self.parent {
+                    self.db
+                        .transform_doc_at(
+                            parent.as_str(),
+                            self.collection_id.as_str(),
+                            self.document_id,
+                            self.precondition,
+                            self.transforms,
+                        )
+                        .await
+                } else {
+                    self.db
+                        .transform_doc(
+                            self.collection_id.as_str(),
+                            self.document_id,
+                            self.precondition,
+                            self.transforms,
+                        )
+                        .await
+                }
+            }),
+        )
+        .await
+    }
+
     /// Adds this transform-only update operation to a [`FirestoreTransaction`].
     ///
     /// # Arguments
@@ -720,3 +1099,93 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::fluent_api::tests::*;
+    use crate::fluent_api::FirestoreExprBuilder;
+    use crate::path;
+
+    struct TestSettings {
+        theme: String,
+        settings: std::collections::HashMap<String, bool>,
+    }
+
+    #[test]
+    fn update_fields_with_map_keys() {
+        let update = FirestoreExprBuilder::new(&mockdb::MockDatabase {})
+            .update()
+            .fields([path!(TestSettings::theme)])
+            .fields_with_keys([
+                (
+                    path!(TestSettings::settings),
+                    vec!["notifications", "email"],
+                ),
+                (path!(TestSettings::settings), vec!["beta-features"]),
+                (
+                    path!(TestSettings::settings),
+                    vec!["example.com", "enabled"],
+                ),
+            ]);
+
+        assert_eq!(
+            update.update_only_fields,
+            Some(vec![
+                "theme".to_string(),
+                "settings.notifications.email".to_string(),
+                "settings.`beta-features`".to_string(),
+                "settings.`example.com`.enabled".to_string(),
+            ])
+        );
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    struct TestProfile {
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        nickname: Option<String>,
+        settings: std::collections::BTreeMap<String, String>,
+    }
+
+    #[test]
+    fn merge_collects_fields_from_object() {
+        let profile = TestProfile {
+            name: "Alice".to_string(),
+            nickname: None,
+            settings: [("theme".to_string(), "dark".to_string())]
+                .into_iter()
+                .collect(),
+        };
+        let db = mockdb::MockDatabase {};
+
+        let merge = FirestoreExprBuilder::new(&db)
+            .update()
+            .merge()
+            .in_col("profiles")
+            .document_id("alice")
+            .object(&profile);
+        assert_eq!(
+            merge.effective_update_only_fields().unwrap(),
+            Some(vec!["name".to_string(), "settings.theme".to_string()])
+        );
+
+        let explicit = FirestoreExprBuilder::new(&db)
+            .update()
+            .merge()
+            .fields([path!(TestProfile::nickname)])
+            .in_col("profiles")
+            .document_id("alice")
+            .object(&profile);
+        assert_eq!(
+            explicit.effective_update_only_fields().unwrap(),
+            Some(vec!["nickname".to_string()])
+        );
+
+        let replace = FirestoreExprBuilder::new(&db)
+            .update()
+            .in_col("profiles")
+            .document_id("alice")
+            .object(&profile);
+        assert_eq!(replace.effective_update_only_fields().unwrap(), None);
+    }
+}
@@ -0,0 +1,362 @@
+//! Importing documents from newline-delimited JSON (NDJSON), e.g. restoring the exports
+//! of [`FirestoreDb::export_ndjson`](crate::FirestoreDb::export_ndjson).
+
+use crate::db::safe_document_path;
+use crate::errors::*;
+use crate::firestore_serde::proto_json::document_from_json;
+use crate::{
+    firestore_document_from_serializable, firestore_field_path_escape, FirestoreBulkWriteResult,
+    FirestoreBulkWriterOptions, FirestoreDb, FirestoreResult, FirestoreWritePrecondition,
+};
+use futures::StreamExt;
+use gcloud_sdk::google::firestore::v1::{write, Document, DocumentMask, Write};
+use rsb_derive::Builder;
+use serde_json::Value as JsonValue;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::Semaphore;
+use tracing::*;
+
+/// The format of the imported records.
+#[derive(Debug, PartialEq, Clone)]
+pub enum FirestoreImportRecords {
+    /// Documents in the format of [`FirestoreDb::export_ndjson`], written to the same paths
+    /// relative to the documents of the database, so they can be restored into another database.
+    Documents,
+    /// Plain JSON objects written into the collection, with the document IDs taken from a field.
+    Objects {
+        /// The parent of the collection, the documents of the database if not specified.
+        parent: Option<String>,
+        /// The ID of the collection.
+        collection_id: String,
+        /// The field of the objects with the document IDs (strings or numbers).
+        id_field: String,
+    },
+}
+
+/// What to do with the documents that already exist.
+#[derive(Debug, PartialEq, Clone)]
+pub enum FirestoreImportConflictPolicy {
+    /// Replaces the existing documents.
+    Overwrite,
+    /// Keeps the existing documents, creating only the missing ones.
+    SkipExisting,
+    /// Updates only the fields in the mask (field paths), keeping the other fields of the existing
+    /// documents. Without a mask, the top-level fields of every record are updated.
+    Merge(Option<Vec<String>>),
+}
+
+/// Parameters of an import of NDJSON records.
+#[derive(Debug, PartialEq, Clone, Builder)]
+pub struct FirestoreImportParams {
+    /// The format of the records.
+    #[default = "FirestoreImportRecords::Documents"]
+    pub records: FirestoreImportRecords,
+    /// What to do with the documents that already exist.
+    #[default = "FirestoreImportConflictPolicy::Overwrite"]
+    pub conflict_policy: FirestoreImportConflictPolicy,
+    /// The options of the bulk writer writing the documents,
+    /// e.g. [`FirestoreBulkWriterOptions::ramp_up`] to throttle the writes.
+    #[default = "FirestoreBulkWriterOptions::new()"]
+    pub writer_options: FirestoreBulkWriterOptions,
+}
+
+/// The statistics of a finished import.
+#[derive(Debug, Default)]
+pub struct FirestoreImportStats {
+    /// The number of records read.
+    pub records: usize,
+    /// The number of written documents.
+    pub written: usize,
+    /// The number of existing documents skipped with [`FirestoreImportConflictPolicy::SkipExisting`].
+    pub skipped: usize,
+    /// The writes that failed, including the retries.
+    pub failed: Vec<FirestoreBulkWriteResult>,
+}
+
+impl FirestoreDb {
+    /// Imports the records read from the newline-delimited JSON, writing them with a
+    /// [`FirestoreBulkWriter`](crate::FirestoreBulkWriter) configured with
+    /// [`FirestoreImportParams::writer_options`].
+    ///
+    /// The reading is paused while the writer has enough writes in flight.
+    /// An invalid record stops the import with an error after the previous records are written.
+    pub async fn import_ndjson<R>(
+        &self,
+        params: FirestoreImportParams,
+        reader: R,
+    ) -> FirestoreResult<FirestoreImportStats>
+    where
+        R: AsyncRead + Unpin + Send,
+    {
+        let (writer, results) = self
+            .create_bulk_writer_with_options(params.writer_options.clone())
+            .await?;
+
+        let max_pending_writes =
+            params.writer_options.max_batch_size * params.writer_options.max_concurrent_batches * 2;
+        let pending_writes = Arc::new(Semaphore::new(max_pending_writes));
+
+        let skip_existing = matches!(
+            params.conflict_policy,
+            FirestoreImportConflictPolicy::SkipExisting
+        );
+        let read_records = {
+            let pending_writes = pending_writes.clone();
+            async move {
+                let mut lines = BufReader::new(reader).lines();
+                let mut records = 0;
+                let mut line_number = 0;
+                let result = loop {
+                    line_number += 1;
+                    let line = match lines.next_line().await {
+                        Ok(Some(line)) => line,
+                        Ok(None) => break Ok(records),
+                        Err(err) => break Err(FirestoreError::from(err)),
+                    };
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let write = match import_record_write(self.get_documents_path(), &params, &line)
+                    {
+                        Ok(write) => write,
+                        Err(err) => break Err(import_record_error(line_number, err)),
+                    };
+                    if let Ok(permit) = pending_writes.acquire().await {
+                        permit.forget();
+                    }
+                    if let Err(err) = writer.add_writes([write]) {
+                        break Err(err);
+                    }
+                    records += 1;
+                };
+                writer.finish().await;
+                result
+            }
+        };
+
+        let collect_results = results.fold(
+            FirestoreImportStats::default(),
+            |mut stats, write_result| {
+                pending_writes.add_permits(1);
+                match &write_result.result {
+                    Ok(_) => stats.written += 1,
+                    Err(FirestoreError::DataConflictError(_)) if skip_existing => {
+                        stats.skipped += 1
+                    }
+                    Err(_) => stats.failed.push(write_result),
+                }
+                futures::future::ready(stats)
+            },
+        );
+
+        let (records, mut stats) = futures::join!(read_records, collect_results);
+        stats.records = records?;
+
+        debug!(
+            records = stats.records,
+            written = stats.written,
+            skipped = stats.skipped,
+            failed = stats.failed.len(),
+            "Imported records."
+        );
+
+        Ok(stats)
+    }
+}
+
+pub(crate) fn import_record_error(line_number: usize, err: FirestoreError) -> FirestoreError {
+    FirestoreError::DeserializeError(FirestoreSerializationError::from_message(format!(
+        "Unable to import the record at line {line_number}: {err}"
+    )))
+}
+
+/// A document to import with the update mask and the precondition of the conflict policy.
+pub(crate) struct FirestoreImportRecord {
+    pub document: Document,
+    pub update_mask: Option<Vec<String>>,
+    pub precondition: Option<FirestoreWritePrecondition>,
+}
+
+fn import_record_write(
+    documents_path: &str,
+    params: &FirestoreImportParams,
+    line: &str,
+) -> FirestoreResult<Write> {
+    let record = import_record(documents_path, params, line)?;
+    Ok(Write {
+        update_mask: record
+            .update_mask
+            .map(|field_paths| DocumentMask { field_paths }),
+        update_transforms: vec![],
+        current_document: record
+            .precondition
+            .map(|cond| cond.try_into())
+            .transpose()?,
+        operation: Some(write::Operation::Update(record.document)),
+    })
+}
+
+pub(crate) fn import_record(
+    documents_path: &str,
+    params: &FirestoreImportParams,
+    line: &str,
+) -> FirestoreResult<FirestoreImportRecord> {
+    let json: JsonValue = serde_json::from_str(line).map_err(|err| {
+        FirestoreError::DeserializeError(FirestoreSerializationError::from_message(format!(
+            "Invalid JSON: {err}"
+        )))
+    })?;
+
+    let document = match &params.records {
+        FirestoreImportRecords::Documents => {
+            let exported = document_from_json(&json)?;
+            let relative_path = exported
+                .name
+                .split_once("/documents/")
+                .map(|(_, relative_path)| relative_path)
+                .filter(|relative_path| !relative_path.is_empty())
+                .ok_or_else(|| {
+                    FirestoreError::DeserializeError(FirestoreSerializationError::from_message(
+                        format!("Invalid document name: {}", exported.name),
+                    ))
+                })?;
+            Document {
+                name: format!("{documents_path}/{relative_path}"),
+                fields: exported.fields,
+                ..Default::default()
+            }
+        }
+        FirestoreImportRecords::Objects {
+            parent,
+            collection_id,
+            id_field,
+        } => {
+            let document_id = match json.get(id_field) {
+                Some(JsonValue::String(id)) => id.clone(),
+                Some(JsonValue::Number(id)) => id.to_string(),
+                _ => {
+                    return Err(FirestoreError::DeserializeError(
+                        FirestoreSerializationError::from_message(format!(
+                            "Missing document ID in the field {id_field}"
+                        )),
+                    ))
+                }
+            };
+            let document_path = safe_document_path(
+                parent.as_deref().unwrap_or(documents_path),
+                collection_id,
+                document_id,
+            )?;
+            firestore_document_from_serializable(document_path, &json)?
+        }
+    };
+
+    let (update_mask, precondition) = match &params.conflict_policy {
+        FirestoreImportConflictPolicy::Overwrite => (None, None),
+        FirestoreImportConflictPolicy::SkipExisting => {
+            (None, Some(FirestoreWritePrecondition::Exists(false)))
+        }
+        FirestoreImportConflictPolicy::Merge(Some(field_paths)) => {
+            (Some(field_paths.clone()), None)
+        }
+        FirestoreImportConflictPolicy::Merge(None) => {
+            let mut field_paths: Vec<String> = document
+                .fields
+                .keys()
+                .map(|field| firestore_field_path_escape(field))
+                .collect();
+            field_paths.sort();
+            (Some(field_paths), None)
+        }
+    };
+
+    Ok(FirestoreImportRecord {
+        document,
+        update_mask,
+        precondition,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gcloud_sdk::google::firestore::v1::{precondition, value};
+
+    const DOCUMENTS_PATH: &str = "projects/target/databases/(default)/documents";
+
+    fn document(write: &Write) -> &Document {
+        match &write.operation {
+            Some(write::Operation::Update(doc)) => doc,
+            _ => panic!("Expected an update"),
+        }
+    }
+
+    #[test]
+    fn imports_exported_documents_into_the_database() {
+        let line = r#"{"name":"projects/source/databases/(default)/documents/users/u1/orders/o1","updateTime":"2024-01-01T00:00:00Z","fields":{"total":{"integerValue":"30"},"my field":{"stringValue":"x"}}}"#;
+
+        let write =
+            import_record_write(DOCUMENTS_PATH, &FirestoreImportParams::new(), line).unwrap();
+        let doc = document(&write);
+        assert_eq!(doc.name, format!("{DOCUMENTS_PATH}/users/u1/orders/o1"));
+        assert_eq!(doc.update_time, None);
+        assert_eq!(
+            doc.fields["total"].value_type,
+            Some(value::ValueType::IntegerValue(30))
+        );
+        assert_eq!(write.update_mask, None);
+        assert_eq!(write.current_document, None);
+
+        let merge = import_record_write(
+            DOCUMENTS_PATH,
+            &FirestoreImportParams::new()
+                .with_conflict_policy(FirestoreImportConflictPolicy::Merge(None)),
+            line,
+        )
+        .unwrap();
+        assert_eq!(
+            merge.update_mask.unwrap().field_paths,
+            vec!["`my field`".to_string(), "total".to_string()]
+        );
+
+        assert!(import_record_write(
+            DOCUMENTS_PATH,
+            &FirestoreImportParams::new(),
+            r#"{"name":"users/u1","fields":{}}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn imports_objects_with_ids_from_fields() {
+        let params = FirestoreImportParams::new()
+            .with_records(FirestoreImportRecords::Objects {
+                parent: None,
+                collection_id: "users".to_string(),
+                id_field: "user_id".to_string(),
+            })
+            .with_conflict_policy(FirestoreImportConflictPolicy::SkipExisting);
+
+        let write = import_record_write(
+            DOCUMENTS_PATH,
+            &params,
+            r#"{"user_id":42,"name":"Ann","tags":["a"]}"#,
+        )
+        .unwrap();
+        let doc = document(&write);
+        assert_eq!(doc.name, format!("{DOCUMENTS_PATH}/users/42"));
+        assert_eq!(
+            doc.fields["name"].value_type,
+            Some(value::ValueType::StringValue("Ann".to_string()))
+        );
+        assert_eq!(
+            write.current_document.unwrap().condition_type,
+            Some(precondition::ConditionType::Exists(false))
+        );
+
+        assert!(import_record_write(DOCUMENTS_PATH, &params, r#"{"name":"Ann"}"#).is_err());
+        assert!(import_record_write(DOCUMENTS_PATH, &params, r#"{"user_id":"a/b"}"#).is_err());
+        assert!(import_record_write(DOCUMENTS_PATH, &params, "not json").is_err());
+    }
+}
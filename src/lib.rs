@@ -123,6 +123,7 @@
 
 #![allow(clippy::new_without_default)]
 #![allow(clippy::needless_lifetimes)]
+#![allow(mismatched_lifetime_syntaxes)]
 #![forbid(unsafe_code)]
 
 /// Defines the error types used throughout the `firestore-rs` crate.
@@ -209,6 +210,15 @@ mod firestore_document_functions;
 /// from raw Firestore documents.
 pub use firestore_document_functions::*;
 
+mod firestore_entity;
+
+/// Re-exports the [`FirestoreEntity`] trait for types stored in a collection.
+pub use firestore_entity::*;
+
+/// Re-exports the derive macro for [`FirestoreEntity`] (the `derive` feature).
+#[cfg(feature = "derive")]
+pub use firestore_derive::FirestoreEntity;
+
 mod fluent_api;
 
 /// Re-exports all public items from the `fluent_api` module.
@@ -239,3 +249,71 @@ mod cache;
 /// It includes types like [`FirestoreCache`](cache::FirestoreCache) and various
 /// caching backends and configurations.
 pub use cache::*;
+
+#[cfg(feature = "bundles")]
+/// Provides building and parsing of Firestore data bundles.
+///
+/// This module is only available if the `bundles` feature is enabled.
+/// Bundles contain named queries and document snapshots that can be served
+/// to Firestore client SDKs without querying the database.
+mod bundles;
+
+#[cfg(feature = "bundles")]
+/// Re-exports all public items from the `bundles` module.
+///
+/// This is only available if the `bundles` feature is enabled.
+/// It includes [`FirestoreBundleBuilder`](bundles::FirestoreBundleBuilder) and
+/// [`FirestoreBundle`](bundles::FirestoreBundle).
+pub use bundles::*;
+
+#[cfg(feature = "export")]
+/// Provides exporting collections as newline-delimited JSON or Parquet.
+///
+/// This module is only available if the `export` feature is enabled.
+mod export;
+
+#[cfg(feature = "export")]
+/// Re-exports all public items from the `export` module.
+///
+/// This is only available if the `export` feature is enabled.
+/// It includes [`FirestoreExportParams`](export::FirestoreExportParams) used by
+/// [`FirestoreDb::export_ndjson`](crate::FirestoreDb::export_ndjson) and `FirestoreDb::export_parquet`.
+pub use export::*;
+
+#[cfg(feature = "export")]
+/// Provides importing documents from newline-delimited JSON, e.g. restoring exports.
+///
+/// This module is only available if the `export` feature is enabled.
+mod import;
+
+#[cfg(feature = "export")]
+/// Re-exports all public items from the `import` module.
+///
+/// This is only available if the `export` feature is enabled.
+/// It includes [`FirestoreImportParams`](import::FirestoreImportParams) used by
+/// [`FirestoreDb::import_ndjson`](crate::FirestoreDb::import_ndjson).
+pub use import::*;
+
+#[cfg(feature = "admin")]
+/// Provides typed wrappers over the Firestore Admin API.
+///
+/// This module is only available if the `admin` feature is enabled.
+/// It manages composite indexes, single-field index exemptions and TTL policies
+/// using the same credentials as [`FirestoreDb`].
+mod admin;
+
+#[cfg(feature = "admin")]
+/// Re-exports all public items from the `admin` module.
+///
+/// This is only available if the `admin` feature is enabled.
+/// It includes [`FirestoreAdmin`](admin::FirestoreAdmin) and
+/// [`FirestoreIndex`](admin::FirestoreIndex).
+pub use admin::*;
+
+#[cfg(feature = "testing")]
+/// Provides an in-memory database for unit tests using the fluent API.
+///
+/// This module is only available if the `testing` feature is enabled.
+/// It includes [`FirestoreInMemoryDb`](testing::FirestoreInMemoryDb), implementing
+/// the support traits used by the fluent builders without the Firestore emulator.
+pub mod testing;
@@ -0,0 +1,976 @@
+use crate::cache::cache_query_engine::FirestoreCacheQueryEngine;
+use crate::db::{
+    ensure_unique_aggregation_aliases, firestore_document_tree_obj_stream,
+    firestore_document_tree_stream,
+};
+use crate::errors::*;
+use crate::firestore_value::compare_field_values;
+use crate::timestamp_utils::from_timestamp;
+use crate::{
+    FirestoreAggregatedQueryParams, FirestoreAggregationOperator, FirestoreCollectionPath,
+    FirestoreDocumentTreeParams, FirestoreDocumentTreeRegistry, FirestoreExprBuilder,
+    FirestoreFieldTransform, FirestoreFieldTransformType, FirestoreQueryCollection,
+    FirestoreQueryParams, FirestoreResult, FirestoreTransformServerValue, FirestoreTypedCollection,
+    FirestoreValue, FirestoreWritePrecondition, FirestoreWriteResult,
+};
+use chrono::Utc;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use gcloud_sdk::google::firestore::v1::{value, ArrayValue, Document, MapValue, Value};
+use gcloud_sdk::prost_types::Timestamp;
+use rand::distr::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::*;
+
+/// The number of attempts to commit a transaction conflicting with other writes.
+pub const FIRESTORE_IN_MEMORY_TRANSACTION_MAX_ATTEMPTS: usize = 5;
+
+/// An in-memory implementation of the Firestore support traits, usable with the fluent API
+/// in unit tests instead of the emulator.
+///
+/// Supports documents in nested collections, field masks, preconditions and transforms,
+/// queries with the filters, ordering, cursors, offsets and limits supported by the cache
+/// query engine, collection group queries, aggregations, listing and transactions.
+/// Listeners are accepted, but never receive any changes.
+///
+/// Clones share the same documents.
+#[derive(Clone)]
+pub struct FirestoreInMemoryDb {
+    database_path: String,
+    documents_path: String,
+    documents: Arc<RwLock<BTreeMap<String, Document>>>,
+    clock: Arc<AtomicI64>,
+    transaction: Option<Arc<Mutex<FirestoreInMemoryTransactionState>>>,
+}
+
+#[derive(Default)]
+struct FirestoreInMemoryTransactionState {
+    /// The update times of the documents read or written in the transaction
+    /// when they were accessed for the first time (`None` if they didn't exist).
+    versions: BTreeMap<String, Option<Timestamp>>,
+    written: BTreeSet<String>,
+}
+
+impl std::fmt::Debug for FirestoreInMemoryDb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FirestoreInMemoryDb")
+            .field("database_path", &self.database_path)
+            .field("in_transaction", &self.transaction.is_some())
+            .finish()
+    }
+}
+
+fn in_memory_lock_error<E: std::fmt::Display>(err: &E) -> FirestoreError {
+    FirestoreError::SystemError(FirestoreSystemError::new(
+        FirestoreErrorPublicGenericDetails::new("SystemError".into()),
+        format!("In-memory database lock error: {err}"),
+    ))
+}
+
+impl FirestoreInMemoryDb {
+    /// Creates an empty database of the specified project with the `(default)` database ID.
+    pub fn new<S>(google_project_id: S) -> Self
+    where
+        S: AsRef<str>,
+    {
+        Self::with_database_id(google_project_id, "(default)")
+    }
+
+    /// Creates an empty database of the specified project and database ID.
+    pub fn with_database_id<S, DS>(google_project_id: S, database_id: DS) -> Self
+    where
+        S: AsRef<str>,
+        DS: AsRef<str>,
+    {
+        let database_path = format!(
+            "projects/{}/databases/{}",
+            google_project_id.as_ref(),
+            database_id.as_ref()
+        );
+        let documents_path = format!("{database_path}/documents");
+        Self {
+            database_path,
+            documents_path,
+            documents: Arc::new(RwLock::new(BTreeMap::new())),
+            clock: Arc::new(AtomicI64::new(0)),
+            transaction: None,
+        }
+    }
+
+    /// Provides access to the fluent API, as [`FirestoreDb::fluent()`](crate::FirestoreDb::fluent).
+    #[inline]
+    pub fn fluent(&self) -> FirestoreExprBuilder<'_, FirestoreInMemoryDb> {
+        FirestoreExprBuilder::new(self)
+    }
+
+    /// Creates a handle to a top-level collection with documents of type `T`,
+    /// as [`FirestoreDb::typed_collection()`](crate::FirestoreDb::typed_collection).
+    pub fn typed_collection<T>(
+        &self,
+        collection_id: &str,
+    ) -> FirestoreTypedCollection<T, FirestoreInMemoryDb>
+    where
+        T: Serialize + Send + Sync,
+        for<'de> T: Deserialize<'de>,
+    {
+        FirestoreTypedCollection::new(
+            self.clone(),
+            FirestoreCollectionPath::new(self.documents_path.clone(), collection_id.to_string()),
+        )
+    }
+
+    /// Streams the documents selected by the query into the writer as newline-delimited JSON,
+    /// as [`FirestoreDb::export_ndjson()`](crate::FirestoreDb::export_ndjson).
+    #[cfg(feature = "export")]
+    pub async fn export_ndjson<W>(
+        &self,
+        params: crate::FirestoreExportParams,
+        writer: &mut W,
+    ) -> FirestoreResult<crate::FirestoreExportStats>
+    where
+        W: tokio::io::AsyncWrite + Unpin + Send,
+    {
+        crate::export::export_ndjson(self, params, writer).await
+    }
+
+    /// Imports the records read from the newline-delimited JSON,
+    /// as [`FirestoreDb::import_ndjson()`](crate::FirestoreDb::import_ndjson).
+    ///
+    /// The records are written one by one, and a failed write stops the import with its error.
+    #[cfg(feature = "export")]
+    pub async fn import_ndjson<R>(
+        &self,
+        params: crate::FirestoreImportParams,
+        reader: R,
+    ) -> FirestoreResult<crate::FirestoreImportStats>
+    where
+        R: tokio::io::AsyncRead + Unpin + Send,
+    {
+        use tokio::io::AsyncBufReadExt;
+
+        let skip_existing = matches!(
+            params.conflict_policy,
+            crate::FirestoreImportConflictPolicy::SkipExisting
+        );
+        let mut stats = crate::FirestoreImportStats::default();
+        let mut lines = tokio::io::BufReader::new(reader).lines();
+        let mut line_number = 0;
+        while let Some(line) = lines.next_line().await? {
+            line_number += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = crate::import::import_record(self.get_documents_path(), &params, &line)
+                .map_err(|err| crate::import::import_record_error(line_number, err))?;
+            stats.records += 1;
+            match self.update_document(
+                record.document,
+                record.update_mask.as_ref(),
+                None,
+                record.precondition.as_ref(),
+            ) {
+                Ok(_) => stats.written += 1,
+                Err(FirestoreError::DataConflictError(_)) if skip_existing => stats.skipped += 1,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Returns the path of the database, e.g. `projects/test/databases/(default)`.
+    pub fn get_database_path(&self) -> &String {
+        &self.database_path
+    }
+
+    /// Returns the path of the root documents, used as the default parent.
+    pub fn get_documents_path(&self) -> &String {
+        &self.documents_path
+    }
+
+    /// Builds the parent path of a nested collection, as [`FirestoreDb::parent_path`](crate::FirestoreDb::parent_path).
+    pub fn parent_path<S>(&self, collection_name: &str, document_id: S) -> String
+    where
+        S: AsRef<str>,
+    {
+        format!(
+            "{}/{}/{}",
+            self.documents_path,
+            collection_name,
+            document_id.as_ref()
+        )
+    }
+
+    /// Returns all the stored documents ordered by their paths, e.g. to assert the state in tests.
+    pub fn documents(&self) -> FirestoreResult<Vec<Document>> {
+        Ok(self
+            .documents
+            .read()
+            .map_err(|err| in_memory_lock_error(&err))?
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    /// Streams the descendants of a document, as [`FirestoreDb::stream_document_tree`](crate::FirestoreDb::stream_document_tree).
+    pub async fn stream_document_tree<'b>(
+        &self,
+        params: FirestoreDocumentTreeParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<(String, Document)>>> {
+        Ok(firestore_document_tree_stream(
+            self.clone(),
+            self.documents_path.clone(),
+            params,
+        ))
+    }
+
+    /// Streams the descendants of a document deserialized by their collection IDs,
+    /// as [`FirestoreDb::stream_document_tree_obj`](crate::FirestoreDb::stream_document_tree_obj).
+    pub async fn stream_document_tree_obj<'b, T>(
+        &self,
+        params: FirestoreDocumentTreeParams,
+        registry: FirestoreDocumentTreeRegistry<T>,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<(String, T)>>>
+    where
+        T: Send + 'static,
+    {
+        Ok(firestore_document_tree_obj_stream(
+            self.clone(),
+            self.documents_path.clone(),
+            params,
+            registry,
+        ))
+    }
+
+    /// Removes all the documents.
+    pub fn clear(&self) -> FirestoreResult<()> {
+        self.documents
+            .write()
+            .map_err(|err| in_memory_lock_error(&err))?
+            .clear();
+        Ok(())
+    }
+
+    /// Runs the function in a transaction, retrying it if the documents it has read or written
+    /// were changed by others before the commit.
+    ///
+    /// The function receives a database working on a snapshot of the documents, and its writes
+    /// are applied to this database atomically when the function succeeds.
+    /// Unlike Firestore, the reads in the transaction see the writes made before them.
+    pub async fn run_transaction<T, FN>(&self, func: FN) -> FirestoreResult<T>
+    where
+        FN: Fn(FirestoreInMemoryDb) -> BoxFuture<'static, FirestoreResult<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            let transaction_db = self.begin_transaction()?;
+            let result = func(transaction_db.clone()).await?;
+            match self.commit_transaction(&transaction_db) {
+                Ok(()) => return Ok(result),
+                Err(FirestoreError::DatabaseError(ref err))
+                    if err.retry_possible
+                        && attempt < FIRESTORE_IN_MEMORY_TRANSACTION_MAX_ATTEMPTS =>
+                {
+                    debug!(%err, attempt, "Retrying the conflicting in-memory transaction.");
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn begin_transaction(&self) -> FirestoreResult<FirestoreInMemoryDb> {
+        let snapshot = self
+            .documents
+            .read()
+            .map_err(|err| in_memory_lock_error(&err))?
+            .clone();
+        Ok(Self {
+            documents: Arc::new(RwLock::new(snapshot)),
+            transaction: Some(Arc::new(Mutex::new(
+                FirestoreInMemoryTransactionState::default(),
+            ))),
+            ..self.clone()
+        })
+    }
+
+    fn commit_transaction(&self, transaction_db: &FirestoreInMemoryDb) -> FirestoreResult<()> {
+        let state = match &transaction_db.transaction {
+            Some(state) => state.lock().map_err(|err| in_memory_lock_error(&err))?,
+            None => return Ok(()),
+        };
+        let snapshot = transaction_db
+            .documents
+            .read()
+            .map_err(|err| in_memory_lock_error(&err))?;
+        let mut documents = self
+            .documents
+            .write()
+            .map_err(|err| in_memory_lock_error(&err))?;
+
+        for (document_path, version) in state.versions.iter() {
+            let current_version = documents.get(document_path).and_then(|doc| doc.update_time);
+            if current_version != *version {
+                return Err(gcloud_sdk::tonic::Status::aborted(format!(
+                    "Transaction conflict: document {document_path} was changed"
+                ))
+                .into());
+            }
+        }
+
+        for document_path in state.written.iter() {
+            match snapshot.get(document_path) {
+                Some(doc) => {
+                    documents.insert(document_path.clone(), doc.clone());
+                }
+                None => {
+                    documents.remove(document_path);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn track_access(&self, document_path: &str, doc: Option<&Document>, write: bool) {
+        if let Some(transaction) = &self.transaction {
+            if let Ok(mut state) = transaction.lock() {
+                state
+                    .versions
+                    .entry(document_path.to_string())
+                    .or_insert_with(|| doc.and_then(|doc| doc.update_time));
+                if write {
+                    state.written.insert(document_path.to_string());
+                }
+            }
+        }
+    }
+
+    /// Returns strictly increasing timestamps, so every write changes the update time.
+    fn next_timestamp(&self) -> Timestamp {
+        let now = Utc::now().timestamp_micros();
+        let mut last = self.clock.load(Ordering::SeqCst);
+        loop {
+            let next = now.max(last + 1);
+            match self
+                .clock
+                .compare_exchange(last, next, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => {
+                    return Timestamp {
+                        seconds: next.div_euclid(1_000_000),
+                        nanos: (next.rem_euclid(1_000_000) * 1000) as i32,
+                    }
+                }
+                Err(current) => last = current,
+            }
+        }
+    }
+
+    pub(crate) fn generate_document_id() -> String {
+        rand::rng()
+            .sample_iter(&Alphanumeric)
+            .take(20)
+            .map(char::from)
+            .collect()
+    }
+
+    pub(crate) fn get_document(
+        &self,
+        document_path: &str,
+        return_only_fields: Option<&Vec<String>>,
+    ) -> FirestoreResult<Option<Document>> {
+        let documents = self
+            .documents
+            .read()
+            .map_err(|err| in_memory_lock_error(&err))?;
+        let doc = documents.get(document_path);
+        self.track_access(document_path, doc, false);
+        Ok(doc.map(|doc| project_document(doc.clone(), return_only_fields)))
+    }
+
+    /// Writes the document returned by `update` for the current one, checking the precondition first.
+    /// Returning `None` deletes the document.
+    pub(crate) fn write_document<F>(
+        &self,
+        document_path: &str,
+        precondition: Option<&FirestoreWritePrecondition>,
+        update: F,
+    ) -> FirestoreResult<Option<Document>>
+    where
+        F: FnOnce(Option<&Document>, &Timestamp) -> FirestoreResult<Option<HashMap<String, Value>>>,
+    {
+        let mut documents = self
+            .documents
+            .write()
+            .map_err(|err| in_memory_lock_error(&err))?;
+        let current = documents.get(document_path);
+        self.track_access(document_path, current, true);
+        check_precondition(document_path, current, precondition)?;
+
+        let write_time = self.next_timestamp();
+        match update(current, &write_time)? {
+            Some(fields) => {
+                let doc = Document {
+                    name: document_path.to_string(),
+                    fields,
+                    create_time: Some(
+                        current
+                            .and_then(|doc| doc.create_time)
+                            .unwrap_or(write_time),
+                    ),
+                    update_time: Some(write_time),
+                };
+                documents.insert(document_path.to_string(), doc.clone());
+                Ok(Some(doc))
+            }
+            None => {
+                documents.remove(document_path);
+                Ok(None)
+            }
+        }
+    }
+
+    pub(crate) fn create_document(
+        &self,
+        document_path: &str,
+        fields: HashMap<String, Value>,
+        return_only_fields: Option<&Vec<String>>,
+    ) -> FirestoreResult<Document> {
+        let doc = self.write_document(
+            document_path,
+            Some(&FirestoreWritePrecondition::Exists(false)),
+            |_, _| Ok(Some(fields)),
+        )?;
+        stored_document(doc, return_only_fields)
+    }
+
+    pub(crate) fn update_document(
+        &self,
+        input_doc: Document,
+        update_only: Option<&Vec<String>>,
+        return_only_fields: Option<&Vec<String>>,
+        precondition: Option<&FirestoreWritePrecondition>,
+    ) -> FirestoreResult<Document> {
+        let doc = self.write_document(&input_doc.name, precondition, |current, _| {
+            Ok(Some(match update_only {
+                Some(update_only) => {
+                    let mut fields = current.map(|doc| doc.fields.clone()).unwrap_or_default();
+                    for field_path in update_only {
+                        let path = field_path_segments(field_path);
+                        match get_field(&input_doc.fields, &path) {
+                            Some(value) => set_field(&mut fields, &path, value.clone()),
+                            None => remove_field(&mut fields, &path),
+                        }
+                    }
+                    fields
+                }
+                None => input_doc.fields.clone(),
+            }))
+        })?;
+        stored_document(doc, return_only_fields)
+    }
+
+    pub(crate) fn transform_document(
+        &self,
+        document_path: &str,
+        precondition: Option<&FirestoreWritePrecondition>,
+        transforms: &[FirestoreFieldTransform],
+    ) -> FirestoreResult<FirestoreWriteResult> {
+        let mut transform_results = Vec::with_capacity(transforms.len());
+        let doc = self.write_document(document_path, precondition, |current, write_time| {
+            let mut fields = current.map(|doc| doc.fields.clone()).unwrap_or_default();
+            transform_results = apply_transforms(&mut fields, transforms, write_time)?;
+            Ok(Some(fields))
+        })?;
+        Ok(
+            FirestoreWriteResult::new(transform_results).opt_update_time(
+                doc.and_then(|doc| doc.update_time)
+                    .map(from_timestamp)
+                    .transpose()?,
+            ),
+        )
+    }
+
+    pub(crate) fn delete_document(
+        &self,
+        document_path: &str,
+        precondition: Option<&FirestoreWritePrecondition>,
+    ) -> FirestoreResult<()> {
+        self.write_document(document_path, precondition, |_, _| Ok(None))?;
+        Ok(())
+    }
+
+    /// Returns the documents of the query collection under the parent, ordered by their paths.
+    fn collection_documents(
+        &self,
+        parent: &str,
+        collection: &FirestoreQueryCollection,
+        all_descendants: bool,
+    ) -> FirestoreResult<Vec<Document>> {
+        let collection_ids: Vec<&str> = match collection {
+            FirestoreQueryCollection::Single(collection_id) => vec![collection_id.as_str()],
+            FirestoreQueryCollection::Group(collection_ids) => {
+                collection_ids.iter().map(|id| id.as_str()).collect()
+            }
+        };
+        let prefix = format!("{parent}/");
+        let documents = self
+            .documents
+            .read()
+            .map_err(|err| in_memory_lock_error(&err))?;
+
+        Ok(documents
+            .range(prefix.clone()..)
+            .take_while(|(document_path, _)| document_path.starts_with(&prefix))
+            .filter(|(document_path, _)| {
+                let segments: Vec<&str> = document_path[prefix.len()..].split('/').collect();
+                let collection_id = segments[segments.len().saturating_sub(2)];
+                (segments.len() == 2 || (all_descendants && segments.len() % 2 == 0))
+                    && collection_ids.contains(&collection_id)
+            })
+            .map(|(_, doc)| doc.clone())
+            .collect())
+    }
+
+    pub(crate) async fn query_documents(
+        &self,
+        params: &FirestoreQueryParams,
+    ) -> FirestoreResult<Vec<Document>> {
+        let parent = params
+            .parent
+            .clone()
+            .unwrap_or_else(|| self.documents_path.clone());
+        // The candidate documents are selected by the collections here,
+        // so the engine only filters, orders and limits them as a single collection query.
+        let engine_params = FirestoreQueryParams {
+            collection_id: FirestoreQueryCollection::Single("".to_string()),
+            all_descendants: None,
+            limit_to_last: None,
+            ..params.clone().resolve_document_ids(&self.documents_path)
+        };
+        let engine = FirestoreCacheQueryEngine::new(&engine_params);
+        if !engine.params_supported() {
+            return Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "find_nearest".to_string(),
+                    "Vector search isn't supported by the in-memory database".to_string(),
+                )),
+            ));
+        }
+        let docs: Vec<FirestoreResult<Document>> = self
+            .collection_documents(
+                &parent,
+                &params.collection_id,
+                params.all_descendants.unwrap_or(false),
+            )?
+            .into_iter()
+            .filter(|doc| engine.matches_doc(doc))
+            .map(Ok)
+            .collect();
+
+        let mut docs: Vec<Document> = engine
+            .process_query_stream(futures::stream::iter(docs).boxed())
+            .await?
+            .collect::<Vec<FirestoreResult<Document>>>()
+            .await
+            .into_iter()
+            .collect::<FirestoreResult<Vec<Document>>>()?;
+
+        if let Some(limit_to_last) = params.limit_to_last {
+            docs = docs.split_off(docs.len().saturating_sub(limit_to_last as usize));
+        }
+
+        for doc in docs.iter() {
+            self.track_access(&doc.name, Some(doc), false);
+        }
+
+        Ok(docs
+            .into_iter()
+            .map(|doc| project_document(doc, params.return_only_fields.as_ref()))
+            .collect())
+    }
+
+    pub(crate) async fn aggregate_documents(
+        &self,
+        params: &FirestoreAggregatedQueryParams,
+    ) -> FirestoreResult<Document> {
+        ensure_unique_aggregation_aliases(&params.aggregations)?;
+        let docs = self.query_documents(&params.query_params).await?;
+        let fields = params
+            .aggregations
+            .iter()
+            .filter_map(|aggregation| {
+                aggregation.operator.as_ref().map(|operator| {
+                    let value_type = match operator {
+                        FirestoreAggregationOperator::Count(count) => {
+                            value::ValueType::IntegerValue(
+                                count
+                                    .up_to
+                                    .map(|up_to| docs.len().min(up_to))
+                                    .unwrap_or(docs.len()) as i64,
+                            )
+                        }
+                        FirestoreAggregationOperator::Sum(sum) => {
+                            sum_values(&numeric_values(&docs, &sum.field_name))
+                        }
+                        FirestoreAggregationOperator::Avg(avg) => {
+                            let values = numeric_values(&docs, &avg.field_name);
+                            if values.is_empty() {
+                                value::ValueType::NullValue(0)
+                            } else {
+                                let sum: f64 = values.iter().map(|value| value.as_f64()).sum();
+                                value::ValueType::DoubleValue(sum / values.len() as f64)
+                            }
+                        }
+                    };
+                    (
+                        aggregation.alias.clone(),
+                        Value {
+                            value_type: Some(value_type),
+                        },
+                    )
+                })
+            })
+            .collect();
+
+        Ok(Document {
+            name: "".to_string(),
+            fields,
+            create_time: None,
+            update_time: None,
+        })
+    }
+
+    /// Returns the documents that don't exist in the collection, but have subcollections,
+    /// without fields as Firestore lists them.
+    pub(crate) fn missing_documents(
+        &self,
+        parent: &str,
+        collection_id: &str,
+    ) -> FirestoreResult<Vec<Document>> {
+        let prefix = format!("{parent}/{collection_id}/");
+        let documents = self
+            .documents
+            .read()
+            .map_err(|err| in_memory_lock_error(&err))?;
+        let missing_paths: BTreeSet<String> = documents
+            .range(prefix.clone()..)
+            .take_while(|(document_path, _)| document_path.starts_with(&prefix))
+            .filter_map(|(document_path, _)| {
+                let mut segments = document_path[prefix.len()..].splitn(2, '/');
+                match (segments.next(), segments.next()) {
+                    (Some(document_id), Some(_)) => Some(format!("{prefix}{document_id}")),
+                    _ => None,
+                }
+            })
+            .filter(|document_path| !documents.contains_key(document_path))
+            .collect();
+        Ok(missing_paths
+            .into_iter()
+            .map(|name| Document {
+                name,
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    /// Returns the IDs of the collections with documents under the parent.
+    pub(crate) fn list_collection_ids(&self, parent: &str) -> FirestoreResult<Vec<String>> {
+        let prefix = format!("{parent}/");
+        let documents = self
+            .documents
+            .read()
+            .map_err(|err| in_memory_lock_error(&err))?;
+        let collection_ids: BTreeSet<String> = documents
+            .range(prefix.clone()..)
+            .take_while(|(document_path, _)| document_path.starts_with(&prefix))
+            .filter_map(|(document_path, _)| {
+                document_path[prefix.len()..]
+                    .split('/')
+                    .next()
+                    .map(|collection_id| collection_id.to_string())
+            })
+            .collect();
+        Ok(collection_ids.into_iter().collect())
+    }
+}
+
+fn check_precondition(
+    document_path: &str,
+    current: Option<&Document>,
+    precondition: Option<&FirestoreWritePrecondition>,
+) -> FirestoreResult<()> {
+    match (precondition, current) {
+        (Some(FirestoreWritePrecondition::Exists(true)), None) => Err(
+            gcloud_sdk::tonic::Status::not_found(format!("No document to update: {document_path}"))
+                .into(),
+        ),
+        (Some(FirestoreWritePrecondition::Exists(false)), Some(_)) => {
+            Err(gcloud_sdk::tonic::Status::already_exists(format!(
+                "Document already exists: {document_path}"
+            ))
+            .into())
+        }
+        (Some(FirestoreWritePrecondition::UpdateTime(update_time)), current) => {
+            let current_update_time = current
+                .and_then(|doc| doc.update_time)
+                .map(from_timestamp)
+                .transpose()?;
+            if current_update_time == Some(*update_time) {
+                Ok(())
+            } else {
+                Err(gcloud_sdk::tonic::Status::failed_precondition(format!(
+                    "The update time of {document_path} doesn't match the precondition"
+                ))
+                .into())
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+fn stored_document(
+    doc: Option<Document>,
+    return_only_fields: Option<&Vec<String>>,
+) -> FirestoreResult<Document> {
+    doc.map(|doc| project_document(doc, return_only_fields))
+        .ok_or_else(|| {
+            FirestoreError::SystemError(FirestoreSystemError::new(
+                FirestoreErrorPublicGenericDetails::new("SystemError".into()),
+                "The written document is missing".to_string(),
+            ))
+        })
+}
+
+fn field_path_segments(field_path: &str) -> Vec<String> {
+    field_path
+        .split('.')
+        .map(|segment| segment.replace('`', ""))
+        .collect()
+}
+
+fn get_field<'d>(fields: &'d HashMap<String, Value>, path: &[String]) -> Option<&'d Value> {
+    let (first, rest) = path.split_first()?;
+    let value = fields.get(first)?;
+    if rest.is_empty() {
+        Some(value)
+    } else {
+        match &value.value_type {
+            Some(value::ValueType::MapValue(map)) => get_field(&map.fields, rest),
+            _ => None,
+        }
+    }
+}
+
+fn set_field(fields: &mut HashMap<String, Value>, path: &[String], new_value: Value) {
+    if let Some((first, rest)) = path.split_first() {
+        if rest.is_empty() {
+            fields.insert(first.clone(), new_value);
+        } else {
+            let entry = fields.entry(first.clone()).or_insert_with(|| Value {
+                value_type: Some(value::ValueType::MapValue(MapValue::default())),
+            });
+            if !matches!(entry.value_type, Some(value::ValueType::MapValue(_))) {
+                entry.value_type = Some(value::ValueType::MapValue(MapValue::default()));
+            }
+            if let Some(value::ValueType::MapValue(map)) = &mut entry.value_type {
+                set_field(&mut map.fields, rest, new_value);
+            }
+        }
+    }
+}
+
+fn remove_field(fields: &mut HashMap<String, Value>, path: &[String]) {
+    if let Some((first, rest)) = path.split_first() {
+        if rest.is_empty() {
+            fields.remove(first);
+        } else if let Some(Value {
+            value_type: Some(value::ValueType::MapValue(map)),
+        }) = fields.get_mut(first)
+        {
+            remove_field(&mut map.fields, rest);
+        }
+    }
+}
+
+fn project_document(doc: Document, return_only_fields: Option<&Vec<String>>) -> Document {
+    match return_only_fields {
+        Some(return_only_fields) => {
+            let mut fields = HashMap::new();
+            for field_path in return_only_fields {
+                let path = field_path_segments(field_path);
+                if let Some(value) = get_field(&doc.fields, &path) {
+                    set_field(&mut fields, &path, value.clone());
+                }
+            }
+            Document { fields, ..doc }
+        }
+        None => doc,
+    }
+}
+
+fn is_numeric(value_type: &value::ValueType) -> bool {
+    matches!(
+        value_type,
+        value::ValueType::IntegerValue(_) | value::ValueType::DoubleValue(_)
+    )
+}
+
+fn apply_transform(
+    current: Option<&value::ValueType>,
+    transform_type: &FirestoreFieldTransformType,
+    write_time: &Timestamp,
+) -> FirestoreResult<value::ValueType> {
+    let current_array = || match current {
+        Some(value::ValueType::ArrayValue(array)) => array.values.clone(),
+        _ => Vec::new(),
+    };
+    let operand = |value: &FirestoreValue| {
+        value
+            .value
+            .value_type
+            .clone()
+            .unwrap_or(value::ValueType::NullValue(0))
+    };
+
+    Ok(match transform_type {
+        FirestoreFieldTransformType::SetToServerValue(
+            FirestoreTransformServerValue::RequestTime,
+        ) => value::ValueType::TimestampValue(*write_time),
+        FirestoreFieldTransformType::SetToServerValue(
+            FirestoreTransformServerValue::Unspecified,
+        ) => {
+            return Err(FirestoreError::InvalidParametersError(
+                FirestoreInvalidParametersError::new(FirestoreInvalidParametersPublicDetails::new(
+                    "transform_type".to_string(),
+                    "Unspecified server value".to_string(),
+                )),
+            ))
+        }
+        FirestoreFieldTransformType::Increment(by) => match (current, operand(by)) {
+            (Some(value::ValueType::IntegerValue(a)), value::ValueType::IntegerValue(b)) => {
+                value::ValueType::IntegerValue(a.saturating_add(b))
+            }
+            (Some(value::ValueType::IntegerValue(a)), value::ValueType::DoubleValue(b)) => {
+                value::ValueType::DoubleValue(*a as f64 + b)
+            }
+            (Some(value::ValueType::DoubleValue(a)), value::ValueType::IntegerValue(b)) => {
+                value::ValueType::DoubleValue(a + b as f64)
+            }
+            (Some(value::ValueType::DoubleValue(a)), value::ValueType::DoubleValue(b)) => {
+                value::ValueType::DoubleValue(a + b)
+            }
+            (_, by) => by,
+        },
+        FirestoreFieldTransformType::Maximum(other)
+        | FirestoreFieldTransformType::Minimum(other) => {
+            let other = operand(other);
+            match current {
+                Some(current) if is_numeric(current) && is_numeric(&other) => {
+                    let ordering = compare_field_values(current, &other);
+                    let keep_current =
+                        if matches!(transform_type, FirestoreFieldTransformType::Maximum(_)) {
+                            ordering != std::cmp::Ordering::Less
+                        } else {
+                            ordering != std::cmp::Ordering::Greater
+                        };
+                    if keep_current {
+                        current.clone()
+                    } else {
+                        other
+                    }
+                }
+                _ => other,
+            }
+        }
+        FirestoreFieldTransformType::AppendMissingElements(elements) => {
+            let mut values = current_array();
+            for element in elements {
+                if !values.contains(&element.value) {
+                    values.push(element.value.clone());
+                }
+            }
+            value::ValueType::ArrayValue(ArrayValue { values })
+        }
+        FirestoreFieldTransformType::RemoveAllFromArray(elements) => {
+            let values = current_array()
+                .into_iter()
+                .filter(|value| !elements.iter().any(|element| element.value == *value))
+                .collect();
+            value::ValueType::ArrayValue(ArrayValue { values })
+        }
+    })
+}
+
+fn apply_transforms(
+    fields: &mut HashMap<String, Value>,
+    transforms: &[FirestoreFieldTransform],
+    write_time: &Timestamp,
+) -> FirestoreResult<Vec<FirestoreValue>> {
+    transforms
+        .iter()
+        .map(|transform| {
+            let path = field_path_segments(&transform.field);
+            let new_value = Value {
+                value_type: Some(apply_transform(
+                    get_field(fields, &path).and_then(|value| value.value_type.as_ref()),
+                    &transform.transform_type,
+                    write_time,
+                )?),
+            };
+            set_field(fields, &path, new_value.clone());
+            Ok(FirestoreValue::from(new_value))
+        })
+        .collect()
+}
+
+enum FirestoreNumericValue {
+    Integer(i64),
+    Double(f64),
+}
+
+impl FirestoreNumericValue {
+    fn as_f64(&self) -> f64 {
+        match self {
+            FirestoreNumericValue::Integer(value) => *value as f64,
+            FirestoreNumericValue::Double(value) => *value,
+        }
+    }
+}
+
+fn numeric_values(docs: &[Document], field_name: &str) -> Vec<FirestoreNumericValue> {
+    let path = field_path_segments(field_name);
+    docs.iter()
+        .filter_map(|doc| {
+            match get_field(&doc.fields, &path).and_then(|value| value.value_type.as_ref()) {
+                Some(value::ValueType::IntegerValue(value)) => {
+                    Some(FirestoreNumericValue::Integer(*value))
+                }
+                Some(value::ValueType::DoubleValue(value)) => {
+                    Some(FirestoreNumericValue::Double(*value))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn sum_values(values: &[FirestoreNumericValue]) -> value::ValueType {
+    if values
+        .iter()
+        .all(|value| matches!(value, FirestoreNumericValue::Integer(_)))
+    {
+        let sum = values.iter().try_fold(0i64, |sum, value| match value {
+            FirestoreNumericValue::Integer(value) => sum.checked_add(*value),
+            FirestoreNumericValue::Double(_) => None,
+        });
+        if let Some(sum) = sum {
+            return value::ValueType::IntegerValue(sum);
+        }
+    }
+    value::ValueType::DoubleValue(values.iter().map(|value| value.as_f64()).sum())
+}
@@ -0,0 +1,1119 @@
+use crate::db::{order_batch_get_results, safe_document_path};
+use crate::errors::*;
+use crate::testing::FirestoreInMemoryDb;
+use crate::*;
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{FutureExt, StreamExt};
+use gcloud_sdk::google::firestore::v1::{Document, ListenResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+impl FirestoreInMemoryDb {
+    fn document_path<S>(
+        &self,
+        parent: Option<&str>,
+        collection_id: &str,
+        document_id: S,
+    ) -> FirestoreResult<String>
+    where
+        S: AsRef<str>,
+    {
+        safe_document_path(
+            parent.unwrap_or(self.get_documents_path().as_str()),
+            collection_id,
+            document_id,
+        )
+    }
+
+    fn get_doc_by_path(
+        &self,
+        document_path: &str,
+        return_only_fields: Option<&Vec<String>>,
+    ) -> FirestoreResult<Document> {
+        self.get_document(document_path, return_only_fields)?
+            .ok_or_else(|| {
+                gcloud_sdk::tonic::Status::not_found(format!("Document not found: {document_path}"))
+                    .into()
+            })
+    }
+
+    fn batch_get_docs<S, I>(
+        &self,
+        parent: Option<&str>,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<&Vec<String>>,
+    ) -> FirestoreResult<Vec<(String, Option<Document>)>>
+    where
+        S: AsRef<str>,
+        I: IntoIterator<Item = S>,
+    {
+        document_ids
+            .into_iter()
+            .map(|document_id| {
+                let document_path = self.document_path(parent, collection_id, &document_id)?;
+                Ok((
+                    document_id.as_ref().to_string(),
+                    self.get_document(&document_path, return_only_fields)?,
+                ))
+            })
+            .collect()
+    }
+
+    fn batch_get_objects_ordered_by_path<T, S, I>(
+        &self,
+        parent: Option<&str>,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<&Vec<String>>,
+    ) -> FirestoreResult<Vec<(String, FirestoreBatchGetResult<T>)>>
+    where
+        for<'de> T: Deserialize<'de>,
+        S: AsRef<str>,
+        I: IntoIterator<Item = S>,
+    {
+        let full_doc_ids = document_ids
+            .into_iter()
+            .map(|document_id| self.document_path(parent, collection_id, document_id))
+            .collect::<FirestoreResult<Vec<String>>>()?;
+        let found_docs = full_doc_ids
+            .iter()
+            .map(|document_path| self.get_document(document_path, return_only_fields))
+            .collect::<FirestoreResult<Vec<Option<Document>>>>()?
+            .into_iter()
+            .flatten()
+            .map(|doc| (doc.name.clone(), doc))
+            .collect::<HashMap<String, Document>>();
+        Ok(order_batch_get_results(full_doc_ids, &found_docs))
+    }
+
+    fn with_metadata<T>(document: T) -> FirestoreWithMetadata<T> {
+        FirestoreWithMetadata {
+            document: Some(document),
+            metadata: FirestoreDocumentMetadata::new(0).with_read_time(Utc::now()),
+        }
+    }
+}
+
+fn deserialize_docs<T>(docs: Vec<Document>) -> FirestoreResult<Vec<T>>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    docs.iter().map(FirestoreDb::deserialize_doc_to).collect()
+}
+
+fn deserialize_docs_with_errors<T>(docs: Vec<Document>) -> Vec<FirestoreResult<T>>
+where
+    for<'de> T: Deserialize<'de>,
+{
+    docs.iter().map(FirestoreDb::deserialize_doc_to).collect()
+}
+
+#[async_trait]
+impl FirestoreQuerySupport for FirestoreInMemoryDb {
+    async fn query_doc(&self, params: FirestoreQueryParams) -> FirestoreResult<Vec<Document>> {
+        self.query_documents(&params).await
+    }
+
+    async fn stream_query_doc<'b>(
+        &self,
+        params: FirestoreQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, Document>> {
+        let docs = self.query_documents(&params).await?;
+        Ok(futures::stream::iter(docs).boxed())
+    }
+
+    async fn stream_query_doc_with_errors<'b>(
+        &self,
+        params: FirestoreQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<Document>>> {
+        let docs = self.query_documents(&params).await?;
+        Ok(futures::stream::iter(docs.into_iter().map(Ok)).boxed())
+    }
+
+    async fn stream_query_doc_with_metadata<'b>(
+        &self,
+        params: FirestoreQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<FirestoreDocument>>>>
+    {
+        let docs = self.query_documents(&params).await?;
+        Ok(futures::stream::iter(docs.into_iter().map(|doc| Ok(Self::with_metadata(doc)))).boxed())
+    }
+
+    async fn stream_query_doc_with_meta<'b>(
+        &self,
+        params: FirestoreQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocWithMetaItem<FirestoreDocument>>>>
+    {
+        let docs = self.query_documents(&params).await?;
+        let consistency_selector = params.consistency_selector;
+        // Firestore responds to queries without results with a read time only
+        let items: Vec<FirestoreResult<FirestoreDocWithMetaItem<FirestoreDocument>>> =
+            if docs.is_empty() {
+                vec![Ok(FirestoreDocWithMetaItem::ReadTime {
+                    read_time: Utc::now(),
+                    consistency_selector,
+                })]
+            } else {
+                docs.into_iter()
+                    .filter_map(|doc| {
+                        FirestoreDocWithMetaItem::from_metadata(
+                            Self::with_metadata(doc),
+                            consistency_selector.clone(),
+                        )
+                        .transpose()
+                    })
+                    .collect()
+            };
+        Ok(futures::stream::iter(items).boxed())
+    }
+
+    async fn query_obj<T>(&self, params: FirestoreQueryParams) -> FirestoreResult<Vec<T>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        deserialize_docs(self.query_documents(&params).await?)
+    }
+
+    async fn stream_query_obj<'b, T>(
+        &self,
+        params: FirestoreQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, T>>
+    where
+        for<'de> T: Deserialize<'de>,
+        T: 'b,
+    {
+        let docs = self.query_documents(&params).await?;
+        Ok(futures::stream::iter(docs)
+            .filter_map(|doc| async move { FirestoreDb::deserialize_doc_to(&doc).ok() })
+            .boxed())
+    }
+
+    async fn stream_query_obj_with_errors<'b, T>(
+        &self,
+        params: FirestoreQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<T>>>
+    where
+        for<'de> T: Deserialize<'de>,
+        T: Send + 'b,
+    {
+        let objs = deserialize_docs_with_errors(self.query_documents(&params).await?);
+        Ok(futures::stream::iter(objs).boxed())
+    }
+
+    async fn stream_query_obj_with_metadata<'b, T>(
+        &self,
+        params: FirestoreQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<T>>>>
+    where
+        for<'de> T: Deserialize<'de>,
+        T: Send + 'b,
+    {
+        let objs: Vec<FirestoreResult<FirestoreWithMetadata<T>>> =
+            deserialize_docs_with_errors(self.query_documents(&params).await?)
+                .into_iter()
+                .map(|obj| obj.map(Self::with_metadata))
+                .collect();
+        Ok(futures::stream::iter(objs).boxed())
+    }
+
+    async fn stream_query_obj_with_meta<'b, T>(
+        &self,
+        params: FirestoreQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreDocWithMetaItem<T>>>>
+    where
+        for<'de> T: Deserialize<'de>,
+        T: Send + 'b,
+    {
+        let objs: Vec<FirestoreResult<FirestoreDocWithMetaItem<T>>> = self
+            .stream_query_doc_with_meta(params)
+            .await?
+            .map(|doc_with_meta| {
+                doc_with_meta?.try_map(|doc| FirestoreDb::deserialize_doc_to(&doc))
+            })
+            .collect()
+            .await;
+        Ok(futures::stream::iter(objs).boxed())
+    }
+
+    fn stream_partition_cursors_with_errors(
+        &self,
+        _params: FirestorePartitionQueryParams,
+    ) -> BoxFuture<'_, FirestoreResult<PeekableBoxStream<'_, FirestoreResult<FirestoreQueryCursor>>>>
+    {
+        async move { Ok(futures::stream::empty().boxed().peekable()) }.boxed()
+    }
+
+    async fn stream_partition_query_doc_with_errors(
+        &self,
+        _parallelism: usize,
+        partition_params: FirestorePartitionQueryParams,
+    ) -> FirestoreResult<BoxStream<FirestoreResult<(FirestorePartition, Document)>>> {
+        let docs = self.query_documents(&partition_params.query_params).await?;
+        Ok(futures::stream::iter(
+            docs.into_iter()
+                .map(|doc| Ok((FirestorePartition::new(), doc))),
+        )
+        .boxed())
+    }
+
+    async fn stream_partition_query_obj_with_errors<'a, T>(
+        &'a self,
+        _parallelism: usize,
+        partition_params: FirestorePartitionQueryParams,
+    ) -> FirestoreResult<BoxStream<'a, FirestoreResult<(FirestorePartition, T)>>>
+    where
+        for<'de> T: Deserialize<'de>,
+        T: Send + 'a,
+    {
+        let objs: Vec<FirestoreResult<(FirestorePartition, T)>> = deserialize_docs_with_errors(
+            self.query_documents(&partition_params.query_params).await?,
+        )
+        .into_iter()
+        .map(|obj| obj.map(|obj| (FirestorePartition::new(), obj)))
+        .collect();
+        Ok(futures::stream::iter(objs).boxed())
+    }
+}
+
+#[async_trait]
+impl FirestoreCreateSupport for FirestoreInMemoryDb {
+    async fn create_doc<S>(
+        &self,
+        collection_id: &str,
+        document_id: Option<S>,
+        input_doc: Document,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<Document>
+    where
+        S: AsRef<str> + Send,
+    {
+        let parent = self.get_documents_path().clone();
+        self.create_doc_at(
+            parent.as_str(),
+            collection_id,
+            document_id,
+            input_doc,
+            return_only_fields,
+        )
+        .await
+    }
+
+    async fn create_doc_at<S>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_id: Option<S>,
+        input_doc: Document,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<Document>
+    where
+        S: AsRef<str> + Send,
+    {
+        let document_path = match document_id {
+            Some(document_id) => self.document_path(Some(parent), collection_id, document_id)?,
+            None => {
+                self.document_path(Some(parent), collection_id, Self::generate_document_id())?
+            }
+        };
+        self.create_document(
+            &document_path,
+            input_doc.fields,
+            return_only_fields.as_ref(),
+        )
+    }
+
+    async fn create_obj<I, O, S>(
+        &self,
+        collection_id: &str,
+        document_id: Option<S>,
+        obj: &I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<O>
+    where
+        I: Serialize + Sync + Send,
+        for<'de> O: Deserialize<'de>,
+        S: AsRef<str> + Send,
+    {
+        let parent = self.get_documents_path().clone();
+        self.create_obj_at(
+            parent.as_str(),
+            collection_id,
+            document_id,
+            obj,
+            return_only_fields,
+        )
+        .await
+    }
+
+    async fn create_obj_at<I, O, S>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_id: Option<S>,
+        obj: &I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<O>
+    where
+        I: Serialize + Sync + Send,
+        for<'de> O: Deserialize<'de>,
+        S: AsRef<str> + Send,
+    {
+        let input_doc =
+            crate::firestore_serde::firestore_new_document_from_serializable(collection_id, obj)?;
+        let doc = self
+            .create_doc_at(
+                parent,
+                collection_id,
+                document_id,
+                input_doc,
+                return_only_fields,
+            )
+            .await?;
+        FirestoreDb::deserialize_doc_to(&doc)
+    }
+}
+
+#[async_trait]
+impl FirestoreUpdateSupport for FirestoreInMemoryDb {
+    async fn update_obj<I, O, S>(
+        &self,
+        collection_id: &str,
+        document_id: S,
+        obj: &I,
+        update_only: Option<Vec<String>>,
+        return_only_fields: Option<Vec<String>>,
+        precondition: Option<FirestoreWritePrecondition>,
+    ) -> FirestoreResult<O>
+    where
+        I: Serialize + Sync + Send,
+        for<'de> O: Deserialize<'de>,
+        S: AsRef<str> + Send,
+    {
+        let parent = self.get_documents_path().clone();
+        self.update_obj_at(
+            parent.as_str(),
+            collection_id,
+            document_id,
+            obj,
+            update_only,
+            return_only_fields,
+            precondition,
+        )
+        .await
+    }
+
+    async fn update_obj_at<I, O, S>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_id: S,
+        obj: &I,
+        update_only: Option<Vec<String>>,
+        return_only_fields: Option<Vec<String>>,
+        precondition: Option<FirestoreWritePrecondition>,
+    ) -> FirestoreResult<O>
+    where
+        I: Serialize + Sync + Send,
+        for<'de> O: Deserialize<'de>,
+        S: AsRef<str> + Send,
+    {
+        let document_path = self.document_path(Some(parent), collection_id, document_id)?;
+        let doc = self.update_document(
+            FirestoreDb::serialize_to_doc(document_path, obj)?,
+            update_only.as_ref(),
+            return_only_fields.as_ref(),
+            precondition.as_ref(),
+        )?;
+        FirestoreDb::deserialize_doc_to(&doc)
+    }
+
+    async fn update_doc(
+        &self,
+        _collection_id: &str,
+        firestore_doc: Document,
+        update_only: Option<Vec<String>>,
+        return_only_fields: Option<Vec<String>>,
+        precondition: Option<FirestoreWritePrecondition>,
+    ) -> FirestoreResult<Document> {
+        self.update_document(
+            firestore_doc,
+            update_only.as_ref(),
+            return_only_fields.as_ref(),
+            precondition.as_ref(),
+        )
+    }
+
+    async fn transform_doc<S>(
+        &self,
+        collection_id: &str,
+        document_id: S,
+        precondition: Option<FirestoreWritePrecondition>,
+        transforms: Vec<FirestoreFieldTransform>,
+    ) -> FirestoreResult<FirestoreWriteResult>
+    where
+        S: AsRef<str> + Send,
+    {
+        let parent = self.get_documents_path().clone();
+        self.transform_doc_at(
+            parent.as_str(),
+            collection_id,
+            document_id,
+            precondition,
+            transforms,
+        )
+        .await
+    }
+
+    async fn transform_doc_at<S>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_id: S,
+        precondition: Option<FirestoreWritePrecondition>,
+        transforms: Vec<FirestoreFieldTransform>,
+    ) -> FirestoreResult<FirestoreWriteResult>
+    where
+        S: AsRef<str> + Send,
+    {
+        let document_path = self.document_path(Some(parent), collection_id, document_id)?;
+        self.transform_document(&document_path, precondition.as_ref(), &transforms)
+    }
+}
+
+#[async_trait]
+impl FirestoreDeleteSupport for FirestoreInMemoryDb {
+    async fn delete_by_id<S>(
+        &self,
+        collection_id: &str,
+        document_id: S,
+        precondition: Option<FirestoreWritePrecondition>,
+    ) -> FirestoreResult<()>
+    where
+        S: AsRef<str> + Send,
+    {
+        let document_path = self.document_path(None, collection_id, document_id)?;
+        self.delete_document(&document_path, precondition.as_ref())
+    }
+
+    async fn delete_by_id_at<S>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_id: S,
+        precondition: Option<FirestoreWritePrecondition>,
+    ) -> FirestoreResult<()>
+    where
+        S: AsRef<str> + Send,
+    {
+        let document_path = self.document_path(Some(parent), collection_id, document_id)?;
+        self.delete_document(&document_path, precondition.as_ref())
+    }
+}
+
+/// Parses the page tokens of the listings, which are the offsets of the pages.
+fn page_offset(page_token: Option<&String>) -> FirestoreResult<usize> {
+    page_token
+        .map(|page_token| {
+            page_token.parse::<usize>().map_err(|err| {
+                FirestoreError::InvalidParametersError(FirestoreInvalidParametersError::new(
+                    FirestoreInvalidParametersPublicDetails::new(
+                        "page_token".to_string(),
+                        format!("Invalid page token {page_token}: {err}"),
+                    ),
+                ))
+            })
+        })
+        .transpose()
+        .map(|offset| offset.unwrap_or(0))
+}
+
+fn page_of<T>(
+    items: Vec<T>,
+    page_size: usize,
+    page_token: Option<&String>,
+) -> FirestoreResult<(Vec<T>, Option<String>)> {
+    let offset = page_offset(page_token)?;
+    let total = items.len();
+    let page: Vec<T> = items.into_iter().skip(offset).take(page_size).collect();
+    let next_offset = offset + page.len();
+    let next_page_token = if next_offset < total && !page.is_empty() {
+        Some(next_offset.to_string())
+    } else {
+        None
+    };
+    Ok((page, next_page_token))
+}
+
+impl FirestoreInMemoryDb {
+    async fn list_all_docs(
+        &self,
+        params: &FirestoreListDocParams,
+    ) -> FirestoreResult<Vec<Document>> {
+        let mut documents = self
+            .query_documents(
+                &FirestoreQueryParams::new(params.collection_id.as_str().into())
+                    .opt_parent(params.parent.clone())
+                    .opt_order_by(params.order_by.clone())
+                    .opt_return_only_fields(params.return_only_fields.clone()),
+            )
+            .await?;
+        if params.show_missing == Some(true) {
+            documents.extend(
+                self.missing_documents(
+                    params
+                        .parent
+                        .as_deref()
+                        .unwrap_or(self.get_documents_path().as_str()),
+                    params.collection_id.as_str(),
+                )?,
+            );
+            // Firestore doesn't support ordering with missing documents
+            documents.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        Ok(documents)
+    }
+
+    fn list_all_collection_ids(
+        &self,
+        params: &FirestoreListCollectionIdsParams,
+    ) -> FirestoreResult<Vec<String>> {
+        self.list_collection_ids(
+            params
+                .parent
+                .as_deref()
+                .unwrap_or(self.get_documents_path().as_str()),
+        )
+    }
+}
+
+#[async_trait]
+impl FirestoreListingSupport for FirestoreInMemoryDb {
+    async fn list_doc(
+        &self,
+        params: FirestoreListDocParams,
+    ) -> FirestoreResult<FirestoreListDocResult> {
+        let (documents, page_token) = page_of(
+            self.list_all_docs(&params).await?,
+            params.page_size,
+            params.page_token.as_ref(),
+        )?;
+        Ok(FirestoreListDocResult::new(documents).opt_page_token(page_token))
+    }
+
+    async fn stream_list_doc<'b>(
+        &self,
+        params: FirestoreListDocParams,
+    ) -> FirestoreResult<BoxStream<'b, Document>> {
+        let docs = self.list_all_docs(&params).await?;
+        Ok(futures::stream::iter(docs).boxed())
+    }
+
+    async fn stream_list_doc_with_errors<'b>(
+        &self,
+        params: FirestoreListDocParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<Document>>> {
+        let docs = self.list_all_docs(&params).await?;
+        Ok(futures::stream::iter(docs.into_iter().map(Ok)).boxed())
+    }
+
+    async fn stream_list_obj<'b, T>(
+        &self,
+        params: FirestoreListDocParams,
+    ) -> FirestoreResult<BoxStream<'b, T>>
+    where
+        for<'de> T: Deserialize<'de> + 'b,
+    {
+        let docs = self.list_all_docs(&params).await?;
+        Ok(futures::stream::iter(docs)
+            .filter_map(|doc| async move { FirestoreDb::deserialize_doc_to(&doc).ok() })
+            .boxed())
+    }
+
+    async fn stream_list_obj_with_errors<'b, T>(
+        &self,
+        params: FirestoreListDocParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<T>>>
+    where
+        for<'de> T: Deserialize<'de> + 'b,
+    {
+        let docs = self.list_all_docs(&params).await?;
+        Ok(futures::stream::iter(docs)
+            .map(|doc| FirestoreDb::deserialize_doc_to(&doc))
+            .boxed())
+    }
+
+    async fn list_collection_ids(
+        &self,
+        params: FirestoreListCollectionIdsParams,
+    ) -> FirestoreResult<FirestoreListCollectionIdsResult> {
+        let (collection_ids, page_token) = page_of(
+            self.list_all_collection_ids(&params)?,
+            params.page_size,
+            params.page_token.as_ref(),
+        )?;
+        Ok(FirestoreListCollectionIdsResult::new(collection_ids).opt_page_token(page_token))
+    }
+
+    async fn stream_list_collection_ids_with_errors(
+        &self,
+        params: FirestoreListCollectionIdsParams,
+    ) -> FirestoreResult<BoxStream<FirestoreResult<String>>> {
+        let collection_ids = self.list_all_collection_ids(&params)?;
+        Ok(futures::stream::iter(collection_ids.into_iter().map(Ok)).boxed())
+    }
+
+    async fn stream_list_collection_ids(
+        &self,
+        params: FirestoreListCollectionIdsParams,
+    ) -> FirestoreResult<BoxStream<String>> {
+        let collection_ids = self.list_all_collection_ids(&params)?;
+        Ok(futures::stream::iter(collection_ids).boxed())
+    }
+}
+
+#[async_trait]
+impl FirestoreGetByIdSupport for FirestoreInMemoryDb {
+    async fn get_doc<S>(
+        &self,
+        collection_id: &str,
+        document_id: S,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<Document>
+    where
+        S: AsRef<str> + Send,
+    {
+        let document_path = self.document_path(None, collection_id, document_id)?;
+        self.get_doc_by_path(&document_path, return_only_fields.as_ref())
+    }
+
+    async fn get_doc_at<S>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_id: S,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<Document>
+    where
+        S: AsRef<str> + Send,
+    {
+        let document_path = self.document_path(Some(parent), collection_id, document_id)?;
+        self.get_doc_by_path(&document_path, return_only_fields.as_ref())
+    }
+
+    async fn get_obj<T, S>(&self, collection_id: &str, document_id: S) -> FirestoreResult<T>
+    where
+        for<'de> T: Deserialize<'de>,
+        S: AsRef<str> + Send,
+    {
+        self.get_obj_return_fields(collection_id, document_id, None)
+            .await
+    }
+
+    async fn get_obj_at<T, S>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_id: S,
+    ) -> FirestoreResult<T>
+    where
+        for<'de> T: Deserialize<'de>,
+        S: AsRef<str> + Send,
+    {
+        self.get_obj_at_return_fields(parent, collection_id, document_id, None)
+            .await
+    }
+
+    async fn get_obj_at_return_fields<T, S>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_id: S,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<T>
+    where
+        for<'de> T: Deserialize<'de>,
+        S: AsRef<str> + Send,
+    {
+        let doc = self
+            .get_doc_at(parent, collection_id, document_id, return_only_fields)
+            .await?;
+        FirestoreDb::deserialize_doc_to(&doc)
+    }
+
+    async fn get_obj_if_exists<T, S>(
+        &self,
+        collection_id: &str,
+        document_id: S,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<Option<T>>
+    where
+        for<'de> T: Deserialize<'de>,
+        S: AsRef<str> + Send,
+    {
+        let parent = self.get_documents_path().clone();
+        self.get_obj_at_if_exists(
+            parent.as_str(),
+            collection_id,
+            document_id,
+            return_only_fields,
+        )
+        .await
+    }
+
+    async fn get_obj_at_if_exists<T, S>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_id: S,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<Option<T>>
+    where
+        for<'de> T: Deserialize<'de>,
+        S: AsRef<str> + Send,
+    {
+        match self
+            .get_obj_at_return_fields(parent, collection_id, document_id, return_only_fields)
+            .await
+        {
+            Ok(obj) => Ok(Some(obj)),
+            Err(FirestoreError::DataNotFoundError(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn batch_stream_get_docs_at<S, I>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<BoxStream<(String, Option<Document>)>>
+    where
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send,
+    {
+        let docs = self.batch_get_docs(
+            Some(parent),
+            collection_id,
+            document_ids,
+            return_only_fields.as_ref(),
+        )?;
+        Ok(futures::stream::iter(docs).boxed())
+    }
+
+    async fn batch_stream_get_objects<'a, T, S, I>(
+        &'a self,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<BoxStream<'a, (String, Option<T>)>>
+    where
+        for<'de> T: Deserialize<'de> + 'a,
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send,
+    {
+        let docs = self.batch_get_docs(
+            None,
+            collection_id,
+            document_ids,
+            return_only_fields.as_ref(),
+        )?;
+        Ok(futures::stream::iter(docs)
+            .filter_map(|(document_id, doc)| async move {
+                match doc {
+                    Some(doc) => FirestoreDb::deserialize_doc_to(&doc)
+                        .ok()
+                        .map(|obj| (document_id, Some(obj))),
+                    None => Some((document_id, None)),
+                }
+            })
+            .boxed())
+    }
+
+    async fn batch_stream_get_docs_at_with_errors<S, I>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<BoxStream<FirestoreResult<(String, Option<Document>)>>>
+    where
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send,
+    {
+        let docs = self.batch_get_docs(
+            Some(parent),
+            collection_id,
+            document_ids,
+            return_only_fields.as_ref(),
+        )?;
+        Ok(futures::stream::iter(docs.into_iter().map(Ok)).boxed())
+    }
+
+    async fn batch_get_objects_ordered<T, S, I>(
+        &self,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<Vec<(String, FirestoreBatchGetResult<T>)>>
+    where
+        for<'de> T: Deserialize<'de> + Send,
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send,
+    {
+        self.batch_get_objects_ordered_by_path(
+            None,
+            collection_id,
+            document_ids,
+            return_only_fields.as_ref(),
+        )
+    }
+
+    async fn batch_get_objects_ordered_at<T, S, I>(
+        &self,
+        parent: &str,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<Vec<(String, FirestoreBatchGetResult<T>)>>
+    where
+        for<'de> T: Deserialize<'de> + Send,
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send,
+    {
+        self.batch_get_objects_ordered_by_path(
+            Some(parent),
+            collection_id,
+            document_ids,
+            return_only_fields.as_ref(),
+        )
+    }
+
+    async fn batch_stream_get_objects_with_errors<'a, T, S, I>(
+        &'a self,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<BoxStream<'a, FirestoreResult<(String, Option<T>)>>>
+    where
+        for<'de> T: Deserialize<'de> + Send + 'a,
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send,
+    {
+        let parent = self.get_documents_path().clone();
+        self.batch_stream_get_objects_at_with_errors(
+            parent.as_str(),
+            collection_id,
+            document_ids,
+            return_only_fields,
+        )
+        .await
+    }
+
+    async fn batch_stream_get_docs<S, I>(
+        &self,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<BoxStream<(String, Option<Document>)>>
+    where
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send,
+    {
+        let docs = self.batch_get_docs(
+            None,
+            collection_id,
+            document_ids,
+            return_only_fields.as_ref(),
+        )?;
+        Ok(futures::stream::iter(docs).boxed())
+    }
+
+    async fn batch_stream_get_docs_with_errors<S, I>(
+        &self,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<BoxStream<FirestoreResult<(String, Option<Document>)>>>
+    where
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send,
+    {
+        let docs = self.batch_get_docs(
+            None,
+            collection_id,
+            document_ids,
+            return_only_fields.as_ref(),
+        )?;
+        Ok(futures::stream::iter(docs.into_iter().map(Ok)).boxed())
+    }
+
+    async fn batch_stream_get_objects_at<'a, T, S, I>(
+        &'a self,
+        parent: &str,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<BoxStream<'a, (String, Option<T>)>>
+    where
+        for<'de> T: Deserialize<'de> + Send + 'a,
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send,
+    {
+        let objs: Vec<(String, Option<T>)> = self
+            .batch_stream_get_objects_at_with_errors(
+                parent,
+                collection_id,
+                document_ids,
+                return_only_fields,
+            )
+            .await?
+            .filter_map(|result| futures::future::ready(result.ok()))
+            .collect()
+            .await;
+        Ok(futures::stream::iter(objs).boxed())
+    }
+
+    async fn batch_stream_get_objects_at_with_errors<'a, T, S, I>(
+        &'a self,
+        parent: &str,
+        collection_id: &str,
+        document_ids: I,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<BoxStream<'a, FirestoreResult<(String, Option<T>)>>>
+    where
+        for<'de> T: Deserialize<'de> + Send,
+        S: AsRef<str> + Send,
+        I: IntoIterator<Item = S> + Send,
+    {
+        let docs = self.batch_get_docs(
+            Some(parent),
+            collection_id,
+            document_ids,
+            return_only_fields.as_ref(),
+        )?;
+        Ok(futures::stream::iter(docs)
+            .map(|(document_id, doc)| {
+                let obj = doc
+                    .map(|doc| FirestoreDb::deserialize_doc_to(&doc))
+                    .transpose()?;
+                Ok((document_id, obj))
+            })
+            .boxed())
+    }
+
+    async fn get_obj_return_fields<T, S>(
+        &self,
+        collection_id: &str,
+        document_id: S,
+        return_only_fields: Option<Vec<String>>,
+    ) -> FirestoreResult<T>
+    where
+        for<'de> T: Deserialize<'de>,
+        S: AsRef<str> + Send,
+    {
+        let doc = self
+            .get_doc(collection_id, document_id, return_only_fields)
+            .await?;
+        FirestoreDb::deserialize_doc_to(&doc)
+    }
+}
+
+#[async_trait]
+impl FirestoreListenSupport for FirestoreInMemoryDb {
+    async fn listen_doc_changes<'a, 'b>(
+        &'a self,
+        _targets: Vec<FirestoreListenerTargetParams>,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<ListenResponse>>> {
+        Ok(futures::stream::pending().boxed())
+    }
+}
+
+#[async_trait]
+impl FirestoreAggregatedQuerySupport for FirestoreInMemoryDb {
+    async fn aggregated_query_doc(
+        &self,
+        params: FirestoreAggregatedQueryParams,
+    ) -> FirestoreResult<Vec<Document>> {
+        Ok(vec![self.aggregate_documents(&params).await?])
+    }
+
+    async fn stream_aggregated_query_doc<'b>(
+        &self,
+        params: FirestoreAggregatedQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, Document>> {
+        let doc = self.aggregate_documents(&params).await?;
+        Ok(futures::stream::iter(vec![doc]).boxed())
+    }
+
+    async fn stream_aggregated_query_doc_with_errors<'b>(
+        &self,
+        params: FirestoreAggregatedQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<Document>>> {
+        let doc = self.aggregate_documents(&params).await?;
+        Ok(futures::stream::iter(vec![Ok(doc)]).boxed())
+    }
+
+    async fn stream_aggregated_query_doc_with_metadata<'b>(
+        &self,
+        params: FirestoreAggregatedQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<Document>>>> {
+        let doc = self.aggregate_documents(&params).await?;
+        Ok(futures::stream::iter(vec![Ok(Self::with_metadata(doc))]).boxed())
+    }
+
+    async fn aggregated_query_obj<T>(
+        &self,
+        params: FirestoreAggregatedQueryParams,
+    ) -> FirestoreResult<Vec<T>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        deserialize_docs(vec![self.aggregate_documents(&params).await?])
+    }
+
+    async fn stream_aggregated_query_obj<'b, T>(
+        &self,
+        params: FirestoreAggregatedQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, T>>
+    where
+        for<'de> T: Deserialize<'de>,
+    {
+        let doc = self.aggregate_documents(&params).await?;
+        Ok(futures::stream::iter(vec![doc])
+            .filter_map(|doc| async move { FirestoreDb::deserialize_doc_to(&doc).ok() })
+            .boxed())
+    }
+
+    async fn stream_aggregated_query_obj_with_errors<'b, T>(
+        &self,
+        params: FirestoreAggregatedQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<T>>>
+    where
+        for<'de> T: Deserialize<'de>,
+        T: Send + 'b,
+    {
+        let objs = deserialize_docs_with_errors(vec![self.aggregate_documents(&params).await?]);
+        Ok(futures::stream::iter(objs).boxed())
+    }
+
+    async fn stream_aggregated_query_obj_with_metadata<'b, T>(
+        &self,
+        params: FirestoreAggregatedQueryParams,
+    ) -> FirestoreResult<BoxStream<'b, FirestoreResult<FirestoreWithMetadata<T>>>>
+    where
+        for<'de> T: Deserialize<'de>,
+        T: Send + 'b,
+    {
+        let objs: Vec<FirestoreResult<FirestoreWithMetadata<T>>> =
+            deserialize_docs_with_errors(vec![self.aggregate_documents(&params).await?])
+                .into_iter()
+                .map(|obj| obj.map(Self::with_metadata))
+                .collect();
+        Ok(futures::stream::iter(objs).boxed())
+    }
+}
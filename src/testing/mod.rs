@@ -0,0 +1,647 @@
+//! An in-memory Firestore database for unit tests.
+//!
+//! [`FirestoreInMemoryDb`](crate::testing::FirestoreInMemoryDb) implements the support traits used by the fluent API
+//! ([`FirestoreQuerySupport`](crate::FirestoreQuerySupport), [`FirestoreCreateSupport`](crate::FirestoreCreateSupport), etc.),
+//! so code generic over them can be tested without the Firestore emulator:
+//!
+//! ```rust
+//! use firestore::testing::FirestoreInMemoryDb;
+//! use firestore::*;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+//! struct MyTestStructure {
+//!     some_id: String,
+//!     some_num: u64,
+//! }
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> FirestoreResult<()> {
+//! let db = FirestoreInMemoryDb::new("test-project");
+//!
+//! let obj = MyTestStructure { some_id: "test-1".to_string(), some_num: 42 };
+//! let _: MyTestStructure = db
+//!     .fluent()
+//!     .insert()
+//!     .into("test")
+//!     .document_id(&obj.some_id)
+//!     .object(&obj)
+//!     .execute()
+//!     .await?;
+//!
+//! let found: Vec<MyTestStructure> = db
+//!     .fluent()
+//!     .select()
+//!     .from("test")
+//!     .filter(|q| q.for_all([q.field(path!(MyTestStructure::some_num)).greater_than(10)]))
+//!     .obj()
+//!     .query()
+//!     .await?;
+//! assert_eq!(found, vec![obj]);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Queries support the subset of Firestore queries supported by the cache query engine:
+//! all the filter operators, ordering, cursors, offsets and limits. Vector search isn't supported,
+//! and listeners never receive any changes.
+
+mod in_memory_db;
+pub use in_memory_db::*;
+
+mod in_memory_support;
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::FirestoreInMemoryDb;
+    use crate::*;
+    use futures::{FutureExt, TryStreamExt};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct TestItem {
+        id: String,
+        num: i64,
+        tags: Vec<String>,
+    }
+
+    fn item(id: &str, num: i64) -> TestItem {
+        TestItem {
+            id: id.to_string(),
+            num,
+            tags: vec![format!("tag-{}", num % 2)],
+        }
+    }
+
+    async fn insert_items(db: &FirestoreInMemoryDb, items: &[TestItem]) -> FirestoreResult<()> {
+        for item in items {
+            db.fluent()
+                .insert()
+                .into("items")
+                .document_id(&item.id)
+                .object(item)
+                .execute::<TestItem>()
+                .await?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn crud_with_fluent_api() -> FirestoreResult<()> {
+        let db = FirestoreInMemoryDb::new("test");
+        insert_items(&db, &[item("a", 1)]).await?;
+
+        let inserted_again = db
+            .fluent()
+            .insert()
+            .into("items")
+            .document_id("a")
+            .object(&item("a", 1))
+            .execute::<TestItem>()
+            .await;
+        assert!(matches!(
+            inserted_again,
+            Err(FirestoreError::DataConflictError(_))
+        ));
+
+        let updated: TestItem = db
+            .fluent()
+            .update()
+            .fields(paths!(TestItem::num))
+            .in_col("items")
+            .document_id("a")
+            .object(&item("a", 5))
+            .execute()
+            .await?;
+        assert_eq!(updated.num, 5);
+        assert_eq!(updated.tags, vec!["tag-1".to_string()]);
+
+        db.fluent()
+            .update()
+            .in_col("items")
+            .document_id("a")
+            .transforms(|t| t.fields([t.field(path!(TestItem::num)).increment(10)]))
+            .only_transform()
+            .execute()
+            .await?;
+
+        let found: Option<TestItem> = db
+            .fluent()
+            .select()
+            .by_id_in("items")
+            .obj()
+            .one("a")
+            .await?;
+        assert_eq!(found.map(|item| item.num), Some(15));
+
+        db.fluent()
+            .delete()
+            .from("items")
+            .document_id("a")
+            .execute()
+            .await?;
+        let missing: Option<TestItem> = db
+            .fluent()
+            .select()
+            .by_id_in("items")
+            .obj()
+            .one("a")
+            .await?;
+        assert_eq!(missing, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn query_filters_ordering_and_aggregations() -> FirestoreResult<()> {
+        let db = FirestoreInMemoryDb::new("test");
+        insert_items(
+            &db,
+            &[item("a", 3), item("b", 1), item("c", 4), item("d", 2)],
+        )
+        .await?;
+
+        let found: Vec<TestItem> = db
+            .fluent()
+            .select()
+            .from("items")
+            .filter(|q| {
+                q.for_all([
+                    q.field(path!(TestItem::num)).greater_than(1),
+                    q.field(path!(TestItem::tags))
+                        .array_contains("tag-0".to_string()),
+                ])
+            })
+            .order_by([(path!(TestItem::num), FirestoreQueryDirection::Descending)])
+            .obj()
+            .query()
+            .await?;
+        assert_eq!(found, vec![item("c", 4), item("d", 2)]);
+
+        let limited: Vec<TestItem> = db
+            .fluent()
+            .select()
+            .from("items")
+            .order_by([(path!(TestItem::num), FirestoreQueryDirection::Ascending)])
+            .offset(1)
+            .limit(2)
+            .obj()
+            .query()
+            .await?;
+        assert_eq!(limited, vec![item("d", 2), item("a", 3)]);
+
+        #[derive(Debug, Deserialize)]
+        struct Totals {
+            count: usize,
+            total: i64,
+        }
+
+        let totals: Vec<Totals> = db
+            .fluent()
+            .select()
+            .from("items")
+            .aggregate(|a| {
+                a.fields([
+                    a.field("count").count(),
+                    a.field("total").sum(path!(TestItem::num)),
+                ])
+            })
+            .obj()
+            .query()
+            .await?;
+        assert_eq!(totals[0].count, 4);
+        assert_eq!(totals[0].total, 10);
+
+        let results = db
+            .fluent()
+            .select()
+            .from("items")
+            .aggregate(|a| {
+                a.fields([
+                    a.count(),
+                    a.sum(path!(TestItem::num)),
+                    a.avg(path!(TestItem::num)),
+                ])
+            })
+            .results()
+            .await?;
+        assert_eq!(results.count(), Some(4));
+        assert_eq!(
+            results
+                .sum(path!(TestItem::num))
+                .and_then(|sum| sum.as_i64()),
+            Some(10)
+        );
+        assert_eq!(results.avg(path!(TestItem::num)), Some(2.5));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn transactions_commit_and_retry_conflicts() -> FirestoreResult<()> {
+        let db = FirestoreInMemoryDb::new("test");
+        insert_items(&db, &[item("a", 1)]).await?;
+
+        let attempts = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let outer_db = db.clone();
+        let result = db
+            .run_transaction(|transaction_db| {
+                let outer_db = outer_db.clone();
+                let attempts = attempts.clone();
+                async move {
+                    let current: TestItem = transaction_db.get_obj("items", "a").await?;
+                    if attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                        // A concurrent write outside of the transaction makes the first attempt conflict.
+                        outer_db
+                            .update_obj::<_, TestItem, _>(
+                                "items",
+                                "a",
+                                &item("a", 100),
+                                None,
+                                None,
+                                None,
+                            )
+                            .await?;
+                    }
+                    transaction_db
+                        .update_obj::<_, TestItem, _>(
+                            "items",
+                            "a",
+                            &item("a", current.num + 1),
+                            None,
+                            None,
+                            None,
+                        )
+                        .await?;
+                    Ok(current.num + 1)
+                }
+                .boxed()
+            })
+            .await?;
+
+        assert_eq!(result, 101);
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 2);
+        let stored: TestItem = db.get_obj("items", "a").await?;
+        assert_eq!(stored.num, 101);
+
+        let failed: FirestoreResult<()> = db
+            .run_transaction(|transaction_db| {
+                async move {
+                    transaction_db.delete_by_id("items", "a", None).await?;
+                    Err(gcloud_sdk::tonic::Status::failed_precondition("rollback").into())
+                }
+                .boxed()
+            })
+            .await;
+        assert!(failed.is_err());
+        assert_eq!(db.documents()?.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn typed_collection_operations() -> FirestoreResult<()> {
+        let db = FirestoreInMemoryDb::new("test");
+        let items = db.typed_collection::<TestItem>("items");
+
+        assert_eq!(items.insert("a", &item("a", 1)).await?, item("a", 1));
+        items.insert("b", &item("b", 2)).await?;
+        assert!(matches!(
+            items.insert("a", &item("a", 1)).await,
+            Err(FirestoreError::DataConflictError(_))
+        ));
+
+        let updated = items
+            .update_fields("a", &item("ignored", 5), paths!(TestItem::num))
+            .await?;
+        assert_eq!(updated.id, "a");
+        assert_eq!(updated.num, 5);
+
+        let found: Vec<TestItem> = items
+            .query(|q| {
+                q.filter(|f| f.field(path!(TestItem::num)).greater_than(2))
+                    .order_by([(path!(TestItem::num), FirestoreQueryDirection::Ascending)])
+            })
+            .query()
+            .await?;
+        assert_eq!(
+            found.iter().map(|item| item.num).collect::<Vec<_>>(),
+            vec![5]
+        );
+
+        items.delete("b").await?;
+        assert_eq!(items.get("b").await?, None);
+        assert_eq!(items.all().query().await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stream_pages_with_prefetching() -> FirestoreResult<()> {
+        let db = FirestoreInMemoryDb::new("test");
+        let items: Vec<TestItem> = (0..7).map(|num| item(&format!("i{num}"), num)).collect();
+        insert_items(&db, &items).await?;
+
+        let streamed: Vec<TestItem> = db
+            .fluent()
+            .select()
+            .from("items")
+            .order_by([(path!(TestItem::num), FirestoreQueryDirection::Descending)])
+            .obj()
+            .page(2)
+            .prefetch(3)
+            .stream_query_with_errors()
+            .await?
+            .try_collect()
+            .await?;
+        assert_eq!(streamed, items.into_iter().rev().collect::<Vec<_>>());
+
+        let pages: Vec<FirestorePage<FirestoreDocument>> = db
+            .fluent()
+            .select()
+            .from("items")
+            .page(3)
+            .stream_pages_with_errors()
+            .await?
+            .try_collect()
+            .await?;
+        assert_eq!(
+            pages
+                .iter()
+                .map(|page| page.items.len())
+                .collect::<Vec<_>>(),
+            vec![3, 3, 1]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stream_document_tree() -> FirestoreResult<()> {
+        let db = FirestoreInMemoryDb::new("test");
+        insert_items(&db, &[item("a", 1)]).await?;
+        let tenant_path = db.parent_path("tenants", "t1");
+        let user_path = format!("{tenant_path}/users/u1");
+        for (parent, collection_id, id) in [
+            (tenant_path.as_str(), "users", "u1"),
+            (tenant_path.as_str(), "users", "u2"),
+            (user_path.as_str(), "orders", "o1"),
+        ] {
+            db.fluent()
+                .insert()
+                .into(collection_id)
+                .document_id(id)
+                .parent(parent)
+                .object(&item(id, 1))
+                .execute::<TestItem>()
+                .await?;
+        }
+
+        let mut paths: Vec<String> = db
+            .stream_document_tree(
+                FirestoreDocumentTreeParams::new().with_parent(tenant_path.clone()),
+            )
+            .await?
+            .map_ok(|(path, _)| path)
+            .try_collect()
+            .await?;
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                "tenants/t1/users/u1",
+                "tenants/t1/users/u1/orders/o1",
+                "tenants/t1/users/u2"
+            ]
+        );
+
+        let users: Vec<(String, TestItem)> = db
+            .stream_document_tree_obj(
+                FirestoreDocumentTreeParams::new()
+                    .with_parent(tenant_path)
+                    .with_max_depth(1),
+                FirestoreDocumentTreeRegistry::new().register("users", |user: TestItem| user),
+            )
+            .await?
+            .try_collect()
+            .await?;
+        assert_eq!(users.len(), 2);
+
+        let all_items: Vec<(String, TestItem)> = db
+            .stream_document_tree_obj(
+                FirestoreDocumentTreeParams::new(),
+                FirestoreDocumentTreeRegistry::new()
+                    .register("items", |item: TestItem| item)
+                    .register("orders", |order: TestItem| order),
+            )
+            .await?
+            .try_collect()
+            .await?;
+        assert_eq!(all_items.len(), 2);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "export")]
+    #[tokio::test]
+    async fn export_collection_as_ndjson() -> FirestoreResult<()> {
+        let db = FirestoreInMemoryDb::new("test");
+        insert_items(&db, &[item("a", 1), item("b", 2), item("c", 3)]).await?;
+
+        for partitions in [1, 4] {
+            let mut output: Vec<u8> = Vec::new();
+            let stats = db
+                .export_ndjson(
+                    FirestoreExportParams::new(FirestoreQueryParams::new("items".into()))
+                        .with_partitions(partitions),
+                    &mut output,
+                )
+                .await?;
+            assert_eq!(stats.documents, 3);
+            assert_eq!(stats.bytes, output.len() as u64);
+
+            let lines: Vec<serde_json::Value> = String::from_utf8(output)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+            assert_eq!(lines.len(), 3);
+            let first = lines
+                .iter()
+                .find(|line| line["name"].as_str().unwrap().ends_with("/items/a"))
+                .unwrap();
+            assert_eq!(first["fields"]["num"]["integerValue"], "1");
+            assert_eq!(first["fields"]["id"]["stringValue"], "a");
+            assert!(first["updateTime"].is_string());
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "export")]
+    #[tokio::test]
+    async fn import_exported_ndjson() -> FirestoreResult<()> {
+        let db = FirestoreInMemoryDb::new("test");
+        insert_items(&db, &[item("a", 1), item("b", 2), item("c", 3)]).await?;
+
+        let mut output: Vec<u8> = Vec::new();
+        db.export_ndjson(
+            FirestoreExportParams::new(FirestoreQueryParams::new("items".into())),
+            &mut output,
+        )
+        .await?;
+
+        let restored_db = FirestoreInMemoryDb::new("restored");
+        let stats = restored_db
+            .import_ndjson(FirestoreImportParams::new(), output.as_slice())
+            .await?;
+        assert_eq!(stats.records, 3);
+        assert_eq!(stats.written, 3);
+
+        let relative = |db: &FirestoreInMemoryDb| -> FirestoreResult<Vec<(String, TestItem)>> {
+            db.documents()?
+                .iter()
+                .map(|doc| {
+                    Ok((
+                        doc.name
+                            .trim_start_matches(db.get_documents_path().as_str())
+                            .to_string(),
+                        FirestoreDb::deserialize_doc_to(doc)?,
+                    ))
+                })
+                .collect()
+        };
+        assert_eq!(relative(&restored_db)?, relative(&db)?);
+
+        let stats = restored_db
+            .import_ndjson(
+                FirestoreImportParams::new()
+                    .with_conflict_policy(FirestoreImportConflictPolicy::SkipExisting),
+                output.as_slice(),
+            )
+            .await?;
+        assert_eq!(stats.written, 0);
+        assert_eq!(stats.skipped, 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn query_document_id_key_ranges() -> FirestoreResult<()> {
+        let db = FirestoreInMemoryDb::new("test");
+        insert_items(
+            &db,
+            &[item("a", 1), item("b", 2), item("c", 3), item("d", 4)],
+        )
+        .await?;
+
+        let shard: Vec<TestItem> = db
+            .fluent()
+            .select()
+            .from("items")
+            .filter(|q| q.document_id().in_range("b".."d"))
+            .order_by([FirestoreQueryOrder::document_id(
+                FirestoreQueryDirection::Descending,
+            )])
+            .obj()
+            .query()
+            .await?;
+        assert_eq!(shard, vec![item("c", 3), item("b", 2)]);
+
+        let picked: Vec<TestItem> = db
+            .fluent()
+            .select()
+            .from("items")
+            .filter(|q| q.document_id().is_in(["a", "d"]))
+            .obj()
+            .query()
+            .await?;
+        assert_eq!(picked, vec![item("a", 1), item("d", 4)]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn multi_returns_typed_results_together() -> FirestoreResult<()> {
+        let db = FirestoreInMemoryDb::new("test");
+        insert_items(&db, &[item("a", 1), item("b", 2), item("c", 3)]).await?;
+
+        let (odd, b, missing): (Vec<TestItem>, Option<TestItem>, Option<TestItem>) = db
+            .fluent()
+            .multi()
+            .add(
+                db.fluent()
+                    .select()
+                    .from("items")
+                    .filter(|q| q.field("tags").array_contains("tag-1"))
+                    .order_by([("num", FirestoreQueryDirection::Ascending)])
+                    .obj()
+                    .query(),
+            )
+            .add(db.fluent().select().by_id_in("items").obj().one("b"))
+            .add(db.fluent().select().by_id_in("items").obj().one("z"))
+            .execute()
+            .await?;
+
+        assert_eq!(odd, vec![item("a", 1), item("c", 3)]);
+        assert_eq!(b, Some(item("b", 2)));
+        assert_eq!(missing, None);
+
+        Ok(())
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct TestProfile {
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        nickname: Option<String>,
+        settings: std::collections::BTreeMap<String, String>,
+    }
+
+    #[tokio::test]
+    async fn merge_keeps_fields_missing_in_object() -> FirestoreResult<()> {
+        let db = FirestoreInMemoryDb::new("test");
+        let settings = |entries: &[(&str, &str)]| {
+            entries
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+        db.fluent()
+            .insert()
+            .into("profiles")
+            .document_id("alice")
+            .object(&TestProfile {
+                name: "Alice".to_string(),
+                nickname: Some("Al".to_string()),
+                settings: settings(&[("theme", "dark"), ("lang", "en")]),
+            })
+            .execute::<TestProfile>()
+            .await?;
+
+        let merged: TestProfile = db
+            .fluent()
+            .update()
+            .merge()
+            .in_col("profiles")
+            .document_id("alice")
+            .object(&TestProfile {
+                name: "Alice B.".to_string(),
+                nickname: None,
+                settings: settings(&[("theme", "light")]),
+            })
+            .execute()
+            .await?;
+
+        assert_eq!(
+            merged,
+            TestProfile {
+                name: "Alice B.".to_string(),
+                nickname: Some("Al".to_string()),
+                settings: settings(&[("theme", "light"), ("lang", "en")]),
+            }
+        );
+
+        Ok(())
+    }
+}
@@ -18,10 +18,8 @@ struct MyTestStructure {
 async fn precondition_tests() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let db = setup().await?;
 
-    const TEST_COLLECTION_NAME_NO_PRELOAD: &'static str =
-        "integration-test-caching-persistent-no-preload";
-    const TEST_COLLECTION_NAME_PRELOAD: &'static str =
-        "integration-test-caching-persistent-preload";
+    const TEST_COLLECTION_NAME_NO_PRELOAD: &str = "integration-test-caching-persistent-no-preload";
+    const TEST_COLLECTION_NAME_PRELOAD: &str = "integration-test-caching-persistent-preload";
 
     populate_collection(
         &db,
@@ -74,9 +72,9 @@ async fn precondition_tests() -> Result<(), Box<dyn std::error::Error + Send + S
                         FirestoreCacheCollectionLoadMode::PreloadAllDocs,
                     ),
                 ),
-            temp_db_dir.into_path().join("redb"),
+            temp_db_dir.keep().join("redb"),
         )?,
-        FirestoreTempFilesListenStateStorage::with_temp_dir(temp_state_dir.into_path()),
+        FirestoreTempFilesListenStateStorage::with_temp_dir(temp_state_dir.keep()),
     )
     .await?;
 
@@ -0,0 +1,124 @@
+use chrono::{DateTime, TimeZone, Utc};
+use firestore::testing::FirestoreInMemoryDb;
+use firestore::*;
+use gcloud_sdk::google::firestore::v1::value;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FirestoreEntity)]
+#[firestore(collection = "users", id = "user_id")]
+struct User {
+    user_id: String,
+    name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FirestoreEntity)]
+#[firestore(collection = "counters")]
+struct Counter {
+    id: u64,
+    value: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, FirestoreEntity)]
+#[firestore(collection = "events")]
+struct Event {
+    id: String,
+    #[firestore(timestamp)]
+    created_at: DateTime<Utc>,
+    #[firestore(timestamp)]
+    #[serde(rename = "finishedAt", skip_serializing_if = "Option::is_none")]
+    finished_at: Option<DateTime<Utc>>,
+    name: String,
+}
+
+#[test]
+fn derived_entity_metadata() {
+    let user = User {
+        user_id: "u1".to_string(),
+        name: "Ann".to_string(),
+    };
+    assert_eq!(User::COLLECTION_ID, "users");
+    assert_eq!(user.document_id(), "u1");
+
+    let counter = Counter { id: 42, value: 1 };
+    assert_eq!(Counter::COLLECTION_ID, "counters");
+    assert_eq!(counter.document_id(), "42");
+    assert!(Counter::TIMESTAMP_FIELDS.is_empty());
+
+    assert_eq!(Event::TIMESTAMP_FIELDS, &["created_at", "finishedAt"]);
+}
+
+#[tokio::test]
+async fn write_entities_with_fluent_api() -> FirestoreResult<()> {
+    let db = FirestoreInMemoryDb::new("test");
+    let mut user = User {
+        user_id: "u1".to_string(),
+        name: "Ann".to_string(),
+    };
+
+    let inserted: User = db.fluent().insert().entity(&user).execute().await?;
+    assert_eq!(inserted, user);
+
+    user.name = "Anna".to_string();
+    let updated: User = db.fluent().update().entity(&user).execute().await?;
+    assert_eq!(updated, user);
+
+    db.fluent().delete().entity(&user).execute().await?;
+    let found: Option<User> = db
+        .fluent()
+        .select()
+        .by_id_in(User::COLLECTION_ID)
+        .obj()
+        .one(&user.user_id)
+        .await?;
+    assert_eq!(found, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn write_entity_timestamp_fields() -> FirestoreResult<()> {
+    let db = FirestoreInMemoryDb::new("test");
+    let event = Event {
+        id: "e1".to_string(),
+        created_at: Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap(),
+        finished_at: Some(Utc.with_ymd_and_hms(2024, 1, 2, 4, 0, 0).unwrap()),
+        name: "2024-01-02T03:04:05Z".to_string(),
+    };
+
+    let inserted: Event = db.fluent().insert().entity(&event).execute().await?;
+    assert_eq!(inserted, event);
+
+    let doc = db
+        .fluent()
+        .select()
+        .by_id_in(Event::COLLECTION_ID)
+        .one(&event.id)
+        .await?
+        .expect("Inserted document");
+    let field_value = |field_name: &str| {
+        doc.fields
+            .get(field_name)
+            .and_then(|v| v.value_type.clone())
+    };
+    assert!(matches!(
+        field_value("created_at"),
+        Some(value::ValueType::TimestampValue(_))
+    ));
+    assert!(matches!(
+        field_value("finishedAt"),
+        Some(value::ValueType::TimestampValue(_))
+    ));
+    assert!(matches!(
+        field_value("name"),
+        Some(value::ValueType::StringValue(_))
+    ));
+
+    let unfinished = Event {
+        finished_at: None,
+        ..event
+    };
+    let updated: Event = db.fluent().update().entity(&unfinished).execute().await?;
+    assert_eq!(updated, unfinished);
+
+    Ok(())
+}
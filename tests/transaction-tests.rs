@@ -110,5 +110,40 @@ async fn transaction_tests() -> Result<(), Box<dyn std::error::Error + Send + Sy
         assert!(res.is_ok());
     }
 
+    // Writes returning the previous state of the document
+    {
+        let updated_struct = MyTestStructure {
+            some_string: "Updated".to_string(),
+            ..my_struct.clone()
+        };
+        let previous: Option<MyTestStructure> = db
+            .fluent()
+            .update()
+            .in_col(TEST_COLLECTION_NAME)
+            .document_id(&my_struct.some_id)
+            .object(&updated_struct)
+            .execute_returning_previous()
+            .await?;
+        assert_eq!(previous, Some(my_struct.clone()));
+
+        let deleted: Option<MyTestStructure> = db
+            .fluent()
+            .delete()
+            .from(TEST_COLLECTION_NAME)
+            .document_id(&my_struct.some_id)
+            .execute_returning_previous()
+            .await?;
+        assert_eq!(deleted, Some(updated_struct));
+
+        let deleted: Option<MyTestStructure> = db
+            .fluent()
+            .delete()
+            .from(TEST_COLLECTION_NAME)
+            .document_id(&my_struct.some_id)
+            .execute_returning_previous()
+            .await?;
+        assert_eq!(deleted, None);
+    }
+
     Ok(())
 }